@@ -0,0 +1,41 @@
+/*
+    AST查询工具: 列出某个变量的所有使用位置及读写方式, 不指定变量名时列出全部使用.
+    用法: cargo run --example ast_query -- path/to/file.sy [name]
+*/
+use sysy_alpha::{
+    session::{CompileOptions, Session},
+    span::SourceMap,
+    xref::symbol_uses,
+};
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let path = args.next().unwrap_or_else(|| {
+        eprintln!("usage: ast_query <source.sy> [name]");
+        std::process::exit(2);
+    });
+    let name = args.next();
+
+    let session = Session::new(path, CompileOptions::default());
+    let checked = match session.check() {
+        Ok(checked) => checked,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut sources = SourceMap::new();
+    sources
+        .load(&session.path)
+        .expect("cannot read source file");
+    for u in symbol_uses(&checked.annotated_ast) {
+        if name.as_ref().is_some_and(|n| n != &u.name) {
+            continue;
+        }
+        let (line, column) = sources.line_col(u.span);
+        println!(
+            "{}:{}:{}\t{}\t{:?}\t(declared by node {})",
+            session.path, line, column, u.name, u.kind, u.decl.0
+        );
+    }
+}
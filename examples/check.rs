@@ -0,0 +1,36 @@
+/*
+    最小的检查器: 对一个SysY源文件做完整的前端检查, 有语义错误时打印并以1退出.
+    用法: cargo run --example check -- path/to/file.sy [--extended]
+*/
+use sysy_alpha::{
+    lexer::LangLevel,
+    session::{CompileOptions, Session},
+};
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let path = args.next().unwrap_or_else(|| {
+        eprintln!("usage: check <source.sy> [--extended]");
+        std::process::exit(2);
+    });
+    let mut options = CompileOptions::default();
+    if args.any(|a| a == "--extended") {
+        options.level = LangLevel::Extended;
+    }
+
+    let session = Session::new(path, options);
+    let checked = match session.check() {
+        Ok(checked) => checked,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    for diagnostic in &checked.diagnostics {
+        print!("{}", session.render(diagnostic));
+    }
+    if checked.has_errors() {
+        std::process::exit(1);
+    }
+    println!("{}: ok", session.path);
+}
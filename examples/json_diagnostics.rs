@@ -0,0 +1,71 @@
+/*
+    以JSON数组输出语义错误, 每条包含严重程度, 信息, 文件, 行列号和附加说明, 方便评测脚本和编辑器解析.
+    用法: cargo run --example json_diagnostics -- path/to/file.sy
+*/
+use sysy_alpha::{
+    diagnostics::Severity,
+    session::{CompileOptions, Session},
+    span::{SourceMap, Span},
+};
+
+fn escape(s: &str) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn main() {
+    let path = std::env::args().nth(1).unwrap_or_else(|| {
+        eprintln!("usage: json_diagnostics <source.sy>");
+        std::process::exit(2);
+    });
+    let session = Session::new(path, CompileOptions::default());
+    let checked = match session.check() {
+        Ok(checked) => checked,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut sources = SourceMap::new();
+    sources
+        .load(&session.path)
+        .expect("cannot read source file");
+
+    let mut entries = vec![];
+    for diagnostic in &checked.diagnostics {
+        let severity = match diagnostic.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        };
+        let span = diagnostic
+            .labels
+            .first()
+            .map_or(Span::default(), |l| l.span);
+        let (file, line, column) = sources.location(span);
+        let notes: Vec<String> = diagnostic
+            .notes
+            .iter()
+            .map(|n| format!("\"{}\"", escape(n)))
+            .collect();
+        entries.push(format!(
+            "  {{\"severity\": \"{}\", \"message\": \"{}\", \"file\": \"{}\", \"line\": {}, \"column\": {}, \"notes\": [{}]}}",
+            severity,
+            escape(&diagnostic.message),
+            escape(&file),
+            line,
+            column,
+            notes.join(", ")
+        ));
+    }
+    println!("[\n{}\n]", entries.join(",\n"));
+}
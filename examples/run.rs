@@ -0,0 +1,65 @@
+/*
+    解释器运行器: 检查SysY源文件, 降低成三地址码并按-O优化, 再用interp解释执行.
+    程序的输入从标准输入读取, 输出写到标准输出, 以main的返回值(低8位)退出; 有错误时以1退出.
+    用法: cargo run --example run -- path/to/file.sy [-O0|-O1|-O2] < input
+*/
+use std::io::{Read, Write};
+use sysy_alpha::{
+    lower::lower_with_sources,
+    passes::{OptLevel, PassManager},
+    session::{CompileOptions, Session},
+};
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let path = args.next().unwrap_or_else(|| {
+        eprintln!("usage: run <source.sy> [-O0|-O1|-O2]");
+        std::process::exit(2);
+    });
+    let level = match args.next() {
+        Some(arg) => arg
+            .strip_prefix("-O")
+            .and_then(OptLevel::from_name)
+            .unwrap_or_else(|| {
+                eprintln!("unknown option `{}`", arg);
+                std::process::exit(2);
+            }),
+        None => OptLevel::O0,
+    };
+
+    let session = Session::new(path, CompileOptions::default());
+    let checked = match session.check() {
+        Ok(checked) => checked,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let engine = session.engine();
+    for diagnostic in checked.errors() {
+        eprint!("{}", engine.render(diagnostic));
+    }
+    if checked.has_errors() {
+        std::process::exit(1);
+    }
+    let mut module = lower_with_sources(&checked.hir, engine.sources());
+    PassManager::for_level(level).run(&mut module);
+
+    let mut input = vec![];
+    if let Err(e) = std::io::stdin().read_to_end(&mut input) {
+        eprintln!("cannot read standard input: {}", e);
+        std::process::exit(1);
+    }
+    match session.run(&module, &input) {
+        Ok(outcome) => {
+            let mut stdout = std::io::stdout();
+            let _ = stdout.write_all(&outcome.output);
+            let _ = stdout.flush();
+            std::process::exit(outcome.exit_code & 0xff);
+        }
+        Err(e) => {
+            eprintln!("runtime error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
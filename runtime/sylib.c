@@ -0,0 +1,117 @@
+/*
+    SysY运行时库(libsysy), 行为与比赛提供的sylib.c相同: 输入输出按C的scanf/printf格式,
+    float按十六进制(%a)读写; 计时器在每对starttime/stoptime之间累计, 程序退出时把每个计时器和总计打印到标准错误,
+    评测只比较标准输出和返回值, 因此不受计时输出的影响.
+*/
+#include "sylib.h"
+
+#define _SYSY_N 1024
+
+static int _sysy_l1[_SYSY_N], _sysy_l2[_SYSY_N];
+static int _sysy_h[_SYSY_N], _sysy_m[_SYSY_N], _sysy_s[_SYSY_N], _sysy_us[_SYSY_N];
+static int _sysy_idx;
+static struct timeval _sysy_start, _sysy_end;
+
+/*---------输入-------------*/
+
+int getint(void) {
+    int t = 0;
+    scanf("%d", &t);
+    return t;
+}
+
+int getch(void) {
+    char c = 0;
+    scanf("%c", &c);
+    return (int)c;
+}
+
+float getfloat(void) {
+    float n = 0;
+    scanf("%a", &n);
+    return n;
+}
+
+int getarray(int a[]) {
+    int n = 0;
+    scanf("%d", &n);
+    for (int i = 0; i < n; i++)
+        scanf("%d", &a[i]);
+    return n;
+}
+
+int getfarray(float a[]) {
+    int n = 0;
+    scanf("%d", &n);
+    for (int i = 0; i < n; i++)
+        scanf("%a", &a[i]);
+    return n;
+}
+
+/*---------输出-------------*/
+
+void putint(int a) { printf("%d", a); }
+
+void putch(int a) { printf("%c", a); }
+
+void putarray(int n, int a[]) {
+    printf("%d:", n);
+    for (int i = 0; i < n; i++)
+        printf(" %d", a[i]);
+    printf("\n");
+}
+
+void putfloat(float a) { printf("%a", a); }
+
+void putfarray(int n, float a[]) {
+    printf("%d:", n);
+    for (int i = 0; i < n; i++)
+        printf(" %a", a[i]);
+    printf("\n");
+}
+
+void putf(char a[], ...) {
+    va_list args;
+    va_start(args, a);
+    vfprintf(stdout, a, args);
+    va_end(args);
+}
+
+/*---------计时-------------*/
+
+__attribute__((constructor)) static void _sysy_before_main(void) {
+    for (int i = 0; i < _SYSY_N; i++)
+        _sysy_h[i] = _sysy_m[i] = _sysy_s[i] = _sysy_us[i] = 0;
+    _sysy_idx = 1;
+}
+
+__attribute__((destructor)) static void _sysy_after_main(void) {
+    long long total = 0;
+    for (int i = 1; i < _sysy_idx; i++) {
+        fprintf(stderr, "Timer@%04d-%04d: %dH-%dM-%dS-%dus\n", _sysy_l1[i], _sysy_l2[i],
+                _sysy_h[i], _sysy_m[i], _sysy_s[i], _sysy_us[i]);
+        total += ((_sysy_h[i] * 60LL + _sysy_m[i]) * 60 + _sysy_s[i]) * 1000000 + _sysy_us[i];
+    }
+    fprintf(stderr, "TOTAL: %dH-%dM-%dS-%dus\n", (int)(total / 3600000000LL),
+            (int)(total / 60000000 % 60), (int)(total / 1000000 % 60), (int)(total % 1000000));
+}
+
+void _sysy_starttime(int lineno) {
+    _sysy_l1[_sysy_idx] = lineno;
+    gettimeofday(&_sysy_start, NULL);
+}
+
+void _sysy_stoptime(int lineno) {
+    gettimeofday(&_sysy_end, NULL);
+    _sysy_l2[_sysy_idx] = lineno;
+    _sysy_us[_sysy_idx] += 1000000 * (_sysy_end.tv_sec - _sysy_start.tv_sec) +
+                           _sysy_end.tv_usec - _sysy_start.tv_usec;
+    _sysy_s[_sysy_idx] += _sysy_us[_sysy_idx] / 1000000;
+    _sysy_us[_sysy_idx] %= 1000000;
+    _sysy_m[_sysy_idx] += _sysy_s[_sysy_idx] / 60;
+    _sysy_s[_sysy_idx] %= 60;
+    _sysy_h[_sysy_idx] += _sysy_m[_sysy_idx] / 60;
+    _sysy_m[_sysy_idx] %= 60;
+    if (_sysy_idx < _SYSY_N - 1)
+        _sysy_idx++;
+}
@@ -0,0 +1,29 @@
+/*
+    SysY运行时库(libsysy)的接口, 与比赛提供的sylib.h相同.
+    starttime()/stoptime()是带行号的宏; 编译器生成的代码同样直接调用_sysy_starttime(行号)/_sysy_stoptime(行号).
+*/
+#ifndef __SYLIB_H_
+#define __SYLIB_H_
+
+#include <stdarg.h>
+#include <stdio.h>
+#include <sys/time.h>
+
+/* 输入 */
+int getint(void), getch(void), getarray(int a[]);
+float getfloat(void);
+int getfarray(float a[]);
+
+/* 输出 */
+void putint(int a), putch(int a), putarray(int n, int a[]);
+void putfloat(float a);
+void putfarray(int n, float a[]);
+void putf(char a[], ...);
+
+/* 计时 */
+void _sysy_starttime(int lineno);
+void _sysy_stoptime(int lineno);
+#define starttime() _sysy_starttime(__LINE__)
+#define stoptime() _sysy_stoptime(__LINE__)
+
+#endif
@@ -0,0 +1,178 @@
+use crate::ir::{Function, InstKind, Operand, Type, Value};
+
+/*
+    数组的别名分析(保守的): 每个地址值追溯到它指向的对象(全局变量, 本函数的alloca, 数组形参)和对象内的偏移.
+    偏移是常数加上若干(值, 步长)项, gep的下标是常数时并入常数, 否则记为一项.
+    不同的全局变量, 不同的alloca, 全局变量与alloca, 形参与alloca都不重叠; 形参可能指向任何全局变量或别的形参.
+    同一对象上项相同时按常数判断, 否则可能重叠. 追溯不到对象的地址(phi等)与一切可能重叠.
+    地址只经由gep计算, SysY的数组中也不能存放地址, 所以alloca只有作为实参传给调用时才会被别处访问.
+*/
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Object {
+    Global(String),
+    Stack(Value), //alloca的结果
+    Param(usize), //第几个形参
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub object: Object,
+    pub terms: Vec<(Value, i64)>, //按值排序, 同一个值只出现一次
+    pub offset: i64,              //以字为单位
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AliasResult {
+    NoAlias,
+    MayAlias,
+    MustAlias,
+}
+
+pub struct AliasAnalysis {
+    locations: Vec<Option<Location>>, //下标是值的编号
+    escaped: Vec<Value>,              //作为实参传给调用的alloca
+    unknown_escaped: bool,            //有追溯不到对象的地址传给了调用, 这时所有alloca都算传出去了
+}
+
+impl AliasAnalysis {
+    pub fn compute(function: &Function) -> Self {
+        let mut locations = vec![None; function.value_types.len()];
+        for (i, ty) in function.params.iter().enumerate() {
+            if *ty == Type::Ptr {
+                locations[i] = Some(Location {
+                    object: Object::Param(i),
+                    terms: vec![],
+                    offset: 0,
+                });
+            }
+        }
+        // 块的顺序不一定是定义的顺序(内联和合并块之后), 重复到不再有新的地址被追溯到为止.
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for inst in function.blocks.iter().flat_map(|b| &b.insts) {
+                let Some(dest) = inst.dest else {
+                    continue;
+                };
+                if locations[dest.0 as usize].is_some() {
+                    continue;
+                }
+                let location = match &inst.kind {
+                    InstKind::Alloca { .. } => Some(Location {
+                        object: Object::Stack(dest),
+                        terms: vec![],
+                        offset: 0,
+                    }),
+                    InstKind::Copy(source) => lookup(&locations, source),
+                    InstKind::Gep(base, index, stride) => {
+                        lookup(&locations, base).map(|mut location| {
+                            let stride = *stride as i64;
+                            match index {
+                                Operand::Int(n) => location.offset += *n as i64 * stride,
+                                Operand::Value(v) => add_term(&mut location.terms, *v, stride),
+                                _ => unreachable!("gep index must be an int"),
+                            }
+                            location
+                        })
+                    }
+                    _ => None,
+                };
+                if location.is_some() {
+                    locations[dest.0 as usize] = location;
+                    changed = true;
+                }
+            }
+        }
+        let mut escaped = vec![];
+        let mut unknown_escaped = false;
+        for inst in function.blocks.iter().flat_map(|b| &b.insts) {
+            let InstKind::Call(_, args) = &inst.kind else {
+                continue;
+            };
+            for arg in args {
+                // 字符串常量(putf的格式)是只读的, 不指向任何数组.
+                if function.operand_type(arg) != Type::Ptr || matches!(arg, Operand::Str(_)) {
+                    continue;
+                }
+                match lookup(&locations, arg) {
+                    Some(Location {
+                        object: Object::Stack(slot),
+                        ..
+                    }) if !escaped.contains(&slot) => escaped.push(slot),
+                    None => unknown_escaped = true,
+                    _ => {}
+                }
+            }
+        }
+        AliasAnalysis {
+            locations,
+            escaped,
+            unknown_escaped,
+        }
+    }
+
+    /* 地址指向的位置; 追溯不到对象时为None. */
+    pub fn location(&self, pointer: &Operand) -> Option<Location> {
+        lookup(&self.locations, pointer)
+    }
+
+    pub fn alias(&self, a: &Operand, b: &Operand) -> AliasResult {
+        if a == b {
+            return AliasResult::MustAlias;
+        }
+        let (Some(a), Some(b)) = (self.location(a), self.location(b)) else {
+            return AliasResult::MayAlias;
+        };
+        if a.object != b.object {
+            return match (&a.object, &b.object) {
+                (Object::Param(_), Object::Param(_))
+                | (Object::Param(_), Object::Global(_))
+                | (Object::Global(_), Object::Param(_)) => AliasResult::MayAlias,
+                _ => AliasResult::NoAlias,
+            };
+        }
+        if a.terms != b.terms {
+            AliasResult::MayAlias
+        } else if a.offset == b.offset {
+            AliasResult::MustAlias
+        } else {
+            AliasResult::NoAlias
+        }
+    }
+
+    /* 调用其他函数后这个地址中的值是否可能改变(或者被读取): 本函数没有传出去的alloca不会. */
+    pub fn visible_to_calls(&self, pointer: &Operand) -> bool {
+        match self.location(pointer) {
+            Some(Location {
+                object: Object::Stack(slot),
+                ..
+            }) => self.unknown_escaped || self.escaped.contains(&slot),
+            _ => true,
+        }
+    }
+}
+
+fn lookup(locations: &[Option<Location>], pointer: &Operand) -> Option<Location> {
+    match pointer {
+        Operand::Value(v) => locations[v.0 as usize].clone(),
+        Operand::Global(name) => Some(Location {
+            object: Object::Global(name.clone()),
+            terms: vec![],
+            offset: 0,
+        }),
+        _ => None,
+    }
+}
+
+fn add_term(terms: &mut Vec<(Value, i64)>, value: Value, stride: i64) {
+    match terms.binary_search_by_key(&value, |(v, _)| *v) {
+        Ok(i) => {
+            terms[i].1 += stride;
+            if terms[i].1 == 0 {
+                terms.remove(i);
+            }
+        }
+        Err(i) => terms.insert(i, (value, stride)),
+    }
+}
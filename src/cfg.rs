@@ -0,0 +1,294 @@
+use crate::ir::{BlockId, Function, InstKind, Module};
+use std::fmt::Write;
+
+/*
+    控制流图: 每个函数的基本块和它们之间带种类的边. jump是无条件的Fallthrough边,
+    br产生BranchTrue和BranchFalse两条边(两个目标相同时也是两条). 之后的分析(活跃变量, 支配树, 死代码删除)都基于它.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    Fallthrough,
+    BranchTrue,
+    BranchFalse,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    pub from: BlockId,
+    pub to: BlockId,
+    pub kind: EdgeKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cfg {
+    pub entry: BlockId,
+    pub succs: Vec<Vec<Edge>>, //每个块出去的边, 按终结指令中的顺序
+    pub preds: Vec<Vec<Edge>>, //每个块进来的边, 按前驱块的顺序
+}
+
+impl Cfg {
+    pub fn build(function: &Function) -> Cfg {
+        let n = function.blocks.len();
+        let mut succs = vec![vec![]; n];
+        let mut preds = vec![vec![]; n];
+        for (i, block) in function.blocks.iter().enumerate() {
+            let from = BlockId(i as u32);
+            let edges = match block.terminator().map(|t| &t.kind) {
+                Some(InstKind::Jump(to)) => vec![(*to, EdgeKind::Fallthrough)],
+                Some(InstKind::Branch(_, on_true, on_false)) => vec![
+                    (*on_true, EdgeKind::BranchTrue),
+                    (*on_false, EdgeKind::BranchFalse),
+                ],
+                _ => vec![],
+            };
+            for (to, kind) in edges {
+                let edge = Edge { from, to, kind };
+                succs[i].push(edge);
+                preds[to.0 as usize].push(edge);
+            }
+        }
+        Cfg {
+            entry: BlockId(0),
+            succs,
+            preds,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.succs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.succs.is_empty()
+    }
+
+    pub fn successors(&self, block: BlockId) -> impl Iterator<Item = BlockId> + '_ {
+        self.succs[block.0 as usize].iter().map(|e| e.to)
+    }
+
+    pub fn predecessors(&self, block: BlockId) -> impl Iterator<Item = BlockId> + '_ {
+        self.preds[block.0 as usize].iter().map(|e| e.from)
+    }
+
+    pub fn edges(&self) -> impl Iterator<Item = &Edge> {
+        self.succs.iter().flatten()
+    }
+
+    /* 从入口出发的逆后序; 不可达的块不在其中. 前向数据流分析按这个顺序访问收敛最快. */
+    pub fn reverse_postorder(&self) -> Vec<BlockId> {
+        let mut order = vec![];
+        if self.is_empty() {
+            return order;
+        }
+        let mut visited = vec![false; self.len()];
+        // 显式的栈, 避免很长的if-else链使递归过深; 第二项是下一个要访问的后继.
+        let mut stack = vec![(self.entry, 0)];
+        visited[self.entry.0 as usize] = true;
+        while let Some((block, next)) = stack.last_mut() {
+            let succs = &self.succs[block.0 as usize];
+            if let Some(edge) = succs.get(*next) {
+                *next += 1;
+                if !std::mem::replace(&mut visited[edge.to.0 as usize], true) {
+                    stack.push((edge.to, 0));
+                }
+            } else {
+                order.push(*block);
+                stack.pop();
+            }
+        }
+        order.reverse();
+        order
+    }
+
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.len()];
+        for block in self.reverse_postorder() {
+            reachable[block.0 as usize] = true;
+        }
+        reachable
+    }
+}
+
+/*
+    支配树, 用Cooper, Harvey和Kennedy的迭代算法按逆后序求直接支配者.
+    入口和不可达的块没有直接支配者.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dominators {
+    idom: Vec<Option<BlockId>>,
+    children: Vec<Vec<BlockId>>,
+    order: Vec<Option<usize>>, //块在逆后序中的位置, 不可达的块为None
+}
+
+impl Dominators {
+    pub fn compute(cfg: &Cfg) -> Dominators {
+        let rpo = cfg.reverse_postorder();
+        let mut order = vec![None; cfg.len()];
+        for (i, b) in rpo.iter().enumerate() {
+            order[b.0 as usize] = Some(i);
+        }
+        let mut idom: Vec<Option<BlockId>> = vec![None; cfg.len()];
+        if let Some(&entry) = rpo.first() {
+            idom[entry.0 as usize] = Some(entry);
+        }
+        let intersect = |idom: &[Option<BlockId>], mut a: BlockId, mut b: BlockId| {
+            while a != b {
+                while order[a.0 as usize] > order[b.0 as usize] {
+                    a = idom[a.0 as usize].unwrap();
+                }
+                while order[b.0 as usize] > order[a.0 as usize] {
+                    b = idom[b.0 as usize].unwrap();
+                }
+            }
+            a
+        };
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &b in rpo.iter().skip(1) {
+                let mut new_idom = None;
+                for p in cfg.predecessors(b) {
+                    if idom[p.0 as usize].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => p,
+                        Some(q) => intersect(&idom, p, q),
+                    });
+                }
+                if new_idom.is_some() && idom[b.0 as usize] != new_idom {
+                    idom[b.0 as usize] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+        if let Some(&entry) = rpo.first() {
+            idom[entry.0 as usize] = None;
+        }
+        let mut children = vec![vec![]; cfg.len()];
+        for &b in &rpo {
+            if let Some(parent) = idom[b.0 as usize] {
+                children[parent.0 as usize].push(b);
+            }
+        }
+        Dominators {
+            idom,
+            children,
+            order,
+        }
+    }
+
+    pub fn idom(&self, block: BlockId) -> Option<BlockId> {
+        self.idom[block.0 as usize]
+    }
+
+    /* 支配树上的子节点, 按逆后序. */
+    pub fn children(&self, block: BlockId) -> &[BlockId] {
+        &self.children[block.0 as usize]
+    }
+
+    pub fn is_reachable(&self, block: BlockId) -> bool {
+        self.order[block.0 as usize].is_some()
+    }
+
+    /* a是否支配b(每个可达的块支配自己). */
+    pub fn dominates(&self, a: BlockId, mut b: BlockId) -> bool {
+        if !self.is_reachable(a) || !self.is_reachable(b) {
+            return false;
+        }
+        loop {
+            if a == b {
+                return true;
+            }
+            match self.idom(b) {
+                Some(parent) => b = parent,
+                None => return false,
+            }
+        }
+    }
+
+    /* 支配边界: b支配某个前驱但不严格支配的块. 插入phi的位置由它决定. */
+    pub fn frontiers(&self, cfg: &Cfg) -> Vec<Vec<BlockId>> {
+        let mut frontiers: Vec<Vec<BlockId>> = vec![vec![]; cfg.len()];
+        for b in 0..cfg.len() {
+            let block = BlockId(b as u32);
+            if !self.is_reachable(block) {
+                continue;
+            }
+            let preds: Vec<BlockId> = cfg
+                .predecessors(block)
+                .filter(|p| self.is_reachable(*p))
+                .collect();
+            if preds.len() < 2 {
+                continue;
+            }
+            for p in preds {
+                let mut runner = p;
+                while Some(runner) != self.idom(block) {
+                    if !frontiers[runner.0 as usize].contains(&block) {
+                        frontiers[runner.0 as usize].push(block);
+                    }
+                    match self.idom(runner) {
+                        Some(parent) => runner = parent,
+                        None => break,
+                    }
+                }
+            }
+        }
+        frontiers
+    }
+}
+
+/* DOT中双引号字符串内的转义; 指令按行左对齐(\l). */
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/* 一个函数的控制流图, 作为DOT的子图; 节点名带函数名前缀, 多个函数可以放在同一张图里. */
+fn write_function(out: &mut String, function: &Function) {
+    let cfg = Cfg::build(function);
+    let name = &function.name;
+    let _ = writeln!(out, "  subgraph \"cluster_{}\" {{", escape(name));
+    let _ = writeln!(out, "    label=\"{}\";", escape(name));
+    for (i, block) in function.blocks.iter().enumerate() {
+        let mut label = format!("{}:\\l", BlockId(i as u32));
+        for inst in &block.insts {
+            label.push_str(&format!("  {}\\l", escape(&inst.to_string())));
+        }
+        let _ = writeln!(
+            out,
+            "    \"{}.bb{}\" [label=\"{}\"];",
+            escape(name),
+            i,
+            label
+        );
+    }
+    for edge in cfg.edges() {
+        let style = match edge.kind {
+            EdgeKind::Fallthrough => "",
+            EdgeKind::BranchTrue => " [label=\"true\", color=darkgreen]",
+            EdgeKind::BranchFalse => " [label=\"false\", color=red]",
+        };
+        let _ = writeln!(
+            out,
+            "    \"{}.{}\" -> \"{}.{}\"{};",
+            escape(name),
+            edge.from,
+            escape(name),
+            edge.to,
+            style
+        );
+    }
+    let _ = writeln!(out, "  }}");
+}
+
+/* 模块中所有有定义的函数的控制流图, Graphviz的DOT格式. */
+pub fn to_dot(module: &Module) -> String {
+    let mut out = String::from("digraph cfg {\n  node [shape=box, fontname=\"monospace\"];\n");
+    for function in module.functions.iter().filter(|f| !f.is_declaration()) {
+        write_function(&mut out, function);
+    }
+    out.push_str("}\n");
+    out
+}
@@ -0,0 +1,13 @@
+/*
+    后端: 把三地址码(见ir模块)翻译成目标代码.
+    llvm: LLVM IR文本(.ll), 可以用lli直接运行或交给clang编译, 用来在本地后端完成之前检查生成的代码.
+    koopa: Koopa IR文本(.koopa), 北大编译原理课程的评测工具可以直接接受.
+    frame: 本地后端的栈帧布局和各目标的调用约定.
+    riscv: RV64GC汇编(.s), 第一个本地后端.
+    object: 把riscv的汇编直接汇编成ELF目标文件(.o), 可以不经过外部汇编器直接链接.
+*/
+pub mod frame;
+pub mod koopa;
+pub mod llvm;
+pub mod object;
+pub mod riscv;
@@ -0,0 +1,375 @@
+use crate::{
+    ir::{Function, InstKind, Module, Operand, Type, Value},
+    target::Target,
+};
+use std::fmt::{self, Write};
+
+/*
+    栈帧布局和调用约定: 本地后端需要的偏移都在这里算好, 各目标的汇编输出只读取Frame, 不自己计算偏移.
+    栈帧从sp向上依次是: 调用其他函数时在栈上传递的实参, 每个值的溢出槽, phi的影子槽, 局部数组(alloca), 保存的寄存器.
+    目前的代码生成不做寄存器分配, 每个有结果的值都有一个溢出槽; phi的值先由前驱写入影子槽,
+    到块的开头再复制到自己的槽中, 这样同一个块的多个phi不会互相覆盖.
+    偏移都相对于序言之后的sp; 栈上传入的形参在sp + size之上, 即调用者的实参区.
+*/
+
+/* 一个目标的调用约定(RISC-V的LP64D, ARM的AAPCS-VFP硬浮点). */
+#[derive(Debug, PartialEq, Eq)]
+pub struct Abi {
+    pub target: Target,
+    pub word: i64,        //指针和栈上实参槽的字节数
+    pub stack_align: i64, //调用时sp的对齐
+    pub int_args: &'static [&'static str],
+    pub float_args: &'static [&'static str],
+    pub int_ret: &'static str,
+    pub float_ret: &'static str,
+    pub return_address: &'static str,
+    pub callee_saved: &'static [&'static str],
+    pub max_offset: i64, //load/store的立即数偏移能直接编码的最大值, 最小值是-max_offset - 1
+}
+
+static RISCV64: Abi = Abi {
+    target: Target::Riscv64,
+    word: 8,
+    stack_align: 16,
+    int_args: &["a0", "a1", "a2", "a3", "a4", "a5", "a6", "a7"],
+    float_args: &["fa0", "fa1", "fa2", "fa3", "fa4", "fa5", "fa6", "fa7"],
+    int_ret: "a0",
+    float_ret: "fa0",
+    return_address: "ra",
+    callee_saved: &[
+        "s0", "s1", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "fs0", "fs1",
+        "fs2", "fs3", "fs4", "fs5", "fs6", "fs7", "fs8", "fs9", "fs10", "fs11",
+    ],
+    max_offset: 2047,
+};
+
+// vldr/vstr的偏移只有8位(乘4), 比ldr/str的12位小, 按小的算.
+static ARMV7: Abi = Abi {
+    target: Target::Armv7,
+    word: 4,
+    stack_align: 8,
+    int_args: &["r0", "r1", "r2", "r3"],
+    float_args: &[
+        "s0", "s1", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "s12", "s13",
+        "s14", "s15",
+    ],
+    int_ret: "r0",
+    float_ret: "s0",
+    return_address: "lr",
+    callee_saved: &[
+        "r4", "r5", "r6", "r7", "r8", "r9", "r10", "r11", "s16", "s17", "s18", "s19", "s20", "s21",
+        "s22", "s23", "s24", "s25", "s26", "s27", "s28", "s29", "s30", "s31",
+    ],
+    max_offset: 1020,
+};
+
+/* 一个实参的位置. Stack是相对于调用时sp的偏移. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgLocation {
+    Reg(&'static str),
+    Pair(&'static str, &'static str), //ARM变长参数中的double占一对相邻的通用寄存器
+    Stack(i64),
+}
+
+impl fmt::Display for ArgLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArgLocation::Reg(r) => write!(f, "{}", r),
+            ArgLocation::Pair(lo, hi) => write!(f, "{}:{}", lo, hi),
+            ArgLocation::Stack(offset) => write!(f, "[sp+{}]", offset),
+        }
+    }
+}
+
+fn align_to(n: i64, align: i64) -> i64 {
+    (n + align - 1) / align * align
+}
+
+impl Abi {
+    pub fn for_target(target: Target) -> &'static Abi {
+        match target {
+            Target::Riscv64 => &RISCV64,
+            Target::Armv7 => &ARMV7,
+        }
+    }
+
+    /* 一个值在溢出槽中占的字节数. */
+    pub fn size_of(&self, ty: Type) -> i64 {
+        match ty {
+            Type::Void => 0,
+            Type::I32 | Type::F32 => 4,
+            Type::Ptr => self.word,
+        }
+    }
+
+    pub fn fits_offset(&self, offset: i64) -> bool {
+        (-self.max_offset - 1..=self.max_offset).contains(&offset)
+    }
+
+    /*
+        按顺序给实参分配寄存器, 寄存器用完后放到栈上, 返回每个实参的位置和栈上实参区的大小(已对齐).
+        下标从fixed开始的是变长参数, 其中的float按C的规则提升为double.
+        RISC-V: 整数和指针用a0-a7, float用fa0-fa7, 浮点寄存器用完后用剩下的整数寄存器;
+        变长参数都走整数寄存器. 栈上每个实参占8字节.
+        ARM: 整数和指针用r0-r3, float用s0-s15; 栈上每个实参占4字节,
+        变长参数中的double放在一对从偶数号开始的寄存器或8字节对齐的栈槽中.
+    */
+    pub fn assign_args(&self, types: &[Type], fixed: usize) -> (Vec<ArgLocation>, i64) {
+        let mut next_int = 0;
+        let mut next_float = 0;
+        let mut stack = 0;
+        let mut locations = vec![];
+        for (i, &ty) in types.iter().enumerate() {
+            let variadic = i >= fixed;
+            let location = match (self.target, ty) {
+                (_, Type::F32) if !variadic && next_float < self.float_args.len() => {
+                    next_float += 1;
+                    ArgLocation::Reg(self.float_args[next_float - 1])
+                }
+                (Target::Armv7, Type::F32) if variadic => {
+                    next_int += next_int % 2;
+                    if next_int + 1 < self.int_args.len() {
+                        next_int += 2;
+                        ArgLocation::Pair(self.int_args[next_int - 2], self.int_args[next_int - 1])
+                    } else {
+                        next_int = self.int_args.len();
+                        stack = align_to(stack, 8) + 8;
+                        ArgLocation::Stack(stack - 8)
+                    }
+                }
+                (Target::Armv7, Type::F32) => {
+                    stack += 4;
+                    ArgLocation::Stack(stack - 4)
+                }
+                _ if next_int < self.int_args.len() => {
+                    next_int += 1;
+                    ArgLocation::Reg(self.int_args[next_int - 1])
+                }
+                _ => {
+                    stack += self.word;
+                    ArgLocation::Stack(stack - self.word)
+                }
+            };
+            locations.push(location);
+        }
+        (locations, align_to(stack, self.stack_align))
+    }
+
+    /* 返回值所在的寄存器; void没有返回值. */
+    pub fn return_register(&self, ty: Type) -> Option<&'static str> {
+        match ty {
+            Type::Void => None,
+            Type::F32 => Some(self.float_ret),
+            Type::I32 | Type::Ptr => Some(self.int_ret),
+        }
+    }
+
+    /* 调用name时各实参的位置; 不认识的函数按没有变长参数处理. */
+    pub fn call_args(
+        &self,
+        module: &Module,
+        caller: &Function,
+        name: &str,
+        args: &[Operand],
+    ) -> (Vec<ArgLocation>, i64) {
+        let types: Vec<Type> = args.iter().map(|a| caller.operand_type(a)).collect();
+        let fixed = module
+            .function(name)
+            .filter(|f| f.variadic)
+            .map_or(args.len(), |f| f.params.len());
+        self.assign_args(&types, fixed)
+    }
+}
+
+/* 一个局部数组: alloca的结果, 偏移和字节数. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Local {
+    pub value: Value,
+    pub name: String,
+    pub offset: i64,
+    pub size: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub abi: &'static Abi,
+    pub size: i64,                    //序言中sp减去的字节数, 按abi.stack_align对齐
+    pub outgoing: i64,                //[sp, sp + outgoing)是调用时栈上传递的实参
+    pub slots: Vec<Option<i64>>,      //每个值的溢出槽, 下标是值的编号; alloca和没有结果的值为None
+    pub phi_copies: Vec<Option<i64>>, //phi的影子槽
+    pub locals: Vec<Local>,
+    pub saved: Vec<(&'static str, i64)>, //保存的寄存器和槽的偏移
+    pub params: Vec<ArgLocation>, //形参传入的位置, Stack相对于调用者的sp(即本函数的sp + size)
+}
+
+impl Frame {
+    pub fn build(module: &Module, function: &Function, target: Target) -> Frame {
+        let abi = Abi::for_target(target);
+        let count = function.value_types.len();
+        let mut slots = vec![None; count];
+        let mut phi_copies = vec![None; count];
+        let mut allocas = vec![];
+        let mut phis = vec![];
+        let mut outgoing = 0;
+        let mut calls = false;
+        for inst in function.blocks.iter().flat_map(|b| &b.insts) {
+            match &inst.kind {
+                InstKind::Alloca { len, name, .. } => {
+                    allocas.push((inst.dest.unwrap(), name.clone(), *len as i64 * 4))
+                }
+                InstKind::Phi(_) => phis.push(inst.dest.unwrap()),
+                InstKind::Call(name, args) => {
+                    calls = true;
+                    let (_, stack) = abi.call_args(module, function, name, args);
+                    outgoing = outgoing.max(stack);
+                }
+                _ => {}
+            }
+        }
+        // 先放形参和指令的结果, 再放phi的影子槽; 每个槽按自己的大小对齐.
+        let mut offset = outgoing;
+        let is_alloca: Vec<bool> = {
+            let mut flags = vec![false; count];
+            for (v, ..) in &allocas {
+                flags[v.0 as usize] = true;
+            }
+            flags
+        };
+        for v in 0..count {
+            let size = abi.size_of(function.value_types[v]);
+            if size == 0 || is_alloca[v] {
+                continue;
+            }
+            offset = align_to(offset, size);
+            slots[v] = Some(offset);
+            offset += size;
+        }
+        for v in phis {
+            let size = abi.size_of(function.value_type(v));
+            offset = align_to(offset, size);
+            phi_copies[v.0 as usize] = Some(offset);
+            offset += size;
+        }
+        // 数组按字长对齐, 以后可以按字长成对读写.
+        let mut locals = vec![];
+        for (value, name, size) in allocas {
+            offset = align_to(offset, abi.word);
+            locals.push(Local {
+                value,
+                name,
+                offset,
+                size,
+            });
+            offset += size;
+        }
+        let mut saved = vec![];
+        if calls {
+            offset = align_to(offset, abi.word);
+            saved.push((abi.return_address, offset));
+            offset += abi.word;
+        }
+        let (params, _) = abi.assign_args(&function.params, function.params.len());
+        Frame {
+            abi,
+            size: align_to(offset, abi.stack_align),
+            outgoing,
+            slots,
+            phi_copies,
+            locals,
+            saved,
+            params,
+        }
+    }
+
+    pub fn slot(&self, value: Value) -> Option<i64> {
+        self.slots[value.0 as usize]
+    }
+
+    pub fn phi_copy(&self, value: Value) -> Option<i64> {
+        self.phi_copies[value.0 as usize]
+    }
+
+    pub fn local(&self, value: Value) -> Option<&Local> {
+        self.locals.iter().find(|l| l.value == value)
+    }
+
+    /* 形参在栈上传入时, 它相对于序言之后的sp的偏移. */
+    pub fn incoming(&self, param: usize) -> Option<i64> {
+        match self.params[param] {
+            ArgLocation::Stack(offset) => Some(self.size + offset),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let far = |offset: i64| {
+            if self.abi.fits_offset(offset) {
+                ""
+            } else {
+                "  (out of immediate range)"
+            }
+        };
+        writeln!(f, "  frame {} bytes", self.size)?;
+        if self.outgoing > 0 {
+            writeln!(f, "  sp+0: outgoing arguments, {} bytes", self.outgoing)?;
+        }
+        for (v, slot) in self.slots.iter().enumerate() {
+            if let Some(offset) = slot {
+                writeln!(f, "  sp+{}: %{}{}", offset, v, far(*offset))?;
+            }
+        }
+        for (v, slot) in self.phi_copies.iter().enumerate() {
+            if let Some(offset) = slot {
+                writeln!(f, "  sp+{}: %{} phi copy{}", offset, v, far(*offset))?;
+            }
+        }
+        for local in &self.locals {
+            writeln!(
+                f,
+                "  sp+{}: %{} {}, {} bytes{}",
+                local.offset,
+                local.value.0,
+                local.name,
+                local.size,
+                far(local.offset + local.size - 4)
+            )?;
+        }
+        for (reg, offset) in &self.saved {
+            writeln!(f, "  sp+{}: saved {}{}", offset, reg, far(*offset))?;
+        }
+        for (i, location) in self.params.iter().enumerate() {
+            match self.incoming(i) {
+                Some(offset) => writeln!(f, "  param %{} in sp+{}", i, offset)?,
+                None => writeln!(f, "  param %{} in {}", i, location)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/* 每个函数的栈帧布局(--dump-frame), 以及其中每个调用的实参和返回值的位置. */
+pub fn dump(module: &Module, target: Target) -> String {
+    let abi = Abi::for_target(target);
+    let mut out = String::new();
+    for function in module.functions.iter().filter(|f| !f.is_declaration()) {
+        let _ = writeln!(out, "@{} ({}):", function.name, target.name());
+        let _ = write!(out, "{}", Frame::build(module, function, target));
+        for inst in function.blocks.iter().flat_map(|b| &b.insts) {
+            let InstKind::Call(name, args) = &inst.kind else {
+                continue;
+            };
+            let (locations, _) = abi.call_args(module, function, name, args);
+            let locations: Vec<String> = locations.iter().map(|l| l.to_string()).collect();
+            let _ = write!(out, "  call @{}({})", name, locations.join(", "));
+            match abi.return_register(inst.ty) {
+                Some(reg) => {
+                    let _ = writeln!(out, " -> {}", reg);
+                }
+                None => out.push('\n'),
+            }
+        }
+    }
+    out
+}
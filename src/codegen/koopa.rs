@@ -0,0 +1,362 @@
+use crate::ir::{BinOp, BlockId, CmpOp, Function, Global, Inst, InstKind, Module, Operand, Type};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+/*
+    三地址码 -> Koopa IR文本(北大编译原理课程使用的格式), 局部变量是alloc出的内存, 与三地址码相同.
+    Koopa只有i32, 没有float和字符串, 用到它们的程序(包括putf)无法翻译, 返回错误.
+    指针都按*i32处理: 数组的alloc先用getelemptr取得首元素的地址, gep的步长乘到下标上再getptr.
+    phi改写成Koopa的基本块参数, 由前驱的jump/br传入.
+*/
+pub fn emit(module: &Module) -> Result<String, String> {
+    if !module.strings.is_empty() {
+        return Err("Koopa IR has no string constants (putf is not supported)".to_string());
+    }
+    // 与课程的习惯相同, 库函数的声明放在最前面.
+    let mut out = String::new();
+    let (decls, defs): (Vec<_>, Vec<_>) = module.functions.iter().partition(|f| f.is_declaration());
+    for function in &decls {
+        FnEmitter::new(module, function, &mut out).emit()?;
+    }
+    if !decls.is_empty() {
+        out.push('\n');
+    }
+    for global in &module.globals {
+        emit_global(&mut out, global)?;
+    }
+    for function in defs {
+        if !out.is_empty() && !out.ends_with("\n\n") {
+            out.push('\n');
+        }
+        FnEmitter::new(module, function, &mut out).emit()?;
+    }
+    Ok(out)
+}
+
+fn ty(t: Type) -> Result<&'static str, String> {
+    match t {
+        Type::I32 => Ok("i32"),
+        Type::Ptr => Ok("*i32"),
+        Type::Void => Ok("unit"),
+        Type::F32 => Err("Koopa IR has no float type".to_string()),
+    }
+}
+
+fn alloc_type(elem: Type, len: usize) -> Result<String, String> {
+    let elem = ty(elem)?;
+    Ok(if len == 1 {
+        elem.to_string()
+    } else {
+        format!("[{}, {}]", elem, len)
+    })
+}
+
+/* Koopa的名字中不能有'.', 换成'_'(提升成全局常量的局部数组名为"函数.变量"). */
+fn symbol(name: &str) -> String {
+    name.replace('.', "_")
+}
+
+fn emit_global(out: &mut String, global: &Global) -> Result<(), String> {
+    let init = if global.init.is_empty() {
+        "zeroinit".to_string()
+    } else {
+        let items = global
+            .init
+            .iter()
+            .map(|v| match v {
+                Operand::Int(n) => Ok(n.to_string()),
+                _ => Err("Koopa IR has no float type".to_string()),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if global.len == 1 {
+            items[0].clone()
+        } else {
+            format!("{{{}}}", items.join(", "))
+        }
+    };
+    let _ = writeln!(
+        out,
+        "global @{} = alloc {}, {}",
+        symbol(&global.name),
+        alloc_type(global.elem, global.len)?,
+        init
+    );
+    Ok(())
+}
+
+fn block(id: BlockId) -> String {
+    format!("%bb{}", id.0)
+}
+
+struct FnEmitter<'a> {
+    module: &'a Module,
+    func: &'a Function,
+    out: &'a mut String,
+    temps: usize,
+    arrays: HashSet<u32>, //alloc [i32, N]的结果, 使用前要转换成*i32
+    args: HashMap<(BlockId, BlockId), Vec<Operand>>, //(前驱, 块) -> 传给块参数的值
+}
+
+impl<'a> FnEmitter<'a> {
+    fn new(module: &'a Module, func: &'a Function, out: &'a mut String) -> Self {
+        let mut args: HashMap<(BlockId, BlockId), Vec<Operand>> = HashMap::new();
+        for (i, b) in func.blocks.iter().enumerate() {
+            for inst in &b.insts {
+                if let InstKind::Phi(incoming) = &inst.kind {
+                    for (pred, value) in incoming {
+                        args.entry((*pred, BlockId(i as u32)))
+                            .or_default()
+                            .push(value.clone());
+                    }
+                }
+            }
+        }
+        FnEmitter {
+            module,
+            func,
+            out,
+            temps: 0,
+            arrays: HashSet::new(),
+            args,
+        }
+    }
+
+    fn emit(mut self) -> Result<(), String> {
+        let func = self.func;
+        let ret = match func.ret {
+            Type::Void => String::new(),
+            t => format!(": {}", ty(t)?),
+        };
+        if func.is_declaration() {
+            if func.variadic {
+                return Err(format!(
+                    "Koopa IR has no variadic functions (`{}`)",
+                    func.name
+                ));
+            }
+            let params = func
+                .params
+                .iter()
+                .map(|&t| ty(t))
+                .collect::<Result<Vec<_>, _>>()?;
+            let _ = writeln!(
+                self.out,
+                "decl @{}({}){}",
+                func.name,
+                params.join(", "),
+                ret
+            );
+            return Ok(());
+        }
+        let params = func
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, &t)| Ok(format!("%v{}: {}", i, ty(t)?)))
+            .collect::<Result<Vec<_>, String>>()?;
+        let _ = writeln!(
+            self.out,
+            "fun @{}({}){} {{",
+            func.name,
+            params.join(", "),
+            ret
+        );
+        // Koopa的入口块不能有前驱, 需要时先进入一个只跳转到bb0的块.
+        if !func.predecessors()[0].is_empty() {
+            let _ = writeln!(self.out, "%entry:\n  jump %bb0");
+        }
+        for (i, b) in func.blocks.iter().enumerate() {
+            let phis: Vec<String> = b
+                .insts
+                .iter()
+                .filter(|inst| matches!(inst.kind, InstKind::Phi(_)))
+                .map(|inst| Ok(format!("%v{}: {}", inst.dest.unwrap().0, ty(inst.ty)?)))
+                .collect::<Result<_, String>>()?;
+            if phis.is_empty() {
+                let _ = writeln!(self.out, "%bb{}:", i);
+            } else {
+                let _ = writeln!(self.out, "%bb{}({}):", i, phis.join(", "));
+            }
+            for inst in &b.insts {
+                self.inst(BlockId(i as u32), inst)?;
+            }
+        }
+        let _ = writeln!(self.out, "}}");
+        Ok(())
+    }
+
+    fn temp(&mut self) -> String {
+        self.temps += 1;
+        format!("%t{}", self.temps)
+    }
+
+    fn line(&mut self, text: String) {
+        let _ = writeln!(self.out, "  {}", text);
+    }
+
+    fn operand(&self, value: &Operand) -> Result<String, String> {
+        match value {
+            Operand::Value(v) => Ok(format!("%v{}", v.0)),
+            Operand::Int(n) => Ok(n.to_string()),
+            Operand::Global(name) => Ok(format!("@{}", symbol(name))),
+            Operand::Float(_) => Err("Koopa IR has no float type".to_string()),
+            Operand::Str(_) => Err("Koopa IR has no string constants".to_string()),
+        }
+    }
+
+    /* 作为*i32使用的地址: 数组(全局的或alloc出的)先取首元素的地址. */
+    fn pointer(&mut self, value: &Operand) -> Result<String, String> {
+        let is_array = match value {
+            Operand::Global(name) => self.module.global(name).is_some_and(|g| g.len > 1),
+            Operand::Value(v) => self.arrays.contains(&v.0),
+            _ => false,
+        };
+        let text = self.operand(value)?;
+        if !is_array {
+            return Ok(text);
+        }
+        let t = self.temp();
+        self.line(format!("{} = getelemptr {}, 0", t, text));
+        Ok(t)
+    }
+
+    /* 指针类型的实参和返回值也要转换, 其余的值原样使用. */
+    fn value(&mut self, value: &Operand) -> Result<String, String> {
+        if self.func.operand_type(value) == Type::Ptr {
+            self.pointer(value)
+        } else {
+            self.operand(value)
+        }
+    }
+
+    /* 跳转到target时附带它的块参数. */
+    fn target(&mut self, from: BlockId, target: BlockId) -> Result<String, String> {
+        let Some(args) = self.args.get(&(from, target)).cloned() else {
+            return Ok(block(target));
+        };
+        let args = args
+            .iter()
+            .map(|a| self.value(a))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(format!("{}({})", block(target), args.join(", ")))
+    }
+
+    fn inst(&mut self, current: BlockId, inst: &Inst) -> Result<(), String> {
+        use InstKind::*;
+        let dest = inst.dest.map(|v| format!("%v{}", v.0)).unwrap_or_default();
+        match &inst.kind {
+            Binary(op, a, b) => {
+                let name = match op {
+                    BinOp::Add => "add",
+                    BinOp::Sub => "sub",
+                    BinOp::Mul => "mul",
+                    BinOp::Div => "div",
+                    BinOp::Rem => "mod",
+                    BinOp::Shl => "shl",
+                    BinOp::Shr => "sar",
+                    BinOp::And => "and",
+                    BinOp::Or => "or",
+                    BinOp::Xor => "xor",
+                    _ => return Err("Koopa IR has no float type".to_string()),
+                };
+                let line = format!(
+                    "{} = {} {}, {}",
+                    dest,
+                    name,
+                    self.operand(a)?,
+                    self.operand(b)?
+                );
+                self.line(line);
+            }
+            Icmp(op, a, b) => {
+                let name = match op {
+                    CmpOp::Eq => "eq",
+                    CmpOp::Ne => "ne",
+                    CmpOp::Lt => "lt",
+                    CmpOp::Le => "le",
+                    CmpOp::Gt => "gt",
+                    CmpOp::Ge => "ge",
+                };
+                let line = format!(
+                    "{} = {} {}, {}",
+                    dest,
+                    name,
+                    self.operand(a)?,
+                    self.operand(b)?
+                );
+                self.line(line);
+            }
+            Fcmp(..) | FNeg(_) | IntToFloat(_) | FloatToInt(_) => {
+                return Err("Koopa IR has no float type".to_string())
+            }
+            Alloca { elem, len, .. } => {
+                if *len > 1 {
+                    self.arrays.insert(inst.dest.unwrap().0);
+                }
+                let line = format!("{} = alloc {}", dest, alloc_type(*elem, *len)?);
+                self.line(line);
+            }
+            Load(addr) => {
+                let addr = self.pointer(addr)?;
+                self.line(format!("{} = load {}", dest, addr));
+            }
+            Store(value, addr) => {
+                let value = self.value(value)?;
+                let addr = self.pointer(addr)?;
+                self.line(format!("store {}, {}", value, addr));
+            }
+            Gep(base, index, stride) => {
+                let base = self.pointer(base)?;
+                let offset = match (index, stride) {
+                    (Operand::Int(n), _) => n.wrapping_mul(*stride as i32).to_string(),
+                    (index, 1) => self.operand(index)?,
+                    (index, _) => {
+                        let t = self.temp();
+                        let line = format!("{} = mul {}, {}", t, self.operand(index)?, stride);
+                        self.line(line);
+                        t
+                    }
+                };
+                self.line(format!("{} = getptr {}, {}", dest, base, offset));
+            }
+            Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|a| self.value(a))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let assign = if dest.is_empty() {
+                    String::new()
+                } else {
+                    format!("{} = ", dest)
+                };
+                self.line(format!("{}call @{}({})", assign, name, args.join(", ")));
+            }
+            // 已经作为块参数声明.
+            Phi(_) => {}
+            Copy(a) => {
+                let line = if inst.ty == Type::Ptr {
+                    format!("{} = getptr {}, 0", dest, self.pointer(a)?)
+                } else {
+                    format!("{} = add {}, 0", dest, self.operand(a)?)
+                };
+                self.line(line);
+            }
+            Jump(target) => {
+                let target = self.target(current, *target)?;
+                self.line(format!("jump {}", target));
+            }
+            Branch(cond, on_true, on_false) => {
+                let cond = self.operand(cond)?;
+                let on_true = self.target(current, *on_true)?;
+                let on_false = self.target(current, *on_false)?;
+                self.line(format!("br {}, {}, {}", cond, on_true, on_false));
+            }
+            Ret(None) => self.line("ret".to_string()),
+            Ret(Some(value)) => {
+                let value = self.value(value)?;
+                self.line(format!("ret {}", value));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,350 @@
+use crate::ir::{BinOp, BlockId, CmpOp, Function, Global, Inst, InstKind, Module, Operand, Type};
+use std::fmt::Write;
+
+/*
+    三地址码 -> LLVM IR文本. 使用不透明指针(ptr), LLVM 15以前的工具需要-opaque-pointers.
+    值命名为%vN, 块命名为bbN, 与三地址码的文本形式一一对应; 翻译中产生的临时值命名为%tN.
+    三地址码的比较结果和条件是i32, 翻译时用zext和icmp ne在i1和i32之间转换.
+    所有元素都是4字节, gep按[stride x i32]寻址, 对float数组同样适用.
+*/
+pub fn emit(module: &Module) -> String {
+    let mut out = String::new();
+    for global in &module.globals {
+        emit_global(&mut out, global);
+    }
+    for (i, s) in module.strings.iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "@.str.{} = private unnamed_addr constant [{} x i8] c\"{}\\00\"",
+            i,
+            s.len() + 1,
+            escape(s)
+        );
+    }
+    for function in &module.functions {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        FnEmitter::new(module, function, &mut out).emit();
+    }
+    out
+}
+
+fn ty(ty: Type) -> &'static str {
+    match ty {
+        Type::Void => "void",
+        Type::I32 => "i32",
+        Type::F32 => "float",
+        Type::Ptr => "ptr",
+    }
+}
+
+/* LLVM的float常量写成与之相等的double的十六进制形式, 这样任何float都能精确表示. */
+fn float(x: f32) -> String {
+    format!("0x{:016X}", (x as f64).to_bits())
+}
+
+fn constant(value: &Operand) -> String {
+    match value {
+        Operand::Float(x) => float(*x),
+        other => operand(other),
+    }
+}
+
+fn operand(value: &Operand) -> String {
+    match value {
+        Operand::Value(v) => format!("%v{}", v.0),
+        Operand::Int(n) => n.to_string(),
+        Operand::Float(x) => float(*x),
+        Operand::Global(name) => format!("@{}", name),
+        Operand::Str(i) => format!("@.str.{}", i),
+    }
+}
+
+fn block(id: BlockId) -> String {
+    format!("%bb{}", id.0)
+}
+
+/* c"..."中可打印字符原样输出, 引号, 反斜杠和其他字节写成\XX. */
+fn escape(s: &str) -> String {
+    let mut out = String::new();
+    for b in s.bytes() {
+        if (0x20..0x7f).contains(&b) && b != b'"' && b != b'\\' {
+            out.push(b as char);
+        } else {
+            let _ = write!(out, "\\{:02X}", b);
+        }
+    }
+    out
+}
+
+fn emit_global(out: &mut String, global: &Global) {
+    let keyword = if global.constant {
+        "constant"
+    } else {
+        "global"
+    };
+    let elem = ty(global.elem);
+    let init = if global.init.is_empty() {
+        "zeroinitializer".to_string()
+    } else {
+        let items: Vec<_> = global
+            .init
+            .iter()
+            .map(|v| format!("{} {}", elem, constant(v)))
+            .collect();
+        format!("[{}]", items.join(", "))
+    };
+    let _ = writeln!(
+        out,
+        "@{} = {} [{} x {}] {}",
+        global.name, keyword, global.len, elem, init
+    );
+}
+
+fn signature(function: &Function) -> String {
+    let mut params: Vec<String> = function.params.iter().map(|&t| ty(t).to_string()).collect();
+    if function.variadic {
+        params.push("...".to_string());
+    }
+    params.join(", ")
+}
+
+struct FnEmitter<'a> {
+    module: &'a Module,
+    func: &'a Function,
+    out: &'a mut String,
+    temps: usize,
+}
+
+impl<'a> FnEmitter<'a> {
+    fn new(module: &'a Module, func: &'a Function, out: &'a mut String) -> Self {
+        FnEmitter {
+            module,
+            func,
+            out,
+            temps: 0,
+        }
+    }
+
+    fn emit(mut self) {
+        let func = self.func;
+        if func.is_declaration() {
+            let _ = writeln!(
+                self.out,
+                "declare {} @{}({})",
+                ty(func.ret),
+                func.name,
+                signature(func)
+            );
+            return;
+        }
+        let params: Vec<_> = func
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, &t)| format!("{} %v{}", ty(t), i))
+            .collect();
+        let _ = writeln!(
+            self.out,
+            "define {} @{}({}) {{",
+            ty(func.ret),
+            func.name,
+            params.join(", ")
+        );
+        // LLVM的入口块不能有前驱, 需要时先进入一个只跳转到bb0的块.
+        if !func.predecessors()[0].is_empty() {
+            let _ = writeln!(self.out, "entry:\n  br label %bb0");
+        }
+        for (i, b) in func.blocks.iter().enumerate() {
+            let _ = writeln!(self.out, "bb{}:", i);
+            for inst in &b.insts {
+                self.inst(inst);
+            }
+        }
+        let _ = writeln!(self.out, "}}");
+    }
+
+    fn temp(&mut self) -> String {
+        self.temps += 1;
+        format!("%t{}", self.temps)
+    }
+
+    fn line(&mut self, text: String) {
+        let _ = writeln!(self.out, "  {}", text);
+    }
+
+    /* 带类型的操作数, 如i32 %v3. */
+    fn typed(&self, value: &Operand) -> String {
+        format!("{} {}", ty(self.func.operand_type(value)), operand(value))
+    }
+
+    /* 把i32的条件转换成i1; 常量条件直接写成true/false. */
+    fn condition(&mut self, value: &Operand) -> String {
+        if let Operand::Int(n) = value {
+            return if *n != 0 { "true" } else { "false" }.to_string();
+        }
+        let t = self.temp();
+        self.line(format!("{} = icmp ne i32 {}, 0", t, operand(value)));
+        t
+    }
+
+    fn inst(&mut self, inst: &Inst) {
+        use InstKind::*;
+        let dest = inst.dest.map(|v| format!("%v{}", v.0)).unwrap_or_default();
+        match &inst.kind {
+            Binary(op, a, b) => {
+                let name = match (op, inst.ty) {
+                    (BinOp::Div, _) => "sdiv",
+                    (BinOp::Rem, Type::F32) => "frem",
+                    (BinOp::Rem, _) => "srem",
+                    (BinOp::Shr, _) => "ashr",
+                    (op, _) => op.name(),
+                };
+                self.line(format!(
+                    "{} = {} {} {}, {}",
+                    dest,
+                    name,
+                    ty(inst.ty),
+                    operand(a),
+                    operand(b)
+                ));
+            }
+            Icmp(op, a, b) | Fcmp(op, a, b) => {
+                let (kind, name) = if matches!(inst.kind, Icmp(..)) {
+                    ("icmp", icmp(*op))
+                } else {
+                    ("fcmp", fcmp(*op))
+                };
+                let t = self.temp();
+                let line = format!(
+                    "{} = {} {} {}, {}",
+                    t,
+                    kind,
+                    name,
+                    self.typed(a),
+                    operand(b)
+                );
+                self.line(line);
+                self.line(format!("{} = zext i1 {} to i32", dest, t));
+            }
+            FNeg(a) => self.line(format!("{} = fneg float {}", dest, operand(a))),
+            IntToFloat(a) => self.line(format!("{} = sitofp i32 {} to float", dest, operand(a))),
+            FloatToInt(a) => self.line(format!("{} = fptosi float {} to i32", dest, operand(a))),
+            Alloca { elem, len, .. } => {
+                self.line(format!("{} = alloca [{} x {}]", dest, len, ty(*elem)))
+            }
+            Load(addr) => self.line(format!(
+                "{} = load {}, ptr {}",
+                dest,
+                ty(inst.ty),
+                operand(addr)
+            )),
+            Store(value, addr) => {
+                let line = format!("store {}, ptr {}", self.typed(value), operand(addr));
+                self.line(line);
+            }
+            Gep(base, index, stride) => self.line(format!(
+                "{} = getelementptr [{} x i32], ptr {}, i32 {}",
+                dest,
+                stride,
+                operand(base),
+                operand(index)
+            )),
+            Call(name, args) => self.call(&dest, inst.ty, name, args),
+            Phi(incoming) => {
+                let items: Vec<_> = incoming
+                    .iter()
+                    .map(|(b, v)| format!("[ {}, {} ]", operand(v), block(*b)))
+                    .collect();
+                self.line(format!(
+                    "{} = phi {} {}",
+                    dest,
+                    ty(inst.ty),
+                    items.join(", ")
+                ));
+            }
+            // LLVM没有复制指令, 用结果不变的运算代替.
+            Copy(a) => {
+                let line = match inst.ty {
+                    Type::F32 => format!("{} = fadd float {}, -0.0", dest, operand(a)),
+                    Type::Ptr => format!("{} = getelementptr i8, ptr {}, i32 0", dest, operand(a)),
+                    _ => format!("{} = add i32 {}, 0", dest, operand(a)),
+                };
+                self.line(line);
+            }
+            Jump(target) => self.line(format!("br label {}", block(*target))),
+            Branch(cond, on_true, on_false) => {
+                let cond = self.condition(cond);
+                self.line(format!(
+                    "br i1 {}, label {}, label {}",
+                    cond,
+                    block(*on_true),
+                    block(*on_false)
+                ));
+            }
+            Ret(None) => self.line("ret void".to_string()),
+            Ret(Some(value)) => {
+                let line = format!("ret {} {}", ty(self.func.ret), operand(value));
+                self.line(line);
+            }
+        }
+    }
+
+    /* 变长参数按C的规则传递: float提升为double. */
+    fn call(&mut self, dest: &str, ret: Type, name: &str, args: &[Operand]) {
+        let callee = self.module.function(name);
+        let fixed = callee.map_or(args.len(), |f| f.params.len());
+        let variadic = callee.is_some_and(|f| f.variadic);
+        let mut values = vec![];
+        for (i, arg) in args.iter().enumerate() {
+            let arg_ty = self.func.operand_type(arg);
+            if i >= fixed && arg_ty == Type::F32 {
+                let t = self.temp();
+                self.line(format!("{} = fpext float {} to double", t, operand(arg)));
+                values.push(format!("double {}", t));
+            } else {
+                values.push(self.typed(arg));
+            }
+        }
+        let callee_ty = match callee {
+            Some(f) if variadic => format!("{} ({})", ty(ret), signature(f)),
+            _ => ty(ret).to_string(),
+        };
+        let assign = if dest.is_empty() {
+            String::new()
+        } else {
+            format!("{} = ", dest)
+        };
+        self.line(format!(
+            "{}call {} @{}({})",
+            assign,
+            callee_ty,
+            name,
+            values.join(", ")
+        ));
+    }
+}
+
+fn icmp(op: CmpOp) -> &'static str {
+    match op {
+        CmpOp::Eq => "eq",
+        CmpOp::Ne => "ne",
+        CmpOp::Lt => "slt",
+        CmpOp::Le => "sle",
+        CmpOp::Gt => "sgt",
+        CmpOp::Ge => "sge",
+    }
+}
+
+/* 有NaN时只有!=为真, 与C相同. */
+fn fcmp(op: CmpOp) -> &'static str {
+    match op {
+        CmpOp::Eq => "oeq",
+        CmpOp::Ne => "une",
+        CmpOp::Lt => "olt",
+        CmpOp::Le => "ole",
+        CmpOp::Gt => "ogt",
+        CmpOp::Ge => "oge",
+    }
+}
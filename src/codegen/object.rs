@@ -0,0 +1,596 @@
+use super::riscv;
+use crate::ir::Module;
+use object::write::{Object, Relocation, StandardSection, Symbol, SymbolId, SymbolSection};
+use object::{
+    elf, Architecture, BinaryFormat, Endianness, FileFlags, RelocationFlags, SymbolFlags,
+    SymbolKind, SymbolScope,
+};
+use std::collections::HashMap;
+
+/*
+    RV64的ELF可重定位目标文件(.o): 把riscv模块生成的汇编文本直接汇编成机器码, 不再需要外部的汇编器,
+    得到的.o可以直接交给ld/gcc链接.
+    只认识riscv模块会生成的指令, 伪指令和伪操作. 伪指令按GNU as的方式展开: li是addi或lui+addiw,
+    lla是auipc+addi, call是auipc+jalr. 不使用压缩指令, 也不生成链接器松弛(R_RISCV_RELAX)的重定位.
+    同一节中的标签(基本块)在这里直接算出偏移; 函数, 全局变量, 字符串和运行时库的地址生成重定位.
+*/
+pub fn emit(module: &Module) -> Result<Vec<u8>, String> {
+    assemble(&riscv::emit(module)?)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Section {
+    Text,
+    Data,
+    Rodata,
+    Bss,
+}
+
+/* 指令中需要符号地址的地方. */
+enum Fixup {
+    Branch(String), //beq, 目标在同一节中
+    Jump(String),   //jal, 目标在同一节中
+    Call(String),   //auipc+jalr
+    Pcrel(String),  //auipc+addi
+}
+
+struct Assembler {
+    section: Section,
+    bytes: HashMap<Section, Vec<u8>>,
+    bss: u64,
+    labels: HashMap<String, (Section, u64)>,
+    globals: Vec<String>,
+    types: HashMap<String, SymbolKind>,
+    sizes: HashMap<String, u64>,
+    fixups: Vec<(u64, Fixup)>, //只在.text中
+}
+
+/* 整数寄存器的ABI名字 -> 编号. */
+fn xreg(name: &str) -> Result<u32, String> {
+    let n = match name {
+        "zero" => 0,
+        "ra" => 1,
+        "sp" => 2,
+        "gp" => 3,
+        "tp" => 4,
+        "t0" | "t1" | "t2" => 5 + name[1..].parse::<u32>().unwrap(),
+        "s0" | "fp" => 8,
+        "s1" => 9,
+        _ => match (name.as_bytes()[0], name[1..].parse::<u32>()) {
+            (b'a', Ok(i)) if i < 8 => 10 + i,
+            (b's', Ok(i)) if (2..12).contains(&i) => 16 + i,
+            (b't', Ok(i)) if (3..7).contains(&i) => 25 + i,
+            _ => return Err(format!("unknown register `{}`", name)),
+        },
+    };
+    Ok(n)
+}
+
+fn freg(name: &str) -> Result<u32, String> {
+    let bad = || format!("unknown float register `{}`", name);
+    let (kind, index) = name
+        .strip_prefix("ft")
+        .map(|i| ("ft", i))
+        .or_else(|| name.strip_prefix("fs").map(|i| ("fs", i)))
+        .or_else(|| name.strip_prefix("fa").map(|i| ("fa", i)))
+        .ok_or_else(bad)?;
+    let i: u32 = index.parse().map_err(|_| bad())?;
+    match (kind, i) {
+        ("ft", 0..=7) => Ok(i),
+        ("fs", 0..=1) => Ok(8 + i),
+        ("fa", 0..=7) => Ok(10 + i),
+        ("fs", 2..=11) => Ok(16 + i),
+        ("ft", 8..=11) => Ok(20 + i),
+        _ => Err(bad()),
+    }
+}
+
+fn imm(text: &str) -> Result<i64, String> {
+    text.parse()
+        .map_err(|_| format!("bad immediate `{}`", text))
+}
+
+/* "offset(reg)" */
+fn memory(text: &str) -> Result<(i64, u32), String> {
+    let (offset, rest) = text
+        .split_once('(')
+        .ok_or_else(|| format!("bad memory operand `{}`", text))?;
+    Ok((imm(offset)?, xreg(rest.trim_end_matches(')'))?))
+}
+
+fn r_type(opcode: u32, funct3: u32, funct7: u32, rd: u32, rs1: u32, rs2: u32) -> u32 {
+    funct7 << 25 | rs2 << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | opcode
+}
+
+fn i_type(opcode: u32, funct3: u32, rd: u32, rs1: u32, imm: i64) -> u32 {
+    ((imm as u32) & 0xfff) << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | opcode
+}
+
+fn s_type(opcode: u32, funct3: u32, rs1: u32, rs2: u32, imm: i64) -> u32 {
+    let imm = imm as u32;
+    (imm >> 5 & 0x7f) << 25 | rs2 << 20 | rs1 << 15 | funct3 << 12 | (imm & 0x1f) << 7 | opcode
+}
+
+fn b_type(funct3: u32, rs1: u32, rs2: u32, offset: i64) -> u32 {
+    let imm = offset as u32;
+    (imm >> 12 & 1) << 31
+        | (imm >> 5 & 0x3f) << 25
+        | rs2 << 20
+        | rs1 << 15
+        | funct3 << 12
+        | (imm >> 1 & 0xf) << 8
+        | (imm >> 11 & 1) << 7
+        | 0x63
+}
+
+fn j_type(rd: u32, offset: i64) -> u32 {
+    let imm = offset as u32;
+    (imm >> 20 & 1) << 31
+        | (imm >> 1 & 0x3ff) << 21
+        | (imm >> 11 & 1) << 20
+        | (imm >> 12 & 0xff) << 12
+        | rd << 7
+        | 0x6f
+}
+
+fn u_type(opcode: u32, rd: u32, imm: u32) -> u32 {
+    (imm & 0xfffff) << 12 | rd << 7 | opcode
+}
+
+const NOP: u32 = 0x13;
+
+/* 与GNU as相同的li展开: 12位以内用addi, 否则lui + addiw(低12位为0时省略addiw). */
+fn load_immediate(rd: u32, value: i64) -> Result<Vec<u32>, String> {
+    if (-2048..2048).contains(&value) {
+        return Ok(vec![i_type(0x13, 0, rd, 0, value)]);
+    }
+    if value < i32::MIN as i64 || value > i32::MAX as i64 {
+        return Err(format!("immediate {} does not fit in 32 bits", value));
+    }
+    let lo = (value << 52) >> 52;
+    let hi = ((value - lo) >> 12) as u32;
+    let mut words = vec![u_type(0x37, rd, hi)];
+    if lo != 0 {
+        words.push(i_type(0x1b, 0, rd, rd, lo));
+    }
+    Ok(words)
+}
+
+/* 浮点寄存器之间的运算和比较: (funct7, funct3或舍入模式). */
+fn float_op(mnemonic: &str) -> Option<(u32, u32)> {
+    Some(match mnemonic {
+        "fadd.s" => (0x00, 7),
+        "fsub.s" => (0x04, 7),
+        "fmul.s" => (0x08, 7),
+        "fdiv.s" => (0x0c, 7),
+        "feq.s" => (0x50, 2),
+        "flt.s" => (0x50, 1),
+        "fle.s" => (0x50, 0),
+        _ => return None,
+    })
+}
+
+/* 整数寄存器之间的运算: (opcode, funct3, funct7). */
+fn int_op(mnemonic: &str) -> Option<(u32, u32, u32)> {
+    Some(match mnemonic {
+        "add" => (0x33, 0, 0x00),
+        "sub" => (0x33, 0, 0x20),
+        "and" => (0x33, 7, 0x00),
+        "or" => (0x33, 6, 0x00),
+        "xor" => (0x33, 4, 0x00),
+        "slt" => (0x33, 2, 0x00),
+        "mul" => (0x33, 0, 0x01),
+        "addw" => (0x3b, 0, 0x00),
+        "subw" => (0x3b, 0, 0x20),
+        "mulw" => (0x3b, 0, 0x01),
+        "divw" => (0x3b, 4, 0x01),
+        "remw" => (0x3b, 6, 0x01),
+        "sllw" => (0x3b, 1, 0x00),
+        "sraw" => (0x3b, 5, 0x20),
+        _ => return None,
+    })
+}
+
+/* .asciz中的转义. */
+fn unescape(text: &str) -> Result<Vec<u8>, String> {
+    let inner = text
+        .strip_prefix('"')
+        .and_then(|t| t.strip_suffix('"'))
+        .ok_or_else(|| format!("bad string `{}`", text))?;
+    let mut bytes = vec![];
+    let mut chars = inner.bytes().peekable();
+    while let Some(b) = chars.next() {
+        if b != b'\\' {
+            bytes.push(b);
+            continue;
+        }
+        match chars.next() {
+            Some(b'n') => bytes.push(b'\n'),
+            Some(b't') => bytes.push(b'\t'),
+            Some(d @ b'0'..=b'7') => {
+                let mut value = (d - b'0') as u32;
+                for _ in 0..2 {
+                    match chars.peek() {
+                        Some(d @ b'0'..=b'7') => {
+                            value = value * 8 + (d - b'0') as u32;
+                            chars.next();
+                        }
+                        _ => break,
+                    }
+                }
+                bytes.push(value as u8);
+            }
+            Some(other) => bytes.push(other),
+            None => return Err(format!("bad string `{}`", text)),
+        }
+    }
+    bytes.push(0);
+    Ok(bytes)
+}
+
+impl Assembler {
+    fn new() -> Self {
+        Assembler {
+            section: Section::Text,
+            bytes: HashMap::new(),
+            bss: 0,
+            labels: HashMap::new(),
+            globals: vec![],
+            types: HashMap::new(),
+            sizes: HashMap::new(),
+            fixups: vec![],
+        }
+    }
+
+    fn offset(&self) -> u64 {
+        match self.section {
+            Section::Bss => self.bss,
+            s => self.bytes.get(&s).map_or(0, |b| b.len() as u64),
+        }
+    }
+
+    fn data(&mut self) -> &mut Vec<u8> {
+        self.bytes.entry(self.section).or_default()
+    }
+
+    fn word(&mut self, word: u32) {
+        self.data().extend_from_slice(&word.to_le_bytes());
+    }
+
+    fn zeros(&mut self, count: u64) {
+        if self.section == Section::Bss {
+            self.bss += count;
+        } else {
+            let len = self.data().len() + count as usize;
+            self.data().resize(len, 0);
+        }
+    }
+
+    fn align(&mut self, align: u64) {
+        let pad = (align - self.offset() % align) % align;
+        if self.section == Section::Text {
+            for _ in 0..pad / 4 {
+                self.word(NOP);
+            }
+        } else {
+            self.zeros(pad);
+        }
+    }
+
+    fn line(&mut self, line: &str) -> Result<(), String> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(());
+        }
+        if let Some(label) = line.strip_suffix(':') {
+            let here = (self.section, self.offset());
+            if self.labels.insert(label.to_string(), here).is_some() {
+                return Err(format!("label `{}` is defined twice", label));
+            }
+            return Ok(());
+        }
+        let (mnemonic, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let args: Vec<&str> = if rest.is_empty() {
+            vec![]
+        } else {
+            rest.split(", ").map(str::trim).collect()
+        };
+        if mnemonic.starts_with('.') {
+            self.directive(mnemonic, rest.trim(), &args)
+        } else if self.section != Section::Text {
+            Err(format!("instruction outside .text: {}", line))
+        } else {
+            self.instruction(mnemonic, &args)
+                .map_err(|e| format!("{}: {}", line, e))
+        }
+    }
+
+    fn directive(&mut self, name: &str, rest: &str, args: &[&str]) -> Result<(), String> {
+        match name {
+            ".option" => {}
+            ".text" => self.section = Section::Text,
+            ".data" => self.section = Section::Data,
+            ".bss" => self.section = Section::Bss,
+            ".section" if rest == ".rodata" => self.section = Section::Rodata,
+            ".globl" => self.globals.push(rest.to_string()),
+            ".type" => {
+                let kind = match args.get(1) {
+                    Some(&"@function") => SymbolKind::Text,
+                    _ => SymbolKind::Data,
+                };
+                self.types.insert(args[0].to_string(), kind);
+            }
+            ".size" => {
+                let size = match args[1].strip_prefix(".-") {
+                    Some(label) => {
+                        let (_, start) = self.labels[label];
+                        self.offset() - start
+                    }
+                    None => imm(args[1])? as u64,
+                };
+                self.sizes.insert(args[0].to_string(), size);
+            }
+            ".p2align" => self.align(1 << imm(rest)?),
+            ".word" => self.word(imm(rest)? as u32),
+            ".zero" => self.zeros(imm(rest)? as u64),
+            ".asciz" => {
+                let bytes = unescape(rest)?;
+                self.data().extend_from_slice(&bytes);
+            }
+            _ => return Err(format!("unsupported directive `{} {}`", name, rest)),
+        }
+        Ok(())
+    }
+
+    fn fixup(&mut self, fixup: Fixup) {
+        let offset = self.offset();
+        self.fixups.push((offset, fixup));
+    }
+
+    fn instruction(&mut self, mnemonic: &str, args: &[&str]) -> Result<(), String> {
+        let arg = |i: usize| {
+            args.get(i)
+                .copied()
+                .ok_or_else(|| format!("missing operand {}", i + 1))
+        };
+        let words = if let Some((opcode, funct3, funct7)) = int_op(mnemonic) {
+            let (rd, rs1, rs2) = (xreg(arg(0)?)?, xreg(arg(1)?)?, xreg(arg(2)?)?);
+            vec![r_type(opcode, funct3, funct7, rd, rs1, rs2)]
+        } else if let Some((funct7, funct3)) = float_op(mnemonic) {
+            // 比较的结果写到整数寄存器.
+            let rd = if funct7 == 0x50 {
+                xreg(arg(0)?)?
+            } else {
+                freg(arg(0)?)?
+            };
+            let (rs1, rs2) = (freg(arg(1)?)?, freg(arg(2)?)?);
+            vec![r_type(0x53, funct3, funct7, rd, rs1, rs2)]
+        } else {
+            match mnemonic {
+                "addi" | "xori" | "addiw" | "slli" => {
+                    let (opcode, funct3) = match mnemonic {
+                        "addi" => (0x13, 0),
+                        "xori" => (0x13, 4),
+                        "addiw" => (0x1b, 0),
+                        _ => (0x13, 1),
+                    };
+                    let value = imm(arg(2)?)?;
+                    let range = if mnemonic == "slli" {
+                        0..64
+                    } else {
+                        -2048..2048
+                    };
+                    if !range.contains(&value) {
+                        return Err(format!("immediate {} out of range", value));
+                    }
+                    vec![i_type(
+                        opcode,
+                        funct3,
+                        xreg(arg(0)?)?,
+                        xreg(arg(1)?)?,
+                        value,
+                    )]
+                }
+                "lw" | "ld" | "flw" => {
+                    let (offset, base) = memory(arg(1)?)?;
+                    let (opcode, funct3, rd) = match mnemonic {
+                        "lw" => (0x03, 2, xreg(arg(0)?)?),
+                        "ld" => (0x03, 3, xreg(arg(0)?)?),
+                        _ => (0x07, 2, freg(arg(0)?)?),
+                    };
+                    vec![i_type(opcode, funct3, rd, base, offset)]
+                }
+                "sw" | "sd" | "fsw" | "fsd" => {
+                    let (offset, base) = memory(arg(1)?)?;
+                    let (opcode, funct3, rs2) = match mnemonic {
+                        "sw" => (0x23, 2, xreg(arg(0)?)?),
+                        "sd" => (0x23, 3, xreg(arg(0)?)?),
+                        "fsw" => (0x27, 2, freg(arg(0)?)?),
+                        _ => (0x27, 3, freg(arg(0)?)?),
+                    };
+                    vec![s_type(opcode, funct3, base, rs2, offset)]
+                }
+                "li" => load_immediate(xreg(arg(0)?)?, imm(arg(1)?)?)?,
+                "seqz" => vec![i_type(0x13, 3, xreg(arg(0)?)?, xreg(arg(1)?)?, 1)],
+                "snez" => vec![r_type(0x33, 3, 0, xreg(arg(0)?)?, 0, xreg(arg(1)?)?)],
+                "fneg.s" => {
+                    let rs = freg(arg(1)?)?;
+                    vec![r_type(0x53, 1, 0x10, freg(arg(0)?)?, rs, rs)]
+                }
+                "fcvt.s.w" => vec![r_type(0x53, 7, 0x68, freg(arg(0)?)?, xreg(arg(1)?)?, 0)],
+                "fcvt.w.s" => {
+                    if arg(2)? != "rtz" {
+                        return Err("only rtz rounding is supported".to_string());
+                    }
+                    vec![r_type(0x53, 1, 0x60, xreg(arg(0)?)?, freg(arg(1)?)?, 0)]
+                }
+                "fcvt.d.s" => vec![r_type(0x53, 0, 0x21, freg(arg(0)?)?, freg(arg(1)?)?, 0)],
+                "fmv.w.x" => vec![r_type(0x53, 0, 0x78, freg(arg(0)?)?, xreg(arg(1)?)?, 0)],
+                "fmv.x.w" => vec![r_type(0x53, 0, 0x70, xreg(arg(0)?)?, freg(arg(1)?)?, 0)],
+                "fmv.x.d" => vec![r_type(0x53, 0, 0x71, xreg(arg(0)?)?, freg(arg(1)?)?, 0)],
+                "j" => {
+                    self.fixup(Fixup::Jump(arg(0)?.to_string()));
+                    vec![j_type(0, 0)]
+                }
+                "beqz" => {
+                    let rs = xreg(arg(0)?)?;
+                    self.fixup(Fixup::Branch(arg(1)?.to_string()));
+                    vec![b_type(0, rs, 0, 0)]
+                }
+                "call" => {
+                    self.fixup(Fixup::Call(arg(0)?.to_string()));
+                    vec![u_type(0x17, 1, 0), i_type(0x67, 0, 1, 1, 0)]
+                }
+                "lla" => {
+                    let rd = xreg(arg(0)?)?;
+                    self.fixup(Fixup::Pcrel(arg(1)?.to_string()));
+                    vec![u_type(0x17, rd, 0), i_type(0x13, 0, rd, rd, 0)]
+                }
+                "ret" => vec![i_type(0x67, 0, 0, 1, 0)],
+                _ => return Err(format!("unsupported instruction `{}`", mnemonic)),
+            }
+        };
+        for word in words {
+            self.word(word);
+        }
+        Ok(())
+    }
+
+    /* 同一节中的跳转直接填入偏移. */
+    fn patch(&mut self, offset: u64, target: &str, jump: bool) -> Result<(), String> {
+        let (section, address) = match self.labels.get(target) {
+            Some(&(Section::Text, address)) => (Section::Text, address),
+            _ => return Err(format!("branch target `{}` is not in .text", target)),
+        };
+        let distance = address as i64 - offset as i64;
+        let (word, limit) = if jump {
+            (j_type(0, distance), 1 << 20)
+        } else {
+            let text = &self.bytes[&section];
+            let old = u32::from_le_bytes(text[offset as usize..][..4].try_into().unwrap());
+            (b_type(0, old >> 15 & 0x1f, 0, distance), 1 << 12)
+        };
+        if !(-limit..limit).contains(&distance) {
+            return Err(format!("branch to `{}` is out of range", target));
+        }
+        let text = self.bytes.get_mut(&section).unwrap();
+        text[offset as usize..][..4].copy_from_slice(&word.to_le_bytes());
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Vec<u8>, String> {
+        let mut obj = Object::new(BinaryFormat::Elf, Architecture::Riscv64, Endianness::Little);
+        obj.flags = FileFlags::Elf {
+            os_abi: elf::ELFOSABI_NONE,
+            abi_version: 0,
+            e_flags: elf::EF_RISCV_FLOAT_ABI_DOUBLE,
+        };
+        let fixups = std::mem::take(&mut self.fixups);
+        for (offset, fixup) in &fixups {
+            match fixup {
+                Fixup::Branch(target) => self.patch(*offset, target, false)?,
+                Fixup::Jump(target) => self.patch(*offset, target, true)?,
+                Fixup::Call(_) | Fixup::Pcrel(_) => {}
+            }
+        }
+        let mut sections = HashMap::new();
+        for (section, standard, align) in [
+            (Section::Text, StandardSection::Text, 4),
+            (Section::Data, StandardSection::Data, 8),
+            (Section::Rodata, StandardSection::ReadOnlyData, 8),
+        ] {
+            if let Some(bytes) = self.bytes.get(&section).filter(|b| !b.is_empty()) {
+                let id = obj.section_id(standard);
+                obj.append_section_data(id, bytes, align);
+                sections.insert(section, id);
+            }
+        }
+        if self.bss > 0 {
+            let id = obj.section_id(StandardSection::UninitializedData);
+            obj.append_section_bss(id, self.bss, 8);
+            sections.insert(Section::Bss, id);
+        }
+        // 基本块的标签(.L开头, 在.text中)只在这里用到, 不进符号表.
+        let mut symbols: HashMap<String, SymbolId> = HashMap::new();
+        let mut labels: Vec<(&String, &(Section, u64))> = self.labels.iter().collect();
+        labels.sort_by_key(|(_, &(section, offset))| (section as u8, offset));
+        for (name, &(section, offset)) in labels {
+            if name.starts_with(".L") && section == Section::Text {
+                continue;
+            }
+            let global = self.globals.contains(name);
+            let id = obj.add_symbol(Symbol {
+                name: name.as_bytes().to_vec(),
+                value: offset,
+                size: self.sizes.get(name).copied().unwrap_or(0),
+                kind: self.types.get(name).copied().unwrap_or(SymbolKind::Label),
+                scope: if global {
+                    SymbolScope::Dynamic
+                } else {
+                    SymbolScope::Compilation
+                },
+                weak: false,
+                section: SymbolSection::Section(sections[&section]),
+                flags: SymbolFlags::None,
+            });
+            symbols.insert(name.clone(), id);
+        }
+        let text = sections.get(&Section::Text).copied();
+        let mut pcrel = 0;
+        for (offset, fixup) in &fixups {
+            let (target, r_type) = match fixup {
+                Fixup::Call(target) => (target, elf::R_RISCV_CALL_PLT),
+                Fixup::Pcrel(target) => (target, elf::R_RISCV_PCREL_HI20),
+                _ => continue,
+            };
+            // 没有定义的是运行时库的函数.
+            let symbol = *symbols.entry(target.clone()).or_insert_with(|| {
+                obj.add_symbol(Symbol {
+                    name: target.as_bytes().to_vec(),
+                    value: 0,
+                    size: 0,
+                    kind: SymbolKind::Unknown,
+                    scope: SymbolScope::Dynamic,
+                    weak: false,
+                    section: SymbolSection::Undefined,
+                    flags: SymbolFlags::None,
+                })
+            });
+            let text = text.unwrap();
+            let relocation = |symbol, offset, r_type| Relocation {
+                offset,
+                symbol,
+                addend: 0,
+                flags: RelocationFlags::Elf { r_type },
+            };
+            obj.add_relocation(text, relocation(symbol, *offset, r_type))
+                .map_err(|e| e.to_string())?;
+            // addi的低12位指向auipc处的标签, 由链接器从那里找到对应的高20位.
+            if r_type == elf::R_RISCV_PCREL_HI20 {
+                let hi = obj.add_symbol(Symbol {
+                    name: format!(".Lpcrel_hi{}", pcrel).into_bytes(),
+                    value: *offset,
+                    size: 0,
+                    kind: SymbolKind::Label,
+                    scope: SymbolScope::Compilation,
+                    weak: false,
+                    section: SymbolSection::Section(text),
+                    flags: SymbolFlags::None,
+                });
+                obj.add_relocation(text, relocation(hi, offset + 4, elf::R_RISCV_PCREL_LO12_I))
+                    .map_err(|e| e.to_string())?;
+                pcrel += 1;
+            }
+        }
+        obj.write().map_err(|e| e.to_string())
+    }
+}
+
+/* 把riscv模块生成的汇编文本汇编成ELF目标文件. */
+pub fn assemble(asm: &str) -> Result<Vec<u8>, String> {
+    let mut assembler = Assembler::new();
+    for line in asm.lines() {
+        assembler.line(line)?;
+    }
+    assembler.finish()
+}
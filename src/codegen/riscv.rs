@@ -0,0 +1,555 @@
+use super::frame::{ArgLocation, Frame};
+use crate::{
+    ir::{BinOp, BlockId, CmpOp, Function, Global, Inst, InstKind, Module, Operand, Type, Value},
+    target::Target,
+};
+use std::fmt::Write;
+
+/*
+    三地址码 -> RV64GC汇编(GNU as的语法), 调用约定是LP64D.
+    还没有寄存器分配: 每条指令从栈帧中的槽读取操作数到临时寄存器, 计算后写回自己的槽,
+    栈帧的布局全部来自frame模块. t0-t2和ft0-ft1存放操作数和结果, t5用来构造浮点常量,
+    t6用来构造超出12位立即数范围的偏移.
+    phi在前驱的终结指令之前写入影子槽, 在块的开头复制到自己的槽(见frame模块).
+    条件跳转的范围只有4KiB, 所以br总是写成beqz跳过一条j, 大函数中也不会超出范围.
+*/
+pub fn emit(module: &Module) -> Result<String, String> {
+    let mut out = String::from("  .option nopic\n  .text\n");
+    for function in module.functions.iter().filter(|f| !f.is_declaration()) {
+        FnEmitter::new(module, function, &mut out).emit()?;
+    }
+    // 有初始值的变量放在.data, 常量放在.rodata, 全0的变量放在.bss, 都不需要运行时初始化.
+    for name in [".data", ".section .rodata", ".bss"] {
+        let mut globals = module
+            .globals
+            .iter()
+            .filter(|g| section(g) == name)
+            .peekable();
+        if globals.peek().is_some() {
+            let _ = writeln!(out, "\n  {}", name);
+        }
+        for global in globals {
+            emit_global(&mut out, global);
+        }
+    }
+    if !module.strings.is_empty() {
+        out.push_str("\n  .section .rodata\n");
+        for (i, s) in module.strings.iter().enumerate() {
+            let _ = writeln!(out, ".L.str.{}:\n  .asciz \"{}\"", i, escape(s));
+        }
+    }
+    Ok(out)
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::new();
+    for b in text.bytes() {
+        match b {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            b'\n' => out.push_str("\\n"),
+            b'\t' => out.push_str("\\t"),
+            b' '..=b'~' => out.push(b as char),
+            _ => {
+                let _ = write!(out, "\\{:03o}", b);
+            }
+        }
+    }
+    out
+}
+
+/* 全局变量的一个字: int原样输出, float输出它的位模式. */
+fn word(value: &Operand) -> u32 {
+    match value {
+        Operand::Int(v) => *v as u32,
+        Operand::Float(v) => v.to_bits(),
+        _ => 0,
+    }
+}
+
+fn section(global: &Global) -> &'static str {
+    if global.constant {
+        ".section .rodata"
+    } else if global.init.is_empty() {
+        ".bss"
+    } else {
+        ".data"
+    }
+}
+
+/*
+    数组按8字节对齐, 标量按4字节对齐. 初始值已经由语义分析展开成逐个元素的常量;
+    连续的0合并成一条.zero. 提升成全局常量的局部数组(名字中有'.')不导出.
+*/
+fn emit_global(out: &mut String, global: &Global) {
+    let name = &global.name;
+    if !name.contains('.') {
+        let _ = writeln!(out, "  .globl {}", name);
+    }
+    let align = if global.len > 1 { 3 } else { 2 };
+    let _ = writeln!(
+        out,
+        "  .type {}, @object\n  .size {}, {}\n  .p2align {}\n{}:",
+        name,
+        name,
+        global.size(),
+        align,
+        name
+    );
+    let mut words: Vec<u32> = global.init.iter().map(word).collect();
+    words.resize(global.len, 0);
+    let mut i = 0;
+    while i < words.len() {
+        let zeros = words[i..].iter().take_while(|w| **w == 0).count();
+        if zeros > 1 {
+            let _ = writeln!(out, "  .zero {}", zeros * 4);
+            i += zeros;
+        } else {
+            let _ = writeln!(out, "  .word {}", words[i]);
+            i += 1;
+        }
+    }
+}
+
+fn fits_imm(n: i64) -> bool {
+    (-2048..=2047).contains(&n)
+}
+
+struct FnEmitter<'a> {
+    module: &'a Module,
+    func: &'a Function,
+    frame: Frame,
+    out: &'a mut String,
+    branches: usize,
+}
+
+impl<'a> FnEmitter<'a> {
+    fn new(module: &'a Module, func: &'a Function, out: &'a mut String) -> Self {
+        FnEmitter {
+            module,
+            func,
+            frame: Frame::build(module, func, Target::Riscv64),
+            out,
+            branches: 0,
+        }
+    }
+
+    fn line(&mut self, text: impl AsRef<str>) {
+        self.out.push_str("  ");
+        self.out.push_str(text.as_ref());
+        self.out.push('\n');
+    }
+
+    fn label(&self, block: BlockId) -> String {
+        format!(".L{}_{}", self.func.name, block.0)
+    }
+
+    /* sp + offset作为load/store的地址; 超出立即数范围时先算到t6中. */
+    fn address(&mut self, offset: i64) -> String {
+        if fits_imm(offset) {
+            return format!("{}(sp)", offset);
+        }
+        self.line(format!("li t6, {}", offset));
+        self.line("add t6, sp, t6");
+        "0(t6)".to_string()
+    }
+
+    /* reg = sp + offset */
+    fn add_sp(&mut self, reg: &str, offset: i64) {
+        if fits_imm(offset) {
+            self.line(format!("addi {}, sp, {}", reg, offset));
+        } else {
+            self.line(format!("li {}, {}", reg, offset));
+            self.line(format!("add {}, sp, {}", reg, reg));
+        }
+    }
+
+    fn load_slot(&mut self, reg: &str, offset: i64, ty: Type) {
+        let op = match ty {
+            Type::Ptr => "ld",
+            Type::F32 => "flw",
+            _ => "lw",
+        };
+        let addr = self.address(offset);
+        self.line(format!("{} {}, {}", op, reg, addr));
+    }
+
+    fn store_slot(&mut self, reg: &str, offset: i64, ty: Type) {
+        let op = match ty {
+            Type::Ptr => "sd",
+            Type::F32 => "fsw",
+            _ => "sw",
+        };
+        let addr = self.address(offset);
+        self.line(format!("{} {}, {}", op, reg, addr));
+    }
+
+    /* 把整数或地址操作数读到通用寄存器. */
+    fn load(&mut self, reg: &str, operand: &Operand) {
+        match operand {
+            Operand::Value(v) => match self.frame.local(*v) {
+                Some(local) => {
+                    let offset = local.offset;
+                    self.add_sp(reg, offset);
+                }
+                None => {
+                    let offset = self.frame.slot(*v).unwrap();
+                    self.load_slot(reg, offset, self.func.value_type(*v));
+                }
+            },
+            Operand::Int(n) => self.line(format!("li {}, {}", reg, n)),
+            Operand::Float(x) => self.line(format!("li {}, {}", reg, x.to_bits() as i32)),
+            Operand::Global(name) => self.line(format!("lla {}, {}", reg, name)),
+            Operand::Str(i) => self.line(format!("lla {}, .L.str.{}", reg, i)),
+        }
+    }
+
+    /* 把float操作数读到浮点寄存器. */
+    fn load_float(&mut self, reg: &str, operand: &Operand) {
+        match operand {
+            Operand::Value(v) => {
+                let offset = self.frame.slot(*v).unwrap();
+                self.load_slot(reg, offset, Type::F32);
+            }
+            _ => {
+                self.load("t5", operand);
+                self.line(format!("fmv.w.x {}, t5", reg));
+            }
+        }
+    }
+
+    fn store(&mut self, reg: &str, dest: Value) {
+        let offset = self.frame.slot(dest).unwrap();
+        self.store_slot(reg, offset, self.func.value_type(dest));
+    }
+
+    fn emit(mut self) -> Result<(), String> {
+        let name = self.func.name.clone();
+        let _ = writeln!(
+            self.out,
+            "\n  .globl {}\n  .p2align 1\n  .type {}, @function\n{}:",
+            name, name, name
+        );
+        self.prologue();
+        for b in 0..self.func.blocks.len() {
+            let block = BlockId(b as u32);
+            if b > 0 {
+                let _ = writeln!(self.out, "{}:", self.label(block));
+            }
+            for inst in &self.func.block(block).insts {
+                if inst.kind.is_terminator() {
+                    self.phi_copies(block);
+                }
+                self.inst(inst)?;
+            }
+        }
+        let _ = writeln!(self.out, "  .size {}, .-{}", name, name);
+        Ok(())
+    }
+
+    fn prologue(&mut self) {
+        let size = self.frame.size;
+        if fits_imm(-size) {
+            self.line(format!("addi sp, sp, -{}", size));
+        } else {
+            self.line(format!("li t0, {}", size));
+            self.line("sub sp, sp, t0");
+        }
+        for (reg, offset) in self.frame.saved.clone() {
+            self.store_slot(reg, offset, Type::Ptr);
+        }
+        // 形参都存入自己的槽; 浮点寄存器用完后的float通过整数寄存器传入.
+        for (i, &ty) in self.func.params.iter().enumerate() {
+            let param = Value(i as u32);
+            match (self.frame.params[i], self.frame.incoming(i)) {
+                (ArgLocation::Reg(reg), _) if ty == Type::F32 && !reg.starts_with('f') => {
+                    self.line(format!("fmv.w.x ft0, {}", reg));
+                    self.store("ft0", param);
+                }
+                (ArgLocation::Reg(reg), _) => self.store(reg, param),
+                (_, Some(offset)) => {
+                    let reg = if ty == Type::F32 { "ft0" } else { "t0" };
+                    self.load_slot(reg, offset, ty);
+                    self.store(reg, param);
+                }
+                _ => unreachable!("parameters are never passed in register pairs"),
+            }
+        }
+    }
+
+    fn epilogue(&mut self) {
+        for (reg, offset) in self.frame.saved.clone() {
+            self.load_slot(reg, offset, Type::Ptr);
+        }
+        let size = self.frame.size;
+        if fits_imm(size) {
+            self.line(format!("addi sp, sp, {}", size));
+        } else {
+            self.line(format!("li t0, {}", size));
+            self.line("add sp, sp, t0");
+        }
+        self.line("ret");
+    }
+
+    /* 把从block流向后继的phi的值写入影子槽. */
+    fn phi_copies(&mut self, block: BlockId) {
+        for succ in self.func.block(block).successors() {
+            for inst in &self.func.block(succ).insts {
+                let (InstKind::Phi(incoming), Some(dest)) = (&inst.kind, inst.dest) else {
+                    continue;
+                };
+                let Some((_, value)) = incoming.iter().find(|(b, _)| *b == block) else {
+                    continue;
+                };
+                let offset = self.frame.phi_copy(dest).unwrap();
+                let reg = self.load_typed(value, inst.ty);
+                self.store_slot(reg, offset, inst.ty);
+            }
+        }
+    }
+
+    /* 按类型把操作数读到t0或ft0. */
+    fn load_typed(&mut self, operand: &Operand, ty: Type) -> &'static str {
+        if ty == Type::F32 {
+            self.load_float("ft0", operand);
+            "ft0"
+        } else {
+            self.load("t0", operand);
+            "t0"
+        }
+    }
+
+    fn inst(&mut self, inst: &Inst) -> Result<(), String> {
+        match &inst.kind {
+            InstKind::Binary(op, a, b) if inst.ty == Type::F32 => {
+                let op = match op {
+                    BinOp::FAdd => "fadd.s",
+                    BinOp::FSub => "fsub.s",
+                    BinOp::FMul => "fmul.s",
+                    BinOp::FDiv => "fdiv.s",
+                    _ => return Err(format!("no float instruction for {:?}", op)),
+                };
+                self.load_float("ft0", a);
+                self.load_float("ft1", b);
+                self.line(format!("{} ft0, ft0, ft1", op));
+                self.store("ft0", inst.dest.unwrap());
+            }
+            InstKind::Binary(op, a, b) => {
+                let op = match op {
+                    BinOp::Add => "addw",
+                    BinOp::Sub => "subw",
+                    BinOp::Mul => "mulw",
+                    BinOp::Div => "divw",
+                    BinOp::Rem => "remw",
+                    BinOp::Shl => "sllw",
+                    BinOp::Shr => "sraw",
+                    BinOp::And => "and",
+                    BinOp::Or => "or",
+                    BinOp::Xor => "xor",
+                    _ => return Err(format!("no integer instruction for {:?}", op)),
+                };
+                self.load("t0", a);
+                self.load("t1", b);
+                self.line(format!("{} t0, t0, t1", op));
+                self.store("t0", inst.dest.unwrap());
+            }
+            InstKind::Icmp(op, a, b) => {
+                self.load("t0", a);
+                self.load("t1", b);
+                match op {
+                    CmpOp::Eq | CmpOp::Ne => {
+                        self.line("sub t0, t0, t1");
+                        let set = if *op == CmpOp::Eq { "seqz" } else { "snez" };
+                        self.line(format!("{} t0, t0", set));
+                    }
+                    CmpOp::Lt => self.line("slt t0, t0, t1"),
+                    CmpOp::Gt => self.line("slt t0, t1, t0"),
+                    CmpOp::Le => {
+                        self.line("slt t0, t1, t0");
+                        self.line("xori t0, t0, 1");
+                    }
+                    CmpOp::Ge => {
+                        self.line("slt t0, t0, t1");
+                        self.line("xori t0, t0, 1");
+                    }
+                }
+                self.store("t0", inst.dest.unwrap());
+            }
+            InstKind::Fcmp(op, a, b) => {
+                self.load_float("ft0", a);
+                self.load_float("ft1", b);
+                match op {
+                    CmpOp::Eq => self.line("feq.s t0, ft0, ft1"),
+                    CmpOp::Ne => {
+                        self.line("feq.s t0, ft0, ft1");
+                        self.line("xori t0, t0, 1");
+                    }
+                    CmpOp::Lt => self.line("flt.s t0, ft0, ft1"),
+                    CmpOp::Le => self.line("fle.s t0, ft0, ft1"),
+                    CmpOp::Gt => self.line("flt.s t0, ft1, ft0"),
+                    CmpOp::Ge => self.line("fle.s t0, ft1, ft0"),
+                }
+                self.store("t0", inst.dest.unwrap());
+            }
+            InstKind::FNeg(a) => {
+                self.load_float("ft0", a);
+                self.line("fneg.s ft0, ft0");
+                self.store("ft0", inst.dest.unwrap());
+            }
+            InstKind::IntToFloat(a) => {
+                self.load("t0", a);
+                self.line("fcvt.s.w ft0, t0");
+                self.store("ft0", inst.dest.unwrap());
+            }
+            InstKind::FloatToInt(a) => {
+                self.load_float("ft0", a);
+                self.line("fcvt.w.s t0, ft0, rtz");
+                self.store("t0", inst.dest.unwrap());
+            }
+            // 局部数组的地址在用到时由sp算出, 不占溢出槽.
+            InstKind::Alloca { .. } => {}
+            InstKind::Load(addr) => {
+                self.load("t0", addr);
+                let (op, reg) = match inst.ty {
+                    Type::F32 => ("flw", "ft0"),
+                    Type::Ptr => ("ld", "t1"),
+                    _ => ("lw", "t1"),
+                };
+                self.line(format!("{} {}, 0(t0)", op, reg));
+                self.store(reg, inst.dest.unwrap());
+            }
+            InstKind::Store(value, addr) => {
+                self.load("t0", addr);
+                match self.func.operand_type(value) {
+                    Type::F32 => {
+                        self.load_float("ft0", value);
+                        self.line("fsw ft0, 0(t0)");
+                    }
+                    Type::Ptr => {
+                        self.load("t1", value);
+                        self.line("sd t1, 0(t0)");
+                    }
+                    _ => {
+                        self.load("t1", value);
+                        self.line("sw t1, 0(t0)");
+                    }
+                }
+            }
+            InstKind::Gep(base, index, stride) => {
+                self.load("t0", base);
+                let bytes = *stride as i64 * 4;
+                match index {
+                    Operand::Int(i) => {
+                        let offset = *i as i64 * bytes;
+                        if fits_imm(offset) {
+                            self.line(format!("addi t0, t0, {}", offset));
+                        } else {
+                            self.line(format!("li t1, {}", offset));
+                            self.line("add t0, t0, t1");
+                        }
+                    }
+                    _ => {
+                        self.load("t1", index);
+                        if bytes.count_ones() == 1 {
+                            self.line(format!("slli t1, t1, {}", bytes.trailing_zeros()));
+                        } else {
+                            self.line(format!("li t2, {}", bytes));
+                            self.line("mul t1, t1, t2");
+                        }
+                        self.line("add t0, t0, t1");
+                    }
+                }
+                self.store("t0", inst.dest.unwrap());
+            }
+            InstKind::Call(name, args) => self.call(inst, name, args),
+            InstKind::Phi(_) => {
+                let dest = inst.dest.unwrap();
+                let offset = self.frame.phi_copy(dest).unwrap();
+                let reg = if inst.ty == Type::F32 { "ft0" } else { "t0" };
+                self.load_slot(reg, offset, inst.ty);
+                self.store(reg, dest);
+            }
+            InstKind::Copy(a) => {
+                let reg = self.load_typed(a, inst.ty);
+                self.store(reg, inst.dest.unwrap());
+            }
+            InstKind::Jump(target) => {
+                let label = self.label(*target);
+                self.line(format!("j {}", label));
+            }
+            InstKind::Branch(cond, on_true, on_false) => {
+                let (on_true, on_false) = (self.label(*on_true), self.label(*on_false));
+                self.branches += 1;
+                let skip = format!(".L{}_br{}", self.func.name, self.branches);
+                self.load("t0", cond);
+                self.line(format!("beqz t0, {}", skip));
+                self.line(format!("j {}", on_true));
+                let _ = writeln!(self.out, "{}:", skip);
+                self.line(format!("j {}", on_false));
+            }
+            InstKind::Ret(value) => {
+                if let Some(value) = value {
+                    let reg = self.frame.abi.return_register(self.func.ret).unwrap();
+                    if self.func.ret == Type::F32 {
+                        self.load_float(reg, value);
+                    } else {
+                        self.load(reg, value);
+                    }
+                }
+                self.epilogue();
+            }
+        }
+        Ok(())
+    }
+
+    /*
+        实参按frame模块分配的位置放好后调用. 变长参数中的float提升为double,
+        和浮点寄存器用完后的float一样通过整数寄存器(或栈)传递.
+    */
+    fn call(&mut self, inst: &Inst, name: &str, args: &[Operand]) {
+        let (locations, _) = self.frame.abi.call_args(self.module, self.func, name, args);
+        let fixed = self
+            .module
+            .function(name)
+            .filter(|f| f.variadic)
+            .map_or(args.len(), |f| f.params.len());
+        for (i, (arg, location)) in args.iter().zip(locations).enumerate() {
+            let is_float = self.func.operand_type(arg) == Type::F32;
+            let promoted = is_float && i >= fixed;
+            match location {
+                ArgLocation::Reg(reg) if reg.starts_with('f') => self.load_float(reg, arg),
+                ArgLocation::Reg(reg) if promoted => {
+                    self.load_float("ft0", arg);
+                    self.line("fcvt.d.s ft0, ft0");
+                    self.line(format!("fmv.x.d {}, ft0", reg));
+                }
+                ArgLocation::Reg(reg) if is_float => {
+                    self.load_float("ft0", arg);
+                    self.line(format!("fmv.x.w {}, ft0", reg));
+                }
+                ArgLocation::Reg(reg) => self.load(reg, arg),
+                ArgLocation::Stack(offset) if promoted => {
+                    self.load_float("ft0", arg);
+                    self.line("fcvt.d.s ft0, ft0");
+                    let addr = self.address(offset);
+                    self.line(format!("fsd ft0, {}", addr));
+                }
+                ArgLocation::Stack(offset) if is_float => {
+                    self.load_float("ft0", arg);
+                    self.store_slot("ft0", offset, Type::F32);
+                }
+                // 栈上的int也写满8字节, 高位是符号扩展.
+                ArgLocation::Stack(offset) => {
+                    self.load("t0", arg);
+                    self.store_slot("t0", offset, Type::Ptr);
+                }
+                ArgLocation::Pair(..) => unreachable!("RISC-V passes doubles in one register"),
+            }
+        }
+        self.line(format!("call {}", name));
+        if let Some(dest) = inst.dest {
+            let reg = self.frame.abi.return_register(inst.ty).unwrap();
+            self.store(reg, dest);
+        }
+    }
+}
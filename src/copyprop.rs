@@ -0,0 +1,52 @@
+use crate::ir::{Function, InstKind, Module, Operand};
+use std::collections::HashMap;
+
+/*
+    复制传播: copy的结果换成它的来源, 来源都相同的phi(不算自身)也是复制; 之后删除这些指令.
+    mem2reg和SCCP之后剩下的, 以及内联等改写产生的复制都在这里清理.
+*/
+pub fn run_module(module: &mut Module) -> bool {
+    let mut changed = false;
+    for function in module.functions.iter_mut().filter(|f| !f.is_declaration()) {
+        changed |= run(function);
+    }
+    changed
+}
+
+/* 返回是否删除了复制. */
+pub fn run(function: &mut Function) -> bool {
+    let mut replace = HashMap::new();
+    for inst in function.blocks.iter().flat_map(|b| &b.insts) {
+        let Some(dest) = inst.dest else {
+            continue;
+        };
+        match &inst.kind {
+            InstKind::Copy(source) => {
+                replace.insert(dest, source.clone());
+            }
+            InstKind::Phi(incoming) => {
+                let mut sources = incoming
+                    .iter()
+                    .map(|(_, v)| v)
+                    .filter(|v| **v != Operand::Value(dest));
+                if let Some(first) = sources.next() {
+                    if sources.all(|v| v == first) {
+                        replace.insert(dest, first.clone());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    if replace.is_empty() {
+        return false;
+    }
+    for block in &mut function.blocks {
+        block
+            .insts
+            .retain(|inst| !inst.dest.is_some_and(|d| replace.contains_key(&d)));
+    }
+    function.replace_values(&replace);
+    function.renumber_values();
+    true
+}
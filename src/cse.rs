@@ -0,0 +1,180 @@
+use crate::{
+    cfg::{Cfg, Dominators},
+    effects::Effects,
+    ir::{resolve, BinOp, BlockId, CmpOp, Function, Inst, InstKind, Module, Operand, Value},
+};
+use std::collections::HashMap;
+
+/*
+    公共子表达式删除: 纯指令(算术, 比较, 类型转换, gep)按运算和操作数散列, 与之前算过的相同时复用结果.
+    local只在扩展基本块(只有一个前驱的块接在前驱之后)中查找; global沿支配树查找,
+    支配当前块的块中算过的表达式都可以复用. load可能读到不同的内存, 不参与;
+    call只有被调函数没有副作用(见effects模块)时参与, 如f(x) + f(x)只调用一次.
+*/
+pub fn run_module(module: &mut Module, global: bool) -> bool {
+    let effects = Effects::compute(module);
+    let mut changed = false;
+    for function in module.functions.iter_mut().filter(|f| !f.is_declaration()) {
+        let cfg = Cfg::build(function);
+        changed |= if global {
+            global_with(function, &Dominators::compute(&cfg), &effects)
+        } else {
+            local_with(function, &cfg, &effects)
+        };
+    }
+    changed
+}
+
+/* 在扩展基本块中删除公共子表达式, 返回是否改变了函数. 不知道其他函数, 调用都不参与. */
+pub fn local(function: &mut Function) -> bool {
+    let cfg = Cfg::build(function);
+    local_with(function, &cfg, &Effects::default())
+}
+
+/* 同local, 使用已经建好的控制流图和模块中函数的副作用. */
+pub fn local_with(function: &mut Function, cfg: &Cfg, effects: &Effects) -> bool {
+    let single_pred = |b: BlockId| {
+        let mut preds = cfg.predecessors(b);
+        match (preds.next(), preds.next()) {
+            (Some(p), None) if b.0 != 0 => Some(p),
+            _ => None,
+        }
+    };
+    let mut children = vec![vec![]; function.blocks.len()];
+    let mut roots = vec![];
+    for b in 0..function.blocks.len() {
+        let block = BlockId(b as u32);
+        match single_pred(block) {
+            Some(pred) if pred != block => children[pred.0 as usize].push(block),
+            _ => roots.push(block),
+        }
+    }
+    let mut replace = HashMap::new();
+    for root in roots {
+        walk(function, &children, root, effects, &mut replace);
+    }
+    finish(function, replace)
+}
+
+/* 沿支配树删除公共子表达式, 返回是否改变了函数. 不知道其他函数, 调用都不参与. */
+pub fn global(function: &mut Function) -> bool {
+    let doms = Dominators::compute(&Cfg::build(function));
+    global_with(function, &doms, &Effects::default())
+}
+
+/* 同global, 使用已经算好的支配树和模块中函数的副作用. */
+pub fn global_with(function: &mut Function, doms: &Dominators, effects: &Effects) -> bool {
+    let children: Vec<Vec<BlockId>> = (0..function.blocks.len())
+        .map(|b| doms.children(BlockId(b as u32)).to_vec())
+        .collect();
+    let mut replace = HashMap::new();
+    walk(function, &children, BlockId(0), effects, &mut replace);
+    finish(function, replace)
+}
+
+fn finish(function: &mut Function, replace: HashMap<Value, Operand>) -> bool {
+    if replace.is_empty() {
+        return false;
+    }
+    for block in &mut function.blocks {
+        block
+            .insts
+            .retain(|inst| !inst.dest.is_some_and(|d| replace.contains_key(&d)));
+    }
+    function.replace_values(&replace);
+    function.renumber_values();
+    true
+}
+
+fn commutative(kind: &InstKind) -> bool {
+    match kind {
+        InstKind::Binary(op, ..) => matches!(
+            op,
+            BinOp::Add
+                | BinOp::Mul
+                | BinOp::And
+                | BinOp::Or
+                | BinOp::Xor
+                | BinOp::FAdd
+                | BinOp::FMul
+        ),
+        InstKind::Icmp(op, ..) | InstKind::Fcmp(op, ..) => matches!(op, CmpOp::Eq | CmpOp::Ne),
+        _ => false,
+    }
+}
+
+/* 纯指令的散列键: 没有结果的文本形式; 可交换的运算先把操作数排序. */
+fn key(inst: &Inst, effects: &Effects) -> Option<String> {
+    let pure = match &inst.kind {
+        InstKind::Binary(..)
+        | InstKind::Icmp(..)
+        | InstKind::Fcmp(..)
+        | InstKind::FNeg(_)
+        | InstKind::IntToFloat(_)
+        | InstKind::FloatToInt(_)
+        | InstKind::Gep(..) => true,
+        InstKind::Call(name, _) => effects.is_pure(name),
+        _ => false,
+    };
+    if !pure {
+        return None;
+    }
+    let mut kind = inst.kind.clone();
+    if commutative(&kind) {
+        if let [a, b] = &mut kind.operands_mut()[..] {
+            if a.to_string() > b.to_string() {
+                std::mem::swap(*a, *b);
+            }
+        }
+    }
+    let canonical = Inst {
+        dest: None,
+        ty: inst.ty,
+        kind,
+    };
+    Some(canonical.to_string())
+}
+
+/* 深度优先遍历children构成的树, 散列表随进出子树扩大和恢复. */
+fn walk(
+    function: &mut Function,
+    children: &[Vec<BlockId>],
+    root: BlockId,
+    effects: &Effects,
+    replace: &mut HashMap<Value, Operand>,
+) {
+    let mut table: HashMap<String, Operand> = HashMap::new();
+    let mut added: Vec<Vec<String>> = vec![];
+    let mut stack = vec![(root, false)];
+    while let Some((block, done)) = stack.pop() {
+        if done {
+            for key in added.pop().unwrap() {
+                table.remove(&key);
+            }
+            continue;
+        }
+        let mut keys = vec![];
+        for inst in &mut function.blocks[block.0 as usize].insts {
+            for operand in inst.kind.operands_mut() {
+                resolve(operand, replace);
+            }
+            let (Some(dest), Some(key)) = (inst.dest, key(inst, effects)) else {
+                continue;
+            };
+            match table.get(&key) {
+                Some(existing) => {
+                    replace.insert(dest, existing.clone());
+                }
+                None => {
+                    table.insert(key.clone(), Operand::Value(dest));
+                    keys.push(key);
+                }
+            }
+        }
+        added.push(keys);
+        stack.push((block, true));
+        for &child in children[block.0 as usize].iter().rev() {
+            stack.push((child, false));
+        }
+    }
+}
@@ -0,0 +1,289 @@
+use crate::{
+    alias::{AliasAnalysis, AliasResult},
+    cfg::{Cfg, Dominators},
+    effects::{Effect, Effects},
+    ir::{BlockId, Function, Inst, InstKind, Module, Operand},
+    loops::{Loop, LoopForest},
+};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/*
+    数据依赖图: 节点是指令, 边表示后一条指令必须在前一条之后执行.
+    Data是用到前一条指令的结果; Flow/Anti/Output分别是可能重叠的地址上store->load, load->store, store->store;
+    Order是有副作用的调用之间, 以及调用与它可能访问的load/store之间的顺序.
+    基本块的图只有同一次执行中的依赖. 循环的图包括循环中所有的块, 跨迭代的依赖(carried)是
+    回边送到循环头phi的值, 以及每对有内存依赖的指令从后一条到下一次迭代的前一条; 不计算依赖距离.
+    指令调度用基本块的图; 两种图都可以输出成DOT.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepKind {
+    Data,
+    Flow,
+    Anti,
+    Output,
+    Order,
+}
+
+impl DepKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            DepKind::Data => "data",
+            DepKind::Flow => "flow",
+            DepKind::Anti => "anti",
+            DepKind::Output => "output",
+            DepKind::Order => "order",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dependence {
+    pub from: usize, //nodes中的下标
+    pub to: usize,
+    pub kind: DepKind,
+    pub carried: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DependenceGraph {
+    pub nodes: Vec<(BlockId, usize)>, //(块, 块中第几条指令)
+    pub edges: Vec<Dependence>,
+}
+
+impl DependenceGraph {
+    /* 一个基本块中所有指令(包括phi和终结指令)之间的依赖. */
+    pub fn for_block(
+        function: &Function,
+        block: BlockId,
+        aliases: &AliasAnalysis,
+        effects: &Effects,
+    ) -> Self {
+        let nodes = (0..function.block(block).insts.len())
+            .map(|i| (block, i))
+            .collect();
+        Self::build(function, nodes, None, aliases, effects)
+    }
+
+    /* 循环中所有块的指令, 块按逆后序排列(忽略回边时的拓扑序). */
+    pub fn for_loop(
+        function: &Function,
+        cfg: &Cfg,
+        l: &Loop,
+        aliases: &AliasAnalysis,
+        effects: &Effects,
+    ) -> Self {
+        let nodes = cfg
+            .reverse_postorder()
+            .into_iter()
+            .filter(|b| l.contains(*b))
+            .flat_map(|b| (0..function.block(b).insts.len()).map(move |i| (b, i)))
+            .collect();
+        Self::build(function, nodes, Some(l.header), aliases, effects)
+    }
+
+    fn build(
+        function: &Function,
+        nodes: Vec<(BlockId, usize)>,
+        header: Option<BlockId>,
+        aliases: &AliasAnalysis,
+        effects: &Effects,
+    ) -> Self {
+        let inst = |n: usize| {
+            let (b, i) = nodes[n];
+            &function.block(b).insts[i]
+        };
+        let defined: HashMap<_, usize> = (0..nodes.len())
+            .filter_map(|n| inst(n).dest.map(|d| (d, n)))
+            .collect();
+        let mut edges = vec![];
+        for (to, &(block, _)) in nodes.iter().enumerate() {
+            let is_phi = matches!(inst(to).kind, InstKind::Phi(_));
+            for operand in inst(to).kind.operands() {
+                let Operand::Value(v) = operand else {
+                    continue;
+                };
+                let Some(&from) = defined.get(v) else {
+                    continue;
+                };
+                // phi用到后面的值时经过了回边: 是本循环的就是跨迭代的依赖, 否则(基本块自己的回边, 内层循环)不画出来.
+                let carried = is_phi && header == Some(block);
+                if (from < to || carried)
+                    && !edges
+                        .iter()
+                        .any(|e: &Dependence| (e.from, e.to, e.kind) == (from, to, DepKind::Data))
+                {
+                    edges.push(Dependence {
+                        from,
+                        to,
+                        kind: DepKind::Data,
+                        carried,
+                    });
+                }
+            }
+            for from in 0..to {
+                let Some(kind) = memory_dependence(aliases, effects, inst(from), inst(to)) else {
+                    continue;
+                };
+                edges.push(Dependence {
+                    from,
+                    to,
+                    kind,
+                    carried: false,
+                });
+                if header.is_some() {
+                    let back = memory_dependence(aliases, effects, inst(to), inst(from)).unwrap();
+                    edges.push(Dependence {
+                        from: to,
+                        to: from,
+                        kind: back,
+                        carried: true,
+                    });
+                }
+            }
+        }
+        DependenceGraph { nodes, edges }
+    }
+
+    /* 节点在图中的下标. */
+    pub fn node(&self, block: BlockId, index: usize) -> Option<usize> {
+        self.nodes.iter().position(|n| *n == (block, index))
+    }
+}
+
+enum Access<'a> {
+    Load(&'a Operand),
+    Store(&'a Operand),
+    Call(Effect),
+    None,
+}
+
+fn access<'a>(inst: &'a Inst, effects: &Effects) -> Access<'a> {
+    match &inst.kind {
+        InstKind::Load(addr) => Access::Load(addr),
+        InstKind::Store(_, addr) => Access::Store(addr),
+        InstKind::Call(name, _) if !effects.is_pure(name) => Access::Call(effects.effect(name)),
+        _ => Access::None,
+    }
+}
+
+/* x在y之前时, y是否因为内存或调用必须在x之后. */
+fn memory_dependence(
+    aliases: &AliasAnalysis,
+    effects: &Effects,
+    x: &Inst,
+    y: &Inst,
+) -> Option<DepKind> {
+    let may_alias = |a, b| aliases.alias(a, b) != AliasResult::NoAlias;
+    let kind = match (access(x, effects), access(y, effects)) {
+        (Access::Store(a), Access::Load(b)) if may_alias(a, b) => DepKind::Flow,
+        (Access::Load(a), Access::Store(b)) if may_alias(a, b) => DepKind::Anti,
+        (Access::Store(a), Access::Store(b)) if may_alias(a, b) => DepKind::Output,
+        (Access::Call(e), Access::Call(f)) if e.max(f) > Effect::ReadsMemory => DepKind::Order,
+        (Access::Call(e), Access::Load(a)) | (Access::Load(a), Access::Call(e))
+            if e > Effect::ReadsMemory && aliases.visible_to_calls(a) =>
+        {
+            DepKind::Order
+        }
+        (Access::Call(_), Access::Store(a)) | (Access::Store(a), Access::Call(_))
+            if aliases.visible_to_calls(a) =>
+        {
+            DepKind::Order
+        }
+        _ => return None,
+    };
+    Some(kind)
+}
+
+/* 输出DOT时按基本块还是按循环画图. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    Block,
+    Loop,
+}
+
+impl Scope {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "block" => Some(Scope::Block),
+            "loop" => Some(Scope::Loop),
+            _ => None,
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/* 一张图作为DOT的子图; 跨迭代的边是虚线, 内存和调用的依赖标出种类. */
+fn write_graph(out: &mut String, function: &Function, title: &str, graph: &DependenceGraph) {
+    let prefix = escape(&format!("{}.{}", function.name, title));
+    let _ = writeln!(out, "  subgraph \"cluster_{}\" {{", prefix);
+    let _ = writeln!(
+        out,
+        "    label=\"{}\";",
+        escape(&format!("@{} {}", function.name, title))
+    );
+    for (n, &(b, i)) in graph.nodes.iter().enumerate() {
+        let text = function.block(b).insts[i].to_string();
+        let _ = writeln!(
+            out,
+            "    \"{}.{}\" [label=\"{}: {}\"];",
+            prefix,
+            n,
+            b,
+            escape(&text)
+        );
+    }
+    for edge in &graph.edges {
+        let mut attrs = vec![];
+        if edge.kind != DepKind::Data {
+            attrs.push(format!("label=\"{}\"", edge.kind.name()));
+            attrs.push("color=blue".to_string());
+        }
+        if edge.carried {
+            attrs.push("style=dashed".to_string());
+        }
+        let attrs = if attrs.is_empty() {
+            String::new()
+        } else {
+            format!(" [{}]", attrs.join(", "))
+        };
+        let _ = writeln!(
+            out,
+            "    \"{}.{}\" -> \"{}.{}\"{};",
+            prefix, edge.from, prefix, edge.to, attrs
+        );
+    }
+    let _ = writeln!(out, "  }}");
+}
+
+/* 模块中每个基本块(或每个循环)的数据依赖图, Graphviz的DOT格式. */
+pub fn to_dot(module: &Module, scope: Scope) -> String {
+    let effects = Effects::compute(module);
+    let mut out = String::from("digraph ddg {\n  node [shape=box, fontname=\"monospace\"];\n");
+    for function in module.functions.iter().filter(|f| !f.is_declaration()) {
+        let aliases = AliasAnalysis::compute(function);
+        match scope {
+            Scope::Block => {
+                for b in 0..function.blocks.len() {
+                    let block = BlockId(b as u32);
+                    let graph = DependenceGraph::for_block(function, block, &aliases, &effects);
+                    write_graph(&mut out, function, &block.to_string(), &graph);
+                }
+            }
+            Scope::Loop => {
+                let cfg = Cfg::build(function);
+                let forest = LoopForest::compute(&cfg, &Dominators::compute(&cfg));
+                for l in &forest.loops {
+                    let graph = DependenceGraph::for_loop(function, &cfg, l, &aliases, &effects);
+                    write_graph(&mut out, function, &format!("loop {}", l.header), &graph);
+                }
+            }
+        }
+    }
+    out.push_str("}\n");
+    out
+}
@@ -0,0 +1,387 @@
+use crate::span::{FileId, SourceFile, SourceMap, Span};
+use colored::{ColoredString, Colorize};
+
+/*
+    各阶段共用的诊断信息(Diagnostic)与渲染器.
+    一条诊断由严重程度, 主信息, 若干标注(Label)和附加说明(note)组成,
+    labels中的第一个是主标注(primary), 其余是次要标注(secondary),
+    渲染时会把每个标注所在的源代码行打印出来, 并在对应位置下方画出^^^.
+    fixes是可以自动应用的修改建议(fix-it), 渲染时给出修改后的那一行.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span, //标注在源代码中的区间
+    pub message: String,
+}
+
+/* 修改建议: 把span替换成replacement(为空即删除). */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fix {
+    pub span: Span,
+    pub replacement: String,
+    pub message: String,
+}
+
+/*
+    诊断的种类, 调用者可以据此过滤, 统计或决定是否中止, 而不必解析信息文本.
+    目前只有语义分析的诊断带有种类; 注释中是信息里对应的"Error type"编号, 标明"警告"的种类是Warning.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DiagnosticKind {
+    UndefinedSymbol,     // 1, 3: 未定义的变量或函数
+    Redefinition,        // 2, 4: 重复定义, 以及互相冲突的函数声明
+    NotAFunction,        // 5: 调用的不是函数
+    FunctionAsValue,     // 6: 把函数当作变量使用
+    TypeMismatch,        // 7, 10, 11: 赋值, 实参, 返回值, 运算数, 条件和下标的类型不符
+    IndexMismatch,       // 8: 下标的个数与变量的维度不符, 或常量下标越界
+    ArgumentCount,       // 9: 实参个数不符(包括putf格式串中的转换说明)
+    BreakOutsideLoop,    // 12
+    ContinueOutsideLoop, // 13
+    AssignToConstant,    // 14: 给常量赋值
+    NotConstant,         // 11: 需要常量表达式的地方(常量初始值, case标签)不是常量
+    InvalidArray,        // 15: 数组维度不是正数, 或初始化列表超出数组的大小
+    DuplicateLabel,      // 16: switch中重复的case值或多个default
+    DivisionByZero,      // 11: 常量表达式中整数除以0或对0取模
+    InvalidMain,         // 17: 没有int main(), 或main的返回值/参数不对
+    UnusedVariable,      // 警告: 从未被读取的变量, 常量或参数
+    UnusedFunction,      // 警告: 从main出发调用不到的函数
+    UninitializedRead,   // 警告: 局部变量可能在赋值之前被读取
+    IntegerOverflow,     // 警告: 常量表达式的整数运算溢出, 按32位补码回绕
+    Shadowing,           // 警告(需要开启): 局部变量遮蔽了外层的参数, 局部变量或全局变量
+    ConstantCondition,   // 警告(需要开启): if/while的条件总是真或总是假
+}
+
+impl DiagnosticKind {
+    /* 警告所属的类别; 错误的种类没有类别. */
+    pub fn warning_category(self) -> Option<WarningCategory> {
+        use DiagnosticKind::*;
+        match self {
+            UnusedVariable => Some(WarningCategory::Unused),
+            UnusedFunction => Some(WarningCategory::Unreachable),
+            UninitializedRead => Some(WarningCategory::Uninitialized),
+            IntegerOverflow => Some(WarningCategory::Overflow),
+            Shadowing => Some(WarningCategory::Shadowing),
+            ConstantCondition => Some(WarningCategory::ConstantCondition),
+            _ => None,
+        }
+    }
+}
+
+/* 语义分析的警告类别, 可以分别打开或关闭(见WarningConfig). */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WarningCategory {
+    Unused,            // 从未被读取的变量, 常量和参数
+    Shadowing,         // 局部变量遮蔽外层的同名变量
+    Unreachable,       // 从main出发调用不到的函数
+    Overflow,          // 常量表达式的整数溢出
+    ConstantCondition, // 总是真或总是假的if/while条件
+    Uninitialized,     // 局部变量可能在赋值之前被读取
+}
+
+impl WarningCategory {
+    pub const ALL: [WarningCategory; 6] = [
+        WarningCategory::Unused,
+        WarningCategory::Shadowing,
+        WarningCategory::Unreachable,
+        WarningCategory::Overflow,
+        WarningCategory::ConstantCondition,
+        WarningCategory::Uninitialized,
+    ];
+
+    /* 类别在命令行中的名字, 如-Wno-unused. */
+    pub fn name(self) -> &'static str {
+        match self {
+            WarningCategory::Unused => "unused",
+            WarningCategory::Shadowing => "shadowing",
+            WarningCategory::Unreachable => "unreachable",
+            WarningCategory::Overflow => "overflow",
+            WarningCategory::ConstantCondition => "constant-condition",
+            WarningCategory::Uninitialized => "uninitialized",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.name() == name)
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/*
+    语义分析的警告配置: 每个类别可以单独打开或关闭, werror把保留下来的警告都升级为错误.
+    默认打开unused, unreachable, overflow和uninitialized; shadowing和constant-condition需要显式打开.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WarningConfig {
+    enabled: u8, //按WarningCategory::bit的位集合
+    pub werror: bool,
+}
+
+impl Default for WarningConfig {
+    fn default() -> Self {
+        let mut config = WarningConfig {
+            enabled: 0,
+            werror: false,
+        };
+        for category in WarningCategory::ALL {
+            let opt_in = matches!(
+                category,
+                WarningCategory::Shadowing | WarningCategory::ConstantCondition
+            );
+            config.set(category, !opt_in);
+        }
+        config
+    }
+}
+
+impl WarningConfig {
+    pub fn is_enabled(&self, category: WarningCategory) -> bool {
+        self.enabled & category.bit() != 0
+    }
+
+    pub fn set(&mut self, category: WarningCategory, enabled: bool) {
+        if enabled {
+            self.enabled |= category.bit();
+        } else {
+            self.enabled &= !category.bit();
+        }
+    }
+
+    /* 去掉关闭的类别的警告; werror时把其余的警告升级为错误. 错误和没有类别的诊断原样保留. */
+    pub fn apply(&self, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        diagnostics
+            .into_iter()
+            .filter_map(|mut d| {
+                if d.severity != Severity::Warning {
+                    return Some(d);
+                }
+                let category = d.kind.and_then(DiagnosticKind::warning_category);
+                if category.is_some_and(|c| !self.is_enabled(c)) {
+                    return None;
+                }
+                if self.werror {
+                    d.severity = Severity::Error;
+                    d = d.with_note(match category {
+                        Some(c) => format!("warning `{}` treated as an error (-Werror)", c.name()),
+                        None => "warning treated as an error (-Werror)".to_string(),
+                    });
+                }
+                Some(d)
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub kind: Option<DiagnosticKind>,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub fixes: Vec<Fix>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity,
+            message: message.into(),
+            kind: None,
+            labels: vec![],
+            notes: vec![],
+            fixes: vec![],
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, message)
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /* 追加一个标注, 第一次调用添加的是主标注. */
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_kind(mut self, kind: DiagnosticKind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_fix(
+        mut self,
+        span: Span,
+        replacement: impl Into<String>,
+        message: impl Into<String>,
+    ) -> Self {
+        self.fixes.push(Fix {
+            span,
+            replacement: replacement.into(),
+            message: message.into(),
+        });
+        self
+    }
+
+    /* 把诊断渲染成多行文本, file是标注所在的源文件. 不带颜色, 输出多个文件的诊断请用DiagnosticEngine. */
+    pub fn render(&self, file: &SourceFile) -> String {
+        self.render_with(|_| Some(file), false)
+    }
+
+    /* 渲染的具体实现: file_of按FileId找到标注所在的源文件, 找不到时只输出信息, 不输出源代码片段. */
+    fn render_with<'a>(
+        &self,
+        file_of: impl Fn(FileId) -> Option<&'a SourceFile>,
+        color: bool,
+    ) -> String {
+        let paint = |text: String, style: fn(ColoredString) -> ColoredString| {
+            if color {
+                style(text.normal()).to_string()
+            } else {
+                text
+            }
+        };
+        let (title, severity_style): (_, fn(ColoredString) -> ColoredString) = match self.severity {
+            Severity::Error => ("error", |s| s.red().bold()),
+            Severity::Warning => ("warning", |s| s.yellow().bold()),
+            Severity::Note => ("note", |s| s.cyan().bold()),
+        };
+        let gutter = |text: String| paint(text, |s| s.blue().bold());
+        let mut out = String::new();
+        out.push_str(&format!(
+            "{}{}\n",
+            paint(format!("{}:", title), severity_style),
+            paint(format!(" {}", self.message), |s| s.bold())
+        ));
+        if let Some(primary) = self.labels.first() {
+            if let Some(file) = file_of(primary.span.file_id) {
+                let (_, column) = file.line_col(primary.span.start);
+                let (name, line) = file.logical_location(primary.span.start);
+                out.push_str(&format!(
+                    "{} {}:{}:{}\n",
+                    gutter("  -->".into()),
+                    name,
+                    line,
+                    column
+                ));
+            }
+        }
+        for (i, label) in self.labels.iter().enumerate() {
+            let Some(file) = file_of(label.span.file_id) else {
+                continue;
+            };
+            let (_, column) = file.line_col(label.span.start);
+            let (_, line) = file.logical_location(label.span.start);
+            let text: String = file.line_text(label.span.start).iter().collect();
+            // 主标注用严重程度的颜色, 次要标注用行号栏的颜色.
+            let caret_style = if i == 0 {
+                severity_style
+            } else {
+                |s: ColoredString| s.blue().bold()
+            };
+            out.push_str(&format!("{}\n", gutter("     |".into())));
+            out.push_str(&format!("{} {}\n", gutter(format!(" {:3} |", line)), text));
+            out.push_str(&format!(
+                "{} {}{}\n",
+                gutter("     |".into()),
+                " ".repeat(column - 1),
+                paint(
+                    format!("{} {}", "^".repeat(label.span.len().max(1)), label.message),
+                    caret_style
+                )
+            ));
+        }
+        for note in &self.notes {
+            out.push_str(&format!("{} {}\n", gutter("     = note:".into()), note));
+        }
+        for fix in &self.fixes {
+            let Some(file) = file_of(fix.span.file_id) else {
+                continue;
+            };
+            let (_, line) = file.logical_location(fix.span.start);
+            let chars = &file.chars;
+            let start = fix.span.start.min(chars.len());
+            let end = fix.span.end.min(chars.len());
+            let line_start = file.line_range(file.line(start)).start;
+            let line_end = file.line_range(file.line(end)).end;
+            let before: String = chars[line_start..start].iter().collect();
+            let after: String = chars[end..line_end].iter().collect();
+            out.push_str(&format!(
+                "{} {}\n",
+                gutter("     = help:".into()),
+                fix.message
+            ));
+            out.push_str(&format!(
+                "{} {}{}{}\n",
+                gutter(format!(" {:3} |", line)),
+                before,
+                paint(fix.replacement.clone(), |s| s.green()),
+                after
+            ));
+        }
+        out
+    }
+}
+
+/*
+    共享的诊断输出引擎: 持有参与编译的全部源文件(SourceMap), 按标注的span.file_id找到源代码,
+    渲染出带^^^的片段. 词法, 语法和语义分析的诊断都经由这里输出.
+    color打开时用ANSI颜色区分标题, 行号栏和标注; 关闭时与Diagnostic::render的输出完全相同.
+*/
+pub struct DiagnosticEngine {
+    sources: SourceMap,
+    color: bool,
+}
+
+impl DiagnosticEngine {
+    pub fn new(sources: SourceMap) -> Self {
+        DiagnosticEngine {
+            sources,
+            color: false,
+        }
+    }
+
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn sources(&self) -> &SourceMap {
+        &self.sources
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        diagnostic.render_with(|id| self.sources.get(id), self.color)
+    }
+
+    /* 渲染并输出到标准输出, 与编译器其余的输出保持顺序. */
+    pub fn emit(&self, diagnostic: &Diagnostic) {
+        print!("{}", self.render(diagnostic));
+    }
+}
@@ -0,0 +1,96 @@
+use crate::{
+    alias::{AliasAnalysis, Location, Object},
+    ir::{InstKind, Module},
+};
+use std::collections::HashMap;
+
+/*
+    函数的副作用分析: 按函数体和被调函数把每个函数归为四类之一, 后一类包含前一类.
+    只访问自己的局部数组(包括传给被调函数的)不算访问内存; 读写全局变量和数组形参才算.
+    运行时库函数(getint, putint, starttime等)都只有声明, 一律按输入输出处理.
+    递归的函数从Pure开始反复计算到不再变化.
+    没有副作用的函数的调用可以像算术指令一样参与CSE, 结果没有用到时可以删除.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Effect {
+    Pure,
+    ReadsMemory,
+    WritesMemory,
+    DoesIo,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Effects {
+    functions: HashMap<String, Effect>,
+}
+
+impl Effects {
+    pub fn compute(module: &Module) -> Effects {
+        let mut functions: HashMap<String, Effect> = module
+            .functions
+            .iter()
+            .map(|f| {
+                let effect = if f.is_declaration() {
+                    Effect::DoesIo
+                } else {
+                    Effect::Pure
+                };
+                (f.name.clone(), effect)
+            })
+            .collect();
+        let aliases: Vec<AliasAnalysis> = module
+            .functions
+            .iter()
+            .map(AliasAnalysis::compute)
+            .collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (function, aliases) in module.functions.iter().zip(&aliases) {
+                if function.is_declaration() {
+                    continue;
+                }
+                let nonlocal = |addr| {
+                    !matches!(
+                        aliases.location(addr),
+                        Some(Location {
+                            object: Object::Stack(_),
+                            ..
+                        })
+                    )
+                };
+                let mut effect = Effect::Pure;
+                for inst in function.blocks.iter().flat_map(|b| &b.insts) {
+                    let this = match &inst.kind {
+                        InstKind::Load(addr) if nonlocal(addr) => Effect::ReadsMemory,
+                        InstKind::Store(_, addr) if nonlocal(addr) => Effect::WritesMemory,
+                        // 模块中没有的函数按输入输出处理.
+                        InstKind::Call(name, _) => {
+                            functions.get(name).copied().unwrap_or(Effect::DoesIo)
+                        }
+                        _ => Effect::Pure,
+                    };
+                    effect = effect.max(this);
+                }
+                if effect > functions[&function.name] {
+                    functions.insert(function.name.clone(), effect);
+                    changed = true;
+                }
+            }
+        }
+        Effects { functions }
+    }
+
+    /* 不知道的函数按输入输出处理, 所以空的Effects(默认值)对任何调用都是保守的. */
+    pub fn effect(&self, function: &str) -> Effect {
+        self.functions
+            .get(function)
+            .copied()
+            .unwrap_or(Effect::DoesIo)
+    }
+
+    pub fn is_pure(&self, function: &str) -> bool {
+        self.effect(function) == Effect::Pure
+    }
+}
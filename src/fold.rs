@@ -0,0 +1,195 @@
+use crate::{parser::Node, NodeKind, NodeType};
+
+/*
+    AST的变换: Folder为每个节点生成一个新节点. 默认实现是恒等变换(fold_children),
+    只重写关心的fold_*方法就能写出小的改写(比如把某种语法糖展开成已有的节点),
+    不必再写一遍覆盖所有NodeType的match. 语义分析(semantics::traverse)也是一个Folder.
+    子节点的定义与visit::children相同: Access/Assign/Call中指向声明或被调函数的字段原样保留, 不被变换.
+*/
+pub trait Folder {
+    /* 变换任意节点, 默认按种类分派到下面的fold_*方法. */
+    fn fold_node(&mut self, node: &Node) -> Node {
+        dispatch(self, node)
+    }
+
+    fn fold_decl(&mut self, node: &Node) -> Node {
+        fold_children(self, node)
+    }
+    fn fold_decl_stmt(&mut self, node: &Node) -> Node {
+        fold_children(self, node)
+    }
+    fn fold_init_list(&mut self, node: &Node) -> Node {
+        fold_children(self, node)
+    }
+    fn fold_assign(&mut self, node: &Node) -> Node {
+        fold_children(self, node)
+    }
+    fn fold_expr_stmt(&mut self, node: &Node) -> Node {
+        fold_children(self, node)
+    }
+    fn fold_access(&mut self, node: &Node) -> Node {
+        fold_children(self, node)
+    }
+    fn fold_binop(&mut self, node: &Node) -> Node {
+        fold_children(self, node)
+    }
+    fn fold_unary(&mut self, node: &Node) -> Node {
+        fold_children(self, node)
+    }
+    fn fold_cond(&mut self, node: &Node) -> Node {
+        fold_children(self, node)
+    }
+    fn fold_comma(&mut self, node: &Node) -> Node {
+        fold_children(self, node)
+    }
+    fn fold_cast(&mut self, node: &Node) -> Node {
+        fold_children(self, node)
+    }
+    fn fold_func(&mut self, node: &Node) -> Node {
+        fold_children(self, node)
+    }
+    fn fold_block(&mut self, node: &Node) -> Node {
+        fold_children(self, node)
+    }
+    fn fold_return(&mut self, node: &Node) -> Node {
+        fold_children(self, node)
+    }
+    fn fold_call(&mut self, node: &Node) -> Node {
+        fold_children(self, node)
+    }
+    fn fold_if(&mut self, node: &Node) -> Node {
+        fold_children(self, node)
+    }
+    fn fold_while(&mut self, node: &Node) -> Node {
+        fold_children(self, node)
+    }
+    fn fold_switch(&mut self, node: &Node) -> Node {
+        fold_children(self, node)
+    }
+    fn fold_case(&mut self, node: &Node) -> Node {
+        fold_children(self, node)
+    }
+    /* 没有子节点的语句: Continue, Break, Empty */
+    fn fold_jump(&mut self, node: &Node) -> Node {
+        node.clone()
+    }
+    /* 常量和占位节点: Number, FloatNumber, Str, Nil */
+    fn fold_leaf(&mut self, node: &Node) -> Node {
+        node.clone()
+    }
+}
+
+/* 按节点的种类调用对应的fold_*方法. */
+pub fn dispatch<F: Folder + ?Sized>(folder: &mut F, node: &Node) -> Node {
+    match node.kind() {
+        NodeKind::Decl => folder.fold_decl(node),
+        NodeKind::DeclStmt => folder.fold_decl_stmt(node),
+        NodeKind::InitList => folder.fold_init_list(node),
+        NodeKind::Assign => folder.fold_assign(node),
+        NodeKind::ExprStmt => folder.fold_expr_stmt(node),
+        NodeKind::Access => folder.fold_access(node),
+        NodeKind::BinOp => folder.fold_binop(node),
+        NodeKind::UnaryOp => folder.fold_unary(node),
+        NodeKind::Cond => folder.fold_cond(node),
+        NodeKind::Comma => folder.fold_comma(node),
+        NodeKind::Cast => folder.fold_cast(node),
+        NodeKind::Func => folder.fold_func(node),
+        NodeKind::Block => folder.fold_block(node),
+        NodeKind::Return => folder.fold_return(node),
+        NodeKind::Call => folder.fold_call(node),
+        NodeKind::If => folder.fold_if(node),
+        NodeKind::While => folder.fold_while(node),
+        NodeKind::Switch => folder.fold_switch(node),
+        NodeKind::Case => folder.fold_case(node),
+        NodeKind::Continue | NodeKind::Break | NodeKind::Empty => folder.fold_jump(node),
+        NodeKind::Nil | NodeKind::Number | NodeKind::FloatNumber | NodeKind::Str => {
+            folder.fold_leaf(node)
+        }
+    }
+}
+
+/* 变换一组顶层节点. */
+pub fn fold_all<F: Folder + ?Sized>(folder: &mut F, ast: &[Node]) -> Vec<Node> {
+    ast.iter().map(|node| folder.fold_node(node)).collect()
+}
+
+/*
+    按源代码顺序把node的每个子节点换成folder.fold_node的结果,
+    节点本身的编号, 位置和类型不变. 函数原型的函数体(Nil)原样保留.
+*/
+pub fn fold_children<F: Folder + ?Sized>(folder: &mut F, node: &Node) -> Node {
+    use NodeType::*;
+    let mut fold = |n: &Node| folder.fold_node(n);
+    let node_type = match &node.node_type {
+        Decl(ty, name, dims, inits, scope) => {
+            let dims = dims.as_ref().map(|d| d.iter().map(&mut fold).collect());
+            let inits = inits.as_ref().map(|i| i.iter().map(&mut fold).collect());
+            Decl(ty.clone(), name.clone(), dims, inits, scope.clone())
+        }
+        DeclStmt(nodes) => DeclStmt(nodes.iter().map(fold).collect()),
+        InitList(nodes) => InitList(nodes.iter().map(fold).collect()),
+        Block(nodes) => Block(nodes.iter().map(fold).collect()),
+        Comma(nodes) => Comma(nodes.iter().map(fold).collect()),
+        Access(name, indexes, decl) => {
+            let indexes = indexes.as_ref().map(|i| i.iter().map(fold).collect());
+            Access(name.clone(), indexes, *decl)
+        }
+        Assign(name, indexes, expr, decl) => {
+            let indexes = indexes.as_ref().map(|i| i.iter().map(&mut fold).collect());
+            Assign(name.clone(), indexes, Box::new(fold(expr)), *decl)
+        }
+        ExprStmt(expr) => ExprStmt(Box::new(fold(expr))),
+        UnaryOp(op, operand) => UnaryOp(op.clone(), Box::new(fold(operand))),
+        Cast(ty, expr) => Cast(ty.clone(), Box::new(fold(expr))),
+        BinOp(op, lhs, rhs) => {
+            let lhs = fold(lhs);
+            BinOp(op.clone(), Box::new(lhs), Box::new(fold(rhs)))
+        }
+        Cond(cond, on_true, on_false) => {
+            let cond = fold(cond);
+            let on_true = fold(on_true);
+            Cond(Box::new(cond), Box::new(on_true), Box::new(fold(on_false)))
+        }
+        Func(ret, name, params, body) => {
+            let params = params.iter().map(&mut fold).collect();
+            let body = if matches!(body.node_type, Nil) {
+                body.clone()
+            } else {
+                Box::new(fold(body))
+            };
+            Func(ret.clone(), name.clone(), params, body)
+        }
+        Return(expr) => Return(expr.as_ref().map(|e| Box::new(fold(e)))),
+        Call(name, args, callee) => Call(name.clone(), args.iter().map(fold).collect(), *callee),
+        If(cond, on_true, on_false) => {
+            let cond = fold(cond);
+            let on_true = fold(on_true);
+            If(
+                Box::new(cond),
+                Box::new(on_true),
+                on_false.as_ref().map(|f| Box::new(fold(f))),
+            )
+        }
+        While(cond, body) => {
+            let cond = fold(cond);
+            While(Box::new(cond), Box::new(fold(body)))
+        }
+        Switch(scrutinee, arms) => {
+            let scrutinee = fold(scrutinee);
+            Switch(Box::new(scrutinee), arms.iter().map(fold).collect())
+        }
+        Case(label, stmts) => {
+            let label = label.as_ref().map(|l| Box::new(fold(l)));
+            Case(label, stmts.iter().map(fold).collect())
+        }
+        Continue | Break | Empty | Nil | Number(_) | FloatNumber(_) | Str(_) => {
+            node.node_type.clone()
+        }
+    };
+    Node {
+        id: node.id,
+        node_type,
+        basic_type: node.basic_type.clone(),
+        span: node.span,
+    }
+}
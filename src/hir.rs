@@ -0,0 +1,556 @@
+use crate::{
+    parser::{Node, NodeId},
+    semantics::runtime_declarations,
+    span::Span,
+    BasicType, NodeType, Scope, TokenType,
+};
+use std::collections::HashMap;
+
+/*
+    HIR(High-level IR): 语义分析的结果, 一棵带类型的树.
+    与语义AST(复用parser::Node)的区别:
+      1. 每个表达式都有确定的类型(ty), 语句和声明没有类型字段, 不再用basic_type = Nil表示"没有类型".
+      2. 对变量和函数的引用是符号编号(SymbolId), 符号表(symbols)中记录名字, 类型和声明的位置,
+         不再在每个Access/Assign/Call中挂一份声明节点.
+      3. 语句, 表达式和声明是不同的类型, 某个位置能出现什么由类型保证.
+    节点编号(id)和位置(span)与语义AST相同. 语义分析出错时, 占位的Nil节点对应Error.
+    检查器在语义AST上工作, from_nodes由它的结果建立HIR; 编译器在语义AST上的变换(whole_program...)做完之后只建立一次.
+    to_nodes把HIR转换回语义AST, 供只拿到HIR的调用者使用现有的打印和检查(verify).
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SymbolId(pub usize);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SymbolKind {
+    Variable(Scope),
+    Function {
+        ret: BasicType,
+        params: Vec<SymbolId>,
+        prototype: bool, //函数原型(没有函数体)
+    },
+    // 未定义的函数(已经报告过错误), 调用它的Call仍然需要一个被调用者.
+    Unresolved,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    /*
+        在整个程序中唯一的名字(alpha-renaming): 全局变量和函数就是name, 与之前的符号重名的
+        局部变量和参数改名为"name.序号", 例如嵌套作用域中的第二个i是i.1. '.'不会出现在SysY的标识符中.
+    */
+    pub unique_name: String,
+    pub ty: BasicType, //变量的类型; 函数为Func(返回类型)
+    pub kind: SymbolKind,
+    pub decl: NodeId, //声明节点的编号, 运行时库函数的声明不在树中
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hir {
+    pub items: Vec<Item>,
+    pub symbols: Vec<Symbol>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    pub id: NodeId,
+    pub span: Span,
+    pub kind: ItemKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ItemKind {
+    Globals(Vec<VarDecl>),
+    Func(Func),
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Func {
+    pub symbol: SymbolId,
+    pub params: Vec<VarDecl>,
+    pub body: Option<Stmt>, //函数原型为None
+}
+
+/* 一个变量(常量, 形参)的声明; 名字, 类型和作用域在符号表中. */
+#[derive(Debug, Clone, PartialEq)]
+pub struct VarDecl {
+    pub id: NodeId,
+    pub span: Span,
+    pub symbol: SymbolId,
+    pub dims: Option<Vec<Expr>>,
+    pub init: Option<Vec<Expr>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub id: NodeId,
+    pub span: Span,
+    pub kind: StmtKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtKind {
+    Decls(Vec<VarDecl>),
+    Assign(Assign),
+    Expr(Expr),
+    Block(Vec<Stmt>),
+    Return(Option<Expr>),
+    If(Expr, Box<Stmt>, Option<Box<Stmt>>),
+    While(Expr, Box<Stmt>),
+    Switch(Expr, Vec<Case>),
+    Break,
+    Continue,
+    Empty,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Case {
+    pub id: NodeId,
+    pub span: Span,
+    pub label: Option<Expr>, //None表示default
+    pub body: Vec<Stmt>,
+}
+
+/* 赋值既是语句, 也可以是逗号表达式的操作数(扩展模式). */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assign {
+    pub target: SymbolId,
+    pub indexes: Option<Vec<Expr>>,
+    pub value: Box<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub id: NodeId,
+    pub span: Span,
+    pub ty: BasicType,
+    pub kind: ExprKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Int(i32),
+    Float(f32),
+    Str(String),
+    Var(SymbolId, Option<Vec<Expr>>),
+    Assign(Box<Assign>),
+    Binary(TokenType, Box<Expr>, Box<Expr>),
+    Unary(TokenType, Box<Expr>),
+    Cond(Box<Expr>, Box<Expr>, Box<Expr>),
+    Comma(Vec<Expr>),
+    Cast(BasicType, Box<Expr>),
+    Call(SymbolId, Vec<Expr>),
+    InitList(Vec<Expr>),
+    Error,
+}
+
+impl Hir {
+    pub fn symbol(&self, id: SymbolId) -> &Symbol {
+        &self.symbols[id.0]
+    }
+
+    /* 从语义AST(semantic的结果)建立HIR: 声明和被引用的声明节点按编号合并成同一个符号. */
+    pub fn from_nodes(annotated_ast: &[Node]) -> Hir {
+        let mut builder = Builder {
+            symbols: vec![],
+            by_decl: HashMap::new(),
+            renamed: HashMap::new(),
+        };
+        // 先登记全局变量和函数: 调用中挂的被调用者是未经检查的副本, 符号以树中检查过的声明为准.
+        for node in annotated_ast {
+            match &node.node_type {
+                NodeType::DeclStmt(decls) => decls.iter().for_each(|d| {
+                    builder.symbol(d, "");
+                }),
+                NodeType::Func(..) => {
+                    builder.symbol(node, "");
+                }
+                _ => {}
+            }
+        }
+        let items = annotated_ast.iter().map(|n| builder.item(n)).collect();
+        Hir {
+            items,
+            symbols: builder.symbols,
+        }
+    }
+
+    /* 转换回语义AST, 引用中记录的是符号的声明编号(Symbol::decl). */
+    pub fn to_nodes(&self) -> Vec<Node> {
+        let mut converter = Converter { hir: self };
+        self.items.iter().map(|i| converter.item(i)).collect()
+    }
+}
+
+struct Builder {
+    symbols: Vec<Symbol>,
+    by_decl: HashMap<NodeId, SymbolId>,
+    renamed: HashMap<String, usize>, //每个名字已经有几个符号, 用于生成unique_name
+}
+
+impl Builder {
+    /* 声明节点(树中的Decl/Func, 或引用中的声明)对应的符号, 第一次遇到时加入符号表. */
+    fn symbol(&mut self, decl: &Node, referenced_as: &str) -> SymbolId {
+        if let Some(id) = self.by_decl.get(&decl.id) {
+            return *id;
+        }
+        let (name, ty, kind) = match &decl.node_type {
+            NodeType::Decl(ty, name, _, _, scope) => (
+                name.clone(),
+                ty.clone(),
+                SymbolKind::Variable(scope.clone()),
+            ),
+            NodeType::Func(ret, name, params, body) => {
+                let params = params.iter().map(|p| self.symbol(p, "")).collect();
+                let kind = SymbolKind::Function {
+                    ret: ret.clone(),
+                    params,
+                    prototype: matches!(body.node_type, NodeType::Nil),
+                };
+                (name.clone(), BasicType::Func(Box::new(ret.clone())), kind)
+            }
+            _ => (
+                referenced_as.to_string(),
+                BasicType::Nil,
+                SymbolKind::Unresolved,
+            ),
+        };
+        let count = self.renamed.entry(name.clone()).or_insert(0);
+        let unique_name = match kind {
+            SymbolKind::Variable(Scope::Local | Scope::Params) if *count > 0 => {
+                format!("{}.{}", name, count)
+            }
+            _ => name.clone(),
+        };
+        *count += 1;
+        let id = SymbolId(self.symbols.len());
+        self.symbols.push(Symbol {
+            name,
+            unique_name,
+            ty,
+            kind,
+            decl: decl.id,
+            span: decl.span,
+        });
+        self.by_decl.insert(decl.id, id);
+        id
+    }
+
+    /*
+        引用(Access/Assign/Call)中的声明编号对应的符号. 树中的声明在引用之前已经登记过,
+        其余的是运行时库函数, 或者未定义的名字(已经报告过错误).
+    */
+    fn reference(&mut self, decl: NodeId, name: &str) -> SymbolId {
+        if let Some(id) = self.by_decl.get(&decl) {
+            return *id;
+        }
+        match runtime_declarations().iter().find(|d| d.id == decl) {
+            Some(runtime) => self.symbol(runtime, ""),
+            None => self.symbol(
+                &Node {
+                    id: decl,
+                    ..Node::new(NodeType::Nil)
+                },
+                name,
+            ),
+        }
+    }
+
+    fn item(&mut self, node: &Node) -> Item {
+        let kind = match &node.node_type {
+            NodeType::DeclStmt(decls) => ItemKind::Globals(self.var_decls(decls)),
+            NodeType::Func(_, _, params, body) => {
+                let symbol = self.symbol(node, "");
+                let params = self.var_decls(params);
+                let body = match body.node_type {
+                    NodeType::Nil => None,
+                    _ => Some(self.stmt(body)),
+                };
+                ItemKind::Func(Func {
+                    symbol,
+                    params,
+                    body,
+                })
+            }
+            _ => ItemKind::Error,
+        };
+        Item {
+            id: node.id,
+            span: node.span,
+            kind,
+        }
+    }
+
+    fn var_decls(&mut self, decls: &[Node]) -> Vec<VarDecl> {
+        decls.iter().map(|d| self.var_decl(d)).collect()
+    }
+
+    fn var_decl(&mut self, node: &Node) -> VarDecl {
+        let NodeType::Decl(_, _, dims, inits, _) = &node.node_type else {
+            unreachable!("only Decl nodes appear in declaration lists")
+        };
+        VarDecl {
+            id: node.id,
+            span: node.span,
+            symbol: self.symbol(node, ""),
+            dims: dims.as_ref().map(|d| self.exprs(d)),
+            init: inits.as_ref().map(|i| self.exprs(i)),
+        }
+    }
+
+    fn stmts(&mut self, nodes: &[Node]) -> Vec<Stmt> {
+        nodes.iter().map(|n| self.stmt(n)).collect()
+    }
+
+    fn stmt(&mut self, node: &Node) -> Stmt {
+        use NodeType::*;
+        let kind = match &node.node_type {
+            DeclStmt(decls) => StmtKind::Decls(self.var_decls(decls)),
+            Assign(..) => StmtKind::Assign(self.assign(node)),
+            ExprStmt(expr) => StmtKind::Expr(self.expr(expr)),
+            Block(stmts) => StmtKind::Block(self.stmts(stmts)),
+            Return(expr) => StmtKind::Return(expr.as_ref().map(|e| self.expr(e))),
+            If(cond, on_true, on_false) => StmtKind::If(
+                self.expr(cond),
+                Box::new(self.stmt(on_true)),
+                on_false.as_ref().map(|f| Box::new(self.stmt(f))),
+            ),
+            While(cond, body) => StmtKind::While(self.expr(cond), Box::new(self.stmt(body))),
+            Switch(scrutinee, arms) => {
+                let cases = arms
+                    .iter()
+                    .filter_map(|arm| match &arm.node_type {
+                        Case(label, body) => Some(self::Case {
+                            id: arm.id,
+                            span: arm.span,
+                            label: label.as_ref().map(|l| self.expr(l)),
+                            body: self.stmts(body),
+                        }),
+                        _ => None,
+                    })
+                    .collect();
+                StmtKind::Switch(self.expr(scrutinee), cases)
+            }
+            Break => StmtKind::Break,
+            Continue => StmtKind::Continue,
+            Empty => StmtKind::Empty,
+            _ => StmtKind::Error,
+        };
+        Stmt {
+            id: node.id,
+            span: node.span,
+            kind,
+        }
+    }
+
+    fn assign(&mut self, node: &Node) -> Assign {
+        let NodeType::Assign(name, indexes, value, decl) = &node.node_type else {
+            unreachable!()
+        };
+        Assign {
+            target: self.reference(*decl, name),
+            indexes: indexes.as_ref().map(|i| self.exprs(i)),
+            value: Box::new(self.expr(value)),
+        }
+    }
+
+    fn exprs(&mut self, nodes: &[Node]) -> Vec<Expr> {
+        nodes.iter().map(|n| self.expr(n)).collect()
+    }
+
+    fn expr(&mut self, node: &Node) -> Expr {
+        use NodeType::*;
+        let boxed = |b: &mut Builder, n: &Node| Box::new(b.expr(n));
+        let kind = match &node.node_type {
+            Number(n) => ExprKind::Int(*n),
+            FloatNumber(f) => ExprKind::Float(*f),
+            Str(s) => ExprKind::Str(s.clone()),
+            Access(name, indexes, decl) => ExprKind::Var(
+                self.reference(*decl, name),
+                indexes.as_ref().map(|i| self.exprs(i)),
+            ),
+            Assign(..) => ExprKind::Assign(Box::new(self.assign(node))),
+            BinOp(op, lhs, rhs) => ExprKind::Binary(op.clone(), boxed(self, lhs), boxed(self, rhs)),
+            UnaryOp(op, operand) => ExprKind::Unary(op.clone(), boxed(self, operand)),
+            Cond(cond, on_true, on_false) => ExprKind::Cond(
+                boxed(self, cond),
+                boxed(self, on_true),
+                boxed(self, on_false),
+            ),
+            Comma(operands) => ExprKind::Comma(self.exprs(operands)),
+            Cast(ty, expr) => ExprKind::Cast(ty.clone(), boxed(self, expr)),
+            Call(name, args, callee) => {
+                let callee = self.reference(*callee, name);
+                ExprKind::Call(callee, self.exprs(args))
+            }
+            InitList(elements) => ExprKind::InitList(self.exprs(elements)),
+            _ => ExprKind::Error,
+        };
+        Expr {
+            id: node.id,
+            span: node.span,
+            ty: node.basic_type.clone(),
+            kind,
+        }
+    }
+}
+
+struct Converter<'a> {
+    hir: &'a Hir,
+}
+
+fn node(id: NodeId, span: Span, basic_type: BasicType, node_type: NodeType) -> Node {
+    Node {
+        id,
+        span,
+        basic_type,
+        node_type,
+    }
+}
+
+impl Converter<'_> {
+    /* 引用符号时记录的声明编号. */
+    fn decl(&self, id: SymbolId) -> NodeId {
+        self.hir.symbol(id).decl
+    }
+
+    fn item(&mut self, item: &Item) -> Node {
+        let node_type = match &item.kind {
+            ItemKind::Globals(decls) => NodeType::DeclStmt(self.var_decls(decls)),
+            ItemKind::Func(func) => {
+                let symbol = self.hir.symbol(func.symbol);
+                let SymbolKind::Function { ret, .. } = &symbol.kind else {
+                    unreachable!()
+                };
+                let body = match &func.body {
+                    Some(body) => self.stmt(body),
+                    None => node(item.id, item.span, BasicType::Nil, NodeType::Nil),
+                };
+                NodeType::Func(
+                    ret.clone(),
+                    symbol.name.clone(),
+                    self.var_decls(&func.params),
+                    Box::new(body),
+                )
+            }
+            ItemKind::Error => NodeType::Nil,
+        };
+        node(item.id, item.span, BasicType::Nil, node_type)
+    }
+
+    fn var_decls(&mut self, decls: &[VarDecl]) -> Vec<Node> {
+        decls.iter().map(|d| self.var_decl(d)).collect()
+    }
+
+    fn var_decl(&mut self, decl: &VarDecl) -> Node {
+        let symbol = self.hir.symbol(decl.symbol);
+        let SymbolKind::Variable(scope) = &symbol.kind else {
+            unreachable!()
+        };
+        let node_type = NodeType::Decl(
+            symbol.ty.clone(),
+            symbol.name.clone(),
+            decl.dims.as_ref().map(|d| self.exprs(d)),
+            decl.init.as_ref().map(|i| self.exprs(i)),
+            scope.clone(),
+        );
+        node(decl.id, decl.span, BasicType::Nil, node_type)
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) -> Vec<Node> {
+        stmts.iter().map(|s| self.stmt(s)).collect()
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Node {
+        let boxed = |c: &mut Converter, s: &Stmt| Box::new(c.stmt(s));
+        let node_type = match &stmt.kind {
+            StmtKind::Decls(decls) => NodeType::DeclStmt(self.var_decls(decls)),
+            StmtKind::Assign(assign) => self.assign(assign),
+            StmtKind::Expr(expr) => NodeType::ExprStmt(Box::new(self.expr(expr))),
+            StmtKind::Block(stmts) => NodeType::Block(self.stmts(stmts)),
+            StmtKind::Return(expr) => {
+                NodeType::Return(expr.as_ref().map(|e| Box::new(self.expr(e))))
+            }
+            StmtKind::If(cond, on_true, on_false) => NodeType::If(
+                Box::new(self.expr(cond)),
+                boxed(self, on_true),
+                on_false.as_ref().map(|f| boxed(self, f)),
+            ),
+            StmtKind::While(cond, body) => {
+                NodeType::While(Box::new(self.expr(cond)), boxed(self, body))
+            }
+            StmtKind::Switch(scrutinee, cases) => {
+                let arms = cases
+                    .iter()
+                    .map(|case| {
+                        let label = case.label.as_ref().map(|l| Box::new(self.expr(l)));
+                        let body = self.stmts(&case.body);
+                        node(
+                            case.id,
+                            case.span,
+                            BasicType::Nil,
+                            NodeType::Case(label, body),
+                        )
+                    })
+                    .collect();
+                NodeType::Switch(Box::new(self.expr(scrutinee)), arms)
+            }
+            StmtKind::Break => NodeType::Break,
+            StmtKind::Continue => NodeType::Continue,
+            StmtKind::Empty => NodeType::Empty,
+            StmtKind::Error => NodeType::Nil,
+        };
+        node(stmt.id, stmt.span, BasicType::Nil, node_type)
+    }
+
+    fn assign(&mut self, assign: &Assign) -> NodeType {
+        NodeType::Assign(
+            self.hir.symbol(assign.target).name.clone(),
+            assign.indexes.as_ref().map(|i| self.exprs(i)),
+            Box::new(self.expr(&assign.value)),
+            self.decl(assign.target),
+        )
+    }
+
+    fn exprs(&mut self, exprs: &[Expr]) -> Vec<Node> {
+        exprs.iter().map(|e| self.expr(e)).collect()
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Node {
+        let boxed = |c: &mut Converter, e: &Expr| Box::new(c.expr(e));
+        let node_type = match &expr.kind {
+            ExprKind::Int(n) => NodeType::Number(*n),
+            ExprKind::Float(f) => NodeType::FloatNumber(*f),
+            ExprKind::Str(s) => NodeType::Str(s.clone()),
+            ExprKind::Var(symbol, indexes) => NodeType::Access(
+                self.hir.symbol(*symbol).name.clone(),
+                indexes.as_ref().map(|i| self.exprs(i)),
+                self.decl(*symbol),
+            ),
+            ExprKind::Assign(assign) => self.assign(assign),
+            ExprKind::Binary(op, lhs, rhs) => {
+                NodeType::BinOp(op.clone(), boxed(self, lhs), boxed(self, rhs))
+            }
+            ExprKind::Unary(op, operand) => NodeType::UnaryOp(op.clone(), boxed(self, operand)),
+            ExprKind::Cond(cond, on_true, on_false) => NodeType::Cond(
+                boxed(self, cond),
+                boxed(self, on_true),
+                boxed(self, on_false),
+            ),
+            ExprKind::Comma(operands) => NodeType::Comma(self.exprs(operands)),
+            ExprKind::Cast(ty, operand) => NodeType::Cast(ty.clone(), boxed(self, operand)),
+            ExprKind::Call(callee, args) => NodeType::Call(
+                self.hir.symbol(*callee).name.clone(),
+                self.exprs(args),
+                self.decl(*callee),
+            ),
+            ExprKind::InitList(elements) => NodeType::InitList(self.exprs(elements)),
+            ExprKind::Error => NodeType::Nil,
+        };
+        node(expr.id, expr.span, expr.ty.clone(), node_type)
+    }
+}
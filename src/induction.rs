@@ -0,0 +1,329 @@
+use crate::{
+    ir::{BinOp, BlockId, CmpOp, Function, Inst, InstKind, Operand, Type, Value},
+    loops::LoopForest,
+};
+use std::collections::HashMap;
+
+/*
+    归纳变量分析:
+    基本归纳变量是循环头中的phi i = phi [preheader: init, latch: i + step], step是常数;
+    派生归纳变量是循环中由基本归纳变量经常数的加减乘和左移得到的值 j = scale * i + offset.
+    第n次迭代(从0开始)中 i = init + n * step, j = scale * init + offset + n * scale * step, 都按i32回绕.
+    只看从循环外一处进入, 只有一条回边的循环. 强度削弱, 循环展开的迭代次数和下标范围的推理都以此为基础.
+*/
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicInduction {
+    pub lp: usize, //LoopForest::loops中的下标
+    pub header: BlockId,
+    pub preheader: BlockId, //唯一的循环外前驱, 不一定只跳到循环头
+    pub latch: BlockId,
+    pub init: Operand,
+    pub step: i32,
+}
+
+/* 值 = scale * basic + offset, basic是基本归纳变量. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Affine {
+    pub basic: Value,
+    pub scale: i32,
+    pub offset: i32,
+}
+
+/* 第n次迭代中的值为 init * scale + offset + n * step; step已经乘过scale. */
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClosedForm {
+    pub init: Operand,
+    pub scale: i32,
+    pub offset: i32,
+    pub step: i32,
+}
+
+impl ClosedForm {
+    /* init是常数时第n次迭代中的值. */
+    pub fn at(&self, n: i32) -> Option<i32> {
+        let Operand::Int(init) = self.init else {
+            return None;
+        };
+        Some(
+            init.wrapping_mul(self.scale)
+                .wrapping_add(self.offset)
+                .wrapping_add(n.wrapping_mul(self.step)),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Inductions {
+    basic: HashMap<Value, BasicInduction>,
+    affine: HashMap<Value, Affine>, //包括基本归纳变量自己(scale 1, offset 0)
+}
+
+impl Inductions {
+    pub fn compute(function: &Function, loops: &LoopForest) -> Inductions {
+        // 先把循环头中所有两个来源的i32 phi都当作基本归纳变量, 推出派生的值之后,
+        // 只保留回边上的值是phi + 常数的那些, 这样i = i + 1 + 2, 经过复制的i也能认出来.
+        let mut basic = HashMap::new();
+        let mut next = HashMap::new();
+        for (lp, l) in loops.loops.iter().enumerate() {
+            let ([preheader], [latch]) = (&l.entering[..], &l.latches[..]) else {
+                continue;
+            };
+            for inst in &function.block(l.header).insts {
+                let (Some(phi), InstKind::Phi(incoming)) = (inst.dest, &inst.kind) else {
+                    continue;
+                };
+                if inst.ty != Type::I32 || incoming.len() != 2 {
+                    continue;
+                }
+                let source = |pred| incoming.iter().find(|(p, _)| p == pred).map(|(_, v)| v);
+                let (Some(init), Some(Operand::Value(value))) = (source(preheader), source(latch))
+                else {
+                    continue;
+                };
+                next.insert(phi, *value);
+                basic.insert(
+                    phi,
+                    BasicInduction {
+                        lp,
+                        header: l.header,
+                        preheader: *preheader,
+                        latch: *latch,
+                        init: init.clone(),
+                        step: 0,
+                    },
+                );
+            }
+        }
+        let mut affine = derive(function, loops, &basic);
+        basic.retain(|phi, induction| match affine.get(&next[phi]) {
+            Some(a) if a.basic == *phi && a.scale == 1 => {
+                induction.step = a.offset;
+                true
+            }
+            _ => false,
+        });
+        affine.retain(|_, a| basic.contains_key(&a.basic));
+        Inductions { basic, affine }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.basic.is_empty()
+    }
+
+    pub fn basic(&self, value: Value) -> Option<&BasicInduction> {
+        self.basic.get(&value)
+    }
+
+    /* 基本和派生的归纳变量都有; 不是归纳变量时为None. */
+    pub fn affine(&self, value: Value) -> Option<Affine> {
+        self.affine.get(&value).copied()
+    }
+
+    pub fn closed_form(&self, value: Value) -> Option<ClosedForm> {
+        let a = self.affine(value)?;
+        let basic = &self.basic[&a.basic];
+        Some(ClosedForm {
+            init: basic.init.clone(),
+            scale: a.scale,
+            offset: a.offset,
+            step: basic.step.wrapping_mul(a.scale),
+        })
+    }
+
+    /* 循环的基本归纳变量, 按编号排序. */
+    pub fn of_loop(&self, lp: usize) -> Vec<Value> {
+        let mut values: Vec<Value> = self
+            .basic
+            .iter()
+            .filter(|(_, b)| b.lp == lp)
+            .map(|(v, _)| *v)
+            .collect();
+        values.sort();
+        values
+    }
+
+    /*
+        循环体执行的次数: 只有循环头能离开循环, 循环头的条件比较一个初值是常数的归纳变量和常数,
+        并且归纳变量在最后一次比较之前不回绕时才能算出来.
+    */
+    pub fn trip_count(&self, function: &Function, loops: &LoopForest, lp: usize) -> Option<u32> {
+        let l = &loops.loops[lp];
+        let exits_elsewhere = l.blocks.iter().any(|&b| {
+            b != l.header
+                && function
+                    .block(b)
+                    .successors()
+                    .iter()
+                    .any(|s| !l.contains(*s))
+        });
+        if exits_elsewhere {
+            return None;
+        }
+        let InstKind::Branch(Operand::Value(cond), on_true, on_false) =
+            &function.block(l.header).terminator()?.kind
+        else {
+            return None;
+        };
+        let stay = match (l.contains(*on_true), l.contains(*on_false)) {
+            (true, false) => true,
+            (false, true) => false,
+            _ => return None,
+        };
+        let Some(Inst {
+            kind: InstKind::Icmp(op, a, b),
+            ..
+        }) = function
+            .block(l.header)
+            .insts
+            .iter()
+            .find(|i| i.dest == Some(*cond))
+        else {
+            return None;
+        };
+        // 整理成 归纳变量 op 常数, 条件成立时留在循环中.
+        let (op, iv, bound) = match (a, b) {
+            (Operand::Value(v), Operand::Int(n)) => (*op, *v, *n),
+            (Operand::Int(n), Operand::Value(v)) => (swapped(*op), *v, *n),
+            _ => return None,
+        };
+        let op = if stay { op } else { inverse(op) };
+        let form = self.closed_form(iv)?;
+        if self.basic[&self.affine(iv)?.basic].lp != lp {
+            return None;
+        }
+        let start = form.at(0)? as i64;
+        count(op, start, form.step as i64, bound as i64)
+    }
+}
+
+/* 循环中由基本归纳变量(的候选)经常数运算得到的值; 块的顺序不一定是定义的顺序, 重复到不再变化. */
+fn derive(
+    function: &Function,
+    loops: &LoopForest,
+    basic: &HashMap<Value, BasicInduction>,
+) -> HashMap<Value, Affine> {
+    let mut affine: HashMap<Value, Affine> = basic
+        .keys()
+        .map(|v| {
+            let a = Affine {
+                basic: *v,
+                scale: 1,
+                offset: 0,
+            };
+            (*v, a)
+        })
+        .collect();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (b, block) in function.blocks.iter().enumerate() {
+            for inst in &block.insts {
+                let Some(dest) = inst.dest else {
+                    continue;
+                };
+                if inst.ty != Type::I32 || affine.contains_key(&dest) {
+                    continue;
+                }
+                let of = |operand: &Operand| match operand {
+                    Operand::Value(v) => affine.get(v).copied(),
+                    _ => None,
+                };
+                let derived = match &inst.kind {
+                    InstKind::Copy(x) => of(x),
+                    InstKind::Binary(op, x, y) => match (op, x, y) {
+                        (BinOp::Add, x, Operand::Int(c)) | (BinOp::Add, Operand::Int(c), x) => {
+                            of(x).map(|a| a.then(1, *c))
+                        }
+                        (BinOp::Sub, x, Operand::Int(c)) => {
+                            of(x).map(|a| a.then(1, c.wrapping_neg()))
+                        }
+                        (BinOp::Sub, Operand::Int(c), x) => of(x).map(|a| a.then(-1, *c)),
+                        (BinOp::Mul, x, Operand::Int(c)) | (BinOp::Mul, Operand::Int(c), x) => {
+                            of(x).map(|a| a.then(*c, 0))
+                        }
+                        (BinOp::Shl, x, Operand::Int(c)) if (0..32).contains(c) => {
+                            of(x).map(|a| a.then(1i32.wrapping_shl(*c as u32), 0))
+                        }
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                // 只有循环中的值每次迭代都重新计算.
+                let Some(derived) = derived else {
+                    continue;
+                };
+                if loops.loops[basic[&derived.basic].lp].contains(BlockId(b as u32)) {
+                    affine.insert(dest, derived);
+                    changed = true;
+                }
+            }
+        }
+    }
+    affine
+}
+
+impl Affine {
+    /* scale * self + offset */
+    fn then(self, scale: i32, offset: i32) -> Affine {
+        Affine {
+            basic: self.basic,
+            scale: self.scale.wrapping_mul(scale),
+            offset: self.offset.wrapping_mul(scale).wrapping_add(offset),
+        }
+    }
+}
+
+/* a op b 等价于 b swapped(op) a. */
+fn swapped(op: CmpOp) -> CmpOp {
+    match op {
+        CmpOp::Lt => CmpOp::Gt,
+        CmpOp::Le => CmpOp::Ge,
+        CmpOp::Gt => CmpOp::Lt,
+        CmpOp::Ge => CmpOp::Le,
+        op => op,
+    }
+}
+
+fn inverse(op: CmpOp) -> CmpOp {
+    match op {
+        CmpOp::Eq => CmpOp::Ne,
+        CmpOp::Ne => CmpOp::Eq,
+        CmpOp::Lt => CmpOp::Ge,
+        CmpOp::Le => CmpOp::Gt,
+        CmpOp::Gt => CmpOp::Le,
+        CmpOp::Ge => CmpOp::Lt,
+    }
+}
+
+/* start + n * step op bound 从n = 0起连续成立的次数; 不会停止或者中途回绕时为None. */
+fn count(op: CmpOp, start: i64, step: i64, bound: i64) -> Option<u32> {
+    let holds = |v: i64| match op {
+        CmpOp::Eq => v == bound,
+        CmpOp::Ne => v != bound,
+        CmpOp::Lt => v < bound,
+        CmpOp::Le => v <= bound,
+        CmpOp::Gt => v > bound,
+        CmpOp::Ge => v >= bound,
+    };
+    if !holds(start) {
+        return Some(0);
+    }
+    let n = match op {
+        CmpOp::Lt if step > 0 => (bound - start + step - 1) / step,
+        CmpOp::Le if step > 0 => (bound - start) / step + 1,
+        CmpOp::Gt if step < 0 => (start - bound - step - 1) / -step,
+        CmpOp::Ge if step < 0 => (start - bound) / -step + 1,
+        CmpOp::Ne if step != 0 && (bound - start) % step == 0 && (bound - start) / step > 0 => {
+            (bound - start) / step
+        }
+        CmpOp::Eq if step != 0 => 1,
+        _ => return None,
+    };
+    // 最后一次比较的值也要在i32的范围内, 否则中途已经回绕了.
+    let last = start + n * step;
+    (i32::MIN as i64..=i32::MAX as i64)
+        .contains(&last)
+        .then_some(n)
+        .and_then(|n| u32::try_from(n).ok())
+}
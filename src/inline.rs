@@ -0,0 +1,221 @@
+use crate::ir::{BlockId, Function, Inst, InstKind, Module, Operand, Type, Value};
+use std::collections::{HashMap, HashSet};
+
+/*
+    函数内联: 把小函数(指令数不超过budget)和只在一处被调用的函数的函数体复制到调用处.
+    调用所在的块在call处拆成两半, 被调函数的块和值重新编号后接在中间, ret变成跳到后一半的jump,
+    返回值由后一半开头的phi合并. 被调函数的alloca移到调用者的入口块.
+    直接或间接递归的函数不内联; 内联后不再被调用的函数(main除外)被删除.
+*/
+pub const DEFAULT_BUDGET: usize = 40;
+
+/* 返回是否内联了调用. */
+pub fn run_module(module: &mut Module, budget: usize) -> bool {
+    let mut changed = false;
+    // 每一轮内联上一轮展开出的调用; 没有递归, 轮数受调用链的深度限制, 这里再设一个上限.
+    for _ in 0..8 {
+        let candidates = candidates(module, budget);
+        if candidates.is_empty() {
+            break;
+        }
+        let mut inlined = false;
+        for f in 0..module.functions.len() {
+            if module.functions[f].is_declaration() {
+                continue;
+            }
+            let mut caller = std::mem::replace(
+                &mut module.functions[f],
+                Function::new("", Type::Void, vec![]),
+            );
+            while let Some((block, index, callee)) = find_call(&caller, &candidates) {
+                let callee = module.function(&callee).unwrap().clone();
+                inline_call(&mut caller, block, index, &callee);
+                inlined = true;
+            }
+            if inlined {
+                caller.merge_blocks();
+                caller.renumber_values();
+            }
+            module.functions[f] = caller;
+        }
+        if !inlined {
+            break;
+        }
+        changed = true;
+    }
+    if changed {
+        remove_dead_functions(module);
+    }
+    changed
+}
+
+fn calls(function: &Function) -> impl Iterator<Item = &String> {
+    function
+        .blocks
+        .iter()
+        .flat_map(|b| &b.insts)
+        .filter_map(|inst| match &inst.kind {
+            InstKind::Call(name, _) => Some(name),
+            _ => None,
+        })
+}
+
+fn size(function: &Function) -> usize {
+    function.blocks.iter().map(|b| b.insts.len()).sum()
+}
+
+/* 可以内联的函数: 有定义, 不在调用图的环上, 足够小或只被调用一次. */
+fn candidates(module: &Module, budget: usize) -> HashSet<String> {
+    let graph: HashMap<&str, Vec<&String>> = module
+        .functions
+        .iter()
+        .map(|f| (f.name.as_str(), calls(f).collect()))
+        .collect();
+    let mut call_sites: HashMap<&str, usize> = HashMap::new();
+    for function in &module.functions {
+        for name in calls(function) {
+            *call_sites.entry(name.as_str()).or_default() += 1;
+        }
+    }
+    let recursive = |name: &str| {
+        let mut seen = HashSet::new();
+        let mut stack: Vec<&str> = graph[name].iter().map(|s| s.as_str()).collect();
+        while let Some(f) = stack.pop() {
+            if f == name {
+                return true;
+            }
+            if seen.insert(f) {
+                stack.extend(graph.get(f).into_iter().flatten().map(|s| s.as_str()));
+            }
+        }
+        false
+    };
+    module
+        .functions
+        .iter()
+        .filter(|f| !f.is_declaration() && f.name != "main")
+        .filter(|f| size(f) <= budget || call_sites.get(f.name.as_str()) == Some(&1))
+        .filter(|f| !recursive(&f.name))
+        .map(|f| f.name.clone())
+        .collect()
+}
+
+fn find_call(function: &Function, candidates: &HashSet<String>) -> Option<(usize, usize, String)> {
+    for (b, block) in function.blocks.iter().enumerate() {
+        for (i, inst) in block.insts.iter().enumerate() {
+            if let InstKind::Call(name, _) = &inst.kind {
+                if candidates.contains(name) {
+                    return Some((b, i, name.clone()));
+                }
+            }
+        }
+    }
+    None
+}
+
+fn inline_call(caller: &mut Function, block: usize, index: usize, callee: &Function) {
+    let call = caller.blocks[block].insts[index].clone();
+    let InstKind::Call(_, args) = &call.kind else {
+        unreachable!("find_call returns calls")
+    };
+    // 拆开调用所在的块: call之后的指令放到新块rest中, 它的后继里来自原块的phi项改成来自rest.
+    let rest = caller.new_block();
+    let tail = caller.blocks[block].insts.split_off(index + 1);
+    caller.blocks[block].insts.pop();
+    let from = BlockId(block as u32);
+    for succ in tail.last().map_or(vec![], |t| t.kind.successors()) {
+        for inst in &mut caller.blocks[succ.0 as usize].insts {
+            if let InstKind::Phi(incoming) = &mut inst.kind {
+                for (pred, _) in incoming.iter_mut() {
+                    if *pred == from {
+                        *pred = rest;
+                    }
+                }
+            }
+        }
+    }
+    caller.block_mut(rest).insts = tail;
+
+    // 被调函数的形参换成实参, 其余的值和块换成调用者中的新值和新块.
+    let mut values: HashMap<Value, Operand> = HashMap::new();
+    for (i, arg) in args.iter().enumerate() {
+        values.insert(Value(i as u32), arg.clone());
+    }
+    for v in callee.params.len()..callee.value_types.len() {
+        let new = caller.new_value(callee.value_types[v]);
+        values.insert(Value(v as u32), Operand::Value(new));
+    }
+    let blocks: Vec<BlockId> = callee.blocks.iter().map(|_| caller.new_block()).collect();
+    let mut returns = vec![];
+    let mut allocas = vec![];
+    for (b, body) in callee.blocks.iter().enumerate() {
+        let mut insts = vec![];
+        for inst in &body.insts {
+            let mut inst = inst.clone();
+            inst.dest = inst.dest.map(|d| match values[&d] {
+                Operand::Value(v) => v,
+                _ => unreachable!("only parameters map to arguments"),
+            });
+            for operand in inst.kind.operands_mut() {
+                if let Operand::Value(v) = operand {
+                    *operand = values[v].clone();
+                }
+            }
+            for target in inst.kind.successors_mut() {
+                *target = blocks[target.0 as usize];
+            }
+            if let InstKind::Phi(incoming) = &mut inst.kind {
+                for (pred, _) in incoming.iter_mut() {
+                    *pred = blocks[pred.0 as usize];
+                }
+            }
+            match inst.kind {
+                InstKind::Ret(value) => {
+                    returns.push((blocks[b], value));
+                    insts.push(Inst {
+                        dest: None,
+                        ty: Type::Void,
+                        kind: InstKind::Jump(rest),
+                    });
+                }
+                InstKind::Alloca { .. } => allocas.push(inst),
+                _ => insts.push(inst),
+            }
+        }
+        caller.block_mut(blocks[b]).insts = insts;
+    }
+    caller.blocks[block].insts.push(Inst {
+        dest: None,
+        ty: Type::Void,
+        kind: InstKind::Jump(blocks[0]),
+    });
+    caller.blocks[0].insts.splice(0..0, allocas);
+
+    // 返回值: 只有一个ret时直接使用, 否则在rest开头用phi合并.
+    if let Some(dest) = call.dest {
+        let incoming: Vec<(BlockId, Operand)> = returns
+            .into_iter()
+            .map(|(b, v)| (b, v.unwrap_or(Operand::Int(0))))
+            .collect();
+        if let [(_, value)] = &incoming[..] {
+            caller.replace_values(&HashMap::from([(dest, value.clone())]));
+        } else {
+            caller.block_mut(rest).insts.insert(
+                0,
+                Inst {
+                    dest: Some(dest),
+                    ty: call.ty,
+                    kind: InstKind::Phi(incoming),
+                },
+            );
+        }
+    }
+}
+
+/* 删除没有被调用的函数定义(main和运行时库的声明除外). */
+fn remove_dead_functions(module: &mut Module) {
+    let called: HashSet<String> = module.functions.iter().flat_map(calls).cloned().collect();
+    module
+        .functions
+        .retain(|f| f.name == "main" || called.contains(&f.name));
+}
@@ -0,0 +1,735 @@
+use crate::ir::{BinOp, BlockId, CmpOp, Function, InstKind, Module, Operand, Type, Value};
+use crate::utils::FloatFormat;
+use std::collections::HashMap;
+
+/*
+    三地址码解释器: 直接执行Module, 运行时库函数(getint, putf等)按libsysy的行为在这里实现.
+    用来对比优化前后程序的输出(差分测试), 也供sysy_alpha run --ir使用.
+    内存是一个字数组, 地址以字为单位: 0不使用, 之后依次是全局变量, 调用栈上的alloca在函数返回时释放.
+    每个值保存为32位: int原样, float按位, 地址是字的下标. 调用用显式的栈, 深递归不会耗尽宿主的栈.
+    执行出错(除以0, 越界访问, 超出限制)时返回描述错误的字符串.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    pub steps: Option<u64>, //最多执行的指令条数, None时不限制
+    pub memory_words: usize,
+    pub call_depth: usize,
+    pub float_format: FloatFormat, //putf的%f默认保留的小数位数
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            steps: None,
+            memory_words: 1 << 26,
+            call_depth: 1 << 20,
+            float_format: FloatFormat::default(),
+        }
+    }
+}
+
+/* 程序正常结束时的结果: main的返回值, 标准输出的内容, 执行的指令条数. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    pub exit_code: i32,
+    pub output: Vec<u8>,
+    pub steps: u64,
+}
+
+impl Outcome {
+    /* 评测用的.out文件格式: 输出之后是单独一行的返回值(取低8位), 输出不以换行结尾时先补一个. */
+    pub fn expected_file(&self) -> Vec<u8> {
+        let mut text = self.output.clone();
+        if !text.is_empty() && !text.ends_with(b"\n") {
+            text.push(b'\n');
+        }
+        text.extend_from_slice(format!("{}\n", self.exit_code & 0xff).as_bytes());
+        text
+    }
+}
+
+pub fn run(module: &Module, input: &[u8]) -> Result<Outcome, String> {
+    run_with(module, input, &Limits::default())
+}
+
+pub fn run_with(module: &Module, input: &[u8], limits: &Limits) -> Result<Outcome, String> {
+    Machine::new(module, input, limits)?.run()
+}
+
+struct Frame<'m> {
+    function: &'m Function,
+    values: Vec<u32>,
+    block: BlockId,
+    pc: usize,
+    stack_base: usize,   //返回时memory截断到这里, 释放这一层的alloca
+    dest: Option<Value>, //调用者中接收返回值的值
+}
+
+struct Machine<'m> {
+    module: &'m Module,
+    functions: HashMap<&'m str, &'m Function>,
+    globals: HashMap<&'m str, u32>,
+    memory: Vec<u32>,
+    input: &'m [u8],
+    cursor: usize,
+    output: Vec<u8>,
+    frames: Vec<Frame<'m>>,
+    steps: u64,
+    limits: Limits,
+}
+
+impl<'m> Machine<'m> {
+    fn new(module: &'m Module, input: &'m [u8], limits: &Limits) -> Result<Self, String> {
+        let mut memory = vec![0];
+        let mut globals = HashMap::new();
+        for global in &module.globals {
+            globals.insert(global.name.as_str(), memory.len() as u32);
+            let start = memory.len();
+            memory.resize(start + global.len, 0);
+            for (i, value) in global.init.iter().enumerate() {
+                memory[start + i] = constant(value)?;
+            }
+        }
+        if memory.len() > limits.memory_words {
+            return Err(format!(
+                "globals need {} words, more than the limit of {}",
+                memory.len(),
+                limits.memory_words
+            ));
+        }
+        let functions = module
+            .functions
+            .iter()
+            .map(|f| (f.name.as_str(), f))
+            .collect();
+        Ok(Machine {
+            module,
+            functions,
+            globals,
+            memory,
+            input,
+            cursor: 0,
+            output: vec![],
+            frames: vec![],
+            steps: 0,
+            limits: *limits,
+        })
+    }
+
+    fn run(mut self) -> Result<Outcome, String> {
+        let main = match self.functions.get("main") {
+            Some(f) if !f.is_declaration() => *f,
+            _ => return Err("no definition of `main`".to_string()),
+        };
+        self.call(main, vec![], None)?;
+        loop {
+            self.steps += 1;
+            if self.limits.steps.is_some_and(|limit| self.steps > limit) {
+                return Err(format!("step limit of {} exceeded", self.steps - 1));
+            }
+            if let Some(exit_code) = self.step()? {
+                return Ok(Outcome {
+                    exit_code,
+                    output: self.output,
+                    steps: self.steps,
+                });
+            }
+        }
+    }
+
+    fn frame(&self) -> &Frame<'m> {
+        self.frames.last().unwrap()
+    }
+
+    fn call(
+        &mut self,
+        function: &'m Function,
+        args: Vec<u32>,
+        dest: Option<Value>,
+    ) -> Result<(), String> {
+        if self.frames.len() >= self.limits.call_depth {
+            return Err(format!(
+                "call depth limit of {} exceeded in `{}`",
+                self.limits.call_depth, function.name
+            ));
+        }
+        let mut values = vec![0; function.value_types.len()];
+        values[..args.len()].copy_from_slice(&args);
+        self.frames.push(Frame {
+            function,
+            values,
+            block: BlockId(0),
+            pc: 0,
+            stack_base: self.memory.len(),
+            dest,
+        });
+        Ok(())
+    }
+
+    /* 执行一条指令; main返回时给出它的返回值. */
+    fn step(&mut self) -> Result<Option<i32>, String> {
+        let frame = self.frames.last_mut().unwrap();
+        let function = frame.function;
+        let Some(inst) = function.blocks[frame.block.0 as usize].insts.get(frame.pc) else {
+            return Err(format!(
+                "fell off the end of {} in `{}`",
+                frame.block, function.name
+            ));
+        };
+        frame.pc += 1;
+        let result = match &inst.kind {
+            InstKind::Binary(op, a, b) => Some(self.binary(*op, a, b)?),
+            InstKind::Icmp(op, a, b) => {
+                let (a, b) = (self.eval(a)? as i32, self.eval(b)? as i32);
+                Some(compare(*op, a, b) as u32)
+            }
+            InstKind::Fcmp(op, a, b) => {
+                let (a, b) = (self.eval_float(a)?, self.eval_float(b)?);
+                Some(compare(*op, a, b) as u32)
+            }
+            InstKind::FNeg(a) => Some((-self.eval_float(a)?).to_bits()),
+            InstKind::IntToFloat(a) => Some((self.eval(a)? as i32 as f32).to_bits()),
+            // Rust的as在溢出时取边界值, NaN得到0, 与C的未定义行为相比更确定.
+            InstKind::FloatToInt(a) => Some(self.eval_float(a)? as i32 as u32),
+            InstKind::Alloca { len, .. } => {
+                let address = self.memory.len();
+                if address + len > self.limits.memory_words {
+                    return Err(format!(
+                        "out of memory allocating {} words in `{}`",
+                        len, function.name
+                    ));
+                }
+                self.memory.resize(address + len, 0);
+                Some(address as u32)
+            }
+            InstKind::Load(address) => {
+                let address = self.address(address, 1)?;
+                Some(self.memory[address])
+            }
+            InstKind::Store(value, address) => {
+                let value = self.eval(value)?;
+                let address = self.address(address, 1)?;
+                self.memory[address] = value;
+                None
+            }
+            InstKind::Gep(base, index, stride) => {
+                let base = self.eval(base)?;
+                let index = self.eval(index)? as i32;
+                Some(base.wrapping_add((index as u32).wrapping_mul(*stride as u32)))
+            }
+            InstKind::Copy(a) => Some(self.eval(a)?),
+            InstKind::Phi(_) => {
+                return Err(format!(
+                    "phi after the start of {} in `{}`",
+                    self.frame().block,
+                    function.name
+                ))
+            }
+            InstKind::Call(name, args) => {
+                let Some(&callee) = self.functions.get(name.as_str()) else {
+                    return Err(format!("call to undefined function `{}`", name));
+                };
+                if callee.is_declaration() {
+                    self.builtin(name, args)?
+                } else {
+                    let args = args
+                        .iter()
+                        .map(|a| self.eval(a))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    self.call(callee, args, inst.dest)?;
+                    return Ok(None);
+                }
+            }
+            InstKind::Jump(target) => {
+                self.enter(*target)?;
+                None
+            }
+            InstKind::Branch(cond, on_true, on_false) => {
+                let target = if self.eval(cond)? != 0 {
+                    *on_true
+                } else {
+                    *on_false
+                };
+                self.enter(target)?;
+                None
+            }
+            InstKind::Ret(value) => {
+                let value = match value {
+                    Some(v) => Some(self.eval(v)?),
+                    None => None,
+                };
+                let frame = self.frames.pop().unwrap();
+                self.memory.truncate(frame.stack_base);
+                let Some(caller) = self.frames.last_mut() else {
+                    return Ok(Some(value.unwrap_or(0) as i32));
+                };
+                if let (Some(dest), Some(value)) = (frame.dest, value) {
+                    caller.values[dest.0 as usize] = value;
+                }
+                None
+            }
+        };
+        if let (Some(dest), Some(value)) = (inst.dest, result) {
+            self.frames.last_mut().unwrap().values[dest.0 as usize] = value;
+        }
+        Ok(None)
+    }
+
+    /* 跳到target: 按来自当前块的边同时求出开头所有phi的值. */
+    fn enter(&mut self, target: BlockId) -> Result<(), String> {
+        let frame = self.frame();
+        let from = frame.block;
+        let block = &frame.function.blocks[target.0 as usize];
+        let mut assigned = vec![];
+        for inst in &block.insts {
+            let InstKind::Phi(incoming) = &inst.kind else {
+                break;
+            };
+            let Some((_, value)) = incoming.iter().find(|(b, _)| *b == from) else {
+                return Err(format!(
+                    "phi in {} of `{}` has no value from {}",
+                    target, frame.function.name, from
+                ));
+            };
+            assigned.push((inst.dest, self.eval(value)?));
+        }
+        let frame = self.frames.last_mut().unwrap();
+        frame.pc = assigned.len();
+        frame.block = target;
+        for (dest, value) in assigned {
+            if let Some(dest) = dest {
+                frame.values[dest.0 as usize] = value;
+            }
+        }
+        Ok(())
+    }
+
+    fn eval(&self, operand: &Operand) -> Result<u32, String> {
+        match operand {
+            Operand::Value(v) => Ok(self.frame().values[v.0 as usize]),
+            Operand::Global(name) => self
+                .globals
+                .get(name.as_str())
+                .copied()
+                .ok_or_else(|| format!("undefined global `{}`", name)),
+            Operand::Str(_) => Err("string constant used outside putf".to_string()),
+            other => constant(other),
+        }
+    }
+
+    fn eval_float(&self, operand: &Operand) -> Result<f32, String> {
+        self.eval(operand).map(f32::from_bits)
+    }
+
+    /* 操作数作为地址, 检查从它开始的len个字都在已分配的内存中. */
+    fn address(&self, operand: &Operand, len: usize) -> Result<usize, String> {
+        let address = self.eval(operand)? as usize;
+        if address == 0 || address + len > self.memory.len() {
+            return Err(format!(
+                "out-of-bounds access to word {} in `{}`",
+                address as i32,
+                self.frame().function.name
+            ));
+        }
+        Ok(address)
+    }
+
+    fn binary(&self, op: BinOp, a: &Operand, b: &Operand) -> Result<u32, String> {
+        if matches!(op, BinOp::FAdd | BinOp::FSub | BinOp::FMul | BinOp::FDiv) {
+            let (a, b) = (self.eval_float(a)?, self.eval_float(b)?);
+            let result = match op {
+                BinOp::FAdd => a + b,
+                BinOp::FSub => a - b,
+                BinOp::FMul => a * b,
+                _ => a / b,
+            };
+            return Ok(result.to_bits());
+        }
+        let (a, b) = (self.eval(a)? as i32, self.eval(b)? as i32);
+        let result = match op {
+            BinOp::Add => a.wrapping_add(b),
+            BinOp::Sub => a.wrapping_sub(b),
+            BinOp::Mul => a.wrapping_mul(b),
+            BinOp::Div | BinOp::Rem if b == 0 => {
+                return Err(format!(
+                    "division by zero in `{}`",
+                    self.frame().function.name
+                ))
+            }
+            BinOp::Div => a.wrapping_div(b),
+            BinOp::Rem => a.wrapping_rem(b),
+            BinOp::Shl => a.wrapping_shl(b as u32),
+            BinOp::Shr => a.wrapping_shr(b as u32),
+            BinOp::And => a & b,
+            BinOp::Or => a | b,
+            BinOp::Xor => a ^ b,
+            BinOp::FAdd | BinOp::FSub | BinOp::FMul | BinOp::FDiv => unreachable!(),
+        };
+        Ok(result as u32)
+    }
+
+    /*---------运行时库-------------*/
+
+    fn builtin(&mut self, name: &str, args: &[Operand]) -> Result<Option<u32>, String> {
+        if name == "putf" {
+            let Some(Operand::Str(format)) = args.first() else {
+                return Err("putf needs a string constant as its format".to_string());
+            };
+            let format = self.module.strings[*format].clone();
+            // 可变参数按实参的类型解释, float按C的规则提升为double.
+            let function = self.frame().function;
+            let args = args[1..]
+                .iter()
+                .map(|a| {
+                    let bits = self.eval(a)?;
+                    Ok(match function.operand_type(a) {
+                        Type::F32 => Arg::Float(f32::from_bits(bits) as f64),
+                        _ => Arg::Int(bits as i32),
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            let text = printf(&format, &args, &self.limits.float_format)?;
+            self.print(&text);
+            return Ok(None);
+        }
+        let args = args
+            .iter()
+            .map(|a| self.eval(a))
+            .collect::<Result<Vec<_>, _>>()?;
+        let result = match (name, &args[..]) {
+            ("getint", []) => Some(self.read_int() as u32),
+            ("getch", []) => Some(self.read_char() as u32),
+            ("getfloat", []) => Some(self.read_float().to_bits()),
+            ("getarray", [a]) | ("getfarray", [a]) => {
+                let n = self.read_int();
+                let start = self.address(&Operand::Int(*a as i32), n.max(0) as usize)?;
+                for i in 0..n.max(0) as usize {
+                    self.memory[start + i] = if name == "getarray" {
+                        self.read_int() as u32
+                    } else {
+                        self.read_float().to_bits()
+                    };
+                }
+                Some(n as u32)
+            }
+            ("putint", [n]) => {
+                self.print(&(*n as i32).to_string());
+                None
+            }
+            ("putch", [c]) => {
+                self.output.push(*c as u8);
+                None
+            }
+            ("putfloat", [x]) => {
+                self.print(&hex_float(f32::from_bits(*x) as f64));
+                None
+            }
+            ("putarray", [n, a]) | ("putfarray", [n, a]) => {
+                let n = *n as i32;
+                let start = self.address(&Operand::Int(*a as i32), n.max(0) as usize)?;
+                let mut text = format!("{}:", n);
+                for i in 0..n.max(0) as usize {
+                    let word = self.memory[start + i];
+                    if name == "putarray" {
+                        text += &format!(" {}", word as i32);
+                    } else {
+                        text += &format!(" {}", hex_float(f32::from_bits(word) as f64));
+                    }
+                }
+                text.push('\n');
+                self.print(&text);
+                None
+            }
+            // 计时只影响libsysy在退出时打印到标准错误的统计, 解释执行时没有意义.
+            ("_sysy_starttime", [_]) | ("_sysy_stoptime", [_]) => None,
+            _ => {
+                return Err(format!(
+                    "unknown runtime function `{}` with {} arguments",
+                    name,
+                    args.len()
+                ))
+            }
+        };
+        Ok(result)
+    }
+
+    fn print(&mut self, text: &str) {
+        self.output.extend_from_slice(text.as_bytes());
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .input
+            .get(self.cursor)
+            .is_some_and(|c| c.is_ascii_whitespace())
+        {
+            self.cursor += 1;
+        }
+    }
+
+    fn read_char(&mut self) -> i32 {
+        match self.input.get(self.cursor) {
+            Some(&c) => {
+                self.cursor += 1;
+                c as i32
+            }
+            None => -1,
+        }
+    }
+
+    /* scanf("%d"): 读不到数时返回0. */
+    fn read_int(&mut self) -> i32 {
+        self.skip_whitespace();
+        let start = self.cursor;
+        if matches!(self.input.get(self.cursor), Some(b'-' | b'+')) {
+            self.cursor += 1;
+        }
+        while self.input.get(self.cursor).is_some_and(u8::is_ascii_digit) {
+            self.cursor += 1;
+        }
+        let text = String::from_utf8_lossy(&self.input[start..self.cursor]);
+        text.parse::<i64>().map_or(0, |n| n as i32)
+    }
+
+    /* scanf("%a"): 十进制或十六进制的浮点数, 读不到数时返回0. 输入中的数以空白分隔. */
+    fn read_float(&mut self) -> f32 {
+        self.skip_whitespace();
+        let start = self.cursor;
+        while let Some(&c) = self.input.get(self.cursor) {
+            let previous = self.input[start..self.cursor].last().copied();
+            let sign = matches!(c, b'-' | b'+')
+                && (previous.is_none() || matches!(previous, Some(b'e' | b'E' | b'p' | b'P')));
+            if !(c.is_ascii_alphanumeric() || c == b'.' || sign) {
+                break;
+            }
+            self.cursor += 1;
+        }
+        let text = String::from_utf8_lossy(&self.input[start..self.cursor]);
+        parse_float(&text).unwrap_or(0.0)
+    }
+}
+
+/* Int/Float常量的32位表示. */
+fn constant(operand: &Operand) -> Result<u32, String> {
+    match operand {
+        Operand::Int(n) => Ok(*n as u32),
+        Operand::Float(x) => Ok(x.to_bits()),
+        other => Err(format!("`{}` is not a constant", other)),
+    }
+}
+
+fn compare<T: PartialOrd>(op: CmpOp, a: T, b: T) -> bool {
+    match op {
+        CmpOp::Eq => a == b,
+        CmpOp::Ne => a != b,
+        CmpOp::Lt => a < b,
+        CmpOp::Le => a <= b,
+        CmpOp::Gt => a > b,
+        CmpOp::Ge => a >= b,
+    }
+}
+
+/*---------格式化-------------*/
+
+enum Arg {
+    Int(i32),
+    Float(f64),
+}
+
+/* printf的一个子集: 标志-+0空格, 宽度, 精度, 转换d i u x X o c f F e E g G a A %. 没有写精度的%f按float_format. */
+fn printf(format: &str, args: &[Arg], float_format: &FloatFormat) -> Result<String, String> {
+    let mut out = String::new();
+    let mut args = args.iter();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        let mut flags = String::new();
+        while let Some(&f) = chars.peek().filter(|f| "-+0 #".contains(**f)) {
+            flags.push(f);
+            chars.next();
+        }
+        let mut width = 0;
+        while let Some(d) = chars.peek().and_then(|c| c.to_digit(10)) {
+            width = width * 10 + d as usize;
+            chars.next();
+        }
+        let mut precision = None;
+        if chars.peek() == Some(&'.') {
+            chars.next();
+            let mut p = 0;
+            while let Some(d) = chars.peek().and_then(|c| c.to_digit(10)) {
+                p = p * 10 + d as usize;
+                chars.next();
+            }
+            precision = Some(p);
+        }
+        // 长度修饰符(l, h等)不影响这里的结果.
+        while chars.peek().is_some_and(|c| "hlLqjzt".contains(*c)) {
+            chars.next();
+        }
+        let Some(conversion) = chars.next() else {
+            return Err(format!(
+                "incomplete conversion at the end of \"{}\"",
+                format
+            ));
+        };
+        if conversion == '%' {
+            out.push('%');
+            continue;
+        }
+        let Some(arg) = args.next() else {
+            return Err(format!("too few arguments for \"{}\"", format));
+        };
+        let (int, float) = match arg {
+            Arg::Int(n) => (*n, *n as f64),
+            Arg::Float(x) => (*x as i32, *x),
+        };
+        let mut text = match conversion {
+            'd' | 'i' => int.to_string(),
+            'u' => (int as u32).to_string(),
+            'x' => format!("{:x}", int as u32),
+            'X' => format!("{:X}", int as u32),
+            'o' => format!("{:o}", int as u32),
+            'c' => (int as u8 as char).to_string(),
+            'f' | 'F' => fixed(float, precision.unwrap_or(float_format.precision)),
+            'e' | 'E' => scientific(float, precision.unwrap_or(6)),
+            'g' | 'G' => general(float, precision.unwrap_or(6), flags.contains('#')),
+            'a' | 'A' => hex_float(float),
+            other => return Err(format!("unsupported conversion `%{}`", other)),
+        };
+        if conversion.is_ascii_uppercase() && conversion != 'X' {
+            text = text.to_uppercase();
+        }
+        let negative = text.starts_with('-');
+        if !negative && "dieEfFgGaA".contains(conversion) {
+            if flags.contains('+') {
+                text.insert(0, '+');
+            } else if flags.contains(' ') {
+                text.insert(0, ' ');
+            }
+        }
+        if text.len() < width {
+            let pad = width - text.len();
+            if flags.contains('-') {
+                text.push_str(&" ".repeat(pad));
+            } else if flags.contains('0') && float.is_finite() && conversion != 'c' {
+                let sign = usize::from(text.starts_with(['-', '+', ' ']));
+                text.insert_str(sign, &"0".repeat(pad));
+            } else {
+                text.insert_str(0, &" ".repeat(pad));
+            }
+        }
+        out.push_str(&text);
+    }
+    Ok(out)
+}
+
+/* %f: 与putfloat, dump文件相同, 由FloatFormat格式化(包括nan/inf的写法). 参数都是float提升而来, 转回f32不损失精度. */
+fn fixed(x: f64, precision: usize) -> String {
+    FloatFormat {
+        precision,
+        ..Default::default()
+    }
+    .format(x as f32)
+}
+
+/* nan和inf在各种转换中的写法相同, 同%f. */
+fn non_finite(x: f64) -> Option<String> {
+    (!x.is_finite()).then(|| FloatFormat::default().format(x as f32))
+}
+
+/* %e: 指数至少两位, 带符号, 如1.500000e+00. */
+fn scientific(x: f64, precision: usize) -> String {
+    if let Some(text) = non_finite(x) {
+        return text;
+    }
+    let text = format!("{:.*e}", precision, x);
+    let (mantissa, exponent) = text.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    let sign = if exponent < 0 { '-' } else { '+' };
+    format!("{}e{}{:02}", mantissa, sign, exponent.abs())
+}
+
+/* %g: 按指数选择%e或%f, 再去掉小数部分末尾的0(除非有#标志). */
+fn general(x: f64, precision: usize, keep_zeros: bool) -> String {
+    if let Some(text) = non_finite(x) {
+        return text;
+    }
+    let p = precision.max(1);
+    let e = scientific(x, p - 1);
+    let exponent: i32 = e.rsplit_once('e').unwrap().1.parse().unwrap();
+    let mut text = if exponent < -4 || exponent >= p as i32 {
+        e
+    } else {
+        fixed(x, (p as i32 - 1 - exponent) as usize)
+    };
+    if !keep_zeros {
+        let (number, suffix) = match text.find('e') {
+            Some(i) => (text[..i].to_string(), text[i..].to_string()),
+            None => (text.clone(), String::new()),
+        };
+        let number = if number.contains('.') {
+            number.trim_end_matches('0').trim_end_matches('.')
+        } else {
+            &number
+        };
+        text = format!("{}{}", number, suffix);
+    }
+    text
+}
+
+/* %a: 十六进制浮点数, 与glibc相同, 如0x1.8p+1, 0x0p+0. */
+pub fn hex_float(x: f64) -> String {
+    if let Some(text) = non_finite(x) {
+        return text;
+    }
+    let sign = if x.is_sign_negative() { "-" } else { "" };
+    if x == 0.0 {
+        return format!("{}0x0p+0", sign);
+    }
+    let bits = x.to_bits();
+    let biased = ((bits >> 52) & 0x7ff) as i32;
+    let mantissa = bits & ((1 << 52) - 1);
+    let (lead, exponent) = if biased == 0 {
+        (0, -1022)
+    } else {
+        (1, biased - 1023)
+    };
+    let digits = format!("{:013x}", mantissa);
+    let digits = digits.trim_end_matches('0');
+    if digits.is_empty() {
+        format!("{}0x{}p{:+}", sign, lead, exponent)
+    } else {
+        format!("{}0x{}.{}p{:+}", sign, lead, digits, exponent)
+    }
+}
+
+/* 十进制(1.5, 2e3)或十六进制(0x1.8p1)的浮点数. */
+fn parse_float(text: &str) -> Option<f32> {
+    let (negative, rest) = match text.as_bytes().first() {
+        Some(b'-') => (true, &text[1..]),
+        Some(b'+') => (false, &text[1..]),
+        _ => (false, text),
+    };
+    let value = match rest.get(..2) {
+        Some("0x") | Some("0X") => {
+            let rest = &rest[2..];
+            let (digits, exponent) = match rest.find(['p', 'P']) {
+                Some(i) => (&rest[..i], rest[i + 1..].parse::<i32>().ok()?),
+                None => (rest, 0),
+            };
+            let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+            let mut mantissa = 0.0f64;
+            for c in whole.chars().chain(fraction.chars()) {
+                mantissa = mantissa * 16.0 + c.to_digit(16)? as f64;
+            }
+            mantissa * 2f64.powi(exponent - 4 * fraction.len() as i32)
+        }
+        _ => rest.parse::<f64>().ok()?,
+    };
+    Some(if negative { -value } else { value } as f32)
+}
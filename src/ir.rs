@@ -0,0 +1,755 @@
+use std::collections::HashMap;
+use std::fmt;
+
+/*
+    三地址形式的中间表示(IR), 由lower::lower从HIR生成, 是优化和后端的输入.
+    Module包含全局变量, 字符串常量和函数; 函数由基本块组成, 每个基本块是一串指令,
+    最后一条(且只有最后一条)是终结指令(jump, br, ret).
+    每条有结果的指令定义一个新的值(%n), 值只被定义一次(SSA); 函数的形参是%0..%(n-1).
+    降低后的局部变量都放在入口块的alloca中, 通过load/store访问(内存形式), 之后由提升pass改写成值.
+    地址以字(4字节)为单位: int和float都占一个字, gep base, index, stride得到base + index * stride.
+    Display输出稳定的文本格式, 见tests/ir.rs.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Type {
+    Void,
+    I32,
+    F32,
+    Ptr,
+}
+
+/* 函数中的一个值: 形参或者某条指令的结果. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Value(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub u32);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Value(Value),
+    Int(i32),
+    Float(f32),
+    Global(String), //全局变量的地址
+    Str(usize),     //Module::strings中的字符串常量, 只作为putf的格式串
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div, //有符号除法, 向零取整
+    Rem,
+    Shl,
+    Shr, //算术右移
+    And,
+    Or,
+    Xor,
+    FAdd,
+    FSub,
+    FMul,
+    FDiv,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InstKind {
+    Binary(BinOp, Operand, Operand),
+    // 比较的结果是i32的0或1.
+    Icmp(CmpOp, Operand, Operand),
+    Fcmp(CmpOp, Operand, Operand),
+    FNeg(Operand),
+    IntToFloat(Operand),
+    FloatToInt(Operand), //向零截断
+    // 在栈上分配len个elem类型的字, 结果是地址; name是对应的源程序变量(unique_name), 只用于输出.
+    Alloca {
+        elem: Type,
+        len: usize,
+        name: String,
+    },
+    Load(Operand),           //按指令的类型读取
+    Store(Operand, Operand), //值, 地址
+    Gep(Operand, Operand, usize),
+    Call(String, Vec<Operand>),
+    Phi(Vec<(BlockId, Operand)>),
+    Copy(Operand),
+    Jump(BlockId),
+    Branch(Operand, BlockId, BlockId), //条件不为0时跳到第一个块
+    Ret(Option<Operand>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inst {
+    pub dest: Option<Value>,
+    pub ty: Type, //结果的类型, 没有结果时为Void
+    pub kind: InstKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Block {
+    pub insts: Vec<Inst>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub ret: Type,
+    pub params: Vec<Type>,
+    pub variadic: bool,         //params之后还可以有任意个实参(putf)
+    pub blocks: Vec<Block>,     //blocks[0]是入口块; 运行时库函数只有声明, 没有基本块
+    pub value_types: Vec<Type>, //每个值的类型, 下标是值的编号
+}
+
+/* 全局变量和常量; 数组按行主序展开成len个字. */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Global {
+    pub name: String,
+    pub elem: Type,
+    pub len: usize,
+    pub init: Vec<Operand>, //Int/Float常量; 为空时全部是0
+    pub constant: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Module {
+    pub globals: Vec<Global>,
+    pub strings: Vec<String>,
+    pub functions: Vec<Function>,
+}
+
+impl InstKind {
+    pub fn is_terminator(&self) -> bool {
+        matches!(
+            self,
+            InstKind::Jump(_) | InstKind::Branch(..) | InstKind::Ret(_)
+        )
+    }
+
+    /* 指令读取的操作数. */
+    pub fn operands(&self) -> Vec<&Operand> {
+        use InstKind::*;
+        match self {
+            Binary(_, a, b) | Icmp(_, a, b) | Fcmp(_, a, b) | Store(a, b) | Gep(a, b, _) => {
+                vec![a, b]
+            }
+            FNeg(a) | IntToFloat(a) | FloatToInt(a) | Load(a) | Copy(a) | Branch(a, ..) => vec![a],
+            Ret(a) => a.iter().collect(),
+            Call(_, args) => args.iter().collect(),
+            Phi(incoming) => incoming.iter().map(|(_, v)| v).collect(),
+            Alloca { .. } | Jump(_) => vec![],
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        use InstKind::*;
+        match self {
+            Binary(_, a, b) | Icmp(_, a, b) | Fcmp(_, a, b) | Store(a, b) | Gep(a, b, _) => {
+                vec![a, b]
+            }
+            FNeg(a) | IntToFloat(a) | FloatToInt(a) | Load(a) | Copy(a) | Branch(a, ..) => vec![a],
+            Ret(a) => a.iter_mut().collect(),
+            Call(_, args) => args.iter_mut().collect(),
+            Phi(incoming) => incoming.iter_mut().map(|(_, v)| v).collect(),
+            Alloca { .. } | Jump(_) => vec![],
+        }
+    }
+
+    /* 终结指令的后继块. */
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            InstKind::Jump(target) => vec![*target],
+            InstKind::Branch(_, on_true, on_false) => vec![*on_true, *on_false],
+            _ => vec![],
+        }
+    }
+
+    pub fn successors_mut(&mut self) -> Vec<&mut BlockId> {
+        match self {
+            InstKind::Jump(target) => vec![target],
+            InstKind::Branch(_, on_true, on_false) => vec![on_true, on_false],
+            _ => vec![],
+        }
+    }
+}
+
+impl Global {
+    /* 在数据段中占用的字节数: int和float都是4字节. */
+    pub fn size(&self) -> usize {
+        self.len * 4
+    }
+}
+
+impl Block {
+    pub fn terminator(&self) -> Option<&Inst> {
+        self.insts.last().filter(|i| i.kind.is_terminator())
+    }
+
+    pub fn successors(&self) -> Vec<BlockId> {
+        self.terminator().map_or(vec![], |t| t.kind.successors())
+    }
+}
+
+impl Function {
+    pub fn new(name: impl Into<String>, ret: Type, params: Vec<Type>) -> Self {
+        Function {
+            name: name.into(),
+            ret,
+            value_types: params.clone(),
+            params,
+            variadic: false,
+            blocks: vec![],
+        }
+    }
+
+    /* 只有声明的函数(运行时库). */
+    pub fn is_declaration(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    pub fn new_value(&mut self, ty: Type) -> Value {
+        self.value_types.push(ty);
+        Value(self.value_types.len() as u32 - 1)
+    }
+
+    pub fn new_block(&mut self) -> BlockId {
+        self.blocks.push(Block::default());
+        BlockId(self.blocks.len() as u32 - 1)
+    }
+
+    pub fn block(&self, id: BlockId) -> &Block {
+        &self.blocks[id.0 as usize]
+    }
+
+    pub fn block_mut(&mut self, id: BlockId) -> &mut Block {
+        &mut self.blocks[id.0 as usize]
+    }
+
+    pub fn value_type(&self, value: Value) -> Type {
+        self.value_types[value.0 as usize]
+    }
+
+    /* 操作数的类型; 全局变量和字符串是地址. */
+    pub fn operand_type(&self, operand: &Operand) -> Type {
+        match operand {
+            Operand::Value(v) => self.value_type(*v),
+            Operand::Int(_) => Type::I32,
+            Operand::Float(_) => Type::F32,
+            Operand::Global(_) | Operand::Str(_) => Type::Ptr,
+        }
+    }
+
+    /* 每个块的前驱, 按块的顺序. */
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut preds = vec![vec![]; self.blocks.len()];
+        for (i, block) in self.blocks.iter().enumerate() {
+            for succ in block.successors() {
+                let list: &mut Vec<BlockId> = &mut preds[succ.0 as usize];
+                if !list.contains(&BlockId(i as u32)) {
+                    list.push(BlockId(i as u32));
+                }
+            }
+        }
+        preds
+    }
+
+    /*
+        删除从入口块到达不了的块, 其余块按原来的顺序重新编号;
+        phi中来自被删除的块的项一并删除.
+    */
+    pub fn remove_unreachable_blocks(&mut self) {
+        if self.blocks.is_empty() {
+            return;
+        }
+        let mut reachable = vec![false; self.blocks.len()];
+        let mut stack = vec![BlockId(0)];
+        while let Some(id) = stack.pop() {
+            if std::mem::replace(&mut reachable[id.0 as usize], true) {
+                continue;
+            }
+            stack.extend(self.block(id).successors());
+        }
+        let mut renumber = vec![None; self.blocks.len()];
+        let mut next = 0;
+        for (i, &r) in reachable.iter().enumerate() {
+            if r {
+                renumber[i] = Some(BlockId(next));
+                next += 1;
+            }
+        }
+        let blocks = std::mem::take(&mut self.blocks);
+        for (i, mut block) in blocks.into_iter().enumerate() {
+            if !reachable[i] {
+                continue;
+            }
+            for inst in &mut block.insts {
+                if let InstKind::Phi(incoming) = &mut inst.kind {
+                    incoming.retain(|(b, _)| renumber[b.0 as usize].is_some());
+                    for (b, _) in incoming.iter_mut() {
+                        *b = renumber[b.0 as usize].unwrap();
+                    }
+                }
+                for target in inst.kind.successors_mut() {
+                    *target = renumber[target.0 as usize].unwrap();
+                }
+            }
+            self.blocks.push(block);
+        }
+    }
+
+    /*
+        合并只由jump相连的块: a以jump b结尾并且b的前驱只有a时, 把b的指令接到a后面.
+        b中的phi只有一个来源, 直接换成那个值. 合并后删除变得不可达的b.
+    */
+    pub fn merge_blocks(&mut self) {
+        let mut changed = false;
+        loop {
+            let preds = self.predecessors();
+            let mut merged = false;
+            for a in 0..self.blocks.len() {
+                let Some(Inst {
+                    kind: InstKind::Jump(b),
+                    ..
+                }) = self.blocks[a].insts.last()
+                else {
+                    continue;
+                };
+                let b = *b;
+                if b.0 == 0 || b.0 as usize == a || preds[b.0 as usize] != [BlockId(a as u32)] {
+                    continue;
+                }
+                let insts = std::mem::take(&mut self.blocks[b.0 as usize].insts);
+                let mut replace = HashMap::new();
+                let mut rest = vec![];
+                for inst in insts {
+                    match (&inst.kind, inst.dest) {
+                        (InstKind::Phi(incoming), Some(dest)) => {
+                            replace.insert(dest, incoming[0].1.clone());
+                        }
+                        _ => rest.push(inst),
+                    }
+                }
+                // 原来b的后继中来自b的phi项现在来自a.
+                let moved_succs = rest.last().map_or(vec![], |t| t.kind.successors());
+                let block = &mut self.blocks[a].insts;
+                block.pop();
+                block.extend(rest);
+                for succ in moved_succs {
+                    for inst in &mut self.blocks[succ.0 as usize].insts {
+                        if let InstKind::Phi(incoming) = &mut inst.kind {
+                            for (pred, _) in incoming.iter_mut() {
+                                if *pred == b {
+                                    *pred = BlockId(a as u32);
+                                }
+                            }
+                        }
+                    }
+                }
+                self.replace_values(&replace);
+                merged = true;
+                break;
+            }
+            if !merged {
+                break;
+            }
+            changed = true;
+        }
+        if changed {
+            self.remove_unreachable_blocks();
+        }
+    }
+
+    /* 把所有指令中的值按replace替换, 替换的结果本身被替换时继续替换. */
+    pub fn replace_values(&mut self, replace: &HashMap<Value, Operand>) {
+        for inst in self.blocks.iter_mut().flat_map(|b| &mut b.insts) {
+            for operand in inst.kind.operands_mut() {
+                resolve(operand, replace);
+            }
+        }
+    }
+
+    /*
+        删除来源(除自身外)都是同一个值的phi, 直到不再变化; 再删除只被其他无用的phi用到的phi.
+    */
+    pub fn simplify_phis(&mut self) {
+        loop {
+            let mut replace = HashMap::new();
+            for inst in self.blocks.iter().flat_map(|b| &b.insts) {
+                let (Some(dest), InstKind::Phi(incoming)) = (inst.dest, &inst.kind) else {
+                    continue;
+                };
+                let mut sources = incoming
+                    .iter()
+                    .map(|(_, v)| v)
+                    .filter(|v| **v != Operand::Value(dest));
+                let Some(first) = sources.next() else {
+                    continue;
+                };
+                if sources.all(|v| v == first) {
+                    replace.insert(dest, first.clone());
+                }
+            }
+            if replace.is_empty() {
+                break;
+            }
+            for block in &mut self.blocks {
+                block
+                    .insts
+                    .retain(|inst| !inst.dest.is_some_and(|d| replace.contains_key(&d)));
+            }
+            self.replace_values(&replace);
+        }
+        // 从phi以外的指令用到的值出发, 沿phi的来源标记有用的phi.
+        let mut live = vec![false; self.value_types.len()];
+        let mut work = vec![];
+        for inst in self.blocks.iter().flat_map(|b| &b.insts) {
+            if !matches!(inst.kind, InstKind::Phi(_)) {
+                work.extend(inst.kind.operands().into_iter().cloned());
+            }
+        }
+        let phis: HashMap<Value, &Inst> = self
+            .blocks
+            .iter()
+            .flat_map(|b| &b.insts)
+            .filter(|inst| matches!(inst.kind, InstKind::Phi(_)))
+            .map(|inst| (inst.dest.unwrap(), inst))
+            .collect();
+        while let Some(operand) = work.pop() {
+            let Operand::Value(v) = operand else {
+                continue;
+            };
+            if std::mem::replace(&mut live[v.0 as usize], true) {
+                continue;
+            }
+            if let Some(phi) = phis.get(&v) {
+                work.extend(phi.kind.operands().into_iter().cloned());
+            }
+        }
+        for block in &mut self.blocks {
+            block.insts.retain(|inst| {
+                !matches!(inst.kind, InstKind::Phi(_)) || live[inst.dest.unwrap().0 as usize]
+            });
+        }
+    }
+
+    /* 按定义的先后(形参, 然后按块的顺序)重新给值编号, 让文本形式中的编号递增. */
+    pub fn renumber_values(&mut self) {
+        let mut renumber = vec![None; self.value_types.len()];
+        let mut types = self.params.clone();
+        for (i, slot) in renumber.iter_mut().enumerate().take(self.params.len()) {
+            *slot = Some(Value(i as u32));
+        }
+        for inst in self.blocks.iter().flat_map(|b| &b.insts) {
+            if let Some(dest) = inst.dest {
+                renumber[dest.0 as usize] = Some(Value(types.len() as u32));
+                types.push(self.value_types[dest.0 as usize]);
+            }
+        }
+        for inst in self.blocks.iter_mut().flat_map(|b| &mut b.insts) {
+            if let Some(dest) = &mut inst.dest {
+                *dest = renumber[dest.0 as usize].unwrap();
+            }
+            for operand in inst.kind.operands_mut() {
+                if let Operand::Value(v) = operand {
+                    // 没有定义的值(不可达的块中)保持原样, 留给validate报告.
+                    *v = renumber[v.0 as usize].unwrap_or(*v);
+                }
+            }
+        }
+        self.value_types = types;
+    }
+
+    /*
+        检查结构是否完整: 每个块以终结指令结尾且中间没有终结指令, 跳转目标存在,
+        每个值只定义一次并且使用的值都有定义, phi只出现在块的开头. 返回发现的第一个问题.
+    */
+    pub fn validate(&self) -> Result<(), String> {
+        let mut defined = vec![false; self.value_types.len()];
+        for d in defined.iter_mut().take(self.params.len()) {
+            *d = true;
+        }
+        for (b, block) in self.blocks.iter().enumerate() {
+            if block.terminator().is_none() {
+                return Err(format!("{}: bb{} has no terminator", self.name, b));
+            }
+            let mut phis_done = false;
+            for (i, inst) in block.insts.iter().enumerate() {
+                if inst.kind.is_terminator() && i + 1 != block.insts.len() {
+                    return Err(format!(
+                        "{}: terminator in the middle of bb{}",
+                        self.name, b
+                    ));
+                }
+                match inst.kind {
+                    InstKind::Phi(_) if phis_done => {
+                        return Err(format!(
+                            "{}: phi after other instructions in bb{}",
+                            self.name, b
+                        ))
+                    }
+                    InstKind::Phi(_) => {}
+                    _ => phis_done = true,
+                }
+                for target in inst.kind.successors() {
+                    if target.0 as usize >= self.blocks.len() {
+                        return Err(format!(
+                            "{}: jump to missing block bb{}",
+                            self.name, target.0
+                        ));
+                    }
+                }
+                if let Some(dest) = inst.dest {
+                    let Some(slot) = defined.get_mut(dest.0 as usize) else {
+                        return Err(format!("{}: %{} has no type", self.name, dest.0));
+                    };
+                    if std::mem::replace(slot, true) {
+                        return Err(format!("{}: %{} is defined twice", self.name, dest.0));
+                    }
+                }
+            }
+        }
+        for block in &self.blocks {
+            for inst in &block.insts {
+                for operand in inst.kind.operands() {
+                    if let Operand::Value(v) = operand {
+                        if !defined.get(v.0 as usize).copied().unwrap_or(false) {
+                            return Err(format!(
+                                "{}: %{} is used but never defined",
+                                self.name, v.0
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Module {
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.iter().find(|f| f.name == name)
+    }
+
+    pub fn global(&self, name: &str) -> Option<&Global> {
+        self.globals.iter().find(|g| g.name == name)
+    }
+
+    /* 全局变量和常量在数据段(.data, .rodata和.bss)中一共占用的字节数, 不含字符串. */
+    pub fn data_bytes(&self) -> usize {
+        self.globals.iter().map(Global::size).sum()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        self.functions.iter().try_for_each(Function::validate)
+    }
+}
+
+/* 沿replace找到值最终被替换成的操作数. */
+pub fn resolve(operand: &mut Operand, replace: &HashMap<Value, Operand>) {
+    while let Operand::Value(v) = operand {
+        match replace.get(v) {
+            Some(new) => *operand = new.clone(),
+            None => break,
+        }
+    }
+}
+
+/*---------文本格式-------------*/
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Type::Void => "void",
+            Type::I32 => "i32",
+            Type::F32 => "f32",
+            Type::Ptr => "ptr",
+        })
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Value(v) => write!(f, "{}", v),
+            Operand::Int(n) => write!(f, "{}", n),
+            // {:?}输出能精确读回的最短形式, 并且总带小数点或指数, 与整数区分.
+            Operand::Float(x) => write!(f, "{:?}", x),
+            Operand::Global(name) => write!(f, "@{}", name),
+            Operand::Str(i) => write!(f, "@.str.{}", i),
+        }
+    }
+}
+
+impl BinOp {
+    pub fn name(self) -> &'static str {
+        use BinOp::*;
+        match self {
+            Add => "add",
+            Sub => "sub",
+            Mul => "mul",
+            Div => "div",
+            Rem => "rem",
+            Shl => "shl",
+            Shr => "shr",
+            And => "and",
+            Or => "or",
+            Xor => "xor",
+            FAdd => "fadd",
+            FSub => "fsub",
+            FMul => "fmul",
+            FDiv => "fdiv",
+        }
+    }
+}
+
+impl CmpOp {
+    pub fn name(self) -> &'static str {
+        use CmpOp::*;
+        match self {
+            Eq => "eq",
+            Ne => "ne",
+            Lt => "lt",
+            Le => "le",
+            Gt => "gt",
+            Ge => "ge",
+        }
+    }
+}
+
+fn join(operands: &[Operand]) -> String {
+    operands
+        .iter()
+        .map(|o| o.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl fmt::Display for Inst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use InstKind::*;
+        if let Some(dest) = self.dest {
+            write!(f, "{} = ", dest)?;
+        }
+        match &self.kind {
+            Binary(op, a, b) => write!(f, "{} {} {}, {}", op.name(), self.ty, a, b),
+            Icmp(op, a, b) => write!(f, "icmp {} {}, {}", op.name(), a, b),
+            Fcmp(op, a, b) => write!(f, "fcmp {} {}, {}", op.name(), a, b),
+            FNeg(a) => write!(f, "fneg {}", a),
+            IntToFloat(a) => write!(f, "itof {}", a),
+            FloatToInt(a) => write!(f, "ftoi {}", a),
+            Alloca { elem, len, name } => write!(f, "alloca {} x {} ; {}", elem, len, name),
+            Load(addr) => write!(f, "load {} {}", self.ty, addr),
+            Store(value, addr) => write!(f, "store {}, {}", value, addr),
+            Gep(base, index, stride) => write!(f, "gep {}, {}, {}", base, index, stride),
+            Call(name, args) => write!(f, "call {} @{}({})", self.ty, name, join(args)),
+            Phi(incoming) => {
+                let items: Vec<_> = incoming
+                    .iter()
+                    .map(|(b, v)| format!("[{}, {}]", b, v))
+                    .collect();
+                write!(f, "phi {} {}", self.ty, items.join(", "))
+            }
+            Copy(a) => write!(f, "copy {} {}", self.ty, a),
+            Jump(target) => write!(f, "jump {}", target),
+            Branch(cond, on_true, on_false) => {
+                write!(f, "br {}, {}, {}", cond, on_true, on_false)
+            }
+            Ret(None) => write!(f, "ret"),
+            Ret(Some(value)) => write!(f, "ret {}", value),
+        }
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut params: Vec<_> = self
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| {
+                if self.is_declaration() {
+                    ty.to_string()
+                } else {
+                    format!("%{}: {}", i, ty)
+                }
+            })
+            .collect();
+        if self.variadic {
+            params.push("...".to_string());
+        }
+        if self.is_declaration() {
+            return writeln!(
+                f,
+                "declare {} @{}({})",
+                self.ret,
+                self.name,
+                params.join(", ")
+            );
+        }
+        writeln!(
+            f,
+            "fn {} @{}({}) {{",
+            self.ret,
+            self.name,
+            params.join(", ")
+        )?;
+        for (i, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "{}:", BlockId(i as u32))?;
+            for inst in &block.insts {
+                writeln!(f, "  {}", inst)?;
+            }
+        }
+        writeln!(f, "}}")
+    }
+}
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for global in &self.globals {
+            let keyword = if global.constant { "const" } else { "global" };
+            write!(
+                f,
+                "{} @{}: {} x {} = ",
+                keyword, global.name, global.elem, global.len
+            )?;
+            if global.init.is_empty() {
+                writeln!(f, "zeroinit")?;
+            } else {
+                writeln!(f, "[{}]", join(&global.init))?;
+            }
+        }
+        for (i, s) in self.strings.iter().enumerate() {
+            writeln!(f, "string @.str.{} = {:?}", i, s)?;
+        }
+        for (i, function) in self.functions.iter().enumerate() {
+            if i > 0 || !self.globals.is_empty() || !self.strings.is_empty() {
+                writeln!(f)?;
+            }
+            write!(f, "{}", function)?;
+        }
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
+use crate::diagnostics::Diagnostic;
+use crate::span::{FileId, SourceFile, Span};
 use crate::TokenType;
 use std::collections::HashMap;
 use std::fs::File;
@@ -12,6 +14,89 @@ enum CharType {
     Other(char), // 表示在一个"特殊"字符char,特殊字符在于它既不是数字也不是字母.
 }
 
+/*
+    语言级别: SysY2022是比赛/课程要求的严格模式, Extended额外接受字符串, 字符常量,
+    位运算(& | ^ ~ << >>)和自增自减(++ --). 严格模式下遇到这些扩展会给出
+    "not part of SysY"的诊断, 而不是笼统的非法字符错误.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LangLevel {
+    #[default]
+    SysY2022,
+    Extended,
+}
+
+/*
+    词法分析的资源上限, 用于在模糊测试或恶意输入上安全地运行前端.
+    超出任意一项时, 词法分析立即停止并得到LexError::LimitExceeded.
+    SysY的块注释本身不嵌套, max_comment_nesting限制的是一个块注释内部再次出现的注释开头符号的个数.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LexLimits {
+    pub max_tokens: usize,
+    pub max_identifier_len: usize,
+    pub max_literal_len: usize,
+    pub max_comment_nesting: usize,
+}
+
+impl Default for LexLimits {
+    fn default() -> Self {
+        LexLimits {
+            max_tokens: 10_000_000,
+            max_identifier_len: 4096,
+            max_literal_len: 65536,
+            max_comment_nesting: 256,
+        }
+    }
+}
+
+/* 词法分析的全部选项. preserve_trivia打开时, 空白/注释/#line指令也作为token输出,
+ * 这样所有token首尾相接即可精确还原源代码. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LexOptions {
+    pub level: LangLevel,
+    pub limits: LexLimits,
+    pub preserve_trivia: bool,
+    pub file_id: FileId, //源文件在SourceMap中的编号, 记录在每个token的span中
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    Tokens,
+    IdentifierLength,
+    LiteralLength,
+    CommentNesting,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LexError {
+    Io(String), //源文件无法读取
+    LimitExceeded {
+        kind: LimitKind,
+        max: usize,
+        pos: usize,
+    }, //pos是超限处在字符流中的位置
+}
+
+impl std::fmt::Display for LexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LexError::Io(msg) => write!(f, "cannot read source file: {}", msg),
+            LexError::LimitExceeded { kind, max, .. } => {
+                let what = match kind {
+                    LimitKind::Tokens => "number of tokens",
+                    LimitKind::IdentifierLength => "identifier length",
+                    LimitKind::LiteralLength => "literal length",
+                    LimitKind::CommentNesting => "comment nesting",
+                };
+                write!(f, "lexer limit exceeded: {} is larger than {}", what, max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LexError {}
+
 /*----------------About token-----------------*/
 #[derive(Clone)]
 pub struct Token {
@@ -26,15 +111,15 @@ pub struct Token {
     pub source: Rc<String>,
     pub line_start: Rc<usize>,
     pub line_no: usize,
-    pub startpos: usize,
-    pub endpos: usize,
+    pub span: Span,
+    pub suffix: Option<String>, //数字字面量的后缀(如1.5f中的f, 10u中的u), 仅扩展模式
 }
 
 /* 实现Debug trait, 让Token可以使用{:?}被打印到控制台或者指定文件. */
 impl std::fmt::Debug for Token {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         //get token content
-        let content: String = self.buf[self.startpos..self.endpos].iter().collect();
+        let content: String = self.buf[self.span.start..self.span.end].iter().collect();
         //write token to stdout Stream.
         write!(
             f,
@@ -45,25 +130,86 @@ impl std::fmt::Debug for Token {
 }
 
 impl Token {
+    /* token在源代码中的原文 */
+    pub fn text(&self) -> String {
+        self.buf[self.span.start..self.span.end].iter().collect()
+    }
+
+    /* 是否是空白/注释等trivia */
+    pub fn is_trivia(&self) -> bool {
+        matches!(
+            self.sort,
+            TokenType::Whitespace | TokenType::Comment | TokenType::Directive | TokenType::Skipped
+        )
+    }
+
     pub fn new(
         sort: TokenType,
         buf: Rc<Vec<char>>,
         source: Rc<String>,
         line_start: Rc<usize>,
         line_no: usize,
-        startpos: usize,
-        endpos: usize,
+        span: Span,
     ) -> Self {
-        let _ = endpos;
         Token {
             sort,
             buf,
             source,
             line_start,
             line_no,
-            startpos,
-            endpos: 0,
+            span,
+            suffix: None,
+        }
+    }
+}
+
+/*
+    Token的序列化形式: 不保存整个源文件的字符流(buf), 只保存token自己的原文text.
+    反序列化时重建的buf在span之前用空格填充, 因此text()和span仍然一致, 但取不到同一行的其他字符.
+*/
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct TokenRepr {
+    sort: TokenType,
+    text: String,
+    source: String,
+    line_no: usize,
+    span: Span,
+    suffix: Option<String>,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Token {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        TokenRepr {
+            sort: self.sort.clone(),
+            text: self.text(),
+            source: self.source.to_string(),
+            line_no: self.line_no,
+            span: self.span,
+            suffix: self.suffix.clone(),
         }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Token {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = TokenRepr::deserialize(deserializer)?;
+        let mut buf = vec![' '; repr.span.start];
+        buf.extend(repr.text.chars());
+        let span = Span::new(repr.span.file_id, repr.span.start, buf.len());
+        let mut token = Token::new(
+            repr.sort,
+            Rc::new(buf),
+            Rc::new(repr.source),
+            Rc::new(repr.span.start),
+            repr.line_no,
+            span,
+        );
+        token.suffix = repr.suffix;
+        Ok(token)
     }
 }
 
@@ -75,7 +221,14 @@ pub struct Lexer {
     line_no: usize,
     tokens: Vec<Token>,
     source: Rc<String>,
-    is_panicked: bool,
+    diagnostics: Vec<Diagnostic>, //词法错误, 由调用者决定如何输出
+    level: LangLevel,
+    logical_source: Rc<String>, //#line指令指定的文件名, 默认与source相同
+    line_delta: isize,          //#line指令带来的行号偏移: 逻辑行号 = line_no + line_delta
+    limits: LexLimits,
+    limit_error: Option<LexError>, //一旦超出限制就记录在这里, scan随即停止
+    preserve_trivia: bool,
+    file_id: FileId,
 }
 
 impl Lexer {
@@ -89,16 +242,24 @@ impl Lexer {
     */
 
     /* Lexer的构造函数 */
-    fn new(path: Rc<String>) -> Self {
-        Lexer {
-            chars: Rc::new(Self::get_source(&path)),
+    fn new(path: Rc<String>, options: &LexOptions) -> Result<Self, LexError> {
+        let chars = Self::get_source(&path).map_err(|e| LexError::Io(e.to_string()))?;
+        Ok(Lexer {
+            chars: Rc::new(chars),
             current: 0,
             line_starts: vec![0],
             line_no: 1,     //各IDE,行号都是从1开始.
             tokens: vec![], //用于存放提取出来的token。
+            logical_source: path.clone(),
             source: path,
-            is_panicked: false,
-        }
+            diagnostics: vec![],
+            level: options.level,
+            line_delta: 0,
+            limits: options.limits,
+            limit_error: None,
+            preserve_trivia: options.preserve_trivia,
+            file_id: options.file_id,
+        })
     }
 
     /* 给予Lexer识别并提取不同类型token的能力 */
@@ -106,23 +267,73 @@ impl Lexer {
         Token::new(
             sort,
             self.chars.clone(),
-            self.source.clone(),
+            self.logical_source.clone(),
             Rc::new(self.line_starts[self.line_no - 1]), //行号从1开始,列号从0开始.
-            self.line_no,
-            self.current,
-            0,
+            self.logical_line(),
+            Span::new(self.file_id, self.current, self.current), //调用者在token识别完后再更新span.end
         )
     }
 
+    /* 当前源文件中[start, end)的区间 */
+    fn span(&self, start: usize, end: usize) -> Span {
+        Span::new(self.file_id, start, end)
+    }
+
+    /* 经过#line指令调整后的当前行号, 报错和token中记录的都是这个逻辑行号. */
+    fn logical_line(&self) -> usize {
+        (self.line_no as isize + self.line_delta) as usize
+    }
+
     /* 读取文件内容 */
-    fn get_source(path: &str) -> Vec<char> {
+    fn get_source(path: &str) -> std::io::Result<Vec<char>> {
         let mut content = String::new();
-        let mut file = File::open(path).expect("File cannot be opened");
-        file.read_to_string(&mut content)
-            .expect("File cannot be converted to string");
+        let mut file = File::open(path)?;
+        file.read_to_string(&mut content)?;
         // rust的编码是unicode(utf-8), 不支持字符串用下标访问, !:必须把字符串转换为字符数组.
         // 注意: 这里的chars()是迭代器, 不是数组, 所以访问单个字符的时候用方法get(). 范式为:chars.get()
-        content.chars().collect()
+        Ok(content.chars().collect())
+    }
+
+    /* 检查value是否超出kind对应的上限, 超出时记录LexError(只记录第一次). */
+    fn exceeds(&mut self, kind: LimitKind, value: usize) -> bool {
+        let max = match kind {
+            LimitKind::Tokens => self.limits.max_tokens,
+            LimitKind::IdentifierLength => self.limits.max_identifier_len,
+            LimitKind::LiteralLength => self.limits.max_literal_len,
+            LimitKind::CommentNesting => self.limits.max_comment_nesting,
+        };
+        if value > max && self.limit_error.is_none() {
+            self.limit_error = Some(LexError::LimitExceeded {
+                kind,
+                max,
+                pos: self.current,
+            });
+        }
+        value > max
+    }
+
+    /* 输出一个trivia token(t在trivia开头处创建), 相邻的空白合并成一个token. */
+    fn push_trivia(&mut self, mut t: Token) {
+        if !self.preserve_trivia {
+            return;
+        }
+        if t.sort == TokenType::Whitespace {
+            if let Some(last) = self.tokens.last_mut() {
+                if last.sort == TokenType::Whitespace && last.span.end == t.span.start {
+                    last.span.end = self.current;
+                    return;
+                }
+            }
+        }
+        t.span.end = self.current;
+        self.push_token(t);
+    }
+
+    /* 所有token都经由这里加入tokens, 以便统一检查token总数. */
+    fn push_token(&mut self, t: Token) {
+        if !self.exceeds(LimitKind::Tokens, self.tokens.len() + 1) {
+            self.tokens.push(t);
+        }
     }
 
     /* 预处理, 主要是去掉空格和换行符, 并将其转换为对应的枚举类型.*/
@@ -140,12 +351,15 @@ impl Lexer {
         match self.chars.get(self.current..self.current + 2) {
             //若是以0x(0X)开头, 则说明是十六进制数.
             Some(&['0', 'x']) | Some(&['0', 'X']) => {
+                let token_start = self.current;
                 self.current += 2;
-                self.parse_number(16);
+                self.parse_number(16, token_start);
             }
+            //0.5之类以"0."开头的是十进制浮点数.
+            Some(&['0', '.']) => self.parse_decimal(),
             //若是以0与任何一个字符开头, 则说明是八进制数.
             Some(&['0', _]) => {
-                self.parse_number(8);
+                self.parse_number(8, self.current);
             }
             //否则就是十进制数, 10进制数又分10进制整数和10进制浮点数.
             _ => self.parse_decimal(),
@@ -155,93 +369,191 @@ impl Lexer {
     //  解析10进制整数和浮点数.
     fn parse_decimal(&mut self) {
         let start = self.current;
-        let mut integer_sum = 0;
+        let mut integer_sum: u32 = 0;
         let mut integer_len = 0;
-        let mut fraction_sum = 0;
         let mut fraction_len = 0;
         let mut is_float = false;
         for c in self.chars[self.current..].iter() {
             if let Some(val) = c.to_digit(10) {
                 if is_float {
-                    fraction_sum = fraction_sum * 10 + val;
                     fraction_len += 1;
                 } else {
-                    integer_sum = integer_sum * 10 + val;
+                    // 超出范围的整数按32位补码回绕, 避免溢出panic.
+                    integer_sum = integer_sum.wrapping_mul(10).wrapping_add(val);
                     integer_len += 1;
                 }
-            } else if *c == '.' {
+            } else if *c == '.' && !is_float {
                 is_float = true;
             } else {
                 break;
             }
         }
+        let literal_len = if is_float && fraction_len > 0 {
+            integer_len + fraction_len + 1
+        } else {
+            integer_len
+        };
+        if self.exceeds(LimitKind::LiteralLength, literal_len) {
+            self.current = start + literal_len;
+            return;
+        }
         if is_float && fraction_len > 0 {
-            let float_value =
-                integer_sum as f64 + fraction_sum as f64 / 10_f64.powi(fraction_len as i32);
-            self.current = start + integer_len + fraction_len + 1;
+            let text: String = self.chars[start..start + literal_len].iter().collect();
+            let float_value: f64 = text.parse().unwrap_or(0.0);
+            self.current = start + literal_len;
             let mut t = self.new_token(TokenType::FloatNumber(float_value as f32));
-            t.endpos = self.current;
-            self.tokens.push(t);
+            t.span.start = start;
+            self.push_number(t, true);
         } else {
             let int_value = integer_sum;
             self.current = start + integer_len;
             let mut t = self.new_token(TokenType::IntNumber(int_value as i32));
-            t.endpos = self.current;
-            self.tokens.push(t);
+            t.span.start = start;
+            self.push_number(t, false);
         }
     }
 
     //解析8进制和16进制数,同时进行进制表示检查。
-    fn parse_number(&mut self, base: u32) {
-        let light = match base {
-            8 => 1,
-            16 => 2,
-            _ => unreachable!(),
-        };
-        let mut sum = 0;
+    fn parse_number(&mut self, base: u32, token_start: usize) {
+        let mut sum: i32 = 0;
         let mut len = 0;
         let start = self.current; // Store the initial value of self.current
         let mut flag = true;
         for c in self.chars[self.current..].iter() {
             if let Some(val) = c.to_digit(base) {
-                sum = sum * base as i32 + val as i32;
+                sum = sum.wrapping_mul(base as i32).wrapping_add(val as i32);
                 len += 1;
             } else {
+                // 数字后紧跟后缀(如017u, 0x1Fl)时在此结束, 后缀交给push_number处理.
+                if flag && self.suffix_run(start + len) > 0 {
+                    break;
+                }
                 if c.is_alphanumeric() {
                     flag = false;
                     len += 1;
                     continue;
                 }
-                if flag == false {
-                    if light == 1 {
-                        self.error(
-                            "Lexer error: Illegal OCTAL number",
-                            "Error type A at this line: Illegal octal number",
-                        );
-                    } else if light == 2 {
-                        self.error(
-                            "Lexer error: Illegal HEXIDECIMAL number",
-                            "Error type A at this line: Illegal hexadecimal number",
-                        );
-                    }
-                }
                 break;
             }
         }
         self.current = start + len;
+        if self.exceeds(LimitKind::LiteralLength, len) {
+            return;
+        }
         if flag {
             let mut t = self.new_token(TokenType::IntNumber(sum));
-            t.endpos = self.current;
-            self.tokens.push(t);
+            t.span.start = token_start;
+            self.push_number(t, false);
         } else {
+            let what = if base == 8 { "octal" } else { "hexadecimal" };
+            self.report(
+                Diagnostic::error(format!(
+                    "Error type A at this line: Illegal {} number",
+                    what
+                ))
+                .with_label(
+                    self.span(token_start, self.current),
+                    format!("not a valid {} number", what),
+                ),
+            );
             let mut t = self.new_token(TokenType::WrongFormat(
                 "Wrong Oct/Hex representation!".into(),
             ));
-            t.endpos = self.current;
-            self.tokens.push(t);
+            t.span.start = token_start;
+            t.span.end = self.current;
+            self.push_token(t);
         }
     }
 
+    /*
+        pos处合法的字面量后缀的长度(不合法则为0), 不区分大小写:
+        整数可带u, l, ul, lu, ll, ull, llu; 浮点数可带f, l.
+        后缀之后不能再紧跟字母数字, 否则整体不算后缀(如10uz).
+    */
+    fn suffix_len(&self, pos: usize, is_float: bool) -> usize {
+        let end = self.chars[pos..]
+            .iter()
+            .position(|c| !(c.is_ascii_alphanumeric() || *c == '_'))
+            .map_or(self.chars.len(), |i| pos + i);
+        let word = self.chars[pos..end]
+            .iter()
+            .collect::<String>()
+            .to_ascii_lowercase();
+        let valid: &[&str] = if is_float {
+            &["f", "l"]
+        } else {
+            &["u", "l", "ul", "lu", "ll", "ull", "llu"]
+        };
+        if valid.contains(&word.as_str()) {
+            end - pos
+        } else {
+            0
+        }
+    }
+
+    /*
+        pos处"看起来像后缀"的字符串长度: 紧跟在数字后、只由u/l/f(不分大小写)组成的一串字母,
+        如10u, 1L, 1.0f, 也包括10f, 1ul这类在C中也不合法的组合. 否则为0.
+    */
+    fn suffix_run(&self, pos: usize) -> usize {
+        let end = self.chars[pos..]
+            .iter()
+            .position(|c| !(c.is_ascii_alphanumeric() || *c == '_'))
+            .map_or(self.chars.len(), |i| pos + i);
+        let all_suffix = self.chars[pos..end]
+            .iter()
+            .all(|c| matches!(c, 'u' | 'U' | 'l' | 'L' | 'f' | 'F'));
+        if all_suffix {
+            end - pos
+        } else {
+            0
+        }
+    }
+
+    /*
+        数字字面量已扫描到self.current, 处理可能的后缀后把token推入tokens.
+        扩展模式下合法的后缀记录在token.suffix中, 由语义分析决定是否合法;
+        其余情况(严格模式, 或扩展模式下不合法的组合)报错, 给出删除后缀的修改建议, 并跳过后缀.
+    */
+    fn push_number(&mut self, mut t: Token, is_float: bool) {
+        let valid = self.suffix_len(self.current, is_float);
+        if valid > 0 && self.level == LangLevel::Extended {
+            t.suffix = Some(
+                self.chars[self.current..self.current + valid]
+                    .iter()
+                    .collect(),
+            );
+            self.current += valid;
+        }
+        t.span.end = self.current;
+        let literal: String = self.chars[t.span.start..t.span.end].iter().collect();
+        self.push_token(t);
+        let len = self.suffix_run(self.current);
+        if len > 0 {
+            self.reject_suffix(len, &literal, is_float);
+        }
+    }
+
+    /*
+        报告数字后面的后缀, 并把它作为Skipped跳过. 严格模式下与其他扩展语法相同(见reject_extension),
+        扩展模式下只有不合法的组合才会到这里.
+    */
+    fn reject_suffix(&mut self, len: usize, literal: &str, is_float: bool) {
+        let span = self.span(self.current, self.current + len);
+        let fix = format!("remove the suffix: `{}`", literal);
+        let diagnostic = if self.level == LangLevel::Extended {
+            let suffix: String = self.chars[self.current..self.current + len]
+                .iter()
+                .collect();
+            let kind = if is_float { "floating" } else { "integer" };
+            Diagnostic::error(format!("invalid suffix `{}` on {} literal", suffix, kind))
+                .with_label(span, "invalid suffix")
+        } else {
+            self.extension_error(len, "numeric suffixes")
+        };
+        self.skip(len, diagnostic.with_fix(span, "", fix));
+    }
+
     /*
         扫描标识符, 并判断是否是关键字.
         整体的思路是:
@@ -261,6 +573,10 @@ impl Lexer {
                 break;
             }
         }
+        if self.exceeds(LimitKind::IdentifierLength, len) {
+            self.current += len;
+            return;
+        }
         let name: String = self.chars[self.current..self.current + len]
             .iter()
             .collect();
@@ -274,32 +590,75 @@ impl Lexer {
         }
         //step4. add to tokens.
         self.current += len;
-        t.endpos = self.current; //更新当前Token的end字段位置
-        self.tokens.push(t); //把识别到的token加入tokens中, 这就是词法分析的根本目的嘛！
+        t.span.end = self.current; //更新当前Token的end字段位置
+        self.push_token(t); //把识别到的token加入tokens中, 这就是词法分析的根本目的嘛！
+    }
+
+    /*
+        处理#line N "file"指令(只能出现在行首), 由模板生成SysY代码的工具用它把报错位置映射回原文件.
+        指令的下一行的逻辑行号为N, 之后的token的文件名改为file(可省略).
+    */
+    fn line_directive(&mut self) {
+        let thisline = self.line_starts[self.line_no - 1];
+        let at_line_start = self.chars[thisline..self.current]
+            .iter()
+            .all(|c| *c == ' ' || *c == '\t');
+        let line_end = self.chars[self.current..]
+            .iter()
+            .position(|&c| c == '\n')
+            .map_or(self.chars.len(), |i| self.current + i);
+        match parse_line_directive(&self.chars[self.current..line_end]) {
+            Some((line, file)) if at_line_start => {
+                let t = self.new_token(TokenType::Directive);
+                self.line_delta = line as isize - (self.line_no as isize + 1);
+                if let Some(file) = file {
+                    self.logical_source = Rc::new(file);
+                }
+                self.current = line_end;
+                self.push_trivia(t);
+            }
+            _ => self.invalid_character(),
+        }
     }
 
     /* 处理行注释 */
     fn line_comment(&mut self) {
-        while self.chars.get(self.current) != Some(&'\n') {
+        let t = self.new_token(TokenType::Comment);
+        while let Some(&c) = self.chars.get(self.current) {
+            if c == '\n' {
+                break;
+            }
             self.current += 1;
         }
+        self.push_trivia(t);
     }
 
     /*
-    块注释的处理思路, 首先,因为是预读识别出/*来的, 所以要更新current指针,
+    块注释的处理思路, 首先,因为是预读识别出/*来的, 所以要记下开头的位置并更新current指针,
     然后用while循环从字符流chars中源源不断地拿到单个字符进行解析, 分三种情况,
         1. 读到*字符, 预读下一个是不是/, 如果是则注释结束, 更新current指针返回
         2. 读到\n字符, 则要更新行号, 而且每次行号更新后还要刷新每行的起始列号(要考虑缩进的问题)
         3. 两者都不是, 则忽略所读的内容, current指针向前加1即可
-    如果循环结束了, 都没有返回, 说明根本没读到*/这个结束的标注, 则报错.
+    如果循环结束了, 都没有返回, 说明根本没读到*/这个结束的标注, 则报错:
+    主标注指向注释的开头, 次要标注指向文件末尾.
      */
     fn block_comment(&mut self) {
+        let opener = self.current;
+        let t = self.new_token(TokenType::Comment);
+        let mut nesting = 1;
         self.current += 2;
         while let Some(&c) = self.chars.get(self.current) {
+            if c == '/' && self.chars.get(self.current + 1) == Some(&'*') {
+                nesting += 1;
+                if self.exceeds(LimitKind::CommentNesting, nesting) {
+                    return;
+                }
+            }
             if c == '*' {
                 if let Some(&judge) = self.chars.get(self.current + 1) {
                     if judge == '/' {
                         self.current += 2;
+                        self.push_trivia(t);
                         return;
                     }
                 }
@@ -310,54 +669,211 @@ impl Lexer {
             }
             self.current += 1; // '\n'和其它单个字符在这里一起+1了.
         }
-        self.error(
-            "block comment not end",
-            "maybe you can close the comment by adding */ ?",
+        // 文件末尾若是换行符, 则把EOF标注放在最后一行的行尾.
+        let mut eof = self.chars.len();
+        if eof > opener + 2 && self.chars[eof - 1] == '\n' {
+            eof -= 1;
+        }
+        self.report(
+            Diagnostic::error("unterminated block comment")
+                .with_label(self.span(opener, opener + 2), "comment starts here")
+                .with_label(
+                    self.span(eof, eof),
+                    "reached end of file without a closing `*/`",
+                )
+                .with_note("close the comment by adding `*/`"),
         );
+        self.push_trivia(t);
     }
 
-    /* 用于处理Lexical Analysis阶段的报错信息 */
-    fn error(&mut self, msg: &str, suggest: &str) {
-        /* step1. collect error info */
-        let mut len = 0;
-        let thisline = self.line_starts[self.line_no - 1];
-        let mut white_space_pos = 0;
-        for &c in self.chars[thisline..].iter() {
-            if c == '\n' {
-                break;
+    /* 字符串字面量, 支持\n \t \\ \" 等转义. SysY中只用作putf的格式串, 语法和语义分析会限制它出现的位置. */
+    fn string_literal(&mut self) {
+        let start = self.current;
+        let mut value = String::new();
+        let mut end = self.current + 1;
+        let mut closed = false;
+        while let Some(&c) = self.chars.get(end) {
+            match c {
+                '"' => {
+                    closed = true;
+                    end += 1;
+                    break;
+                }
+                '\n' => break,
+                '\\' => {
+                    value.push(match self.chars.get(end + 1) {
+                        Some('\n') | None => break,
+                        Some(&other) => unescape(other).unwrap_or(other),
+                    });
+                    end += 2;
+                }
+                _ => {
+                    value.push(c);
+                    end += 1;
+                }
+            }
+        }
+        if self.exceeds(LimitKind::LiteralLength, end - start) {
+            self.current = end;
+            return;
+        }
+        if !closed {
+            let t = self.new_token(TokenType::Skipped);
+            self.report(
+                Diagnostic::error("unterminated string literal")
+                    .with_label(self.span(start, end), "string starts here")
+                    .with_note("add a closing `\"` on the same line"),
+            );
+            self.current = end;
+            self.push_trivia(t);
+            return;
+        }
+        let mut t = self.new_token(TokenType::StrLiteral(value));
+        self.current = end;
+        t.span.end = self.current;
+        self.push_token(t);
+    }
+
+    /*
+        扩展: 字符常量'c'或'\n'等转义字符, 按其ASCII码当作整数常量, 如putch('A')即putch(65).
+        一直扫描到同一行中的闭合单引号, 空字符常量, 多个字符, 未闭合, 非ASCII字符和未知转义都会报错.
+        报错的字符常量(以及严格模式下的所有字符常量)变成值为0的占位常量.
+    */
+    fn char_literal(&mut self) {
+        let start = self.current;
+        let mut values = vec![];
+        let mut bad_escape = None;
+        let mut end = start + 1;
+        let mut closed = false;
+        while let Some(&c) = self.chars.get(end) {
+            match c {
+                '\'' => {
+                    closed = true;
+                    end += 1;
+                    break;
+                }
+                '\n' => break,
+                '\\' => match self.chars.get(end + 1) {
+                    Some('\n') | None => {
+                        end += 1;
+                        break;
+                    }
+                    Some(&other) => {
+                        match unescape(other) {
+                            Some(v) => values.push(v),
+                            None => {
+                                bad_escape.get_or_insert(end);
+                                values.push(other);
+                            }
+                        }
+                        end += 2;
+                    }
+                },
+                _ => {
+                    values.push(c);
+                    end += 1;
+                }
             }
-            if c == '=' {
-                white_space_pos = len;
+        }
+        let error = if self.level == LangLevel::SysY2022 {
+            Some(self.extension_error(end - start, "character literals"))
+        } else if !closed {
+            Some(
+                Diagnostic::error("unterminated character literal")
+                    .with_label(self.span(start, end), "literal starts here")
+                    .with_note("add a closing `'` on the same line"),
+            )
+        } else if let Some(pos) = bad_escape {
+            let text: String = self.chars[pos..pos + 2].iter().collect();
+            Some(
+                Diagnostic::error(format!("unknown escape sequence `{}`", text))
+                    .with_label(self.span(pos, pos + 2), "unknown escape")
+                    .with_note("supported escapes: \\n \\t \\r \\0 \\a \\b \\f \\v \\\\ \\' \\\""),
+            )
+        } else if values.is_empty() {
+            Some(Diagnostic::error("empty character literal").with_label(self.span(start, end), ""))
+        } else if values.len() > 1 {
+            Some(
+                Diagnostic::error("character literal may only contain one character")
+                    .with_label(
+                        self.span(start, end),
+                        format!("{} characters", values.len()),
+                    )
+                    .with_note("use a string literal \"...\" for more than one character"),
+            )
+        } else if !values[0].is_ascii() {
+            Some(
+                Diagnostic::error("non-ASCII character in character literal")
+                    .with_label(self.span(start, end), ""),
+            )
+        } else {
+            None
+        };
+        // 出错时仍然留下一个值为0的整数常量占位, 以免语法和语义分析因为少了一个实参之类的原因再报告错误.
+        let value = match error {
+            Some(diagnostic) => {
+                self.report(diagnostic);
+                0
             }
-            len += 1;
-        }
-        let error_info: String = self.chars[thisline..thisline + len].iter().collect();
-        /* step2. print error info */
-        println!("{}: {}", "Lexical analysis error", msg);
-        println!(
-            "{} file:{}, line:{}, column:{}.",
-            "Error location ---->",
-            self.source,
-            self.line_no,
-            self.current - thisline + 1
+            None => values[0] as i32,
+        };
+        let mut t = self.new_token(TokenType::IntNumber(value));
+        self.current = end;
+        t.span.end = self.current;
+        self.push_token(t);
+    }
+
+    /* 严格模式下遇到扩展语法: 报告并跳过len个字符. */
+    fn reject_extension(&mut self, len: usize, what: &str) {
+        let diagnostic = self.extension_error(len, what);
+        self.skip(len, diagnostic);
+    }
+
+    /* 从self.current开始的len个字符是扩展语法what, 所有扩展语法的诊断格式相同. */
+    fn extension_error(&self, len: usize, what: &str) -> Diagnostic {
+        let start = self.current;
+        let text: String = self.chars[start..start + len].iter().collect();
+        Diagnostic::error(format!("{} are not part of SysY", what))
+            .with_label(
+                self.span(start, start + len),
+                format!("`{}` is an extension", text),
+            )
+            .with_note("enable the extended language level (LangLevel::Extended) to accept it")
+    }
+
+    /* 报告diagnostic, 并把从self.current开始的len个字符作为Skipped跳过. */
+    fn skip(&mut self, len: usize, diagnostic: Diagnostic) {
+        let t = self.new_token(TokenType::Skipped);
+        self.report(diagnostic);
+        self.current += len;
+        self.push_trivia(t);
+    }
+
+    /* 非法字符: 报错并跳过这个字符. */
+    fn invalid_character(&mut self) {
+        let c = self.chars[self.current];
+        let diagnostic = Diagnostic::error(format!(
+            "Error type A at this line: Invalid character '{}'",
+            c
+        ))
+        .with_label(
+            self.span(self.current, self.current + 1),
+            "not part of SysY",
         );
-        println!("  {}  ", "|");
-        println!(" {:3}{} {}", self.line_no.to_string(), "|", error_info);
-        /* step3. give suggestion on correcting*/
-        print!("    {}", "|");
-        // 获取错误字符的具体位置, 在前面填充若干个空格
-        for _ in 0..self.current - thisline + 1 {
-            print!("{}", ' ');
-        }
+        self.skip(1, diagnostic);
+    }
 
-        let c: String = self.chars[thisline + white_space_pos + 1..thisline + len]
-            .iter()
-            .collect();
-        // 指出错误字符具体位置, 并打印出修正意见
-        println!("{} {}:{}", "^", suggest, c);
-        println!("  {}", "|");
-        self.current += 1;
-        self.is_panicked = true;
+    /* 记录一条词法错误, 由tokenize等入口统一交给诊断引擎输出或返回给调用者. */
+    fn report(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    /* 按词法分析器读入的源代码输出记录下的诊断(供直接打印的旧接口使用). */
+    fn print_diagnostics(&self) {
+        let file = SourceFile::from_chars(self.source.as_str(), self.chars.to_vec());
+        for diagnostic in &self.diagnostics {
+            print!("{}", diagnostic.render(&file));
+        }
     }
 
     /* Lexer做词法分析的核心函数, 调用了上述所有封装好的函数, 对源字符流进行解析. */
@@ -367,12 +883,19 @@ impl Lexer {
         double_signs: &HashMap<String, TokenType>,
     ) {
         while let Some(target) = self.pre_process() {
+            if self.limit_error.is_some() {
+                break;
+            }
             match target {
                 CharType::Spacebar => {
+                    let t = self.new_token(TokenType::Whitespace);
                     self.current += 1;
+                    self.push_trivia(t);
                 }
                 CharType::Linefeed => {
+                    let t = self.new_token(TokenType::Whitespace);
                     self.current += 1;
+                    self.push_trivia(t);
                     self.line_no += 1;
                     self.line_starts.push(self.current);
                 }
@@ -385,38 +908,93 @@ impl Lexer {
                     _ => {
                         let mut t = self.new_token(TokenType::Divide);
                         self.current += 1;
-                        t.endpos = self.current;
-                        self.tokens.push(t);
+                        t.span.end = self.current;
+                        self.push_token(t);
                     }
                 },
+                CharType::Other('#') => self.line_directive(),
+                CharType::Other('"') => self.string_literal(),
+                CharType::Other('\'') => self.char_literal(),
 
                 CharType::Other(_) => {
                     if let Some(operator) = self.chars.get(self.current..self.current + 2) {
                         let operation_unit: String = operator.iter().collect();
+                        if let Some(sort) = Self::extended_double_sign(&operation_unit) {
+                            // 严格模式下"++""--"仍按两个单符号处理(a--b即a-(-b)), 只拒绝移位运算.
+                            if self.level == LangLevel::Extended {
+                                let mut t = self.new_token(sort);
+                                self.current += 2;
+                                t.span.end = self.current;
+                                self.push_token(t);
+                                continue;
+                            } else if matches!(sort, TokenType::ShiftLeft | TokenType::ShiftRight) {
+                                self.reject_extension(2, "bitwise operators");
+                                continue;
+                            }
+                        }
                         if let Some(sort) = double_signs.get(&operation_unit) {
                             let mut t = self.new_token(sort.clone());
                             self.current += 2;
-                            t.endpos = self.current;
-                            self.tokens.push(t);
+                            t.span.end = self.current;
+                            self.push_token(t);
                             continue;
                         }
                     }
                     if let Some(operator) = Self::single_sign(self.chars[self.current]) {
                         let mut t = self.new_token(operator.clone());
                         self.current += 1;
-                        t.endpos = self.current;
-                        self.tokens.push(t);
+                        t.span.end = self.current;
+                        self.push_token(t);
+                    } else if let Some(operator) =
+                        Self::extended_single_sign(self.chars[self.current])
+                    {
+                        if self.level == LangLevel::Extended {
+                            let mut t = self.new_token(operator);
+                            self.current += 1;
+                            t.span.end = self.current;
+                            self.push_token(t);
+                        } else {
+                            let what = match operator {
+                                TokenType::Colon => "case labels and conditional expressions",
+                                TokenType::Question => "conditional expressions",
+                                _ => "bitwise operators",
+                            };
+                            self.reject_extension(1, what);
+                        }
                     } else {
-                        self.error(
-                            "invalid character!",
-                            "Error type A at this line:Invalid character",
-                        );
+                        self.invalid_character();
                     }
                 }
             }
         }
     }
 
+    /* 扩展模式的双符号表 */
+    fn extended_double_sign(s: &str) -> Option<TokenType> {
+        use TokenType::*;
+        match s {
+            "<<" => Some(ShiftLeft),
+            ">>" => Some(ShiftRight),
+            "++" => Some(Increment),
+            "--" => Some(Decrement),
+            _ => None,
+        }
+    }
+
+    /* 扩展模式的单符号表 */
+    fn extended_single_sign(c: char) -> Option<TokenType> {
+        use TokenType::*;
+        match c {
+            '&' => Some(BitAnd),
+            '|' => Some(BitOr),
+            '^' => Some(BitXor),
+            '~' => Some(BitNot),
+            ':' => Some(Colon),
+            '?' => Some(Question),
+            _ => None,
+        }
+    }
+
     /* 单符号表 */
     fn single_sign(c: char) -> Option<TokenType> {
         use TokenType::*;
@@ -450,22 +1028,198 @@ impl Lexer {
 
 /* tokenize: use Lexer to tokenize the source(stored in path), charStreams -> Tokens */
 pub fn tokenize(path: String) -> Vec<Token> {
+    tokenize_with_level(path, LangLevel::SysY2022)
+}
+
+/* tokenize_with_level: 同tokenize, 但可以指定语言级别(是否接受扩展语法). */
+pub fn tokenize_with_level(path: String, level: LangLevel) -> Vec<Token> {
     /*
        整体的解决步骤：
        0.这是一个库函数(暴露给外界), 库函数一般是封装内部对象的实例函数, 所以需要先new一个对象,再调用该对象的方法.
        1."tokenize"这个动作的执行者是Lexer, 先New一个Lexer作为执行词法分析的实体.
        2.调用Lexer的成员函数scan(),扫描整个文件,把扫描到的一个个词法单元装入lexer.tokens中.
-       3.返回tokens
+       3.返回tokens (若超出资源上限, 报告错误并返回已识别出的tokens)
     */
-    let mut lexer = Lexer::new(Rc::new(path));
-    lexer.scan(&keyword_table_init(), &double_sign_table_init());
+    let options = LexOptions {
+        level,
+        ..Default::default()
+    };
+    let mut lexer = run_lexer(path, &options).expect("File cannot be opened");
+    if let Some(e) = lexer.limit_error.clone() {
+        if let LexError::LimitExceeded { pos, .. } = e {
+            let span = Span::new(options.file_id, pos, pos + 1);
+            lexer.report(Diagnostic::error(e.to_string()).with_label(span, "here"));
+        }
+    }
+    lexer.print_diagnostics();
     lexer.tokens
 }
 
+/*
+    try_tokenize: 可失败的词法分析, 无法读取文件或超出资源上限时返回LexError, 不会panic.
+    其余的词法错误照旧直接打印; 需要自行处理它们的调用者请用tokenize_with_diagnostics.
+*/
+pub fn try_tokenize(path: String, options: &LexOptions) -> Result<Vec<Token>, LexError> {
+    let lexer = run_lexer(path, options)?;
+    lexer.print_diagnostics();
+    match lexer.limit_error {
+        Some(e) => Err(e),
+        None => Ok(lexer.tokens),
+    }
+}
+
+/* 同try_tokenize, 但不打印: 把词法错误(非法字符, 格式错误的数字等)作为诊断一并返回. */
+pub fn tokenize_with_diagnostics(
+    path: String,
+    options: &LexOptions,
+) -> Result<(Vec<Token>, Vec<Diagnostic>), LexError> {
+    let lexer = run_lexer(path, options)?;
+    match lexer.limit_error {
+        Some(e) => Err(e),
+        None => Ok((lexer.tokens, lexer.diagnostics)),
+    }
+}
+
+/* 字符常量和字符串常量中的转义字符: '\\'之后的字符c对应的字符, 未知转义返回None. */
+fn unescape(c: char) -> Option<char> {
+    match c {
+        'n' => Some('\n'),
+        't' => Some('\t'),
+        'r' => Some('\r'),
+        '0' => Some('\0'),
+        'a' => Some('\x07'),
+        'b' => Some('\x08'),
+        'f' => Some('\x0c'),
+        'v' => Some('\x0b'),
+        '\\' | '\'' | '"' | '?' => Some(c),
+        _ => None,
+    }
+}
+
+/* token的粗粒度分类, 供语法高亮和编辑器插件使用. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenCategory {
+    Keyword,
+    Literal,
+    Operator,
+    Identifier,
+    Punctuation,
+    Comment,
+    Directive,  //#line等预处理指令
+    Whitespace, //空白, classify_file不会返回它
+    Invalid,    //格式错误的数字或报错后跳过的字符
+}
+
+pub fn classify(token: &Token) -> TokenCategory {
+    use TokenType::*;
+    match token.sort {
+        Void | Int | Float | Const | IntConst | FloatConst | If | Else | While | Continue
+        | Break | Return | Switch | Case | Default => TokenCategory::Keyword,
+        IntNumber(_) | FloatNumber(_) | StrLiteral(_) => TokenCategory::Literal,
+        Identifier(_) => TokenCategory::Identifier,
+        Plus | Minus | Multi | Divide | Mods | Assign | Equal | NotEqual | Lesserthan
+        | Greaterthan | LessEqual | GreatEqual | And | Or | Not | BitAnd | BitOr | BitXor
+        | BitNot | ShiftLeft | ShiftRight | Increment | Decrement => TokenCategory::Operator,
+        Comma | Semicolon | LeftParen | RightParen | LeftBracket | RightBracket | LeftBrace
+        | RightBrace | Colon | Eof => TokenCategory::Punctuation,
+        Question => TokenCategory::Operator,
+        Comment => TokenCategory::Comment,
+        Directive => TokenCategory::Directive,
+        Whitespace => TokenCategory::Whitespace,
+        WrongFormat(_) | Skipped => TokenCategory::Invalid,
+    }
+}
+
+/*
+    classify_file: 对整个文件做词法分析(保留注释等trivia), 返回每个非空白token的
+    (字符区间, 分类), 区间按源代码字符下标计算, 按出现顺序排列.
+*/
+pub fn classify_file(
+    path: String,
+    level: LangLevel,
+) -> Result<Vec<(std::ops::Range<usize>, TokenCategory)>, LexError> {
+    let options = LexOptions {
+        level,
+        preserve_trivia: true,
+        ..Default::default()
+    };
+    let tokens = try_tokenize(path, &options)?;
+    Ok(tokens
+        .iter()
+        .map(|t| (t.span.range(), classify(t)))
+        .filter(|(_, category)| *category != TokenCategory::Whitespace)
+        .collect())
+}
+
+fn run_lexer(path: String, options: &LexOptions) -> Result<Lexer, LexError> {
+    let mut lexer = Lexer::new(Rc::new(path), options)?;
+    lexer.scan(
+        &keyword_table_init(options.level),
+        &double_sign_table_init(),
+    );
+    // debug构建(测试/模糊测试)下, 保留trivia时检查token是否恰好覆盖整个输入.
+    if cfg!(debug_assertions) && options.preserve_trivia && lexer.limit_error.is_none() {
+        if let Err(e) = check_span_coverage(&lexer.tokens, lexer.chars.len()) {
+            panic!("lexer span invariant violated in {}: {}", lexer.source, e);
+        }
+    }
+    Ok(lexer)
+}
+
+/*
+    检查token(包括trivia)的区间span是否首尾相接、恰好铺满长度为len的输入:
+    没有空隙, 没有重叠, 也没有空token. 只有在LexOptions::preserve_trivia打开时才成立.
+*/
+pub fn check_span_coverage(tokens: &[Token], len: usize) -> Result<(), String> {
+    let mut expected = 0;
+    for (i, t) in tokens.iter().enumerate() {
+        if t.span.start != expected {
+            return Err(format!(
+                "token #{} {:?} starts at {} but the previous token ends at {}",
+                i, t.sort, t.span.start, expected
+            ));
+        }
+        if t.span.end <= t.span.start {
+            return Err(format!(
+                "token #{} {:?} has an empty or inverted span {}..{}",
+                i, t.sort, t.span.start, t.span.end
+            ));
+        }
+        expected = t.span.end;
+    }
+    if expected != len {
+        return Err(format!(
+            "tokens end at {} but the input has {} characters",
+            expected, len
+        ));
+    }
+    Ok(())
+}
+
 /*---------------tools function-------------------*/
 
+/* 解析一行#line N "file"指令, 返回(N, file), 不是合法指令时返回None. */
+pub(crate) fn parse_line_directive(line: &[char]) -> Option<(usize, Option<String>)> {
+    let text: String = line.iter().collect();
+    let rest = text.trim_start().strip_prefix("#line")?;
+    if !rest.starts_with([' ', '\t']) {
+        return None;
+    }
+    let rest = rest.trim();
+    let digits = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    let line_no = rest[..digits].parse::<usize>().ok()?;
+    let rest = rest[digits..].trim();
+    if rest.is_empty() {
+        return Some((line_no, None));
+    }
+    let file = rest.strip_prefix('"')?.strip_suffix('"')?;
+    Some((line_no, Some(file.to_string())))
+}
+
 /* 关键字表 */
-fn keyword_table_init() -> HashMap<String, TokenType> {
+fn keyword_table_init(level: LangLevel) -> HashMap<String, TokenType> {
     let mut table = HashMap::new();
 
     /* int,float,void,const, if,else,while,continue,break,return */
@@ -480,6 +1234,13 @@ fn keyword_table_init() -> HashMap<String, TokenType> {
     table.insert("continue".into(), TokenType::Continue);
     table.insert("break".into(), TokenType::Break);
     table.insert("return".into(), TokenType::Return);
+
+    /* 扩展模式的关键字, 严格模式下它们仍是普通标识符 */
+    if level == LangLevel::Extended {
+        table.insert("switch".into(), TokenType::Switch);
+        table.insert("case".into(), TokenType::Case);
+        table.insert("default".into(), TokenType::Default);
+    }
     table
 }
 
@@ -1,16 +1,52 @@
+pub mod alias;
+pub mod cfg;
+pub mod codegen;
+pub mod copyprop;
+pub mod cse;
+pub mod ddg;
+pub mod diagnostics;
+pub mod effects;
+pub mod fold;
+pub mod hir;
+pub mod induction;
+pub mod inline;
+pub mod interp;
+pub mod ir;
 pub mod lexer;
+pub mod loops;
+pub mod lower;
+pub mod mem2reg;
 pub mod parser;
+pub mod passes;
+pub mod peephole;
+pub mod preprocess;
+pub mod sccp;
+pub mod schedule;
 pub mod semantics;
+pub mod session;
+pub mod short_circuit;
+pub mod span;
+pub mod strength;
+pub mod target;
+pub mod toolchain;
+pub mod uninit;
 pub mod utils;
-use parser::Node;
+pub mod verify;
+pub mod visit;
+pub mod whole_program;
+pub mod xref;
+use parser::{Node, NodeId};
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TokenType {
     //Literals: 带值的枚举类型,类比扑克牌的花色和面值.
     IntNumber(i32),
     FloatNumber(f32),
     Identifier(String),
     WrongFormat(String),
+    StrLiteral(String), //字符串字面量, 只能作为putf的格式参数
+
     //Keywords
     /*--return value--*/
     Void,
@@ -27,6 +63,9 @@ pub enum TokenType {
     Continue,
     Break,
     Return,
+    Switch,  //扩展模式
+    Case,    //扩展模式
+    Default, //扩展模式
 
     /*--operators--*/
     Plus,
@@ -49,6 +88,22 @@ pub enum TokenType {
     Or,
     Not,
 
+    /*--bitwise & increment (LangLevel::Extended)--*/
+    BitAnd,
+    BitOr,
+    BitXor,
+    BitNot,
+    ShiftLeft,
+    ShiftRight,
+    Increment,
+    Decrement,
+
+    /*--Trivia: 只在LexOptions::preserve_trivia打开时产生, parser会忽略它们--*/
+    Whitespace,
+    Comment,
+    Directive,
+    Skipped, //报错后被跳过的字符
+
     /*--Symbols--*/
     Comma,
     Semicolon,
@@ -58,22 +113,106 @@ pub enum TokenType {
     RightBracket,
     LeftBrace,
     RightBrace,
+    Colon,    //扩展模式, 用于case标签和条件表达式
+    Question, //扩展模式, 条件表达式
+    Eof,      //token流的结束, 由语法分析器补在末尾
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BasicType {
     Nil,
     Int,
     Float,
-    Const, //这里的Const现在约定是整形常数.
+    Const,      //这里的Const现在约定是整形常数.
+    ConstFloat, //浮点常数, 如const float PI = 3.14;
     Void,
     IntArray(Vec<usize>),
     FloatArray(Vec<usize>),
     ConstArray(Vec<usize>), //约定是整形常数数组.
-    Func(Box<BasicType>),   //用于函数的返回值.
+    ConstFloatArray(Vec<usize>),
+    Func(Box<BasicType>), //用于函数的返回值.
+}
+
+/* 以SysY源码的写法打印类型, 例如int[3][4], int[][5], const int, float, void(), 供诊断和AST打印共用. */
+impl std::fmt::Display for BasicType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn write_dims(f: &mut std::fmt::Formatter<'_>, dims: &[usize]) -> std::fmt::Result {
+            for d in dims {
+                if *d == 0 {
+                    write!(f, "[]")?;
+                } else {
+                    write!(f, "[{}]", d)?;
+                }
+            }
+            Ok(())
+        }
+        match self {
+            BasicType::Nil => write!(f, "<unknown>"),
+            BasicType::Int => write!(f, "int"),
+            BasicType::Float => write!(f, "float"),
+            BasicType::Const => write!(f, "const int"),
+            BasicType::ConstFloat => write!(f, "const float"),
+            BasicType::Void => write!(f, "void"),
+            BasicType::IntArray(dims) => {
+                write!(f, "int")?;
+                write_dims(f, dims)
+            }
+            BasicType::FloatArray(dims) => {
+                write!(f, "float")?;
+                write_dims(f, dims)
+            }
+            BasicType::ConstArray(dims) => {
+                write!(f, "const int")?;
+                write_dims(f, dims)
+            }
+            BasicType::ConstFloatArray(dims) => {
+                write!(f, "const float")?;
+                write_dims(f, dims)
+            }
+            BasicType::Func(ret) => write!(f, "{}()", ret),
+        }
+    }
+}
+
+//编译期可确定的常量值, 常量表达式求值(semantics::const_eval)的结果, 也用于初始化数据的展开.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConstValue {
+    Int(i32),
+    Float(f32),
+}
+
+impl ConstValue {
+    /* 转换成int, float向零截断(与C相同). */
+    pub fn as_int(self) -> i32 {
+        match self {
+            ConstValue::Int(v) => v,
+            ConstValue::Float(v) => v as i32,
+        }
+    }
+
+    pub fn as_float(self) -> f32 {
+        match self {
+            ConstValue::Int(v) => v as f32,
+            ConstValue::Float(v) => v,
+        }
+    }
+
+    pub fn is_float(self) -> bool {
+        matches!(self, ConstValue::Float(_))
+    }
+
+    /* 作为条件时是否为真(不等于0). */
+    pub fn is_true(self) -> bool {
+        match self {
+            ConstValue::Int(v) => v != 0,
+            ConstValue::Float(v) => v != 0.0,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Scope {
     Global,
     Local,
@@ -81,6 +220,7 @@ pub enum Scope {
 }
 
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum NodeType {
     /*
         以下每一个枚举成员都可能是Ast中的一个Node所属的类型之一
@@ -114,29 +254,162 @@ pub enum NodeType {
     InitList(Vec<Node>),
     // Name, [index], Exp, lhs_exp.
     // eg: a[1] = 10; 在此之前, a[1] = 0(lhs_exp);
-    Assign(String, Option<Vec<Node>>, Box<Node>, Box<Node>),
+    // 最后一个字段(以及Access/Call的最后一个字段)是语义分析解析到的声明节点的编号, 引用声明时不拷贝任何节点.
+    // 声明是树中的Decl/Func(形参是Func中的Decl), 或运行时库函数(见semantics::runtime_declarations);
+    // 语法分析的结果和未定义的名字引用的编号不属于任何声明.
+    Assign(String, Option<Vec<Node>>, Box<Node>, NodeId),
     // 表达式语句, 一个表达式后跟一个';'
     ExprStmt(Box<Node>),
     // ArrayName, [index], Exp(二维数组按行取可以取出一行元素,Exp在这里就代表多维数组中按某一维度进行访问).
-    Access(String, Option<Vec<Node>>, Box<Node>),
+    Access(String, Option<Vec<Node>>, NodeId),
     // BinaryOperator, lhs, rhs.
     BinOp(TokenType, Box<Node>, Box<Node>),
+    // UnaryOperator(Plus/Minus/Not), operand. eg: -x, !x, --x(即-(-x))
+    UnaryOp(TokenType, Box<Node>),
+    // 条件表达式 cond ? on_true : on_false, 扩展模式.
+    Cond(Box<Node>, Box<Node>, Box<Node>),
+    // 逗号表达式 (a, b, c), 扩展模式, 值为最后一个操作数. 操作数可以是赋值(Assign).
+    Comma(Vec<Node>),
+    // 隐式类型转换(目标类型, 被转换的表达式), 由语义分析在int与float相遇处插入, 语法分析不会生成.
+    Cast(BasicType, Box<Node>),
 
     /* 函数类 */
-    // Func(Type, Name, [Params], Block).
+    // Func(Type, Name, [Params], Block), 函数原型(int f(int a);)的Block为Nil.
     Func(BasicType, String, Vec<Node>, Box<Node>),
     Block(Vec<Node>),
     Return(Option<Box<Node>>),
-    Call(String, Vec<Node>, Box<Node>),
+    Call(String, Vec<Node>, NodeId),
 
     /* 结构-循环类 */
     If(Box<Node>, Box<Node>, Option<Box<Node>>),
     While(Box<Node>, Box<Node>),
+    // Switch(被判断的表达式, [Case]), 扩展模式. 匹配到某个Case后顺序执行其后所有Case的语句(fall-through), 直到break.
+    Switch(Box<Node>, Vec<Node>),
+    // Case(标签常量表达式, 语句), 标签为None表示default.
+    Case(Option<Box<Node>>, Vec<Node>),
     Continue,
     Break,
+    // 空语句: 单独的';'
+    Empty,
 
     /* 结点值类 */
     Nil,
     Number(i32),
     FloatNumber(f32),
+    // 字符串字面量(已处理转义), 只能作为putf的格式参数.
+    Str(String),
+}
+
+/* NodeType去掉携带的数据后的"种类", 给只关心节点是什么的分析工具使用(见Node::kind). */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeKind {
+    Decl,
+    DeclStmt,
+    InitList,
+    Assign,
+    ExprStmt,
+    Access,
+    BinOp,
+    UnaryOp,
+    Cond,
+    Comma,
+    Cast,
+    Func,
+    Block,
+    Return,
+    Call,
+    If,
+    While,
+    Switch,
+    Case,
+    Continue,
+    Break,
+    Empty,
+    Nil,
+    Number,
+    FloatNumber,
+    Str,
+}
+
+/*
+    节点按文法分成几大类: 声明(含函数定义), 语句, 表达式, 以及占位用的Nil.
+    Assign在SysY中是语句, 扩展模式下也可以作为逗号表达式的操作数出现.
+    这里的match不带通配分支, NodeType增加成员时编译器会要求在此归类.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeClass {
+    Decl,
+    Stmt,
+    Expr,
+    Nil,
+}
+
+impl NodeKind {
+    /* 全部种类, 按NodeType中声明的顺序. */
+    pub const ALL: [NodeKind; 26] = [
+        NodeKind::Decl,
+        NodeKind::DeclStmt,
+        NodeKind::InitList,
+        NodeKind::Assign,
+        NodeKind::ExprStmt,
+        NodeKind::Access,
+        NodeKind::BinOp,
+        NodeKind::UnaryOp,
+        NodeKind::Cond,
+        NodeKind::Comma,
+        NodeKind::Cast,
+        NodeKind::Func,
+        NodeKind::Block,
+        NodeKind::Return,
+        NodeKind::Call,
+        NodeKind::If,
+        NodeKind::While,
+        NodeKind::Switch,
+        NodeKind::Case,
+        NodeKind::Continue,
+        NodeKind::Break,
+        NodeKind::Empty,
+        NodeKind::Nil,
+        NodeKind::Number,
+        NodeKind::FloatNumber,
+        NodeKind::Str,
+    ];
+
+    pub fn class(self) -> NodeClass {
+        match self {
+            NodeKind::Decl | NodeKind::DeclStmt | NodeKind::InitList | NodeKind::Func => {
+                NodeClass::Decl
+            }
+            NodeKind::Assign
+            | NodeKind::ExprStmt
+            | NodeKind::Block
+            | NodeKind::Return
+            | NodeKind::If
+            | NodeKind::While
+            | NodeKind::Switch
+            | NodeKind::Case
+            | NodeKind::Continue
+            | NodeKind::Break
+            | NodeKind::Empty => NodeClass::Stmt,
+            NodeKind::Access
+            | NodeKind::BinOp
+            | NodeKind::UnaryOp
+            | NodeKind::Cond
+            | NodeKind::Comma
+            | NodeKind::Cast
+            | NodeKind::Call
+            | NodeKind::Number
+            | NodeKind::FloatNumber
+            | NodeKind::Str => NodeClass::Expr,
+            NodeKind::Nil => NodeClass::Nil,
+        }
+    }
+
+    pub fn is_expr(self) -> bool {
+        self.class() == NodeClass::Expr
+    }
+
+    pub fn is_stmt(self) -> bool {
+        self.class() == NodeClass::Stmt
+    }
 }
@@ -0,0 +1,256 @@
+use crate::{
+    cfg::{Cfg, Dominators},
+    ir::{BlockId, Function, Inst, InstKind, Module, Operand, Type},
+};
+use std::fmt::{self, Write};
+
+/*
+    自然循环: 回边(头支配尾的边)确定循环头, 从回边的尾逆着控制流走到头得到循环体; 同一个头的回边合成一个循环.
+    循环按包含关系组成森林, 深度从1开始, 不在任何循环中的块深度为0.
+    preheader是循环外唯一的前驱并且只跳到循环头; 没有时由insert_preheaders补上(入口块是循环头时除外).
+    LICM, 循环展开和强度削弱都从这里取得循环的结构.
+*/
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Loop {
+    pub header: BlockId,
+    pub blocks: Vec<BlockId>,   //包括循环头, 按编号排序
+    pub latches: Vec<BlockId>,  //回边的起点
+    pub entering: Vec<BlockId>, //循环外跳到循环头的块
+    pub preheader: Option<BlockId>,
+    pub exits: Vec<BlockId>,   //循环外, 有前驱在循环中的块
+    pub parent: Option<usize>, //loops中外层循环的下标
+    pub children: Vec<usize>,
+    pub depth: usize,
+}
+
+impl Loop {
+    pub fn contains(&self, block: BlockId) -> bool {
+        self.blocks.binary_search(&block).is_ok()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LoopForest {
+    pub loops: Vec<Loop>,          //外层循环在内层之前
+    innermost: Vec<Option<usize>>, //每个块所在的最内层循环
+}
+
+impl LoopForest {
+    pub fn compute(cfg: &Cfg, doms: &Dominators) -> LoopForest {
+        // 按循环头收集回边的尾.
+        let mut headers: Vec<(BlockId, Vec<BlockId>)> = vec![];
+        for edge in cfg.edges() {
+            if !doms.dominates(edge.to, edge.from) {
+                continue;
+            }
+            match headers.iter_mut().find(|(h, _)| *h == edge.to) {
+                Some((_, latches)) if latches.contains(&edge.from) => {}
+                Some((_, latches)) => latches.push(edge.from),
+                None => headers.push((edge.to, vec![edge.from])),
+            }
+        }
+        let mut loops: Vec<Loop> = headers
+            .into_iter()
+            .map(|(header, latches)| natural_loop(cfg, doms, header, latches))
+            .collect();
+        // 大的循环在前, 这样每个循环的外层循环已经排在它前面.
+        loops.sort_by_key(|l| (std::cmp::Reverse(l.blocks.len()), l.header));
+        let mut innermost = vec![None; cfg.len()];
+        for i in 0..loops.len() {
+            let parent = (0..i).rev().find(|&p| loops[p].contains(loops[i].header));
+            loops[i].parent = parent;
+            loops[i].depth = parent.map_or(1, |p| loops[p].depth + 1);
+            if let Some(p) = parent {
+                loops[p].children.push(i);
+            }
+            for &b in &loops[i].blocks {
+                innermost[b.0 as usize] = Some(i);
+            }
+        }
+        LoopForest { loops, innermost }
+    }
+
+    /* 最外层的循环. */
+    pub fn roots(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.loops.len()).filter(|&i| self.loops[i].parent.is_none())
+    }
+
+    pub fn innermost(&self, block: BlockId) -> Option<usize> {
+        self.innermost[block.0 as usize]
+    }
+
+    /* 块的循环嵌套深度, 不在循环中为0. */
+    pub fn depth(&self, block: BlockId) -> usize {
+        self.innermost(block).map_or(0, |l| self.loops[l].depth)
+    }
+}
+
+fn natural_loop(cfg: &Cfg, doms: &Dominators, header: BlockId, latches: Vec<BlockId>) -> Loop {
+    let mut member = vec![false; cfg.len()];
+    member[header.0 as usize] = true;
+    let mut work = latches.clone();
+    while let Some(block) = work.pop() {
+        if std::mem::replace(&mut member[block.0 as usize], true) {
+            continue;
+        }
+        work.extend(cfg.predecessors(block).filter(|p| doms.is_reachable(*p)));
+    }
+    let blocks: Vec<BlockId> = (0..cfg.len())
+        .filter(|&b| member[b])
+        .map(|b| BlockId(b as u32))
+        .collect();
+    let mut entering: Vec<BlockId> = cfg
+        .predecessors(header)
+        .filter(|p| !member[p.0 as usize] && doms.is_reachable(*p))
+        .collect();
+    entering.dedup();
+    let preheader = match entering[..] {
+        [p] if cfg.successors(p).all(|s| s == header) => Some(p),
+        _ => None,
+    };
+    let mut exits = vec![];
+    for &b in &blocks {
+        for succ in cfg.successors(b) {
+            if !member[succ.0 as usize] && !exits.contains(&succ) {
+                exits.push(succ);
+            }
+        }
+    }
+    exits.sort();
+    Loop {
+        header,
+        blocks,
+        latches,
+        entering,
+        preheader,
+        exits,
+        parent: None,
+        children: vec![],
+        depth: 0,
+    }
+}
+
+/*
+    给没有preheader的循环补上: 新块只跳到循环头, 循环外的前驱改为跳到新块,
+    循环头的phi中来自循环外的项合并到新块中(多个来源时在新块中建phi). 返回是否加了块.
+*/
+pub fn insert_preheaders(function: &mut Function) -> bool {
+    let mut changed = false;
+    // 每加一个块控制流就变了, 重新计算后再处理下一个循环.
+    loop {
+        let cfg = Cfg::build(function);
+        let doms = Dominators::compute(&cfg);
+        let forest = LoopForest::compute(&cfg, &doms);
+        let Some(target) = forest
+            .loops
+            .iter()
+            .find(|l| l.preheader.is_none() && l.header != BlockId(0))
+        else {
+            return changed;
+        };
+        let header = target.header;
+        let entering = target.entering.clone();
+        let preheader = function.new_block();
+        for &pred in &entering {
+            let terminator = function.block_mut(pred).insts.last_mut().unwrap();
+            for succ in terminator.kind.successors_mut() {
+                if *succ == header {
+                    *succ = preheader;
+                }
+            }
+        }
+        let mut phis = vec![];
+        for i in 0..function.block(header).insts.len() {
+            let InstKind::Phi(incoming) = &function.block(header).insts[i].kind else {
+                break;
+            };
+            let ty = function.block(header).insts[i].ty;
+            let (outside, inside): (Vec<_>, Vec<_>) = incoming
+                .iter()
+                .cloned()
+                .partition(|(p, _)| entering.contains(p));
+            let value = match &outside[..] {
+                [(_, v)] => v.clone(),
+                _ if outside.iter().all(|(_, v)| *v == outside[0].1) => outside[0].1.clone(),
+                _ => {
+                    let merged = function.new_value(ty);
+                    phis.push(Inst {
+                        dest: Some(merged),
+                        ty,
+                        kind: InstKind::Phi(outside),
+                    });
+                    Operand::Value(merged)
+                }
+            };
+            let mut incoming = inside;
+            incoming.push((preheader, value));
+            function.block_mut(header).insts[i].kind = InstKind::Phi(incoming);
+        }
+        let block = function.block_mut(preheader);
+        block.insts = phis;
+        block.insts.push(Inst {
+            dest: None,
+            ty: Type::Void,
+            kind: InstKind::Jump(header),
+        });
+        changed = true;
+    }
+}
+
+pub fn insert_preheaders_module(module: &mut Module) -> bool {
+    let mut changed = false;
+    for function in module.functions.iter_mut().filter(|f| !f.is_declaration()) {
+        changed |= insert_preheaders(function);
+    }
+    changed
+}
+
+/* 缩进表示嵌套, 如"loop bb1 (depth 1): blocks bb1 bb2, latches bb2, preheader bb0, exits bb3". */
+impl fmt::Display for LoopForest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn list(blocks: &[BlockId]) -> String {
+            let mut out = String::new();
+            for b in blocks {
+                let _ = write!(out, " {}", b);
+            }
+            out
+        }
+        let mut stack: Vec<usize> = self.roots().collect();
+        stack.reverse();
+        while let Some(i) = stack.pop() {
+            let l = &self.loops[i];
+            writeln!(
+                f,
+                "{}loop {} (depth {}): blocks{}, latches{}, preheader {}, exits{}",
+                "  ".repeat(l.depth - 1),
+                l.header,
+                l.depth,
+                list(&l.blocks),
+                list(&l.latches),
+                l.preheader.map_or("none".to_string(), |p| p.to_string()),
+                if l.exits.is_empty() {
+                    " none".to_string()
+                } else {
+                    list(&l.exits)
+                }
+            )?;
+            stack.extend(l.children.iter().rev());
+        }
+        Ok(())
+    }
+}
+
+/* 模块中每个有循环的函数的循环森林, 供--dump-loops输出. */
+pub fn dump(module: &Module) -> String {
+    let mut out = String::new();
+    for function in module.functions.iter().filter(|f| !f.is_declaration()) {
+        let cfg = Cfg::build(function);
+        let doms = Dominators::compute(&cfg);
+        let forest = LoopForest::compute(&cfg, &doms);
+        if !forest.loops.is_empty() {
+            let _ = write!(out, "@{}:\n{}", function.name, forest);
+        }
+    }
+    out
+}
@@ -0,0 +1,785 @@
+use crate::{
+    hir::{
+        Assign, Case, Expr, ExprKind, Hir, ItemKind, Stmt, StmtKind, SymbolId, SymbolKind, VarDecl,
+    },
+    ir::{BinOp, BlockId, CmpOp, Function, Global, Inst, InstKind, Module, Operand, Type},
+    span::{SourceMap, Span},
+    BasicType, Scope, TokenType,
+};
+use std::collections::HashMap;
+
+/*
+    HIR -> IR. 只处理没有语义错误的程序(Error节点按0处理).
+    局部变量和标量形参放在入口块的alloca中(内存形式); 数组形参本身就是地址, 直接使用.
+    &&, ||和!出现在条件中时直接生成分支, 出现在值中时用phi合并0和1; ?:同样用phi.
+    运行时库函数和只有原型的函数按被调用到的生成声明.
+    与官方sylib.h中的宏相同, 运行时库的starttime()/stoptime()调用libsysy的_sysy_starttime(行号)/_sysy_stoptime(行号),
+    行号按sources中的源文件求出(考虑预处理留下的#line).
+*/
+/* 同lower_with_sources, 没有源文件时计时函数的行号记为0. */
+pub fn lower(hir: &Hir) -> Module {
+    lower_with_sources(hir, &SourceMap::new())
+}
+
+pub fn lower_with_sources(hir: &Hir, sources: &SourceMap) -> Module {
+    let mut module = Module::default();
+    for item in &hir.items {
+        match &item.kind {
+            ItemKind::Globals(decls) => {
+                for decl in decls {
+                    module.globals.push(lower_global(hir, decl));
+                }
+            }
+            ItemKind::Func(func) => {
+                let Some(body) = &func.body else {
+                    continue;
+                };
+                let mut lowerer = FnLowerer::new(
+                    hir,
+                    sources,
+                    &mut module.strings,
+                    &mut module.globals,
+                    func.symbol,
+                );
+                lowerer.params(&func.params);
+                lowerer.stmt(body);
+                module.functions.push(lowerer.finish());
+            }
+            ItemKind::Error => {}
+        }
+    }
+    declare_callees(hir, &mut module);
+    module
+}
+
+/* 变量(或数组元素)在IR中的类型. */
+pub fn element_type(ty: &BasicType) -> Type {
+    match ty {
+        BasicType::Int | BasicType::Const | BasicType::IntArray(_) | BasicType::ConstArray(_) => {
+            Type::I32
+        }
+        BasicType::Float
+        | BasicType::ConstFloat
+        | BasicType::FloatArray(_)
+        | BasicType::ConstFloatArray(_) => Type::F32,
+        _ => Type::Void,
+    }
+}
+
+/* 表达式的值在IR中的类型: 标量是i32/f32, (部分下标的)数组是地址. */
+fn value_type(ty: &BasicType) -> Type {
+    match ty {
+        BasicType::IntArray(_)
+        | BasicType::FloatArray(_)
+        | BasicType::ConstArray(_)
+        | BasicType::ConstFloatArray(_) => Type::Ptr,
+        other => element_type(other),
+    }
+}
+
+fn dims_of(ty: &BasicType) -> &[usize] {
+    match ty {
+        BasicType::IntArray(dims)
+        | BasicType::FloatArray(dims)
+        | BasicType::ConstArray(dims)
+        | BasicType::ConstFloatArray(dims) => dims,
+        _ => &[],
+    }
+}
+
+/* 第k维的下标每加1, 地址增加的字数. */
+fn stride(dims: &[usize], k: usize) -> usize {
+    dims[k + 1..].iter().product()
+}
+
+fn zero(ty: Type) -> Operand {
+    if ty == Type::F32 {
+        Operand::Float(0.0)
+    } else {
+        Operand::Int(0)
+    }
+}
+
+/* 折叠过的初始值; 语义分析保证全局变量的初始值都是常量. */
+fn constant(expr: &Expr, ty: Type) -> Operand {
+    let value = match &expr.kind {
+        ExprKind::Int(n) => Operand::Int(*n),
+        ExprKind::Float(x) => Operand::Float(*x),
+        ExprKind::Cast(_, inner) => constant(inner, ty),
+        ExprKind::Unary(TokenType::Minus, inner) => match constant(inner, ty) {
+            Operand::Int(n) => Operand::Int(n.wrapping_neg()),
+            Operand::Float(x) => Operand::Float(-x),
+            other => other,
+        },
+        _ => zero(ty),
+    };
+    match (value, ty) {
+        (Operand::Int(n), Type::F32) => Operand::Float(n as f32),
+        (Operand::Float(x), Type::I32) => Operand::Int(x as i32),
+        (value, _) => value,
+    }
+}
+
+/* 可以直接作为初始化数据的表达式, 与constant能处理的形式相同. */
+fn is_literal(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Int(_) | ExprKind::Float(_) => true,
+        ExprKind::Cast(_, inner) | ExprKind::Unary(TokenType::Minus, inner) => is_literal(inner),
+        _ => false,
+    }
+}
+
+fn lower_global(hir: &Hir, decl: &VarDecl) -> Global {
+    let symbol = hir.symbol(decl.symbol);
+    let elem = element_type(&symbol.ty);
+    let len = dims_of(&symbol.ty).iter().product::<usize>().max(1);
+    let mut init: Vec<Operand> = decl
+        .init
+        .iter()
+        .flatten()
+        .map(|e| constant(e, elem))
+        .collect();
+    // 全0的初始值与没有初始值相同, 放进bss.
+    if init.iter().all(|v| *v == zero(elem)) {
+        init.clear();
+    } else {
+        init.resize(len, zero(elem));
+    }
+    Global {
+        name: symbol.unique_name.clone(),
+        elem,
+        len,
+        init,
+        constant: matches!(
+            symbol.ty,
+            BasicType::Const
+                | BasicType::ConstFloat
+                | BasicType::ConstArray(_)
+                | BasicType::ConstFloatArray(_)
+        ),
+    }
+}
+
+/* 为被调用但没有定义的函数(运行时库, 只有原型的函数)生成声明. */
+fn declare_callees(hir: &Hir, module: &mut Module) {
+    let mut called = vec![];
+    for function in &module.functions {
+        for inst in function.blocks.iter().flat_map(|b| &b.insts) {
+            if let InstKind::Call(name, _) = &inst.kind {
+                if !called.contains(name) {
+                    called.push(name.clone());
+                }
+            }
+        }
+    }
+    for name in called {
+        if module.function(&name).is_some() {
+            continue;
+        }
+        if TIMERS.iter().any(|(_, timer)| *timer == name) {
+            module
+                .functions
+                .push(Function::new(name, Type::Void, vec![Type::I32]));
+            continue;
+        }
+        let Some(symbol) = hir
+            .symbols
+            .iter()
+            .find(|s| s.name == name && matches!(s.kind, SymbolKind::Function { .. }))
+        else {
+            continue;
+        };
+        let SymbolKind::Function { ret, params, .. } = &symbol.kind else {
+            unreachable!()
+        };
+        let params = params
+            .iter()
+            .map(|p| value_type(&hir.symbol(*p).ty))
+            .collect();
+        let mut function = Function::new(name.clone(), element_type(ret), params);
+        function.variadic = name == "putf" && symbol.span.is_empty();
+        module.functions.push(function);
+    }
+}
+
+/* 运行时库的计时函数和libsysy中实际的函数. */
+const TIMERS: [(&str, &str); 2] = [
+    ("starttime", "_sysy_starttime"),
+    ("stoptime", "_sysy_stoptime"),
+];
+
+struct FnLowerer<'a> {
+    hir: &'a Hir,
+    sources: &'a SourceMap,
+    strings: &'a mut Vec<String>,
+    globals: &'a mut Vec<Global>, //局部常量数组提升成的全局常量
+    func: Function,
+    current: BlockId,
+    allocas: Vec<Inst>,                //最后统一放到入口块的开头
+    slots: HashMap<SymbolId, Operand>, //局部变量和形参的地址
+    breaks: Vec<BlockId>,              //break跳到的块(循环和switch)
+    continues: Vec<BlockId>,           //continue跳到的块(循环)
+}
+
+impl<'a> FnLowerer<'a> {
+    fn new(
+        hir: &'a Hir,
+        sources: &'a SourceMap,
+        strings: &'a mut Vec<String>,
+        globals: &'a mut Vec<Global>,
+        symbol: SymbolId,
+    ) -> Self {
+        let sym = hir.symbol(symbol);
+        let SymbolKind::Function { ret, params, .. } = &sym.kind else {
+            unreachable!("functions are lowered from function symbols")
+        };
+        let params = params
+            .iter()
+            .map(|p| value_type(&hir.symbol(*p).ty))
+            .collect();
+        let mut func = Function::new(sym.name.clone(), element_type(ret), params);
+        let entry = func.new_block();
+        FnLowerer {
+            hir,
+            sources,
+            strings,
+            globals,
+            func,
+            current: entry,
+            allocas: vec![],
+            slots: HashMap::new(),
+            breaks: vec![],
+            continues: vec![],
+        }
+    }
+
+    /* 标量形参存入自己的栈槽, 之后和局部变量一样读写; 数组形参直接是地址. */
+    fn params(&mut self, params: &[VarDecl]) {
+        for (i, param) in params.iter().enumerate() {
+            let value = Operand::Value(crate::ir::Value(i as u32));
+            let ty = self.hir.symbol(param.symbol).ty.clone();
+            if value_type(&ty) == Type::Ptr {
+                self.slots.insert(param.symbol, value);
+            } else {
+                let slot = self.alloca(param.symbol, 1);
+                self.emit(Type::Void, InstKind::Store(value, slot));
+            }
+        }
+    }
+
+    fn finish(mut self) -> Function {
+        if !self.terminated() {
+            let ret = match self.func.ret {
+                Type::Void => None,
+                ty => Some(zero(ty)),
+            };
+            self.emit(Type::Void, InstKind::Ret(ret));
+        }
+        let entry = &mut self.func.blocks[0].insts;
+        entry.splice(0..0, self.allocas);
+        self.func.remove_unreachable_blocks();
+        self.func.renumber_values();
+        self.func
+    }
+
+    /*---------指令和块-------------*/
+
+    fn terminated(&self) -> bool {
+        self.func.block(self.current).terminator().is_some()
+    }
+
+    /* span开头所在的(逻辑)行号; 不知道源文件时为0. */
+    fn line(&self, span: Span) -> i32 {
+        self.sources
+            .get(span.file_id)
+            .map_or(0, |file| file.logical_location(span.start).1 as i32)
+    }
+
+    /* 在当前块末尾添加指令, 返回结果; 当前块已经结束时(如return之后的语句)先开始一个不可达的新块. */
+    fn emit(&mut self, ty: Type, kind: InstKind) -> Operand {
+        if self.terminated() {
+            self.current = self.func.new_block();
+        }
+        let dest = match (&kind, ty) {
+            (_, Type::Void) => None,
+            _ => Some(self.func.new_value(ty)),
+        };
+        self.func
+            .block_mut(self.current)
+            .insts
+            .push(Inst { dest, ty, kind });
+        dest.map_or(Operand::Int(0), Operand::Value)
+    }
+
+    fn jump(&mut self, target: BlockId) {
+        if !self.terminated() {
+            self.emit(Type::Void, InstKind::Jump(target));
+        }
+    }
+
+    fn branch(&mut self, cond: Operand, on_true: BlockId, on_false: BlockId) {
+        self.emit(Type::Void, InstKind::Branch(cond, on_true, on_false));
+    }
+
+    fn switch_to(&mut self, block: BlockId) {
+        self.current = block;
+    }
+
+    fn alloca(&mut self, symbol: SymbolId, len: usize) -> Operand {
+        let sym = self.hir.symbol(symbol);
+        let dest = self.func.new_value(Type::Ptr);
+        self.allocas.push(Inst {
+            dest: Some(dest),
+            ty: Type::Ptr,
+            kind: InstKind::Alloca {
+                elem: element_type(&sym.ty),
+                len,
+                name: sym.unique_name.clone(),
+            },
+        });
+        let slot = Operand::Value(dest);
+        self.slots.insert(symbol, slot.clone());
+        slot
+    }
+
+    /* int和float之间的隐式转换; 语义分析已经插入了Cast, 这里只是保证类型一致. */
+    fn coerce(&mut self, value: Operand, to: Type) -> Operand {
+        match (self.func.operand_type(&value), to) {
+            (Type::I32, Type::F32) => match value {
+                Operand::Int(n) => Operand::Float(n as f32),
+                value => self.emit(Type::F32, InstKind::IntToFloat(value)),
+            },
+            (Type::F32, Type::I32) => match value {
+                Operand::Float(x) => Operand::Int(x as i32),
+                value => self.emit(Type::I32, InstKind::FloatToInt(value)),
+            },
+            _ => value,
+        }
+    }
+
+    /*---------语句-------------*/
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Decls(decls) => {
+                for decl in decls {
+                    self.local(decl);
+                }
+            }
+            StmtKind::Assign(assign) => {
+                self.assign(assign);
+            }
+            StmtKind::Expr(expr) => {
+                self.expr(expr);
+            }
+            StmtKind::Block(stmts) => stmts.iter().for_each(|s| self.stmt(s)),
+            StmtKind::Return(value) => {
+                let value = value.as_ref().map(|e| {
+                    let v = self.expr(e);
+                    self.coerce(v, self.func.ret)
+                });
+                self.emit(Type::Void, InstKind::Ret(value));
+            }
+            StmtKind::If(cond, on_true, on_false) => {
+                let then_block = self.func.new_block();
+                let end = self.func.new_block();
+                let else_block = if on_false.is_some() {
+                    self.func.new_block()
+                } else {
+                    end
+                };
+                self.cond(cond, then_block, else_block);
+                self.switch_to(then_block);
+                self.stmt(on_true);
+                self.jump(end);
+                if let Some(on_false) = on_false {
+                    self.switch_to(else_block);
+                    self.stmt(on_false);
+                    self.jump(end);
+                }
+                self.switch_to(end);
+            }
+            StmtKind::While(cond, body) => {
+                let head = self.func.new_block();
+                let body_block = self.func.new_block();
+                let exit = self.func.new_block();
+                self.jump(head);
+                self.switch_to(head);
+                self.cond(cond, body_block, exit);
+                self.switch_to(body_block);
+                self.breaks.push(exit);
+                self.continues.push(head);
+                self.stmt(body);
+                self.breaks.pop();
+                self.continues.pop();
+                self.jump(head);
+                self.switch_to(exit);
+            }
+            StmtKind::Switch(scrutinee, cases) => self.switch(scrutinee, cases),
+            StmtKind::Break => {
+                if let Some(&target) = self.breaks.last() {
+                    self.emit(Type::Void, InstKind::Jump(target));
+                }
+            }
+            StmtKind::Continue => {
+                if let Some(&target) = self.continues.last() {
+                    self.emit(Type::Void, InstKind::Jump(target));
+                }
+            }
+            StmtKind::Empty | StmtKind::Error => {}
+        }
+    }
+
+    /* 逐个比较case的值; case的语句依次贯穿(fallthrough), break跳到switch之后. */
+    fn switch(&mut self, scrutinee: &Expr, cases: &[Case]) {
+        let value = self.expr(scrutinee);
+        let value = self.coerce(value, Type::I32);
+        let exit = self.func.new_block();
+        let bodies: Vec<BlockId> = cases.iter().map(|_| self.func.new_block()).collect();
+        let mut default = exit;
+        for (case, &body) in cases.iter().zip(&bodies) {
+            let Some(label) = &case.label else {
+                default = body;
+                continue;
+            };
+            let label = constant(label, Type::I32);
+            let matched = self.emit(Type::I32, InstKind::Icmp(CmpOp::Eq, value.clone(), label));
+            let next = self.func.new_block();
+            self.branch(matched, body, next);
+            self.switch_to(next);
+        }
+        self.jump(default);
+        self.breaks.push(exit);
+        for (i, case) in cases.iter().enumerate() {
+            self.switch_to(bodies[i]);
+            case.body.iter().for_each(|s| self.stmt(s));
+            self.jump(bodies.get(i + 1).copied().unwrap_or(exit));
+        }
+        self.breaks.pop();
+        self.switch_to(exit);
+    }
+
+    fn local(&mut self, decl: &VarDecl) {
+        let ty = self.hir.symbol(decl.symbol).ty.clone();
+        let elem = element_type(&ty);
+        let dims = dims_of(&ty);
+        let len = dims.iter().product::<usize>().max(1);
+        /*
+            局部的常量数组每次进入函数时内容都相同, 并且不会被写入: 提升成只读的全局常量,
+            不在栈上逐个写入. 名字中的'.'不会出现在SysY的标识符中, 不会与别的全局变量重名.
+        */
+        if matches!(ty, BasicType::ConstArray(_) | BasicType::ConstFloatArray(_))
+            && decl.init.iter().flatten().all(is_literal)
+        {
+            let mut global = lower_global(self.hir, decl);
+            global.name = format!("{}.{}", self.func.name, global.name);
+            self.slots
+                .insert(decl.symbol, Operand::Global(global.name.clone()));
+            self.globals.push(global);
+            return;
+        }
+        let slot = self.alloca(decl.symbol, len);
+        let Some(inits) = &decl.init else {
+            return;
+        };
+        if dims.is_empty() {
+            if let Some(init) = inits.first() {
+                let value = self.expr(init);
+                let value = self.coerce(value, elem);
+                self.emit(Type::Void, InstKind::Store(value, slot));
+            }
+            return;
+        }
+        // 较大的数组先用循环清零, 之后只写非0的元素; 小数组逐个写入.
+        let fill = len > 16;
+        if fill {
+            self.zero_fill(slot.clone(), len, elem);
+        }
+        for (offset, init) in inits.iter().enumerate().take(len) {
+            let value = self.expr(init);
+            let value = self.coerce(value, elem);
+            if fill && value == zero(elem) {
+                continue;
+            }
+            let addr = self.emit(
+                Type::Ptr,
+                InstKind::Gep(slot.clone(), Operand::Int(offset as i32), 1),
+            );
+            self.emit(Type::Void, InstKind::Store(value, addr));
+        }
+    }
+
+    fn zero_fill(&mut self, base: Operand, len: usize, elem: Type) {
+        let pre = self.current;
+        let head = self.func.new_block();
+        let body = self.func.new_block();
+        let exit = self.func.new_block();
+        self.jump(head);
+        self.switch_to(head);
+        let index = self.func.new_value(Type::I32);
+        let next = self.func.new_value(Type::I32);
+        self.func.block_mut(head).insts.push(Inst {
+            dest: Some(index),
+            ty: Type::I32,
+            kind: InstKind::Phi(vec![(pre, Operand::Int(0)), (body, Operand::Value(next))]),
+        });
+        let index = Operand::Value(index);
+        let more = self.emit(
+            Type::I32,
+            InstKind::Icmp(CmpOp::Lt, index.clone(), Operand::Int(len as i32)),
+        );
+        self.branch(more, body, exit);
+        self.switch_to(body);
+        let addr = self.emit(Type::Ptr, InstKind::Gep(base, index.clone(), 1));
+        self.emit(Type::Void, InstKind::Store(zero(elem), addr));
+        self.func.block_mut(body).insts.push(Inst {
+            dest: Some(next),
+            ty: Type::I32,
+            kind: InstKind::Binary(BinOp::Add, index, Operand::Int(1)),
+        });
+        self.jump(head);
+        self.switch_to(exit);
+    }
+
+    /*---------地址-------------*/
+
+    /* 变量的地址: 全局变量是@name, 局部变量是alloca的结果, 数组形参是形参本身. */
+    fn base(&self, symbol: SymbolId) -> Operand {
+        let sym = self.hir.symbol(symbol);
+        match sym.kind {
+            SymbolKind::Variable(Scope::Global) => Operand::Global(sym.unique_name.clone()),
+            _ => self.slots.get(&symbol).cloned().unwrap_or(Operand::Int(0)),
+        }
+    }
+
+    /* 按下标逐维计算地址; 下标个数少于维数时得到子数组的地址. */
+    fn address(&mut self, symbol: SymbolId, indexes: &[Expr]) -> Operand {
+        let dims = dims_of(&self.hir.symbol(symbol).ty).to_vec();
+        let mut addr = self.base(symbol);
+        for (k, index) in indexes.iter().enumerate().take(dims.len()) {
+            let index = self.expr(index);
+            let index = self.coerce(index, Type::I32);
+            addr = self.emit(Type::Ptr, InstKind::Gep(addr, index, stride(&dims, k)));
+        }
+        addr
+    }
+
+    fn assign(&mut self, assign: &Assign) -> Operand {
+        let ty = self.hir.symbol(assign.target).ty.clone();
+        let value = self.expr(&assign.value);
+        let value = self.coerce(value, element_type(&ty));
+        let indexes = assign.indexes.as_deref().unwrap_or(&[]);
+        let addr = self.address(assign.target, indexes);
+        self.emit(Type::Void, InstKind::Store(value.clone(), addr));
+        value
+    }
+
+    /*---------表达式-------------*/
+
+    fn expr(&mut self, expr: &Expr) -> Operand {
+        match &expr.kind {
+            ExprKind::Int(n) => Operand::Int(*n),
+            ExprKind::Float(x) => Operand::Float(*x),
+            ExprKind::Str(s) => {
+                self.strings.push(s.clone());
+                Operand::Str(self.strings.len() - 1)
+            }
+            ExprKind::Var(symbol, indexes) => {
+                let indexes = indexes.as_deref().unwrap_or(&[]);
+                let addr = self.address(*symbol, indexes);
+                match value_type(&expr.ty) {
+                    Type::Ptr => addr,
+                    ty => self.emit(ty, InstKind::Load(addr)),
+                }
+            }
+            ExprKind::Assign(assign) => self.assign(assign),
+            ExprKind::Binary(op, lhs, rhs) => self.binary(expr, op, lhs, rhs),
+            ExprKind::Unary(op, operand) => {
+                let value = self.expr(operand);
+                match (op, self.func.operand_type(&value)) {
+                    (TokenType::Minus, Type::F32) => self.emit(Type::F32, InstKind::FNeg(value)),
+                    (TokenType::Minus, _) => self.emit(
+                        Type::I32,
+                        InstKind::Binary(BinOp::Sub, Operand::Int(0), value),
+                    ),
+                    (TokenType::Not, Type::F32) => self.emit(
+                        Type::I32,
+                        InstKind::Fcmp(CmpOp::Eq, value, Operand::Float(0.0)),
+                    ),
+                    (TokenType::Not, _) => {
+                        self.emit(Type::I32, InstKind::Icmp(CmpOp::Eq, value, Operand::Int(0)))
+                    }
+                    _ => value,
+                }
+            }
+            ExprKind::Cond(cond, on_true, on_false) => {
+                let ty = value_type(&expr.ty);
+                let then_block = self.func.new_block();
+                let else_block = self.func.new_block();
+                let end = self.func.new_block();
+                self.cond(cond, then_block, else_block);
+                let mut incoming = vec![];
+                for (block, branch) in [(then_block, on_true), (else_block, on_false)] {
+                    self.switch_to(block);
+                    let value = self.expr(branch);
+                    let value = self.coerce(value, ty);
+                    incoming.push((self.current, value));
+                    self.jump(end);
+                }
+                self.switch_to(end);
+                self.phi(ty, incoming)
+            }
+            ExprKind::Comma(exprs) => {
+                let mut last = Operand::Int(0);
+                for e in exprs {
+                    last = self.expr(e);
+                }
+                last
+            }
+            ExprKind::Cast(ty, operand) => {
+                let value = self.expr(operand);
+                self.coerce(value, element_type(ty))
+            }
+            ExprKind::Call(callee, args) => {
+                let sym = self.hir.symbol(*callee);
+                if sym.span.is_empty() {
+                    if let Some((_, timer)) = TIMERS.iter().find(|(name, _)| *name == sym.name) {
+                        let line = Operand::Int(self.line(expr.span));
+                        return self
+                            .emit(Type::Void, InstKind::Call(timer.to_string(), vec![line]));
+                    }
+                }
+                let name = sym.name.clone();
+                let ret = match &sym.kind {
+                    SymbolKind::Function { ret, .. } => element_type(ret),
+                    _ => Type::Void,
+                };
+                let params: Vec<Type> = match &sym.kind {
+                    SymbolKind::Function { params, .. } => params
+                        .iter()
+                        .map(|p| value_type(&self.hir.symbol(*p).ty))
+                        .collect(),
+                    _ => vec![],
+                };
+                let mut values = vec![];
+                for (i, arg) in args.iter().enumerate() {
+                    let value = self.expr(arg);
+                    let value = match params.get(i) {
+                        Some(&ty) if ty != Type::Ptr => self.coerce(value, ty),
+                        _ => value,
+                    };
+                    values.push(value);
+                }
+                self.emit(ret, InstKind::Call(name, values))
+            }
+            ExprKind::InitList(_) | ExprKind::Error => Operand::Int(0),
+        }
+    }
+
+    fn phi(&mut self, ty: Type, incoming: Vec<(BlockId, Operand)>) -> Operand {
+        if ty == Type::Void {
+            return Operand::Int(0);
+        }
+        self.emit(ty, InstKind::Phi(incoming))
+    }
+
+    fn binary(&mut self, expr: &Expr, op: &TokenType, lhs: &Expr, rhs: &Expr) -> Operand {
+        use TokenType::*;
+        if matches!(op, And | Or) {
+            // 值形式的短路运算: 两个分支分别得到1和0.
+            let on_true = self.func.new_block();
+            let on_false = self.func.new_block();
+            let end = self.func.new_block();
+            self.cond(expr, on_true, on_false);
+            self.switch_to(on_true);
+            self.jump(end);
+            self.switch_to(on_false);
+            self.jump(end);
+            self.switch_to(end);
+            return self.phi(
+                Type::I32,
+                vec![(on_true, Operand::Int(1)), (on_false, Operand::Int(0))],
+            );
+        }
+        let l = self.expr(lhs);
+        let r = self.expr(rhs);
+        let float =
+            self.func.operand_type(&l) == Type::F32 || self.func.operand_type(&r) == Type::F32;
+        let (l, r) = if float {
+            (self.coerce(l, Type::F32), self.coerce(r, Type::F32))
+        } else {
+            (l, r)
+        };
+        if let Some(cmp) = compare_op(op) {
+            let kind = if float {
+                InstKind::Fcmp(cmp, l, r)
+            } else {
+                InstKind::Icmp(cmp, l, r)
+            };
+            return self.emit(Type::I32, kind);
+        }
+        let bin = match (op, float) {
+            (Plus, false) => BinOp::Add,
+            (Minus, false) => BinOp::Sub,
+            (Multi, false) => BinOp::Mul,
+            (Divide, false) => BinOp::Div,
+            (Mods, _) => BinOp::Rem,
+            (Plus, true) => BinOp::FAdd,
+            (Minus, true) => BinOp::FSub,
+            (Multi, true) => BinOp::FMul,
+            (Divide, true) => BinOp::FDiv,
+            _ => return Operand::Int(0),
+        };
+        let ty = if float { Type::F32 } else { Type::I32 };
+        self.emit(ty, InstKind::Binary(bin, l, r))
+    }
+
+    /* 条件跳转: 条件为真跳到on_true, 否则跳到on_false. */
+    fn cond(&mut self, expr: &Expr, on_true: BlockId, on_false: BlockId) {
+        match &expr.kind {
+            ExprKind::Binary(TokenType::And, lhs, rhs) => {
+                let mid = self.func.new_block();
+                self.cond(lhs, mid, on_false);
+                self.switch_to(mid);
+                self.cond(rhs, on_true, on_false);
+            }
+            ExprKind::Binary(TokenType::Or, lhs, rhs) => {
+                let mid = self.func.new_block();
+                self.cond(lhs, on_true, mid);
+                self.switch_to(mid);
+                self.cond(rhs, on_true, on_false);
+            }
+            ExprKind::Unary(TokenType::Not, operand) => self.cond(operand, on_false, on_true),
+            ExprKind::Int(n) => {
+                let target = if *n != 0 { on_true } else { on_false };
+                self.jump(target);
+            }
+            _ => {
+                let value = self.expr(expr);
+                // br只看i32是否为0, float先与0比较.
+                let value = if self.func.operand_type(&value) == Type::F32 {
+                    self.emit(
+                        Type::I32,
+                        InstKind::Fcmp(CmpOp::Ne, value, Operand::Float(0.0)),
+                    )
+                } else {
+                    value
+                };
+                self.branch(value, on_true, on_false);
+            }
+        }
+    }
+}
+
+fn compare_op(op: &TokenType) -> Option<CmpOp> {
+    use TokenType::*;
+    Some(match op {
+        Equal => CmpOp::Eq,
+        NotEqual => CmpOp::Ne,
+        Lesserthan => CmpOp::Lt,
+        LessEqual => CmpOp::Le,
+        Greaterthan => CmpOp::Gt,
+        GreatEqual => CmpOp::Ge,
+        _ => return None,
+    })
+}
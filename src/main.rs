@@ -1,24 +1,530 @@
-use std::path::Path;
+use std::io::{IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
 use sysy_alpha::{
-    lexer::tokenize, parser::parse, semantics::semantic, utils::print_tokens, utils::print_tree,
+    cfg,
+    codegen::{frame, koopa, llvm, object, riscv},
+    ddg,
+    diagnostics::{Diagnostic, DiagnosticEngine, WarningCategory, WarningConfig},
+    hir::Hir,
+    interp,
+    lexer::{tokenize_with_diagnostics, LangLevel, LexOptions},
+    loops,
+    lower::{lower, lower_with_sources},
+    parser::{parse_recovering, Node},
+    passes::{OptLevel, Pass, PassManager},
+    preprocess::preprocess_to_file,
+    schedule::LatencyTable,
+    semantics::annotate_with,
+    short_circuit::lower_short_circuit,
+    span::SourceMap,
+    target::Target,
+    toolchain::{self, Assembler, Toolchain},
+    utils::print_tokens,
+    utils::print_tree,
+    verify::{verify, verify_module},
+    whole_program::prune_unreachable,
 };
 
+/* 编译到哪个阶段为止: 课程的前几个阶段只评测词法/语法分析, 此时完全跳过语义分析. */
+#[derive(PartialEq, PartialOrd)]
+enum Stage {
+    Lex,
+    Parse,
+    Semantic,
+}
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: sysy_alpha [run --ir] [source.sy] [--preprocess] [--whole-program] [--lower-short-circuit] [--verify] [--emit-ir] [--emit-llvm] [--emit-koopa] [--emit-asm] [--emit-obj] [--link [-o <executable>] [--as <command>|builtin] [--ld <command>] [--runtime <libsysy.a|sylib.c>]] [--dump-cfg] [--dump-loops] [--dump-ddg block|loop] [--dump-frame] [--mem2reg] [--inline] [--sccp] [--copy-prop] [--cse|--local-cse] [--loop-simplify] [--strength-reduce] [--peephole] [--schedule] [-O0|-O1|-O2] [--target riscv64|armv7] [--dump-after <pass>|all] [-w] [-W<category>] [-Wno-<category>] [-Werror] [--max-errors N] [--stop-after lex|parse]"
+    );
+    std::process::exit(2);
+}
+
+/*
+    --verify: 检查pass之后的语义AST(verify)或三地址码(verify_module), 发现内部错误时打印并以3退出.
+    有语义错误的程序不检查(由调用者判断), 那时AST中有为继续分析而生成的占位节点.
+*/
+fn run_verifier(errors: Vec<Diagnostic>, engine: &DiagnosticEngine) {
+    if errors.is_empty() {
+        return;
+    }
+    for error in &errors {
+        eprint!("{}", engine.render(error));
+    }
+    std::process::exit(3);
+}
+
+/*
+    语义分析, 语法树的打印, HIR的转换和降低都沿语法树递归, 很长的运算链(如5000项的a + 1 + 1 + ...)
+    是同样深的左斜树, 主线程默认的8MB栈不够用; 整个编译在栈更大的线程中进行(只占用虚拟地址空间, 用到时才分配).
+*/
+const COMPILER_STACK_SIZE: usize = 1 << 30;
+
 fn main() {
-    /* 定义文件路径: .sy源代码路径, token输出路径, ast输出路径. */
-    let source_path = String::from("./test.sy");
-    let source = source_path.clone();
-    let token_path = String::from("./test.tokens");
-    let ast_path = String::from("./test.ast");
+    let compiler = std::thread::Builder::new()
+        .name("compiler".to_string())
+        .stack_size(COMPILER_STACK_SIZE)
+        .spawn(compile)
+        .unwrap_or_else(|e| {
+            eprintln!("cannot start the compiler thread: {}", e);
+            std::process::exit(1);
+        });
+    // panic的信息已经由线程打印, 按panic时的惯例以101退出.
+    if compiler.join().is_err() {
+        std::process::exit(101);
+    }
+}
+
+fn compile() {
+    /*
+        解析命令行: 开头的run --ir(解释执行优化后的三地址码, 不写任何中间文件), 源文件路径(默认./test.sy), --preprocess, --whole-program, --lower-short-circuit, --verify, --emit-ir, --emit-llvm, --emit-koopa, --emit-asm, --emit-obj, --link(汇编并链接成可执行文件, -o/--as/--ld/--runtime指定输出, 汇编器, 链接器和运行时库), --dump-cfg, --dump-loops, --dump-ddg block|loop, --dump-frame, --mem2reg, --inline, --sccp, --copy-prop, --cse, --local-cse, --loop-simplify, --strength-reduce, --peephole, --schedule,
+        -O0/-O1/-O2(预设的优化流水线, 单独打开的pass接在它后面), --target riscv64|armv7(目标机器, 默认riscv64), --dump-after <pass>|all(在某个pass之后输出IR),
+        -w(不输出警告), -W<类别>/-Wno-<类别>(打开/关闭一类语义警告, 如-Wshadowing), -Werror(语义警告当作错误),
+        --max-errors N, --stop-after lex|parse.
+    */
+    let mut source_path = String::from("./test.sy");
+    let mut stop_after = Stage::Semantic;
+    let mut run_preprocessor = false;
+    let mut whole_program = false;
+    let mut lower_logic = false;
+    let mut verify_passes = false;
+    let mut emit_ir = false;
+    let mut emit_llvm = false;
+    let mut emit_koopa = false;
+    let mut emit_asm = false;
+    let mut emit_obj = false;
+    let mut link = false;
+    let mut exe_path = None;
+    let mut assembler = None;
+    let mut linker = None;
+    let mut runtime = None;
+    let mut dump_cfg = false;
+    let mut dump_loops = false;
+    let mut dump_ddg = None;
+    let mut dump_frame = false;
+    let mut opt_level = OptLevel::O0;
+    let mut target = Target::default();
+    let mut extra_passes = vec![];
+    let mut dump_after = None;
+    let mut show_warnings = true;
+    let mut warnings = WarningConfig::default();
+    let mut max_errors = None;
+    let mut args = std::env::args().skip(1).peekable();
+    let run_ir = args.next_if(|a| a == "run").is_some();
+    let mut interpret = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--stop-after" => {
+                stop_after = match args.next().as_deref() {
+                    Some("lex") => Stage::Lex,
+                    Some("parse") => Stage::Parse,
+                    _ => usage(),
+                }
+            }
+            "--preprocess" => run_preprocessor = true,
+            "--whole-program" => whole_program = true,
+            "--lower-short-circuit" => lower_logic = true,
+            "--verify" => verify_passes = true,
+            "--emit-ir" => emit_ir = true,
+            "--emit-llvm" => emit_llvm = true,
+            "--emit-koopa" => emit_koopa = true,
+            "--emit-asm" => emit_asm = true,
+            "--emit-obj" => emit_obj = true,
+            "--link" => link = true,
+            "-o" => exe_path = Some(args.next().unwrap_or_else(|| usage())),
+            "--as" => {
+                assembler = match args.next() {
+                    Some(a) if a == "builtin" => Some(Assembler::Builtin),
+                    Some(a) => Some(Assembler::Command(a)),
+                    None => usage(),
+                }
+            }
+            "--ld" => linker = Some(args.next().unwrap_or_else(|| usage())),
+            "--runtime" => runtime = Some(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+            "--dump-cfg" => dump_cfg = true,
+            "--dump-loops" => dump_loops = true,
+            "--dump-ddg" => {
+                dump_ddg = match args.next().as_deref().and_then(ddg::Scope::from_name) {
+                    Some(scope) => Some(scope),
+                    None => usage(),
+                }
+            }
+            "--dump-frame" => dump_frame = true,
+            "--ir" if run_ir => interpret = true,
+            "--dump-after" => {
+                dump_after = match args.next().as_deref() {
+                    Some("all") => Some(None),
+                    Some(name) => match Pass::from_name(name) {
+                        Some(pass) => Some(Some(pass)),
+                        None => usage(),
+                    },
+                    None => usage(),
+                }
+            }
+            "--target" => {
+                target = match args.next().as_deref().and_then(Target::from_name) {
+                    Some(t) => t,
+                    None => usage(),
+                }
+            }
+            _ if arg.starts_with("-O") => match OptLevel::from_name(&arg[2..]) {
+                Some(level) => opt_level = level,
+                None => usage(),
+            },
+            _ if arg.starts_with("--") && Pass::from_name(&arg[2..]).is_some() => {
+                let pass = Pass::from_name(&arg[2..]).unwrap();
+                // --cse和--local-cse只保留最后一个.
+                let other = match pass {
+                    Pass::Cse => Some(Pass::LocalCse),
+                    Pass::LocalCse => Some(Pass::Cse),
+                    _ => None,
+                };
+                extra_passes.retain(|p| *p != pass && Some(*p) != other);
+                extra_passes.push(pass);
+            }
+            "-w" => show_warnings = false,
+            "-Werror" => warnings.werror = true,
+            _ if arg.starts_with("-W") => {
+                let (name, enabled) = match arg[2..].strip_prefix("no-") {
+                    Some(name) => (name, false),
+                    None => (&arg[2..], true),
+                };
+                match WarningCategory::from_name(name) {
+                    Some(category) => warnings.set(category, enabled),
+                    None => usage(),
+                }
+            }
+            "--max-errors" => {
+                // 0表示不限制.
+                max_errors = match args.next().map(|n| n.parse::<usize>()) {
+                    Some(Ok(0)) => None,
+                    Some(Ok(n)) => Some(n),
+                    _ => usage(),
+                }
+            }
+            _ if arg.starts_with("--") => usage(),
+            _ => source_path = arg,
+        }
+    }
+
+    // 目前只能解释执行三地址码, 以后的本地后端会在这里加上别的执行方式.
+    if run_ir && !interpret {
+        usage();
+    }
+
+    /* 定义文件路径: .sy源代码路径, token输出路径, ast输出路径, ir, ll, koopa, 汇编, 目标文件和dot输出路径(与源文件同名, 扩展名不同), 以及--link生成的可执行文件. */
+    let token_path = Path::new(&source_path).with_extension("tokens");
+    let ast_path = Path::new(&source_path).with_extension("ast");
+    let ir_path = Path::new(&source_path).with_extension("ir");
+    let ll_path = Path::new(&source_path).with_extension("ll");
+    let koopa_path = Path::new(&source_path).with_extension("koopa");
+    let asm_path = Path::new(&source_path).with_extension("s");
+    let obj_path = Path::new(&source_path).with_extension("o");
+    let exe_path =
+        exe_path.map_or_else(|| Path::new(&source_path).with_extension(""), PathBuf::from);
+    let dot_path = Path::new(&source_path).with_extension("dot");
+    let loops_path = Path::new(&source_path).with_extension("loops");
+    let ddg_path = Path::new(&source_path).with_extension("ddg.dot");
+    let frame_path = Path::new(&source_path).with_extension("frame");
+    let source_dir = match Path::new(&source_path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
 
-    /* 词法分析, 源字符流 -> 词法单元流tokens */
-    let tokens = tokenize(source_path);
-    print_tokens(&tokens, Path::new(&token_path));
+    /* 可选的预处理, 展开#include/#define后的代码写入.i文件, 之后各阶段都读取它. */
+    if run_preprocessor {
+        source_path = match preprocess_to_file(&source_path) {
+            Ok(path) => path,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        };
+    }
 
-    /* 语法分析, 词法单元流tokens -> 语法树ast, [feat]:支持浮点类型的语法分析 */
-    let ast = parse(tokens);
-    print_tree(&ast, Path::new(&ast_path), "ast", false);
+    /* 各阶段的诊断都交给同一个引擎, 按读入的源代码渲染; 输出到终端时带颜色. */
+    let mut sources = SourceMap::new();
+    if let Err(e) = sources.load(&source_path) {
+        eprintln!("cannot read {}: {}", source_path, e);
+        std::process::exit(1);
+    }
+    let engine = DiagnosticEngine::new(sources).with_color(std::io::stdout().is_terminal());
+
+    /* 词法分析, 源字符流 -> 词法单元流tokens; 有词法错误时仍做语法分析以报告语法错误, 但不再做语义分析和生成代码. */
+    let (tokens, lex_failed) = match tokenize_with_diagnostics(source_path, &LexOptions::default())
+    {
+        Ok((tokens, diagnostics)) => {
+            for diagnostic in &diagnostics {
+                report(&engine, diagnostic, run_ir);
+            }
+            (tokens, diagnostics.iter().any(Diagnostic::is_error))
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    if !run_ir {
+        print_tokens(&tokens, &token_path);
+    }
+    if stop_after == Stage::Lex {
+        if lex_failed {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    /*
+        语法分析, 词法单元流tokens -> 语法树ast, [feat]:支持浮点类型的语法分析.
+        有语法错误时仍输出能解析出的部分AST(出错处为Nil节点), 再以1退出.
+    */
+    let (ast, errors) = parse_recovering(tokens, &LangLevel::default().into());
+    for e in &errors {
+        report(&engine, &e.to_diagnostic(), run_ir);
+    }
+    if !run_ir {
+        print_tree(&ast, &ast_path, "ast", false);
+    }
+    if !errors.is_empty() || lex_failed {
+        std::process::exit(1);
+    }
+    if stop_after == Stage::Parse {
+        return;
+    }
 
     /* 语义分析, 语法树ast -> 语义树sem(附带类型信息的ast) */
-    let annotated_ast = semantic(&ast, &source);
-    print_tree(&annotated_ast, Path::new(&ast_path), "sem", true);
+    let (mut annotated_ast, diagnostics) = annotate_with(&ast, &warnings, max_errors);
+    for diagnostic in &diagnostics {
+        if show_warnings || diagnostic.is_error() {
+            report(&engine, diagnostic, run_ir);
+        }
+    }
+    let has_errors = diagnostics.iter().any(Diagnostic::is_error);
+    let verify_passes = verify_passes && !has_errors;
+    if verify_passes {
+        run_verifier(verify("semantic", &annotated_ast), &engine);
+    }
+
+    /*
+        全程序模式: 删除main不可达的函数和未使用的全局声明, 并在标准错误上报告删除了什么.
+        数据段缩小的字节数由删除前后降低得到的模块计算, 与后端输出的全局变量一致; 有错误时不生成代码, 也就不统计.
+    */
+    if whole_program {
+        let data_bytes = |ast: &[Node]| lower(&Hir::from_nodes(ast)).data_bytes();
+        let before = (!has_errors).then(|| data_bytes(&annotated_ast));
+        let (pruned, removed) = prune_unreachable(annotated_ast);
+        annotated_ast = pruned;
+        for name in &removed.functions {
+            eprintln!("whole-program: removed unreachable function `{}`", name);
+        }
+        for name in &removed.globals {
+            eprintln!("whole-program: removed unused global `{}`", name);
+        }
+        eprint!(
+            "whole-program: {} functions, {} globals removed",
+            removed.functions.len(),
+            removed.globals.len()
+        );
+        match before {
+            Some(before) => eprintln!(
+                ", data section shrunk by {} bytes",
+                before - data_bytes(&annotated_ast)
+            ),
+            None => eprintln!(),
+        }
+        if verify_passes {
+            run_verifier(verify("whole-program", &annotated_ast), &engine);
+        }
+    }
+
+    /* 把&&和||改写成嵌套的if, 之后的阶段按顺序求值即可满足短路语义. */
+    if lower_logic {
+        annotated_ast = lower_short_circuit(annotated_ast);
+        if verify_passes {
+            run_verifier(verify("lower-short-circuit", &annotated_ast), &engine);
+        }
+    }
+    if !run_ir {
+        print_tree(&annotated_ast, &ast_path, "sem", true);
+    }
+    // 有语义错误(包括-Werror升级的警告)时不再生成代码, 以1退出.
+    if has_errors {
+        std::process::exit(1);
+    }
+
+    /*
+        没有语义错误时生成三地址码, 写入.ir文件; 翻译成的LLVM IR和Koopa IR分别写入.ll和.koopa文件,
+        各函数的控制流图以DOT格式写入.dot文件, 循环森林写入.loops文件,
+        每个基本块(或每个循环)的数据依赖图以DOT格式写入.ddg.dot文件. --emit-asm输出--target的汇编到.s文件(目前只有riscv64),
+        --emit-obj不经过外部汇编器, 直接把它汇编成ELF目标文件写入.o文件,
+        --link在写出.s之后调用汇编器和链接器(默认是--target的GNU交叉工具链), 与libsysy(--runtime, 默认是源文件目录中的libsysy.a, 没有时用链接器编译内置的runtime/sylib.c)
+        链接成可执行文件(-o, 默认是去掉扩展名的源文件名); 工具找不到或者执行失败时以1退出.
+        --dump-frame把本地后端为每个函数计算的栈帧布局写入.frame文件. --mem2reg先把局部标量提升为SSA值, --inline内联小函数和只调用一次的函数, --sccp之后做条件常量传播, --copy-prop删除复制,
+        --cse/--local-cse沿支配树/在扩展基本块中删除公共子表达式, --loop-simplify给没有preheader的循环补上,
+        --strength-reduce把乘除2的幂和循环中归纳变量的乘法换成更便宜的运算, --peephole按规则表做窥孔优化,
+        --schedule按目标机器的指令延迟在基本块内重排指令.
+        这些pass都由PassManager运行, --verify在降低之后和每个pass之后检查三地址码; --dump-after把每个(或指定的)pass之后的IR写入<源文件>.<序号>.<pass>.ir.
+        run --ir从标准输入读取程序的输入, 输出写到标准输出, 以main的返回值退出; 执行出错时以1退出.
+    */
+    if emit_ir
+        || emit_llvm
+        || emit_koopa
+        || emit_asm
+        || emit_obj
+        || link
+        || dump_cfg
+        || dump_loops
+        || dump_ddg.is_some()
+        || dump_frame
+        || dump_after.is_some()
+        || run_ir
+    {
+        // 语义AST上的变换都做完了, 只在这里建立一次HIR.
+        let mut module = lower_with_sources(&Hir::from_nodes(&annotated_ast), engine.sources());
+        if verify_passes {
+            run_verifier(verify_module("lower", &module), &engine);
+        }
+        // 单独打开的pass按固定的顺序接在-O的流水线之后, 与它们在命令行中的顺序无关.
+        extra_passes.sort_by_key(|p| Pass::ALL.iter().position(|q| q == p));
+        let mut manager = PassManager::for_level(opt_level);
+        manager.set_latencies(LatencyTable::for_target(target));
+        for pass in extra_passes {
+            manager.add(pass);
+        }
+        let mut step = 0;
+        manager.run_with(&mut module, |pass, module| {
+            step += 1;
+            if verify_passes {
+                run_verifier(verify_module(pass.name(), module), &engine);
+            }
+            if dump_after.is_some_and(|d| d.is_none_or(|p| p == pass)) {
+                let path = ir_path.with_extension(format!("{}.{}.ir", step, pass.name()));
+                write_output(&path, &module.to_string());
+            }
+        });
+        if run_ir {
+            let mut input = vec![];
+            if let Err(e) = std::io::stdin().read_to_end(&mut input) {
+                eprintln!("cannot read standard input: {}", e);
+                std::process::exit(1);
+            }
+            match interp::run(&module, &input) {
+                Ok(outcome) => {
+                    let mut stdout = std::io::stdout();
+                    let _ = stdout.write_all(&outcome.output);
+                    let _ = stdout.flush();
+                    std::process::exit(outcome.exit_code & 0xff);
+                }
+                Err(e) => {
+                    eprintln!("runtime error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        if emit_ir {
+            write_output(&ir_path, &module.to_string());
+        }
+        if emit_llvm {
+            write_output(&ll_path, &llvm::emit(&module));
+        }
+        if dump_cfg {
+            write_output(&dot_path, &cfg::to_dot(&module));
+        }
+        if dump_loops {
+            write_output(&loops_path, &loops::dump(&module));
+        }
+        if let Some(scope) = dump_ddg {
+            write_output(&ddg_path, &ddg::to_dot(&module, scope));
+        }
+        if dump_frame {
+            write_output(&frame_path, &frame::dump(&module, target));
+        }
+        if emit_asm || link {
+            let asm = match target {
+                Target::Riscv64 => riscv::emit(&module),
+                Target::Armv7 => Err("the armv7 backend is not implemented yet".to_string()),
+            };
+            match asm {
+                Ok(text) => write_output(&asm_path, &text),
+                Err(e) => {
+                    eprintln!("cannot emit assembly: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        if link {
+            let mut toolchain = Toolchain::for_target(target);
+            if let Some(assembler) = assembler {
+                toolchain.assembler = assembler;
+            }
+            if let Some(linker) = linker {
+                toolchain.linker = linker;
+            }
+            toolchain.runtime = runtime.or_else(|| toolchain::find_runtime(&source_dir));
+            // 没有指定也没有找到libsysy.a时, 在临时目录中编译内置的运行时库, 链接之后删除.
+            let bundled = toolchain.runtime.is_none().then(|| {
+                std::env::temp_dir().join(format!(
+                    "sysy_alpha_runtime_{}_{}",
+                    target.name(),
+                    std::process::id()
+                ))
+            });
+            let mut result = Ok(());
+            if let Some(dir) = &bundled {
+                result = toolchain
+                    .build_runtime(dir)
+                    .map(|o| toolchain.runtime = Some(o));
+            }
+            let result = result.and_then(|_| toolchain.build(&asm_path, &exe_path));
+            if let Some(dir) = &bundled {
+                let _ = std::fs::remove_dir_all(dir);
+            }
+            if let Err(e) = result {
+                eprintln!("cannot link: {}", e);
+                std::process::exit(1);
+            }
+        }
+        if emit_obj {
+            let obj = match target {
+                Target::Riscv64 => object::emit(&module),
+                Target::Armv7 => Err("the armv7 backend is not implemented yet".to_string()),
+            };
+            match obj {
+                Ok(bytes) => write_bytes(&obj_path, &bytes),
+                Err(e) => {
+                    eprintln!("cannot emit object file: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        if emit_koopa {
+            match koopa::emit(&module) {
+                Ok(text) => write_output(&koopa_path, &text),
+                Err(e) => {
+                    eprintln!("cannot emit Koopa IR: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+}
+
+/* 诊断通常打印到标准输出; run --ir时标准输出属于被执行的程序, 改为打印到标准错误. */
+fn report(engine: &DiagnosticEngine, diagnostic: &Diagnostic, to_stderr: bool) {
+    if to_stderr {
+        eprint!("{}", engine.render(diagnostic));
+    } else {
+        engine.emit(diagnostic);
+    }
+}
+
+fn write_output(path: &Path, text: &str) {
+    write_bytes(path, text.as_bytes());
+}
+
+fn write_bytes(path: &Path, bytes: &[u8]) {
+    if let Err(e) = std::fs::write(path, bytes) {
+        eprintln!("cannot write {}: {}", path.display(), e);
+        std::process::exit(1);
+    }
 }
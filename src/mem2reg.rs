@@ -0,0 +1,200 @@
+use crate::{
+    cfg::{Cfg, Dominators},
+    ir::{resolve, BlockId, Function, Inst, InstKind, Module, Operand, Type, Value},
+};
+use std::collections::HashMap;
+
+/*
+    mem2reg: 把只被load/store直接访问的标量alloca提升为SSA值.
+    按迭代支配边界插入phi, 再沿支配树重命名: load换成当前的值, store更新当前的值, 两者都删除.
+    没有被写过就读的变量按0处理(SysY中它的值是未定义的).
+    最后删除没有用到的phi和所有来源都相同的phi.
+*/
+pub fn promote_module(module: &mut Module) -> usize {
+    module
+        .functions
+        .iter_mut()
+        .filter(|f| !f.is_declaration())
+        .map(promote)
+        .sum()
+}
+
+/* 返回提升的变量个数. */
+pub fn promote(function: &mut Function) -> usize {
+    function.remove_unreachable_blocks();
+    let slots = promotable(function);
+    if slots.is_empty() {
+        return 0;
+    }
+    let cfg = Cfg::build(function);
+    let doms = Dominators::compute(&cfg);
+    let frontiers = doms.frontiers(&cfg);
+
+    // 插入phi: phis[块]中是(变量, phi的值), 来源在重命名时填写.
+    // 按alloca的先后处理, phi的编号与HashMap的遍历顺序无关.
+    let mut order: Vec<(Value, Type)> = slots.iter().map(|(s, t)| (*s, *t)).collect();
+    order.sort_by_key(|(slot, _)| slot.0);
+    let mut phis: Vec<Vec<(Value, Value)>> = vec![vec![]; function.blocks.len()];
+    for (slot, ty) in order {
+        let mut work: Vec<BlockId> = vec![];
+        for (i, block) in function.blocks.iter().enumerate() {
+            let stores = block.insts.iter().any(
+                |inst| matches!(&inst.kind, InstKind::Store(_, Operand::Value(a)) if *a == slot),
+            );
+            if stores {
+                work.push(BlockId(i as u32));
+            }
+        }
+        let mut has_phi = vec![false; function.blocks.len()];
+        while let Some(block) = work.pop() {
+            for &f in &frontiers[block.0 as usize] {
+                if !std::mem::replace(&mut has_phi[f.0 as usize], true) {
+                    let value = function.new_value(ty);
+                    phis[f.0 as usize].push((slot, value));
+                    work.push(f);
+                }
+            }
+        }
+    }
+    for list in &mut phis {
+        list.sort_by_key(|(_, value)| value.0);
+    }
+
+    let mut renamer = Renamer {
+        slots: &slots,
+        phis: &phis,
+        incoming: vec![vec![]; function.blocks.len()],
+        current: slots.keys().map(|&s| (s, vec![])).collect(),
+        replace: HashMap::new(),
+    };
+    renamer.rename(function, &doms, &cfg, BlockId(0));
+    let Renamer {
+        mut incoming,
+        replace,
+        ..
+    } = renamer;
+    for list in &mut incoming {
+        list.sort_by_key(|(pred, _)| pred.0);
+    }
+
+    // 把phi放到块的开头, 删除提升了的alloca.
+    for (i, block) in function.blocks.iter_mut().enumerate() {
+        let mut new_phis = vec![];
+        for (k, &(_, value)) in phis[i].iter().enumerate() {
+            let ty = function.value_types[value.0 as usize];
+            let sources = incoming[i]
+                .iter()
+                .map(|(pred, values)| (*pred, values[k].clone()));
+            new_phis.push(Inst {
+                dest: Some(value),
+                ty,
+                kind: InstKind::Phi(sources.collect()),
+            });
+        }
+        block.insts.retain(|inst| {
+            !matches!(inst.kind, InstKind::Alloca { .. })
+                || !slots.contains_key(&inst.dest.unwrap())
+        });
+        block.insts.splice(0..0, new_phis);
+    }
+    function.replace_values(&replace);
+    function.simplify_phis();
+    function.renumber_values();
+    slots.len()
+}
+
+/* 只作为load和store的地址出现的标量alloca, 以及它的类型. */
+fn promotable(function: &Function) -> HashMap<Value, Type> {
+    let mut slots = HashMap::new();
+    for inst in function.blocks.iter().flat_map(|b| &b.insts) {
+        if let (Some(dest), InstKind::Alloca { elem, len: 1, .. }) = (inst.dest, &inst.kind) {
+            slots.insert(dest, *elem);
+        }
+    }
+    for inst in function.blocks.iter().flat_map(|b| &b.insts) {
+        let escaping: Vec<&Operand> = match &inst.kind {
+            InstKind::Load(_) => vec![],
+            InstKind::Store(value, _) => vec![value],
+            kind => kind.operands(),
+        };
+        for operand in escaping {
+            if let Operand::Value(v) = operand {
+                slots.remove(v);
+            }
+        }
+    }
+    slots
+}
+
+struct Renamer<'a> {
+    slots: &'a HashMap<Value, Type>,
+    phis: &'a [Vec<(Value, Value)>],
+    incoming: Vec<Vec<(BlockId, Vec<Operand>)>>, //每个块的每个前驱传给各phi的值
+    current: HashMap<Value, Vec<Operand>>,       //每个变量的值栈
+    replace: HashMap<Value, Operand>,            //被删除的load -> 它读到的值
+}
+
+impl Renamer<'_> {
+    fn value(&self, slot: Value) -> Operand {
+        match self.current[&slot].last() {
+            Some(value) => value.clone(),
+            None if self.slots[&slot] == Type::F32 => Operand::Float(0.0),
+            None => Operand::Int(0),
+        }
+    }
+
+    fn rename(&mut self, function: &mut Function, doms: &Dominators, cfg: &Cfg, block: BlockId) {
+        // 沿支配树深度优先, 栈中记录离开块时要恢复的值栈长度.
+        let mut stack = vec![(block, false)];
+        let mut saved: Vec<HashMap<Value, usize>> = vec![];
+        while let Some((block, done)) = stack.pop() {
+            if done {
+                let lens = saved.pop().unwrap();
+                for (slot, len) in lens {
+                    self.current.get_mut(&slot).unwrap().truncate(len);
+                }
+                continue;
+            }
+            saved.push(self.current.iter().map(|(s, v)| (*s, v.len())).collect());
+            let b = block.0 as usize;
+            for &(slot, value) in &self.phis[b] {
+                self.current
+                    .get_mut(&slot)
+                    .unwrap()
+                    .push(Operand::Value(value));
+            }
+            let insts = std::mem::take(&mut function.blocks[b].insts);
+            let mut kept = Vec::with_capacity(insts.len());
+            for inst in insts {
+                match &inst.kind {
+                    InstKind::Load(Operand::Value(addr)) if self.slots.contains_key(addr) => {
+                        let value = self.value(*addr);
+                        self.replace.insert(inst.dest.unwrap(), value);
+                    }
+                    InstKind::Store(value, Operand::Value(addr))
+                        if self.slots.contains_key(addr) =>
+                    {
+                        let mut value = value.clone();
+                        resolve(&mut value, &self.replace);
+                        self.current.get_mut(addr).unwrap().push(value);
+                    }
+                    _ => kept.push(inst),
+                }
+            }
+            function.blocks[b].insts = kept;
+            let mut succs: Vec<BlockId> = cfg.successors(block).collect();
+            succs.dedup();
+            for succ in succs {
+                let values = self.phis[succ.0 as usize]
+                    .iter()
+                    .map(|&(slot, _)| self.value(slot))
+                    .collect();
+                self.incoming[succ.0 as usize].push((block, values));
+            }
+            stack.push((block, true));
+            for &child in doms.children(block).iter().rev() {
+                stack.push((child, false));
+            }
+        }
+    }
+}
@@ -1,36 +1,59 @@
-use crate::lexer::Token;
+use crate::diagnostics::{Diagnostic, Fix};
+use crate::lexer::{classify, LangLevel, Token, TokenCategory};
+use crate::span::Span;
 use crate::BasicType;
+use crate::NodeKind;
 use crate::NodeType;
 use crate::Scope;
 use crate::TokenType;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/*
+    每个Node创建时分配一个唯一编号, 后续阶段用它来引用节点, 而不必比较整棵子树.
+    语义分析改写节点时沿用原节点的编号(见visit::node_map).
+*/
+static NEXT_NODE_ID: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NodeId(pub usize);
+
+impl NodeId {
+    fn next() -> Self {
+        NodeId(NEXT_NODE_ID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /* 还没有解析到声明的引用(语法分析的结果, 未定义的名字)使用的编号, 它不属于任何节点. */
+    pub fn unresolved() -> Self {
+        NodeId::next()
+    }
+}
+
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Node {
+    pub id: NodeId,            //id是节点的唯一编号
     pub node_type: NodeType,   //NodeType是Ast的节点类型
     pub basic_type: BasicType, //BasicType是SysY语言的基本类型
-    pub startpos: usize,       //startpos是(该)节点在源代码字符流的起始位置
-    pub endpos: usize,         //endpos是(该)节点在源代码字符流的结束位置
+    pub span: Span,            //span是(该)节点在源代码中的区间
 }
 
 impl Node {
     /*
        Node可执行的"动作列表" (ActionList)
        构造函数(创建一个特定类型的新Node),
-       零值初始化, 定界, 二元操作(因为二元运算符太多,这里统一抽出来).
+       定界, 二元操作(因为二元运算符太多,这里统一抽出来).
     */
     pub fn new(ntype: NodeType) -> Self {
         Node {
+            id: NodeId::next(),
             node_type: ntype,
             basic_type: BasicType::Nil,
-            startpos: 0,
-            endpos: 0,
+            span: Span::default(),
         }
     }
-    fn zero_init() -> Self {
-        Node::new(NodeType::Number(0))
-    }
-    fn bound(mut self, start: usize, end: usize) -> Self {
-        self.startpos = start;
-        self.endpos = end;
+    fn bound(mut self, span: Span) -> Self {
+        self.span = span;
         self
     }
     fn binary_operation(sort: TokenType, lhs: Node, rhs: Node) -> Self {
@@ -38,44 +61,355 @@ impl Node {
     }
 }
 
+/* Node::as_decl的结果: (类型, 名字, 维度, 初始化列表, 作用域) */
+pub type DeclParts<'a> = (
+    &'a BasicType,
+    &'a str,
+    Option<&'a [Node]>,
+    Option<&'a [Node]>,
+    &'a Scope,
+);
+
+/*
+    给分析工具用的访问器: kind()只看节点种类, as_xxx()在节点是对应种类时借出其中的字段,
+    不必为了取一两个字段就写完整的match, 也不必clone子树.
+*/
+impl Node {
+    pub fn kind(&self) -> NodeKind {
+        match &self.node_type {
+            NodeType::Decl(..) => NodeKind::Decl,
+            NodeType::DeclStmt(_) => NodeKind::DeclStmt,
+            NodeType::InitList(_) => NodeKind::InitList,
+            NodeType::Assign(..) => NodeKind::Assign,
+            NodeType::ExprStmt(_) => NodeKind::ExprStmt,
+            NodeType::Access(..) => NodeKind::Access,
+            NodeType::BinOp(..) => NodeKind::BinOp,
+            NodeType::UnaryOp(..) => NodeKind::UnaryOp,
+            NodeType::Cond(..) => NodeKind::Cond,
+            NodeType::Comma(_) => NodeKind::Comma,
+            NodeType::Cast(..) => NodeKind::Cast,
+            NodeType::Func(..) => NodeKind::Func,
+            NodeType::Block(_) => NodeKind::Block,
+            NodeType::Return(_) => NodeKind::Return,
+            NodeType::Call(..) => NodeKind::Call,
+            NodeType::If(..) => NodeKind::If,
+            NodeType::While(..) => NodeKind::While,
+            NodeType::Switch(..) => NodeKind::Switch,
+            NodeType::Case(..) => NodeKind::Case,
+            NodeType::Continue => NodeKind::Continue,
+            NodeType::Break => NodeKind::Break,
+            NodeType::Empty => NodeKind::Empty,
+            NodeType::Nil => NodeKind::Nil,
+            NodeType::Number(_) => NodeKind::Number,
+            NodeType::FloatNumber(_) => NodeKind::FloatNumber,
+            NodeType::Str(_) => NodeKind::Str,
+        }
+    }
+
+    pub fn as_decl(&self) -> Option<DeclParts<'_>> {
+        match &self.node_type {
+            NodeType::Decl(ty, name, dims, inits, scope) => {
+                Some((ty, name, dims.as_deref(), inits.as_deref(), scope))
+            }
+            _ => None,
+        }
+    }
+
+    /* (变量名, 下标, 语义分析后指向的声明的编号) */
+    pub fn as_access(&self) -> Option<(&str, Option<&[Node]>, NodeId)> {
+        match &self.node_type {
+            NodeType::Access(name, indexes, decl) => Some((name, indexes.as_deref(), *decl)),
+            _ => None,
+        }
+    }
+
+    /* (变量名, 下标, 右值, 语义分析后指向的声明的编号) */
+    pub fn as_assign(&self) -> Option<(&str, Option<&[Node]>, &Node, NodeId)> {
+        match &self.node_type {
+            NodeType::Assign(name, indexes, expr, decl) => {
+                Some((name, indexes.as_deref(), expr, *decl))
+            }
+            _ => None,
+        }
+    }
+
+    /* (运算符, 左操作数, 右操作数) */
+    pub fn as_binop(&self) -> Option<(&TokenType, &Node, &Node)> {
+        match &self.node_type {
+            NodeType::BinOp(op, lhs, rhs) => Some((op, lhs, rhs)),
+            _ => None,
+        }
+    }
+
+    /* (运算符, 操作数) */
+    pub fn as_unary(&self) -> Option<(&TokenType, &Node)> {
+        match &self.node_type {
+            NodeType::UnaryOp(op, operand) => Some((op, operand)),
+            _ => None,
+        }
+    }
+
+    /* (函数名, 实参, 语义分析后指向的被调函数的编号) */
+    pub fn as_call(&self) -> Option<(&str, &[Node], NodeId)> {
+        match &self.node_type {
+            NodeType::Call(name, args, callee) => Some((name, args, *callee)),
+            _ => None,
+        }
+    }
+
+    /* (返回类型, 函数名, 形参, 函数体), 函数原型的函数体是Nil. */
+    pub fn as_func(&self) -> Option<(&BasicType, &str, &[Node], &Node)> {
+        match &self.node_type {
+            NodeType::Func(ret, name, params, body) => Some((ret, name, params, body)),
+            _ => None,
+        }
+    }
+
+    pub fn as_number(&self) -> Option<i32> {
+        match self.node_type {
+            NodeType::Number(num) => Some(num),
+            _ => None,
+        }
+    }
+
+    pub fn as_float(&self) -> Option<f32> {
+        match self.node_type {
+            NodeType::FloatNumber(num) => Some(num),
+            _ => None,
+        }
+    }
+}
+
+/* 语法分析的结果: 编译单元中的各个声明和函数. */
+pub type Ast = Vec<Node>;
+
+/*
+    一条语法错误. expected是期望的token(如果能确定), found是实际遇到的token原文,
+    line是出错行从行首到该token结尾的原文, 用于按原来的格式打印.
+    fix是可以直接应用的修改建议, 如在上一个token之后插入缺少的';'.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    pub expected: Option<String>,
+    pub found: String,
+    pub source: String,
+    pub line_no: usize,
+    pub column: usize, //从1开始
+    pub line: String,
+    pub span: Span,
+    pub fix: Option<Fix>,
+}
+
+impl ParseError {
+    /* 转换成共享的Diagnostic, 以便与语义错误一起渲染. */
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let label = match &self.expected {
+            Some(expected) => format!("expected {}, found `{}`", expected, self.found),
+            None => format!("found `{}`", self.found),
+        };
+        let diagnostic = Diagnostic::error(self.message.clone()).with_label(self.span, label);
+        match &self.fix {
+            Some(fix) => {
+                diagnostic.with_fix(fix.span, fix.replacement.clone(), fix.message.clone())
+            }
+            None => diagnostic,
+        }
+    }
+}
+
+/* 按语法分析器一直以来的格式打印. */
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        //step1.告诉你你出错的类型, 这里是语法分析出错, 具体是遇到了不合规的Token
+        writeln!(f, "Parsing error: Error type B found.")?;
+        //step2.告诉你出错的地点:文件名(路径),行号,列号
+        writeln!(f, "  --> {}:{}:{}", self.source, self.line_no, self.column)?;
+        //step3.告诉你出错的具体内容
+        writeln!(f, "   |")?;
+        writeln!(f, "{:3}| {}", self.line_no.to_string(), self.line)?;
+        writeln!(f, "   |{}^ {}", " ".repeat(self.column), self.message)?;
+        writeln!(f, "   |")
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/*
+    语法分析的资源上限. max_depth限制表达式和初始化列表的嵌套层数: 每层括号, 下标, 实参列表,
+    条件表达式的分支, 一元运算符和初始化列表的花括号各算一层. 超出时报告"too deeply nested"并跳过
+    这一层的内容, 嵌套再深的输入也不会耗尽调用栈, 后续各阶段遍历的树的深度也有上限.
+    默认值在调试构建下2MB栈的线程(如测试线程)中也够用.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseLimits {
+    pub max_depth: usize,
+}
+
+impl Default for ParseLimits {
+    fn default() -> Self {
+        ParseLimits { max_depth: 128 }
+    }
+}
+
+/*
+    语法分析的选项: 每个扩展语法单独一个开关, 关闭时遇到它会报告需要打开哪个开关, 但仍然生成节点继续分析.
+    allow_comma同时控制逗号表达式和表达式中的赋值, allow_strings控制作为实参的字符串字面量(putf的格式串),
+    allow_const_params控制const修饰的形参(如int f(const int a[])).
+    From<LangLevel>给出与语言级别一致的预设: Extended打开全部扩展, SysY2022只保留putf需要的字符串.
+    '?', ':'和switch/case/default只有按Extended做词法分析时才会成为token, 见lex_level.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParserOptions {
+    pub allow_ternary: bool,
+    pub allow_switch: bool,
+    pub allow_comma: bool,
+    pub allow_strings: bool,
+    pub allow_const_params: bool,
+    pub limits: ParseLimits,
+}
+
+impl ParserOptions {
+    /* 词法分析应当使用的语言级别: 打开了需要扩展token的语法时为Extended. */
+    pub fn lex_level(&self) -> LangLevel {
+        if self.allow_ternary || self.allow_switch {
+            LangLevel::Extended
+        } else {
+            LangLevel::SysY2022
+        }
+    }
+}
+
+impl From<LangLevel> for ParserOptions {
+    fn from(level: LangLevel) -> Self {
+        let extended = level == LangLevel::Extended;
+        ParserOptions {
+            allow_ternary: extended,
+            allow_switch: extended,
+            allow_comma: extended,
+            allow_strings: true,
+            allow_const_params: extended,
+            limits: ParseLimits::default(),
+        }
+    }
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        LangLevel::default().into()
+    }
+}
+
 pub struct Parser {
-    tokens: Vec<Token>, //用于存放lexer解析后的一个个token
-    current: usize,     //current代表当前处理token的下标
+    tokens: Vec<Token>,      //用于存放lexer解析后的一个个token, 最后一个是Eof
+    current: usize,          //current代表当前处理token的下标
+    errors: Vec<ParseError>, //已发现的语法错误
+    options: ParserOptions,  //哪些扩展语法可以使用, 以及嵌套层数的上限
+    depth: usize,            //当前所在的嵌套层数, 见ParseLimits
 }
 
 impl Parser {
     /*------------------构造函数------------------*/
-    fn new(tokens: Vec<Token>) -> Self {
-        Parser { tokens, current: 0 }
+    fn new(tokens: Vec<Token>, options: ParserOptions) -> Self {
+        Parser {
+            tokens,
+            current: 0,
+            errors: vec![],
+            options,
+            depth: 0,
+        }
     }
 
     /*------------------辅助函数-------------------*/
+    // 越过末尾时一直停在Eof上, 出错后的恢复不会越界.
     fn get_current_token(&self) -> Token {
-        self.tokens[self.current].clone()
+        self.tokens[self.current.min(self.tokens.len() - 1)].clone()
+    }
+
+    fn at_eof(&self) -> bool {
+        self.current >= self.tokens.len() - 1
     }
 
     fn get_startpos(&self) -> usize {
-        self.tokens[self.current].startpos
+        self.tokens[self.current.min(self.tokens.len() - 1)]
+            .span
+            .start
+    }
+
+    /* 当前源文件中[start, end)的区间 */
+    fn span(&self, start: usize, end: usize) -> Span {
+        Span::new(self.tokens[0].span.file_id, start, end)
     }
 
     fn get_endpos(&self) -> usize {
-        self.tokens[self.current - 1].endpos
+        let last = self.current.saturating_sub(1).min(self.tokens.len() - 1);
+        self.tokens[last].span.end
+    }
+
+    /* 记录一个语法错误, expected为期望的token. */
+    fn error_at(&mut self, t: &Token, message: String, expected: Option<String>) {
+        let lstart = *t.line_start;
+        self.errors.push(ParseError {
+            message,
+            expected,
+            found: t.buf[t.span.start..t.span.end].iter().collect(),
+            source: t.source.to_string(),
+            line_no: t.line_no,
+            column: t.span.start - lstart + 1, //列号是从1开始的, 所以最后+1.
+            line: t.buf[lstart..t.span.end].iter().collect(),
+            span: t.span,
+            fix: None,
+        });
+    }
+
+    /* 记录缺少的token: 位置在上一个token(prev)之后, 并建议在那里插入它. */
+    fn error_after(&mut self, prev: &Token, found: &Token, message: String, expected: String) {
+        let lstart = *prev.line_start;
+        let span = self.span(prev.span.end, prev.span.end);
+        self.errors.push(ParseError {
+            message,
+            found: found.buf[found.span.start..found.span.end].iter().collect(),
+            source: prev.source.to_string(),
+            line_no: prev.line_no,
+            column: prev.span.end - lstart + 1,
+            line: prev.buf[lstart..prev.span.end].iter().collect(),
+            span,
+            fix: Some(Fix {
+                span,
+                replacement: expected.trim_matches('\'').to_string(),
+                message: format!("insert {} here", expected),
+            }),
+            expected: Some(expected),
+        });
     }
 
     fn type_judge(&mut self, sort: TokenType) -> bool {
         let t = self.get_current_token();
         if t.sort != sort {
-            return false;
+            false
         } else {
             self.current += 1;
-            return true;
+            true
         }
     }
 
+    /* 列表类循环的条件: 遇到sort时吃掉它并结束, 到达Eof时报告缺少sort并结束. */
+    fn until(&mut self, sort: TokenType) -> bool {
+        if self.type_judge(sort.clone()) {
+            return false;
+        }
+        if self.at_eof() {
+            self.type_check(sort);
+            return false;
+        }
+        true
+    }
+
     fn type_check(&mut self, sort: TokenType) {
         let t = self.get_current_token();
         let mut sign = String::new();
         if t.sort != sort {
+            let expected = format!("{:?}", sort);
             match sort {
                 TokenType::Comma => sign = "','".to_string(),
                 TokenType::Semicolon => sign = "';'".to_string(),
@@ -87,11 +421,128 @@ impl Parser {
                 TokenType::RightParen => sign = "')'".to_string(),
                 _ => {}
             }
-            t.wrong_token(format!("Error type B at this line: missing {:?}", sign));
+            let message = format!("Error type B at this line: missing {:?}", sign);
+            let expected = if sign.is_empty() { expected } else { sign };
+            // 缺少的';'或')'当作已经插入, 不吃掉当前token, 从它开始继续分析.
+            if self.can_insert(&sort, &t) {
+                let prev = self.tokens[self.current - 1].clone();
+                self.error_after(&prev, &t, message, expected);
+                return;
+            }
+            self.error_at(&t, message, Some(expected));
         }
         self.current += 1;
     }
 
+    /*
+        缺少sort时能否假装插入它: 只对';'和')'这样做, 并且当前token不像是写错的sort本身,
+        即它在下一行, 是文件末尾, 或者是能开始下一个语法成分的关键字, 标识符, 花括号(或')'之后的';').
+        同一行中的其他token(如把';'写成了':')仍按原来的方式跳过.
+    */
+    fn can_insert(&self, sort: &TokenType, t: &Token) -> bool {
+        let past_end = self.current == 0 || self.current >= self.tokens.len();
+        if !matches!(sort, TokenType::Semicolon | TokenType::RightParen) || past_end {
+            return false;
+        }
+        let prev = &self.tokens[self.current - 1];
+        t.line_no != prev.line_no
+            || matches!(
+                t.sort,
+                TokenType::Eof | TokenType::LeftBrace | TokenType::RightBrace
+            )
+            || (*sort == TokenType::RightParen && t.sort == TokenType::Semicolon)
+            || matches!(
+                classify(t),
+                TokenCategory::Keyword | TokenCategory::Identifier
+            )
+    }
+
+    /*
+        进入一层嵌套(levels层, 一元运算符连用时一次进入多层). 超出上限时报告错误并返回false,
+        这时调用者不再进入这一层, 而是用skip_group跳过它的内容.
+    */
+    fn enter(&mut self, levels: usize) -> bool {
+        if self.depth + levels > self.options.limits.max_depth {
+            // 报告在刚读过的开括号(或运算符)上.
+            let t = self.tokens[self.current.saturating_sub(1).min(self.tokens.len() - 1)].clone();
+            let message = format!(
+                "Error type B at this line: expression too deeply nested (more than {} levels)",
+                self.options.limits.max_depth
+            );
+            self.error_at(&t, message, None);
+            return false;
+        }
+        self.depth += levels;
+        true
+    }
+
+    fn leave(&mut self, levels: usize) {
+        self.depth -= levels;
+    }
+
+    /* 在t处遇到了扩展语法: 没有打开对应的开关(ParserOptions中名为flag的字段)时报告, 语法分析照常进行. */
+    fn extension(&mut self, t: &Token, allowed: bool, what: &str, flag: &str) {
+        if !allowed {
+            let message = format!(
+                "Error type B at this line: {}, enable ParserOptions::{} to accept them",
+                what, flag
+            );
+            self.error_at(t, message, None);
+        }
+    }
+
+    /* 跳过一组括号中的内容(开括号已经读过), 直到与之匹配的闭括号(读掉)或文件末尾. */
+    fn skip_group(&mut self) {
+        let mut depth = 1;
+        while !self.at_eof() {
+            let t = self.get_current_token();
+            self.current += 1;
+            match t.sort {
+                TokenType::LeftParen | TokenType::LeftBracket | TokenType::LeftBrace => depth += 1,
+                TokenType::RightParen | TokenType::RightBracket | TokenType::RightBrace => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /*
+        跳过条件表达式'?'之后的两个分支(其中的条件表达式成对跳过): 直到同一括号层中属于外层的':',
+        ';', ','或闭括号(都不读掉), 或文件末尾.
+    */
+    fn skip_branches(&mut self) {
+        let mut depth = 0;
+        let mut open = 1; // 还没有遇到':'的'?'个数, 包括已经读过的这一个
+        while !self.at_eof() {
+            match self.get_current_token().sort {
+                TokenType::LeftParen | TokenType::LeftBracket | TokenType::LeftBrace => depth += 1,
+                TokenType::RightParen | TokenType::RightBracket | TokenType::RightBrace
+                    if depth > 0 =>
+                {
+                    depth -= 1
+                }
+                TokenType::Question if depth == 0 => open += 1,
+                TokenType::Colon if depth == 0 && open > 0 => open -= 1,
+                TokenType::RightParen
+                | TokenType::RightBracket
+                | TokenType::RightBrace
+                | TokenType::Semicolon
+                | TokenType::Comma
+                | TokenType::Colon
+                    if depth == 0 =>
+                {
+                    return
+                }
+                _ => {}
+            }
+            self.current += 1;
+        }
+    }
+
     /*------------语法分析:核心函数列表-------------*/
 
     /*-----------------变量类---------------------*/
@@ -102,16 +553,24 @@ impl Parser {
             TokenType::Void => Some(BasicType::Void),
             TokenType::Int => Some(BasicType::Int),
             TokenType::Float => Some(BasicType::Float),
-            TokenType::Const => {
-                self.type_check(TokenType::Int); //读一个Const马上要读一个Int.
-                Some(BasicType::Const)
-            }
+            TokenType::Const => Some(self.const_type()),
             _ => {
-                t.wrong_token("Error type B at this line: invalid type declare".into());
+                let message = "Error type B at this line: invalid type declare".to_string();
+                self.error_at(&t, message, Some("a type name".into()));
                 None
             }
         };
-        result.expect("Typename required")
+        result.unwrap_or(BasicType::Int)
+    }
+
+    /* 读过const之后读元素类型: const int或const float. */
+    fn const_type(&mut self) -> BasicType {
+        if self.type_judge(TokenType::Float) {
+            BasicType::ConstFloat
+        } else {
+            self.type_check(TokenType::Int);
+            BasicType::Const
+        }
     }
 
     fn get_identifier(&mut self) -> String {
@@ -120,8 +579,9 @@ impl Parser {
             self.current += 1;
             name = id.clone();
         } else {
-            self.get_current_token()
-                .wrong_token("Error typbe B at this line: expect function or value name".into());
+            let t = self.get_current_token();
+            let message = "Error typbe B at this line: expect function or value name".to_string();
+            self.error_at(&t, message, Some("an identifier".into()));
             return "".to_string();
         }
         name
@@ -135,14 +595,21 @@ impl Parser {
             let startpos = self.get_startpos();
             if allow_empty {
                 allow_empty = false;
-                while !self.type_judge(TokenType::RightBracket) {
+                while self.until(TokenType::RightBracket) {
                     self.current += 1;
                 }
                 let endpos = self.get_endpos();
-                v.push(Node::new(NodeType::Nil).bound(startpos, endpos));
+                v.push(Node::new(NodeType::Nil).bound(self.span(startpos, endpos)));
                 continue;
             }
-            let len = self.const_exp(false);
+            if !self.enter(1) {
+                self.skip_group();
+                let endpos = self.get_endpos();
+                v.push(Node::new(NodeType::Nil).bound(self.span(startpos, endpos)));
+                continue;
+            }
+            let len = self.cond_exp(false);
+            self.leave(1);
             v.push(len);
             self.type_check(TokenType::RightBracket);
         } //while结束后, v中应该已经有了所有的维度了.
@@ -160,18 +627,18 @@ impl Parser {
         let t = self.get_current_token();
         self.current += 1;
         let basic_type = match t.sort {
-            TokenType::Const => {
-                self.type_check(TokenType::Int);
-                Some(BasicType::Const)
-            }
+            TokenType::Const => Some(self.const_type()),
             TokenType::Int => Some(BasicType::Int),
             TokenType::Float => Some(BasicType::Float),
+            // void变量在语法上按声明读入, 由语义分析报告.
+            TokenType::Void => Some(BasicType::Void),
             _ => {
-                t.wrong_token("Error type B at this line: type define".into());
+                let message = "Error type B at this line: type define".to_string();
+                self.error_at(&t, message, Some("a type name".into()));
                 None
             }
         }
-        .expect("type_check type define");
+        .unwrap_or(BasicType::Int);
 
         /*
            几个声明的例子, 对号入座：
@@ -182,9 +649,15 @@ impl Parser {
         */
         let mut first = true;
         let mut decl_list = vec![]; //声明列表
-        while !self.type_judge(TokenType::Semicolon) {
+        while self.until(TokenType::Semicolon) {
             if first {
                 first = false;
+            } else if self.get_current_token().sort != TokenType::Comma
+                && self.can_insert(&TokenType::Semicolon, &self.get_current_token())
+            {
+                // 声明后面既不是','也不是';', 多半是漏了';': 假装插入它, 声明语句到此结束.
+                self.type_check(TokenType::Semicolon);
+                break;
             } else {
                 // 除了声明的第一个元素,后面都先读逗号.
                 self.type_check(TokenType::Comma);
@@ -197,15 +670,16 @@ impl Parser {
                 //有等于号, 说明要初始化
                 if dims.is_none() {
                     //add.exp()用于初始化单个变量
-                    init = Some(vec![self.add_exp(false)]);
+                    init = Some(vec![self.cond_exp(false)]);
                 } else {
                     //init_val()用于初始化数组
                     init = Some(self.init_list());
                 }
-            } else if basic_type == BasicType::Const {
-                self.get_current_token()
-                    .wrong_token("Error type B at this line: assign in const declaration".into());
-                unreachable!();
+            } else if matches!(basic_type, BasicType::Const | BasicType::ConstFloat) {
+                let t = self.get_current_token();
+                let message = "Error type B at this line: assign in const declaration".to_string();
+                self.error_at(&t, message, Some("'='".into()));
+                init = None;
             } else {
                 init = None;
             }
@@ -219,25 +693,40 @@ impl Parser {
                     init,
                     scope.clone(),
                 ))
-                .bound(startpos, endpos),
+                .bound(self.span(startpos, endpos)),
             );
         }
         let endpos = self.get_endpos();
         //声明语句
-        Node::new(NodeType::DeclStmt(decl_list)).bound(startpos, endpos)
+        Node::new(NodeType::DeclStmt(decl_list)).bound(self.span(startpos, endpos))
     }
 
+    /*
+        init_list: 初始化列表, 用于初始化数组.
+        一个数组：int a[5] = {1, 2, 3, 4, 5};
+        二维数组：int a[5][5] = { {1, 2, 3, 4, 5}, {1, 2, 3, 4, 5} };
+        嵌套的花括号用显式的栈处理而不递归: 栈中每一项是一层尚未结束的列表(已读到的元素, 是否还没有元素, 起始位置).
+    */
     fn init_list(&mut self) -> Vec<Node> {
-        // init_val:初始化列表,用于初始化数组.
-        // 一个数组：int a[5] = {1, 2, 3, 4, 5};
-        // 二维数组：int a[5][5] = { {1, 2, 3, 4, 5}, {1, 2, 3, 4, 5} };
-        let mut init = vec![];
-        let mut first = true;
         self.type_check(TokenType::LeftBrace); // 左大括号
-        while !self.type_judge(TokenType::RightBrace) {
+        let mut stack: Vec<(Vec<Node>, bool, usize)> = vec![(vec![], true, 0)];
+        loop {
+            if !self.until(TokenType::RightBrace) {
+                // 当前这一层结束: 最外层就是结果, 内层作为一个InitList元素放入上一层.
+                let (init, _, startpos) = stack.pop().unwrap();
+                let Some(parent) = stack.last_mut() else {
+                    return init;
+                };
+                let endpos = self.get_endpos();
+                let n = Node::new(NodeType::InitList(init));
+                parent.0.push(n.bound(self.span(startpos, endpos)));
+                self.leave(1);
+                continue;
+            }
             // 首元素(元素0), 然后,ele1 ,ele2 ,ele3 ...
-            if first {
-                first = false;
+            let first = &mut stack.last_mut().unwrap().1;
+            if *first {
+                *first = false;
             } else {
                 self.type_check(TokenType::Comma);
             }
@@ -245,20 +734,31 @@ impl Parser {
             let startpos = self.get_startpos();
             match self.get_current_token().sort {
                 TokenType::LeftBrace => {
-                    let n = Node::new(NodeType::InitList(self.init_list()));
-                    let endpos = self.get_endpos();
-                    init.push(n.bound(startpos, endpos));
+                    self.current += 1;
+                    if self.enter(1) {
+                        stack.push((vec![], true, startpos));
+                    } else {
+                        self.skip_group();
+                    }
                 }
-                TokenType::Identifier(_) | TokenType::IntNumber(_) | TokenType::LeftParen => {
-                    init.push(self.add_exp(false));
+                TokenType::Identifier(_)
+                | TokenType::IntNumber(_)
+                | TokenType::FloatNumber(_)
+                | TokenType::LeftParen
+                | TokenType::Plus
+                | TokenType::Minus
+                | TokenType::Not => {
+                    let exp = self.cond_exp(false);
+                    stack.last_mut().unwrap().0.push(exp);
                 }
                 _ => {
-                    self.get_current_token()
-                        .wrong_token("Error type B at this line : expession or initlist".into());
+                    let t = self.get_current_token();
+                    let message = "Error type B at this line : expession or initlist".to_string();
+                    self.error_at(&t, message, Some("an expression or '{'".into()));
+                    self.current += 1;
                 }
             }
         }
-        init
     }
 
     fn stmt(&mut self) -> Node {
@@ -271,30 +771,30 @@ impl Parser {
                 let index = self.seek_array(false);
                 // Token是标识符, 后面还跟着一个=号, 一眼赋值语句。
                 if self.type_judge(TokenType::Assign) {
-                    let exp = self.add_exp(false);
+                    let exp = self.cond_exp(false);
                     self.type_check(TokenType::Semicolon);
                     let endpos = self.get_endpos();
                     Node::new(NodeType::Assign(
                         id,
                         index,
                         Box::new(exp),
-                        Box::new(Node::zero_init()),
+                        NodeId::unresolved(),
                     ))
-                    .bound(startpos, endpos)
+                    .bound(self.span(startpos, endpos))
                 } else {
                     // 否则是"表达式语句"(表达式后面跟着一个分号)
                     self.current = pos - 1;
-                    let exp = self.add_exp(false);
+                    let exp = self.cond_exp(false);
                     self.type_check(TokenType::Semicolon);
                     let endpos = self.get_endpos();
-                    Node::new(NodeType::ExprStmt(Box::new(exp))).bound(startpos, endpos)
+                    Node::new(NodeType::ExprStmt(Box::new(exp))).bound(self.span(startpos, endpos))
                 }
             }
             TokenType::Int | TokenType::Const => {
                 self.current -= 1;
                 self.decl_stmt(Scope::Local)
             }
-            TokenType::Float => {
+            TokenType::Float | TokenType::Void => {
                 self.current -= 1;
                 self.decl_stmt(Scope::Local)
             }
@@ -303,19 +803,18 @@ impl Parser {
                 self.block()
             }
             TokenType::If => {
-                let on_false: Option<Box<Node>>;
                 self.type_check(TokenType::LeftParen);
                 let cond = self.l_or_exp();
                 self.type_check(TokenType::RightParen);
                 let on_true = self.stmt();
-                if self.type_judge(TokenType::Else) {
-                    on_false = Some(Box::new(self.stmt()));
+                let on_false = if self.type_judge(TokenType::Else) {
+                    Some(Box::new(self.stmt()))
                 } else {
-                    on_false = None;
-                }
+                    None
+                };
                 let endpos = self.get_endpos();
                 Node::new(NodeType::If(Box::new(cond), Box::new(on_true), on_false))
-                    .bound(startpos, endpos)
+                    .bound(self.span(startpos, endpos))
             }
             TokenType::While => {
                 self.type_check(TokenType::LeftParen);
@@ -323,80 +822,153 @@ impl Parser {
                 self.type_check(TokenType::RightParen);
                 let body = self.stmt();
                 let endpos = self.get_endpos();
-                Node::new(NodeType::While(Box::new(cond), Box::new(body))).bound(startpos, endpos)
+                Node::new(NodeType::While(Box::new(cond), Box::new(body)))
+                    .bound(self.span(startpos, endpos))
+            }
+            TokenType::Switch => {
+                let allowed = self.options.allow_switch;
+                self.extension(
+                    &t,
+                    allowed,
+                    "switch statements are not part of SysY",
+                    "allow_switch",
+                );
+                self.type_check(TokenType::LeftParen);
+                let scrutinee = self.add_exp(false);
+                self.type_check(TokenType::RightParen);
+                self.type_check(TokenType::LeftBrace);
+                let mut arms = vec![];
+                while self.until(TokenType::RightBrace) {
+                    arms.push(self.case_arm());
+                }
+                let endpos = self.get_endpos();
+                Node::new(NodeType::Switch(Box::new(scrutinee), arms))
+                    .bound(self.span(startpos, endpos))
             }
             TokenType::Break => {
                 self.type_check(TokenType::Semicolon);
                 let endpos = self.get_endpos();
-                Node::new(NodeType::Break).bound(startpos, endpos)
+                Node::new(NodeType::Break).bound(self.span(startpos, endpos))
             }
             TokenType::Continue => {
                 self.type_check(TokenType::Semicolon);
                 let endpos = self.get_endpos();
-                Node::new(NodeType::Continue).bound(startpos, endpos)
+                Node::new(NodeType::Continue).bound(self.span(startpos, endpos))
+            }
+            TokenType::Semicolon => {
+                let endpos = self.get_endpos();
+                Node::new(NodeType::Empty).bound(self.span(startpos, endpos))
             }
             TokenType::Return => {
                 let ret: Option<Box<Node>>;
                 if self.type_judge(TokenType::Semicolon) {
                     ret = None;
                 } else {
-                    ret = Some(Box::new(self.add_exp(false)));
+                    ret = Some(Box::new(self.cond_exp(false)));
                     self.type_check(TokenType::Semicolon);
                 }
                 let endpos = self.get_endpos();
-                Node::new(NodeType::Return(ret)).bound(startpos, endpos)
+                Node::new(NodeType::Return(ret)).bound(self.span(startpos, endpos))
             }
             _ => {
-                let exp = self.add_exp(false);
+                // 表达式语句, 第一个token也属于表达式.
+                self.current -= 1;
+                let exp = self.cond_exp(false);
                 self.type_check(TokenType::Semicolon);
                 let endpos = self.get_endpos();
-                Node::new(NodeType::ExprStmt(Box::new(exp))).bound(startpos, endpos)
+                Node::new(NodeType::ExprStmt(Box::new(exp))).bound(self.span(startpos, endpos))
             }
         }
     }
 
+    /* switch中的一个分支: case const_exp: stmt* 或 default: stmt*, 语句一直读到下一个case/default或'}'. */
+    fn case_arm(&mut self) -> Node {
+        let startpos = self.get_startpos();
+        let label = if self.type_judge(TokenType::Case) {
+            Some(Box::new(self.cond_exp(false)))
+        } else if self.type_judge(TokenType::Default) {
+            None
+        } else {
+            let t = self.get_current_token();
+            let message = "Error type B at this line: expect `case` or `default`".to_string();
+            self.error_at(&t, message, Some("`case` or `default`".into()));
+            self.stmt();
+            return Node::new(NodeType::Case(None, vec![]))
+                .bound(self.span(startpos, self.get_endpos()));
+        };
+        self.type_check(TokenType::Colon);
+        let mut stmts = vec![];
+        while !matches!(
+            self.get_current_token().sort,
+            TokenType::Case | TokenType::Default | TokenType::RightBrace | TokenType::Eof
+        ) {
+            stmts.push(self.stmt());
+        }
+        let endpos = self.get_endpos();
+        Node::new(NodeType::Case(label, stmts)).bound(self.span(startpos, endpos))
+    }
+
     /*---------------函数类-----------------------*/
 
     fn param(&mut self) -> Node {
         let startpos = self.get_startpos();
-        self.type_check(TokenType::Int);
+        // 形参只能是int或float(及其数组), 扩展语法中可以有const修饰: 函数中不能修改它.
+        let t = self.get_current_token();
+        let is_const = self.type_judge(TokenType::Const);
+        if is_const {
+            let allowed = self.options.allow_const_params;
+            self.extension(
+                &t,
+                allowed,
+                "const parameters are not part of SysY",
+                "allow_const_params",
+            );
+        }
+        let is_float = self.type_judge(TokenType::Float);
+        if !is_float {
+            self.type_check(TokenType::Int);
+        }
         let name = self.get_identifier();
         let dim = self.seek_array(true);
-        let basic_type: BasicType;
-        if dim.is_none() {
-            basic_type = BasicType::Int;
-        } else {
-            basic_type = BasicType::IntArray(vec![0]);
-        }
+        // 与变量声明相同, 数组形参只记录元素类型, 各维(包括省略的第一维)由语义分析求值.
+        let basic_type = match (is_float, is_const) {
+            (false, false) => BasicType::Int,
+            (false, true) => BasicType::Const,
+            (true, false) => BasicType::Float,
+            (true, true) => BasicType::ConstFloat,
+        };
         let endpos = self.get_endpos();
         Node::new(NodeType::Decl(basic_type, name, dim, None, Scope::Params))
-            .bound(startpos, endpos)
+            .bound(self.span(startpos, endpos))
     }
 
     fn block(&mut self) -> Node {
         let startpos = self.get_startpos();
         let mut stmts = vec![];
         self.type_check(TokenType::LeftBrace);
-        while !self.type_judge(TokenType::RightBrace) {
+        while self.until(TokenType::RightBrace) {
             stmts.push(self.stmt());
         }
         let endpos = self.get_endpos();
-        Node::new(NodeType::Block(stmts)).bound(startpos, endpos)
+        Node::new(NodeType::Block(stmts)).bound(self.span(startpos, endpos))
     }
 
     /*-----------------表达式类----------------- */
 
     fn primary_exp(&mut self, cond: bool) -> Node {
         let t = self.get_current_token();
-        let startpos = t.startpos;
+        let startpos = t.span.start;
         self.current += 1;
 
         let result = match &t.sort {
             TokenType::LeftParen => {
-                let exp = self.const_exp(cond);
-                if self.type_judge(TokenType::RightParen) {
+                if self.enter(1) {
+                    let exp = self.comma_exp(cond);
+                    self.leave(1);
+                    self.type_check(TokenType::RightParen);
                     Some(exp)
                 } else {
+                    self.skip_group();
                     None
                 }
             }
@@ -405,244 +977,282 @@ impl Parser {
             TokenType::Identifier(id) => {
                 if self.type_judge(TokenType::LeftParen) {
                     let mut args = vec![];
-                    if !self.type_judge(TokenType::RightParen) {
-                        args.push(self.const_exp(cond));
+                    if !self.enter(1) {
+                        self.skip_group();
+                    } else if !self.type_judge(TokenType::RightParen) {
+                        args.push(self.call_arg(cond));
                         while self.type_judge(TokenType::Comma) {
-                            args.push(self.const_exp(cond));
-                        }
-                        if self.type_judge(TokenType::RightParen) {
-                            Some(Node::new(NodeType::Call(
-                                id.clone(),
-                                args,
-                                Box::new(Node::zero_init()),
-                            )))
-                        } else {
-                            None
+                            args.push(self.call_arg(cond));
                         }
+                        self.type_check(TokenType::RightParen);
+                        self.leave(1);
                     } else {
-                        Some(Node::new(NodeType::Call(
-                            id.clone(),
-                            args,
-                            Box::new(Node::zero_init()),
-                        )))
+                        self.leave(1);
                     }
+                    Some(Node::new(NodeType::Call(
+                        id.clone(),
+                        args,
+                        NodeId::unresolved(),
+                    )))
                 } else {
                     Some(Node::new(NodeType::Access(
                         id.to_string(),
                         self.seek_array(false),
-                        Box::new(Node::zero_init()),
+                        NodeId::unresolved(),
                     )))
                 }
             }
             _ => {
-                t.wrong_token("Error type B at this line : Expression cannot resolved!".into());
+                let message = "Error type B at this line : Expression cannot resolved!".to_string();
+                self.error_at(&t, message, Some("an expression".into()));
+                // 表达式之后的符号(如"x + ;"中的';')留给外层, 不再因为它被吃掉而报告第二个错误.
+                if matches!(
+                    t.sort,
+                    TokenType::Semicolon
+                        | TokenType::Comma
+                        | TokenType::RightParen
+                        | TokenType::RightBracket
+                        | TokenType::RightBrace
+                        | TokenType::Eof
+                ) {
+                    self.current -= 1;
+                    return Node::new(NodeType::Nil).bound(self.span(startpos, startpos));
+                }
                 None
             }
         };
 
         let endpos = self.get_endpos();
         match result {
-            Some(node) => node.bound(startpos, endpos),
-            None => Node::zero_init().bound(startpos, endpos),
+            Some(node) => node.bound(self.span(startpos, endpos)),
+            None => Node::new(NodeType::Nil).bound(self.span(startpos, endpos)),
         }
     }
 
-    /* Unary expessions:一元表达式 */
-    // 明确一点, SysY语言的单目运算符(作用于单独一个变量的运算符)有+,-,!
-    // 其中, +a代表自增1, -a代表自减1, !a代表取反(只能在条件表达式中使用).
-    fn unary_exp(&mut self, cond: bool) -> Node {
-        /* params: cond代表是否是条件表达式 */
-        let startpos = self.get_startpos();
-        loop {
-            if self.type_judge(TokenType::Plus) {
-                // 自增
-                continue;
-            } else if self.type_judge(TokenType::Minus) {
-                // 自减
-                let mut rhs = Node::binary_operation(
-                    TokenType::Minus,
-                    Node::zero_init(),
-                    self.primary_exp(cond),
-                );
-                let endpos = self.get_endpos();
-                rhs = rhs.bound(startpos, endpos);
-                return rhs;
-            } else if cond && self.type_judge(TokenType::Not) {
-                // 取反
-                let mut rhs = Node::binary_operation(
-                    TokenType::Equal,
-                    self.primary_exp(cond),
-                    Node::zero_init(),
-                );
-                let endpos = self.get_endpos();
-                rhs = rhs.bound(startpos, endpos);
-                return rhs;
-            } else {
-                break;
-            }
+    /* 函数调用的实参: 表达式, 或者字符串字面量(如putf("%d\n", x)的格式参数, 是否合法由语义分析检查). */
+    fn call_arg(&mut self, cond: bool) -> Node {
+        let t = self.get_current_token();
+        if let TokenType::StrLiteral(value) = &t.sort {
+            let allowed = self.options.allow_strings;
+            self.extension(
+                &t,
+                allowed,
+                "string literal arguments are disabled",
+                "allow_strings",
+            );
+            self.current += 1;
+            return Node::new(NodeType::Str(value.clone())).bound(t.span);
         }
-
-        self.primary_exp(cond)
+        self.cond_exp(cond)
     }
 
-    /* mul_exp:乘除模表达式
-     *    - mul_exp * mul_exp
-     *    - mul_exp / mul_exp
-     *    - mul_exp % mul_exp */
-    fn mul_exp(&mut self, cond: bool) -> Node {
+    /*
+        括号中的表达式, 扩展模式下可以是逗号表达式:
+         *    - operand , operand , ...
+         *    - cond_exp
+        操作数还可以是赋值(a[i] = exp), 这时即使只有一个操作数也生成Comma节点, 使赋值总是以Comma的操作数出现.
+        严格模式下两者都报告专门的错误, 但仍然生成节点继续分析.
+    */
+    fn comma_exp(&mut self, cond: bool) -> Node {
         let startpos = self.get_startpos();
-        let mut lhs = self.unary_exp(cond);
-
-        /* 循环处理乘除模表达式 */
-        loop {
-            if self.type_judge(TokenType::Multi) {
-                // 乘
-                lhs = Node::binary_operation(TokenType::Multi, lhs, self.unary_exp(cond));
-                let endpos = self.get_endpos();
-                lhs = lhs.bound(startpos, endpos);
-            } else if self.type_judge(TokenType::Divide) {
-                // 除
-                lhs = Node::binary_operation(TokenType::Divide, lhs, self.unary_exp(cond));
-                let endpos = self.get_endpos();
-                lhs = lhs.bound(startpos, endpos);
-            } else if self.type_judge(TokenType::Mods) {
-                // 模
-                lhs = Node::binary_operation(TokenType::Mods, lhs, self.unary_exp(cond));
-                let endpos = self.get_endpos();
-                lhs = lhs.bound(startpos, endpos);
-            } else {
-                return lhs;
-            }
+        let first = self.get_current_token();
+        let mut operands = vec![self.comma_operand(cond)];
+        let mut first_comma = None;
+        while self.get_current_token().sort == TokenType::Comma {
+            first_comma.get_or_insert(self.get_current_token());
+            self.current += 1;
+            operands.push(self.comma_operand(cond));
+        }
+        if first_comma.is_none() && operands[0].kind() != NodeKind::Assign {
+            return operands.pop().unwrap();
         }
+        let (t, what) = match first_comma {
+            Some(t) => (t, "comma expressions are not part of SysY"),
+            None => (first, "assignments inside expressions are not part of SysY"),
+        };
+        let allowed = self.options.allow_comma;
+        self.extension(&t, allowed, what, "allow_comma");
+        let endpos = self.get_endpos();
+        Node::new(NodeType::Comma(operands)).bound(self.span(startpos, endpos))
     }
 
-    /* add_exp:加减表达式
-     *    - add_exp + add_exp
-     *    - add_exp - add_exp
-     *    - mul_exp */
-    fn add_exp(&mut self, cond: bool) -> Node {
+    fn comma_operand(&mut self, cond: bool) -> Node {
+        if !self.assign_ahead() {
+            return self.cond_exp(cond);
+        }
         let startpos = self.get_startpos();
-        let mut lhs = self.mul_exp(cond);
+        let name = self.get_identifier();
+        let index = self.seek_array(false);
+        self.type_check(TokenType::Assign);
+        let exp = self.cond_exp(false);
+        let endpos = self.get_endpos();
+        Node::new(NodeType::Assign(
+            name,
+            index,
+            Box::new(exp),
+            NodeId::unresolved(),
+        ))
+        .bound(self.span(startpos, endpos))
+    }
 
-        loop {
-            if self.type_judge(TokenType::Plus) {
-                //加法
-                lhs = Node::binary_operation(TokenType::Plus, lhs, self.mul_exp(cond));
-                let endpos = self.get_endpos();
-                lhs = lhs.bound(startpos, endpos);
-            } else if self.type_judge(TokenType::Minus) {
-                //减法
-                lhs = Node::binary_operation(TokenType::Minus, lhs, self.mul_exp(cond));
-                let endpos = self.get_endpos();
-                lhs = lhs.bound(startpos, endpos);
-            } else {
-                //mul_exp， 直接返回lhs.
-                return lhs;
+    /* 从当前token向后看是否是赋值: 标识符, 若干[...], 然后是'='. */
+    fn assign_ahead(&self) -> bool {
+        let mut tokens = self.tokens.iter().skip(self.current);
+        if !matches!(
+            tokens.next().map(|t| &t.sort),
+            Some(TokenType::Identifier(_))
+        ) {
+            return false;
+        }
+        let mut depth = 0;
+        for t in tokens {
+            match t.sort {
+                TokenType::LeftBracket => depth += 1,
+                TokenType::RightBracket if depth > 0 => depth -= 1,
+                TokenType::Assign if depth == 0 => return true,
+                _ if depth == 0 => return false,
+                _ => {}
             }
         }
+        false
     }
 
-    /* const_exp:常量表达式 */
-    fn const_exp(&mut self, cond: bool) -> Node {
-        self.add_exp(cond)
-    }
-
-    /* rel_exp:关系表达式
-     *    - rel_exp < rel_exp
-     *    - rel_exp > rel_exp
-     *    - rel_exp <= rel_exp
-     *    - rel_exp >= rel_exp
-     *    - add_exp */
-    fn rel_exp(&mut self) -> Node {
-        let startpos = self.get_startpos();
-        let mut lhs = self.add_exp(true);
+    /* Unary expessions:一元表达式 */
+    // 明确一点, SysY语言的单目运算符(作用于单独一个变量的运算符)有+,-,!
+    // 其中, +a是正号, -a是取负, !a代表逻辑取反(SysY规定只在条件中出现, 这里到处都接受).
+    // 单目运算符可以连用(如--x, !!x), 每个运算符都生成一个UnaryOp节点.
+    // 连用的运算符先依次读出, 解析完操作数后再从里向外组合, 不需要递归; 每个运算符算一层嵌套.
+    fn unary_exp(&mut self, cond: bool) -> Node {
+        /* params: cond代表是否是条件表达式 */
+        let mut ops = vec![];
         loop {
-            if self.type_judge(TokenType::Lesserthan) {
-                // <
-                lhs = Node::binary_operation(TokenType::Lesserthan, lhs, self.add_exp(true));
-                let endpos = self.get_endpos();
-                lhs = lhs.bound(startpos, endpos);
-            } else if self.type_judge(TokenType::Greaterthan) {
-                // >
-                lhs = Node::binary_operation(TokenType::Greaterthan, lhs, self.add_exp(true));
-                let endpos = self.get_endpos();
-                lhs = lhs.bound(startpos, endpos);
-            } else if self.type_judge(TokenType::LessEqual) {
-                // <=
-                lhs = Node::binary_operation(TokenType::LessEqual, lhs, self.add_exp(true));
-                let endpos = self.get_endpos();
-                lhs = lhs.bound(startpos, endpos);
-            } else if self.type_judge(TokenType::GreatEqual) {
-                // >=
-                lhs = Node::binary_operation(TokenType::GreatEqual, lhs, self.add_exp(true));
-                let endpos = self.get_endpos();
-                lhs = lhs.bound(startpos, endpos);
-            } else {
-                return lhs;
+            let startpos = self.get_startpos();
+            let op = self.get_current_token().sort;
+            // '!'在任何表达式中都能解析, 由语义分析检查操作数的类型.
+            if !matches!(op, TokenType::Plus | TokenType::Minus | TokenType::Not) {
+                break;
             }
+            self.current += 1;
+            ops.push((op, startpos));
+        }
+        if ops.is_empty() {
+            return self.primary_exp(cond);
+        }
+        let levels = ops.len();
+        if !self.enter(levels) {
+            // 超出嵌套上限: 已经报告了错误, 丢掉这些运算符, 只保留操作数.
+            return self.primary_exp(cond);
         }
+        let mut node = self.primary_exp(cond);
+        self.leave(levels);
+        let endpos = self.get_endpos();
+        for (op, startpos) in ops.into_iter().rev() {
+            node =
+                Node::new(NodeType::UnaryOp(op, Box::new(node))).bound(self.span(startpos, endpos));
+        }
+        node
     }
 
-    /* eq_exp:相等性表达式
-     *    - rel_exp
-     *    - eq_exp == rel_exp
-     *    - eq_exp != rel_exp */
-    fn eq_exp(&mut self) -> Node {
+    /*
+        二元表达式(Pratt分析): 先解析一个一元表达式, 然后只要下一个token是优先级不低于min_prec的
+        二元运算符, 就以更高一级的优先级解析右操作数(因此都是左结合), 组合成BinOp.
+        优先级见BINARY_OPERATORS, 对应SysY文法中的mul_exp, add_exp, rel_exp, eq_exp, l_and_exp, l_or_exp.
+    */
+    fn binary_exp(&mut self, min_prec: u8, cond: bool) -> Node {
         let startpos = self.get_startpos();
-        let mut lhs = self.rel_exp();
+        let mut lhs = self.unary_exp(cond);
         loop {
-            if self.type_judge(TokenType::Equal) {
-                lhs = Node::binary_operation(TokenType::Equal, lhs, self.rel_exp());
-                let endpos = self.get_endpos();
-                lhs = lhs.bound(startpos, endpos);
-            } else if self.type_judge(TokenType::NotEqual) {
-                lhs = Node::binary_operation(TokenType::NotEqual, lhs, self.rel_exp());
-                let endpos = self.get_endpos();
-                lhs = lhs.bound(startpos, endpos);
-            } else {
-                return lhs;
-            }
+            let op = self.get_current_token().sort;
+            let prec = match binary_precedence(&op) {
+                Some(prec) if prec >= min_prec => prec,
+                _ => return lhs,
+            };
+            self.current += 1;
+            let rhs = self.binary_exp(prec + 1, cond);
+            let endpos = self.get_endpos();
+            lhs = Node::binary_operation(op, lhs, rhs).bound(self.span(startpos, endpos));
         }
     }
 
-    /* l_and_exp:逻辑与表达式
-     *    - EqExp
-     *    - LAndExp && EqExp
-     * */
-    fn l_and_exp(&mut self) -> Node {
-        let startpos = self.get_startpos();
-        let mut lhs = self.eq_exp();
-        loop {
-            if self.type_judge(TokenType::And) {
-                lhs = Node::binary_operation(TokenType::And, lhs, self.eq_exp());
-                let endpos = self.get_endpos();
-                lhs = lhs.bound(startpos, endpos);
-            } else {
-                return lhs;
-            }
-        }
+    /* add_exp:算术表达式(SysY的Exp), 不含关系和逻辑运算 */
+    fn add_exp(&mut self, cond: bool) -> Node {
+        self.binary_exp(ADD_PREC, cond)
     }
 
-    /** l_or_exp:逻辑或表达式
-     *    - l_and_exp || l_or_exp
-     *    - l_and_exp */
+    /* l_or_exp:条件表达式(SysY的Cond), 包含全部二元运算 */
     fn l_or_exp(&mut self) -> Node {
-        let startpos = self.get_startpos();
-        let mut lhs = self.l_and_exp();
-        loop {
-            if self.type_judge(TokenType::Or) {
-                lhs = Node::binary_operation(TokenType::Or, lhs, self.l_and_exp());
-                let endpos = self.get_endpos();
-                lhs = lhs.bound(startpos, endpos);
+        self.binary_exp(L_OR_PREC, true)
+    }
+
+    /*
+        cond_exp:条件表达式(扩展模式), 优先级低于l_or_exp, 右结合:
+         *    - l_or_exp ? cond_exp : cond_exp
+         *    - add_exp (条件中为l_or_exp)
+        SysY的普通表达式只是add_exp, 因此只有在当前表达式中确实出现'?'时才按l_or_exp解析条件部分.
+        常量表达式(ConstExp)也由这里解析, 是否为常量由语义分析检查.
+    */
+    fn cond_exp(&mut self, cond: bool) -> Node {
+        if !self.ternary_ahead() {
+            return if cond {
+                self.l_or_exp()
             } else {
-                return lhs;
+                self.add_exp(false)
+            };
+        }
+        let startpos = self.get_startpos();
+        let condition = self.l_or_exp();
+        let question = self.get_current_token();
+        let allowed = self.options.allow_ternary;
+        self.extension(
+            &question,
+            allowed,
+            "conditional expressions are not part of SysY",
+            "allow_ternary",
+        );
+        self.type_check(TokenType::Question);
+        if !self.enter(1) {
+            self.skip_branches();
+            return condition;
+        }
+        let on_true = self.cond_exp(cond);
+        self.type_check(TokenType::Colon);
+        let on_false = self.cond_exp(cond);
+        self.leave(1);
+        let endpos = self.get_endpos();
+        Node::new(NodeType::Cond(
+            Box::new(condition),
+            Box::new(on_true),
+            Box::new(on_false),
+        ))
+        .bound(self.span(startpos, endpos))
+    }
+
+    /* 从当前token向后看, 在同一括号层内、当前表达式结束之前是否有'?'. */
+    fn ternary_ahead(&self) -> bool {
+        let mut depth = 0;
+        for t in self.tokens.iter().skip(self.current) {
+            match t.sort {
+                TokenType::LeftParen | TokenType::LeftBracket => depth += 1,
+                TokenType::RightParen | TokenType::RightBracket => {
+                    if depth == 0 {
+                        return false;
+                    }
+                    depth -= 1;
+                }
+                TokenType::Question if depth == 0 => return true,
+                TokenType::Colon | TokenType::Comma if depth == 0 => return false,
+                TokenType::Semicolon | TokenType::LeftBrace | TokenType::RightBrace => {
+                    return false
+                }
+                _ => {}
             }
         }
+        false
     }
 
     /* 处理编译单元, 每处理好一个就返回一个ast中的Node.
      * 处理依据SysY(2022)语言定义:
-     * CompUnit → [ CompUnit ] ( Decl | FuncDef ) */
+     * CompUnit → [ CompUnit ] ( Decl | FuncDef | FuncDecl ), 其中FuncDecl(函数原型)是扩展. */
     fn comp_unit(&mut self) -> Node {
         /* 初始化变量:获取当前token的索引, 起始位置, 基本类型, 变量名 */
         let index = self.current;
@@ -650,7 +1260,7 @@ impl Parser {
         let basic_type = self.get_basic_type();
         let name = self.get_identifier();
 
-        /* 如果当前token是左括号, 说明是函数定义 */
+        /* 如果当前token是左括号, 说明是函数定义或函数原型 */
         if self.type_judge(TokenType::LeftParen) {
             let mut params = vec![];
             if !self.type_judge(TokenType::RightParen) {
@@ -660,10 +1270,15 @@ impl Parser {
                 }
                 self.type_check(TokenType::RightParen);
             }
-            let body = self.block();
+            // 以分号结尾的是函数原型(只有声明没有函数体), 函数体用Nil占位.
+            let body = if self.type_judge(TokenType::Semicolon) {
+                Node::new(NodeType::Nil)
+            } else {
+                self.block()
+            };
             let endpos = self.get_endpos();
             return Node::new(NodeType::Func(basic_type, name, params, Box::new(body)))
-                .bound(startpos, endpos);
+                .bound(self.span(startpos, endpos));
         }
 
         self.current = index;
@@ -671,52 +1286,81 @@ impl Parser {
     }
 }
 
-impl Token {
-    fn wrong_token(&self, expect: String) {
-        let lstart = *self.line_start;
-        //出错的信息是保存在self.buf中的, 根据index可以把它取出来, 当然这里要转换为迭代器再用collect收集.
-        let errline: String = self.buf[*self.line_start..self.endpos].iter().collect();
+/*
+    二元运算符及其优先级, 数字越大结合越紧, 全部左结合.
+    增加新的二元运算符只需要在这里加一项(语义分析中还要为它定义求值和类型规则).
+*/
+const BINARY_OPERATORS: &[(TokenType, u8)] = &[
+    (TokenType::Or, L_OR_PREC),
+    (TokenType::And, 2),
+    (TokenType::Equal, 3),
+    (TokenType::NotEqual, 3),
+    (TokenType::Lesserthan, 4),
+    (TokenType::Greaterthan, 4),
+    (TokenType::LessEqual, 4),
+    (TokenType::GreatEqual, 4),
+    (TokenType::Plus, ADD_PREC),
+    (TokenType::Minus, ADD_PREC),
+    (TokenType::Multi, 6),
+    (TokenType::Divide, 6),
+    (TokenType::Mods, 6),
+];
+pub(crate) const L_OR_PREC: u8 = 1;
+const ADD_PREC: u8 = 5;
 
-        //step1.告诉你你出错的类型, 这里是语法分析出错, 具体是遇到了不合规的Token
-        println!("{}: {}", "Parsing error", "Error type B found.",);
-        //step2.告诉你出错的地点:文件名(路径),行号,列号
-        println!(
-            "  {} {}:{}:{}",
-            "-->",
-            self.source,
-            self.line_no,
-            self.startpos - lstart + 1 //列号是从1开始的, 所以最后+1.
-        );
-        //step3.告诉你出错的具体内容
-        println!("   {}", "|");
-        println!(
-            "{:3}{} {}",
-            self.line_no.to_string(),
-            "|",
-            errline //errline才是错误的具体内容
-        );
-        print!("   {}", "|");
-        for _ in 0..self.startpos - lstart + 1 {
-            print!("{}", ' ');
-        }
-        println!(
-            "{} {}",
-            "^", //^表示在行首,
-            expect
-        );
+pub(crate) fn binary_precedence(sort: &TokenType) -> Option<u8> {
+    BINARY_OPERATORS
+        .iter()
+        .find(|(op, _)| op == sort)
+        .map(|(_, prec)| *prec)
+}
+
+/*----------------对外提供的库函数------------------*/
+/*
+    语法分析: tokens -> Ast. 有语法错误时返回全部错误(按发现的顺序), 不打印也不会panic,
+    由调用者决定如何报告(Display按原来的格式输出, to_diagnostic接入共享的渲染器).
+*/
+pub fn parse(tokens: Vec<Token>) -> Result<Ast, Vec<ParseError>> {
+    parse_with_level(tokens, LangLevel::default())
+}
 
-        println!("   {}", "|");
-        //panic!("Untype_checked token");
+/* 按指定的语言级别做语法分析, 严格模式下扩展的语法(如逗号表达式)是语法错误. */
+pub fn parse_with_level(tokens: Vec<Token>, level: LangLevel) -> Result<Ast, Vec<ParseError>> {
+    parse_with_options(tokens, &level.into())
+}
+
+/* 同parse, 逐项指定允许的扩展语法和嵌套层数等上限. */
+pub fn parse_with_options(
+    tokens: Vec<Token>,
+    options: &ParserOptions,
+) -> Result<Ast, Vec<ParseError>> {
+    let (ast, errors) = parse_recovering(tokens, options);
+    if errors.is_empty() {
+        Ok(ast)
+    } else {
+        Err(errors)
     }
 }
 
-/*----------------对外提供的库函数------------------*/
-pub fn parse(tokens: Vec<Token>) -> Vec<Node> {
+/*
+    出错后仍然返回能够解析出的AST: 每个声明和函数都保留, 无法解析的表达式由占位的Nil节点代替
+    (带有出错区域的位置). 语义分析和编辑器功能可以在其余部分上照常运行.
+*/
+pub fn parse_recovering(tokens: Vec<Token>, options: &ParserOptions) -> (Ast, Vec<ParseError>) {
+    let mut tokens: Vec<Token> = tokens.into_iter().filter(|t| !t.is_trivia()).collect();
+    let Some(last) = tokens.last() else {
+        return (vec![], vec![]);
+    };
+    // 在末尾补一个Eof, 出错恢复时游标停在这里.
+    let mut eof = last.clone();
+    eof.sort = TokenType::Eof;
+    eof.span.start = last.span.end;
+    tokens.push(eof);
+
     let mut ast_nodes = vec![];
-    let len = tokens.len();
-    let mut parser = Parser::new(tokens);
-    while parser.current != len {
+    let mut parser = Parser::new(tokens, *options);
+    while !parser.at_eof() {
         ast_nodes.push(parser.comp_unit());
     }
-    ast_nodes
+    (ast_nodes, parser.errors)
 }
@@ -0,0 +1,285 @@
+use crate::{
+    cfg::{Cfg, Dominators},
+    copyprop, cse,
+    effects::Effects,
+    inline,
+    ir::{Function, Module},
+    loops::{self, LoopForest},
+    mem2reg, peephole, sccp,
+    schedule::{self, LatencyTable},
+    strength,
+};
+
+/*
+    pass管理器: 按顺序运行一串IR上的变换, 并缓存每个函数的控制流图, 支配树和循环.
+    变换改变了函数而又不保持控制流时, 这个函数的缓存作废; inline增删函数, 之后全部作废.
+    函数的副作用在一次运行中只算一次: 变换只会删除副作用, 先前的结果仍然是保守的.
+    -O0不做优化, -O1做不改变函数边界的清理, -O2再加上内联, 全局CSE, 补preheader, 强度削弱和指令调度.
+    指令调度按set_latencies给出的目标机器延迟表进行, 默认是RISC-V的.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pass {
+    Mem2Reg,
+    Inline,
+    Sccp,
+    CopyProp,
+    Cse,
+    LocalCse,
+    LoopSimplify,
+    StrengthReduce,
+    Peephole,
+    Schedule,
+}
+
+impl Pass {
+    pub const ALL: [Pass; 10] = [
+        Pass::Mem2Reg,
+        Pass::Inline,
+        Pass::Sccp,
+        Pass::CopyProp,
+        Pass::Cse,
+        Pass::LocalCse,
+        Pass::LoopSimplify,
+        Pass::StrengthReduce,
+        Pass::Peephole,
+        Pass::Schedule,
+    ];
+
+    /* pass在命令行中的名字, 与单独打开它的选项相同, 如--copy-prop. */
+    pub fn name(self) -> &'static str {
+        match self {
+            Pass::Mem2Reg => "mem2reg",
+            Pass::Inline => "inline",
+            Pass::Sccp => "sccp",
+            Pass::CopyProp => "copy-prop",
+            Pass::Cse => "cse",
+            Pass::LocalCse => "local-cse",
+            Pass::LoopSimplify => "loop-simplify",
+            Pass::StrengthReduce => "strength-reduce",
+            Pass::Peephole => "peephole",
+            Pass::Schedule => "schedule",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| p.name() == name)
+    }
+
+    /* 改变函数后控制流图(包括边的种类)是否不变; 不变时缓存的分析结果仍然可用. */
+    pub fn preserves_cfg(self) -> bool {
+        matches!(
+            self,
+            Pass::CopyProp | Pass::Cse | Pass::LocalCse | Pass::StrengthReduce | Pass::Schedule
+        )
+    }
+
+    /* 单独运行这个pass, 不使用缓存; 返回是否改变了模块. */
+    pub fn run(self, module: &mut Module) -> bool {
+        PassManager::new().add(self).run(module)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum OptLevel {
+    #[default]
+    O0,
+    O1,
+    O2,
+}
+
+impl OptLevel {
+    /* 解析-O后面的部分, 如"2". */
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "0" => Some(OptLevel::O0),
+            "1" => Some(OptLevel::O1),
+            "2" => Some(OptLevel::O2),
+            _ => None,
+        }
+    }
+
+    pub fn pipeline(self) -> &'static [Pass] {
+        match self {
+            OptLevel::O0 => &[],
+            OptLevel::O1 => &[
+                Pass::Mem2Reg,
+                Pass::Sccp,
+                Pass::CopyProp,
+                Pass::LocalCse,
+                Pass::Peephole,
+            ],
+            OptLevel::O2 => &[
+                Pass::Mem2Reg,
+                Pass::Inline,
+                Pass::Sccp,
+                Pass::CopyProp,
+                Pass::Cse,
+                Pass::LoopSimplify,
+                Pass::StrengthReduce,
+                Pass::Peephole,
+                Pass::Schedule,
+            ],
+        }
+    }
+}
+
+/* 一个函数缓存的分析结果, None表示还没有算或已经作废. */
+#[derive(Default)]
+struct Analyses {
+    cfg: Option<Cfg>,
+    doms: Option<Dominators>,
+    loops: Option<LoopForest>,
+}
+
+#[derive(Default)]
+pub struct PassManager {
+    passes: Vec<Pass>,
+    analyses: Vec<Analyses>, //下标与module.functions相同
+    effects: Option<Effects>,
+    latencies: LatencyTable,
+    computed: usize,
+}
+
+impl PassManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn for_level(level: OptLevel) -> Self {
+        let mut manager = Self::new();
+        for &pass in level.pipeline() {
+            manager.add(pass);
+        }
+        manager
+    }
+
+    pub fn add(&mut self, pass: Pass) -> &mut Self {
+        self.passes.push(pass);
+        self
+    }
+
+    pub fn set_latencies(&mut self, latencies: LatencyTable) -> &mut Self {
+        self.latencies = latencies;
+        self
+    }
+
+    pub fn passes(&self) -> &[Pass] {
+        &self.passes
+    }
+
+    /* 到目前为止算过多少次控制流图和支配树(每个函数的一次计算算一次), 用于观察缓存的效果. */
+    pub fn analyses_computed(&self) -> usize {
+        self.computed
+    }
+
+    /* 依次运行所有pass, 返回是否改变了模块. */
+    pub fn run(&mut self, module: &mut Module) -> bool {
+        self.run_with(module, |_, _| {})
+    }
+
+    /* 同run, 每个pass之后调用after, 供--dump-after等调试输出使用. */
+    pub fn run_with(&mut self, module: &mut Module, mut after: impl FnMut(Pass, &Module)) -> bool {
+        // 缓存只在一次运行中有效, 调用者可能在两次运行之间改动了模块.
+        self.analyses.clear();
+        self.effects = None;
+        let mut changed = false;
+        for i in 0..self.passes.len() {
+            let pass = self.passes[i];
+            changed |= self.run_pass(pass, module);
+            after(pass, module);
+        }
+        changed
+    }
+
+    fn run_pass(&mut self, pass: Pass, module: &mut Module) -> bool {
+        match pass {
+            Pass::Inline => {
+                let changed = inline::run_module(module, inline::DEFAULT_BUDGET);
+                if changed {
+                    self.analyses.clear();
+                }
+                return changed;
+            }
+            Pass::Mem2Reg => {
+                // promote先删除不可达的块, 没有提升任何变量时控制流也可能变了.
+                let blocks = |m: &Module| m.functions.iter().map(|f| f.blocks.len()).sum::<usize>();
+                let before = blocks(module);
+                let changed = mem2reg::promote_module(module) > 0 || blocks(module) != before;
+                if changed {
+                    self.analyses.clear();
+                }
+                return changed;
+            }
+            _ => {}
+        }
+        self.analyses
+            .resize_with(module.functions.len(), Analyses::default);
+        let effects = self.effects.get_or_insert_with(|| Effects::compute(module));
+        let mut changed = false;
+        for (i, function) in module.functions.iter_mut().enumerate() {
+            if function.is_declaration() {
+                continue;
+            }
+            let cache = &mut self.analyses[i];
+            let function_changed = match pass {
+                Pass::Sccp => sccp::run(function),
+                Pass::CopyProp => copyprop::run(function),
+                Pass::Peephole => peephole::run_with(function, effects),
+                Pass::LoopSimplify => loops::insert_preheaders(function),
+                Pass::Schedule => schedule::run_with(function, &self.latencies, effects),
+                Pass::LocalCse => {
+                    let (cfg, _) = analyses(cache, function, &mut self.computed);
+                    cse::local_with(function, cfg, effects)
+                }
+                Pass::Cse => {
+                    let (_, doms) = analyses(cache, function, &mut self.computed);
+                    cse::global_with(function, doms, effects)
+                }
+                Pass::StrengthReduce => {
+                    let (doms, loops) = loop_forest(cache, function, &mut self.computed);
+                    strength::run_with(function, doms, loops)
+                }
+                Pass::Inline | Pass::Mem2Reg => unreachable!(),
+            };
+            if function_changed && !pass.preserves_cfg() {
+                *cache = Analyses::default();
+            }
+            changed |= function_changed;
+        }
+        changed
+    }
+}
+
+/* 取出函数的控制流图和支配树, 没有缓存时计算并记录次数. */
+fn analyses<'a>(
+    cache: &'a mut Analyses,
+    function: &Function,
+    computed: &mut usize,
+) -> (&'a Cfg, &'a Dominators) {
+    if cache.cfg.is_none() {
+        cache.cfg = Some(Cfg::build(function));
+        *computed += 1;
+    }
+    let cfg = cache.cfg.as_ref().unwrap();
+    if cache.doms.is_none() {
+        cache.doms = Some(Dominators::compute(cfg));
+        *computed += 1;
+    }
+    (cfg, cache.doms.as_ref().unwrap())
+}
+
+/* 同analyses, 再取出循环森林. */
+fn loop_forest<'a>(
+    cache: &'a mut Analyses,
+    function: &Function,
+    computed: &mut usize,
+) -> (&'a Dominators, &'a LoopForest) {
+    analyses(cache, function, computed);
+    if cache.loops.is_none() {
+        let (cfg, doms) = (cache.cfg.as_ref().unwrap(), cache.doms.as_ref().unwrap());
+        cache.loops = Some(LoopForest::compute(cfg, doms));
+        *computed += 1;
+    }
+    (cache.doms.as_ref().unwrap(), cache.loops.as_ref().unwrap())
+}
@@ -0,0 +1,372 @@
+use crate::{
+    alias::{AliasAnalysis, AliasResult},
+    effects::{Effect, Effects},
+    ir::{resolve, BinOp, CmpOp, Function, Inst, InstKind, Module, Operand, Value},
+};
+use std::collections::HashMap;
+
+/*
+    窥孔优化: 逐条指令按规则表改写, 重复到不再变化. 规则只看一条指令和它的操作数的定义,
+    以及块内已知的内存内容(store之后的load直接使用存入的值). 别名分析决定store和call之后哪些内容仍然可信.
+    添加规则: 代数恒等式加到IDENTITIES, 两个相同操作数的比较加到SELF_COMPARES, 其他形式在RULES中加一个函数.
+*/
+
+/* 操作数的模式: 任意值, 某个整数常量, 或与左操作数相同. */
+#[derive(Clone, Copy)]
+enum Pat {
+    Any,
+    Int(i32),
+    Same,
+}
+
+/* 恒等式的结果: 左操作数, 右操作数, 或整数常量. */
+#[derive(Clone, Copy)]
+enum Out {
+    Lhs,
+    Rhs,
+    Int(i32),
+}
+
+/* 整数运算的代数恒等式: (运算, 左操作数, 右操作数, 结果). 浮点运算因为有-0.0和NaN不参与. */
+const IDENTITIES: &[(BinOp, Pat, Pat, Out)] = &[
+    (BinOp::Add, Pat::Any, Pat::Int(0), Out::Lhs),
+    (BinOp::Add, Pat::Int(0), Pat::Any, Out::Rhs),
+    (BinOp::Sub, Pat::Any, Pat::Int(0), Out::Lhs),
+    (BinOp::Sub, Pat::Any, Pat::Same, Out::Int(0)),
+    (BinOp::Mul, Pat::Any, Pat::Int(1), Out::Lhs),
+    (BinOp::Mul, Pat::Int(1), Pat::Any, Out::Rhs),
+    (BinOp::Mul, Pat::Any, Pat::Int(0), Out::Int(0)),
+    (BinOp::Mul, Pat::Int(0), Pat::Any, Out::Int(0)),
+    (BinOp::Div, Pat::Any, Pat::Int(1), Out::Lhs),
+    (BinOp::Rem, Pat::Any, Pat::Int(1), Out::Int(0)),
+    (BinOp::Shl, Pat::Any, Pat::Int(0), Out::Lhs),
+    (BinOp::Shl, Pat::Int(0), Pat::Any, Out::Int(0)),
+    (BinOp::Shr, Pat::Any, Pat::Int(0), Out::Lhs),
+    (BinOp::Shr, Pat::Int(0), Pat::Any, Out::Int(0)),
+    (BinOp::And, Pat::Any, Pat::Int(0), Out::Int(0)),
+    (BinOp::And, Pat::Int(0), Pat::Any, Out::Int(0)),
+    (BinOp::And, Pat::Any, Pat::Int(-1), Out::Lhs),
+    (BinOp::And, Pat::Any, Pat::Same, Out::Lhs),
+    (BinOp::Or, Pat::Any, Pat::Int(0), Out::Lhs),
+    (BinOp::Or, Pat::Int(0), Pat::Any, Out::Rhs),
+    (BinOp::Or, Pat::Any, Pat::Same, Out::Lhs),
+    (BinOp::Xor, Pat::Any, Pat::Int(0), Out::Lhs),
+    (BinOp::Xor, Pat::Int(0), Pat::Any, Out::Rhs),
+    (BinOp::Xor, Pat::Any, Pat::Same, Out::Int(0)),
+];
+
+/* 两个操作数相同的整数比较的结果. */
+const SELF_COMPARES: &[(CmpOp, i32)] = &[
+    (CmpOp::Eq, 1),
+    (CmpOp::Ne, 0),
+    (CmpOp::Lt, 0),
+    (CmpOp::Le, 1),
+    (CmpOp::Gt, 0),
+    (CmpOp::Ge, 1),
+];
+
+/* 规则的改写结果: 用一个操作数代替指令的结果(删除指令), 换成另一条指令, 或直接删除没有结果的指令. */
+enum Rewrite {
+    Replace(Operand),
+    Kind(InstKind),
+    Remove,
+}
+
+/* 规则能看到的上下文: 函数中值的定义, 当前块中已知的内存内容((地址, 值)). */
+struct Context<'a> {
+    function: &'a Function,
+    defs: HashMap<Value, &'a InstKind>,
+    aliases: AliasAnalysis,
+    memory: Vec<(Operand, Operand)>,
+}
+
+impl Context<'_> {
+    /* 一定与addr是同一个字的地址中已知的值. */
+    fn known(&self, addr: &Operand) -> Option<&Operand> {
+        self.memory
+            .iter()
+            .find(|(a, _)| self.aliases.alias(a, addr) == AliasResult::MustAlias)
+            .map(|(_, v)| v)
+    }
+}
+
+type Rule = fn(&Context, &Inst) -> Option<Rewrite>;
+
+/* 按顺序尝试的规则, 第一个匹配的生效. */
+const RULES: &[(&str, Rule)] = &[
+    ("algebraic-identity", algebraic_identity),
+    ("self-compare", self_compare),
+    ("compare-of-compare", compare_of_compare),
+    ("branch-on-compare", branch_on_compare),
+    ("load-after-store", load_after_store),
+    ("redundant-store", redundant_store),
+];
+
+fn matches(pat: Pat, operand: &Operand, lhs: &Operand) -> bool {
+    match pat {
+        Pat::Any => true,
+        Pat::Int(n) => *operand == Operand::Int(n),
+        Pat::Same => operand == lhs,
+    }
+}
+
+fn algebraic_identity(_: &Context, inst: &Inst) -> Option<Rewrite> {
+    let InstKind::Binary(op, lhs, rhs) = &inst.kind else {
+        return None;
+    };
+    IDENTITIES
+        .iter()
+        .find(|(o, l, r, _)| o == op && matches(*l, lhs, lhs) && matches(*r, rhs, lhs))
+        .map(|(.., out)| {
+            Rewrite::Replace(match out {
+                Out::Lhs => lhs.clone(),
+                Out::Rhs => rhs.clone(),
+                Out::Int(n) => Operand::Int(*n),
+            })
+        })
+}
+
+fn self_compare(_: &Context, inst: &Inst) -> Option<Rewrite> {
+    match &inst.kind {
+        InstKind::Icmp(op, lhs, rhs) if lhs == rhs => SELF_COMPARES
+            .iter()
+            .find(|(o, _)| o == op)
+            .map(|(_, n)| Rewrite::Replace(Operand::Int(*n))),
+        _ => None,
+    }
+}
+
+fn is_compare(context: &Context, operand: &Operand) -> bool {
+    match operand {
+        Operand::Value(v) => matches!(
+            context.defs.get(v),
+            Some(InstKind::Icmp(..) | InstKind::Fcmp(..))
+        ),
+        _ => false,
+    }
+}
+
+fn inverse(op: CmpOp) -> CmpOp {
+    match op {
+        CmpOp::Eq => CmpOp::Ne,
+        CmpOp::Ne => CmpOp::Eq,
+        CmpOp::Lt => CmpOp::Ge,
+        CmpOp::Le => CmpOp::Gt,
+        CmpOp::Gt => CmpOp::Le,
+        CmpOp::Ge => CmpOp::Lt,
+    }
+}
+
+/* 比较的结果只有0和1: c != 0就是c, (a < b) == 0就是a >= b. */
+fn compare_of_compare(context: &Context, inst: &Inst) -> Option<Rewrite> {
+    let InstKind::Icmp(op, c, Operand::Int(0)) = &inst.kind else {
+        return None;
+    };
+    if !is_compare(context, c) {
+        return None;
+    }
+    match op {
+        CmpOp::Ne => Some(Rewrite::Replace(c.clone())),
+        CmpOp::Eq => match context.defs.get(&value(c)?) {
+            Some(InstKind::Icmp(inner, a, b)) => Some(Rewrite::Kind(InstKind::Icmp(
+                inverse(*inner),
+                a.clone(),
+                b.clone(),
+            ))),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/* 分支本身就判断条件是否为0: br (x != 0)就是br x, br (x == 0)交换两个目标. */
+fn branch_on_compare(context: &Context, inst: &Inst) -> Option<Rewrite> {
+    let InstKind::Branch(cond, then, otherwise) = &inst.kind else {
+        return None;
+    };
+    match context.defs.get(&value(cond)?) {
+        Some(InstKind::Icmp(CmpOp::Ne, x, Operand::Int(0))) => Some(Rewrite::Kind(
+            InstKind::Branch(x.clone(), *then, *otherwise),
+        )),
+        Some(InstKind::Icmp(CmpOp::Eq, x, Operand::Int(0))) => Some(Rewrite::Kind(
+            InstKind::Branch(x.clone(), *otherwise, *then),
+        )),
+        _ => None,
+    }
+}
+
+/* 块内store或load过的地址, 之后没有可能改写内存的指令时, 再次load得到同一个值. */
+fn load_after_store(context: &Context, inst: &Inst) -> Option<Rewrite> {
+    let InstKind::Load(addr) = &inst.kind else {
+        return None;
+    };
+    let known = context.known(addr)?;
+    (context.function.operand_type(known) == inst.ty).then(|| Rewrite::Replace(known.clone()))
+}
+
+/* 把地址中已有的值再存回去的store没有作用. */
+fn redundant_store(context: &Context, inst: &Inst) -> Option<Rewrite> {
+    let InstKind::Store(value, addr) = &inst.kind else {
+        return None;
+    };
+    (context.known(addr) == Some(value)).then_some(Rewrite::Remove)
+}
+
+fn value(operand: &Operand) -> Option<Value> {
+    match operand {
+        Operand::Value(v) => Some(*v),
+        _ => None,
+    }
+}
+
+pub fn run_module(module: &mut Module) -> bool {
+    let effects = Effects::compute(module);
+    let mut changed = false;
+    for function in module.functions.iter_mut().filter(|f| !f.is_declaration()) {
+        changed |= run_with(function, &effects);
+    }
+    changed
+}
+
+/* 重复应用规则直到不再变化, 返回是否改变了函数. */
+pub fn run(function: &mut Function) -> bool {
+    run_with(function, &Effects::default())
+}
+
+/* 同run, 结果没有用到的调用在被调函数不写内存也不做输入输出时一并删除. */
+pub fn run_with(function: &mut Function, effects: &Effects) -> bool {
+    let mut changed = false;
+    while sweep(function) {
+        changed = true;
+    }
+    changed |= remove_dead_code(function, effects);
+    if changed {
+        function.renumber_values();
+    }
+    changed
+}
+
+fn sweep(function: &mut Function) -> bool {
+    let mut replace: HashMap<Value, Operand> = HashMap::new();
+    let mut rewrites = vec![];
+    let mut removed = vec![];
+    {
+        let mut context = Context {
+            function,
+            defs: function
+                .blocks
+                .iter()
+                .flat_map(|b| &b.insts)
+                .filter_map(|inst| Some((inst.dest?, &inst.kind)))
+                .collect(),
+            aliases: AliasAnalysis::compute(function),
+            memory: vec![],
+        };
+        for (b, block) in function.blocks.iter().enumerate() {
+            context.memory.clear();
+            for (i, inst) in block.insts.iter().enumerate() {
+                // 先代入本轮已经得到的替换, 让后面的指令看到改写后的操作数.
+                let mut inst = inst.clone();
+                for operand in inst.kind.operands_mut() {
+                    resolve(operand, &replace);
+                }
+                match RULES.iter().find_map(|(_, rule)| rule(&context, &inst)) {
+                    Some(Rewrite::Replace(operand)) => {
+                        replace.insert(inst.dest.unwrap(), operand);
+                        continue;
+                    }
+                    Some(Rewrite::Kind(kind)) => {
+                        inst.kind = kind;
+                        rewrites.push((b, i, inst.kind.clone()));
+                    }
+                    Some(Rewrite::Remove) => {
+                        removed.push((b, i));
+                        continue;
+                    }
+                    None => {}
+                }
+                // 更新已知的内存内容: store之后可能与它重叠的地址, call之后被调函数能访问的地址都不再可信.
+                let aliases = &context.aliases;
+                match &inst.kind {
+                    InstKind::Store(value, addr) => {
+                        context
+                            .memory
+                            .retain(|(a, _)| aliases.alias(a, addr) == AliasResult::NoAlias);
+                        context.memory.push((addr.clone(), value.clone()));
+                    }
+                    InstKind::Load(addr) => {
+                        let dest = Operand::Value(inst.dest.unwrap());
+                        context.memory.push((addr.clone(), dest));
+                    }
+                    InstKind::Call(..) => {
+                        context.memory.retain(|(a, _)| !aliases.visible_to_calls(a))
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    if replace.is_empty() && rewrites.is_empty() && removed.is_empty() {
+        return false;
+    }
+    for (b, i, kind) in rewrites {
+        function.blocks[b].insts[i].kind = kind;
+    }
+    // 从后往前删除, 前面的下标不受影响.
+    for (b, i) in removed.into_iter().rev() {
+        function.blocks[b].insts.remove(i);
+    }
+    for block in &mut function.blocks {
+        block
+            .insts
+            .retain(|inst| !inst.dest.is_some_and(|d| replace.contains_key(&d)));
+    }
+    function.replace_values(&replace);
+    true
+}
+
+/* 删除结果没有被使用的无副作用指令, 直到不再变化. */
+fn remove_dead_code(function: &mut Function, effects: &Effects) -> bool {
+    let mut changed = false;
+    loop {
+        let mut used = vec![false; function.value_types.len()];
+        for inst in function.blocks.iter().flat_map(|b| &b.insts) {
+            for operand in inst.kind.operands() {
+                if let Operand::Value(v) = operand {
+                    used[v.0 as usize] = true;
+                }
+            }
+        }
+        let mut removed = false;
+        for block in &mut function.blocks {
+            block.insts.retain(|inst| {
+                let removable = match &inst.kind {
+                    InstKind::Binary(..)
+                    | InstKind::Icmp(..)
+                    | InstKind::Fcmp(..)
+                    | InstKind::FNeg(_)
+                    | InstKind::IntToFloat(_)
+                    | InstKind::FloatToInt(_)
+                    | InstKind::Load(_)
+                    | InstKind::Gep(..)
+                    | InstKind::Copy(_) => true,
+                    InstKind::Call(name, _) => effects.effect(name) <= Effect::ReadsMemory,
+                    _ => false,
+                };
+                // 没有结果的调用(void函数)只要可以删除就是死代码.
+                let unused = inst
+                    .dest
+                    .map_or(matches!(inst.kind, InstKind::Call(..)), |d| {
+                        !used[d.0 as usize]
+                    });
+                let dead = removable && unused;
+                removed |= dead;
+                !dead
+            });
+        }
+        if !removed {
+            break changed;
+        }
+        changed = true;
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/*
+    可选的预处理阶段, 在词法分析之前执行, 只支持两种指令:
+        1. #include "file.sy"   把文件内容原样插入(路径相对于当前文件), 每个文件只会被包含一次;
+        2. #define NAME value   无参数宏, 之后出现的标识符NAME被替换成value.
+    输出中插入#line N "file"指令, 词法分析器据此把每个token的位置映射回原文件,
+    因此后续所有阶段的报错都指向原始的文件和行号.
+*/
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreprocessError {
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "preprocess error: {}:{}: {}",
+            self.file, self.line, self.message
+        )
+    }
+}
+
+impl std::error::Error for PreprocessError {}
+
+struct Preprocessor {
+    macros: HashMap<String, String>,
+    included: HashSet<PathBuf>, //已经包含过的文件, 用于include-once和防止循环包含
+    output: String,
+    in_block_comment: bool,
+}
+
+impl Preprocessor {
+    fn new() -> Self {
+        Preprocessor {
+            macros: HashMap::new(),
+            included: HashSet::new(),
+            output: String::new(),
+            in_block_comment: false,
+        }
+    }
+
+    fn process_file(&mut self, path: &Path) -> Result<(), PreprocessError> {
+        let name = path.to_string_lossy().to_string();
+        let key = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if !self.included.insert(key) {
+            return Ok(());
+        }
+        let content = std::fs::read_to_string(path).map_err(|e| PreprocessError {
+            file: name.clone(),
+            line: 0,
+            message: e.to_string(),
+        })?;
+        self.output.push_str(&format!("#line 1 \"{}\"\n", name));
+        for (i, line) in content.lines().enumerate() {
+            let line_no = i + 1;
+            let error = |message: String| PreprocessError {
+                file: name.clone(),
+                line: line_no,
+                message,
+            };
+            let trimmed = line.trim_start();
+            if self.in_block_comment || !trimmed.starts_with('#') {
+                let expanded = self.expand(line);
+                self.output.push_str(&expanded);
+                self.output.push('\n');
+                continue;
+            }
+            let directive = trimmed[1..].trim_start();
+            if let Some(rest) = directive.strip_prefix("include") {
+                let file = rest
+                    .trim()
+                    .strip_prefix('"')
+                    .and_then(|r| r.strip_suffix('"'))
+                    .ok_or_else(|| error("expected #include \"file\"".to_string()))?;
+                let target = path.parent().unwrap_or(Path::new("")).join(file);
+                if !target.exists() {
+                    return Err(error(format!("included file `{}` not found", file)));
+                }
+                self.process_file(&target)?;
+                self.output
+                    .push_str(&format!("#line {} \"{}\"\n", line_no + 1, name));
+            } else if let Some(rest) = directive.strip_prefix("define") {
+                let rest = rest.trim();
+                let end = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                let macro_name = &rest[..end];
+                if macro_name.is_empty() || rest[end..].starts_with('(') {
+                    return Err(error(
+                        "only object-like macros `#define NAME value` are supported".to_string(),
+                    ));
+                }
+                // 宏的值在定义时就展开, 因此宏之间的引用不会无限递归.
+                let value = self.expand(rest[end..].trim());
+                self.macros.insert(macro_name.to_string(), value);
+                self.output.push('\n');
+            } else if directive.starts_with("line") {
+                self.output.push_str(line);
+                self.output.push('\n');
+            } else {
+                return Err(error(format!(
+                    "unsupported preprocessor directive `{}`",
+                    trimmed
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /* 替换一行中的宏, 跳过注释和字符串/字符常量. */
+    fn expand(&mut self, line: &str) -> String {
+        let chars: Vec<char> = line.chars().collect();
+        let mut out = String::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            if self.in_block_comment {
+                out.push(c);
+                if c == '*' && chars.get(i + 1) == Some(&'/') {
+                    out.push('/');
+                    i += 1;
+                    self.in_block_comment = false;
+                }
+                i += 1;
+            } else if c == '/' && chars.get(i + 1) == Some(&'/') {
+                out.extend(&chars[i..]);
+                break;
+            } else if c == '/' && chars.get(i + 1) == Some(&'*') {
+                out.push_str("/*");
+                self.in_block_comment = true;
+                i += 2;
+            } else if c == '"' || c == '\'' {
+                let mut j = i + 1;
+                while j < chars.len() && chars[j] != c {
+                    if chars[j] == '\\' {
+                        j += 1;
+                    }
+                    j += 1;
+                }
+                let end = (j + 1).min(chars.len());
+                out.extend(&chars[i..end]);
+                i = end;
+            } else if c.is_ascii_alphabetic() || c == '_' {
+                let mut j = i;
+                while j < chars.len() && (chars[j].is_ascii_alphanumeric() || chars[j] == '_') {
+                    j += 1;
+                }
+                let word: String = chars[i..j].iter().collect();
+                match self.macros.get(&word) {
+                    Some(value) => out.push_str(value),
+                    None => out.push_str(&word),
+                }
+                i = j;
+            } else if c.is_ascii_digit() {
+                // 数字(包括0x1F这类)整体跳过, 避免把其中的字母当成标识符.
+                let mut j = i;
+                while j < chars.len() && (chars[j].is_ascii_alphanumeric() || chars[j] == '.') {
+                    j += 1;
+                }
+                out.extend(&chars[i..j]);
+                i = j;
+            } else {
+                out.push(c);
+                i += 1;
+            }
+        }
+        out
+    }
+}
+
+/* 对path指向的源文件做预处理, 返回展开后的源代码(带#line指令). */
+pub fn preprocess(path: &str) -> Result<String, PreprocessError> {
+    let mut preprocessor = Preprocessor::new();
+    preprocessor.process_file(Path::new(path))?;
+    Ok(preprocessor.output)
+}
+
+/* 预处理并把结果写到与源文件同名的.i文件中, 返回.i文件的路径, 供后续各阶段读取. */
+pub fn preprocess_to_file(path: &str) -> Result<String, PreprocessError> {
+    let output = preprocess(path)?;
+    let out_path = Path::new(path).with_extension("i");
+    std::fs::write(&out_path, output).map_err(|e| PreprocessError {
+        file: out_path.to_string_lossy().to_string(),
+        line: 0,
+        message: e.to_string(),
+    })?;
+    Ok(out_path.to_string_lossy().to_string())
+}
@@ -0,0 +1,293 @@
+use crate::ir::{BinOp, BlockId, CmpOp, Function, InstKind, Module, Operand, Value};
+use std::collections::{HashMap, HashSet};
+
+/*
+    稀疏条件常量传播(SCCP, Wegman和Zadeck): 在SSA形式上同时求值的格和可执行的边.
+    值的格: Top(还没有信息) > 常量 > Bottom(不是常量); phi只合并来自可执行边的值,
+    条件是常量的br只有一条边可执行. 最后把常量代入使用处, 删除算出常量的指令,
+    把条件确定的br改成jump, 删除不可达的块, 再合并只由jump相连的块.
+    除以0和float的NaN比较等不折叠, 留给运行时.
+*/
+pub fn run_module(module: &mut Module) -> bool {
+    let mut changed = false;
+    for function in module.functions.iter_mut().filter(|f| !f.is_declaration()) {
+        changed |= run(function);
+    }
+    changed
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Lattice {
+    Top,
+    Const(Operand),
+    Bottom,
+}
+
+impl Lattice {
+    fn meet(&self, other: &Lattice) -> Lattice {
+        match (self, other) {
+            (Lattice::Top, x) | (x, Lattice::Top) => x.clone(),
+            (Lattice::Const(a), Lattice::Const(b)) if same_constant(a, b) => self.clone(),
+            _ => Lattice::Bottom,
+        }
+    }
+}
+
+/* float按位比较, 这样NaN等于自己, -0.0不等于0.0. */
+fn same_constant(a: &Operand, b: &Operand) -> bool {
+    match (a, b) {
+        (Operand::Float(x), Operand::Float(y)) => x.to_bits() == y.to_bits(),
+        _ => a == b,
+    }
+}
+
+/* 操作数都是常量时计算指令的结果; 不能或不应折叠时返回None. */
+pub fn evaluate(kind: &InstKind, operands: &[Operand]) -> Option<Operand> {
+    use Operand::{Float, Int};
+    let result = match (kind, operands) {
+        (InstKind::Binary(op, ..), [Int(a), Int(b)]) => Int(match op {
+            BinOp::Add => a.wrapping_add(*b),
+            BinOp::Sub => a.wrapping_sub(*b),
+            BinOp::Mul => a.wrapping_mul(*b),
+            BinOp::Div if *b != 0 => a.wrapping_div(*b),
+            BinOp::Rem if *b != 0 => a.wrapping_rem(*b),
+            BinOp::Shl => a.wrapping_shl(*b as u32),
+            BinOp::Shr => a.wrapping_shr(*b as u32),
+            BinOp::And => a & b,
+            BinOp::Or => a | b,
+            BinOp::Xor => a ^ b,
+            _ => return None,
+        }),
+        (InstKind::Binary(op, ..), [Float(a), Float(b)]) => Float(match op {
+            BinOp::FAdd => a + b,
+            BinOp::FSub => a - b,
+            BinOp::FMul => a * b,
+            BinOp::FDiv => a / b,
+            _ => return None,
+        }),
+        (InstKind::Icmp(op, ..), [Int(a), Int(b)]) => Int(compare(*op, a, b) as i32),
+        (InstKind::Fcmp(op, ..), [Float(a), Float(b)]) => {
+            if a.is_nan() || b.is_nan() {
+                return None;
+            }
+            Int(compare(*op, a, b) as i32)
+        }
+        (InstKind::FNeg(_), [Float(a)]) => Float(-a),
+        (InstKind::IntToFloat(_), [Int(a)]) => Float(*a as f32),
+        // 超出int范围的转换在C中是未定义行为, 不折叠.
+        (InstKind::FloatToInt(_), [Float(a)]) if a.is_finite() && a.abs() < 2147483648.0 => {
+            Int(*a as i32)
+        }
+        (InstKind::Copy(_), [value]) => value.clone(),
+        _ => return None,
+    };
+    Some(result)
+}
+
+fn compare<T: PartialOrd>(op: CmpOp, a: T, b: T) -> bool {
+    match op {
+        CmpOp::Eq => a == b,
+        CmpOp::Ne => a != b,
+        CmpOp::Lt => a < b,
+        CmpOp::Le => a <= b,
+        CmpOp::Gt => a > b,
+        CmpOp::Ge => a >= b,
+    }
+}
+
+struct Solver<'a> {
+    function: &'a Function,
+    values: Vec<Lattice>,
+    executable: Vec<bool>,
+    edges: HashSet<(BlockId, BlockId)>,
+    uses: Vec<Vec<(usize, usize)>>, //每个值被哪些(块, 指令)用到
+    flow_work: Vec<(Option<BlockId>, BlockId)>,
+    ssa_work: Vec<Value>,
+}
+
+impl<'a> Solver<'a> {
+    fn new(function: &'a Function) -> Self {
+        let mut uses = vec![vec![]; function.value_types.len()];
+        for (b, block) in function.blocks.iter().enumerate() {
+            for (i, inst) in block.insts.iter().enumerate() {
+                for operand in inst.kind.operands() {
+                    if let Operand::Value(v) = operand {
+                        uses[v.0 as usize].push((b, i));
+                    }
+                }
+            }
+        }
+        let mut values = vec![Lattice::Top; function.value_types.len()];
+        // 形参的值在调用时才知道.
+        for value in values.iter_mut().take(function.params.len()) {
+            *value = Lattice::Bottom;
+        }
+        Solver {
+            function,
+            values,
+            executable: vec![false; function.blocks.len()],
+            edges: HashSet::new(),
+            uses,
+            flow_work: vec![(None, BlockId(0))],
+            ssa_work: vec![],
+        }
+    }
+
+    fn lattice(&self, operand: &Operand) -> Lattice {
+        match operand {
+            Operand::Value(v) => self.values[v.0 as usize].clone(),
+            Operand::Int(_) | Operand::Float(_) => Lattice::Const(operand.clone()),
+            Operand::Global(_) | Operand::Str(_) => Lattice::Bottom,
+        }
+    }
+
+    fn solve(&mut self) {
+        while !self.flow_work.is_empty() || !self.ssa_work.is_empty() {
+            while let Some((from, to)) = self.flow_work.pop() {
+                if let Some(from) = from {
+                    if !self.edges.insert((from, to)) {
+                        continue;
+                    }
+                }
+                let b = to.0 as usize;
+                let first = !std::mem::replace(&mut self.executable[b], true);
+                for (i, inst) in self.function.blocks[b].insts.iter().enumerate() {
+                    // 再次到达的块只需要重新计算phi.
+                    if first || matches!(inst.kind, InstKind::Phi(_)) {
+                        self.visit(b, i);
+                    }
+                }
+            }
+            while let Some(value) = self.ssa_work.pop() {
+                for k in 0..self.uses[value.0 as usize].len() {
+                    let (b, i) = self.uses[value.0 as usize][k];
+                    if self.executable[b] {
+                        self.visit(b, i);
+                    }
+                }
+            }
+        }
+    }
+
+    fn visit(&mut self, b: usize, i: usize) {
+        let inst = &self.function.blocks[b].insts[i];
+        let block = BlockId(b as u32);
+        let new = match &inst.kind {
+            InstKind::Jump(target) => {
+                self.flow_work.push((Some(block), *target));
+                return;
+            }
+            InstKind::Branch(cond, on_true, on_false) => {
+                match self.lattice(cond) {
+                    Lattice::Top => {}
+                    Lattice::Const(Operand::Int(n)) => {
+                        let target = if n != 0 { *on_true } else { *on_false };
+                        self.flow_work.push((Some(block), target));
+                    }
+                    _ => {
+                        self.flow_work.push((Some(block), *on_true));
+                        self.flow_work.push((Some(block), *on_false));
+                    }
+                }
+                return;
+            }
+            InstKind::Phi(incoming) => incoming
+                .iter()
+                .filter(|(pred, _)| self.edges.contains(&(*pred, block)))
+                .fold(Lattice::Top, |acc, (_, v)| acc.meet(&self.lattice(v))),
+            kind @ (InstKind::Binary(..)
+            | InstKind::Icmp(..)
+            | InstKind::Fcmp(..)
+            | InstKind::FNeg(_)
+            | InstKind::IntToFloat(_)
+            | InstKind::FloatToInt(_)
+            | InstKind::Copy(_)) => {
+                let inputs: Vec<Lattice> =
+                    kind.operands().iter().map(|o| self.lattice(o)).collect();
+                if inputs.contains(&Lattice::Bottom) {
+                    Lattice::Bottom
+                } else if inputs.contains(&Lattice::Top) {
+                    Lattice::Top
+                } else {
+                    let operands: Vec<Operand> = inputs
+                        .into_iter()
+                        .map(|l| match l {
+                            Lattice::Const(c) => c,
+                            _ => unreachable!(),
+                        })
+                        .collect();
+                    evaluate(kind, &operands).map_or(Lattice::Bottom, Lattice::Const)
+                }
+            }
+            _ => Lattice::Bottom,
+        };
+        let Some(dest) = inst.dest else {
+            return;
+        };
+        // 格只会下降: 与旧值求交, 避免在常量之间来回变化.
+        let old = &self.values[dest.0 as usize];
+        let new = if *old == Lattice::Top {
+            new
+        } else {
+            old.meet(&new)
+        };
+        if new != *old {
+            self.values[dest.0 as usize] = new;
+            self.ssa_work.push(dest);
+        }
+    }
+}
+
+/* 返回是否改变了函数. */
+pub fn run(function: &mut Function) -> bool {
+    let mut solver = Solver::new(function);
+    solver.solve();
+    let Solver {
+        values,
+        executable,
+        edges,
+        ..
+    } = solver;
+    let before = function.clone();
+
+    let mut replace = HashMap::new();
+    for (v, lattice) in values.iter().enumerate() {
+        if let Lattice::Const(c) = lattice {
+            replace.insert(Value(v as u32), c.clone());
+        }
+    }
+    for (b, block) in function.blocks.iter_mut().enumerate() {
+        if !executable[b] {
+            continue;
+        }
+        let id = BlockId(b as u32);
+        block
+            .insts
+            .retain(|inst| !inst.dest.is_some_and(|d| replace.contains_key(&d)));
+        for inst in &mut block.insts {
+            match &mut inst.kind {
+                InstKind::Phi(incoming) => {
+                    incoming.retain(|(pred, _)| edges.contains(&(*pred, id)));
+                }
+                InstKind::Branch(_, on_true, on_false) => {
+                    let (t, f) = (*on_true, *on_false);
+                    match (edges.contains(&(id, t)), edges.contains(&(id, f))) {
+                        (true, false) => inst.kind = InstKind::Jump(t),
+                        (false, true) => inst.kind = InstKind::Jump(f),
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    function.replace_values(&replace);
+    function.remove_unreachable_blocks();
+    function.simplify_phis();
+    function.merge_blocks();
+    if *function == before {
+        return false;
+    }
+    function.renumber_values();
+    true
+}
@@ -0,0 +1,275 @@
+use crate::{
+    alias::AliasAnalysis,
+    ddg::{DepKind, DependenceGraph},
+    effects::Effects,
+    ir::{BinOp, BlockId, Function, Inst, InstKind, Module, Operand, Value},
+    target::Target,
+};
+use std::collections::{HashMap, HashSet};
+
+/*
+    表调度: 在每个基本块内按依赖关系重排指令, 让load等长延迟指令的结果被用到之前先执行别的指令,
+    同时不让同时活跃的值超过寄存器的个数. 在寄存器分配之前运行.
+    依赖取自基本块的数据依赖图(ddg). phi留在块的开头, 终结指令留在最后.
+    每个周期发射一条指令. 就绪的指令中先选到块末尾的关键路径最长的; 活跃的值达到寄存器个数时先选能释放寄存器的.
+*/
+
+/* 各类指令从发射到结果可用的周期数, 以及可分配的寄存器个数. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencyTable {
+    pub alu: u32, //整数加减, 逻辑, 移位, 比较, 地址计算和复制
+    pub mul: u32,
+    pub div: u32, //除法和取模
+    pub load: u32,
+    pub store: u32,
+    pub fadd: u32, //浮点加减, 取负和比较
+    pub fmul: u32,
+    pub fdiv: u32,
+    pub convert: u32, //整数与浮点之间的转换
+    pub call: u32,
+    pub registers: usize,
+}
+
+impl LatencyTable {
+    pub fn for_target(target: Target) -> Self {
+        match target {
+            // 按常见的顺序双发射核(如SiFive U74)估计.
+            Target::Riscv64 => LatencyTable {
+                alu: 1,
+                mul: 3,
+                div: 20,
+                load: 3,
+                store: 1,
+                fadd: 5,
+                fmul: 5,
+                fdiv: 20,
+                convert: 4,
+                call: 1,
+                registers: 27,
+            },
+            // Cortex-A7/A9一类的核, 通用寄存器少得多.
+            Target::Armv7 => LatencyTable {
+                alu: 1,
+                mul: 3,
+                div: 12,
+                load: 3,
+                store: 1,
+                fadd: 4,
+                fmul: 5,
+                fdiv: 15,
+                convert: 4,
+                call: 1,
+                registers: 12,
+            },
+        }
+    }
+
+    pub fn latency(&self, kind: &InstKind) -> u32 {
+        match kind {
+            InstKind::Binary(BinOp::Mul, ..) => self.mul,
+            InstKind::Binary(BinOp::Div | BinOp::Rem, ..) => self.div,
+            InstKind::Binary(BinOp::FAdd | BinOp::FSub, ..) | InstKind::FNeg(_) => self.fadd,
+            InstKind::Fcmp(..) => self.fadd,
+            InstKind::Binary(BinOp::FMul, ..) => self.fmul,
+            InstKind::Binary(BinOp::FDiv, ..) => self.fdiv,
+            InstKind::IntToFloat(_) | InstKind::FloatToInt(_) => self.convert,
+            InstKind::Load(_) => self.load,
+            InstKind::Store(..) => self.store,
+            InstKind::Call(..) => self.call,
+            _ => self.alu,
+        }
+    }
+}
+
+impl Default for LatencyTable {
+    fn default() -> Self {
+        Self::for_target(Target::default())
+    }
+}
+
+pub fn run_module(module: &mut Module, table: &LatencyTable) -> bool {
+    let effects = Effects::compute(module);
+    let mut changed = false;
+    for function in module.functions.iter_mut().filter(|f| !f.is_declaration()) {
+        changed |= run_with(function, table, &effects);
+    }
+    changed
+}
+
+/* 不知道其他函数时, 所有调用都按有输入输出处理. */
+pub fn run(function: &mut Function, table: &LatencyTable) -> bool {
+    run_with(function, table, &Effects::default())
+}
+
+pub fn run_with(function: &mut Function, table: &LatencyTable, effects: &Effects) -> bool {
+    let aliases = AliasAnalysis::compute(function);
+    // 别的块, phi和终结指令用到的值在定义它的块的末尾仍然活跃.
+    let mut defined_in = HashMap::new();
+    for (b, block) in function.blocks.iter().enumerate() {
+        for dest in block.insts.iter().filter_map(|i| i.dest) {
+            defined_in.insert(dest, b);
+        }
+    }
+    let mut live_out = HashSet::new();
+    for (b, block) in function.blocks.iter().enumerate() {
+        for inst in &block.insts {
+            let escapes = inst.kind.is_terminator() || matches!(inst.kind, InstKind::Phi(_));
+            for operand in inst.kind.operands() {
+                if let Operand::Value(v) = operand {
+                    if escapes || defined_in.get(v) != Some(&b) {
+                        live_out.insert(*v);
+                    }
+                }
+            }
+        }
+    }
+    let mut changed = false;
+    for b in 0..function.blocks.len() {
+        let insts = &function.blocks[b].insts;
+        let phis = insts
+            .iter()
+            .take_while(|i| matches!(i.kind, InstKind::Phi(_)))
+            .count();
+        let end = insts.len().saturating_sub(1).max(phis);
+        let body = &insts[phis..end];
+        let graph = DependenceGraph::for_block(function, BlockId(b as u32), &aliases, effects);
+        let dependences: Vec<(usize, usize, DepKind)> = graph
+            .edges
+            .iter()
+            .filter(|e| (phis..end).contains(&e.from) && (phis..end).contains(&e.to))
+            .map(|e| (e.from - phis, e.to - phis, e.kind))
+            .collect();
+        let order = schedule(body, table, |v| live_out.contains(&v), &dependences);
+        if order.iter().enumerate().any(|(i, j)| i != *j) {
+            let insts = &mut function.blocks[b].insts;
+            let mut body: Vec<Option<Inst>> = insts.drain(phis..end).map(Some).collect();
+            let reordered: Vec<Inst> = order.iter().map(|&i| body[i].take().unwrap()).collect();
+            insts.splice(phis..phis, reordered);
+            changed = true;
+        }
+    }
+    changed
+}
+
+/* 返回body中指令的新顺序(下标). */
+fn schedule(
+    body: &[Inst],
+    table: &LatencyTable,
+    live_out: impl Fn(Value) -> bool,
+    dependences: &[(usize, usize, DepKind)],
+) -> Vec<usize> {
+    let n = body.len();
+    if n < 2 {
+        return (0..n).collect();
+    }
+    let index: HashMap<Value, usize> = body
+        .iter()
+        .enumerate()
+        .filter_map(|(i, inst)| inst.dest.map(|d| (d, i)))
+        .collect();
+    let latency: Vec<u32> = body.iter().map(|i| table.latency(&i.kind)).collect();
+    // succs[i]: (后继, 后继最早在i发射后多少个周期发射)
+    let mut succs: Vec<Vec<(usize, u32)>> = vec![vec![]; n];
+    let mut preds = vec![0usize; n];
+    let mut uses: HashMap<Value, usize> = HashMap::new();
+    for inst in body {
+        for operand in inst.kind.operands() {
+            if let Operand::Value(v) = operand {
+                if index.contains_key(v) {
+                    *uses.entry(*v).or_default() += 1;
+                }
+            }
+        }
+    }
+    // 用到结果的要等前一条的延迟, 其他依赖只要排在后面.
+    for &(i, j, kind) in dependences {
+        let delay = if kind == DepKind::Data { latency[i] } else { 1 };
+        match succs[i].iter_mut().find(|(s, _)| *s == j) {
+            Some(edge) => edge.1 = edge.1.max(delay),
+            None => {
+                succs[i].push((j, delay));
+                preds[j] += 1;
+            }
+        }
+    }
+    // 到块末尾的最长延迟.
+    let mut height = vec![0u32; n];
+    for i in (0..n).rev() {
+        height[i] = succs[i]
+            .iter()
+            .map(|&(j, delay)| delay + height[j])
+            .max()
+            .unwrap_or(0)
+            .max(latency[i]);
+    }
+    // 定义时还没有用过, 用总的使用次数判断结果是否占用寄存器.
+    let total = uses.clone();
+    let needed = |inst: &Inst| {
+        inst.dest
+            .is_some_and(|d| total.contains_key(&d) || live_out(d))
+    };
+    // 调度这条指令后活跃的值减少多少.
+    let released = |inst: &Inst, uses: &HashMap<Value, usize>| {
+        let mut seen = vec![];
+        let mut count = 0isize;
+        for operand in inst.kind.operands() {
+            if let Operand::Value(v) = operand {
+                if index.contains_key(v) && !seen.contains(v) {
+                    seen.push(*v);
+                    let here = inst
+                        .kind
+                        .operands()
+                        .iter()
+                        .filter(|o| **o == &Operand::Value(*v))
+                        .count();
+                    if uses[v] == here && !live_out(*v) {
+                        count += 1;
+                    }
+                }
+            }
+        }
+        count - needed(inst) as isize
+    };
+    let mut earliest = vec![0u32; n];
+    let mut done = vec![false; n];
+    let mut order = Vec::with_capacity(n);
+    let mut live = 0usize;
+    let mut cycle = 0u32;
+    while order.len() < n {
+        let ready: Vec<usize> = (0..n).filter(|&i| !done[i] && preds[i] == 0).collect();
+        let first = ready.iter().map(|&i| earliest[i]).min().unwrap();
+        cycle = cycle.max(first);
+        // 寄存器紧张时宁可等待, 也要先执行能释放寄存器的指令.
+        let tight = live >= table.registers;
+        let pick = ready
+            .into_iter()
+            .filter(|&i| tight || earliest[i] <= cycle)
+            .max_by_key(|&i| {
+                let release = released(&body[i], &uses);
+                if tight {
+                    (release, height[i] as isize, std::cmp::Reverse(i))
+                } else {
+                    (height[i] as isize, release, std::cmp::Reverse(i))
+                }
+            })
+            .unwrap();
+        cycle = cycle.max(earliest[pick]);
+        let release = released(&body[pick], &uses);
+        live = (live as isize - release).max(0) as usize;
+        for operand in body[pick].kind.operands() {
+            if let Operand::Value(v) = operand {
+                if let Some(u) = uses.get_mut(v) {
+                    *u -= 1;
+                }
+            }
+        }
+        for &(j, delay) in &succs[pick] {
+            preds[j] -= 1;
+            earliest[j] = earliest[j].max(cycle + delay);
+        }
+        done[pick] = true;
+        order.push(pick);
+        cycle += 1;
+    }
+    order
+}
@@ -1,27 +1,203 @@
-use crate::{parser::Node, BasicType, NodeType, Scope, TokenType};
-use colored::Colorize;
-use std::{collections::HashMap, fs::File, io::Read, path::Path, usize};
+use crate::{
+    diagnostics::{Diagnostic, DiagnosticKind, Severity, WarningConfig},
+    fold::{fold_children, Folder},
+    hir::Hir,
+    parser::{Node, NodeId},
+    span::Span,
+    uninit::uninitialized_reads,
+    utils::operator_text,
+    visit::{children, node_map},
+    xref::{callees, symbol_uses, UsageKind},
+    BasicType, ConstValue, NodeType, Scope, TokenType,
+};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    sync::OnceLock,
+};
 
-static mut FILEPATH: String = String::new();
+/* 同一条错误信息(通常是同一个符号引发的)最多完整保留的次数, 超出的合并成一条并附加"and N more uses". */
+const MAX_SAME_MESSAGE: usize = 3;
 
-#[derive(Clone)]
-pub struct Var {
-    basic_type: BasicType,
-    node: Node,
+/*
+    一条语义错误. 语义分析期间报告的错误先缓存起来, 结束时统一整理成Diagnostic:
+    (信息, 区间)完全相同的错误只保留一次; 同一信息出现超过MAX_SAME_MESSAGE次时只保留第一处.
+    Repeat是已经报告过的错误(如同一个未定义的名字)的又一次出现, 它不单独输出, 也不计入错误个数上限,
+    只让第一处附加"and N more uses".
+*/
+enum Pending {
+    Spot {
+        kind: DiagnosticKind,
+        msg: String,
+        span: Span,
+    },
+    Report(Diagnostic),
+    Repeat {
+        msg: String,
+        span: Span,
+    },
+}
+
+/*
+    缓存按线程隔离, 语义分析没有其他全局状态(源文件的位置在Span的FileId中):
+    不同线程可以同时分析各自的程序(如并行运行的测试, 编辑器插件, 多文件构建), 互不影响.
+*/
+thread_local! {
+    static PENDING: RefCell<Vec<Pending>> = const { RefCell::new(vec![]) };
+}
+
+impl Pending {
+    fn message(&self) -> &str {
+        match self {
+            Pending::Spot { msg, .. } | Pending::Repeat { msg, .. } => msg,
+            Pending::Report(diagnostic) => &diagnostic.message,
+        }
+    }
+
+    fn key(&self) -> (String, Span) {
+        match self {
+            Pending::Spot { msg, span, .. } | Pending::Repeat { msg, span } => (msg.clone(), *span),
+            Pending::Report(diagnostic) => {
+                let span = diagnostic
+                    .labels
+                    .first()
+                    .map_or(Span::default(), |l| l.span);
+                (diagnostic.message.clone(), span)
+            }
+        }
+    }
+
+    /* more: 被合并掉的同类错误个数 */
+    fn into_diagnostic(self, more: Option<usize>) -> Diagnostic {
+        let mut diagnostic = match self {
+            Pending::Spot { kind, msg, span } => {
+                Diagnostic::error(msg).with_kind(kind).with_label(span, "")
+            }
+            Pending::Report(diagnostic) => diagnostic,
+            Pending::Repeat { .. } => unreachable!("repeats are merged into the first report"),
+        };
+        if let Some(n) = more {
+            diagnostic = diagnostic.with_note(format!("and {} more uses", n));
+        }
+        diagnostic
+    }
+}
+
+/*
+    编译期数组越界检查: 下标在折叠后是常量时与声明的维度比较, 只报告错误, 不改变生成的节点.
+    数组形参的第一维长度未知(为0), 不检查.
+*/
+fn check_bounds(name: &str, dims: &[usize], indexes: &[Node]) {
+    for (i, (index, &len)) in indexes.iter().zip(dims).enumerate() {
+        let NodeType::Number(value) = index.node_type else {
+            continue;
+        };
+        if len != 0 && (value < 0 || value as usize >= len) {
+            report(out_of_bounds(name, index, value, i, len));
+        }
+    }
+}
+
+/* 常量数组的求值(const_eval)也用它报告越界, 两处报告的是同一个诊断, 会被去重. */
+fn out_of_bounds(name: &str, index: &Node, value: i32, dim: usize, len: usize) -> Diagnostic {
+    Diagnostic::error(format!(
+        "Error type 8 at this line: Index {} out of bounds for `{}`",
+        value, name
+    ))
+    .with_kind(DiagnosticKind::IndexMismatch)
+    .with_label(
+        index.span,
+        format!("dimension {} has length {}", dim + 1, len),
+    )
+}
+
+/* 已经缓存的错误(不含警告)个数, 用于错误个数上限. */
+fn pending_errors() -> usize {
+    PENDING.with(|p| {
+        p.borrow()
+            .iter()
+            .filter(|e| match e {
+                Pending::Report(d) => d.is_error(),
+                Pending::Spot { .. } => true,
+                Pending::Repeat { .. } => false,
+            })
+            .count()
+    })
+}
+
+/* 已经缓存的错误个数, 与discard_unevaluated配合使用. */
+fn pending_len() -> usize {
+    PENDING.with(|p| p.borrow().len())
+}
+
+/* 丢掉第since个之后报告的除以0和溢出: 它们在不会被求值的操作数中(如0 && 1 / 0). */
+fn discard_unevaluated(since: usize) {
+    PENDING.with(|p| {
+        let mut pending = p.borrow_mut();
+        let tail = pending.split_off(since);
+        pending.extend(tail.into_iter().filter(|e| {
+            !matches!(e, Pending::Report(d) if matches!(
+                d.kind,
+                Some(DiagnosticKind::DivisionByZero | DiagnosticKind::IntegerOverflow)
+            ))
+        }));
+    });
 }
 
-impl Var {
-    pub fn new(basic_type: BasicType, node: Node) -> Self {
-        Var { basic_type, node }
+/* 取出缓存的语义错误: 先去重, 再对重复过多(或者有Repeat)的信息限流. */
+fn take_errors() -> Vec<Diagnostic> {
+    let pending = PENDING.with(|p| std::mem::take(&mut *p.borrow_mut()));
+    let mut seen = HashSet::new();
+    let unique: Vec<Pending> = pending
+        .into_iter()
+        .filter(|p| seen.insert(p.key()))
+        .collect();
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    let mut repeated = HashSet::new();
+    for p in &unique {
+        *counts.entry(p.message().to_string()).or_default() += 1;
+        if let Pending::Repeat { msg, .. } = p {
+            repeated.insert(msg.clone());
+        }
+    }
+    let mut collapsed = HashSet::new();
+    let mut diagnostics = vec![];
+    for p in unique {
+        let count = counts[p.message()];
+        if let Pending::Repeat { .. } = p {
+            continue;
+        }
+        if count <= MAX_SAME_MESSAGE && !repeated.contains(p.message()) {
+            diagnostics.push(p.into_diagnostic(None));
+        } else if collapsed.insert(p.message().to_string()) {
+            diagnostics.push(p.into_diagnostic(Some(count - 1)));
+        }
     }
+    diagnostics
+}
+
+#[derive(Clone)]
+pub struct Var {
+    basic_type: BasicType,
+    decl: NodeId, //带有类型的声明节点的编号, 节点本身只在Runtime::declarations中保存一份
 }
 
 pub struct Runtime {
     global: HashMap<String, Var>,
     local: Vec<HashMap<String, Var>>,
     loop_count: usize,
+    switch_depth: usize, //当前所在的switch层数, break也可以出现在switch中
     cur_func_name: String,
     cur_func_type: BasicType,
+    declarations: HashMap<NodeId, Node>, //按编号索引的全部声明(包括已经离开作用域的), 作用域和引用中只记录编号
+    const_reads: RefCell<HashSet<NodeId>>, //被读取过的常量; 它们的访问已折叠成Number, xref中看不到
+    undefined: RefCell<HashMap<String, String>>, //已经报告过未定义的名字和报告的信息, 之后的使用记为Repeat
+}
+
+impl Default for Runtime {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Runtime {
@@ -30,8 +206,12 @@ impl Runtime {
             global: HashMap::new(),
             local: vec![],
             loop_count: 0,
+            switch_depth: 0,
             cur_func_name: String::new(),
             cur_func_type: BasicType::Nil,
+            declarations: HashMap::new(),
+            const_reads: RefCell::new(HashSet::new()),
+            undefined: RefCell::new(HashMap::new()),
         }
     }
 
@@ -53,33 +233,61 @@ impl Runtime {
 
     fn is_in_loop(&mut self) -> bool {
         if self.loop_count == 0 {
-            return false;
+            false
         } else if self.loop_count > 0 {
-            return true;
+            true
         } else {
             unreachable!()
         }
     }
 
-    fn set_cur_func(&mut self, func_name: &String, func_type: &BasicType) {
-        self.cur_func_name = func_name.clone();
+    fn can_break(&self) -> bool {
+        self.loop_count > 0 || self.switch_depth > 0
+    }
+
+    fn set_cur_func(&mut self, func_name: &str, func_type: &BasicType) {
+        self.cur_func_name = func_name.to_string();
         self.cur_func_type = func_type.clone();
     }
 
     fn get_cur_func(&mut self) -> (String, BasicType) {
-        return (self.cur_func_name.clone(), self.cur_func_type.clone());
+        (self.cur_func_name.clone(), self.cur_func_type.clone())
     }
 
     ///将node节点(代表变量或者函数)新增到全局表或者当前作用域中。
     fn insert(&mut self, name: String, basic_type: BasicType, node: Node) {
         // step1. Check if a function with the same name exists
-        if matches!(node.node_type, NodeType::Func(..)) {
+        // 函数原型可以出现多次, 也可以与之后的定义合并, 但签名必须一致.
+        if let NodeType::Func(_, _, _, body) = &node.node_type {
             if let Some(val) = self.global.get(&name) {
-                if matches!(val.node.node_type, NodeType::Func(..)) {
-                    node.error_spot(format!(
-                        "Error type 4 at this line: function `{}` has already defined here!",
-                        name
-                    ));
+                let prev = &self.declarations[&val.decl];
+                if let NodeType::Func(_, _, _, prev_body) = &prev.node_type {
+                    if !same_signature(prev, &node) {
+                        let mut diagnostic = Diagnostic::error(format!(
+                            "Error type 4 at this line: conflicting declarations of function `{}`",
+                            name
+                        ))
+                        .with_kind(DiagnosticKind::Redefinition)
+                        .with_label(node.span, "signature differs");
+                        // 运行时库函数没有源代码位置
+                        if prev.span.end > prev.span.start {
+                            diagnostic =
+                                diagnostic.with_label(prev.span, "previously declared here");
+                        }
+                        report(diagnostic);
+                    } else if !is_prototype(prev_body) && !is_prototype(body) {
+                        node.error_spot(
+                            DiagnosticKind::Redefinition,
+                            format!(
+                            "Error type 4 at this line: function `{}` has already defined here!",
+                            name
+                        ),
+                        );
+                    }
+                    // 已有声明时, 后来的原型不覆盖它(尤其不能覆盖已有的定义).
+                    if is_prototype(body) {
+                        return;
+                    }
                 }
             }
         }
@@ -88,840 +296,2468 @@ impl Runtime {
         if matches!(node.node_type, NodeType::Decl(..)) {
             if self.local.is_empty() {
                 if let Some(val) = self.global.get(&name) {
-                    if !matches!(val.node.node_type, NodeType::Func(..)) {
-                        node.error_spot(format!(
-                            "Error type 2 at this line: redefined global variable: `{}`.",
-                            name
-                        ));
+                    if !matches!(self.declarations[&val.decl].node_type, NodeType::Func(..)) {
+                        node.error_spot(
+                            DiagnosticKind::Redefinition,
+                            format!(
+                                "Error type 2 at this line: redefined global variable: `{}`.",
+                                name
+                            ),
+                        );
                     }
                 }
-            } else {
-                if self.local.last().unwrap().contains_key(&name) {
-                    node.error_spot(format!(
+            } else if self.local.last().unwrap().contains_key(&name) {
+                node.error_spot(
+                    DiagnosticKind::Redefinition,
+                    format!(
                         "Error type 2 at this line: redefined variable: `{}` in this scope!",
                         name
-                    ))
-                }
+                    ),
+                )
+            } else {
+                self.warn_shadowing(&name, &node);
             }
         }
         // step3. Insert into the global or current scope
-        if self.local.is_empty() || matches!(node.node_type, NodeType::Func(..)) {
-            self.global.insert(name, Var::new(basic_type, node));
+        let var = Var {
+            basic_type,
+            decl: node.id,
+        };
+        let is_func = matches!(node.node_type, NodeType::Func(..));
+        self.declarations.insert(node.id, node);
+        if self.local.is_empty() || is_func {
+            self.global.insert(name, var);
         } else {
-            self.local
-                .last_mut()
-                .unwrap()
-                .insert(name, Var::new(basic_type, node));
+            self.local.last_mut().unwrap().insert(name, var);
         }
     }
 
-    fn find(&self, name: &String, node: &Node) -> (BasicType, Node) {
-        // step1. 从当前局部作用域往回查找
-        for map in self.local.iter().rev() {
-            if let Some(var) = map.get(name) {
-                return (var.basic_type.clone(), var.node.clone());
-            }
+    /* Access/Assign/Call引用的声明; 未定义的名字没有声明. */
+    fn declaration(&self, id: NodeId) -> Option<&Node> {
+        self.declarations.get(&id)
+    }
+
+    /*
+        局部变量与外层的参数, 局部变量或全局变量同名时给出警告(默认不输出, 见WarningConfig).
+        参数本身与全局变量同名不算.
+    */
+    fn warn_shadowing(&self, name: &String, node: &Node) {
+        if !matches!(node.node_type, NodeType::Decl(.., Scope::Local)) {
+            return;
         }
-        // step2. 在全局作用域中查找
-        if let Some(var) = self.global.get(name) {
-            return (var.basic_type.clone(), var.node.clone());
+        let Some(outer) = self
+            .local
+            .iter()
+            .rev()
+            .skip(1)
+            .find_map(|map| map.get(name))
+            .or_else(|| self.global.get(name))
+        else {
+            return;
+        };
+        let outer = &self.declarations[&outer.decl];
+        let NodeType::Decl(.., scope) = &outer.node_type else {
+            return;
+        };
+        let what = match scope {
+            Scope::Params => "parameter",
+            Scope::Local => "local variable",
+            Scope::Global => "global variable",
+        };
+        report(
+            Diagnostic::warning(format!("declaration of `{}` shadows a {}", name, what))
+                .with_kind(DiagnosticKind::Shadowing)
+                .with_label(node.span, "shadowing declaration")
+                .with_label(outer.span, format!("{} declared here", what)),
+        );
+    }
+
+    /*
+        查找node引用的名字, 返回它的类型和声明节点; 未定义时报告错误并返回(Nil, None).
+        变量的读写由xref从语义分析后的AST得到; 常量的读取在这里记录, 因为它们在之后的AST中已经折叠成Number.
+    */
+    fn find(&self, name: &String, node: &Node) -> (BasicType, Option<&Node>) {
+        // step1. 从当前局部作用域往回查找, step2. 在全局作用域中查找
+        let found = self
+            .local
+            .iter()
+            .rev()
+            .find_map(|map| map.get(name))
+            .or_else(|| self.global.get(name));
+        if let Some(var) = found {
+            let constant = matches!(
+                var.basic_type,
+                BasicType::Const
+                    | BasicType::ConstFloat
+                    | BasicType::ConstArray(_)
+                    | BasicType::ConstFloatArray(_)
+            );
+            if constant && !matches!(node.node_type, NodeType::Assign(..)) {
+                self.const_reads.borrow_mut().insert(var.decl);
+            }
+            (var.basic_type.clone(), self.declaration(var.decl))
         } else {
-            match node.node_type {
-                NodeType::Call(..) => {
-                    node.error_spot(format!(
-                        "Error type 3 at this line: undefined function `{:?}`",
-                        name
-                    ));
+            // 同一个名字只报告第一次未定义, 后面的使用记为Repeat, 不再淹没它.
+            if let Some(msg) = self.undefined.borrow().get(name) {
+                PENDING.with(|p| {
+                    p.borrow_mut().push(Pending::Repeat {
+                        msg: msg.clone(),
+                        span: node.span,
+                    })
+                });
+                return (BasicType::Nil, None);
+            }
+            let is_call = matches!(node.node_type, NodeType::Call(..));
+            let msg = if is_call {
+                format!("Error type 3 at this line: undefined function `{:?}`", name)
+            } else {
+                format!("Error type 1 at this line: undefined variable {:?}", name)
+            };
+            let msg = match self.similar_name(name, is_call) {
+                // 名字在节点的开头(x, x[i], x = ..., f(...)), 修改建议只替换名字本身.
+                Some(similar) => {
+                    let msg = format!("{}, did you mean `{}`?", msg, similar);
+                    report(
+                        Diagnostic::error(msg.clone())
+                            .with_kind(DiagnosticKind::UndefinedSymbol)
+                            .with_label(node.span, "")
+                            .with_fix(
+                                Span::new(
+                                    node.span.file_id,
+                                    node.span.start,
+                                    node.span.start + name.chars().count(),
+                                ),
+                                similar.clone(),
+                                format!("replace with `{}`", similar),
+                            ),
+                    );
+                    msg
                 }
-                _ => {
-                    node.error_spot(format!(
-                        "Error type 1 at this line: undefined variable {:?}.",
-                        name
-                    ));
+                None => {
+                    let msg = if is_call { msg } else { format!("{}.", msg) };
+                    node.error_spot(DiagnosticKind::UndefinedSymbol, msg.clone());
+                    msg
                 }
+            };
+            self.undefined.borrow_mut().insert(name.clone(), msg);
+            (BasicType::Nil, None)
+        }
+    }
+
+    /*
+        拼写最接近name的可见名字(包括运行时库函数): 调用只找函数, 其余只找变量和常量.
+        编辑距离不超过名字长度的1/3(至少为1)并且小于名字长度才算接近(y不会被当成x的笔误);
+        距离相同时取字典序最小的, 保证结果确定.
+    */
+    fn similar_name(&self, name: &str, is_call: bool) -> Option<&String> {
+        let len = name.chars().count();
+        let limit = (len / 3).max(1).min(len.saturating_sub(1));
+        self.local
+            .iter()
+            .chain(std::iter::once(&self.global))
+            .flat_map(|map| map.iter())
+            .filter(|(_, var)| {
+                matches!(self.declarations[&var.decl].node_type, NodeType::Func(..)) == is_call
+            })
+            .map(|(candidate, _)| (edit_distance(name, candidate), candidate))
+            .filter(|&(distance, _)| distance <= limit)
+            .min()
+            .map(|(_, candidate)| candidate)
+    }
+}
+
+/* 两个名字之间的编辑距离(插入, 删除, 替换各算一次; 相邻两个字符交换也算一次). */
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // d[i][j]: a的前i个字符与b的前j个字符之间的距离.
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = (a[i - 1] != b[j - 1]) as usize;
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
             }
-            return (BasicType::Nil, Node::new(NodeType::Nil))  
         }
     }
+    d[a.len()][b.len()]
 }
 
 impl Node {
-    fn error_spot(&self, msg: String) {
-        let path = unsafe { Path::new(&FILEPATH) };
-        let mut code = String::new();
-        File::open(path)
-            .expect("failed to read source code")
-            .read_to_string(&mut code)
-            .expect("read code to String failed");
-        let code_chars: Vec<char> = code.chars().collect();
-        let mut line_startpos = self.startpos;
-        while line_startpos != 0 && code_chars[line_startpos] != '\n' {
-            line_startpos -= 1;
-        }
-        let mut line_endpos = self.endpos;
-        while line_endpos != code.len() && code_chars[line_endpos] != '\n' {
-            line_endpos += 1;
-        }
-        let mut startpos_line = 1;
-        let mut index = 0;
-        while index != line_startpos {
-            if code_chars[index] == '\n' {
-                startpos_line += 1;
-            }
-            index += 1;
-        }
-        let code_lines = code[line_startpos..line_endpos].to_string();
-        let mut sign_lines = String::new();
-        for i in line_startpos..line_endpos {
-            if code_chars[i] == '\n' {
-                sign_lines.push('\n');
-                continue;
-            }
-            if self.startpos <= i && i < self.endpos {
-                sign_lines.push('^');
-            } else {
-                sign_lines.push(' ');
-            }
+    fn error_spot(&self, kind: DiagnosticKind, msg: String) {
+        PENDING.with(|p| {
+            p.borrow_mut().push(Pending::Spot {
+                kind,
+                msg,
+                span: self.span,
+            })
+        });
+    }
+
+    /* 出错后代替本节点继续分析的Nil节点, 保留本节点的编号和位置. */
+    fn placeholder(&self) -> Node {
+        Node {
+            id: self.id,
+            span: self.span,
+            node_type: NodeType::Nil,
+            basic_type: BasicType::Nil,
         }
-        //Error message
-        println!("{}: {}", "sementic error".red().bold(), msg.bold());
-        println!(
-            "  {} {}:{}",
-            "-->".blue().bold(),
-            startpos_line + 1,
-            self.startpos - line_startpos
-        );
-        for (i, (code_line, sign_line)) in code_lines
-            .split('\n')
-            .into_iter()
-            .zip(sign_lines.split('\n').into_iter())
-            .enumerate()
-        {
-            if code_line.trim().is_empty() {
-                continue;
-            }
-            println!("     {}", "|".blue().bold());
-            println!(
-                "  {3:3}{2} {}\n     {2} {}\n",
-                code_line,
-                sign_line.red().bold(),
-                "|".blue().bold(),
-                (startpos_line + i).to_string().blue().bold()
-            );
+    }
+}
+
+/* 函数原型(int f(int a);)的函数体是Nil. */
+fn is_prototype(body: &Node) -> bool {
+    matches!(body.node_type, NodeType::Nil)
+}
+
+/* 两个Func节点的返回类型和各参数类型是否都相同(参数名可以不同). */
+fn same_signature(a: &Node, b: &Node) -> bool {
+    let param_types = |params: &[Node]| -> Vec<BasicType> {
+        params
+            .iter()
+            .map(|p| match &p.node_type {
+                NodeType::Decl(ty, ..) => ty.clone(),
+                _ => p.basic_type.clone(),
+            })
+            .collect()
+    };
+    match (&a.node_type, &b.node_type) {
+        (NodeType::Func(ret_a, _, params_a, _), NodeType::Func(ret_b, _, params_b, _)) => {
+            ret_a == ret_b && param_types(params_a) == param_types(params_b)
         }
+        _ => false,
     }
 }
 
+/* 报告一条带有多个标注或修改建议的语义错误(同样先缓存, 由take_errors统一整理). */
+fn report(diagnostic: Diagnostic) {
+    PENDING.with(|p| p.borrow_mut().push(Pending::Report(diagnostic)));
+}
+
+/*
+    语义分析: 遍历parser生成的AST, 对每个节点做语义检查(类型检查, 作用域, 常量折叠),
+    结果是带类型信息的新AST(Annotated AST). 每种节点的检查写在Checker对应的fold_*方法中,
+    只是重建节点的情况(DeclStmt, ExprStmt...)交给Folder的默认实现.
+*/
 fn traverse(node: &Node, ctx: &mut Runtime) -> Node {
-    /* params: node代表当前节点, ctx代表runtime环境 */
-    /* 1. 遍历parser生成的AST树, 对AST上的每个Node进行语义检查 */
-    /* 2. 语义分析的结果是新的AST树, 就是你要的Anonated AST, 经过语义检查后的AST. */
-    use NodeType::*;
-    match &node.node_type {
-        /*---------第一类:Numbers,Variables and Arrays--------*/
-        Number(_) => {
-            let mut new_node = node.clone();
-            new_node.basic_type = BasicType::Const;
-            new_node //返回Const语义的节点
-        }
-        FloatNumber(_) => {
-            let mut new_node = node.clone();
-            new_node.basic_type = BasicType::Float;
-            new_node
-        }
-        /* variable, eg: int a[3][3] = {{1,2,3},{4,5,6},{7,8,9}}, local */
-        Decl(basic_type, name, dims, inits, scope) => {
-            let mut ty = basic_type.clone();
-            // step1. 处理维度
-            let new_dims = if let Some(dim) = dims {
-                let mut new = vec![];
-                let mut n = vec![];
-                for dim_node in dim {
-                    let result = eval(&dim_node, ctx);
-                    if result <= 0 && !matches!(dim_node.node_type, NodeType::Nil) {
-                        dim_node.error_spot(format!("Dimension of {} should > 0", name));
-                    }
-                    new.push(Node {
-                        startpos: dim_node.startpos,
-                        endpos: dim_node.endpos,
-                        node_type: Number(result),
-                        basic_type: BasicType::Const, // 这里的basic_type是Const, 因为数组的大小是常量√, 不管你是啥数组。
-                    });
-                    n.push(result as usize);
-                }
-                if ty == BasicType::Int || matches!(ty, BasicType::IntArray(_)) {
-                    ty = BasicType::IntArray(n);
-                } else if ty == BasicType::Const || matches!(ty, BasicType::ConstArray(_)) {
-                    ty = BasicType::ConstArray(n);
-                }
-                Some(new)
-            } else {
-                None
-            };
+    Checker {
+        ctx,
+        discarded: HashSet::new(),
+        arguments: HashMap::new(),
+    }
+    .fold_node(node)
+}
 
-            // step2. 处理初始化列表
-            let mut new_inits = vec![];
-            if let Some(init_nodes) = inits {
-                // 如果是一维初始化列表, 处理:
-                if new_dims.is_none() && init_nodes.len() == 1 {
-                    let mut new_node;
-                    new_node = traverse(&init_nodes[0], ctx);
-                    if basic_type == &BasicType::Const || scope == &Scope::Global {
-                        new_node = Node {
-                            startpos: init_nodes[0].startpos,
-                            endpos: init_nodes[0].endpos,
-                            node_type: Number(eval(&init_nodes[0], ctx)),
-                            basic_type: BasicType::Const,
-                        };
+struct Checker<'a> {
+    ctx: &'a mut Runtime,
+    discarded: HashSet<NodeId>, //值被丢弃的函数调用(表达式语句, 逗号表达式中不是最后的操作数), 只有它们可以是void
+    arguments: HashMap<NodeId, String>, //直接作为实参的函数调用 -> 被调用的函数名, 用于报告void实参
+}
+
+impl Folder for Checker<'_> {
+    /*---------第一类:Numbers,Variables and Arrays--------*/
+    fn fold_leaf(&mut self, node: &Node) -> Node {
+        let mut new_node = node.clone();
+        new_node.basic_type = match node.node_type {
+            NodeType::Number(_) => BasicType::Const, //返回Const语义的节点
+            NodeType::FloatNumber(_) => BasicType::Float,
+            // 字符串没有SysY类型, 只作为putf的格式参数出现(见Checker::check_format_args).
+            NodeType::Str(_) => BasicType::Nil,
+            // 语法错误处代替表达式的占位节点(见parse_recovering), 当作int, 不再引起类型错误.
+            NodeType::Nil => BasicType::Int,
+            _ => unreachable!(),
+        };
+        new_node
+    }
+
+    /* variable, eg: int a[3][3] = {{1,2,3},{4,5,6},{7,8,9}}, local */
+    fn fold_decl(&mut self, node: &Node) -> Node {
+        use NodeType::*;
+        let Decl(basic_type, name, dims, inits, scope) = &node.node_type else {
+            unreachable!()
+        };
+        let mut ty = basic_type.clone();
+        // 变量不能是void(如void y;), 报告后按int继续分析.
+        if ty == BasicType::Void {
+            node.error_spot(
+                DiagnosticKind::TypeMismatch,
+                format!("Error type 7 at this line: Variable {} declared void", name),
+            );
+            ty = BasicType::Int;
+        }
+        // step1. 处理维度
+        let new_dims = if let Some(dim) = dims {
+            let mut new = vec![];
+            let mut n = vec![];
+            for dim_node in dim {
+                // 维度必须是int常量表达式; 出错时按1继续, 不再连带报告维度不为正.
+                let result = match const_eval(dim_node, self.ctx) {
+                    Ok(ConstValue::Int(value)) => value,
+                    Ok(ConstValue::Float(_)) => {
+                        dim_node.error_spot(
+                            DiagnosticKind::InvalidArray,
+                            format!(
+                                "Error type 15 at this line: Dimension of {} should be an integer",
+                                name
+                            ),
+                        );
+                        1
                     }
-                    new_inits.push(new_node);
-                } else if let Some(ref n_dims) = new_dims {
-                    // 如果是多维初始化列表, 处理.
-                    if scope == &Scope::Global {
-                        new_inits = expand_inits(&n_dims, &init_nodes, true, ctx, 0);
-                    } else {
-                        new_inits = expand_inits(&n_dims, &init_nodes, false, ctx, 0);
+                    Err(diagnostic) => {
+                        report(diagnostic);
+                        1
                     }
-                } else {
-                    node.error_spot(format!("error_spot initializer for {}", name));
-                    unreachable!()
+                };
+                if result <= 0 && !matches!(dim_node.node_type, NodeType::Nil) {
+                    dim_node.error_spot(
+                        DiagnosticKind::InvalidArray,
+                        format!(
+                            "Error type 15 at this line: Dimension of {} should > 0",
+                            name
+                        ),
+                    );
                 }
+                new.push(Node {
+                    id: dim_node.id,
+                    span: dim_node.span,
+                    node_type: Number(result),
+                    basic_type: BasicType::Const, // 这里的basic_type是Const, 因为数组的大小是常量√, 不管你是啥数组。
+                });
+                n.push(result as usize);
+            }
+            if ty == BasicType::Int || matches!(ty, BasicType::IntArray(_)) {
+                ty = BasicType::IntArray(n);
+            } else if ty == BasicType::Const || matches!(ty, BasicType::ConstArray(_)) {
+                ty = BasicType::ConstArray(n);
+            } else if ty == BasicType::Float || matches!(ty, BasicType::FloatArray(_)) {
+                ty = BasicType::FloatArray(n);
+            } else if ty == BasicType::ConstFloat || matches!(ty, BasicType::ConstFloatArray(_)) {
+                ty = BasicType::ConstFloatArray(n);
             }
-            let n_inits = if new_inits.is_empty() {
-                None
+            Some(new)
+        } else {
+            None
+        };
+
+        // step2. 处理初始化列表
+        let is_float = matches!(basic_type, BasicType::Float | BasicType::ConstFloat);
+        let is_const = matches!(basic_type, BasicType::Const | BasicType::ConstFloat);
+        // 常量和全局变量的初始值必须是常量表达式, 在编译期求值.
+        let required = if is_const {
+            Some(ConstInit::Constant(name))
+        } else if scope == &Scope::Global {
+            Some(ConstInit::Global(name))
+        } else {
+            None
+        };
+        let mut new_inits = vec![];
+        if let Some(init_nodes) = inits {
+            // 如果是一维初始化列表, 处理:
+            if new_dims.is_none() && init_nodes.len() == 1 {
+                let new_node = if let Some(required) = required {
+                    // 先做类型检查, 再求出常量值.
+                    self.fold_node(&init_nodes[0]);
+                    fold_init(&init_nodes[0], is_float, self.ctx, required)
+                } else {
+                    // 与赋值相同, 初始值是int/float标量即可, 两者之间隐式转换.
+                    // 已经报过错的占位节点(类型为Nil)不再重复报告.
+                    let new_node = self.fold_node(&init_nodes[0]);
+                    if not_scalar(&new_node.basic_type) {
+                        init_nodes[0].error_spot(
+                            DiagnosticKind::TypeMismatch,
+                            format!(
+                                "Error type 7 at this line: Initializer of {} should be int/float, found `{}`",
+                                name, new_node.basic_type
+                            ),
+                        );
+                    }
+                    convert(new_node, basic_type)
+                };
+                new_inits.push(new_node);
+            } else if let Some(ref n_dims) = new_dims {
+                // 如果是多维初始化列表, 处理. 常量数组和全局数组的每个元素都要求值.
+                new_inits = expand_inits(n_dims, init_nodes, required, is_float, self.ctx, 0);
             } else {
-                Some(new_inits)
-            };
-            // step3. 新声明节点推入作用域
-            let new_node = Node::new(Decl(
+                node.error_spot(
+                    DiagnosticKind::InvalidArray,
+                    format!(
+                        "Error type 15 at this line: Initializer list of {} has more than one element",
+                        name
+                    ),
+                );
+            }
+        }
+        let n_inits = if new_inits.is_empty() {
+            None
+        } else {
+            Some(new_inits)
+        };
+        /*
+            step3. 新声明节点推入作用域. 符号表中的声明带有类型, Access/Assign引用的就是它;
+            之后只有常量的初始值还会被读取(declared_value), 变量的初始值不复制到符号表中.
+        */
+        let decl = Node {
+            id: node.id,
+            span: node.span,
+            node_type: Decl(
                 ty.clone(),
                 name.clone(),
-                new_dims,
-                n_inits,
+                new_dims.clone(),
+                if is_const { n_inits.clone() } else { None },
                 scope.clone(),
-            ));
-            ctx.insert(name.clone(), ty, new_node.clone());
-            new_node
+            ),
+            basic_type: ty.clone(),
+        };
+        self.ctx.insert(name.clone(), ty.clone(), decl);
+        Node {
+            id: node.id,
+            span: node.span,
+            node_type: Decl(ty, name.clone(), new_dims, n_inits, scope.clone()),
+            basic_type: BasicType::Nil,
         }
-        DeclStmt(decls) => {
-            // 用Vec![]来存储声明语句的结果。
-            let mut new_node = vec![];
-            for decl in decls {
-                // 将每一条声明语句的结果处理后都存入Vec![]中,
-                new_node.push(traverse(&decl, ctx));
+    }
+
+    fn fold_decl_stmt(&mut self, node: &Node) -> Node {
+        // 依次处理一条声明语句中的每个声明.
+        fold_children(self, node)
+    }
+
+    fn fold_access(&mut self, node: &Node) -> Node {
+        use NodeType::*;
+        let Access(name, indexes, _) = &node.node_type else {
+            unreachable!()
+        };
+        let (mut basic_type, found) = self.ctx.find(name, node);
+        // 声明的编号和作用域; 未定义时为None, 名字是函数时作用域为None.
+        let decl = found.map(|d| (d.id, d.as_decl().map(|(.., scope)| scope.clone())));
+        if let Some((decl, Some(scope))) = decl {
+            // const形参只是不能修改, 值在编译期未知, 读取时按int/float处理.
+            let is_param = scope == Scope::Params;
+            if is_param {
+                basic_type = match basic_type {
+                    BasicType::Const => BasicType::Int,
+                    BasicType::ConstFloat => BasicType::Float,
+                    other => other,
+                };
             }
-            Node::new(DeclStmt(new_node)) //返回DeclStmt语义的节点
-        }
-        Access(name, indexes, _) => {
-            let (basic_type, n) = ctx.find(name, node);
-            if let NodeType::Decl(_, _, _, _, _) = n.node_type {
-                match &basic_type {
-                    BasicType::Const => {
-                        let num = eval(node, ctx);
-                        let mut new_node = Node {
-                            startpos: node.startpos,
-                            endpos: node.endpos,
-                            node_type: Number(num),
-                            basic_type: BasicType::Const,
+            match &basic_type {
+                BasicType::ConstFloat => Node {
+                    id: node.id,
+                    span: node.span,
+                    node_type: FloatNumber(fold_const(node, self.ctx).as_float()),
+                    basic_type: BasicType::Float,
+                },
+                BasicType::Const => Node {
+                    id: node.id,
+                    span: node.span,
+                    node_type: Number(fold_const(node, self.ctx).as_int()),
+                    basic_type: BasicType::Const,
+                },
+                BasicType::Int => Node {
+                    id: node.id,
+                    span: node.span,
+                    node_type: Access(name.clone(), indexes.clone(), decl),
+                    basic_type: BasicType::Int,
+                },
+                BasicType::Float => Node {
+                    id: node.id,
+                    span: node.span,
+                    node_type: Access(name.clone(), indexes.clone(), decl),
+                    basic_type: BasicType::Float,
+                },
+                BasicType::IntArray(dims)
+                | BasicType::ConstArray(dims)
+                | BasicType::FloatArray(dims)
+                | BasicType::ConstFloatArray(dims) => {
+                    if indexes.is_none() {
+                        return Node {
+                            id: node.id,
+                            span: node.span,
+                            node_type: Access(name.clone(), None, decl),
+                            basic_type: basic_type.clone(),
                         };
-                        new_node.basic_type = BasicType::Const;
-                        return new_node;
                     }
-                    BasicType::Int => {
-                        let mut nn = n.clone();
-                        nn.basic_type = basic_type.clone();
-                        Node {
-                            startpos: node.startpos,
-                            endpos: node.endpos,
-                            node_type: Access(name.clone(), indexes.clone(), Box::new(nn)),
-                            basic_type: BasicType::Int,
+                    let mut new_indexes = vec![];
+                    for index in indexes.as_ref().unwrap() {
+                        let new_index = self.fold_node(index);
+                        if !matches!(
+                            new_index.basic_type,
+                            BasicType::Int | BasicType::Const | BasicType::Nil
+                        ) {
+                            node.error_spot(
+                                DiagnosticKind::TypeMismatch,
+                                format!(
+                                    "Error type 7 at this line: Index of {} should be int or const",
+                                    name
+                                ),
+                            );
                         }
+                        new_indexes.push(new_index);
                     }
-                    BasicType::Float => {
-                        let mut nn = n.clone();
-                        nn.basic_type = basic_type.clone();
-                        Node {
-                            startpos: node.startpos,
-                            endpos: node.endpos,
-                            node_type: Access(name.clone(), indexes.clone(), Box::new(nn)),
-                            basic_type: BasicType::Float,
-                        }
+                    let dim_len = dims.len();
+                    let index_len = new_indexes.len();
+                    if index_len > dim_len {
+                        node.error_spot(
+                            DiagnosticKind::IndexMismatch,
+                            format!(
+                                "Error type 8 at this line: {} has {} dimensions but {} indexes",
+                                name, dim_len, index_len
+                            ),
+                        );
+                        return node.placeholder();
                     }
-                    BasicType::IntArray(dims) | BasicType::ConstArray(dims) => {
-                        if indexes.is_none() {
-                            let mut nn = n.clone();
-                            nn.basic_type = basic_type.clone();
-                            return Node {
-                                startpos: node.startpos,
-                                endpos: node.endpos,
-                                node_type: Access(name.clone(), None, Box::new(nn)),
-                                basic_type: basic_type.clone(),
-                            };
+                    check_bounds(name, dims, &new_indexes);
+                    let bty = if matches!(&basic_type, BasicType::IntArray(_)) {
+                        if index_len == dim_len {
+                            BasicType::Int
+                        } else {
+                            let arr = dims[index_len..dim_len].to_vec();
+                            BasicType::IntArray(arr)
                         }
-                        let mut new_indexes = vec![];
-                        for index in indexes.as_ref().unwrap() {
-                            let new_index = traverse(&index, ctx);
-                            if new_index.basic_type != BasicType::Int
-                                && new_index.basic_type != BasicType::Const
-                            {
-                                node.error_spot(format!(
-                                    "Index of {} should be int or const",
-                                    name
-                                ));
-                            }
-                            new_indexes.push(new_index);
+                    } else if matches!(&basic_type, BasicType::FloatArray(_)) {
+                        if index_len == dim_len {
+                            BasicType::Float
+                        } else {
+                            let arr = dims[index_len..dim_len].to_vec();
+                            BasicType::FloatArray(arr)
                         }
-                        let dim_len = dims.len();
-                        let index_len = new_indexes.len();
-                        let bty = if matches!(&basic_type, BasicType::IntArray(_)) {
-                            if index_len == dim_len {
-                                BasicType::Int
-                            } else {
-                                let arr = dims[index_len..dim_len].to_vec();
-                                BasicType::IntArray(arr)
-                            }
+                    } else if matches!(&basic_type, BasicType::ConstFloatArray(_)) {
+                        // 表达式中没有const float类型, 元素按float读取, 常量表达式中由const_eval求值.
+                        if index_len == dim_len {
+                            BasicType::Float
                         } else {
-                            if index_len == dim_len {
-                                BasicType::Const
-                            } else {
-                                let arr = dims[index_len..dim_len].to_vec();
-                                BasicType::ConstArray(arr)
-                            }
-                        };
-                        let mut nn = n.clone();
-                        nn.basic_type = basic_type.clone();
-                        Node {
-                            startpos: node.startpos,
-                            endpos: node.endpos,
-                            node_type: Access(name.clone(), Some(new_indexes), Box::new(nn)),
-                            basic_type: bty,
+                            let arr = dims[index_len..dim_len].to_vec();
+                            BasicType::ConstFloatArray(arr)
+                        }
+                    } else {
+                        // 下标都是常量时元素才是编译期常量; const数组形参的元素总是运行时的值.
+                        let constant = !is_param
+                            && new_indexes.iter().all(|i| i.basic_type == BasicType::Const);
+                        if index_len == dim_len && constant {
+                            BasicType::Const
+                        } else if index_len == dim_len {
+                            BasicType::Int
+                        } else {
+                            let arr = dims[index_len..dim_len].to_vec();
+                            BasicType::ConstArray(arr)
                         }
+                    };
+                    Node {
+                        id: node.id,
+                        span: node.span,
+                        node_type: Access(name.clone(), Some(new_indexes), decl),
+                        basic_type: bty,
                     }
-                    _ => unreachable!(),
                 }
-            } else {
-                node.error_spot(format!(
-                    "Error type 6 : {} cannot be accessed since it is a function",
-                    name
-                ));
-                //unreachable!()
-                Node::new(Nil)
+                _ => unreachable!(),
             }
+        } else if decl.is_none() {
+            // 未定义, find已经报告过.
+            node.placeholder()
+        } else {
+            node.error_spot(
+                DiagnosticKind::FunctionAsValue,
+                format!(
+                    "Error type 6 at this line: {} cannot be accessed since it is a function",
+                    name
+                ),
+            );
+            //unreachable!()
+            node.placeholder()
         }
-        Assign(name, indexes, expr, _) => {
-            let (basic_type, n) = ctx.find(name, node);
-            if let Decl(_, _, _, _, _) = n.node_type {
-                match &basic_type {
-                    BasicType::Const | BasicType::ConstArray(_) => {
-                        node.error_spot(format!("Cannot assign to constant {}", name));
-                        unreachable!()
+    }
+
+    fn fold_assign(&mut self, node: &Node) -> Node {
+        use NodeType::*;
+        let Assign(name, indexes, expr, _) = &node.node_type else {
+            unreachable!()
+        };
+        let (basic_type, found) = self.ctx.find(name, node);
+        let decl = found.map(|d| (d.id, matches!(d.node_type, Decl(..))));
+        if let Some((decl, true)) = decl {
+            match &basic_type {
+                BasicType::Const
+                | BasicType::ConstFloat
+                | BasicType::ConstArray(_)
+                | BasicType::ConstFloatArray(_) => {
+                    node.error_spot(
+                        DiagnosticKind::AssignToConstant,
+                        format!(
+                            "Error type 14 at this line: Cannot assign to constant {}",
+                            name
+                        ),
+                    );
+                    node.placeholder()
+                }
+                BasicType::Int | BasicType::Float => {
+                    if indexes.is_some() {
+                        node.error_spot(DiagnosticKind::IndexMismatch, format!(
+                            "Error type 8 at this line: Variable {} should not have indexes in assign",
+                            name
+                        ));
                     }
-                    BasicType::Int => {
-                        if indexes.is_some() {
-                            node.error_spot(format!(
-                                "Error type 8 at this line: Integer {} should not have indexes in assign",
-                                name
-                            ));
-                        }
-                        let new_expr = traverse(expr, ctx);
-                        if new_expr.basic_type != BasicType::Int
-                            && new_expr.basic_type != BasicType::Const
-                        {
-                            node.error_spot(format!(
-                                "Error type 7 at this line: Should assign int/const to int"
-                            ))
-                        }
-                        Node {
-                            startpos: node.startpos,
-                            endpos: node.endpos,
-                            node_type: Assign(
-                                name.clone(),
-                                None,
-                                Box::new(new_expr),
-                                Box::new(n.clone()),
+                    // int和float之间隐式转换.
+                    let new_expr = self.fold_node(expr);
+                    if not_scalar(&new_expr.basic_type) {
+                        node.error_spot(
+                            DiagnosticKind::TypeMismatch,
+                            format!(
+                                "Error type 7 at this line: Should assign int/float to {}",
+                                basic_type
                             ),
-                            basic_type: BasicType::Nil,
-                        }
+                        )
                     }
-                    BasicType::IntArray(dims) => {
-                        if indexes.is_none() {
-                            node.error_spot(format!(
-                                "Integer array {} should have indexes in assign",
+                    let new_expr = convert(new_expr, &basic_type);
+                    Node {
+                        id: node.id,
+                        span: node.span,
+                        node_type: Assign(name.clone(), None, Box::new(new_expr), decl),
+                        basic_type: BasicType::Nil,
+                    }
+                }
+                BasicType::IntArray(dims) | BasicType::FloatArray(dims) => {
+                    if indexes.is_none() {
+                        node.error_spot(
+                            DiagnosticKind::IndexMismatch,
+                            format!(
+                                "Error type 8 at this line: Array {} should have indexes in assign",
                                 name
-                            ));
-                        }
-                        let new_expr = traverse(expr, ctx);
-                        if new_expr.basic_type != BasicType::Int
-                            && new_expr.basic_type != BasicType::Const
-                        {
-                            node.error_spot(format!("Should assign int/const to int"));
-                        }
-                        if indexes.as_ref().unwrap().len() != dims.len() {
-                            node.error_spot(format!(
-                                "Indexes of {} should be {} instead of {}",
+                            ),
+                        );
+                        return node.placeholder();
+                    }
+                    let new_expr = self.fold_node(expr);
+                    if not_scalar(&new_expr.basic_type) {
+                        node.error_spot(
+                            DiagnosticKind::TypeMismatch,
+                            "Error type 7 at this line: Should assign int/float to an array element".to_string(),
+                        );
+                    }
+                    let element_type = if matches!(basic_type, BasicType::FloatArray(_)) {
+                        BasicType::Float
+                    } else {
+                        BasicType::Int
+                    };
+                    let new_expr = convert(new_expr, &element_type);
+                    if indexes.as_ref().unwrap().len() != dims.len() {
+                        node.error_spot(
+                            DiagnosticKind::IndexMismatch,
+                            format!(
+                                "Error type 8 at this line: Indexes of {} should be {} instead of {}",
                                 name,
                                 dims.len(),
                                 indexes.as_ref().unwrap().len()
-                            ))
-                        }
-                        let mut new_indexes = vec![];
-                        for index in indexes.as_ref().unwrap() {
-                            let new_index = traverse(&index, ctx);
-                            if new_index.basic_type != BasicType::Int
-                                && new_index.basic_type != BasicType::Const
-                            {
-                                node.error_spot(format!(
-                                    "Error type 7 at this line: Index of array `{}` is not an integer",
-                                    name, 
-                                ));
-                            }
-                            new_indexes.push(new_index);
-                        }
-
-                        let mut decl_node = n.clone();
-                        decl_node.basic_type = basic_type;
-                        Node {
-                            startpos: node.startpos,
-                            endpos: node.endpos,
-                            node_type: Assign(
-                                name.clone(),
-                                Some(new_indexes),
-                                Box::new(new_expr),
-                                Box::new(decl_node),
                             ),
-                            basic_type: BasicType::Nil,
+                        )
+                    }
+                    let mut new_indexes = vec![];
+                    for index in indexes.as_ref().unwrap() {
+                        let new_index = self.fold_node(index);
+                        if !matches!(
+                            new_index.basic_type,
+                            BasicType::Int | BasicType::Const | BasicType::Nil
+                        ) {
+                            node.error_spot(
+                                DiagnosticKind::TypeMismatch,
+                                format!(
+                                "Error type 7 at this line: Index of array `{}` is not an integer",
+                                name,
+                            ),
+                            );
                         }
+                        new_indexes.push(new_index);
+                    }
+                    check_bounds(name, dims, &new_indexes);
+
+                    Node {
+                        id: node.id,
+                        span: node.span,
+                        node_type: Assign(
+                            name.clone(),
+                            Some(new_indexes),
+                            Box::new(new_expr),
+                            decl,
+                        ),
+                        basic_type: BasicType::Nil,
                     }
-                    _ => unreachable!(),
                 }
-            } else {   
-                node.error_spot(format!(
+                _ => unreachable!(),
+            }
+        } else if decl.is_none() {
+            node.placeholder()
+        } else {
+            node.error_spot(
+                DiagnosticKind::FunctionAsValue,
+                format!(
                     "Error type 6 at this line: You can't use a function like a variable: `{}` !",
                     name
-                ));
-                Node::new(NodeType::Nil)
-            }
+                ),
+            );
+            node.placeholder()
         }
-        BinOp(ttype, lhs, rhs) => {
-            let new_lhs = traverse(&lhs, ctx);
-            if new_lhs.basic_type != BasicType::Int && new_lhs.basic_type != BasicType::Const {
-                lhs.error_spot(format!(
-                    "Error type 11 at this line: type mismatched for operands."
-                ));
-            }
-            let new_rhs = traverse(&rhs, ctx);
-            if new_rhs.basic_type != BasicType::Int && new_rhs.basic_type != BasicType::Const {
-                rhs.error_spot(format!(
-                    "Error type 11 at this line: type mismatched for operands."
-                ));
+    }
+
+    fn fold_binop(&mut self, node: &Node) -> Node {
+        use NodeType::*;
+        let BinOp(ttype, lhs, rhs) = &node.node_type else {
+            unreachable!()
+        };
+        // 运算数都必须是int/float标量, '%'只能用于int.
+        let mut new_lhs = self.fold_node(lhs);
+        // &&和||的左操作数是常量并且已经决定了结果时, 右操作数不会被求值, 其中的除以0不算错误.
+        let short_circuits = match (ttype, &new_lhs.node_type) {
+            (TokenType::And, Number(n)) => *n == 0,
+            (TokenType::Or, Number(n)) => *n != 0,
+            _ => false,
+        };
+        let mark = pending_len();
+        let mut new_rhs = self.fold_node(rhs);
+        if short_circuits {
+            discard_unevaluated(mark);
+        }
+        for (operand, new_operand) in [(lhs, &new_lhs), (rhs, &new_rhs)] {
+            if not_scalar(&new_operand.basic_type) {
+                operand.error_spot(
+                    DiagnosticKind::TypeMismatch,
+                    "Error type 11 at this line: type mismatched for operands.".to_string(),
+                );
+            } else if *ttype == TokenType::Mods && new_operand.basic_type == BasicType::Float {
+                operand.error_spot(
+                    DiagnosticKind::TypeMismatch,
+                    "Error type 11 at this line: operands of '%' should be int".to_string(),
+                );
             }
-            if new_lhs.basic_type == BasicType::Const && new_rhs.basic_type == BasicType::Const {
+        }
+        if new_lhs.basic_type == BasicType::Const && new_rhs.basic_type == BasicType::Const {
+            return Node {
+                id: node.id,
+                span: node.span,
+                node_type: Number(fold_const(node, self.ctx).as_int()),
+                basic_type: BasicType::Const,
+            };
+        }
+        // 算术和比较运算有一边是float时, 另一边先转换成float; &&和||的两边各自与0比较, 不需要转换.
+        let operand_type = arithmetic_type(&new_lhs.basic_type, &new_rhs.basic_type);
+        if !matches!(ttype, TokenType::And | TokenType::Or) {
+            new_lhs = convert(new_lhs, &operand_type);
+            new_rhs = convert(new_rhs, &operand_type);
+        }
+        // 关系和逻辑运算的结果总是int; 算术运算的结果是转换后的类型.
+        let basic_type = if is_relational(ttype) {
+            BasicType::Int
+        } else {
+            operand_type
+        };
+        Node {
+            id: node.id,
+            span: node.span,
+            node_type: BinOp(ttype.clone(), Box::new(new_lhs), Box::new(new_rhs)),
+            basic_type,
+        }
+    }
+
+    fn fold_unary(&mut self, node: &Node) -> Node {
+        use NodeType::*;
+        let UnaryOp(ttype, operand) = &node.node_type else {
+            unreachable!()
+        };
+        let new_operand = self.fold_node(operand);
+        let basic_type = match (&new_operand.basic_type, ttype) {
+            (BasicType::Const, _) => {
                 return Node {
-                    startpos: node.startpos,
-                    endpos: node.endpos,
-                    node_type: Number(eval(node, ctx)),
+                    id: node.id,
+                    span: node.span,
+                    node_type: Number(fold_const(node, self.ctx).as_int()),
                     basic_type: BasicType::Const,
                 };
             }
-            Node {
-                startpos: node.startpos,
-                endpos: node.endpos,
-                node_type: BinOp(ttype.clone(), Box::new(new_lhs), Box::new(new_rhs)),
-                basic_type: BasicType::Int,
-            }
-        }
-        /*---------第二类:Expression---------------*/
-        ExprStmt(expr) => Node {
-            startpos: node.startpos,
-            endpos: node.endpos,
-            node_type: ExprStmt(Box::new(traverse(expr, ctx))),
-            basic_type: BasicType::Nil,
-        },
-        /*---------第三类:Function-----------------*/
-        Call(name, call_args, _) => {
-            let (_, n) = ctx.find(&name, node);
-            if let Func(ret, _, def_args, _) = &n.node_type {
-                if call_args.len() != def_args.len() {
-                    node.error_spot(format!(
-                        "Error type 9 at this line: Argument length of {} should be {} instead of {}",
-                        name,
-                        def_args.len(),
-                        call_args.len()
-                    ));
-                }
-                let mut new_call_args = vec![];
-                for (call_arg, def_arg) in call_args.iter().zip(def_args.iter()) {
-                    let new_call_arg = traverse(&call_arg, ctx);
-                    new_call_args.push(new_call_arg.clone());
-                    //Both int/const
-                    if let Decl(def_basic_type, _, _, _, _) = &def_arg.node_type {
-                        if def_basic_type == &BasicType::Int
-                            && (new_call_arg.basic_type == BasicType::Int
-                                || new_call_arg.basic_type == BasicType::Const)
-                        {
-                            continue;
-                        }
-                    }
-                    //Both array
-                    if let Decl(def_basic_type, _, _, _, _) = &def_arg.node_type {
-                        if let BasicType::IntArray(def_dims) = def_basic_type {
-                            if let BasicType::IntArray(call_dims) = &new_call_arg.basic_type {
-                                for (call_dim, def_dim) in
-                                    call_dims.iter().zip(def_dims.iter()).skip(1)
-                                {
-                                    if call_dim != def_dim {
-                                        call_arg.error_spot(format!(
-                                            "error_spot dimension in function call {}",
-                                            name
-                                        ));
-                                    }
-                                }
-                                continue;
-                            }
-                        }
-                    }
-                    //Others
-                    call_arg.error_spot(format!(
-                        "Error type 10 at this line: Unmatched type in function call {}",
-                        name
-                    ));
-                }
-                Node {
-                    startpos: node.startpos,
-                    endpos: node.endpos,
-                    node_type: Call(name.clone(), new_call_args, Box::new(n.clone())),
-                    basic_type: ret.clone(),
+            (BasicType::Int, _) => BasicType::Int,
+            (BasicType::Nil, _) => BasicType::Nil,
+            (BasicType::Float, TokenType::Not) => BasicType::Int,
+            (BasicType::Float, _) => {
+                // 浮点字面量的正负号直接折叠, 如-1.5.
+                if let FloatNumber(num) = new_operand.node_type {
+                    let value = if *ttype == TokenType::Minus {
+                        -num
+                    } else {
+                        num
+                    };
+                    return Node {
+                        id: node.id,
+                        span: node.span,
+                        node_type: FloatNumber(value),
+                        basic_type: BasicType::Float,
+                    };
                 }
-            } else {
-                node.error_spot(format!(
-                    "Error type 5 at this line: {} is not a function!",
-                    name
-                ));
-                Node::new(NodeType::Nil)
+                BasicType::Float
             }
-        }
-        Func(ret, name, args, body) => {
-            ctx.set_cur_func(name, ret);
-            let mut new_args = vec![];
-            ctx.enter_scope();
-            for arg in args {
-                new_args.push(traverse(arg, ctx));
+            _ => {
+                operand.error_spot(
+                    DiagnosticKind::TypeMismatch,
+                    "Error type 11 at this line: type mismatched for operands.".to_string(),
+                );
+                BasicType::Int
             }
-            ctx.insert(
-                name.clone(),
-                BasicType::Func(Box::new(ret.clone())),
-                Node::new(NodeType::Func(
-                    ret.clone(),
-                    name.clone(),
-                    new_args.clone(),
-                    body.clone(),
-                )),
+        };
+        Node {
+            id: node.id,
+            span: node.span,
+            node_type: UnaryOp(ttype.clone(), Box::new(new_operand)),
+            basic_type,
+        }
+    }
+
+    fn fold_cond(&mut self, node: &Node) -> Node {
+        use NodeType::*;
+        let Cond(cond, on_true, on_false) = &node.node_type else {
+            unreachable!()
+        };
+        let new_cond = self.fold_node(cond);
+        if not_scalar(&new_cond.basic_type) {
+            cond.error_spot(
+                DiagnosticKind::TypeMismatch,
+                "Error type 11 at this line: Condition of conditional expression should be int/float"
+                    .to_string(),
             );
-            let new_body = traverse(body, ctx);
-            ctx.exit_scope();
-            Node {
-                startpos: node.startpos,
-                endpos: node.endpos,
-                node_type: Func(ret.clone(), name.clone(), new_args, Box::new(new_body)),
-                basic_type: BasicType::Nil,
-            }
         }
-        Block(stmts) => {
-            ctx.enter_scope();
-            let mut new_stmts = vec![];
-            for stmt in stmts {
-                new_stmts.push(traverse(&stmt, ctx));
-            }
-            ctx.exit_scope();
-            Node {
-                startpos: node.startpos,
-                endpos: node.endpos,
-                node_type: Block(new_stmts),
-                basic_type: BasicType::Nil,
+        let new_on_true = self.fold_node(on_true);
+        let new_on_false = self.fold_node(on_false);
+        // 两个分支都必须是int/float标量, 有一边是float时结果为float.
+        for (arm, new_arm) in [(on_true, &new_on_true), (on_false, &new_on_false)] {
+            if not_scalar(&new_arm.basic_type) {
+                arm.error_spot(DiagnosticKind::TypeMismatch, format!(
+                    "Error type 11 at this line: branch of conditional expression should be int/float, found `{}`",
+                    new_arm.basic_type
+                ));
             }
         }
-        Return(expr) => {
-            let new_expr: Option<Box<Node>>;
-            let mut ret_type: BasicType;
-            let (_, ret) = ctx.get_cur_func();
-            if let Some(exp) = expr {
-                let new_exp = traverse(exp, ctx);
-                ret_type = new_exp.basic_type.clone();
-                new_expr = Some(Box::new(new_exp));
-            } else {
-                ret_type = BasicType::Void;
-                new_expr = None;
+        let all_const = [&new_cond, &new_on_true, &new_on_false]
+            .iter()
+            .all(|n| n.basic_type == BasicType::Const);
+        if all_const {
+            return Node {
+                id: node.id,
+                span: node.span,
+                node_type: Number(fold_const(node, self.ctx).as_int()),
+                basic_type: BasicType::Const,
+            };
+        }
+        let basic_type = arithmetic_type(&new_on_true.basic_type, &new_on_false.basic_type);
+        let new_on_true = convert(new_on_true, &basic_type);
+        let new_on_false = convert(new_on_false, &basic_type);
+        Node {
+            id: node.id,
+            span: node.span,
+            node_type: Cond(
+                Box::new(new_cond),
+                Box::new(new_on_true),
+                Box::new(new_on_false),
+            ),
+            basic_type,
+        }
+    }
+
+    fn fold_comma(&mut self, node: &Node) -> Node {
+        let NodeType::Comma(operands) = &node.node_type else {
+            unreachable!()
+        };
+        self.discard_calls(&operands[..operands.len() - 1]);
+        let mut new_node = fold_children(self, node);
+        let NodeType::Comma(new_operands) = &new_node.node_type else {
+            unreachable!()
+        };
+        // 值是最后一个操作数; 最后是赋值时值为被赋值的变量(元素). 逗号表达式不是常量.
+        let last = new_operands.last().unwrap();
+        new_node.basic_type = match (&last.node_type, &last.basic_type) {
+            (NodeType::Assign(.., decl), _) => match self.ctx.declaration(*decl) {
+                Some(Node {
+                    basic_type: BasicType::Float | BasicType::FloatArray(_),
+                    ..
+                }) => BasicType::Float,
+                _ => BasicType::Int,
+            },
+            (_, BasicType::Const) => BasicType::Int,
+            (_, basic_type) => basic_type.clone(),
+        };
+        new_node
+    }
+
+    /*---------第二类:Expression---------------*/
+    fn fold_expr_stmt(&mut self, node: &Node) -> Node {
+        let NodeType::ExprStmt(expr) = &node.node_type else {
+            unreachable!()
+        };
+        self.discard_calls(std::slice::from_ref(expr));
+        fold_children(self, node)
+    }
+
+    /*---------第三类:Function-----------------*/
+    /*---------第三类:Function-----------------*/
+    fn fold_call(&mut self, node: &Node) -> Node {
+        use NodeType::*;
+        let Call(name, call_args, _) = &node.node_type else {
+            unreachable!()
+        };
+        let (_, found) = self.ctx.find(name, node);
+        let undefined = found.is_none();
+        // 只取出被调函数的编号和签名(返回类型, 形参), 符号表中的声明节点不被复制.
+        let callee = found.and_then(|f| {
+            let (ret, _, params, _) = f.as_func()?;
+            Some((f.id, ret.clone(), params.to_vec(), is_variadic(f)))
+        });
+        if let Some((callee, mut ret, def_args, variadic)) = callee {
+            // void函数的调用没有值, 只能单独作为语句; 报告后按int继续, 不再连带报告类型不符.
+            if ret == BasicType::Void && !self.discarded.contains(&node.id) {
+                let diagnostic = match self.arguments.get(&node.id) {
+                    Some(outer) => Diagnostic::error(format!(
+                        "Error type 10 at this line: void function {} passed as an argument to {}",
+                        name, outer
+                    )),
+                    None => Diagnostic::error(format!(
+                        "Error type 7 at this line: void function {} used as a value",
+                        name
+                    )),
+                };
+                report(
+                    diagnostic
+                        .with_kind(DiagnosticKind::TypeMismatch)
+                        .with_label(node.span, "this call has no value"),
+                );
+                ret = BasicType::Int;
             }
-            if ret_type == BasicType::Const {
-                ret_type = BasicType::Int;
+            if variadic {
+                let new_call_args = self.check_format_args(node);
+                return Node {
+                    id: node.id,
+                    span: node.span,
+                    node_type: Call(name.clone(), new_call_args, callee),
+                    basic_type: ret.clone(),
+                };
+            }
+            if call_args.len() != def_args.len() {
+                node.error_spot(
+                    DiagnosticKind::ArgumentCount,
+                    format!(
+                    "Error type 9 at this line: Argument length of {} should be {} instead of {}",
+                    name,
+                    def_args.len(),
+                    call_args.len()
+                ),
+                );
             }
-            if ret_type != ret {
-                node.error_spot(format!("Error type 10 at this line : type mismatched for return"));
+            let mut new_call_args = vec![];
+            for (call_arg, def_arg) in call_args.iter().zip(def_args.iter()) {
+                if let Str(_) = &call_arg.node_type {
+                    call_arg.error_spot(DiagnosticKind::TypeMismatch, format!(
+                        "Error type 10 at this line: string literal passed to {}, only putf takes a format string",
+                        name
+                    ));
+                    new_call_args.push(call_arg.clone());
+                    continue;
+                }
+                if let Call(..) = &call_arg.node_type {
+                    self.arguments.insert(call_arg.id, name.clone());
+                }
+                let new_call_arg = self.fold_node(call_arg);
+                // 标量实参转换成形参的类型.
+                let param_type = match &def_arg.node_type {
+                    Decl(ty, ..) => ty.clone(),
+                    _ => BasicType::Nil,
+                };
+                new_call_args.push(convert(new_call_arg.clone(), &param_type));
+                //Both scalar: int/const/float之间隐式转换.
+                if let Decl(def_basic_type, _, _, _, _) = &def_arg.node_type {
+                    if is_scalar(def_basic_type) && is_scalar(&new_call_arg.basic_type) {
+                        continue;
+                    }
+                }
+                //Both array: 元素类型相同, 除第一维外, 其余各维长度必须一致.
+                if let Decl(
+                    def_basic_type @ (BasicType::IntArray(def_dims)
+                    | BasicType::ConstArray(def_dims)
+                    | BasicType::FloatArray(def_dims)
+                    | BasicType::ConstFloatArray(def_dims)),
+                    param_name,
+                    _,
+                    _,
+                    _,
+                ) = &def_arg.node_type
+                {
+                    if let (
+                        BasicType::IntArray(call_dims) | BasicType::ConstArray(call_dims),
+                        BasicType::IntArray(_) | BasicType::ConstArray(_),
+                    )
+                    | (
+                        BasicType::FloatArray(call_dims) | BasicType::ConstFloatArray(call_dims),
+                        BasicType::FloatArray(_) | BasicType::ConstFloatArray(_),
+                    ) = (&new_call_arg.basic_type, def_basic_type)
+                    {
+                        // const数组只能传给const形参, 否则函数中可以修改它.
+                        if matches!(
+                            (&new_call_arg.basic_type, def_basic_type),
+                            (BasicType::ConstArray(_), BasicType::IntArray(_))
+                                | (BasicType::ConstFloatArray(_), BasicType::FloatArray(_))
+                        ) {
+                            report(argument_mismatch(
+                                format!(
+                                    "constant array passed to non-const parameter `{}` in function call {}",
+                                    param_name, name
+                                ),
+                                call_arg,
+                                &new_call_arg.basic_type,
+                                def_arg,
+                            ));
+                        }
+                        let matched = call_dims.len() == def_dims.len()
+                            && call_dims
+                                .iter()
+                                .zip(def_dims.iter())
+                                .skip(1)
+                                .all(|(call_dim, def_dim)| call_dim == def_dim);
+                        if !matched {
+                            report(argument_mismatch(
+                                format!(
+                                    "mismatched array shape for parameter `{}` in function call {}",
+                                    param_name, name
+                                ),
+                                call_arg,
+                                &new_call_arg.basic_type,
+                                def_arg,
+                            ));
+                        }
+                        continue;
+                    }
+                }
+                //Others: 数组与标量互传, 或数组的元素类型不同. 已经报过错的实参(类型为Nil)不再报告.
+                let found = &new_call_arg.basic_type;
+                if *found == BasicType::Nil {
+                    continue;
+                }
+                if let Decl(def_basic_type, param_name, ..) = &def_arg.node_type {
+                    let what = if is_scalar(def_basic_type) && is_array(found) {
+                        "array passed to scalar parameter"
+                    } else if is_array(def_basic_type) && is_scalar(found) {
+                        "scalar passed to array parameter"
+                    } else if is_array(def_basic_type) && is_array(found) {
+                        "mismatched array element type for parameter"
+                    } else {
+                        "unmatched type for parameter"
+                    };
+                    let msg = format!("{} `{}` in function call {}", what, param_name, name);
+                    report(argument_mismatch(msg, call_arg, found, def_arg));
+                    continue;
+                }
+                call_arg.error_spot(
+                    DiagnosticKind::TypeMismatch,
+                    format!(
+                        "Error type 10 at this line: Unmatched type in function call {}",
+                        name
+                    ),
+                );
             }
             Node {
-                startpos: node.startpos,
-                endpos: node.endpos,
-                node_type: Return(new_expr),
-                basic_type: BasicType::Nil,
+                id: node.id,
+                span: node.span,
+                node_type: Call(name.clone(), new_call_args, callee),
+                basic_type: ret.clone(),
             }
+        } else if undefined {
+            node.placeholder()
+        } else {
+            node.error_spot(
+                DiagnosticKind::NotAFunction,
+                format!("Error type 5 at this line: {} is not a function!", name),
+            );
+            node.placeholder()
         }
-        /*---------第四类:Control flow-------------*/
-        If(cond, on_true, on_false) => {
-            let new_cond = traverse(cond, ctx);
-            if new_cond.basic_type != BasicType::Int && new_cond.basic_type != BasicType::Const {
-                node.error_spot(format!("Condition of if statement should be int/const"));
-            }
-            let new_on_false = if let Some(on_false_block) = on_false {
-                Some(Box::new(traverse(on_false_block, ctx)))
-            } else {
-                None
-            };
-            Node {
-                startpos: node.startpos,
-                endpos: node.endpos,
-                node_type: If(
-                    Box::new(new_cond),
-                    Box::new(traverse(on_true, ctx)),
-                    new_on_false,
+    }
+
+    fn fold_func(&mut self, node: &Node) -> Node {
+        use NodeType::*;
+        let Func(ret, name, args, body) = &node.node_type else {
+            unreachable!()
+        };
+        self.ctx.set_cur_func(name, ret);
+        let mut new_args = vec![];
+        self.ctx.enter_scope();
+        for arg in args {
+            new_args.push(self.fold_node(arg));
+        }
+        /*
+            符号表中的函数节点与AST中的编号相同, Call中的被调函数引用的就是它.
+            它只需要签名: 函数体用Empty代替(原型仍是Nil), 不复制整个函数体.
+        */
+        let marker = if is_prototype(body) { Nil } else { Empty };
+        let func = Node {
+            id: node.id,
+            span: node.span,
+            node_type: Func(
+                ret.clone(),
+                name.clone(),
+                new_args.clone(),
+                Box::new(Node::new(marker)),
+            ),
+            basic_type: BasicType::Nil,
+        };
+        self.ctx
+            .insert(name.clone(), BasicType::Func(Box::new(ret.clone())), func);
+        // 函数原型没有函数体, 原样保留Nil.
+        let new_body = if is_prototype(body) {
+            body.as_ref().clone()
+        } else {
+            self.fold_node(body)
+        };
+        self.ctx.exit_scope();
+        Node {
+            id: node.id,
+            span: node.span,
+            node_type: Func(ret.clone(), name.clone(), new_args, Box::new(new_body)),
+            basic_type: BasicType::Nil,
+        }
+    }
+
+    fn fold_block(&mut self, node: &Node) -> Node {
+        self.ctx.enter_scope();
+        let new_node = fold_children(self, node);
+        self.ctx.exit_scope();
+        new_node
+    }
+
+    fn fold_return(&mut self, node: &Node) -> Node {
+        use NodeType::*;
+        let Return(expr) = &node.node_type else {
+            unreachable!()
+        };
+        let new_expr: Option<Box<Node>>;
+        let mut ret_type: BasicType;
+        let (func, ret) = self.ctx.get_cur_func();
+        if let Some(exp) = expr {
+            let new_exp = self.fold_node(exp);
+            ret_type = new_exp.basic_type.clone();
+            new_expr = Some(Box::new(convert(new_exp, &ret)));
+        } else {
+            ret_type = BasicType::Void;
+            new_expr = None;
+        }
+        if ret_type == BasicType::Const {
+            ret_type = BasicType::Int;
+        }
+        // int和float函数的返回值之间隐式转换.
+        let converts = matches!(ret, BasicType::Int | BasicType::Float) && is_scalar(&ret_type);
+        if ret == BasicType::Void && expr.is_some() {
+            node.error_spot(
+                DiagnosticKind::TypeMismatch,
+                format!(
+                    "Error type 10 at this line: void function {} should not return a value",
+                    func
                 ),
+            );
+        } else if ret != BasicType::Void && expr.is_none() {
+            node.error_spot(
+                DiagnosticKind::TypeMismatch,
+                format!(
+                    "Error type 10 at this line: function {} should return a value of type {}",
+                    func, ret
+                ),
+            );
+        } else if ret_type != ret && !converts && ret_type != BasicType::Nil {
+            node.error_spot(
+                DiagnosticKind::TypeMismatch,
+                "Error type 10 at this line: type mismatched for return".to_string(),
+            );
+        }
+        Node {
+            id: node.id,
+            span: node.span,
+            node_type: Return(new_expr),
+            basic_type: BasicType::Nil,
+        }
+    }
+
+    /*---------第四类:Control flow-------------*/
+    /*---------第四类:Control flow-------------*/
+    fn fold_if(&mut self, node: &Node) -> Node {
+        use NodeType::*;
+        let If(cond, on_true, on_false) = &node.node_type else {
+            unreachable!()
+        };
+        let new_cond = self.fold_node(cond);
+        if not_scalar(&new_cond.basic_type) {
+            node.error_spot(
+                DiagnosticKind::TypeMismatch,
+                "Error type 11 at this line: Condition of if statement should be int/float"
+                    .to_string(),
+            );
+        }
+        warn_constant_condition("if", cond, &new_cond);
+        let new_on_false = on_false
+            .as_ref()
+            .map(|on_false_block| Box::new(self.fold_node(on_false_block)));
+        Node {
+            id: node.id,
+            span: node.span,
+            node_type: If(
+                Box::new(new_cond),
+                Box::new(self.fold_node(on_true)),
+                new_on_false,
+            ),
+            basic_type: BasicType::Nil,
+        }
+    }
+
+    fn fold_while(&mut self, node: &Node) -> Node {
+        use NodeType::*;
+        let While(cond, body) = &node.node_type else {
+            unreachable!()
+        };
+        let new_cond = self.fold_node(cond);
+        if not_scalar(&new_cond.basic_type) {
+            node.error_spot(
+                DiagnosticKind::TypeMismatch,
+                "Error type 11 at this line: Condition of while statement should be int/float"
+                    .to_string(),
+            );
+        }
+        warn_constant_condition("while", cond, &new_cond);
+        self.ctx.startpos_loop();
+        let new_body = Box::new(self.fold_node(body));
+        self.ctx.endpos_loop();
+        Node {
+            id: node.id,
+            span: node.span,
+            node_type: While(Box::new(new_cond), new_body),
+            basic_type: BasicType::Nil,
+        }
+    }
+
+    fn fold_switch(&mut self, node: &Node) -> Node {
+        use NodeType::*;
+        let Switch(scrutinee, arms) = &node.node_type else {
+            unreachable!()
+        };
+        let new_scrutinee = self.fold_node(scrutinee);
+        if new_scrutinee.basic_type != BasicType::Int
+            && new_scrutinee.basic_type != BasicType::Const
+        {
+            scrutinee.error_spot(
+                DiagnosticKind::TypeMismatch,
+                "Error type 11 at this line: Expression of switch statement should be int/const"
+                    .to_string(),
+            );
+        }
+        // 所有case共享同一个作用域, 标签必须是互不相同的整数常量, default至多一个.
+        let mut seen: HashMap<i32, &Node> = HashMap::new();
+        let mut default: Option<&Node> = None;
+        let mut new_arms = vec![];
+        self.ctx.enter_scope();
+        self.ctx.switch_depth += 1;
+        for arm in arms {
+            let Case(label, stmts) = &arm.node_type else {
+                unreachable!()
+            };
+            let new_label = match label {
+                Some(label) => {
+                    let new_label = self.fold_node(label);
+                    match new_label.node_type {
+                        Number(value) => {
+                            if let Some(previous) = seen.get(&value) {
+                                report(
+                                    Diagnostic::error(format!(
+                                        "Error type 16 at this line: duplicate case label `{}` in switch statement",
+                                        value
+                                    ))
+                                    .with_kind(DiagnosticKind::DuplicateLabel)
+                                    .with_label(label.span, "duplicate label")
+                                    .with_label(previous.span, "first used here"),
+                                );
+                            } else {
+                                seen.insert(value, label);
+                            }
+                        }
+                        _ => label.error_spot(
+                            DiagnosticKind::NotConstant,
+                            "Error type 11 at this line: Case label should be an integer constant"
+                                .to_string(),
+                        ),
+                    }
+                    Some(Box::new(new_label))
+                }
+                None => {
+                    if let Some(previous) = default {
+                        report(
+                            Diagnostic::error(
+                                "Error type 16 at this line: multiple default labels in one switch",
+                            )
+                            .with_kind(DiagnosticKind::DuplicateLabel)
+                            .with_label(
+                                Span {
+                                    end: arm.span.start + 7,
+                                    ..arm.span
+                                },
+                                "second default",
+                            )
+                            .with_label(
+                                Span {
+                                    end: previous.span.start + 7,
+                                    ..previous.span
+                                },
+                                "first default here",
+                            ),
+                        );
+                    } else {
+                        default = Some(arm);
+                    }
+                    None
+                }
+            };
+            let new_stmts = stmts.iter().map(|stmt| self.fold_node(stmt)).collect();
+            new_arms.push(Node {
+                id: arm.id,
+                span: arm.span,
+                node_type: Case(new_label, new_stmts),
                 basic_type: BasicType::Nil,
-            }
+            });
         }
-        While(cond, body) => {
-            let new_cond = traverse(cond, ctx);
-            if new_cond.basic_type != BasicType::Int && new_cond.basic_type != BasicType::Const {
-                node.error_spot(format!("Condition of if statement should be int/const"));
+        self.ctx.switch_depth -= 1;
+        self.ctx.exit_scope();
+        Node {
+            id: node.id,
+            span: node.span,
+            node_type: Switch(Box::new(new_scrutinee), new_arms),
+            basic_type: BasicType::Nil,
+        }
+    }
+
+    fn fold_jump(&mut self, node: &Node) -> Node {
+        match node.node_type {
+            NodeType::Break if !self.ctx.can_break() => {
+                node.error_spot(
+                    DiagnosticKind::BreakOutsideLoop,
+                    "Error type 12 at this line: Break should in a loop".to_string(),
+                );
             }
-            ctx.startpos_loop();
-            let new_body = Box::new(traverse(body, ctx));
-            ctx.endpos_loop();
-            Node {
-                startpos: node.startpos,
-                endpos: node.endpos,
-                node_type: While(Box::new(new_cond), new_body),
-                basic_type: BasicType::Nil,
+            NodeType::Continue if !self.ctx.is_in_loop() => {
+                node.error_spot(
+                    DiagnosticKind::ContinueOutsideLoop,
+                    "Error type 13 at this line: Continue should in a loop".to_string(),
+                );
             }
+            _ => {}
         }
-        Break => {
-            if !ctx.is_in_loop() {
-                node.error_spot(format!(
-                    "Error type 12 at this line: Break should in a loop"
-                ));
-            }
-            node.clone() //返回带Break语义的节点
+        node.clone() //返回带Break/Continue语义的节点
+    }
+}
+
+impl Checker<'_> {
+    /* 记录exprs中直接出现的函数调用: 它们的值被丢弃, 可以调用void函数. */
+    fn discard_calls(&mut self, exprs: &[Node]) {
+        let calls = exprs
+            .iter()
+            .filter(|e| matches!(e.node_type, NodeType::Call(..)));
+        self.discarded.extend(calls.map(|e| e.id));
+    }
+
+    /*
+        putf(format, ...): 第一个实参必须是字符串字面量, 其余实参是int/float标量,
+        个数与格式串中的转换说明(%d, %c, %f等, %%除外)相同.
+    */
+    fn check_format_args(&mut self, node: &Node) -> Vec<Node> {
+        let NodeType::Call(name, call_args, _) = &node.node_type else {
+            unreachable!()
+        };
+        let mut new_call_args = vec![];
+        let Some(NodeType::Str(format)) = call_args.first().map(|arg| &arg.node_type) else {
+            node.error_spot(
+                DiagnosticKind::TypeMismatch,
+                format!(
+                    "Error type 10 at this line: first argument of {} should be a format string",
+                    name
+                ),
+            );
+            return call_args.clone();
+        };
+        new_call_args.push(call_args[0].clone());
+        let expected = conversion_count(format);
+        if call_args.len() - 1 != expected {
+            node.error_spot(
+                DiagnosticKind::ArgumentCount,
+                format!(
+                "Error type 9 at this line: format string of {} expects {} arguments instead of {}",
+                name,
+                expected,
+                call_args.len() - 1
+            ),
+            );
         }
-        Continue => {
-            if !ctx.is_in_loop() {
-                node.error_spot(format!(
-                    "Error type 13 at this line: Continue should in a loop"
-                ));
+        for call_arg in &call_args[1..] {
+            if let NodeType::Str(_) = &call_arg.node_type {
+                call_arg.error_spot(
+                    DiagnosticKind::TypeMismatch,
+                    format!(
+                        "Error type 10 at this line: only the first argument of {} can be a string",
+                        name
+                    ),
+                );
+                new_call_args.push(call_arg.clone());
+                continue;
             }
-            node.clone() //返回带Continue语义的节点
-        }
-        _ => unreachable!(),
-    }
-}
-
-fn eval(node: &Node, ctx: &Runtime) -> i32 {
-    // step1. 实现二元运算符的Eval.
-    impl TokenType {
-        fn calc(&self, lhs: i32, rhs: i32) -> i32 {
-            use TokenType::*;
-            match self {
-                //5种算术运算
-                Plus => lhs + rhs,
-                Minus => lhs - rhs,
-                Multi => lhs * rhs,
-                Divide => lhs / rhs,
-                Mods => lhs % rhs,
-                //6种关系运算
-                Equal => (lhs == rhs) as i32,
-                NotEqual => (lhs != rhs) as i32,
-                Lesserthan => (lhs < rhs) as i32,
-                Greaterthan => (lhs > rhs) as i32,
-                LessEqual => (lhs <= rhs) as i32,
-                GreatEqual => (lhs >= rhs) as i32,
-                //2种逻辑运算
-                And => (lhs != 0 && rhs != 0) as i32,
-                Or => (lhs != 0 || rhs != 0) as i32,
-                _ => unreachable!(),
+            let new_call_arg = self.fold_node(call_arg);
+            if !matches!(
+                new_call_arg.basic_type,
+                BasicType::Int | BasicType::Const | BasicType::Float
+            ) {
+                call_arg.error_spot(
+                    DiagnosticKind::TypeMismatch,
+                    format!(
+                        "Error type 10 at this line: Unmatched type in function call {}",
+                        name
+                    ),
+                );
             }
+            new_call_args.push(new_call_arg);
+        }
+        new_call_args
+    }
+}
+
+/* 实现二元运算符的Eval. 整数运算与目标机器相同, 溢出时按32位补码回绕(除数为0由调用者排除). */
+impl TokenType {
+    fn calc(&self, lhs: i32, rhs: i32) -> i32 {
+        use TokenType::*;
+        match self {
+            //5种算术运算
+            Plus => lhs.wrapping_add(rhs),
+            Minus => lhs.wrapping_sub(rhs),
+            Multi => lhs.wrapping_mul(rhs),
+            Divide => lhs.wrapping_div(rhs),
+            Mods => lhs.wrapping_rem(rhs),
+            //6种关系运算
+            Equal => (lhs == rhs) as i32,
+            NotEqual => (lhs != rhs) as i32,
+            Lesserthan => (lhs < rhs) as i32,
+            Greaterthan => (lhs > rhs) as i32,
+            LessEqual => (lhs <= rhs) as i32,
+            GreatEqual => (lhs >= rhs) as i32,
+            //2种逻辑运算
+            And => (lhs != 0 && rhs != 0) as i32,
+            Or => (lhs != 0 || rhs != 0) as i32,
+            _ => unreachable!(),
+        }
+    }
+
+    /* 算术运算的结果超出了i32的范围(calc中回绕了). */
+    fn overflows(&self, lhs: i32, rhs: i32) -> bool {
+        use TokenType::*;
+        match self {
+            Plus => lhs.checked_add(rhs).is_none(),
+            Minus => lhs.checked_sub(rhs).is_none(),
+            Multi => lhs.checked_mul(rhs).is_none(),
+            Divide | Mods => lhs == i32::MIN && rhs == -1,
+            _ => false,
+        }
+    }
+
+    /* 浮点版本, 关系和逻辑运算的结果是1.0或0.0. */
+    fn calc_float(&self, lhs: f32, rhs: f32) -> f32 {
+        use TokenType::*;
+        let truth = |b: bool| if b { 1.0 } else { 0.0 };
+        match self {
+            Plus => lhs + rhs,
+            Minus => lhs - rhs,
+            Multi => lhs * rhs,
+            Divide => lhs / rhs,
+            Mods => lhs % rhs,
+            Equal => truth(lhs == rhs),
+            NotEqual => truth(lhs != rhs),
+            Lesserthan => truth(lhs < rhs),
+            Greaterthan => truth(lhs > rhs),
+            LessEqual => truth(lhs <= rhs),
+            GreatEqual => truth(lhs >= rhs),
+            And => truth(lhs != 0.0 && rhs != 0.0),
+            Or => truth(lhs != 0.0 || rhs != 0.0),
+            _ => unreachable!(),
         }
     }
+}
+
+/* 常量表达式中是否有浮点数参与运算(浮点字面量或const float). */
+/*
+    能参与运算, 作为条件或隐式转换的标量类型: int, 整型常量和float.
+    const float只会是声明(如const形参)的类型, 表达式中已经折叠成float.
+*/
+fn is_scalar(ty: &BasicType) -> bool {
+    matches!(
+        ty,
+        BasicType::Int | BasicType::Const | BasicType::Float | BasicType::ConstFloat
+    )
+}
+
+/* 需要标量的地方出现了别的类型; Nil是出错的表达式(占位节点), 已经报告过, 不再连带报告. */
+fn not_scalar(ty: &BasicType) -> bool {
+    !is_scalar(ty) && *ty != BasicType::Nil
+}
+
+/* 数组类型(整个数组或部分下标得到的子数组). */
+fn is_array(ty: &BasicType) -> bool {
+    matches!(
+        ty,
+        BasicType::IntArray(_)
+            | BasicType::FloatArray(_)
+            | BasicType::ConstArray(_)
+            | BasicType::ConstFloatArray(_)
+    )
+}
+
+/* 实参call_arg(类型为found)与形参def_arg不符: 标出期望和实际的类型, 以及形参的声明. */
+fn argument_mismatch(
+    what: String,
+    call_arg: &Node,
+    found: &BasicType,
+    def_arg: &Node,
+) -> Diagnostic {
+    let expected = match &def_arg.node_type {
+        NodeType::Decl(ty, ..) => ty.clone(),
+        _ => BasicType::Nil,
+    };
+    let mut diagnostic = Diagnostic::error(format!("Error type 10 at this line: {}", what))
+        .with_kind(DiagnosticKind::TypeMismatch)
+        .with_label(
+            call_arg.span,
+            format!("expected `{}`, found `{}`", expected, found),
+        );
+    // 运行时库函数的形参没有源代码位置
+    if !def_arg.span.is_empty() {
+        diagnostic = diagnostic.with_label(def_arg.span, "parameter declared here");
+    }
+    diagnostic
+}
+
+/* 结果总是int的关系和逻辑运算符. */
+pub fn is_relational(op: &TokenType) -> bool {
+    use TokenType::*;
+    matches!(
+        op,
+        Equal | NotEqual | Lesserthan | Greaterthan | LessEqual | GreatEqual | And | Or
+    )
+}
+
+/*
+    if/while的条件在编译期就能确定时给出警告(默认不输出, 见WarningConfig):
+    折叠成常量的条件(字面量本身如while (1)除外), 以及int变量与自身比较(如x == x).
+*/
+fn warn_constant_condition(what: &str, cond: &Node, new_cond: &Node) {
     use NodeType::*;
+    let (value, label) = match &new_cond.node_type {
+        Number(n) if new_cond.basic_type == BasicType::Const && !is_literal(cond) => {
+            (*n != 0, format!("evaluates to {}", n))
+        }
+        BinOp(op, lhs, rhs) => {
+            let (Access(name, None, l), Access(_, None, r)) = (&lhs.node_type, &rhs.node_type)
+            else {
+                return;
+            };
+            // float变量可能是NaN, x == x不一定成立.
+            if l != r || lhs.basic_type != BasicType::Int {
+                return;
+            }
+            let value = match op {
+                TokenType::Equal | TokenType::LessEqual | TokenType::GreatEqual => true,
+                TokenType::NotEqual | TokenType::Lesserthan | TokenType::Greaterthan => false,
+                _ => return,
+            };
+            (value, format!("`{}` is compared with itself", name))
+        }
+        _ => return,
+    };
+    report(
+        Diagnostic::warning(format!(
+            "condition of {} statement is always {}",
+            what, value
+        ))
+        .with_kind(DiagnosticKind::ConstantCondition)
+        .with_label(cond.span, label),
+    );
+}
+
+/* 带可选正负号的数字字面量. */
+fn is_literal(node: &Node) -> bool {
     match &node.node_type {
-        Nil => return 0,
-        Call(name, _, _) => {
-            node.error_spot(format!(
-                "Cannot call function {} in constant expression",
+        NodeType::Number(_) | NodeType::FloatNumber(_) => true,
+        NodeType::UnaryOp(TokenType::Plus | TokenType::Minus, operand) => is_literal(operand),
+        _ => false,
+    }
+}
+
+/* calc和calc_float能计算的双目运算符: 5种算术运算加上关系和逻辑运算. */
+fn is_binary_operator(op: &TokenType) -> bool {
+    use TokenType::*;
+    matches!(op, Plus | Minus | Multi | Divide | Mods) || is_relational(op)
+}
+
+/* 两个标量做算术运算(或作为条件表达式的两个分支)的结果类型: 有一边是float时为float. */
+fn arithmetic_type(lhs: &BasicType, rhs: &BasicType) -> BasicType {
+    if *lhs == BasicType::Float || *rhs == BasicType::Float {
+        BasicType::Float
+    } else {
+        BasicType::Int
+    }
+}
+
+/*
+    int与float之间的隐式转换: 把标量表达式expr转换成target类型. 类型已经相同时原样返回,
+    常量直接折叠成目标类型的常量(float转int向零截断), 其余的包一层Cast节点.
+    数组, void等非标量原样返回, 类型错误由调用者报告.
+*/
+fn convert(expr: Node, target: &BasicType) -> Node {
+    let from = match expr.basic_type {
+        BasicType::Int | BasicType::Const => BasicType::Int,
+        BasicType::Float => BasicType::Float,
+        _ => return expr,
+    };
+    let target = match target {
+        BasicType::Int | BasicType::Const => BasicType::Int,
+        BasicType::Float | BasicType::ConstFloat => BasicType::Float,
+        _ => return expr,
+    };
+    if from == target {
+        return expr;
+    }
+    match (&expr.node_type, &target) {
+        (NodeType::Number(num), BasicType::Float) => Node {
+            node_type: NodeType::FloatNumber(*num as f32),
+            basic_type: BasicType::Float,
+            ..expr
+        },
+        (NodeType::FloatNumber(num), BasicType::Int) => Node {
+            node_type: NodeType::Number(*num as i32),
+            basic_type: BasicType::Const,
+            ..expr
+        },
+        _ => {
+            let span = expr.span;
+            let mut cast = Node::new(NodeType::Cast(target.clone(), Box::new(expr)));
+            cast.span = span;
+            cast.basic_type = target;
+            cast
+        }
+    }
+}
+
+/* 常量表达式中的错误: 带种类, 标注在出错的节点上. */
+fn const_error(node: &Node, kind: DiagnosticKind, msg: String) -> Diagnostic {
+    Diagnostic::error(msg)
+        .with_kind(kind)
+        .with_label(node.span, "")
+}
+
+/* 初始值必须在编译期求值的声明: 常量, 以及(非常量的)全局变量. */
+#[derive(Clone, Copy)]
+enum ConstInit<'a> {
+    Constant(&'a str),
+    Global(&'a str),
+}
+
+/* 声明的初始值init不是常量表达式, cause是const_eval对其中第一个非常量部分的报告. */
+fn not_constant_init(init: &Node, required: ConstInit, cause: Diagnostic) -> Diagnostic {
+    let culprit = cause.labels.first().map_or(init.span, |l| l.span);
+    let msg = match required {
+        ConstInit::Constant(name) => format!(
+            "Error type 11 at this line: initializer of constant {} is not a constant expression",
+            name
+        ),
+        ConstInit::Global(name) => format!(
+            "Error type 11 at this line: global initializer of {} is not a constant expression",
+            name
+        ),
+    };
+    // 原因作为标注, 不再重复错误类型的前缀.
+    let reason = match cause.message.split_once(" at this line: ") {
+        Some((error_type, reason)) if error_type.starts_with("Error type ") => reason,
+        _ => &cause.message,
+    };
+    Diagnostic::error(msg)
+        .with_kind(DiagnosticKind::NotConstant)
+        .with_label(culprit, reason)
+}
+
+/* 常量声明(或折叠过的初始值)中的第offset个值, 出错的声明没有折叠好的值时按0. */
+fn declared_value(decl: &Node, offset: usize) -> ConstValue {
+    let init = decl
+        .as_decl()
+        .and_then(|(_, _, _, inits, _)| inits)
+        .and_then(|inits| inits.get(offset));
+    match init.map(|n| &n.node_type) {
+        Some(NodeType::Number(num)) => ConstValue::Int(*num),
+        Some(NodeType::FloatNumber(num)) => ConstValue::Float(*num),
+        _ => ConstValue::Int(0),
+    }
+}
+
+/*
+    求常量表达式的值, 结果带有类型: 有float参与的算术运算得到Float, 关系和逻辑运算得到Int.
+    用于数组维度, 常量的初始值, 全局变量的初始值以及全为常量的表达式的折叠.
+    遇到第一个不是常量的部分(变量, 函数调用, 逗号表达式)或非法的运算时返回对应的Diagnostic.
+*/
+pub fn const_eval(node: &Node, ctx: &Runtime) -> Result<ConstValue, Diagnostic> {
+    use NodeType::*;
+    match &node.node_type {
+        // 语法错误的占位节点, 已经报告过.
+        Nil => Ok(ConstValue::Int(0)),
+        Number(num) => Ok(ConstValue::Int(*num)),
+        FloatNumber(num) => Ok(ConstValue::Float(*num)),
+        Call(name, _, _) => Err(const_error(
+            node,
+            DiagnosticKind::NotConstant,
+            format!(
+                "Error type 11 at this line: Cannot call function {} in constant expression",
                 name
-            ));
-            unreachable!()
+            ),
+        )),
+        Comma(_) => Err(const_error(
+            node,
+            DiagnosticKind::NotConstant,
+            "Error type 11 at this line: comma expression in constant expression".to_string(),
+        )),
+        Cast(ty, expr) => {
+            let value = const_eval(expr, ctx)?;
+            Ok(match ty {
+                BasicType::Float => ConstValue::Float(value.as_float()),
+                _ => ConstValue::Int(value.as_int()),
+            })
+        }
+        UnaryOp(ttype, operand) => {
+            let value = const_eval(operand, ctx)?;
+            match (ttype, value) {
+                (TokenType::Not, _) => Ok(ConstValue::Int(!value.is_true() as i32)),
+                (TokenType::Plus, _) => Ok(value),
+                (TokenType::Minus, ConstValue::Int(v)) => {
+                    /*
+                        -2147483648没有对应的正数, 取负后回绕成它自己.
+                        字面量2147483648在词法分析时已经回绕成i32::MIN, 对它取负正是常见的写法-2147483648, 不报告.
+                    */
+                    if v == i32::MIN && !matches!(operand.node_type, Number(_)) {
+                        report(
+                            Diagnostic::warning(format!(
+                                "integer overflow in constant expression: -({}) wraps to {}",
+                                v, v
+                            ))
+                            .with_kind(DiagnosticKind::IntegerOverflow)
+                            .with_label(node.span, "result does not fit in int"),
+                        );
+                    }
+                    Ok(ConstValue::Int(v.wrapping_neg()))
+                }
+                (TokenType::Minus, ConstValue::Float(v)) => Ok(ConstValue::Float(-v)),
+                _ => Err(const_error(
+                    node,
+                    DiagnosticKind::NotConstant,
+                    format!(
+                        "Error type 11 at this line: unsupported unary operator '{}' in constant expression",
+                        operator_text(ttype)
+                    ),
+                )),
+            }
         }
-        Number(num) => num.clone(),
         BinOp(ttype, lhs, rhs) => {
-            let l = eval(&lhs, ctx);
-            let r = eval(&rhs, ctx);
-            ttype.calc(l, r)
+            // calc只认识算术, 关系和逻辑运算符.
+            if !is_binary_operator(ttype) {
+                return Err(const_error(
+                    node,
+                    DiagnosticKind::NotConstant,
+                    format!(
+                        "Error type 11 at this line: unsupported binary operator '{}' in constant expression",
+                        operator_text(ttype)
+                    ),
+                ));
+            }
+            let l = const_eval(lhs, ctx)?;
+            // &&和||短路: 左操作数已经决定结果时不求右操作数(如0 && 1 / 0).
+            match ttype {
+                TokenType::And if !l.is_true() => return Ok(ConstValue::Int(0)),
+                TokenType::Or if l.is_true() => return Ok(ConstValue::Int(1)),
+                _ => {}
+            }
+            let r = const_eval(rhs, ctx)?;
+            match (l, r) {
+                // 整数除以0没有定义, 不能交给calc; float除以0按IEEE得到inf/NaN, 不报错.
+                (ConstValue::Int(_), ConstValue::Int(0))
+                    if matches!(ttype, TokenType::Divide | TokenType::Mods) =>
+                {
+                    let op = if *ttype == TokenType::Divide {
+                        "/"
+                    } else {
+                        "%"
+                    };
+                    Err(Diagnostic::error(format!(
+                        "Error type 11 at this line: division by zero in '{}'",
+                        op
+                    ))
+                    .with_kind(DiagnosticKind::DivisionByZero)
+                    .with_label(node.span, "divisor evaluates to 0"))
+                }
+                (ConstValue::Int(l), ConstValue::Int(r)) => {
+                    let value = ttype.calc(l, r);
+                    if ttype.overflows(l, r) {
+                        report(
+                            Diagnostic::warning(format!(
+                                "integer overflow in constant expression: {} {} {} wraps to {}",
+                                l,
+                                operator_text(ttype),
+                                r,
+                                value
+                            ))
+                            .with_kind(DiagnosticKind::IntegerOverflow)
+                            .with_label(node.span, "result does not fit in int"),
+                        );
+                    }
+                    Ok(ConstValue::Int(value))
+                }
+                _ if *ttype == TokenType::Mods => {
+                    let operand = if l.is_float() { lhs } else { rhs };
+                    Err(const_error(
+                        operand,
+                        DiagnosticKind::TypeMismatch,
+                        "Error type 11 at this line: operands of '%' should be int".to_string(),
+                    ))
+                }
+                _ => {
+                    let value = ttype.calc_float(l.as_float(), r.as_float());
+                    if is_relational(ttype) {
+                        Ok(ConstValue::Int(value as i32))
+                    } else {
+                        Ok(ConstValue::Float(value))
+                    }
+                }
+            }
+        }
+        // 两个分支都必须是常量; 有一个分支是float时结果为float.
+        Cond(cond, on_true, on_false) => {
+            let cond = const_eval(cond, ctx)?;
+            let t = const_eval(on_true, ctx)?;
+            let f = const_eval(on_false, ctx)?;
+            let value = if cond.is_true() { t } else { f };
+            Ok(if t.is_float() || f.is_float() {
+                ConstValue::Float(value.as_float())
+            } else {
+                value
+            })
         }
         Access(name, indexes, _) => {
-            /* Access a variable
-             *  1. If the variable is a const, return the value of the const
-             *  2. If the variable is a const array, return the value of the const array
-             */
-            let (btype, def_node) = ctx.find(&name, node);
+            let (btype, def_node) = ctx.find(name, node);
+            // 未定义的名字已经报告过, 出错后按0继续.
+            let Some(def_node) = def_node else {
+                return Ok(ConstValue::Int(0));
+            };
+            if matches!(def_node.node_type, Decl(.., Scope::Params)) {
+                return Err(const_error(
+                    node,
+                    DiagnosticKind::NotConstant,
+                    format!(
+                        "Error type 11 at this line: {} is a parameter, not a constant",
+                        name
+                    ),
+                ));
+            }
             match btype {
-                BasicType::Const => {
-                    //Access a const with index
+                BasicType::Const | BasicType::ConstFloat => {
                     if indexes.is_some() {
-                        node.error_spot(format!("Access constant {} with index", name));
-                    }
-                    if let NodeType::Decl(_, _, _, initlist, _) = def_node.node_type.clone() {
-                        if let NodeType::Number(num) = initlist.unwrap()[0].node_type {
-                            return num;
-                        } else {
-                            unreachable!()
-                        }
-                    } else {
-                        unreachable!()
+                        return Err(const_error(
+                            node,
+                            DiagnosticKind::IndexMismatch,
+                            format!(
+                                "Error type 8 at this line: Access constant {} with index",
+                                name
+                            ),
+                        ));
                     }
+                    Ok(declared_value(def_node, 0))
                 }
-                BasicType::ConstArray(dims) => {
-                    if let Some(index) = indexes {
-                        if index.len() == dims.len() {
-                            /* Calculate the offset of the array */
-                            let mut offset = 0;
-                            for (i, indexnode) in index.iter().enumerate() {
-                                let id = eval(indexnode, ctx);
-                                if let Some(n) = dims.get(i + 1) {
-                                    offset += id * (*n as i32);
-                                } else {
-                                    offset += id;
-                                }
-                            }
-                            if let NodeType::Decl(_, _, _, initlist, _) = node.node_type.clone() {
-                                if let Some(n) = initlist.unwrap().get(offset as usize) {
-                                    // 用if let拿到当前的Node.
-                                    if let NodeType::Number(num) = n.node_type {
-                                        // 如果是Number类型, 则返回值
-                                        return num;
-                                    } else {
-                                        unreachable!()
-                                    }
-                                } else {
-                                    //如果索引超出范围, 则报错
-                                    node.error_spot(format!("Index of {} out of range", name));
-                                    unreachable!()
-                                }
-                            } else {
-                                unreachable!()
-                            }
-                        } else {
-                            node.error_spot(format!(
-                                "Dimension of {} should be {} instead of {}",
+                BasicType::ConstArray(dims) | BasicType::ConstFloatArray(dims) => {
+                    let Some(index) = indexes else {
+                        return Err(const_error(
+                            node,
+                            DiagnosticKind::IndexMismatch,
+                            format!(
+                                "Error type 8 at this line: {} should be accessed with index",
+                                name
+                            ),
+                        ));
+                    };
+                    if index.len() != dims.len() {
+                        return Err(const_error(
+                            node,
+                            DiagnosticKind::IndexMismatch,
+                            format!(
+                                "Error type 8 at this line: Dimension of {} should be {} instead of {}",
                                 name,
                                 dims.len(),
                                 index.len()
+                            ),
+                        ));
+                    }
+                    /* Calculate the offset of the array */
+                    let mut offset: i64 = 0;
+                    for (i, index_node) in index.iter().enumerate() {
+                        let ConstValue::Int(id) = const_eval(index_node, ctx)? else {
+                            return Err(const_error(
+                                index_node,
+                                DiagnosticKind::TypeMismatch,
+                                format!(
+                                    "Error type 7 at this line: Index of {} should be int or const",
+                                    name
+                                ),
                             ));
-                            unreachable!()
+                        };
+                        if id < 0 || id as usize >= dims[i] {
+                            return Err(out_of_bounds(name, index_node, id, i, dims[i]));
                         }
-                    } else {
-                        node.error_spot(format!("{} should be accessed with index", name));
-                        unreachable!()
+                        offset = offset * dims[i] as i64 + id as i64;
                     }
+                    Ok(declared_value(def_node, offset as usize))
                 }
-                BasicType::Int | BasicType::IntArray(_) => {
-                    node.error_spot(format!("{} should be a constant", name));
-                    unreachable!()
-                }
-                _ => unreachable!(),
+                BasicType::Int
+                | BasicType::IntArray(_)
+                | BasicType::Float
+                | BasicType::FloatArray(_) => Err(const_error(
+                    node,
+                    DiagnosticKind::NotConstant,
+                    format!("Error type 11 at this line: {} should be a constant", name),
+                )),
+                // 函数名: 出错后按0继续.
+                _ => Ok(ConstValue::Int(0)),
             }
         }
-        _ => unreachable!(),
+        _ => Err(const_error(
+            node,
+            DiagnosticKind::NotConstant,
+            "Error type 11 at this line: expression is not a constant".to_string(),
+        )),
+    }
+}
+
+/* const_eval的便捷形式: 出错时报告错误, 按0继续分析. */
+fn fold_const(node: &Node, ctx: &Runtime) -> ConstValue {
+    const_eval(node, ctx).unwrap_or_else(|diagnostic| {
+        report(diagnostic);
+        ConstValue::Int(0)
+    })
+}
+
+/*
+    把初始值折叠成常量并转换成声明的类型: float/const float声明得到FloatNumber, 其余得到Number.
+    初始值中有变量或函数调用时, 按声明的种类(required)报告"初始值不是常量表达式",
+    并标出第一个不是常量的子表达式.
+*/
+fn fold_init(init: &Node, is_float: bool, ctx: &Runtime, required: ConstInit) -> Node {
+    let value = const_eval(init, ctx).unwrap_or_else(|diagnostic| {
+        if diagnostic.kind == Some(DiagnosticKind::NotConstant) {
+            report(not_constant_init(init, required, diagnostic));
+        } else {
+            report(diagnostic);
+        }
+        ConstValue::Int(0)
+    });
+    let (node_type, basic_type) = if is_float {
+        (NodeType::FloatNumber(value.as_float()), BasicType::Float)
+    } else {
+        (NodeType::Number(value.as_int()), BasicType::Const)
+    };
+    Node {
+        id: init.id,
+        span: init.span,
+        node_type,
+        basic_type,
     }
 }
 
-/* 根据给定维度和初始化列表展开初始化. */
+/*
+    按SysY的规则把(嵌套的)初始化列表展开成level维开始的子数组的全部元素, 不足的部分补0.
+    标量依次填入下一个元素; 花括号对应从当前位置开始的一个子数组: 在比level更内层的维度中,
+    选最外层的、当前位置恰好在其边界上的子数组. 例如int a[2][3] = {{1}, {2}}展开成1 0 0 2 0 0,
+    int a[2][2][2] = {1, 2, {3}, 4}展开成1 2 3 0 4 0 0 0. 超出子数组长度的元素在超出处报告.
+*/
 fn expand_inits(
     dims: &Vec<Node>,
     inits: &Vec<Node>,
-    need_eval: bool,
+    required: Option<ConstInit>,
+    is_float: bool,
     ctx: &mut Runtime,
     level: usize,
 ) -> Vec<Node> {
-    if level == dims.len() {
-        inits
-            .last()
-            .unwrap()
-            .error_spot(format!("Dimension of initializer exceeded"));
-    }
-    let mut max = 1;
-    for dim_node in dims.get(level..).unwrap() {
-        if let NodeType::Number(dim) = dim_node.node_type {
-            max *= dim;
-        }
-    }
+    // 从第k维开始的子数组的元素个数; 不为正的维度已经报告过, 按1继续.
+    let size = |k: usize| -> usize {
+        dims[k..]
+            .iter()
+            .map(|d| match d.node_type {
+                NodeType::Number(dim) => dim.max(1) as usize,
+                _ => 1,
+            })
+            .product()
+    };
+    let max = size(level);
     let mut expanded = vec![];
     for init_node in inits {
+        if expanded.len() >= max {
+            // 标注超出的是哪一层(子)数组的长度, 如int a[2][3]的第二层是[3].
+            let bound: String = dims[level..]
+                .iter()
+                .map(|d| match d.node_type {
+                    NodeType::Number(dim) => format!("[{}]", dim),
+                    _ => "[?]".to_string(),
+                })
+                .collect();
+            let what = if level == 0 { "array" } else { "sub-array" };
+            report(
+                Diagnostic::error("Error type 15 at this line: Length of initializer exceeded")
+                    .with_kind(DiagnosticKind::InvalidArray)
+                    .with_label(
+                        init_node.span,
+                        format!("{} bound `{}` holds only {} elements", what, bound, max),
+                    ),
+            );
+            break;
+        }
         if let NodeType::InitList(inits2) = &init_node.node_type {
-            for new_init in expand_inits(dims, &inits2, need_eval, ctx, level + 1) {
-                expanded.push(new_init);
-            }
+            let position = expanded.len();
+            let Some(k) = (level + 1..dims.len()).find(|&k| position % size(k) == 0) else {
+                let msg = if level + 1 == dims.len() {
+                    "Error type 15 at this line: Dimension of initializer exceeded"
+                } else {
+                    "Error type 15 at this line: Initializer list does not start at a sub-array boundary"
+                };
+                init_node.error_spot(DiagnosticKind::InvalidArray, msg.to_string());
+                continue;
+            };
+            let expanded2 = expand_inits(dims, inits2, required, is_float, ctx, k);
+            expanded.extend(expanded2);
         } else {
-            let new_init = if need_eval {
-                Node {
-                    startpos: init_node.startpos,
-                    endpos: init_node.endpos,
-                    node_type: NodeType::Number(eval(init_node, ctx)),
-                    basic_type: BasicType::Const,
-                }
+            let new_init = if let Some(required) = required {
+                fold_init(init_node, is_float, ctx, required)
             } else {
-                let ini = traverse(init_node, ctx);
-                ini
+                let element_type = if is_float {
+                    BasicType::Float
+                } else {
+                    BasicType::Int
+                };
+                convert(traverse(init_node, ctx), &element_type)
             };
             expanded.push(new_init);
         }
     }
-    if expanded.len() > max as usize {
-        inits
-            .last()
-            .unwrap()
-            .error_spot(format!("Length of initializer exceeded"));
-    } else {
-        for _ in expanded.len()..(max as usize) {
-            expanded.push(Node {
-                startpos: 0,
-                endpos: 0,
-                node_type: NodeType::Number(0),
-                basic_type: BasicType::Const,
-            });
-        }
+    for _ in expanded.len()..max {
+        let mut zero = if is_float {
+            Node::new(NodeType::FloatNumber(0.0))
+        } else {
+            Node::new(NodeType::Number(0))
+        };
+        zero.basic_type = if is_float {
+            BasicType::Float
+        } else {
+            BasicType::Const
+        };
+        expanded.push(zero);
     }
     expanded
 }
 
-pub fn semantic(ast: &Vec<Node>, path: &String) -> Vec<Node> {
-    unsafe { FILEPATH = path.clone() }
+/*
+    语义分析的入口: 返回HIR(带类型, 引用为符号编号的树)和发现的语义错误(去重, 限流后的Diagnostic, 带有DiagnosticKind).
+    这里不打印任何东西, 如何输出以及有错误时是否继续由调用者决定.
+    有错误时树中出错的节点是Error占位节点, 其余部分照常分析.
+*/
+pub fn analyze(ast: &[Node]) -> (Hir, Vec<Diagnostic>) {
+    analyze_with(ast, &WarningConfig::default(), None)
+}
+
+/*
+    同analyze, 但按warnings过滤(或升级)警告; 错误达到max_errors个后停止分析:
+    当前的全局声明或函数检查完就不再继续, 只保留前max_errors个错误, 最后附加一条说明. None表示不限制.
+*/
+pub fn analyze_with(
+    ast: &[Node],
+    warnings: &WarningConfig,
+    max_errors: Option<usize>,
+) -> (Hir, Vec<Diagnostic>) {
+    let (annotated_ast, diagnostics) = annotate_with(ast, warnings, max_errors);
+    (Hir::from_nodes(&annotated_ast), diagnostics)
+}
+
+/* 兼容接口: 与analyze相同, 但结果是带类型信息的AST(语义AST), 供现有的打印和变换使用. */
+pub fn semantic(ast: &[Node]) -> (Vec<Node>, Vec<Diagnostic>) {
+    semantic_with(ast, &WarningConfig::default())
+}
+
+pub fn semantic_with(ast: &[Node], warnings: &WarningConfig) -> (Vec<Node>, Vec<Diagnostic>) {
+    annotate_with(ast, warnings, None)
+}
+
+/*
+    检查器本身在语义AST上工作, analyze_with由它的结果建立HIR.
+    需要在语义AST上做变换(whole_program, short_circuit)再降低的调用者用它, 变换之后只建立一次HIR.
+*/
+pub fn annotate_with(
+    ast: &[Node],
+    warnings: &WarningConfig,
+    max_errors: Option<usize>,
+) -> (Vec<Node>, Vec<Diagnostic>) {
+    // 丢掉同一线程中上一次(panic而)没有取走的错误.
+    PENDING.with(|p| p.borrow_mut().clear());
     let mut ctx = Runtime::new();
+    declare_runtime_library(&mut ctx);
     let mut new_nodes = vec![];
+    let limit_reached = || max_errors.is_some_and(|max| pending_errors() >= max);
     /* 遍历AST树, 并对每个节点进行"语义分析"(实际上就是语义检查+类型判断), 相当于AST的interpreter(解释器) */
-    for node in ast {
-        match &node.node_type {
-            NodeType::DeclStmt(_) => {
-                let new = traverse(node, &mut ctx);
-                new_nodes.push(new);
+    let globals = ast
+        .iter()
+        .filter(|node| matches!(node.node_type, NodeType::DeclStmt(_)));
+    let others = ast
+        .iter()
+        .filter(|node| !matches!(node.node_type, NodeType::DeclStmt(_)));
+    let mut stopped = false;
+    for node in globals.chain(others) {
+        if limit_reached() {
+            stopped = true;
+            break;
+        }
+        new_nodes.push(traverse(node, &mut ctx));
+    }
+    // 停止时程序只检查了一部分, 缺少main和未使用等整体检查的结果不可信.
+    if !stopped {
+        check_main(&new_nodes);
+        warn_unused(&new_nodes, &ctx);
+        for diagnostic in uninitialized_reads(&new_nodes) {
+            report(diagnostic);
+        }
+    }
+    // 升级为错误的警告同样计入max_errors.
+    let mut diagnostics = warnings.apply(take_errors());
+    if let Some(max) = max_errors {
+        let mut errors = 0;
+        diagnostics.retain(|d| {
+            errors += d.is_error() as usize;
+            !d.is_error() || errors <= max
+        });
+        if stopped || errors > max {
+            diagnostics.push(Diagnostic::new(
+                Severity::Note,
+                format!("too many errors, analysis stopped after {} errors", max),
+            ));
+        }
+    }
+    (new_nodes, diagnostics)
+}
+
+/*
+    程序的入口必须是int main(): 有函数体, 返回int, 没有参数.
+    解释器, 代码生成和评测都以此为前提, 不符合时在这里报错, 而不是留给后面的阶段.
+*/
+fn check_main(annotated_ast: &[Node]) {
+    let main = annotated_ast.iter().find(|n| {
+        matches!(n.as_func(), Some((_, name, _, body)) if name == "main" && !is_prototype(body))
+    });
+    let Some(main) = main else {
+        let mut diagnostic =
+            Diagnostic::error("Error type 17 at this line: Program has no `main` function")
+                .with_kind(DiagnosticKind::InvalidMain)
+                .with_note("a SysY program starts at `int main()`");
+        if let Some(first) = annotated_ast.first() {
+            let start = Span::new(first.span.file_id, 0, 0);
+            diagnostic = diagnostic.with_label(start, "");
+        }
+        report(diagnostic);
+        return;
+    };
+    let (ret, _, params, _) = main.as_func().unwrap();
+    let header = Span::new(main.span.file_id, main.span.start, main.span.start);
+    if *ret != BasicType::Int {
+        report(
+            Diagnostic::error(format!(
+                "Error type 17 at this line: `main` should return int, found `{}`",
+                ret
+            ))
+            .with_kind(DiagnosticKind::InvalidMain)
+            .with_label(header, "declared here"),
+        );
+    }
+    if !params.is_empty() {
+        report(
+            Diagnostic::error(format!(
+                "Error type 17 at this line: `main` should take no parameters, found {}",
+                params.len()
+            ))
+            .with_kind(DiagnosticKind::InvalidMain)
+            .with_label(params[0].span, "unexpected parameter"),
+        );
+    }
+}
+
+/*
+    未使用警告: 从未被读取的变量, 常量和参数(函数原型的参数除外), 以及从main出发的调用图中
+    不可达的函数(没有main时不检查函数). 警告的严重程度是Warning, 调用者可以按需过滤.
+    变量的读写方式来自xref::symbol_uses: Read算作使用, Write不算; ReadWrite中,
+    数组作为实参和写数组形参或全局数组的元素(如p[i][j] = 0, 在函数之外可见)算作使用,
+    只写局部数组的元素不算.
+*/
+fn warn_unused(annotated_ast: &[Node], ctx: &Runtime) {
+    // 调用图: 有函数体的函数 -> 它调用的函数; 函数原型的参数不会被读取, 不参与检查.
+    let mut graph = HashMap::new();
+    let mut prototype_params = HashSet::new();
+    for (_, name, params, body) in annotated_ast.iter().filter_map(|n| n.as_func()) {
+        if is_prototype(body) {
+            prototype_params.extend(params.iter().map(|p| p.id));
+        } else {
+            graph.insert(name, callees(std::slice::from_ref(body)));
+        }
+    }
+
+    let nodes = node_map(annotated_ast);
+    let mut used = ctx.const_reads.borrow().clone();
+    for u in symbol_uses(annotated_ast) {
+        let read = match u.kind {
+            UsageKind::Read => true,
+            UsageKind::Write => false,
+            UsageKind::ReadWrite => {
+                let store = matches!(nodes[&u.node].node_type, NodeType::Assign(..));
+                let local = matches!(
+                    nodes.get(&u.decl).and_then(|d| d.as_decl()),
+                    Some((.., Scope::Local))
+                );
+                !(store && local)
             }
-            _ => {}
+        };
+        if read {
+            used.insert(u.decl);
         }
     }
-    for node in ast {
+    let mut declared = vec![];
+    for node in annotated_ast {
+        collect_decls(node, &mut declared);
+    }
+    for decl in declared {
+        let Some((_, name, _, _, scope)) = decl.as_decl() else {
+            continue;
+        };
+        if used.contains(&decl.id) || prototype_params.contains(&decl.id) {
+            continue;
+        }
+        let what = match scope {
+            Scope::Params => "parameter",
+            Scope::Global => "global variable",
+            Scope::Local => "variable",
+        };
+        report(
+            Diagnostic::warning(format!("unused {} `{}`", what, name))
+                .with_kind(DiagnosticKind::UnusedVariable)
+                .with_label(decl.span, "declared here but never read"),
+        );
+    }
+
+    if !graph.contains_key("main") {
+        return;
+    }
+    let mut reachable = HashSet::from(["main"]);
+    let mut stack = vec!["main"];
+    while let Some(func) = stack.pop() {
+        for callee in graph.get(func).into_iter().flatten() {
+            if reachable.insert(callee.as_str()) {
+                stack.push(callee);
+            }
+        }
+    }
+    for node in annotated_ast {
+        if let NodeType::Func(_, name, _, body) = &node.node_type {
+            if is_prototype(body) || reachable.contains(name.as_str()) {
+                continue;
+            }
+            report(
+                Diagnostic::warning(format!("function `{}` is never called from main", name))
+                    .with_kind(DiagnosticKind::UnusedFunction)
+                    .with_label(
+                        Span::new(node.span.file_id, node.span.start, node.span.start),
+                        "defined here",
+                    ),
+            );
+        }
+    }
+}
+
+/* 按源代码顺序收集node中的全部声明(含参数). */
+fn collect_decls<'a>(node: &'a Node, declared: &mut Vec<&'a Node>) {
+    if matches!(node.node_type, NodeType::Decl(..)) {
+        declared.push(node);
+    }
+    for child in children(node) {
+        collect_decls(child, declared);
+    }
+}
+
+/*
+    SysY运行时库(libsysy)中的函数, 在分析用户代码之前加入全局作用域, 这样getch(), putch('A')等
+    调用可以正常通过检查. 格式: (函数名, 返回类型, 参数类型).
+*/
+fn runtime_library() -> Vec<(&'static str, BasicType, Vec<BasicType>)> {
+    use BasicType::*;
+    vec![
+        ("getint", Int, vec![]),
+        ("getch", Int, vec![]),
+        ("getfloat", Float, vec![]),
+        ("getarray", Int, vec![IntArray(vec![0])]),
+        ("getfarray", Int, vec![FloatArray(vec![0])]),
+        ("putint", Void, vec![Int]),
+        ("putch", Void, vec![Int]),
+        ("putfloat", Void, vec![Float]),
+        ("putarray", Void, vec![Int, IntArray(vec![0])]),
+        ("putfarray", Void, vec![Int, FloatArray(vec![0])]),
+        ("starttime", Void, vec![]),
+        ("stoptime", Void, vec![]),
+        // putf(format, ...)的参数个数可变, 由Checker::check_format_args单独检查.
+        ("putf", Void, vec![]),
+    ]
+}
+
+/* 参数个数可变的运行时库函数(putf). 运行时库函数没有源代码位置, 用户自己定义的同名函数不算. */
+pub fn is_variadic(callee: &Node) -> bool {
+    matches!(&callee.node_type, NodeType::Func(_, name, ..) if name == "putf")
+        && callee.span.is_empty()
+}
+
+/* 格式串中转换说明的个数: 每个%开始一个, %%表示%本身. */
+fn conversion_count(format: &str) -> usize {
+    let mut count = 0;
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c == '%' && chars.next() != Some('%') {
+            count += 1;
+        }
+    }
+    count
+}
+
+/*
+    运行时库函数的声明节点. 整个进程只建立一次, 调用它们的Call记录的就是这些节点的编号,
+    HIR和verify等只拿到编号的分析在这里查找不在树中的被调函数.
+*/
+pub fn runtime_declarations() -> &'static [Node] {
+    static DECLARATIONS: OnceLock<Vec<Node>> = OnceLock::new();
+    DECLARATIONS.get_or_init(|| {
+        runtime_library()
+            .into_iter()
+            .map(|(name, ret, param_types)| {
+                let params = param_types
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, ty)| {
+                        let mut param = Node::new(NodeType::Decl(
+                            ty.clone(),
+                            format!("arg{}", i),
+                            None,
+                            None,
+                            Scope::Params,
+                        ));
+                        param.basic_type = ty;
+                        param
+                    })
+                    .collect();
+                let body = Box::new(Node::new(NodeType::Block(vec![])));
+                Node::new(NodeType::Func(ret, name.to_string(), params, body))
+            })
+            .collect()
+    })
+}
+
+fn declare_runtime_library(ctx: &mut Runtime) {
+    for decl in runtime_declarations() {
+        let (ret, name, ..) = decl.as_func().unwrap();
+        ctx.insert(
+            name.to_string(),
+            BasicType::Func(Box::new(ret.clone())),
+            decl.clone(),
+        );
+    }
+}
+
+/*
+    GlobalInit: 一个声明经过语义分析后的初始值, 按行主序展开成一维数组.
+    flat_values中存放编译期已知的部分, 需要运行时求值的元素(只会出现在局部变量中)
+    在flat_values里先占一个0, 并把(偏移量, 对应初始化表达式的NodeId)记录在has_dynamic_parts中.
+    解释器和后端直接使用它, 不必再去遍历补齐过的InitList.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlobalInit {
+    pub decl: NodeId,
+    pub name: String,
+    pub scope: Scope,
+    pub dims: Vec<usize>,
+    pub flat_values: Vec<ConstValue>,
+    pub has_dynamic_parts: Vec<(usize, NodeId)>,
+}
+
+/* 收集语义分析后AST中所有声明的初始值: 全局变量(无初始化则全0), 以及带初始化的局部变量/常量. */
+pub fn global_inits(annotated_ast: &[Node]) -> Vec<GlobalInit> {
+    fn collect(node: &Node, result: &mut Vec<GlobalInit>) {
+        use NodeType::*;
         match &node.node_type {
-            NodeType::DeclStmt(_) => {}
+            Decl(ty, name, _, inits, scope) => {
+                if scope == &Scope::Params || (inits.is_none() && scope != &Scope::Global) {
+                    return;
+                }
+                let (dims, is_float) = match ty {
+                    BasicType::IntArray(d) | BasicType::ConstArray(d) => (d.clone(), false),
+                    BasicType::FloatArray(d) | BasicType::ConstFloatArray(d) => (d.clone(), true),
+                    BasicType::Float | BasicType::ConstFloat => (vec![], true),
+                    _ => (vec![], false),
+                };
+                let zero = if is_float {
+                    ConstValue::Float(0.0)
+                } else {
+                    ConstValue::Int(0)
+                };
+                let total = dims.iter().product::<usize>();
+                let mut flat_values = vec![];
+                let mut has_dynamic_parts = vec![];
+                for (offset, init) in inits.iter().flatten().enumerate() {
+                    let value = match (&init.node_type, is_float) {
+                        (Number(num), false) => ConstValue::Int(*num),
+                        (Number(num), true) => ConstValue::Float(*num as f32),
+                        (FloatNumber(num), false) => ConstValue::Int(*num as i32),
+                        (FloatNumber(num), true) => ConstValue::Float(*num),
+                        _ => {
+                            has_dynamic_parts.push((offset, init.id));
+                            zero
+                        }
+                    };
+                    flat_values.push(value);
+                }
+                flat_values.resize(total.max(flat_values.len()), zero);
+                result.push(GlobalInit {
+                    decl: node.id,
+                    name: name.clone(),
+                    scope: scope.clone(),
+                    dims,
+                    flat_values,
+                    has_dynamic_parts,
+                });
+            }
+            // 声明可以出现在任何语句(包括switch的case)之中, 沿所有子节点查找.
             _ => {
-                let new = traverse(node, &mut ctx);
-                new_nodes.push(new);
+                for child in children(node) {
+                    collect(child, result);
+                }
             }
         }
     }
-    new_nodes
+    let mut result = vec![];
+    for node in annotated_ast {
+        collect(node, &mut result);
+    }
+    result
 }
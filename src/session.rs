@@ -0,0 +1,198 @@
+use crate::{
+    diagnostics::{Diagnostic, DiagnosticEngine, WarningConfig},
+    hir::Hir,
+    interp::{self, Limits, Outcome},
+    ir::Module,
+    lexer::{tokenize_with_diagnostics, LangLevel, LexError, LexLimits, LexOptions, Token},
+    parser::{parse_recovering, parse_with_options, Node, ParseError, ParserOptions},
+    semantics::annotate_with,
+    span::SourceMap,
+    utils::{print_tree_with, FloatFormat},
+};
+use std::path::Path;
+
+/*
+    给嵌入本库的程序(评测脚本, 编辑器插件, examples/下的示例)使用的统一入口:
+    CompileOptions汇总各阶段的选项, Session对一个源文件依次执行词法, 语法和语义分析.
+    无法继续的词法错误和语法错误以CompileError返回; 非法字符等可跳过的词法错误和语义错误以Diagnostic返回,
+    Session不打印任何东西, 输出交给DiagnosticEngine.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CompileOptions {
+    pub level: LangLevel,
+    pub limits: LexLimits,
+    pub parser: Option<ParserOptions>, //None时按level的预设, 见ParserOptions
+    pub float_format: FloatFormat,     //AST的dump文件和解释器输出浮点数的格式
+    pub warnings: WarningConfig,       //语义分析报告哪些类别的警告, 以及是否把警告当作错误
+    pub max_errors: Option<usize>,     //语义错误达到这个个数后停止分析, None时不限制
+}
+
+/* 使分析无法继续的错误: 词法错误, 或者语法错误(全部). */
+#[derive(Debug)]
+pub enum CompileError {
+    Lex(LexError),
+    Parse(Vec<ParseError>),
+}
+
+impl std::fmt::Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompileError::Lex(e) => write!(f, "{}", e),
+            CompileError::Parse(errors) => {
+                for e in errors {
+                    write!(f, "{}", e)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+impl From<LexError> for CompileError {
+    fn from(e: LexError) -> Self {
+        CompileError::Lex(e)
+    }
+}
+
+/* 语义分析的结果: 带类型信息的AST, 以及发现的语义错误. */
+pub struct Checked {
+    pub hir: Hir,
+    pub annotated_ast: Vec<Node>, //检查器的结果, hir由它建立; 供打印和现有的变换使用
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Checked {
+    /* 只有错误才算失败, 警告(如未使用的变量)不影响后续阶段. */
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter().filter(|d| d.is_error())
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter().filter(|d| !d.is_error())
+    }
+}
+
+pub struct Session {
+    pub path: String,
+    pub options: CompileOptions,
+}
+
+impl Session {
+    pub fn new(path: impl Into<String>, options: CompileOptions) -> Self {
+        Session {
+            path: path.into(),
+            options,
+        }
+    }
+
+    fn parser_options(&self) -> ParserOptions {
+        self.options
+            .parser
+            .unwrap_or_else(|| self.options.level.into())
+    }
+
+    /* 单独打开了'?'或switch等扩展时, 词法分析也要按Extended进行才能得到它们的token. */
+    fn lex_level(&self) -> LangLevel {
+        match self.options.parser {
+            Some(parser) if parser.lex_level() == LangLevel::Extended => LangLevel::Extended,
+            _ => self.options.level,
+        }
+    }
+
+    fn lex_options(&self, preserve_trivia: bool) -> LexOptions {
+        LexOptions {
+            level: self.lex_level(),
+            limits: self.options.limits,
+            preserve_trivia,
+            ..Default::default()
+        }
+    }
+
+    /* 词法分析, preserve_trivia为true时保留空白和注释. 同时返回跳过的非法字符等词法错误. */
+    pub fn lex(&self, preserve_trivia: bool) -> Result<(Vec<Token>, Vec<Diagnostic>), LexError> {
+        tokenize_with_diagnostics(self.path.clone(), &self.lex_options(preserve_trivia))
+    }
+
+    /* 同lex, 只要tokens. */
+    pub fn tokens(&self, preserve_trivia: bool) -> Result<Vec<Token>, LexError> {
+        Ok(self.lex(preserve_trivia)?.0)
+    }
+
+    /* 词法+语法分析 */
+    pub fn parse(&self) -> Result<Vec<Node>, CompileError> {
+        parse_with_options(self.tokens(false)?, &self.parser_options()).map_err(CompileError::Parse)
+    }
+
+    /* 词法+语法+语义分析, diagnostics中词法错误在前, 语义错误在后. */
+    pub fn check(&self) -> Result<Checked, CompileError> {
+        let (tokens, mut diagnostics) = self.lex(false)?;
+        let ast =
+            parse_with_options(tokens, &self.parser_options()).map_err(CompileError::Parse)?;
+        let (annotated_ast, semantic_diagnostics) =
+            annotate_with(&ast, &self.options.warnings, self.options.max_errors);
+        diagnostics.extend(semantic_diagnostics);
+        Ok(Checked {
+            hir: Hir::from_nodes(&annotated_ast),
+            annotated_ast,
+            diagnostics,
+        })
+    }
+
+    /* 词法+语法分析, 有语法错误时仍然返回解析出的部分AST(见parse_recovering). */
+    pub fn parse_recovering(&self) -> Result<(Vec<Node>, Vec<ParseError>), LexError> {
+        Ok(parse_recovering(
+            self.tokens(false)?,
+            &self.parser_options(),
+        ))
+    }
+
+    /* 同check, 但语法错误不会中止分析: 在部分AST上做语义分析, 同时返回语法错误. */
+    pub fn check_recovering(&self) -> Result<(Checked, Vec<ParseError>), LexError> {
+        let (tokens, mut diagnostics) = self.lex(false)?;
+        let (ast, errors) = parse_recovering(tokens, &self.parser_options());
+        let (annotated_ast, semantic_diagnostics) =
+            annotate_with(&ast, &self.options.warnings, self.options.max_errors);
+        diagnostics.extend(semantic_diagnostics);
+        let checked = Checked {
+            hir: Hir::from_nodes(&annotated_ast),
+            annotated_ast,
+            diagnostics,
+        };
+        Ok((checked, errors))
+    }
+
+    /* 把AST写入path.extension(见print_tree), 浮点数按options.float_format输出. */
+    pub fn print_tree(&self, ast: &[Node], path: &Path, extension: &str, with_type: bool) {
+        print_tree_with(ast, path, extension, with_type, &self.options.float_format);
+    }
+
+    /* 解释执行降低得到的三地址码(见interp::run), putf的%f按options.float_format输出. */
+    pub fn run(&self, module: &Module, input: &[u8]) -> Result<Outcome, String> {
+        let limits = Limits {
+            float_format: self.options.float_format,
+            ..Default::default()
+        };
+        interp::run_with(module, input, &limits)
+    }
+
+    /* 读入源文件的诊断引擎(不带颜色), 用来渲染这个Session返回的各类诊断. */
+    pub fn engine(&self) -> DiagnosticEngine {
+        let mut sources = SourceMap::new();
+        if sources.load(&self.path).is_err() {
+            sources.add(self.path.as_str(), "");
+        }
+        DiagnosticEngine::new(sources)
+    }
+
+    /* 按源文件渲染一条诊断, 与编译器自身的输出格式相同. 要渲染多条时请复用engine(). */
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        self.engine().render(diagnostic)
+    }
+}
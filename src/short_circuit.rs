@@ -0,0 +1,306 @@
+use crate::{
+    fold::{fold_children, Folder},
+    parser::Node,
+    semantics::is_relational,
+    span::Span,
+    visit::children,
+    BasicType, NodeType, Scope, TokenType,
+};
+
+/*
+    短路求值的显式化(可选的pass, 命令行--lower-short-circuit): 把语义AST中的&&和||改写成嵌套的if,
+    之后的解释器/代码生成只要按顺序求值每个表达式, 就自然满足短路语义(如i < n && a[i] != 0).
+        a && b  =>  int t = 0; if (a) { t = b != 0; }            值为t
+        a || b  =>  int t = 1; if (!a) { t = b != 0; }           值为t
+    条件表达式的分支中有&&/||时同样改写成if, 分支的值存入临时变量.
+    改写出的语句(前置语句)放在所在语句之前; while的条件需要前置语句时改写成
+        while (1) { 前置语句; if (!cond) break; 循环体 }
+    这样continue之后也会重新求值条件. 临时变量名以"__sc"开头, 不会与SysY程序中的名字冲突.
+    没有&&/||的表达式原样保留, 求值顺序不变.
+*/
+pub fn lower_short_circuit(annotated_ast: Vec<Node>) -> Vec<Node> {
+    let mut lowering = Lowering {
+        temps: 0,
+        prelude: vec![],
+    };
+    annotated_ast
+        .iter()
+        .map(|node| lowering.fold_node(node))
+        .collect()
+}
+
+struct Lowering {
+    temps: usize,
+    prelude: Vec<Node>, //当前语句中已经改写出的前置语句, 由lower_stmts放到语句之前
+}
+
+fn has_short_circuit(node: &Node) -> bool {
+    matches!(
+        node.node_type,
+        NodeType::BinOp(TokenType::And | TokenType::Or, ..)
+    ) || children(node).into_iter().any(has_short_circuit)
+}
+
+fn typed(node_type: NodeType, basic_type: BasicType, span: Span) -> Node {
+    Node {
+        basic_type,
+        span,
+        ..Node::new(node_type)
+    }
+}
+
+/* 以node_type替换node的内容, 保留node的编号, 类型和位置; 不复制node原有的子树. */
+fn rebuilt(node: &Node, node_type: NodeType) -> Node {
+    Node {
+        id: node.id,
+        node_type,
+        basic_type: node.basic_type.clone(),
+        span: node.span,
+    }
+}
+
+/* value != 0, 作为&&/||的结果(int 0或1). 比较和!的结果已经是0或1, 不再比较. */
+fn truth(value: Node) -> Node {
+    let span = value.span;
+    match value.node_type {
+        NodeType::Number(n) => typed(NodeType::Number((n != 0) as i32), BasicType::Const, span),
+        NodeType::UnaryOp(TokenType::Not, _) => value,
+        NodeType::BinOp(ref op, ..) if is_relational(op) => value,
+        _ => {
+            let zero = if value.basic_type == BasicType::Float {
+                typed(NodeType::FloatNumber(0.0), BasicType::Float, span)
+            } else {
+                typed(NodeType::Number(0), BasicType::Const, span)
+            };
+            let node_type = NodeType::BinOp(TokenType::NotEqual, Box::new(value), Box::new(zero));
+            typed(node_type, BasicType::Int, span)
+        }
+    }
+}
+
+fn not(value: Node) -> Node {
+    let span = value.span;
+    match value.node_type {
+        NodeType::Number(n) => typed(NodeType::Number((n == 0) as i32), BasicType::Const, span),
+        _ => typed(
+            NodeType::UnaryOp(TokenType::Not, Box::new(value)),
+            BasicType::Int,
+            span,
+        ),
+    }
+}
+
+/* 逗号表达式中被移到前置语句里的操作数: 赋值本身就是语句, 其余的包成表达式语句. */
+fn as_stmt(expr: Node) -> Node {
+    if let NodeType::Assign(..) = expr.node_type {
+        return expr;
+    }
+    let span = expr.span;
+    typed(NodeType::ExprStmt(Box::new(expr)), BasicType::Nil, span)
+}
+
+impl Lowering {
+    /*
+        新的局部临时变量, 声明语句放入前置语句, 返回带有类型的声明节点(树中的Decl没有类型),
+        读写它的Access/Assign记录的是它的编号.
+    */
+    fn temp(&mut self, ty: BasicType, init: Option<Node>, span: Span) -> Node {
+        let name = format!("__sc{}", self.temps);
+        self.temps += 1;
+        let inits = init.map(|init| vec![init]);
+        let decl = typed(
+            NodeType::Decl(ty.clone(), name, None, inits, Scope::Local),
+            BasicType::Nil,
+            span,
+        );
+        let referenced = Node {
+            basic_type: ty,
+            ..decl.clone()
+        };
+        let stmt = typed(NodeType::DeclStmt(vec![decl]), BasicType::Nil, span);
+        self.prelude.push(stmt);
+        referenced
+    }
+
+    fn read(decl: &Node, span: Span) -> Node {
+        let NodeType::Decl(_, name, ..) = &decl.node_type else {
+            unreachable!()
+        };
+        let node_type = NodeType::Access(name.clone(), None, decl.id);
+        typed(node_type, decl.basic_type.clone(), span)
+    }
+
+    fn write(decl: &Node, value: Node) -> Node {
+        let NodeType::Decl(_, name, ..) = &decl.node_type else {
+            unreachable!()
+        };
+        let span = value.span;
+        let node_type = NodeType::Assign(name.clone(), None, Box::new(value), decl.id);
+        typed(node_type, BasicType::Nil, span)
+    }
+
+    /* 单独改写一个表达式, 返回它的前置语句和值; self.prelude保持不变. */
+    fn isolated(&mut self, node: &Node) -> (Vec<Node>, Node) {
+        let outer = std::mem::take(&mut self.prelude);
+        let value = self.fold_node(node);
+        (std::mem::replace(&mut self.prelude, outer), value)
+    }
+
+    /* 依次改写语句, 每条语句的前置语句放在它之前. */
+    fn lower_stmts(&mut self, stmts: &[Node]) -> Vec<Node> {
+        let mut new_stmts = vec![];
+        for stmt in stmts {
+            // 同一条声明语句中后面的初始值可能用到前面的变量, 需要前置语句时拆成每个声明一条.
+            if let NodeType::DeclStmt(decls) = &stmt.node_type {
+                if decls.len() > 1 && has_short_circuit(stmt) {
+                    let split: Vec<Node> = decls
+                        .iter()
+                        .map(|decl| {
+                            typed(
+                                NodeType::DeclStmt(vec![decl.clone()]),
+                                BasicType::Nil,
+                                decl.span,
+                            )
+                        })
+                        .collect();
+                    new_stmts.extend(self.lower_stmts(&split));
+                    continue;
+                }
+            }
+            let (prelude, new_stmt) = self.isolated(stmt);
+            new_stmts.extend(prelude);
+            new_stmts.push(new_stmt);
+        }
+        new_stmts
+    }
+
+    /* if/while的分支: 有前置语句时包成语句块. */
+    fn lower_body(&mut self, stmt: &Node) -> Node {
+        let mut stmts = self.lower_stmts(std::slice::from_ref(stmt));
+        if stmts.len() == 1 {
+            return stmts.pop().unwrap();
+        }
+        typed(NodeType::Block(stmts), BasicType::Nil, stmt.span)
+    }
+
+    /* 把只在某些路径上求值的value连同它的前置语句放进if的分支中, 结果存入临时变量decl. */
+    fn store(decl: &Node, mut prelude: Vec<Node>, value: Node, span: Span) -> Node {
+        prelude.push(Lowering::write(decl, value));
+        typed(NodeType::Block(prelude), BasicType::Nil, span)
+    }
+}
+
+impl Folder for Lowering {
+    fn fold_block(&mut self, node: &Node) -> Node {
+        let NodeType::Block(stmts) = &node.node_type else {
+            unreachable!()
+        };
+        rebuilt(node, NodeType::Block(self.lower_stmts(stmts)))
+    }
+
+    fn fold_case(&mut self, node: &Node) -> Node {
+        let NodeType::Case(label, stmts) = &node.node_type else {
+            unreachable!()
+        };
+        rebuilt(node, NodeType::Case(label.clone(), self.lower_stmts(stmts)))
+    }
+
+    // 条件的前置语句留在self.prelude中, 放到整个if之前.
+    fn fold_if(&mut self, node: &Node) -> Node {
+        let NodeType::If(cond, on_true, on_false) = &node.node_type else {
+            unreachable!()
+        };
+        let cond = self.fold_node(cond);
+        let on_true = self.lower_body(on_true);
+        let on_false = on_false.as_ref().map(|f| Box::new(self.lower_body(f)));
+        rebuilt(
+            node,
+            NodeType::If(Box::new(cond), Box::new(on_true), on_false),
+        )
+    }
+
+    fn fold_while(&mut self, node: &Node) -> Node {
+        let NodeType::While(cond, body) = &node.node_type else {
+            unreachable!()
+        };
+        let (mut prelude, cond) = self.isolated(cond);
+        let body = self.lower_body(body);
+        if prelude.is_empty() {
+            return rebuilt(node, NodeType::While(Box::new(cond), Box::new(body)));
+        }
+        let span = node.span;
+        let exit = typed(
+            NodeType::If(
+                Box::new(not(cond)),
+                Box::new(typed(NodeType::Break, BasicType::Nil, span)),
+                None,
+            ),
+            BasicType::Nil,
+            span,
+        );
+        prelude.push(exit);
+        prelude.push(body);
+        let forever = typed(NodeType::Number(1), BasicType::Const, span);
+        let body = typed(NodeType::Block(prelude), BasicType::Nil, span);
+        rebuilt(node, NodeType::While(Box::new(forever), Box::new(body)))
+    }
+
+    fn fold_binop(&mut self, node: &Node) -> Node {
+        let NodeType::BinOp(ttype @ (TokenType::And | TokenType::Or), lhs, rhs) = &node.node_type
+        else {
+            return fold_children(self, node);
+        };
+        let span = node.span;
+        let lhs = self.fold_node(lhs);
+        let (rhs_prelude, rhs) = self.isolated(rhs);
+        let (init, cond) = if *ttype == TokenType::And {
+            (0, lhs)
+        } else {
+            (1, not(lhs))
+        };
+        let init = typed(NodeType::Number(init), BasicType::Const, span);
+        let decl = self.temp(BasicType::Int, Some(init), span);
+        let on_true = Lowering::store(&decl, rhs_prelude, truth(rhs), span);
+        let branch = NodeType::If(Box::new(cond), Box::new(on_true), None);
+        self.prelude.push(typed(branch, BasicType::Nil, span));
+        Lowering::read(&decl, span)
+    }
+
+    fn fold_cond(&mut self, node: &Node) -> Node {
+        let NodeType::Cond(cond, on_true, on_false) = &node.node_type else {
+            unreachable!()
+        };
+        if !has_short_circuit(on_true) && !has_short_circuit(on_false) {
+            return fold_children(self, node);
+        }
+        let span = node.span;
+        let cond = self.fold_node(cond);
+        let (true_prelude, on_true) = self.isolated(on_true);
+        let (false_prelude, on_false) = self.isolated(on_false);
+        let decl = self.temp(node.basic_type.clone(), None, span);
+        let on_true = Lowering::store(&decl, true_prelude, on_true, span);
+        let on_false = Lowering::store(&decl, false_prelude, on_false, span);
+        let branch = NodeType::If(Box::new(cond), Box::new(on_true), Some(Box::new(on_false)));
+        self.prelude.push(typed(branch, BasicType::Nil, span));
+        Lowering::read(&decl, span)
+    }
+
+    /* 后面的操作数有前置语句时, 它前面的操作数要先求值, 一起移到前置语句中. */
+    fn fold_comma(&mut self, node: &Node) -> Node {
+        let NodeType::Comma(operands) = &node.node_type else {
+            unreachable!()
+        };
+        let mut new_operands: Vec<Node> = vec![];
+        for operand in operands {
+            let before = self.prelude.len();
+            let new_operand = self.fold_node(operand);
+            if self.prelude.len() > before {
+                let added = self.prelude.split_off(before);
+                self.prelude.extend(new_operands.drain(..).map(as_stmt));
+                self.prelude.extend(added);
+            }
+            new_operands.push(new_operand);
+        }
+        rebuilt(node, NodeType::Comma(new_operands))
+    }
+}
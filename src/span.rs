@@ -0,0 +1,170 @@
+use crate::lexer::parse_line_directive;
+
+/*
+    源代码区间与源文件表. Token, Node和诊断信息都只记录Span(文件编号 + 字符区间),
+    需要行号/列号时再通过SourceMap换算, 各阶段不再各自从字符流里数换行符.
+*/
+
+/* 源文件在SourceMap中的编号, 按加入的顺序从0开始. 只编译一个文件时总是FileId(0). */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileId(pub usize);
+
+/* [start, end): 源代码字符流中的一段区间(按字符而不是字节计数). */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub file_id: FileId,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(file_id: FileId, start: usize, end: usize) -> Self {
+        Span {
+            file_id,
+            start,
+            end,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end.saturating_sub(self.start)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /* 从self开始到other结束的区间. */
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.file_id, self.start, other.end)
+    }
+
+    pub fn range(&self) -> std::ops::Range<usize> {
+        self.start..self.end
+    }
+}
+
+/* 一个源文件: 文件名, 字符流, 以及每一行开头的位置(用于换算行号列号). */
+pub struct SourceFile {
+    pub name: String,
+    pub chars: Vec<char>,
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    pub fn new(name: impl Into<String>, text: &str) -> Self {
+        Self::from_chars(name, text.chars().collect())
+    }
+
+    pub fn from_chars(name: impl Into<String>, chars: Vec<char>) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(
+                chars
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &c)| c == '\n')
+                    .map(|(i, _)| i + 1),
+            )
+            .collect();
+        SourceFile {
+            name: name.into(),
+            chars,
+            line_starts,
+        }
+    }
+
+    /* pos所在的(物理)行号, 从1开始. */
+    pub fn line(&self, pos: usize) -> usize {
+        let pos = pos.min(self.chars.len());
+        self.line_starts.partition_point(|&start| start <= pos)
+    }
+
+    /* pos所在的行号和列号(都从1开始). */
+    pub fn line_col(&self, pos: usize) -> (usize, usize) {
+        let line = self.line(pos);
+        (
+            line,
+            pos.min(self.chars.len()) - self.line_starts[line - 1] + 1,
+        )
+    }
+
+    /* 第line行(从1开始)的字符区间, 不含换行符. */
+    pub fn line_range(&self, line: usize) -> std::ops::Range<usize> {
+        let start = self.line_starts[line - 1];
+        let end = self
+            .line_starts
+            .get(line)
+            .map_or(self.chars.len(), |&next| next - 1);
+        start..end
+    }
+
+    /* pos所在的那一整行(不含换行符). */
+    pub fn line_text(&self, pos: usize) -> &[char] {
+        &self.chars[self.line_range(self.line(pos))]
+    }
+
+    /* 考虑#line指令后pos所在的逻辑位置: (文件名, 行号). */
+    pub fn logical_location(&self, pos: usize) -> (String, usize) {
+        let target = self.line(pos);
+        let mut file = self.name.clone();
+        let mut line = 1;
+        for physical in 1..target {
+            match parse_line_directive(&self.chars[self.line_range(physical)]) {
+                Some((n, f)) => {
+                    line = n;
+                    if let Some(f) = f {
+                        file = f;
+                    }
+                }
+                None => line += 1,
+            }
+        }
+        (file, line)
+    }
+}
+
+/* 所有参与编译的源文件, FileId是它们在这里的下标. */
+#[derive(Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, text: &str) -> FileId {
+        self.files.push(SourceFile::new(name, text));
+        FileId(self.files.len() - 1)
+    }
+
+    /* 读入一个源文件. */
+    pub fn load(&mut self, path: &str) -> std::io::Result<FileId> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(self.add(path, &text))
+    }
+
+    pub fn file(&self, id: FileId) -> &SourceFile {
+        &self.files[id.0]
+    }
+
+    /* 同file, 但id不在这里时返回None. */
+    pub fn get(&self, id: FileId) -> Option<&SourceFile> {
+        self.files.get(id.0)
+    }
+
+    /* span起点的(物理)行号和列号. */
+    pub fn line_col(&self, span: Span) -> (usize, usize) {
+        self.file(span.file_id).line_col(span.start)
+    }
+
+    /* span起点经#line调整后的位置: (文件名, 行号, 列号). */
+    pub fn location(&self, span: Span) -> (String, usize, usize) {
+        let file = self.file(span.file_id);
+        let (name, line) = file.logical_location(span.start);
+        (name, line, file.line_col(span.start).1)
+    }
+}
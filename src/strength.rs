@@ -0,0 +1,234 @@
+use crate::{
+    cfg::{Cfg, Dominators},
+    induction::Inductions,
+    ir::{BinOp, BlockId, Function, Inst, InstKind, Module, Operand, Type, Value},
+    loops::LoopForest,
+};
+use std::collections::HashMap;
+
+/*
+    强度削弱:
+    1. 乘以2的幂换成左移; 除以和模2的幂(有符号, 向零取整)换成移位和与运算:
+       bias = (x >> 31) & (2^k - 1), x / 2^k = (x + bias) >> k, x % 2^k = ((x + bias) & (2^k - 1)) - bias.
+    2. 循环中归纳变量乘以常数(i * k, 常见于二维数组的下标i * N + j)换成随循环递增的新phi:
+       对j = scale * i + offset, i = phi [init, i + c], j * k进入循环时为(init * scale + offset) * k, 每次回边加上c * scale * k.
+*/
+pub fn run_module(module: &mut Module) -> bool {
+    let mut changed = false;
+    for function in module.functions.iter_mut().filter(|f| !f.is_declaration()) {
+        changed |= run(function);
+    }
+    changed
+}
+
+pub fn run(function: &mut Function) -> bool {
+    let cfg = Cfg::build(function);
+    let doms = Dominators::compute(&cfg);
+    let loops = LoopForest::compute(&cfg, &doms);
+    run_with(function, &doms, &loops)
+}
+
+/* 同run, 使用已经算好的支配树和循环. */
+pub fn run_with(function: &mut Function, doms: &Dominators, loops: &LoopForest) -> bool {
+    let mut changed = induction_multiplications(function, doms, loops);
+    changed |= arithmetic(function);
+    if changed {
+        function.renumber_values();
+    }
+    changed
+}
+
+/* 正的2的幂返回指数. */
+fn log2(n: i32) -> Option<u32> {
+    (n > 0 && n & (n - 1) == 0).then(|| n.trailing_zeros())
+}
+
+fn binary(dest: Value, op: BinOp, a: Operand, b: Operand) -> Inst {
+    Inst {
+        dest: Some(dest),
+        ty: Type::I32,
+        kind: InstKind::Binary(op, a, b),
+    }
+}
+
+/* 乘, 除, 模2的幂; 返回是否改写了指令. */
+fn arithmetic(function: &mut Function) -> bool {
+    let mut changed = false;
+    for b in 0..function.blocks.len() {
+        let insts = std::mem::take(&mut function.blocks[b].insts);
+        let mut out = Vec::with_capacity(insts.len());
+        for inst in insts {
+            let (Some(dest), InstKind::Binary(op, x, y)) = (inst.dest, &inst.kind) else {
+                out.push(inst);
+                continue;
+            };
+            if inst.ty != Type::I32 {
+                out.push(inst);
+                continue;
+            }
+            let (op, x, y) = (*op, x.clone(), y.clone());
+            match (op, &x, &y) {
+                (BinOp::Mul, other, Operand::Int(n)) | (BinOp::Mul, Operand::Int(n), other)
+                    if log2(*n).is_some_and(|k| k > 0) =>
+                {
+                    let k = log2(*n).unwrap() as i32;
+                    out.push(binary(dest, BinOp::Shl, other.clone(), Operand::Int(k)));
+                }
+                (BinOp::Div | BinOp::Rem, _, Operand::Int(d))
+                    if *d != i32::MIN && log2(d.abs()).is_some_and(|k| k > 0) =>
+                {
+                    let k = log2(d.abs()).unwrap() as i32;
+                    let mask = Operand::Int((1 << k) - 1);
+                    let sign = function.new_value(Type::I32);
+                    let bias = function.new_value(Type::I32);
+                    let biased = function.new_value(Type::I32);
+                    out.push(binary(sign, BinOp::Shr, x.clone(), Operand::Int(31)));
+                    out.push(binary(bias, BinOp::And, Operand::Value(sign), mask.clone()));
+                    out.push(binary(biased, BinOp::Add, x, Operand::Value(bias)));
+                    if op == BinOp::Rem {
+                        // 余数的符号与被除数相同, 与除数的符号无关.
+                        let low = function.new_value(Type::I32);
+                        out.push(binary(low, BinOp::And, Operand::Value(biased), mask));
+                        out.push(binary(
+                            dest,
+                            BinOp::Sub,
+                            Operand::Value(low),
+                            Operand::Value(bias),
+                        ));
+                    } else if *d > 0 {
+                        out.push(binary(
+                            dest,
+                            BinOp::Shr,
+                            Operand::Value(biased),
+                            Operand::Int(k),
+                        ));
+                    } else {
+                        let quotient = function.new_value(Type::I32);
+                        out.push(binary(
+                            quotient,
+                            BinOp::Shr,
+                            Operand::Value(biased),
+                            Operand::Int(k),
+                        ));
+                        out.push(binary(
+                            dest,
+                            BinOp::Sub,
+                            Operand::Int(0),
+                            Operand::Value(quotient),
+                        ));
+                    }
+                }
+                _ => {
+                    out.push(inst);
+                    continue;
+                }
+            }
+            changed = true;
+        }
+        function.blocks[b].insts = out;
+    }
+    changed
+}
+
+/* 在块的终结指令之前插入指令. */
+fn insert_before_terminator(function: &mut Function, block: BlockId, inst: Inst) {
+    let insts = &mut function.block_mut(block).insts;
+    let at = insts.len().saturating_sub(1);
+    insts.insert(at, inst);
+}
+
+/* 循环中的i * k换成新的归纳变量; 返回是否改写了指令. */
+fn induction_multiplications(
+    function: &mut Function,
+    doms: &Dominators,
+    loops: &LoopForest,
+) -> bool {
+    let ivs = Inductions::compute(function, loops);
+    if ivs.is_empty() {
+        return false;
+    }
+    // (归纳变量, 系数) -> 新的归纳变量, 同一个i * k只建一次.
+    // 先找出所有的i * k, 再插入新的指令, 避免边遍历边修改.
+    let mut candidates = vec![];
+    for (b, block) in function.blocks.iter().enumerate() {
+        for inst in &block.insts {
+            let (Some(dest), InstKind::Binary(BinOp::Mul, x, y)) = (inst.dest, &inst.kind) else {
+                continue;
+            };
+            let (iv, k) = match (x, y) {
+                (Operand::Value(v), Operand::Int(k)) | (Operand::Int(k), Operand::Value(v))
+                    if ivs.affine(*v).is_some() =>
+                {
+                    (*v, *k)
+                }
+                _ => continue,
+            };
+            let basic = ivs.affine(iv).unwrap().basic;
+            if doms.dominates(ivs.basic(basic).unwrap().header, BlockId(b as u32)) {
+                candidates.push((dest, iv, k));
+            }
+        }
+    }
+    // (归纳变量, 系数) -> 新的归纳变量, 同一个i * k只建一次.
+    let mut created: HashMap<(Value, i32), Value> = HashMap::new();
+    let mut replace = HashMap::new();
+    for (dest, iv, k) in candidates {
+        let form = ivs.closed_form(iv).unwrap();
+        let induction = ivs.basic(ivs.affine(iv).unwrap().basic).unwrap();
+        let scaled = match created.get(&(iv, k)) {
+            Some(v) => *v,
+            None => {
+                let phi = function.new_value(Type::I32);
+                let next = function.new_value(Type::I32);
+                let (scale, offset) = (form.scale.wrapping_mul(k), form.offset.wrapping_mul(k));
+                let start = match &form.init {
+                    Operand::Int(n) => Operand::Int(n.wrapping_mul(scale).wrapping_add(offset)),
+                    init => {
+                        let mut start = function.new_value(Type::I32);
+                        let mul = binary(start, BinOp::Mul, init.clone(), Operand::Int(scale));
+                        insert_before_terminator(function, induction.preheader, mul);
+                        if offset != 0 {
+                            let shifted = function.new_value(Type::I32);
+                            let add = binary(
+                                shifted,
+                                BinOp::Add,
+                                Operand::Value(start),
+                                Operand::Int(offset),
+                            );
+                            insert_before_terminator(function, induction.preheader, add);
+                            start = shifted;
+                        }
+                        Operand::Value(start)
+                    }
+                };
+                let step = Operand::Int(form.step.wrapping_mul(k));
+                let add = binary(next, BinOp::Add, Operand::Value(phi), step);
+                insert_before_terminator(function, induction.latch, add);
+                function.block_mut(induction.header).insts.insert(
+                    0,
+                    Inst {
+                        dest: Some(phi),
+                        ty: Type::I32,
+                        kind: InstKind::Phi(vec![
+                            (induction.preheader, start),
+                            (induction.latch, Operand::Value(next)),
+                        ]),
+                    },
+                );
+                created.insert((iv, k), phi);
+                phi
+            }
+        };
+        replace.insert(dest, Operand::Value(scaled));
+    }
+    if replace.is_empty() {
+        return false;
+    }
+    for block in &mut function.blocks {
+        block
+            .insts
+            .retain(|inst| !inst.dest.is_some_and(|d| replace.contains_key(&d)));
+    }
+    function.replace_values(&replace);
+    true
+}
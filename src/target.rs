@@ -0,0 +1,27 @@
+/*
+    后端的目标机器. SysY比赛的两个目标: 64位RISC-V(RV64GC)和32位ARM(ARMv7-A, 带VFP).
+    与目标有关的参数(指令延迟, 调用约定等)都按这里的Target选择.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Target {
+    #[default]
+    Riscv64,
+    Armv7,
+}
+
+impl Target {
+    pub const ALL: [Target; 2] = [Target::Riscv64, Target::Armv7];
+
+    /* 命令行中--target的取值. */
+    pub fn name(self) -> &'static str {
+        match self {
+            Target::Riscv64 => "riscv64",
+            Target::Armv7 => "armv7",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| t.name() == name)
+    }
+}
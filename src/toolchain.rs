@@ -0,0 +1,223 @@
+use crate::codegen::object;
+use crate::target::Target;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/*
+    外部工具链: 把后端生成的汇编(.s)交给汇编器得到目标文件(.o), 再用链接器和运行时库libsysy链接成可执行文件.
+    默认使用目标机器的GNU交叉工具链(riscv64-linux-gnu-*, arm-linux-gnueabihf-*)并静态链接,
+    生成的程序可以直接在开发板或qemu-user中运行. 汇编器也可以换成内置的(codegen::object, 只支持riscv64).
+    命令可以带参数(如"clang --target=riscv64-linux-gnu"), 按空白切分; 汇编时在后面加上-o <.o> <.s>,
+    链接时加上各个.o, 运行时库和-o <可执行文件>.
+    工具找不到或者执行失败时返回LinkError, 指出是哪一步, 执行的命令和工具的输出.
+    没有现成的libsysy时, 用随编译器一起发布的运行时库源码(runtime/sylib.c)现场编译, 见build_runtime.
+*/
+
+/* 随编译器发布的运行时库源码, 与比赛的sylib.c/sylib.h接口相同. */
+pub const RUNTIME_SOURCE: &str = include_str!("../runtime/sylib.c");
+pub const RUNTIME_HEADER: &str = include_str!("../runtime/sylib.h");
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Assembler {
+    Builtin,
+    Command(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Toolchain {
+    pub target: Target,
+    pub assembler: Assembler,
+    pub linker: String,
+    pub runtime: Option<PathBuf>, //libsysy(.a, .o或交给链接器编译的.c), None时不链接运行时库
+}
+
+#[derive(Debug)]
+pub enum LinkError {
+    Missing {
+        step: &'static str,
+        command: String,
+    },
+    Failed {
+        step: &'static str,
+        command: String,
+        status: Option<i32>,
+        output: String,
+    },
+    Builtin(String), //内置汇编器的错误
+    Io(String),
+}
+
+impl std::fmt::Display for LinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LinkError::Missing { step, command } => write!(
+                f,
+                "cannot run the {} `{}`: command not found (install it or choose another one with --{})",
+                step,
+                command,
+                if *step == "assembler" { "as" } else { "ld" }
+            ),
+            LinkError::Failed {
+                step,
+                command,
+                status,
+                output,
+            } => {
+                match status {
+                    Some(code) => write!(f, "the {} failed with exit code {}", step, code)?,
+                    None => write!(f, "the {} was terminated by a signal", step)?,
+                }
+                write!(f, "\n  command: {}", command)?;
+                if !output.trim().is_empty() {
+                    write!(f, "\n{}", output.trim_end())?;
+                }
+                Ok(())
+            }
+            LinkError::Builtin(message) => write!(f, "builtin assembler: {}", message),
+            LinkError::Io(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for LinkError {}
+
+impl Toolchain {
+    /* 目标机器默认的交叉工具链. */
+    pub fn for_target(target: Target) -> Self {
+        let (assembler, linker) = match target {
+            Target::Riscv64 => (
+                "riscv64-linux-gnu-as -march=rv64gc -mabi=lp64d",
+                "riscv64-linux-gnu-gcc -static",
+            ),
+            Target::Armv7 => (
+                "arm-linux-gnueabihf-as -march=armv7-a -mfpu=vfpv3-d16 -mfloat-abi=hard",
+                "arm-linux-gnueabihf-gcc -static",
+            ),
+        };
+        Toolchain {
+            target,
+            assembler: Assembler::Command(assembler.to_string()),
+            linker: linker.to_string(),
+            runtime: None,
+        }
+    }
+
+    /* 汇编asm的完整命令行; 内置汇编器时为None. */
+    pub fn assemble_command(&self, asm: &Path, object: &Path) -> Option<Vec<String>> {
+        let Assembler::Command(command) = &self.assembler else {
+            return None;
+        };
+        let mut args: Vec<String> = command.split_whitespace().map(String::from).collect();
+        args.push("-o".to_string());
+        args.push(object.display().to_string());
+        args.push(asm.display().to_string());
+        Some(args)
+    }
+
+    pub fn link_command(&self, objects: &[&Path], output: &Path) -> Vec<String> {
+        let mut args: Vec<String> = self.linker.split_whitespace().map(String::from).collect();
+        args.extend(objects.iter().map(|o| o.display().to_string()));
+        // 运行时库放在最后, 静态库只提取前面用到的符号.
+        if let Some(runtime) = &self.runtime {
+            args.push(runtime.display().to_string());
+        }
+        args.push("-o".to_string());
+        args.push(output.display().to_string());
+        args
+    }
+
+    pub fn assemble(&self, asm: &Path, object: &Path) -> Result<(), LinkError> {
+        match self.assemble_command(asm, object) {
+            Some(args) => run("assembler", &args),
+            None => {
+                if self.target != Target::Riscv64 {
+                    return Err(LinkError::Builtin(format!(
+                        "the {} target is not supported",
+                        self.target.name()
+                    )));
+                }
+                let text = std::fs::read_to_string(asm)
+                    .map_err(|e| LinkError::Io(format!("cannot read {}: {}", asm.display(), e)))?;
+                let bytes = object::assemble(&text).map_err(LinkError::Builtin)?;
+                std::fs::write(object, bytes)
+                    .map_err(|e| LinkError::Io(format!("cannot write {}: {}", object.display(), e)))
+            }
+        }
+    }
+
+    pub fn link(&self, objects: &[&Path], output: &Path) -> Result<(), LinkError> {
+        if let Some(runtime) = self.runtime.as_ref().filter(|r| !r.exists()) {
+            return Err(LinkError::Io(format!(
+                "runtime library {} does not exist",
+                runtime.display()
+            )));
+        }
+        run("linker", &self.link_command(objects, output))
+    }
+
+    /*
+        把内置的运行时库源码写到dir中, 用链接器命令(gcc/clang这类编译器驱动)编译成dir/sylib.o并返回它的路径.
+        编译失败或者找不到编译器时报告为runtime compiler这一步.
+    */
+    pub fn build_runtime(&self, dir: &Path) -> Result<PathBuf, LinkError> {
+        let write = |name: &str, text: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, text)
+                .map_err(|e| LinkError::Io(format!("cannot write {}: {}", path.display(), e)))?;
+            Ok(path)
+        };
+        std::fs::create_dir_all(dir)
+            .map_err(|e| LinkError::Io(format!("cannot create {}: {}", dir.display(), e)))?;
+        write("sylib.h", RUNTIME_HEADER)?;
+        let source = write("sylib.c", RUNTIME_SOURCE)?;
+        let object = dir.join("sylib.o");
+        let mut args: Vec<String> = self.linker.split_whitespace().map(String::from).collect();
+        args.extend(["-O2", "-c", "-o"].map(String::from));
+        args.push(object.display().to_string());
+        args.push(source.display().to_string());
+        run("runtime compiler", &args)?;
+        Ok(object)
+    }
+
+    /* 汇编asm, 目标文件放在可执行文件旁边(扩展名.o), 再链接成output. */
+    pub fn build(&self, asm: &Path, output: &Path) -> Result<(), LinkError> {
+        let object = output.with_extension("o");
+        self.assemble(asm, &object)?;
+        self.link(&[&object], output)
+    }
+}
+
+/* 在目录中找libsysy.a, 找不到时为None. */
+pub fn find_runtime(dir: &Path) -> Option<PathBuf> {
+    let path = dir.join("libsysy.a");
+    path.is_file().then_some(path)
+}
+
+fn run(step: &'static str, args: &[String]) -> Result<(), LinkError> {
+    if args.is_empty() {
+        return Err(LinkError::Io(format!("the {} command is empty", step)));
+    }
+    let command = args.join(" ");
+    let result = Command::new(&args[0]).args(&args[1..]).output();
+    let output = match result {
+        Ok(output) => output,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(LinkError::Missing {
+                step,
+                command: args[0].clone(),
+            })
+        }
+        Err(e) => return Err(LinkError::Io(format!("cannot run `{}`: {}", command, e))),
+    };
+    if output.status.success() {
+        return Ok(());
+    }
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    Err(LinkError::Failed {
+        step,
+        command,
+        status: output.status.code(),
+        output: text,
+    })
+}
@@ -0,0 +1,224 @@
+use crate::{
+    diagnostics::{Diagnostic, DiagnosticKind},
+    parser::{Node, NodeId},
+    span::Span,
+    visit::children,
+    xref::{usage_kind, UsageKind},
+    BasicType, NodeType, Scope, TokenType,
+};
+use std::collections::{HashMap, HashSet};
+
+/*
+    使用前未初始化检查: 在语义分析后的AST上做一遍前向数据流分析, 对每个程序点求出
+    "在所有到达这里的路径上都已经被赋值"的局部变量集合(must分析, 路径汇合处取交集).
+    只检查没有初始化器的局部int/float标量; 数组, 全局变量和参数都视为已初始化.
+    读取一个可能未初始化的变量时报告警告, 同一个变量只报告一次.
+*/
+
+/* 一个程序点的状态: 已初始化的变量(Decl节点的id). 不可达(return/break/continue之后)时不参与汇合. */
+#[derive(Clone)]
+struct State {
+    init: HashSet<NodeId>,
+    reachable: bool,
+}
+
+impl State {
+    fn unreachable() -> Self {
+        State {
+            init: HashSet::new(),
+            reachable: false,
+        }
+    }
+
+    /* 两条路径汇合: 不可达的一方不提供信息, 否则取交集. */
+    fn meet(self, other: State) -> State {
+        match (self.reachable, other.reachable) {
+            (false, _) => other,
+            (_, false) => self,
+            _ => State {
+                init: self.init.intersection(&other.init).copied().collect(),
+                reachable: true,
+            },
+        }
+    }
+}
+
+struct Analysis {
+    tracked: HashMap<NodeId, Span>, //被检查的变量: Decl的id -> 声明的位置
+    reported: HashSet<NodeId>,
+    breaks: Vec<Vec<State>>, //每层循环/switch中break时的状态
+    diagnostics: Vec<Diagnostic>,
+}
+
+/* 返回annotated_ast中所有可能在初始化之前被读取的变量的警告, 按源代码顺序. */
+pub fn uninitialized_reads(annotated_ast: &[Node]) -> Vec<Diagnostic> {
+    let mut analysis = Analysis {
+        tracked: HashMap::new(),
+        reported: HashSet::new(),
+        breaks: vec![],
+        diagnostics: vec![],
+    };
+    for node in annotated_ast {
+        if let NodeType::Func(_, _, _, body) = &node.node_type {
+            let entry = State {
+                init: HashSet::new(),
+                reachable: true,
+            };
+            analysis.stmt(body, entry);
+        }
+    }
+    analysis.diagnostics
+}
+
+/* 条件是非零常量时, 条件为假的分支不可达(如while (1)). */
+fn always_true(cond: &Node) -> bool {
+    matches!(cond.node_type, NodeType::Number(n) if n != 0)
+}
+
+impl Analysis {
+    fn stmt(&mut self, node: &Node, state: State) -> State {
+        match &node.node_type {
+            NodeType::Block(stmts) | NodeType::DeclStmt(stmts) => {
+                stmts.iter().fold(state, |state, s| self.stmt(s, state))
+            }
+            NodeType::Decl(ty, _, dims, inits, scope) => {
+                let mut state = dims
+                    .iter()
+                    .flatten()
+                    .chain(inits.iter().flatten())
+                    .fold(state, |state, n| self.expr(n, state));
+                let scalar = matches!(ty, BasicType::Int | BasicType::Float);
+                if inits.is_none() && scalar && *scope == Scope::Local {
+                    // 循环体中的声明每次执行都重新变成未初始化
+                    self.tracked.insert(node.id, node.span);
+                    state.init.remove(&node.id);
+                }
+                state
+            }
+            NodeType::ExprStmt(expr) => self.expr(expr, state),
+            NodeType::Return(expr) => {
+                if let Some(expr) = expr {
+                    self.expr(expr, state);
+                }
+                State::unreachable()
+            }
+            NodeType::Break => {
+                if let Some(breaks) = self.breaks.last_mut() {
+                    breaks.push(state);
+                }
+                State::unreachable()
+            }
+            // continue回到循环开头, 那里的状态就是进入循环时的状态, 不需要记录.
+            NodeType::Continue => State::unreachable(),
+            NodeType::If(cond, then, otherwise) => {
+                let state = self.expr(cond, state);
+                let after_then = self.stmt(then, state.clone());
+                let after_else = match otherwise {
+                    Some(otherwise) => self.stmt(otherwise, state),
+                    None => state,
+                };
+                after_then.meet(after_else)
+            }
+            /*
+                循环体只会增加已初始化的变量, 所以循环开头的状态就是第一次进入时的状态.
+                退出循环的路径: 条件为假(条件恒真时没有)和每个break.
+            */
+            NodeType::While(cond, body) => {
+                let state = self.expr(cond, state);
+                self.breaks.push(vec![]);
+                self.stmt(body, state.clone());
+                let exit = if always_true(cond) {
+                    State::unreachable()
+                } else {
+                    state
+                };
+                let breaks = self.breaks.pop().unwrap();
+                breaks.into_iter().fold(exit, State::meet)
+            }
+            /*
+                每个case的入口: 从switch跳过来, 或者从上一个case落下来(fall-through).
+                退出switch的路径: 最后一个case执行完, 每个break, 以及没有default时一个都没匹配上.
+            */
+            NodeType::Switch(cond, cases) => {
+                let entry = self.expr(cond, state);
+                self.breaks.push(vec![]);
+                let mut fallthrough = State::unreachable();
+                let mut has_default = false;
+                for case in cases {
+                    let NodeType::Case(label, stmts) = &case.node_type else {
+                        continue;
+                    };
+                    has_default |= label.is_none();
+                    let start = fallthrough.meet(entry.clone());
+                    fallthrough = stmts.iter().fold(start, |state, s| self.stmt(s, state));
+                }
+                let exit = if has_default {
+                    fallthrough
+                } else {
+                    fallthrough.meet(entry)
+                };
+                let breaks = self.breaks.pop().unwrap();
+                breaks.into_iter().fold(exit, State::meet)
+            }
+            // Assign等也可以直接作为语句出现
+            _ => self.expr(node, state),
+        }
+    }
+
+    fn expr(&mut self, node: &Node, state: State) -> State {
+        match &node.node_type {
+            // 读写方式由xref::usage_kind判断; 下标和右边的表达式先求值.
+            NodeType::Access(name, _, decl) | NodeType::Assign(name, _, _, decl) => {
+                let state = children(node)
+                    .into_iter()
+                    .fold(state, |state, n| self.expr(n, state));
+                match usage_kind(node).unwrap() {
+                    UsageKind::Read | UsageKind::ReadWrite => self.read(node, name, *decl, state),
+                    UsageKind::Write => {
+                        let mut state = state;
+                        state.init.insert(*decl);
+                        state
+                    }
+                }
+            }
+            // &&和||的右操作数不一定被求值, 它初始化的变量在之后不算已初始化.
+            NodeType::BinOp(TokenType::And | TokenType::Or, lhs, rhs) => {
+                let state = self.expr(lhs, state);
+                self.expr(rhs, state.clone());
+                state
+            }
+            NodeType::BinOp(_, lhs, rhs) => {
+                let state = self.expr(lhs, state);
+                self.expr(rhs, state)
+            }
+            NodeType::UnaryOp(_, operand) | NodeType::Cast(_, operand) => self.expr(operand, state),
+            NodeType::Cond(cond, on_true, on_false) => {
+                let state = self.expr(cond, state);
+                let after_true = self.expr(on_true, state.clone());
+                let after_false = self.expr(on_false, state);
+                after_true.meet(after_false)
+            }
+            NodeType::Comma(nodes) | NodeType::Call(_, nodes, _) | NodeType::InitList(nodes) => {
+                nodes.iter().fold(state, |state, n| self.expr(n, state))
+            }
+            _ => state,
+        }
+    }
+
+    /* 读取变量: 可能未初始化时报告, 之后把它当作已初始化, 避免同一个错误反复出现. */
+    fn read(&mut self, node: &Node, name: &str, decl: NodeId, mut state: State) -> State {
+        let Some(&declared) = self.tracked.get(&decl) else {
+            return state;
+        };
+        if state.reachable && !state.init.contains(&decl) && self.reported.insert(decl) {
+            self.diagnostics.push(
+                Diagnostic::warning(format!("variable `{}` may be used uninitialized", name))
+                    .with_kind(DiagnosticKind::UninitializedRead)
+                    .with_label(node.span, "read here")
+                    .with_label(declared, "declared here without an initializer"),
+            );
+        }
+        state.init.insert(decl);
+        state
+    }
+}
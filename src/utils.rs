@@ -1,239 +1,672 @@
-use crate::lexer::Token;
-use crate::parser::Node;
-use crate::NodeType;
+use crate::lexer::{check_span_coverage, Token};
+use crate::parser::{binary_precedence, Node, L_OR_PREC};
+use crate::visit::{walk, walk_all, Visitor};
+use crate::TokenType;
+use crate::{BasicType, NodeType, Scope};
 use std::fs::File;
-use std::io::Write;
+use std::io::{BufWriter, Write};
 use std::path::Path;
 
-pub fn print_tokens(tokens: &Vec<Token>, path: &Path) {
+pub fn print_tokens(tokens: &[Token], path: &Path) {
     //用于将Token向量写入文件中
     let mut output = File::create(path.with_extension("tokens")).unwrap();
-    let mut i = 0;
-    for token in tokens {
+    for (i, token) in tokens.iter().enumerate() {
         //使用一个循环, 迭代向量中的每一个token, 将它们按指定格式写入文件中
         output
             .write_fmt(format_args!("TokenNo:{}\n{:?}\n", i, token))
             .expect("");
-        i += 1;
     }
 }
 
-pub fn print_tree(ast: &Vec<Node>, path: &Path, extension: &str, with_type: bool) {
+/*
+    detokenize: 把token流还原成可编译的源代码.
+    如果token流带有trivia(LexOptions::preserve_trivia)从而覆盖了整个源文件, 则逐个拼接token原文, 结果与源文件完全一致;
+    否则按规范格式输出: token之间用空格分隔, 在';' '{' '}'之后换行并按花括号层数缩进.
+*/
+pub fn detokenize(tokens: &[Token]) -> String {
+    let exact = tokens
+        .first()
+        .is_some_and(|t| check_span_coverage(tokens, t.buf.len()).is_ok());
+    if exact {
+        return tokens.iter().map(|t| t.text()).collect();
+    }
+    let mut out = String::new();
+    // braces记录每层花括号是否是初始化列表, 初始化列表的花括号不换行.
+    let mut braces: Vec<bool> = vec![];
+    let mut line_start = true;
+    let mut prev: Option<&TokenType> = None;
+    for token in tokens {
+        use TokenType::*;
+        let in_init = braces.last() == Some(&true);
+        let opens_init = token.sort == LeftBrace && (prev == Some(&Assign) || in_init);
+        let closes_block = token.sort == RightBrace && braces.last() == Some(&false);
+        if closes_block {
+            braces.pop();
+        }
+        if line_start {
+            let depth = braces.iter().filter(|inline| !**inline).count();
+            out.push_str(&"    ".repeat(depth));
+        } else {
+            let glue = matches!(
+                token.sort,
+                Semicolon | Comma | RightParen | RightBracket | LeftBracket
+            ) || matches!(prev, Some(LeftParen) | Some(LeftBracket))
+                || (token.sort == LeftParen && matches!(prev, Some(Identifier(_))))
+                || (token.sort == RightBrace && in_init)
+                || (prev == Some(&LeftBrace) && in_init);
+            if !glue {
+                out.push(' ');
+            }
+        }
+        out.push_str(&token.text());
+        match token.sort {
+            LeftBrace => braces.push(opens_init),
+            RightBrace if !closes_block => {
+                braces.pop();
+            }
+            _ => {}
+        }
+        line_start = token.sort == Semicolon && braces.last() != Some(&true)
+            || (token.sort == LeftBrace && !opens_init)
+            || closes_block;
+        if line_start {
+            out.push('\n');
+        }
+        prev = Some(&token.sort);
+    }
+    if !line_start {
+        out.push('\n');
+    }
+    out
+}
+
+/*
+    浮点数的输出格式, dump文件, 解释器的putfloat/putf和后端的常量输出都应使用同一份配置,
+    默认与评测机的期望一致: 类似C语言printf的%f, 保留6位小数.
+    epsilon是比较两个浮点输出时允许的误差.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FloatFormat {
+    pub precision: usize,
+    pub epsilon: f64,
+}
+
+impl Default for FloatFormat {
+    fn default() -> Self {
+        FloatFormat {
+            precision: 6,
+            epsilon: 1e-6,
+        }
+    }
+}
+
+impl FloatFormat {
+    /* 按%.Nf格式化, nan/inf的写法也与glibc的printf一致. */
+    pub fn format(&self, value: f32) -> String {
+        let value = value as f64;
+        if value.is_nan() {
+            let sign = if value.is_sign_negative() { "-" } else { "" };
+            format!("{}nan", sign)
+        } else if value.is_infinite() {
+            let sign = if value < 0.0 { "-" } else { "" };
+            format!("{}inf", sign)
+        } else {
+            format!("{:.*}", self.precision, value)
+        }
+    }
+
+    /* 两个浮点数在epsilon(绝对误差或相对误差)内视为相等. */
+    pub fn approx_eq(&self, lhs: f64, rhs: f64) -> bool {
+        if lhs == rhs || (lhs.is_nan() && rhs.is_nan()) {
+            return true;
+        }
+        let diff = (lhs - rhs).abs();
+        diff <= self.epsilon || diff <= self.epsilon * lhs.abs().max(rhs.abs())
+    }
+
+    /*
+        比较两段程序输出: 按空白切分后逐项比较, 两项都能解析成数字时按approx_eq比较,
+        否则要求完全相同.
+    */
+    pub fn outputs_match(&self, actual: &str, expected: &str) -> bool {
+        let actual: Vec<&str> = actual.split_whitespace().collect();
+        let expected: Vec<&str> = expected.split_whitespace().collect();
+        actual.len() == expected.len()
+            && actual.iter().zip(&expected).all(|(a, e)| {
+                a == e
+                    || match (a.parse::<f64>(), e.parse::<f64>()) {
+                        (Ok(a), Ok(e)) => self.approx_eq(a, e),
+                        _ => false,
+                    }
+            })
+    }
+}
+
+pub fn print_tree(ast: &[Node], path: &Path, extension: &str, with_type: bool) {
+    print_tree_with(ast, path, extension, with_type, &FloatFormat::default());
+}
+
+/* 同print_tree, 但可以指定浮点数的输出格式. */
+pub fn print_tree_with(
+    ast: &[Node],
+    path: &Path,
+    extension: &str,
+    with_type: bool,
+    float_format: &FloatFormat,
+) {
     /*
      *  打印两种类型的AST树, 用with_type来控制,
      *  一种是带"类型信息"的(语义分析后的AST),
      *  另一种是不带类型的(语法分析后的AST).
      */
-    let mut output = File::create(path.with_extension(extension)).unwrap();
+    let mut printer = TreePrinter {
+        output: BufWriter::new(File::create(path.with_extension(extension)).unwrap()),
+        level: 0,
+        with_type,
+        float_format,
+    };
 
-    // 对ast进行遍历,从root自顶向下深度优先搜索, 递归处理每一个节点.
-    for n in ast {
-        visit(&n, 0, &mut output, with_type);
-    }
+    // 对ast进行遍历,从root自顶向下深度优先搜索, 每个节点打印一行, 子节点多缩进一级.
+    walk_all(&mut printer, ast);
+    printer.output.flush().expect("write error");
+}
+
+/*
+    把AST以JSON输出到path.extension, 每个节点包含id, node_type(携带子节点), basic_type和span,
+    供评测脚本和可视化工具读取(可以用serde_json反序列化回Vec<Node>). 需要serde feature.
+*/
+#[cfg(feature = "serde")]
+pub fn print_tree_json(ast: &[Node], path: &Path, extension: &str) {
+    let output = File::create(path.with_extension(extension)).unwrap();
+    serde_json::to_writer_pretty(std::io::BufWriter::new(output), ast).expect("write error");
+}
+
+/* print_tree的实现: 用Visitor遍历AST, level是当前缩进的级别. */
+struct TreePrinter<'a> {
+    output: BufWriter<File>,
+    level: u32,
+    with_type: bool,
+    float_format: &'a FloatFormat,
+}
 
-    // visit函数的作用是：递归地遍历AST,并将每个节点的信息写入指定的output文件中.
-    fn visit(node: &Node, level: u32, output: &mut File, with_type: bool) {
-        /*
-        params:
-            node初值是AST的root,
-            level是当前缩进的级别,
-            output文件对象,
-            with_type用于区分是带类型信息的AST还是不带类型信息的AST.
-        */
+impl Visitor for TreePrinter<'_> {
+    fn visit_node(&mut self, node: &Node) {
+        let mut line = self.describe(node);
+        // 表达式节点在语义分析后带有类型.
+        let is_expr = node.kind().is_expr();
+        if self.with_type && is_expr {
+            line.push_str(&format!("[Semantic-check] with type: {}", node.basic_type));
+        }
+        self.print_len(line);
+        self.level += 1;
+        walk(self, node);
+        self.level -= 1;
+    }
+}
 
-        //递归(dfs)遍历AST树, 并将其写入文件中, 整体的算法流程就是递归下降Recursive Descending.
+impl TreePrinter<'_> {
+    /* 节点本身的描述(不含子节点) */
+    fn describe(&self, node: &Node) -> String {
         match &node.node_type {
-            //DeclStmt
-            NodeType::DeclStmt(nodes) => {
-                print_len(level, format!("DeclStmt"), output);
-                for n in nodes {
-                    visit(&n, level + 1, output, with_type);
-                }
-            }
-            //Func
-            NodeType::Func(ret, name, args, body) => {
-                print_len(level, format!("Func {},returns {:?}", name, ret), output);
-                //output.write(b"//args\n");
-                for arg in args {
-                    visit(&arg, level + 1, output, with_type);
-                }
-                //output.write(b"//body\n");
-                visit(&body, level + 1, output, with_type);
-            }
-            //Number
-            NodeType::Number(num) => {
-                let mut str = format!("Number {}", num);
-                if with_type {
-                    str.push_str(&format!(
-                        "[Semantic-check] with type: {:?}",
-                        node.basic_type
-                    ));
-                }
-                print_len(level, str, output);
+            NodeType::DeclStmt(_) => "DeclStmt".into(),
+            // 函数原型打印为Prototype, 没有函数体.
+            NodeType::Func(ret, name, _, body) => {
+                let kind = if matches!(body.node_type, NodeType::Nil) {
+                    "Prototype"
+                } else {
+                    "Func"
+                };
+                format!("{} {},returns {}", kind, name, ret)
             }
-            //FloatNumber
-            NodeType::FloatNumber(num) => {
-                let mut str = format!("FloatNumber {}", num);
-                if with_type {
-                    str.push_str(&format!(
-                        "[Semantic-check] with type: {:?}",
-                        node.basic_type
-                    ));
-                }
-                print_len(level, str, output);
-            }
-            //Nil
-            NodeType::Nil => print_len(level, "Nil".into(), output),
-            //Declare
+            NodeType::Number(num) => format!("Number {}", num),
+            NodeType::FloatNumber(num) => format!("FloatNumber {}", self.float_format.format(*num)),
+            NodeType::Nil => "Nil".into(),
+            NodeType::Str(text) => format!("Str {}", string_literal(text)),
             /* 一些SysY语言中变量声明的例子,
               1. int a = 10;
               2. int a[2][5] = { {1,2,3,4,5}, {6,7,8,9,10} };
               3. int f(int x,int y) {return x+y;}
             */
-            NodeType::Decl(basic_type, name, dims, init, scope) => {
-                print_len(
-                    level,
-                    format!("Declare of {}({:?}) in {:?} scope", name, basic_type, scope),
-                    output,
-                );
-                //output.write(b"//dims\n");
-                if let Some(dimslist) = dims {
-                    for dim in dimslist {
-                        visit(&dim, level + 1, output, with_type);
-                    }
-                }
-                //output.write(b"//init\n");
-                if let Some(initlist) = init {
-                    for init1 in initlist {
-                        visit(&init1, level + 1, output, with_type);
-                    }
-                }
+            NodeType::Decl(basic_type, name, _, _, scope) => {
+                format!("Declare of {}({}) in {:?} scope", name, basic_type, scope)
             }
-            //InitList
-            NodeType::InitList(list) => {
-                print_len(level, "Initlist".into(), output);
-                for i in list {
-                    visit(&i, level + 1, output, with_type);
-                }
-            }
-            //Access
-            NodeType::Access(name, indexes, _) => {
-                let mut str = format!("Access {}", name);
-                if with_type {
-                    str.push_str(&format!(
-                        "[Semantic-check] with type: {:?}",
-                        node.basic_type
-                    ));
-                }
-                print_len(level, str, output);
-                if let Some(indexeslist) = indexes {
-                    for index in indexeslist {
-                        visit(&index, level + 1, output, with_type);
-                    }
-                }
+            NodeType::InitList(_) => "Initlist".into(),
+            NodeType::Access(name, _, _) => format!("Access {}", name),
+            NodeType::BinOp(ttype, _, _) => format!("Binop {:?}", ttype),
+            NodeType::UnaryOp(ttype, _) => format!("UnaryOp {:?}", ttype),
+            NodeType::Call(name, _, _) => format!("Function call {}", name),
+            NodeType::Assign(name, _, _, _) => format!("Assign {}", name),
+            NodeType::ExprStmt(_) => "ExprStmt".into(),
+            NodeType::Block(_) => "Block".into(),
+            NodeType::If(..) => "If".into(),
+            NodeType::While(..) => "While".into(),
+            NodeType::Cond(..) => "Cond".into(),
+            NodeType::Comma(_) => "Comma".into(),
+            NodeType::Cast(ty, _) => format!("Cast to {}", ty),
+            NodeType::Switch(..) => "Switch".into(),
+            NodeType::Case(Some(_), _) => "Case".into(),
+            NodeType::Case(None, _) => "Default".into(),
+            NodeType::Break => "Break".into(),
+            NodeType::Continue => "Continue".into(),
+            NodeType::Empty => "Empty".into(),
+            NodeType::Return(_) => "Return".into(),
+        }
+    }
+
+    fn print_len(&mut self, msg: String) {
+        self.output.write_all(b"|").expect("write error");
+        for _ in 0..self.level {
+            self.output.write_all(b"--").expect("write error");
+        }
+        /* 使用format_args!()来构建格式化字符串，然后使用write_fmt()来写入格式化字符串,
+         * 最后使用expect()来处理可能出现的错误, 如果出错就输出"write error".
+         */
+        self.output
+            .write_fmt(format_args!("{}\n", msg))
+            .expect("write error");
+    }
+}
+
+/*
+    emit_source: 把AST重新生成为SysY源代码. 每条语句一行, 每层花括号缩进4个空格,
+    只在运算符优先级需要时加括号(优先级与parser相同), 因此再做一次语法分析得到的AST与原来的结构相同.
+    用于源到源的变换, 缩小出错的测试用例, 以及语法分析器的往返测试.
+    语义分析后的AST也可以输出, 那时常量已被折叠, 数组的维度是折叠后的数字.
+*/
+pub fn emit_source(ast: &[Node]) -> String {
+    let mut emitter = SourceEmitter {
+        out: String::new(),
+        level: 0,
+        joined: false,
+    };
+    // 函数定义前后各空一行.
+    let is_definition = |node: &Node| matches!(&node.node_type, NodeType::Func(_, _, _, body) if !matches!(body.node_type, NodeType::Nil));
+    for (i, node) in ast.iter().enumerate() {
+        if i > 0 && (is_definition(node) || is_definition(&ast[i - 1])) {
+            emitter.out.push('\n');
+        }
+        emitter.stmt(node);
+    }
+    emitter.out
+}
+
+/* 表达式的优先级: 条件表达式最低, 二元运算符见parser::binary_precedence, 其上是一元运算和基本表达式. */
+const COND_PREC: u8 = 0;
+const UNARY_PREC: u8 = 7;
+const PRIMARY_PREC: u8 = 8;
+
+/* emit_source的实现: level是当前缩进的级别, joined表示下一行接在上一行的'}'之后(如"} else {"). */
+struct SourceEmitter {
+    out: String,
+    level: usize,
+    joined: bool,
+}
+
+impl SourceEmitter {
+    fn line(&mut self, text: &str) {
+        if self.joined {
+            self.joined = false;
+            self.out.pop();
+            self.out.push(' ');
+        } else {
+            self.out.push_str(&"    ".repeat(self.level));
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn stmt(&mut self, node: &Node) {
+        match &node.node_type {
+            NodeType::DeclStmt(decls) => {
+                let text = self.decl_stmt(decls);
+                self.line(&text);
             }
-            //BinOp
-            NodeType::BinOp(ttype, lhs, rhs) => {
-                let mut str = format!("Binop {:?}", ttype);
-                if with_type {
-                    str.push_str(&format!(
-                        "[Semantic-check] with type: {:?}",
-                        node.basic_type
-                    ));
-                }
-                print_len(level, str, output);
-                //output.write(b"//lhs\n");
-                visit(&lhs, level + 1, output, with_type);
-                //output.write(b"//rhs\n");
-                visit(&rhs, level + 1, output, with_type);
+            NodeType::Decl(..) => {
+                let text = self.decl_stmt(std::slice::from_ref(node));
+                self.line(&text);
             }
-            //Call
-            NodeType::Call(name, args, _) => {
-                let mut str = format!("Function call {}", name);
-                if with_type {
-                    str.push_str(&format!(
-                        "[Semantic-check] with type: {:?}",
-                        node.basic_type
-                    ));
-                }
-                print_len(level, str, output);
-                for arg in args {
-                    visit(&arg, level + 1, output, with_type);
+            NodeType::Func(ret, name, params, body) => {
+                let params: Vec<String> = params.iter().map(|p| self.declarator(p)).collect();
+                let head = format!("{} {}({})", ret, name, params.join(", "));
+                match &body.node_type {
+                    NodeType::Block(stmts) => self.braced(&head, stmts),
+                    _ => self.line(&format!("{};", head)),
                 }
             }
-            //Assign
-            NodeType::Assign(name, indexes, rhs, _) => {
-                print_len(level, format!("Assign {}", name), output);
-                //output.write(b"//indexes\n");
-                if let Some(indexlist) = indexes {
-                    for index in indexlist {
-                        visit(&index, level + 1, output, with_type);
-                    }
-                }
-                //output.write(b"//rhs\n");
-                visit(&rhs, level + 1, output, with_type);
+            NodeType::Block(stmts) => self.braced("", stmts),
+            NodeType::Assign(..) => {
+                let text = format!("{};", self.assign(node));
+                self.line(&text);
             }
-            //ExprStmt
             NodeType::ExprStmt(expr) => {
-                print_len(level, "ExprStmt".into(), output);
-                visit(&expr, level + 1, output, with_type);
+                let text = format!("{};", self.expr(expr, COND_PREC));
+                self.line(&text);
+            }
+            NodeType::Return(None) => self.line("return;"),
+            NodeType::Return(Some(expr)) => {
+                let text = format!("return {};", self.expr(expr, COND_PREC));
+                self.line(&text);
+            }
+            NodeType::If(..) => self.if_stmt("if", node),
+            NodeType::While(cond, body) => {
+                let head = format!("while ({})", self.expr(cond, L_OR_PREC));
+                self.body(&head, body, false);
+            }
+            NodeType::Switch(scrutinee, arms) => {
+                let head = format!("switch ({}) {{", self.expr(scrutinee, COND_PREC));
+                self.line(&head);
+                self.level += 1;
+                for arm in arms {
+                    self.stmt(arm);
+                }
+                self.level -= 1;
+                self.line("}");
             }
-            //Block
-            NodeType::Block(stmts) => {
-                print_len(level, "Block".into(), output);
+            NodeType::Case(label, stmts) => {
+                let head = match label {
+                    Some(label) => format!("case {}:", self.expr(label, COND_PREC)),
+                    None => "default:".to_string(),
+                };
+                self.line(&head);
+                self.level += 1;
                 for stmt in stmts {
-                    visit(&stmt, level + 1, output, with_type);
+                    self.stmt(stmt);
                 }
+                self.level -= 1;
+            }
+            NodeType::Continue => self.line("continue;"),
+            NodeType::Break => self.line("break;"),
+            NodeType::Empty => self.line(";"),
+            NodeType::Nil => {}
+            // 表达式出现在语句的位置(语义分析后的AST可能如此), 作为表达式语句输出.
+            NodeType::InitList(_)
+            | NodeType::Access(..)
+            | NodeType::BinOp(..)
+            | NodeType::UnaryOp(..)
+            | NodeType::Cond(..)
+            | NodeType::Comma(_)
+            | NodeType::Cast(..)
+            | NodeType::Call(..)
+            | NodeType::Number(_)
+            | NodeType::FloatNumber(_)
+            | NodeType::Str(_) => {
+                let text = format!("{};", self.expr(node, COND_PREC));
+                self.line(&text);
+            }
+        }
+    }
+
+    /* head { stmts }, head为空时是单独的语句块. */
+    fn braced(&mut self, head: &str, stmts: &[Node]) {
+        if head.is_empty() {
+            self.line("{");
+        } else {
+            self.line(&format!("{} {{", head));
+        }
+        self.level += 1;
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+        self.level -= 1;
+        self.line("}");
+    }
+
+    /* if/while/else的子语句: 语句块接在head之后, 其他语句另起一行并多缩进一级. brace为true时总是加花括号. */
+    fn body(&mut self, head: &str, stmt: &Node, brace: bool) {
+        match &stmt.node_type {
+            NodeType::Block(stmts) => self.braced(head, stmts),
+            _ if brace => self.braced(head, std::slice::from_ref(stmt)),
+            _ => {
+                self.line(head);
+                self.level += 1;
+                self.stmt(stmt);
+                self.level -= 1;
+            }
+        }
+    }
+
+    /* if语句, else if连写在一起. keyword是"if"或"else if". */
+    fn if_stmt(&mut self, keyword: &str, node: &Node) {
+        let NodeType::If(cond, on_true, on_false) = &node.node_type else {
+            unreachable!()
+        };
+        let head = format!("{} ({})", keyword, self.expr(cond, L_OR_PREC));
+        // then分支以没有else的if结尾时加上花括号, 否则后面的else会被它吃掉.
+        self.body(&head, on_true, on_false.is_some() && dangling_if(on_true));
+        let Some(on_false) = on_false else {
+            return;
+        };
+        self.joined = self.out.ends_with("}\n");
+        match &on_false.node_type {
+            NodeType::If(..) => self.if_stmt("else if", on_false),
+            _ => self.body("else", on_false, false),
+        }
+    }
+
+    /* 一条声明语句: 类型只写一次, 如int a = 1, b[2] = {1, 2}; */
+    fn decl_stmt(&self, decls: &[Node]) -> String {
+        let Some(NodeType::Decl(ty, ..)) = decls.first().map(|d| &d.node_type) else {
+            return ";".to_string();
+        };
+        let decls: Vec<String> = decls.iter().map(|d| self.declarator(d)).collect();
+        format!("{} {};", type_keyword(ty), decls.join(", "))
+    }
+
+    /* 声明中类型之后的部分(形参则包含类型): 名字, 维度和初始值. */
+    fn declarator(&self, node: &Node) -> String {
+        let NodeType::Decl(ty, name, dims, inits, scope) = &node.node_type else {
+            return self.expr(node, COND_PREC);
+        };
+        let mut text = match scope {
+            Scope::Params => format!("{} {}", type_keyword(ty), name),
+            _ => name.clone(),
+        };
+        text.push_str(&self.indexes(dims.as_deref()));
+        match (inits, dims) {
+            (Some(inits), None) if inits.len() == 1 => {
+                text.push_str(&format!(" = {}", self.expr(&inits[0], COND_PREC)));
             }
-            //If
-            NodeType::If(cond, on_true, on_false) => {
-                print_len(level, "If".into(), output);
-                //output.write(b"//Cond\n");
-                visit(&cond, level + 1, output, with_type);
-                //output.write(b"//True\n");
-                visit(&on_true, level + 1, output, with_type);
-                if let Some(f) = on_false {
-                    //output.write(b"//False\n");
-                    visit(&f, level + 1, output, with_type);
+            (Some(inits), _) => text.push_str(&format!(" = {}", self.init_list(inits))),
+            (None, _) => {}
+        }
+        text
+    }
+
+    fn init_list(&self, inits: &[Node]) -> String {
+        let items: Vec<String> = inits
+            .iter()
+            .map(|init| match &init.node_type {
+                NodeType::InitList(nested) => self.init_list(nested),
+                _ => self.expr(init, COND_PREC),
+            })
+            .collect();
+        format!("{{{}}}", items.join(", "))
+    }
+
+    /* [i][j]...; 形参省略的第一维(Nil)输出为[]. */
+    fn indexes(&self, indexes: Option<&[Node]>) -> String {
+        indexes
+            .unwrap_or_default()
+            .iter()
+            .map(|i| match i.node_type {
+                NodeType::Nil => "[]".to_string(),
+                _ => format!("[{}]", self.expr(i, COND_PREC)),
+            })
+            .collect()
+    }
+
+    fn assign(&self, node: &Node) -> String {
+        let NodeType::Assign(name, indexes, expr, _) = &node.node_type else {
+            unreachable!()
+        };
+        format!(
+            "{}{} = {}",
+            name,
+            self.indexes(indexes.as_deref()),
+            self.expr(expr, COND_PREC)
+        )
+    }
+
+    /* 表达式, 优先级低于min_prec时加括号. */
+    fn expr(&self, node: &Node, min_prec: u8) -> String {
+        let (text, prec) = match &node.node_type {
+            NodeType::Number(num) => {
+                let prec = if *num < 0 { UNARY_PREC } else { PRIMARY_PREC };
+                (num.to_string(), prec)
+            }
+            NodeType::FloatNumber(num) => {
+                // 不用指数形式, 并且总带小数点, 保证词法分析仍把它识别为浮点数.
+                let mut text = num.to_string();
+                if num.is_finite() && !text.contains('.') {
+                    text.push_str(".0");
                 }
+                let prec = if num.is_sign_negative() {
+                    UNARY_PREC
+                } else {
+                    PRIMARY_PREC
+                };
+                (text, prec)
             }
-            //While
-            NodeType::While(cond, body) => {
-                print_len(level, "While".into(), output);
-                //output.write(b"//Cond\n");
-                visit(&cond, level + 1, output, with_type);
-                //output.write(b"//Body\n");
-                visit(&body, level + 1, output, with_type);
-            }
-            //Break
-            NodeType::Break => {
-                print_len(level, "Break".into(), output);
-            }
-            //Continue
-            NodeType::Continue => {
-                print_len(level, "Continue".into(), output);
-            }
-            //Return
-            NodeType::Return(ret) => {
-                print_len(level, "Return".into(), output);
-                if let Some(r) = ret {
-                    // output.write(b"//Return expr\n");
-                    visit(&r, level + 1, output, with_type);
+            NodeType::Access(name, indexes, _) => (
+                format!("{}{}", name, self.indexes(indexes.as_deref())),
+                PRIMARY_PREC,
+            ),
+            NodeType::Call(name, args, _) => {
+                let args: Vec<String> = args.iter().map(|a| self.expr(a, COND_PREC)).collect();
+                (format!("{}({})", name, args.join(", ")), PRIMARY_PREC)
+            }
+            NodeType::UnaryOp(op, operand) => {
+                let mut operand = self.expr(operand, UNARY_PREC);
+                // 连用的一元运算符之间加括号, 避免--x被当成自减.
+                if operand.starts_with(['+', '-', '!']) {
+                    operand = format!("({})", operand);
                 }
+                (format!("{}{}", operator_text(op), operand), UNARY_PREC)
+            }
+            NodeType::BinOp(op, lhs, rhs) => {
+                let prec = binary_precedence(op).unwrap_or(PRIMARY_PREC);
+                let text = format!(
+                    "{} {} {}",
+                    self.expr(lhs, prec),
+                    operator_text(op),
+                    self.expr(rhs, prec + 1)
+                );
+                (text, prec)
+            }
+            NodeType::Cond(cond, on_true, on_false) => {
+                let text = format!(
+                    "{} ? {} : {}",
+                    self.expr(cond, L_OR_PREC),
+                    self.expr(on_true, COND_PREC),
+                    self.expr(on_false, COND_PREC)
+                );
+                (text, COND_PREC)
             }
+            // 逗号表达式只能出现在括号中.
+            NodeType::Comma(operands) => {
+                let operands: Vec<String> = operands
+                    .iter()
+                    .map(|o| match o.node_type {
+                        NodeType::Assign(..) => self.assign(o),
+                        _ => self.expr(o, COND_PREC),
+                    })
+                    .collect();
+                return format!("({})", operands.join(", "));
+            }
+            NodeType::Assign(..) => return format!("({})", self.assign(node)),
+            // SysY没有类型转换的写法, 输出被转换的表达式, 再次分析时会插入同样的转换.
+            NodeType::Cast(_, expr) => return self.expr(expr, min_prec),
+            NodeType::InitList(inits) => (self.init_list(inits), PRIMARY_PREC),
+            NodeType::Str(text) => (string_literal(text), PRIMARY_PREC),
+            // 声明和语句不会出现在表达式的位置.
+            NodeType::Decl(..)
+            | NodeType::DeclStmt(_)
+            | NodeType::ExprStmt(_)
+            | NodeType::Func(..)
+            | NodeType::Block(_)
+            | NodeType::Return(_)
+            | NodeType::If(..)
+            | NodeType::While(..)
+            | NodeType::Switch(..)
+            | NodeType::Case(..)
+            | NodeType::Continue
+            | NodeType::Break
+            | NodeType::Empty
+            | NodeType::Nil => (String::new(), PRIMARY_PREC),
+        };
+        if prec < min_prec {
+            format!("({})", text)
+        } else {
+            text
         }
     }
+}
+
+/* 声明中的类型关键字, 数组类型(语义分析后)取元素的类型. */
+fn type_keyword(ty: &BasicType) -> &'static str {
+    match ty {
+        BasicType::Int | BasicType::IntArray(_) => "int",
+        BasicType::Float | BasicType::FloatArray(_) => "float",
+        BasicType::Const | BasicType::ConstArray(_) => "const int",
+        BasicType::ConstFloat | BasicType::ConstFloatArray(_) => "const float",
+        BasicType::Void => "void",
+        BasicType::Nil | BasicType::Func(_) => "int",
+    }
+}
 
-    fn print_len(level: u32, msg: String, output: &mut File) {
-        output.write(b"|").expect("write error");
-        for _ in 0..level {
-            output.write(b"--").expect("write error");
+/* 字符串字面量的源代码形式: 加上引号, 把不能直接出现的字符写成转义序列. */
+fn string_literal(text: &str) -> String {
+    let mut out = String::from('"');
+    for c in text.chars() {
+        match c {
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '\0' => out.push_str("\\0"),
+            '"' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            _ => out.push(c),
         }
-        /* 使用format_args!()来构建格式化字符串，然后使用write_fmt()来写入格式化字符串,
-         * 最后使用expect()来处理可能出现的错误, 如果出错就输出"write error".
-         */
-        output
-            .write_fmt(format_args!("{}\n", msg))
-            .expect("write error");
+    }
+    out.push('"');
+    out
+}
+
+pub(crate) fn operator_text(op: &TokenType) -> &'static str {
+    use TokenType::*;
+    match op {
+        Plus => "+",
+        Minus => "-",
+        Multi => "*",
+        Divide => "/",
+        Mods => "%",
+        Equal => "==",
+        NotEqual => "!=",
+        Lesserthan => "<",
+        Greaterthan => ">",
+        LessEqual => "<=",
+        GreatEqual => ">=",
+        And => "&&",
+        Or => "||",
+        Not => "!",
+        BitAnd => "&",
+        BitOr => "|",
+        BitXor => "^",
+        BitNot => "~",
+        ShiftLeft => "<<",
+        ShiftRight => ">>",
+        _ => "?",
+    }
+}
+
+/* 语句是否以没有else的if结尾, 这时它后面的else会与这个if匹配. */
+fn dangling_if(node: &Node) -> bool {
+    match &node.node_type {
+        NodeType::If(_, _, None) => true,
+        NodeType::If(_, _, Some(on_false)) => dangling_if(on_false),
+        NodeType::While(_, body) => dangling_if(body),
+        _ => false,
     }
 }
@@ -0,0 +1,379 @@
+use crate::{
+    cfg::{Cfg, Dominators},
+    diagnostics::Diagnostic,
+    ir::{BlockId, Function, InstKind, Module, Operand},
+    parser::{Node, NodeId},
+    semantics::{is_variadic, runtime_declarations},
+    BasicType, NodeType,
+};
+use std::collections::{HashMap, HashSet};
+
+/*
+    --verify: 每个变换(语义分析中的常量折叠, 全程序裁剪...)之后检查语义AST的不变式,
+    把"悄悄生成错误结果"的bug变成立即报告, 并带有位置的内部错误. 只对通过了语义检查的程序有意义,
+    有语义错误时语义分析为了继续检查而生成的占位节点可能违反这些不变式.
+    检查的不变式:
+      1. 表达式节点都有类型, Number是const int, FloatNumber是float, 运算结果不会是const(否则应当已被折叠).
+      2. Access/Assign引用的声明仍然存在于AST中.
+      3. Call引用的函数在AST中(或是运行时库函数), 与调用同名, 实参个数与形参一致(putf除外).
+      4. 每个节点的span.start <= span.end.
+*/
+
+struct Verifier<'a> {
+    pass: &'a str,
+    decls: HashSet<NodeId>,
+    funcs: HashMap<NodeId, &'a Node>,
+    errors: Vec<Diagnostic>,
+}
+
+/* 检查pass之后的语义AST, 返回发现的内部错误(为空表示通过). */
+pub fn verify(pass: &str, annotated_ast: &[Node]) -> Vec<Diagnostic> {
+    let mut verifier = Verifier {
+        pass,
+        decls: HashSet::new(),
+        funcs: HashMap::new(),
+        errors: vec![],
+    };
+    for node in annotated_ast {
+        verifier.declare(node);
+    }
+    for node in annotated_ast {
+        verifier.check(node);
+    }
+    verifier.errors
+}
+
+impl<'a> Verifier<'a> {
+    fn fail(&mut self, node: &Node, msg: String) {
+        self.errors.push(
+            Diagnostic::error(format!("internal error after `{}`: {}", self.pass, msg))
+                .with_label(node.span, format!("node #{}", node.id.0))
+                .with_note("this is a compiler bug, not a problem in the source program"),
+        );
+    }
+
+    /* 收集AST中所有的声明(包括形参和局部变量)以及函数. */
+    fn declare(&mut self, node: &'a Node) {
+        match &node.node_type {
+            NodeType::Decl(..) => {
+                self.decls.insert(node.id);
+            }
+            NodeType::Func(_, _, params, body) => {
+                self.funcs.insert(node.id, node);
+                for param in params {
+                    self.declare(param);
+                }
+                self.declare(body);
+            }
+            NodeType::DeclStmt(nodes) | NodeType::Block(nodes) => {
+                for n in nodes {
+                    self.declare(n);
+                }
+            }
+            NodeType::If(_, on_true, on_false) => {
+                self.declare(on_true);
+                if let Some(f) = on_false {
+                    self.declare(f);
+                }
+            }
+            NodeType::While(_, body) => self.declare(body),
+            NodeType::Switch(_, arms) => {
+                for arm in arms {
+                    self.declare(arm);
+                }
+            }
+            NodeType::Case(_, stmts) => {
+                for stmt in stmts {
+                    self.declare(stmt);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn expect_typed(&mut self, node: &Node) {
+        if node.basic_type == BasicType::Nil {
+            self.fail(node, "expression has no type".to_string());
+        }
+    }
+
+    fn expect_folded(&mut self, node: &Node) {
+        if node.basic_type == BasicType::Const {
+            self.fail(node, "constant expression was not folded".to_string());
+        }
+    }
+
+    fn check_decl_ref(&mut self, node: &Node, name: &str, decl: NodeId) {
+        if !self.decls.contains(&decl) {
+            self.fail(
+                node,
+                format!(
+                    "`{}` refers to a declaration that is no longer in the tree",
+                    name
+                ),
+            );
+        }
+    }
+
+    fn check(&mut self, node: &Node) {
+        use NodeType::*;
+        if node.span.start > node.span.end {
+            self.fail(
+                node,
+                format!("span {}..{} is reversed", node.span.start, node.span.end),
+            );
+        }
+        match &node.node_type {
+            Number(_) => {
+                if node.basic_type != BasicType::Const {
+                    self.fail(node, format!("Number has type `{}`", node.basic_type));
+                }
+            }
+            // 字符串没有SysY类型, 只会是putf的格式参数.
+            Str(_) => {}
+            FloatNumber(_) => {
+                if node.basic_type != BasicType::Float {
+                    self.fail(node, format!("FloatNumber has type `{}`", node.basic_type));
+                }
+            }
+            Decl(_, _, dims, inits, _) => {
+                for n in dims.iter().flatten().chain(inits.iter().flatten()) {
+                    self.check(n);
+                }
+            }
+            DeclStmt(nodes) | InitList(nodes) | Block(nodes) => {
+                for n in nodes {
+                    self.check(n);
+                }
+            }
+            Access(name, indexes, decl) => {
+                self.expect_typed(node);
+                self.check_decl_ref(node, name, *decl);
+                for index in indexes.iter().flatten() {
+                    self.check(index);
+                }
+            }
+            Assign(name, indexes, expr, decl) => {
+                self.check_decl_ref(node, name, *decl);
+                for index in indexes.iter().flatten() {
+                    self.check(index);
+                }
+                self.check(expr);
+            }
+            BinOp(_, lhs, rhs) => {
+                self.expect_typed(node);
+                self.expect_folded(node);
+                self.check(lhs);
+                self.check(rhs);
+            }
+            UnaryOp(_, operand) => {
+                self.expect_typed(node);
+                self.expect_folded(node);
+                self.check(operand);
+            }
+            // 隐式转换只在int与float之间进行, 被转换的表达式的类型与目标类型不同.
+            Cast(ty, expr) => {
+                let from = match expr.basic_type {
+                    BasicType::Const => BasicType::Int,
+                    ref other => other.clone(),
+                };
+                if !matches!(ty, BasicType::Int | BasicType::Float)
+                    || !matches!(from, BasicType::Int | BasicType::Float)
+                    || from == *ty
+                    || node.basic_type != *ty
+                {
+                    self.fail(
+                        node,
+                        format!("invalid cast from `{}` to `{}`", expr.basic_type, ty),
+                    );
+                }
+                self.check(expr);
+            }
+            Cond(cond, on_true, on_false) => {
+                self.expect_typed(node);
+                self.expect_folded(node);
+                self.check(cond);
+                self.check(on_true);
+                self.check(on_false);
+            }
+            Comma(operands) => {
+                self.expect_typed(node);
+                self.expect_folded(node);
+                for operand in operands {
+                    self.check(operand);
+                }
+            }
+            Call(name, args, callee) => {
+                // 运行时库函数没有源代码位置, 也不在AST中.
+                let callee = self.funcs.get(callee).copied().or_else(|| {
+                    runtime_declarations()
+                        .iter()
+                        .find(|decl| decl.id == *callee)
+                });
+                match callee.map(|callee| (callee, &callee.node_type)) {
+                    Some((callee, Func(_, callee_name, params, _))) => {
+                        if callee_name != name {
+                            self.fail(
+                                node,
+                                format!("call to `{}` resolved to `{}`", name, callee_name),
+                            );
+                        } else if params.len() != args.len() && !is_variadic(callee) {
+                            self.fail(
+                                node,
+                                format!(
+                                    "call to `{}` has {} arguments but the callee has {} parameters",
+                                    name,
+                                    args.len(),
+                                    params.len()
+                                ),
+                            );
+                        }
+                    }
+                    _ => self.fail(node, format!("callee `{}` is no longer in the tree", name)),
+                }
+                for arg in args {
+                    self.check(arg);
+                }
+            }
+            ExprStmt(expr) => self.check(expr),
+            Func(_, _, params, body) => {
+                for param in params {
+                    self.check(param);
+                }
+                self.check(body);
+            }
+            Return(expr) => {
+                if let Some(expr) = expr {
+                    self.check(expr);
+                }
+            }
+            If(cond, on_true, on_false) => {
+                self.check(cond);
+                self.check(on_true);
+                if let Some(f) = on_false {
+                    self.check(f);
+                }
+            }
+            While(cond, body) => {
+                self.check(cond);
+                self.check(body);
+            }
+            Switch(scrutinee, arms) => {
+                self.check(scrutinee);
+                for arm in arms {
+                    self.check(arm);
+                }
+            }
+            Case(label, stmts) => {
+                if let Some(label) = label {
+                    self.check(label);
+                }
+                for stmt in stmts {
+                    self.check(stmt);
+                }
+            }
+            Continue | Break | Empty | Nil => {}
+        }
+    }
+}
+
+/*
+    --verify对三地址码的检查, 在降低之后和PassManager的每个pass之后进行. 除了Function::validate的结构检查
+    (每个块以终结指令结尾, 跳转目标存在, 每个值只定义一次), 还检查:
+      1. 可达块中的phi对每个可达的前驱恰好有一个输入, 输入的块都是前驱.
+      2. 可达块中值的定义支配它的使用: 同一块中定义在前; phi的输入在对应前驱的末尾可用.
+    不可达的块不参与支配关系, 不检查其中的使用.
+*/
+pub fn verify_module(pass: &str, module: &Module) -> Vec<Diagnostic> {
+    let mut errors = vec![];
+    for function in module.functions.iter().filter(|f| !f.blocks.is_empty()) {
+        let problems = match function.validate() {
+            Ok(()) => check_ssa(function),
+            Err(e) => vec![e],
+        };
+        errors.extend(problems.into_iter().map(|msg| {
+            Diagnostic::error(format!("internal error after `{}`: {}", pass, msg))
+                .with_note("this is a compiler bug, not a problem in the source program")
+        }));
+    }
+    errors
+}
+
+fn check_ssa(function: &Function) -> Vec<String> {
+    let cfg = Cfg::build(function);
+    let dominators = Dominators::compute(&cfg);
+    let mut errors = vec![];
+    // 每个值定义在哪个块的第几条指令; 形参在入口块之前.
+    let mut defs = vec![None; function.value_types.len()];
+    for d in defs.iter_mut().take(function.params.len()) {
+        *d = Some((BlockId(0), None));
+    }
+    for (b, block) in function.blocks.iter().enumerate() {
+        for (i, inst) in block.insts.iter().enumerate() {
+            if let Some(dest) = inst.dest {
+                defs[dest.0 as usize] = Some((BlockId(b as u32), Some(i)));
+            }
+        }
+    }
+    // use_block的第use_index条指令(None表示块的末尾)能否使用v.
+    let available = |v: usize, use_block: BlockId, use_index: Option<usize>| match defs[v] {
+        Some((def_block, def_index)) if def_block == use_block => match (def_index, use_index) {
+            (None, _) => true,
+            (Some(_), None) => true,
+            (Some(d), Some(u)) => d < u,
+        },
+        Some((def_block, _)) => dominators.dominates(def_block, use_block),
+        None => false,
+    };
+    for (b, block) in function.blocks.iter().enumerate() {
+        let id = BlockId(b as u32);
+        if !dominators.is_reachable(id) {
+            continue;
+        }
+        let mut preds: Vec<BlockId> = cfg.predecessors(id).collect();
+        preds.dedup();
+        for (i, inst) in block.insts.iter().enumerate() {
+            let InstKind::Phi(incoming) = &inst.kind else {
+                for operand in inst.kind.operands() {
+                    if let Operand::Value(v) = operand {
+                        if !available(v.0 as usize, id, Some(i)) {
+                            errors.push(format!(
+                                "{}: %{} is used in {} where its definition does not dominate",
+                                function.name, v.0, id
+                            ));
+                        }
+                    }
+                }
+                continue;
+            };
+            for (from, _) in incoming {
+                if !preds.contains(from) {
+                    errors.push(format!(
+                        "{}: phi in {} has an input from {}, which is not a predecessor",
+                        function.name, id, from
+                    ));
+                }
+            }
+            for pred in preds.iter().filter(|p| dominators.is_reachable(**p)) {
+                let inputs = incoming.iter().filter(|(from, _)| from == pred).count();
+                if inputs != 1 {
+                    errors.push(format!(
+                        "{}: phi in {} has {} inputs from predecessor {}",
+                        function.name, id, inputs, pred
+                    ));
+                }
+            }
+            for (from, value) in incoming {
+                if let Operand::Value(v) = value {
+                    if dominators.is_reachable(*from) && !available(v.0 as usize, *from, None) {
+                        errors.push(format!(
+                            "{}: %{} flows into the phi in {} from {}, where it is not available",
+                            function.name, v.0, id, from
+                        ));
+                    }
+                }
+            }
+        }
+    }
+    errors
+}
@@ -0,0 +1,185 @@
+use crate::{
+    parser::{Node, NodeId},
+    NodeKind, NodeType,
+};
+use std::collections::HashMap;
+
+/*
+    AST的只读遍历. 实现Visitor的分析只需重写关心的visit_*方法, 其余节点由默认实现(walk)继续访问子节点,
+    不必各自再写一遍覆盖所有NodeType的match. 子节点的定义(及顺序)集中在children中:
+      - Access/Assign/Call中指向声明或被调函数的字段不是子节点(它们是声明节点的编号);
+      - 函数原型没有函数体.
+    需要在每个节点前后做同样的事情(比如打印树时的缩进)时重写visit_node.
+*/
+pub trait Visitor {
+    /* 访问任意节点, 默认按种类分派到下面的visit_*方法. */
+    fn visit_node(&mut self, node: &Node) {
+        dispatch(self, node)
+    }
+
+    fn visit_decl(&mut self, node: &Node) {
+        walk(self, node)
+    }
+    fn visit_decl_stmt(&mut self, node: &Node) {
+        walk(self, node)
+    }
+    fn visit_init_list(&mut self, node: &Node) {
+        walk(self, node)
+    }
+    fn visit_assign(&mut self, node: &Node) {
+        walk(self, node)
+    }
+    fn visit_expr_stmt(&mut self, node: &Node) {
+        walk(self, node)
+    }
+    fn visit_access(&mut self, node: &Node) {
+        walk(self, node)
+    }
+    fn visit_binop(&mut self, node: &Node) {
+        walk(self, node)
+    }
+    fn visit_unary(&mut self, node: &Node) {
+        walk(self, node)
+    }
+    fn visit_cond(&mut self, node: &Node) {
+        walk(self, node)
+    }
+    fn visit_comma(&mut self, node: &Node) {
+        walk(self, node)
+    }
+    fn visit_cast(&mut self, node: &Node) {
+        walk(self, node)
+    }
+    fn visit_func(&mut self, node: &Node) {
+        walk(self, node)
+    }
+    fn visit_block(&mut self, node: &Node) {
+        walk(self, node)
+    }
+    fn visit_return(&mut self, node: &Node) {
+        walk(self, node)
+    }
+    fn visit_call(&mut self, node: &Node) {
+        walk(self, node)
+    }
+    fn visit_if(&mut self, node: &Node) {
+        walk(self, node)
+    }
+    fn visit_while(&mut self, node: &Node) {
+        walk(self, node)
+    }
+    fn visit_switch(&mut self, node: &Node) {
+        walk(self, node)
+    }
+    fn visit_case(&mut self, node: &Node) {
+        walk(self, node)
+    }
+    /* 没有子节点的语句: Continue, Break, Empty */
+    fn visit_jump(&mut self, _node: &Node) {}
+    /* 常量和占位节点: Number, FloatNumber, Str, Nil */
+    fn visit_leaf(&mut self, _node: &Node) {}
+}
+
+/* 按节点的种类调用对应的visit_*方法. */
+pub fn dispatch<V: Visitor + ?Sized>(visitor: &mut V, node: &Node) {
+    match node.kind() {
+        NodeKind::Decl => visitor.visit_decl(node),
+        NodeKind::DeclStmt => visitor.visit_decl_stmt(node),
+        NodeKind::InitList => visitor.visit_init_list(node),
+        NodeKind::Assign => visitor.visit_assign(node),
+        NodeKind::ExprStmt => visitor.visit_expr_stmt(node),
+        NodeKind::Access => visitor.visit_access(node),
+        NodeKind::BinOp => visitor.visit_binop(node),
+        NodeKind::UnaryOp => visitor.visit_unary(node),
+        NodeKind::Cond => visitor.visit_cond(node),
+        NodeKind::Comma => visitor.visit_comma(node),
+        NodeKind::Cast => visitor.visit_cast(node),
+        NodeKind::Func => visitor.visit_func(node),
+        NodeKind::Block => visitor.visit_block(node),
+        NodeKind::Return => visitor.visit_return(node),
+        NodeKind::Call => visitor.visit_call(node),
+        NodeKind::If => visitor.visit_if(node),
+        NodeKind::While => visitor.visit_while(node),
+        NodeKind::Switch => visitor.visit_switch(node),
+        NodeKind::Case => visitor.visit_case(node),
+        NodeKind::Continue | NodeKind::Break | NodeKind::Empty => visitor.visit_jump(node),
+        NodeKind::Nil | NodeKind::Number | NodeKind::FloatNumber | NodeKind::Str => {
+            visitor.visit_leaf(node)
+        }
+    }
+}
+
+/* 依次访问node的每个子节点. */
+pub fn walk<V: Visitor + ?Sized>(visitor: &mut V, node: &Node) {
+    for child in children(node) {
+        visitor.visit_node(child);
+    }
+}
+
+/* 依次访问一组顶层节点(semantic/parse的结果). */
+pub fn walk_all<V: Visitor + ?Sized>(visitor: &mut V, ast: &[Node]) {
+    for node in ast {
+        visitor.visit_node(node);
+    }
+}
+
+/* node的子节点, 按源代码中出现的顺序. */
+pub fn children(node: &Node) -> Vec<&Node> {
+    use NodeType::*;
+    let mut result = vec![];
+    match &node.node_type {
+        Decl(_, _, dims, inits, _) => {
+            result.extend(dims.iter().flatten());
+            result.extend(inits.iter().flatten());
+        }
+        DeclStmt(nodes) | InitList(nodes) | Block(nodes) | Comma(nodes) => result.extend(nodes),
+        Access(_, indexes, _) => result.extend(indexes.iter().flatten()),
+        Assign(_, indexes, expr, _) => {
+            result.extend(indexes.iter().flatten());
+            result.push(expr.as_ref());
+        }
+        ExprStmt(expr) | UnaryOp(_, expr) | Cast(_, expr) => result.push(expr.as_ref()),
+        BinOp(_, lhs, rhs) => result.extend([lhs.as_ref(), rhs.as_ref()]),
+        Cond(cond, on_true, on_false) => {
+            result.extend([cond.as_ref(), on_true.as_ref(), on_false.as_ref()])
+        }
+        Func(_, _, params, body) => {
+            result.extend(params);
+            if !matches!(body.node_type, Nil) {
+                result.push(body.as_ref());
+            }
+        }
+        Return(expr) => result.extend(expr.as_deref()),
+        Call(_, args, _) => result.extend(args),
+        If(cond, on_true, on_false) => {
+            result.extend([cond.as_ref(), on_true.as_ref()]);
+            result.extend(on_false.as_deref());
+        }
+        While(cond, body) => result.extend([cond.as_ref(), body.as_ref()]),
+        Switch(scrutinee, arms) => {
+            result.push(scrutinee.as_ref());
+            result.extend(arms);
+        }
+        Case(label, stmts) => {
+            result.extend(label.as_deref());
+            result.extend(stmts);
+        }
+        Continue | Break | Empty | Nil | Number(_) | FloatNumber(_) | Str(_) => {}
+    }
+    result
+}
+
+/* 按编号索引AST中的每个节点, 供用NodeId引用节点的分析查找. */
+pub fn node_map(ast: &[Node]) -> HashMap<NodeId, &Node> {
+    fn insert<'a>(node: &'a Node, map: &mut HashMap<NodeId, &'a Node>) {
+        map.insert(node.id, node);
+        for child in children(node) {
+            insert(child, map);
+        }
+    }
+    let mut map = HashMap::new();
+    for node in ast {
+        insert(node, &mut map);
+    }
+    map
+}
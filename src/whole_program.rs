@@ -0,0 +1,93 @@
+use crate::{
+    parser::Node,
+    xref::{callees, symbol_uses},
+    NodeKind, NodeType,
+};
+use std::collections::HashSet;
+
+/*
+    全程序(--whole-program)模式: 假定源文件就是整个程序, 除了main和运行时库之外没有外部调用者.
+    在这个前提下, 从main出发走不到的函数, 以及这些函数之外没有被访问的全局变量/常量数组都可以删掉,
+    后端生成的代码和数据段随之变小. 标量常量在语义分析中已经被折叠进使用处, 只要没有剩下的访问就会被删除.
+*/
+
+/* 被删除的内容, 用于统计输出. */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Removed {
+    pub functions: Vec<String>,
+    pub globals: Vec<String>,
+}
+
+impl Removed {
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty() && self.globals.is_empty()
+    }
+}
+
+/*
+    删除从main不可达的函数(及其原型)和不可达代码之外没有被使用的全局声明.
+    程序中没有main时什么也不删, 因为此时无法确定入口.
+*/
+pub fn prune_unreachable(annotated_ast: Vec<Node>) -> (Vec<Node>, Removed) {
+    let mut removed = Removed::default();
+    // 有函数体的函数: (函数名, 函数体)
+    let defined: Vec<(&str, &Node)> = annotated_ast
+        .iter()
+        .filter_map(|n| n.as_func())
+        .filter(|(_, _, _, body)| body.kind() != NodeKind::Nil)
+        .map(|(_, name, _, body)| (name, body))
+        .collect();
+    if !defined.iter().any(|(name, _)| *name == "main") {
+        return (annotated_ast, removed);
+    }
+
+    // step1. 从main出发沿调用图求出可达的函数.
+    let mut reachable = HashSet::new();
+    let mut worklist = vec!["main".to_string()];
+    while let Some(name) = worklist.pop() {
+        if !reachable.insert(name.clone()) {
+            continue;
+        }
+        for (_, body) in defined.iter().filter(|(n, _)| *n == name) {
+            worklist.extend(callees(std::slice::from_ref(*body)));
+        }
+    }
+
+    // step2. 可达函数中用到的全局声明.
+    let used: HashSet<_> = defined
+        .iter()
+        .filter(|(name, _)| reachable.contains(*name))
+        .flat_map(|(_, body)| symbol_uses(std::slice::from_ref(*body)))
+        .map(|u| u.decl)
+        .collect();
+
+    // step3. 删除不可达的函数和未使用的全局声明.
+    let mut result = vec![];
+    for mut node in annotated_ast {
+        match &mut node.node_type {
+            NodeType::Func(_, name, _, body) => {
+                if reachable.contains(name) {
+                    result.push(node);
+                } else if !matches!(body.node_type, NodeType::Nil) {
+                    removed.functions.push(name.clone());
+                }
+            }
+            NodeType::DeclStmt(decls) => {
+                decls.retain(|decl| {
+                    if used.contains(&decl.id) {
+                        return true;
+                    }
+                    if let Some((_, name, ..)) = decl.as_decl() {
+                        removed.globals.push(name.to_string());
+                    }
+                    false
+                });
+                if !decls.is_empty() {
+                    result.push(node);
+                }
+            }
+            _ => result.push(node),
+        }
+    }
+    (result, removed)
+}
@@ -0,0 +1,199 @@
+use crate::{
+    parser::{Node, NodeId},
+    span::Span,
+    BasicType, NodeType,
+};
+
+/*
+    交叉引用(xref): 列出语义分析后AST中每一处变量使用, 以及它解析到的声明和使用方式.
+    编辑器的"查找引用"/语义高亮, 以及未使用变量, 死存储之类的分析都基于它, 不必各自再判断读写.
+    注意: 值为编译期常量的访问在语义分析中已被折叠成Number, 不会出现在这里.
+*/
+
+/* 一处使用对变量的影响 */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UsageKind {
+    Read,
+    Write,
+    /*
+        既读又写: 给数组的某个元素赋值(其余元素保持不变, 之前的存储仍然有效),
+        或者把数组作为实参传给函数(被调用者可能读也可能写).
+    */
+    ReadWrite,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolUse {
+    pub node: NodeId, //Access或Assign节点
+    pub name: String,
+    pub decl: NodeId, //被使用的变量的Decl节点
+    pub kind: UsageKind,
+    pub span: Span,
+}
+
+/* collect收集的结果: 变量使用, 以及被调用的函数名(调用图的边). */
+#[derive(Default)]
+struct Refs {
+    uses: Vec<SymbolUse>,
+    calls: Vec<String>,
+}
+
+/* 按源代码顺序收集语义分析后AST(semantic的返回值)中的所有变量使用. */
+pub fn symbol_uses(annotated_ast: &[Node]) -> Vec<SymbolUse> {
+    let mut refs = Refs::default();
+    for node in annotated_ast {
+        collect(node, &mut refs);
+    }
+    refs.uses.sort_by_key(|u| u.span.start);
+    refs.uses
+}
+
+/* annotated_ast中调用到的函数名(去重, 按首次出现的顺序), 包括运行时库函数. */
+pub fn callees(annotated_ast: &[Node]) -> Vec<String> {
+    let mut refs = Refs::default();
+    for node in annotated_ast {
+        collect(node, &mut refs);
+    }
+    let mut seen = std::collections::HashSet::new();
+    refs.calls.retain(|name| seen.insert(name.clone()));
+    refs.calls
+}
+
+/*
+    语义高亮层: 每处使用中变量名本身的字符区间及其读写方式,
+    可与lexer::classify_file的结果叠加, 让编辑器区分被读和被写的变量.
+*/
+pub fn semantic_tokens(annotated_ast: &[Node]) -> Vec<(std::ops::Range<usize>, UsageKind)> {
+    symbol_uses(annotated_ast)
+        .into_iter()
+        .map(|u| (u.span.start..u.span.start + u.name.chars().count(), u.kind))
+        .collect()
+}
+
+/* node上一处使用的方式, 不是Access/Assign时为None. 供只关心单个节点的调用者使用. */
+pub fn usage_kind(node: &Node) -> Option<UsageKind> {
+    match &node.node_type {
+        NodeType::Access(..) => Some(UsageKind::Read),
+        NodeType::Assign(_, None, _, _) => Some(UsageKind::Write),
+        NodeType::Assign(_, Some(_), _, _) => Some(UsageKind::ReadWrite),
+        _ => None,
+    }
+}
+
+fn push_use(node: &Node, name: &str, decl: NodeId, kind: UsageKind, refs: &mut Refs) {
+    refs.uses.push(SymbolUse {
+        node: node.id,
+        name: name.to_string(),
+        decl,
+        kind,
+        span: node.span,
+    });
+}
+
+fn is_array(ty: &BasicType) -> bool {
+    matches!(
+        ty,
+        BasicType::IntArray(_)
+            | BasicType::FloatArray(_)
+            | BasicType::ConstArray(_)
+            | BasicType::ConstFloatArray(_)
+    )
+}
+
+fn collect(node: &Node, refs: &mut Refs) {
+    use NodeType::*;
+    match &node.node_type {
+        Decl(_, _, dims, inits, _) => {
+            for n in dims.iter().flatten().chain(inits.iter().flatten()) {
+                collect(n, refs);
+            }
+        }
+        DeclStmt(nodes) | InitList(nodes) | Block(nodes) => {
+            for n in nodes {
+                collect(n, refs);
+            }
+        }
+        Access(name, indexes, decl) => {
+            push_use(node, name, *decl, UsageKind::Read, refs);
+            for index in indexes.iter().flatten() {
+                collect(index, refs);
+            }
+        }
+        Assign(name, indexes, expr, decl) => {
+            let kind = usage_kind(node).unwrap();
+            push_use(node, name, *decl, kind, refs);
+            for index in indexes.iter().flatten() {
+                collect(index, refs);
+            }
+            collect(expr, refs);
+        }
+        Call(name, args, _) => {
+            refs.calls.push(name.clone());
+            for arg in args {
+                if let Access(name, indexes, decl) = &arg.node_type {
+                    if is_array(&arg.basic_type) {
+                        push_use(arg, name, *decl, UsageKind::ReadWrite, refs);
+                        for index in indexes.iter().flatten() {
+                            collect(index, refs);
+                        }
+                        continue;
+                    }
+                }
+                collect(arg, refs);
+            }
+        }
+        ExprStmt(expr) => collect(expr, refs),
+        BinOp(_, lhs, rhs) => {
+            collect(lhs, refs);
+            collect(rhs, refs);
+        }
+        UnaryOp(_, operand) | Cast(_, operand) => collect(operand, refs),
+        Cond(cond, on_true, on_false) => {
+            collect(cond, refs);
+            collect(on_true, refs);
+            collect(on_false, refs);
+        }
+        Comma(operands) => {
+            for operand in operands {
+                collect(operand, refs);
+            }
+        }
+        Func(_, _, params, body) => {
+            for param in params {
+                collect(param, refs);
+            }
+            collect(body, refs);
+        }
+        Return(expr) => {
+            if let Some(expr) = expr {
+                collect(expr, refs);
+            }
+        }
+        If(cond, on_true, on_false) => {
+            collect(cond, refs);
+            collect(on_true, refs);
+            if let Some(f) = on_false {
+                collect(f, refs);
+            }
+        }
+        While(cond, body) => {
+            collect(cond, refs);
+            collect(body, refs);
+        }
+        Switch(scrutinee, arms) => {
+            collect(scrutinee, refs);
+            for arm in arms {
+                collect(arm, refs);
+            }
+        }
+        Case(label, stmts) => {
+            if let Some(label) = label {
+                collect(label, refs);
+            }
+            for stmt in stmts {
+                collect(stmt, refs);
+            }
+        }
+        Continue | Break | Empty | Nil | Number(_) | FloatNumber(_) | Str(_) => {}
+    }
+}
@@ -0,0 +1,137 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::alias::{AliasAnalysis, AliasResult, Object};
+use sysy_alpha::ir::{Function, InstKind, Module, Operand};
+use sysy_alpha::lower::lower;
+use sysy_alpha::mem2reg::promote_module;
+use sysy_alpha::peephole;
+use sysy_alpha::session::{CompileOptions, Session};
+
+/*
+    别名分析: 不同的全局数组, 不同的局部数组, 数组形参之间的关系, 以及窥孔优化据此保留的已知内存内容.
+*/
+
+fn compile(name: &str, source: &str) -> Module {
+    let dir = TempDir::new("alias");
+    let path = dir.write(name, source);
+    let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    let mut module = lower(&checked.hir);
+    promote_module(&mut module);
+    module
+}
+
+/* 函数中按出现顺序的store地址. */
+fn store_addresses(function: &Function) -> Vec<Operand> {
+    function
+        .blocks
+        .iter()
+        .flat_map(|b| &b.insts)
+        .filter_map(|i| match &i.kind {
+            InstKind::Store(_, addr) => Some(addr.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn loads(function: &Function) -> usize {
+    function
+        .blocks
+        .iter()
+        .flat_map(|b| &b.insts)
+        .filter(|i| matches!(i.kind, InstKind::Load(_)))
+        .count()
+}
+
+#[test]
+fn objects_are_told_apart() {
+    let module = compile(
+        "objects.sy",
+        "int g[4], h[4];
+void f(int p[], int q[], int i) {
+  int l[4], m[4];
+  g[1] = 1; h[1] = 2; l[1] = 3; m[1] = 4; p[1] = 5; q[1] = 6;
+  g[2] = 7; g[i] = 8; l[i] = 9; l[i] = 10; g[1] = 11;
+}
+int main() { int a[4]; f(a, g, 0); return 0; }
+",
+    );
+    let f = module.function("f").unwrap();
+    let aliases = AliasAnalysis::compute(f);
+    let s = store_addresses(f);
+    let [g1, h1, l1, m1, p1, q1, g2, gi, li, li2, g1b] = &s[..] else {
+        panic!("{:?}", s);
+    };
+    use AliasResult::*;
+    assert_eq!(aliases.alias(g1, h1), NoAlias);
+    assert_eq!(aliases.alias(l1, m1), NoAlias);
+    assert_eq!(aliases.alias(g1, l1), NoAlias);
+    assert_eq!(aliases.alias(p1, l1), NoAlias);
+    assert_eq!(aliases.alias(p1, g1), MayAlias);
+    assert_eq!(aliases.alias(p1, q1), MayAlias);
+    assert_eq!(aliases.alias(g1, g2), NoAlias);
+    assert_eq!(aliases.alias(g1, g1b), MustAlias);
+    assert_eq!(aliases.alias(g1, gi), MayAlias);
+    assert_eq!(aliases.alias(li, li2), MustAlias);
+    assert_eq!(aliases.alias(li, g1), NoAlias);
+    assert_eq!(
+        aliases.location(p1).map(|l| (l.object, l.offset)),
+        Some((Object::Param(0), 1))
+    );
+    // f的局部数组没有传给调用, 调用不会访问它们; 形参和全局变量可能被访问.
+    assert!(!aliases.visible_to_calls(l1));
+    assert!(aliases.visible_to_calls(p1));
+    assert!(aliases.visible_to_calls(g1));
+
+    // main的a传给了f.
+    let main = module.function("main").unwrap();
+    let a = main.blocks[0]
+        .insts
+        .iter()
+        .find(|i| matches!(i.kind, InstKind::Alloca { .. }))
+        .and_then(|i| i.dest)
+        .unwrap();
+    assert!(AliasAnalysis::compute(main).visible_to_calls(&Operand::Value(a)));
+}
+
+#[test]
+fn stores_to_other_arrays_keep_known_values() {
+    let mut module = compile(
+        "forward.sy",
+        "int g[4], h[4];
+int main() {
+  int l[4];
+  g[0] = getint();
+  h[0] = 2;
+  l[1] = 3;
+  int s = g[0] + h[0];
+  putint(l[1]);
+  return s + l[1];
+}
+",
+    );
+    assert!(peephole::run_module(&mut module));
+    module.validate().unwrap();
+    // g[0]和h[0]的store互不影响, putint不会访问l, 所有load都被替换.
+    assert_eq!(loads(module.function("main").unwrap()), 0);
+}
+
+#[test]
+fn possibly_aliasing_stores_block_forwarding() {
+    let mut module = compile(
+        "blocked.sy",
+        "int g[4];
+int f(int p[], int i) {
+  g[0] = 1;
+  p[i] = 2;
+  return g[0];
+}
+int main() { return f(g, 0); }
+",
+    );
+    peephole::run_module(&mut module);
+    assert_eq!(loads(module.function("f").unwrap()), 1);
+}
@@ -0,0 +1,107 @@
+mod common;
+
+use common::SourceSession;
+use sysy_alpha::parser::Node;
+use sysy_alpha::session::CompileOptions;
+use sysy_alpha::NodeType;
+
+/*
+    SysY 2022规范: 数组形参的第一维省略, 其余各维必须是常量表达式,
+    可以引用之前声明的全局常量和常量数组的元素. 这里检查形参各维在语义分析中被正确求值.
+*/
+
+fn session(source: &str) -> SourceSession {
+    SourceSession::new("params", "params.sy", source, CompileOptions::default())
+}
+
+fn param_types(source: &str) -> Vec<String> {
+    let checked = session(source).check().unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    types_of_f(&checked.annotated_ast)
+}
+
+fn types_of_f(ast: &[Node]) -> Vec<String> {
+    let func = ast
+        .iter()
+        .find(|n| matches!(&n.node_type, NodeType::Func(_, name, _, _) if name == "f"))
+        .expect("function f");
+    match &func.node_type {
+        NodeType::Func(_, _, params, _) => params
+            .iter()
+            .map(|p| match &p.node_type {
+                NodeType::Decl(ty, ..) => ty.to_string(),
+                _ => unreachable!(),
+            })
+            .collect(),
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn trailing_dimension_uses_global_const() {
+    let types = param_types(
+        "const int N = 3;\n\
+         int f(int a[][N]) { return a[1][2]; }\n\
+         int main() { int x[2][3]; return f(x); }\n",
+    );
+    assert_eq!(types, ["int[][3]"]);
+}
+
+#[test]
+fn trailing_dimensions_use_const_expressions() {
+    let types = param_types(
+        "const int N = 3, M[2] = {2, 4};\n\
+         int f(int a[][N * 2], int b[][M[1]][N + 1], int c) { return a[0][5] + b[0][1][2] + c; }\n\
+         int main() { int x[1][6]; int y[1][4][4]; return f(x, y, 0); }\n",
+    );
+    assert_eq!(types, ["int[][6]", "int[][4][4]", "int"]);
+}
+
+#[test]
+fn first_dimension_may_be_omitted_alone() {
+    let types = param_types(
+        "int f(int a[]) { return a[0]; }\n\
+         int main() { int x[4]; return f(x); }\n",
+    );
+    assert_eq!(types, ["int[]"]);
+}
+
+#[test]
+fn dimensions_are_evaluated_by_semantic_analysis() {
+    // 语法分析只保留各维的表达式(省略的第一维是Nil), 求值(包括字面量和常量)都在语义分析中.
+    let source = "const int N = 3;\n\
+                  int f(int a[][3], float b[][2 * 2][N], int c[]) { return a[0][0]; }\n\
+                  int main() { return 0; }\n";
+    let ast = session(source).parse().unwrap();
+    let func = ast.iter().find_map(|n| n.as_func()).expect("function f");
+    let dims: Vec<usize> = func
+        .2
+        .iter()
+        .map(|p| match &p.node_type {
+            NodeType::Decl(_, _, Some(dims), ..) => dims.len(),
+            _ => unreachable!(),
+        })
+        .collect();
+    assert_eq!(dims, [2, 3, 1]);
+    assert_eq!(types_of_f(&ast), ["int", "float", "int"]);
+    assert_eq!(param_types(source), ["int[][3]", "float[][4][3]", "int[]"]);
+}
+
+#[test]
+fn call_checks_trailing_dimensions() {
+    let checked = session(
+        "int f(int a[][3]) { return a[0][0]; }\n\
+         int main() { int x[2][3]; int y[2][4]; int z[6]; return f(x) + f(y) + f(z); }\n",
+    )
+    .check()
+    .unwrap();
+    let messages: Vec<&str> = checked
+        .diagnostics
+        .iter()
+        .map(|d| d.message.as_str())
+        .collect();
+    assert_eq!(messages.len(), 2, "{:?}", messages);
+    assert!(messages
+        .iter()
+        .all(|m| m.contains("mismatched array shape for parameter `a`")));
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::cfg::{to_dot, Cfg, Dominators, EdgeKind};
+use sysy_alpha::ir::{BlockId, Module};
+use sysy_alpha::lower::lower;
+use sysy_alpha::session::{CompileOptions, Session};
+
+/*
+    控制流图: 边的种类, 前驱和后继一致, 逆后序从入口开始; DOT输出每个函数一个子图.
+*/
+
+fn module(name: &str, source: &str) -> Module {
+    let dir = TempDir::new("cfg");
+    let path = dir.write(name, source);
+    let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    lower(&checked.hir)
+}
+
+const LOOP: &str = "int main() {
+  int i = 0;
+  while (i < 10) {
+    if (i == 5) break;
+    i = i + 1;
+  }
+  return i;
+}
+";
+
+#[test]
+fn edges_have_kinds() {
+    let module = module("loop.sy", LOOP);
+    let main = module.function("main").unwrap();
+    let cfg = Cfg::build(main);
+    assert_eq!(cfg.len(), main.blocks.len());
+    // bb0 -> bb1(循环头) -> bb2(循环体) / bb3(出口)
+    assert_eq!(cfg.succs[0][0].kind, EdgeKind::Fallthrough);
+    assert_eq!(cfg.succs[1][0].to, BlockId(2));
+    assert_eq!(cfg.succs[1][0].kind, EdgeKind::BranchTrue);
+    assert_eq!(cfg.succs[1][1].to, BlockId(3));
+    assert_eq!(cfg.succs[1][1].kind, EdgeKind::BranchFalse);
+    // 循环头的前驱是入口和回边, 出口的前驱是循环条件和break.
+    assert_eq!(cfg.predecessors(BlockId(1)).count(), 2);
+    assert_eq!(cfg.predecessors(BlockId(3)).count(), 2);
+    for edge in cfg.edges() {
+        assert!(cfg.preds[edge.to.0 as usize].contains(edge));
+    }
+    let rpo = cfg.reverse_postorder();
+    assert_eq!(rpo[0], BlockId(0));
+    assert_eq!(rpo.len(), cfg.len());
+    assert!(cfg.reachable().iter().all(|r| *r));
+}
+
+#[test]
+fn dominators_and_frontiers() {
+    let module = module("doms.sy", LOOP);
+    let cfg = Cfg::build(module.function("main").unwrap());
+    let doms = Dominators::compute(&cfg);
+    // bb1是循环头: 支配循环体和出口, 也在循环体内各块的支配边界上.
+    assert_eq!(doms.idom(BlockId(0)), None);
+    assert_eq!(doms.idom(BlockId(1)), Some(BlockId(0)));
+    assert_eq!(doms.idom(BlockId(3)), Some(BlockId(1)));
+    assert!(doms.dominates(BlockId(1), BlockId(2)));
+    assert!(!doms.dominates(BlockId(2), BlockId(3)));
+    let frontiers = doms.frontiers(&cfg);
+    assert_eq!(frontiers[2], vec![BlockId(1), BlockId(3)]);
+    assert!(frontiers[0].is_empty());
+}
+
+#[test]
+fn dot_output() {
+    let module = module(
+        "dot.sy",
+        "int f(int x) { if (x) return 1; return 2; }
+int main() { return f(getint()); }
+",
+    );
+    let dot = to_dot(&module);
+    assert!(dot.starts_with("digraph cfg {\n"));
+    assert!(dot.contains("subgraph \"cluster_f\""));
+    assert!(dot.contains("subgraph \"cluster_main\""));
+    // 声明的函数没有控制流图.
+    assert!(!dot.contains("cluster_getint"));
+    assert!(dot.contains("\"f.bb0\" -> \"f.bb1\" [label=\"true\", color=darkgreen];"));
+    assert!(dot.contains("\"f.bb0\" -> \"f.bb2\" [label=\"false\", color=red];"));
+    assert!(dot.contains("\"main.bb0\" [label=\"bb0:\\l  %0 = call i32 @getint()\\l"));
+    assert!(dot.ends_with("}\n"));
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use common::SourceSession;
+use sysy_alpha::lexer::LangLevel;
+use sysy_alpha::session::CompileOptions;
+use sysy_alpha::TokenType;
+
+/*
+    扩展: 字符常量按ASCII码当作整数常量. 有错的字符常量(以及严格模式下的所有字符常量)只报告一个词法错误,
+    并留下值为0的整数常量占位, 之后的语法和语义分析不会因为它再报告错误.
+*/
+
+fn extended() -> CompileOptions {
+    CompileOptions {
+        level: LangLevel::Extended,
+        ..Default::default()
+    }
+}
+
+fn int_values(name: &str, source: &str) -> Vec<i32> {
+    let session = SourceSession::new("chars", name, source, extended());
+    let (tokens, diagnostics) = session.lex(false).unwrap();
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    tokens
+        .iter()
+        .filter_map(|t| match t.sort {
+            TokenType::IntNumber(v) => Some(v),
+            _ => None,
+        })
+        .collect()
+}
+
+/* 检查putch(<literal>); 返回全部错误的信息. */
+fn errors(name: &str, literal: &str, options: CompileOptions) -> Vec<String> {
+    let source = format!("int main() {{ putch({}); return 0; }}\n", literal);
+    let session = SourceSession::new("chars", name, &source, options);
+    let checked = session.check().unwrap();
+    checked.errors().map(|d| d.message.clone()).collect()
+}
+
+#[test]
+fn extended_literals_are_their_ascii_codes() {
+    assert_eq!(
+        int_values(
+            "values.sy",
+            r#"'A' 'z' '0' ' ' '\n' '\t' '\r' '\0' '\a' '\b' '\f' '\v' '\\' '\'' '\"' '\?' '"'"#
+        ),
+        vec![65, 122, 48, 32, 10, 9, 13, 0, 7, 8, 12, 11, 92, 39, 34, 63, 34]
+    );
+    assert!(errors("putch.sy", "'A'", extended()).is_empty());
+}
+
+#[test]
+fn malformed_literals_report_one_error() {
+    let cases = [
+        ("''", "empty character literal"),
+        ("'ab'", "character literal may only contain one character"),
+        ("'\\q'", "unknown escape sequence `\\q`"),
+        ("'é'", "non-ASCII character in character literal"),
+    ];
+    for (literal, message) in cases {
+        assert_eq!(
+            errors("bad.sy", literal, extended()),
+            vec![message],
+            "{}",
+            literal
+        );
+    }
+
+    // 未闭合的字符常量到行尾为止, 后面的代码放在下一行.
+    let source = "int main() {\n    putch('A\n    );\n    return 0;\n}\n";
+    let session = SourceSession::new("chars", "open.sy", source, extended());
+    let checked = session.check().unwrap();
+    let messages: Vec<_> = checked.errors().map(|d| d.message.as_str()).collect();
+    assert_eq!(messages, vec!["unterminated character literal"]);
+}
+
+#[test]
+fn strict_mode_rejects_character_literals_once() {
+    assert_eq!(
+        errors("strict.sy", "'A'", CompileOptions::default()),
+        vec!["character literals are not part of SysY"]
+    );
+}
+
+#[test]
+fn placeholders_keep_their_span() {
+    let source = "int main() { putch('ab'); return 0; }\n";
+    let session = SourceSession::new("chars", "span.sy", source, extended());
+    let (tokens, diagnostics) = session.lex(false).unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    let placeholder = tokens
+        .iter()
+        .find(|t| t.sort == TokenType::IntNumber(0))
+        .unwrap();
+    let start = source.find('\'').unwrap();
+    assert_eq!(
+        (placeholder.span.start, placeholder.span.end),
+        (start, start + 4)
+    );
+}
@@ -0,0 +1,137 @@
+mod common;
+
+use common::TempDir;
+use std::process::{Command, Output};
+
+/*
+    命令行: 有词法错误或语义错误(包括-Werror升级的警告)时报告诊断并以1退出, 不再生成任何代码;
+    很长的运算链不会耗尽编译器的栈. --stop-after只做到指定的阶段, 该阶段有错时同样以1退出,
+    语法错误时仍输出部分AST.
+*/
+
+fn sysy_alpha(dir: &TempDir, source: &str, args: &[&str]) -> Output {
+    let path = dir.write("main.sy", source);
+    Command::new(env!("CARGO_BIN_EXE_sysy_alpha"))
+        .arg(&path)
+        .args(args)
+        .current_dir(&**dir)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn semantic_errors_stop_before_code_generation() {
+    let dir = TempDir::new("cli");
+    let output = sysy_alpha(
+        &dir,
+        "int main(){ return y; }\n",
+        &["--emit-asm", "--link", "-o", "a.out"],
+    );
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("undefined variable \"y\""), "{}", stdout);
+    assert!(!dir.join("main.s").exists());
+    assert!(!dir.join("a.out").exists());
+}
+
+#[test]
+fn lexer_errors_stop_before_code_generation() {
+    for source in [
+        "int main(){return 0;}\n@",
+        "int main(){return 0;}\n/* never closed",
+    ] {
+        let dir = TempDir::new("cli");
+        let output = sysy_alpha(&dir, source, &["--emit-ir", "--emit-llvm"]);
+        assert_eq!(output.status.code(), Some(1), "{:?}", source);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("error"), "{}", stdout);
+        assert!(!dir.join("main.sem").exists());
+        assert!(!dir.join("main.ir").exists());
+        assert!(!dir.join("main.ll").exists());
+
+        // run --ir也不执行程序.
+        let output = Command::new(env!("CARGO_BIN_EXE_sysy_alpha"))
+            .args(["run", "--ir"])
+            .arg(dir.join("main.sy"))
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(1), "{:?}", source);
+    }
+}
+
+#[test]
+fn stop_after_lex_fails_on_lexer_errors() {
+    let dir = TempDir::new("cli");
+    let output = sysy_alpha(&dir, "int main(){return 0;}\n", &["--stop-after", "lex"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(dir.join("main.tokens").exists());
+
+    let dir = TempDir::new("cli");
+    let output = sysy_alpha(&dir, "int main(){return 0;}\n@", &["--stop-after", "lex"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(dir.join("main.tokens").exists());
+    assert!(!dir.join("main.ast").exists());
+}
+
+#[test]
+fn stop_after_parse_dumps_partial_ast_on_syntax_errors() {
+    let dir = TempDir::new("cli");
+    let output = sysy_alpha(&dir, "int main(){return 0;}\n", &["--stop-after", "parse"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(dir.join("main.ast").exists());
+    assert!(!dir.join("main.sem").exists());
+
+    let dir = TempDir::new("cli");
+    let output = sysy_alpha(
+        &dir,
+        "int helper(){return 1;}\nint main(){return 0}\n",
+        &["--stop-after", "parse"],
+    );
+    assert_eq!(output.status.code(), Some(1));
+    let ast = std::fs::read_to_string(dir.join("main.ast")).unwrap();
+    assert!(ast.contains("helper"), "{}", ast);
+    assert!(!dir.join("main.sem").exists());
+}
+
+#[test]
+fn werror_fails_the_compilation() {
+    let dir = TempDir::new("cli");
+    let source = "int main(){ int x; return 0; }\n";
+    let output = sysy_alpha(&dir, source, &["-Werror", "--emit-ir"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("treated as an error (-Werror)"),
+        "{}",
+        stdout
+    );
+    assert!(!dir.join("main.ir").exists());
+
+    // 不加-Werror时只是警告.
+    let output = sysy_alpha(&dir, source, &["--emit-ir"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(dir.join("main.ir").exists());
+}
+
+#[test]
+fn long_operator_chains_compile() {
+    let dir = TempDir::new("cli");
+    let source = format!(
+        "int main() {{ int a = 0; a = a{}; return a; }}\n",
+        " + 1".repeat(5000)
+    );
+    let output = sysy_alpha(&dir, &source, &["--emit-ir"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(dir.join("main.sem").exists() && dir.join("main.ir").exists());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_sysy_alpha"))
+        .args(["run", "--ir"])
+        .arg(dir.join("main.sy"))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(5000 & 0xff));
+}
@@ -0,0 +1,94 @@
+#![allow(dead_code)]
+
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use sysy_alpha::session::{CompileOptions, Session};
+
+/*
+    集成测试共用的辅助函数. 测试需要把SysY源代码写成文件才能交给Session和编译器;
+    TempDir是一个只属于当前调用的临时目录(按测试名, 进程号和序号区分, 并行的测试互不干扰), drop时连同其中的文件一起删除.
+*/
+pub struct TempDir {
+    path: PathBuf,
+}
+
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "sysy_{}_{}_{}",
+            name,
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+
+    /* 在目录中写一个文件, 返回它的路径. */
+    pub fn write(&self, file: &str, contents: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.path.join(file);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/* 源代码只写一个文件时的简写: 文件和它所在的目录一起存在, drop时删除. */
+pub struct SourceFile {
+    pub path: PathBuf,
+    _dir: TempDir,
+}
+
+impl SourceFile {
+    pub fn new(test: &str, file: &str, source: &str) -> Self {
+        let dir = TempDir::new(test);
+        SourceFile {
+            path: dir.write(file, source),
+            _dir: dir,
+        }
+    }
+
+    pub fn path_string(&self) -> String {
+        self.path.to_string_lossy().into_owned()
+    }
+}
+
+/* 读同一个源文件的Session; Session在用到时才读文件, 所以文件要和它一起存在. */
+pub struct SourceSession {
+    pub session: Session,
+    _file: SourceFile,
+}
+
+impl SourceSession {
+    pub fn new(test: &str, file: &str, source: &str, options: CompileOptions) -> Self {
+        let file = SourceFile::new(test, file, source);
+        SourceSession {
+            session: Session::new(file.path_string(), options),
+            _file: file,
+        }
+    }
+}
+
+impl Deref for SourceSession {
+    type Target = Session;
+
+    fn deref(&self) -> &Session {
+        &self.session
+    }
+}
@@ -0,0 +1,324 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::diagnostics::DiagnosticKind;
+use sysy_alpha::lexer::LangLevel;
+use sysy_alpha::session::{CompileOptions, Session};
+use sysy_alpha::ConstValue;
+
+/*
+    常量表达式按类型求值(ConstValue): int与float混合运算得到float, 赋给int常量时向零截断;
+    数组维度必须是int常量.
+*/
+
+fn check(name: &str, source: &str) -> sysy_alpha::session::Checked {
+    let dir = TempDir::new("const_eval");
+    let path = dir.write(name, source);
+    // 条件表达式属于扩展语法.
+    let options = CompileOptions {
+        level: LangLevel::Extended,
+        ..Default::default()
+    };
+    Session::new(path.to_string_lossy(), options)
+        .check()
+        .unwrap()
+}
+
+/* 全局声明name折叠后的初始值. */
+fn value_of(checked: &sysy_alpha::session::Checked, name: &str) -> ConstValue {
+    let inits = sysy_alpha::semantics::global_inits(&checked.annotated_ast);
+    let init = inits.iter().find(|i| i.name == name).expect(name);
+    init.flat_values[0]
+}
+
+#[test]
+fn mixed_constant_expressions() {
+    let checked = check(
+        "mixed.sy",
+        "const float PI = 3.5;
+const int N = PI * 2;
+const int M = 1 + 2.5;
+const float H = 7 / 2;
+const float Q = N > 6.5 ? 1 : 0.5;
+const int c[2][2][2] = {1, 2, 3, 4, 5, 6, 7, 8};
+int last = c[1][1][0];
+int a[N];
+int main() { return 0; }
+",
+    );
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    assert_eq!(value_of(&checked, "N"), ConstValue::Int(7));
+    assert_eq!(value_of(&checked, "M"), ConstValue::Int(3));
+    // 7 / 2是整数除法.
+    assert_eq!(value_of(&checked, "H"), ConstValue::Float(3.0));
+    assert_eq!(value_of(&checked, "Q"), ConstValue::Float(1.0));
+    assert_eq!(value_of(&checked, "last"), ConstValue::Int(7));
+}
+
+#[test]
+fn invalid_constant_expressions_report_their_kind() {
+    let cases = [
+        ("float_dim.sy", "int a[2.5];", DiagnosticKind::InvalidArray),
+        (
+            "float_mod.sy",
+            "const int m = 1.5 % 2;",
+            DiagnosticKind::TypeMismatch,
+        ),
+        (
+            "range.sy",
+            "const int c[2] = {1, 2};\nconst int x = c[2];",
+            DiagnosticKind::IndexMismatch,
+        ),
+        (
+            "variable.sy",
+            "int v = 1;\nconst float f = v;",
+            DiagnosticKind::NotConstant,
+        ),
+    ];
+    for (name, source, kind) in cases {
+        let checked = check(name, &format!("{}\nint main() {{ return 0; }}\n", source));
+        let kinds: Vec<_> = checked.errors().map(|d| d.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![Some(kind)],
+            "{}: {:?}",
+            name,
+            checked.diagnostics
+        );
+    }
+}
+
+#[test]
+fn constant_initializers_must_be_constant() {
+    let source = "int g = 1;
+int main() {
+  const int x = getint();
+  const int y[2] = {1, g + 1};
+  return x + y[0];
+}
+";
+    let checked = check("const_init.sy", source);
+    let errors: Vec<_> = checked
+        .errors()
+        .map(|d| (d.message.as_str(), d.labels[0].message.as_str()))
+        .collect();
+    assert_eq!(
+        errors,
+        vec![
+            (
+                "Error type 11 at this line: initializer of constant x is not a constant expression",
+                "Cannot call function getint in constant expression"
+            ),
+            (
+                "Error type 11 at this line: initializer of constant y is not a constant expression",
+                "g should be a constant"
+            ),
+        ]
+    );
+    // 标出的是第一个不是常量的子表达式.
+    let label = &checked.errors().nth(1).unwrap().labels[0].span;
+    assert_eq!(&source[label.start..label.end], "g");
+}
+
+#[test]
+fn nested_initializers_align_to_sub_arrays() {
+    let checked = check(
+        "braces.sy",
+        "int a[2][3] = {{1}, {2}};
+int b[2][2][2] = {1, 2, {3}, 4};
+const int c[3][2] = {{1, 2}, 3, 0, {4}};
+int main() { return 0; }
+",
+    );
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    let inits = sysy_alpha::semantics::global_inits(&checked.annotated_ast);
+    let values = |name: &str| -> Vec<i32> {
+        let init = inits.iter().find(|i| i.name == name).expect(name);
+        init.flat_values.iter().map(|v| v.as_int()).collect()
+    };
+    assert_eq!(values("a"), vec![1, 0, 0, 2, 0, 0]);
+    assert_eq!(values("b"), vec![1, 2, 3, 0, 4, 0, 0, 0]);
+    assert_eq!(values("c"), vec![1, 2, 3, 0, 4, 0]);
+}
+
+#[test]
+fn over_long_initializers_are_reported_where_they_overflow() {
+    let cases = [
+        ("long.sy", "int a[2][3] = {1, 2, 3, 4, 5, 6, 7};", "7"),
+        ("long_row.sy", "int a[2][3] = {{1, 2, 3, 4}};", "4"),
+        ("scalar.sy", "int a[3] = {{1}};", "{1}"),
+        ("misaligned.sy", "int a[2][3] = {1, {2}};", "{2}"),
+    ];
+    for (name, source, culprit) in cases {
+        let source = format!("{}\nint main() {{ return 0; }}\n", source);
+        let checked = check(name, &source);
+        let errors: Vec<_> = checked.errors().collect();
+        assert_eq!(errors.len(), 1, "{}: {:?}", name, checked.diagnostics);
+        assert_eq!(errors[0].kind, Some(DiagnosticKind::InvalidArray));
+        let span = errors[0].labels[0].span;
+        assert_eq!(&source[span.start..span.end], culprit, "{}", name);
+    }
+
+    // 超出的长度标注出是哪一层数组的界.
+    let source = "int a[2][3] = {1, 2, 3, 4, 5, 6, 7};\nint b[2][3] = {{1, 2, 3, 4}};\nint main() { return 0; }\n";
+    let checked = check("bounds.sy", source);
+    let labels: Vec<_> = checked
+        .errors()
+        .map(|d| (d.message.as_str(), d.labels[0].message.as_str()))
+        .collect();
+    assert_eq!(
+        labels,
+        vec![
+            (
+                "Error type 15 at this line: Length of initializer exceeded",
+                "array bound `[2][3]` holds only 6 elements"
+            ),
+            (
+                "Error type 15 at this line: Length of initializer exceeded",
+                "sub-array bound `[3]` holds only 3 elements"
+            ),
+        ]
+    );
+}
+
+#[test]
+fn global_initializers_must_be_constant() {
+    let source = "int v = 1;
+int f() { return 2; }
+int g = 1 + v * 2;
+int h[2][2] = {{1}, {f()}};
+const int N = 2;
+int ok[N] = {N, N * 2};
+int main() { return g + h[1][0] + ok[0]; }
+";
+    let checked = check("global_init.sy", source);
+    let errors: Vec<_> = checked
+        .errors()
+        .map(|d| {
+            let span = d.labels[0].span;
+            (d.message.as_str(), &source[span.start..span.end])
+        })
+        .collect();
+    assert_eq!(
+        errors,
+        vec![
+            (
+                "Error type 11 at this line: global initializer of g is not a constant expression",
+                "v"
+            ),
+            (
+                "Error type 11 at this line: global initializer of h is not a constant expression",
+                "f()"
+            ),
+        ]
+    );
+}
+
+#[test]
+fn integer_overflow_wraps_with_a_warning() {
+    let checked = check(
+        "overflow.sy",
+        "const int MAX = 2147483647;
+const int a = MAX + 1;
+const int b = (-MAX - 1) / -1;
+const int c = 0 && MAX * 2 ? 1 : 2;
+int main() { return c; }
+",
+    );
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    assert_eq!(value_of(&checked, "a"), ConstValue::Int(i32::MIN));
+    assert_eq!(value_of(&checked, "b"), ConstValue::Int(i32::MIN));
+    assert_eq!(value_of(&checked, "c"), ConstValue::Int(2));
+    // 不会被求值的MAX * 2不报告.
+    let overflows: Vec<_> = checked
+        .warnings()
+        .filter(|d| d.kind == Some(DiagnosticKind::IntegerOverflow))
+        .map(|d| d.message.as_str())
+        .collect();
+    assert_eq!(
+        overflows,
+        vec![
+            "integer overflow in constant expression: 2147483647 + 1 wraps to -2147483648",
+            "integer overflow in constant expression: -2147483648 / -1 wraps to -2147483648",
+        ]
+    );
+}
+
+#[test]
+fn unary_operators_fold_in_constants() {
+    let checked = check(
+        "unary.sy",
+        "const int N = -(-3);
+const int M = !N + !0 + +N;
+const float F = -(-1.5);
+const int a[2] = {N, !F};
+int i = a[-(-1)];
+const int MIN = -(-2147483647 - 1);
+int main() { return 0; }
+",
+    );
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    assert_eq!(value_of(&checked, "N"), ConstValue::Int(3));
+    assert_eq!(value_of(&checked, "M"), ConstValue::Int(4));
+    assert_eq!(value_of(&checked, "F"), ConstValue::Float(1.5));
+    assert_eq!(value_of(&checked, "i"), ConstValue::Int(0));
+    // -2147483648取负回绕成它自己.
+    assert_eq!(value_of(&checked, "MIN"), ConstValue::Int(i32::MIN));
+    let overflows: Vec<_> = checked
+        .warnings()
+        .filter(|d| d.kind == Some(DiagnosticKind::IntegerOverflow))
+        .map(|d| d.message.as_str())
+        .collect();
+    assert_eq!(
+        overflows,
+        vec!["integer overflow in constant expression: -(-2147483648) wraps to -2147483648"]
+    );
+}
+
+#[test]
+fn int_min_literal_does_not_warn() {
+    let checked = check(
+        "int_min.sy",
+        "const int LOW = -2147483648;
+int main() { putint(-2147483648); return LOW; }
+",
+    );
+    assert!(checked.diagnostics.is_empty(), "{:?}", checked.diagnostics);
+    assert_eq!(value_of(&checked, "LOW"), ConstValue::Int(i32::MIN));
+}
+
+#[test]
+fn const_float_arrays_are_constant() {
+    let checked = check(
+        "float_table.sy",
+        "const float w[2][2] = {{0.5, 1}, {2}};
+const float half = w[0][0] * 2;
+float sum(const float v[][2]) { return v[1][0]; }
+float scale(float v[][2]) { return v[0][1]; }
+int main() {
+  w[1][1] = 3.0;
+  return sum(w) + scale(w);
+}
+",
+    );
+    assert_eq!(value_of(&checked, "half"), ConstValue::Float(1.0));
+    let decl = sysy_alpha::visit::node_map(&checked.annotated_ast)
+        .into_values()
+        .find_map(|n| match &n.node_type {
+            sysy_alpha::NodeType::Decl(ty, name, ..) if name == "w" => Some(ty.to_string()),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(decl, "const float[2][2]");
+    // 不能给const float数组的元素赋值, 也不能把它传给非const的形参.
+    let errors: Vec<_> = checked.errors().map(|d| d.kind).collect();
+    assert_eq!(
+        errors,
+        vec![
+            Some(DiagnosticKind::AssignToConstant),
+            Some(DiagnosticKind::TypeMismatch)
+        ],
+        "{:?}",
+        checked.diagnostics
+    );
+}
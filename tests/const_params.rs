@@ -0,0 +1,95 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::diagnostics::DiagnosticKind;
+use sysy_alpha::lexer::LangLevel;
+use sysy_alpha::session::{CompileOptions, Session};
+
+/*
+    const形参(扩展语法): 函数中不能给它赋值; const数组只能传给const数组形参;
+    const形参的值在编译期未知, 不能出现在常量表达式中.
+*/
+
+fn errors(name: &str, source: &str) -> Vec<(Option<DiagnosticKind>, String)> {
+    let dir = TempDir::new("const_params");
+    let path = dir.write(name, source);
+    let options = CompileOptions {
+        level: LangLevel::Extended,
+        ..Default::default()
+    };
+    let checked = Session::new(path.to_string_lossy(), options)
+        .check()
+        .unwrap();
+    checked
+        .errors()
+        .map(|d| (d.kind, d.message.clone()))
+        .collect()
+}
+
+#[test]
+fn const_arrays_can_be_passed_to_const_parameters() {
+    let source = "int sum(const int a[], const int n) {
+  int s = 0, i = 0;
+  while (i < n) { s = s + a[i]; i = i + 1; }
+  return s;
+}
+const int c[3] = {1, 2, 3};
+int main() {
+  int b[2] = {4, 5};
+  int i = 1;
+  return sum(c, 3) + sum(b, 2) + c[i];
+}
+";
+    assert!(errors("ok.sy", source).is_empty());
+}
+
+#[test]
+fn constness_survives_the_call_boundary() {
+    assert_eq!(
+        errors(
+            "write.sy",
+            "int f(const int a[], const int n) { a[0] = n; n = 1; return 0; }\n\
+             int main() { int b[1]; return f(b, 1); }\n"
+        ),
+        vec![
+            (
+                Some(DiagnosticKind::AssignToConstant),
+                "Error type 14 at this line: Cannot assign to constant a".to_string()
+            ),
+            (
+                Some(DiagnosticKind::AssignToConstant),
+                "Error type 14 at this line: Cannot assign to constant n".to_string()
+            ),
+        ]
+    );
+    assert_eq!(
+        errors(
+            "pass.sy",
+            "int g(int a[]) { a[0] = 1; return 0; }\n\
+             int f(const int a[]) { return g(a); }\n\
+             const int c[2] = {1, 2};\n\
+             int main() { return f(c) + g(c); }\n"
+        ),
+        vec![
+            (
+                Some(DiagnosticKind::TypeMismatch),
+                "Error type 10 at this line: constant array passed to non-const parameter `a` in function call g".to_string()
+            );
+            2
+        ]
+    );
+}
+
+#[test]
+fn const_parameters_are_not_constant_expressions() {
+    assert_eq!(
+        errors(
+            "dim.sy",
+            "int f(const int n) { int b[n]; b[0] = n; return b[0]; }\nint main() { return f(1); }\n"
+        ),
+        vec![(
+            Some(DiagnosticKind::NotConstant),
+            "Error type 11 at this line: n is a parameter, not a constant".to_string()
+        )]
+    );
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::diagnostics::{DiagnosticKind, WarningCategory, WarningConfig};
+use sysy_alpha::session::{CompileOptions, Session};
+
+/*
+    条件警告: 折叠成常量的if/while条件和int变量与自身的比较;
+    字面量条件(while (1))和float的自身比较(可能是NaN)不报告.
+    只有在WarningConfig中打开ConstantCondition类别时才输出.
+*/
+
+const SOURCE: &str = "const int N = 3;
+int main() {
+  int x = getint();
+  float f = 1.0;
+  while (1) { if (x) break; }
+  while (1 > 2) x = x + 1;
+  if (x == x) x = 1;
+  if (x < x) x = 2;
+  if (f == f) x = 3;
+  if (N - 3) x = 4;
+  return x;
+}
+";
+
+fn conditions(name: &str, warn_constant_conditions: bool) -> Vec<(String, usize, String)> {
+    let dir = TempDir::new("conditions");
+    let path = dir.write(name, SOURCE);
+    let mut warnings = WarningConfig::default();
+    warnings.set(WarningCategory::ConstantCondition, warn_constant_conditions);
+    let options = CompileOptions {
+        warnings,
+        ..Default::default()
+    };
+    let checked = Session::new(path.to_string_lossy(), options)
+        .check()
+        .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    checked
+        .warnings()
+        .filter(|d| d.kind == Some(DiagnosticKind::ConstantCondition))
+        .map(|d| {
+            let label = &d.labels[0];
+            let line = SOURCE[..label.span.start].lines().count();
+            (d.message.clone(), line, label.message.clone())
+        })
+        .collect()
+}
+
+#[test]
+fn constant_conditions_are_reported() {
+    let expected = [
+        ("while", "false", 6, "evaluates to 0"),
+        ("if", "true", 7, "`x` is compared with itself"),
+        ("if", "false", 8, "`x` is compared with itself"),
+        ("if", "false", 10, "evaluates to 0"),
+    ];
+    let expected: Vec<_> = expected
+        .iter()
+        .map(|(stmt, value, line, label)| {
+            (
+                format!("condition of {} statement is always {}", stmt, value),
+                *line,
+                label.to_string(),
+            )
+        })
+        .collect();
+    assert_eq!(conditions("on.sy", true), expected);
+}
+
+#[test]
+fn constant_condition_warnings_are_opt_in() {
+    assert!(conditions("off.sy", false).is_empty());
+}
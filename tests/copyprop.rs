@@ -0,0 +1,60 @@
+use sysy_alpha::copyprop;
+use sysy_alpha::ir::{BinOp, BlockId, Function, Inst, InstKind, Operand, Type, Value};
+
+/*
+    复制传播: 用到copy结果的地方换成来源(包括复制的复制), 来源相同的phi同样删除.
+*/
+
+fn inst(dest: Option<Value>, ty: Type, kind: InstKind) -> Inst {
+    Inst { dest, ty, kind }
+}
+
+#[test]
+fn copies_are_replaced_by_their_sources() {
+    // fn i32 @f(%0: i32): %1 = copy %0; %2 = copy %1; %3 = add %2, %1; ret %3
+    let mut f = Function::new("f", Type::I32, vec![Type::I32]);
+    let entry = f.new_block();
+    let a = f.new_value(Type::I32);
+    let b = f.new_value(Type::I32);
+    let c = f.new_value(Type::I32);
+    f.block_mut(entry).insts = vec![
+        inst(Some(a), Type::I32, InstKind::Copy(Operand::Value(Value(0)))),
+        inst(Some(b), Type::I32, InstKind::Copy(Operand::Value(a))),
+        inst(
+            Some(c),
+            Type::I32,
+            InstKind::Binary(BinOp::Add, Operand::Value(b), Operand::Value(a)),
+        ),
+        inst(None, Type::Void, InstKind::Ret(Some(Operand::Value(c)))),
+    ];
+    assert!(copyprop::run(&mut f));
+    f.validate().unwrap();
+    assert_eq!(
+        f.to_string(),
+        "fn i32 @f(%0: i32) {\nbb0:\n  %1 = add i32 %0, %0\n  ret %1\n}\n"
+    );
+    assert!(!copyprop::run(&mut f));
+}
+
+#[test]
+fn phis_with_one_source_are_copies() {
+    let mut f = Function::new("g", Type::I32, vec![Type::I32]);
+    let entry = f.new_block();
+    let next = f.new_block();
+    let phi = f.new_value(Type::I32);
+    f.block_mut(entry).insts = vec![inst(None, Type::Void, InstKind::Jump(next))];
+    f.block_mut(next).insts = vec![
+        inst(
+            Some(phi),
+            Type::I32,
+            InstKind::Phi(vec![(BlockId(0), Operand::Int(7))]),
+        ),
+        inst(None, Type::Void, InstKind::Ret(Some(Operand::Value(phi)))),
+    ];
+    assert!(copyprop::run(&mut f));
+    assert_eq!(f.block(next).insts.len(), 1);
+    assert_eq!(
+        f.block(next).insts[0].kind,
+        InstKind::Ret(Some(Operand::Int(7)))
+    );
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::cse;
+use sysy_alpha::ir::{BinOp, InstKind, Module};
+use sysy_alpha::lower::lower;
+use sysy_alpha::mem2reg::promote_module;
+use sysy_alpha::session::{CompileOptions, Session};
+
+/*
+    公共子表达式删除: 相同的下标计算只算一次; local只看扩展基本块, global沿支配树.
+*/
+
+fn module(name: &str, source: &str) -> Module {
+    let dir = TempDir::new("cse");
+    let path = dir.write(name, source);
+    let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    let mut module = lower(&checked.hir);
+    promote_module(&mut module);
+    module
+}
+
+fn count(module: &Module, pred: impl Fn(&InstKind) -> bool) -> usize {
+    module
+        .functions
+        .iter()
+        .flat_map(|f| &f.blocks)
+        .flat_map(|b| &b.insts)
+        .filter(|i| pred(&i.kind))
+        .count()
+}
+
+const SOURCE: &str = "int a[10][10];
+int main() {
+  int i = getint(), j = getint();
+  return a[i][j] + a[i][j];
+}
+";
+
+#[test]
+fn index_math_is_shared() {
+    let mut m = module("index.sy", SOURCE);
+    let geps = count(&m, |k| matches!(k, InstKind::Gep(..)));
+    assert!(cse::run_module(&mut m, false));
+    m.validate().unwrap_or_else(|e| panic!("{}\n{}", e, m));
+    // a[i][j]的两次地址计算合并成一次; 地址相同的load不合并.
+    assert_eq!(count(&m, |k| matches!(k, InstKind::Gep(..))), geps - 2);
+    assert_eq!(count(&m, |k| matches!(k, InstKind::Load(_))), 2);
+}
+
+/* if之前算过的i * 10: if中的一次两者都能复用, if之后的一次(两个前驱)只有global能复用. */
+#[test]
+fn global_reaches_across_joins() {
+    let source = "int main() {
+  int i = getint(), j = getint();
+  int x = i * 10 + j;
+  if (x) x = x + (j + i * 10);
+  putint(i * 10 + j);
+  return x;
+}
+";
+    let muls = |m: &Module| {
+        count(
+            m,
+            |k| matches!(k, InstKind::Binary(op, ..) if *op == BinOp::Mul),
+        )
+    };
+    let mut local = module("local.sy", source);
+    assert_eq!(muls(&local), 3);
+    cse::run_module(&mut local, false);
+    assert_eq!(muls(&local), 2);
+    let mut global = module("global.sy", source);
+    cse::run_module(&mut global, true);
+    global
+        .validate()
+        .unwrap_or_else(|e| panic!("{}\n{}", e, global));
+    assert_eq!(muls(&global), 1);
+}
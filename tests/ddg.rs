@@ -0,0 +1,152 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::alias::AliasAnalysis;
+use sysy_alpha::cfg::{Cfg, Dominators};
+use sysy_alpha::ddg::{self, DepKind, DependenceGraph, Scope};
+use sysy_alpha::effects::Effects;
+use sysy_alpha::ir::{BlockId, Function, Module};
+use sysy_alpha::loops::LoopForest;
+use sysy_alpha::lower::lower;
+use sysy_alpha::mem2reg::promote_module;
+use sysy_alpha::session::{CompileOptions, Session};
+
+/*
+    数据依赖图: 基本块中的数据和内存依赖, 循环中跨迭代的依赖, 以及DOT输出.
+*/
+
+fn compile(name: &str, source: &str) -> Module {
+    let dir = TempDir::new("ddg");
+    let path = dir.write(name, source);
+    let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    let mut module = lower(&checked.hir);
+    promote_module(&mut module);
+    module
+}
+
+/* 图中(种类, 是否跨迭代, 起点指令, 终点指令)的文本, 便于按内容检查. */
+fn edges(function: &Function, graph: &DependenceGraph) -> Vec<(DepKind, bool, String, String)> {
+    let text = |n: usize| {
+        let (b, i) = graph.nodes[n];
+        function.block(b).insts[i].to_string()
+    };
+    graph
+        .edges
+        .iter()
+        .map(|e| (e.kind, e.carried, text(e.from), text(e.to)))
+        .collect()
+}
+
+#[test]
+fn block_graph_has_data_and_memory_dependences() {
+    let module = compile(
+        "block.sy",
+        "int g[4], h[4];
+int main() {
+  int x = getint();
+  g[0] = x;
+  int y = g[1];
+  h[0] = y + 1;
+  g[1] = 2;
+  g[0] = 3;
+  return y;
+}
+",
+    );
+    let main = module.function("main").unwrap();
+    let effects = Effects::compute(&module);
+    let graph =
+        DependenceGraph::for_block(main, BlockId(0), &AliasAnalysis::compute(main), &effects);
+    assert_eq!(graph.nodes.len(), main.blocks[0].insts.len());
+    let edges = edges(main, &graph);
+    let count = |kind| edges.iter().filter(|e| e.0 == kind).count();
+    // g[1]的load之后有g[1]的store, g[0]写了两次; g[0]和g[1]不重叠, h与g不重叠.
+    assert_eq!(count(DepKind::Anti), 1, "{:?}", edges);
+    assert_eq!(count(DepKind::Output), 1, "{:?}", edges);
+    assert_eq!(count(DepKind::Flow), 0, "{:?}", edges);
+    assert!(edges.iter().all(|e| !e.1));
+    assert!(edges
+        .iter()
+        .any(|e| e.0 == DepKind::Data && e.2.contains("getint") && e.3.starts_with("store")));
+    assert!(graph.edges.iter().all(|e| e.from < e.to));
+}
+
+#[test]
+fn loop_graph_has_carried_dependences() {
+    let module = compile(
+        "loop.sy",
+        "int a[10];
+int main() {
+  int i = 1;
+  while (i < 10) {
+    a[i] = a[i - 1] + i;
+    i = i + 1;
+  }
+  return a[9];
+}
+",
+    );
+    let main = module.function("main").unwrap();
+    let cfg = Cfg::build(main);
+    let forest = LoopForest::compute(&cfg, &Dominators::compute(&cfg));
+    let graph = DependenceGraph::for_loop(
+        main,
+        &cfg,
+        &forest.loops[0],
+        &AliasAnalysis::compute(main),
+        &Effects::compute(&module),
+    );
+    let edges = edges(main, &graph);
+    // i的phi从回边取得i + 1.
+    assert!(edges
+        .iter()
+        .any(|e| e.0 == DepKind::Data && e.1 && e.2.contains("add") && e.3.contains("phi")));
+    // a[i - 1]与a[i]可能重叠: 本次迭代中先读后写, 写的值下次迭代被读.
+    assert!(edges.iter().any(|e| e.0 == DepKind::Anti && !e.1));
+    assert!(edges.iter().any(|e| e.0 == DepKind::Flow && e.1));
+    // 节点覆盖循环中所有的指令.
+    let insts: usize = forest.loops[0]
+        .blocks
+        .iter()
+        .map(|b| main.block(*b).insts.len())
+        .sum();
+    assert_eq!(graph.nodes.len(), insts);
+}
+
+#[test]
+fn graphs_are_exported_as_dot() {
+    let module = compile(
+        "dot.sy",
+        "int main() {
+  int i = 0, s = 0;
+  while (i < 3) { putint(i); putch(32); s = s + i; i = i + 1; }
+  return s;
+}
+",
+    );
+    let blocks = ddg::to_dot(&module, Scope::Block);
+    assert!(blocks.starts_with("digraph ddg {\n"), "{}", blocks);
+    let main = module.function("main").unwrap();
+    assert_eq!(
+        blocks.matches("subgraph").count(),
+        main.blocks.len(),
+        "{}",
+        blocks
+    );
+    // 两次输出的调用之间有顺序依赖.
+    assert!(
+        blocks.contains("[label=\"order\", color=blue]"),
+        "{}",
+        blocks
+    );
+    assert!(!blocks.contains("style=dashed"), "{}", blocks);
+
+    let loops = ddg::to_dot(&module, Scope::Loop);
+    assert_eq!(loops.matches("subgraph").count(), 1, "{}", loops);
+    assert!(loops.contains("label=\"@main loop bb"), "{}", loops);
+    assert!(loops.contains("style=dashed"), "{}", loops);
+    assert_eq!(Scope::from_name("function"), None);
+}
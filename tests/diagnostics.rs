@@ -0,0 +1,98 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::diagnostics::{Diagnostic, DiagnosticEngine};
+use sysy_alpha::session::{CompileOptions, Session};
+use sysy_alpha::span::{SourceMap, Span};
+
+/*
+    DiagnosticEngine持有全部源文件, 按每个标注的file_id找到对应的源代码;
+    不带颜色时的输出与Diagnostic::render相同. 词法错误也作为诊断返回, 不再直接打印.
+*/
+
+#[test]
+fn labels_are_rendered_from_their_own_file() {
+    let mut sources = SourceMap::new();
+    let lib = sources.add("lib.sy", "int f(int a);\n");
+    let main = sources.add("main.sy", "int main() {\n  return f();\n}\n");
+    let call = "int main() {\n  return ".len();
+    let diagnostic = Diagnostic::error("Error type 9 at this line: wrong number of arguments")
+        .with_label(Span::new(main, call, call + 3), "called with 0 arguments")
+        .with_label(Span::new(lib, 4, 5), "declared with 1 parameter here");
+    let engine = DiagnosticEngine::new(sources);
+    let text = engine.render(&diagnostic);
+    assert!(text.contains("  --> main.sy:2:10\n"), "{}", text);
+    assert!(text.contains("   2 |   return f();\n"), "{}", text);
+    assert!(text.contains("   1 | int f(int a);\n"), "{}", text);
+
+    // 单个文件时与Diagnostic::render一致.
+    let only_main = Diagnostic::error("oops").with_label(Span::new(main, call, call + 1), "here");
+    assert_eq!(
+        engine.render(&only_main),
+        only_main.render(engine.sources().file(main))
+    );
+}
+
+#[test]
+fn color_only_adds_escape_codes() {
+    colored::control::set_override(true);
+    let mut sources = SourceMap::new();
+    let id = sources.add("a.sy", "int a = $;\n");
+    let diagnostic = Diagnostic::error("bad").with_label(Span::new(id, 8, 9), "here");
+    let engine = DiagnosticEngine::new(sources).with_color(true);
+    let colored = engine.render(&diagnostic);
+    assert!(colored.contains("\x1b["));
+    let mut plain = String::new();
+    let mut chars = colored.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|&c| c == 'm');
+        } else {
+            plain.push(c);
+        }
+    }
+    assert_eq!(plain, diagnostic.render(engine.sources().file(id)));
+}
+
+#[test]
+fn lexer_errors_are_returned_as_diagnostics() {
+    let dir = TempDir::new("diagnostics");
+    let path = dir.join("lex.sy");
+    std::fs::write(&path, "int main() {\n  return 0;\n}\n$\n").unwrap();
+    let session = Session::new(path.to_string_lossy(), CompileOptions::default());
+    let checked = session.check().unwrap();
+    assert_eq!(checked.diagnostics.len(), 1);
+    let text = session.render(&checked.diagnostics[0]);
+    assert!(text.contains("Invalid character '$'"), "{}", text);
+    assert!(text.contains(":4:1\n"), "{}", text);
+    assert!(text.contains("   4 | $\n"), "{}", text);
+}
+
+#[test]
+fn suffixes_are_reported_like_other_extensions() {
+    let dir = TempDir::new("diagnostics");
+    let path = dir.write("suffix.sy", "int main() {\n  return 10u & 1;\n}\n");
+    let session = Session::new(path.to_string_lossy(), CompileOptions::default());
+    let (_, diagnostics) = session.lex(false).unwrap();
+    let summary: Vec<(&str, &str)> = diagnostics
+        .iter()
+        .map(|d| (d.message.as_str(), d.labels[0].message.as_str()))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (
+                "numeric suffixes are not part of SysY",
+                "`u` is an extension"
+            ),
+            (
+                "bitwise operators are not part of SysY",
+                "`&` is an extension"
+            ),
+        ]
+    );
+    assert_eq!(diagnostics[0].notes, diagnostics[1].notes);
+    // 后缀额外带有删除它的修改建议.
+    assert_eq!(diagnostics[0].fixes[0].replacement, "");
+    assert_eq!(diagnostics[0].fixes[0].message, "remove the suffix: `10`");
+}
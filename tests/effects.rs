@@ -0,0 +1,117 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::cse;
+use sysy_alpha::effects::{Effect, Effects};
+use sysy_alpha::ir::{InstKind, Module};
+use sysy_alpha::lower::lower;
+use sysy_alpha::mem2reg::promote_module;
+use sysy_alpha::peephole;
+use sysy_alpha::session::{CompileOptions, Session};
+
+/*
+    副作用分析: 函数按函数体和被调函数分类, 没有副作用的调用参与CSE, 结果没用到时被删除.
+*/
+
+fn compile(name: &str, source: &str) -> Module {
+    let dir = TempDir::new("effects");
+    let path = dir.write(name, source);
+    let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    let mut module = lower(&checked.hir);
+    promote_module(&mut module);
+    module
+}
+
+fn calls(module: &Module, function: &str, callee: &str) -> usize {
+    module
+        .function(function)
+        .unwrap()
+        .blocks
+        .iter()
+        .flat_map(|b| &b.insts)
+        .filter(|i| matches!(&i.kind, InstKind::Call(name, _) if name == callee))
+        .count()
+}
+
+const SOURCE: &str = "int g[4];
+int sq(int x) { return x * x; }
+int fact(int n) { if (n <= 1) return 1; return n * fact(n - 1); }
+int local(int n) { int a[4]; a[0] = n; return a[0] + sq(n); }
+int first(int a[]) { return a[0]; }
+int peek() { return g[1]; }
+void poke(int v) { g[2] = v; }
+void fill(int a[]) { a[0] = 1; }
+int echo() { int x = getint(); return x; }
+int twice() { return peek() + echo(); }
+int main() {
+  int a[4];
+  fill(a);
+  poke(sq(3) + sq(3));
+  putint(fact(5) + fact(5) + first(a) + first(a) + twice() + local(2));
+  return 0;
+}
+";
+
+#[test]
+fn functions_are_classified() {
+    let module = compile("classify.sy", SOURCE);
+    let effects = Effects::compute(&module);
+    assert_eq!(effects.effect("sq"), Effect::Pure);
+    assert_eq!(effects.effect("fact"), Effect::Pure);
+    assert_eq!(effects.effect("local"), Effect::Pure);
+    assert_eq!(effects.effect("first"), Effect::ReadsMemory);
+    assert_eq!(effects.effect("peek"), Effect::ReadsMemory);
+    assert_eq!(effects.effect("poke"), Effect::WritesMemory);
+    assert_eq!(effects.effect("fill"), Effect::WritesMemory);
+    assert_eq!(effects.effect("echo"), Effect::DoesIo);
+    assert_eq!(effects.effect("twice"), Effect::DoesIo);
+    assert_eq!(effects.effect("main"), Effect::DoesIo);
+    for builtin in ["getint", "putint"] {
+        assert_eq!(effects.effect(builtin), Effect::DoesIo);
+    }
+    // 不在模块中的函数同样保守处理.
+    assert_eq!(effects.effect("missing"), Effect::DoesIo);
+    assert!(!Effects::default().is_pure("sq"));
+}
+
+#[test]
+fn duplicate_pure_calls_are_shared() {
+    let mut module = compile("cse.sy", SOURCE);
+    assert!(cse::run_module(&mut module, true));
+    module.validate().unwrap();
+    assert_eq!(calls(&module, "main", "sq"), 1);
+    assert_eq!(calls(&module, "main", "fact"), 1);
+    // 读内存的first之间可能有store, 两次调用都保留.
+    assert_eq!(calls(&module, "main", "first"), 2);
+
+    // 函数级的入口不知道其他函数, 调用不参与.
+    let mut module = compile("cse2.sy", SOURCE);
+    let main = module
+        .functions
+        .iter_mut()
+        .find(|f| f.name == "main")
+        .unwrap();
+    cse::global(main);
+    assert_eq!(calls(&module, "main", "sq"), 2);
+}
+
+#[test]
+fn unused_calls_without_side_effects_are_removed() {
+    let mut module = compile(
+        "dce.sy",
+        "int g[2];
+int sq(int x) { return x * x; }
+int peek() { return g[0]; }
+void poke(int v) { g[1] = v; }
+int main() { sq(2); peek(); poke(3); putint(4); return 0; }
+",
+    );
+    assert!(peephole::run_module(&mut module));
+    assert_eq!(calls(&module, "main", "sq"), 0);
+    assert_eq!(calls(&module, "main", "peek"), 0);
+    assert_eq!(calls(&module, "main", "poke"), 1);
+    assert_eq!(calls(&module, "main", "putint"), 1);
+}
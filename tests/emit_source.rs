@@ -0,0 +1,120 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::lexer::{try_tokenize, LangLevel, LexOptions};
+use sysy_alpha::parser::{parse_with_level, Ast, Node};
+use sysy_alpha::utils::emit_source;
+use sysy_alpha::visit::children;
+use sysy_alpha::NodeType;
+
+/*
+    往返测试: 源代码 -> AST -> emit_source -> AST, 两棵AST的结构相同,
+    并且再输出一次得到完全相同的文本.
+*/
+
+fn parse_source(source: &str) -> Ast {
+    let dir = TempDir::new("emit");
+    let path = dir.write("emit.sy", source);
+
+    let options = LexOptions {
+        level: LangLevel::Extended,
+        ..Default::default()
+    };
+    let tokens = try_tokenize(path.to_string_lossy().to_string(), &options).unwrap();
+    match parse_with_level(tokens, LangLevel::Extended) {
+        Ok(ast) => ast,
+        Err(errors) => panic!("{:?} should parse: {}", source, errors[0]),
+    }
+}
+
+/* 节点的种类, 携带的名字/运算符/常量, 以及子节点, 不含位置. */
+fn shape(node: &Node) -> String {
+    let own = match &node.node_type {
+        NodeType::Decl(ty, name, ..) => format!("{}:{}", ty, name),
+        NodeType::Func(ty, name, ..) => format!("{}:{}", ty, name),
+        NodeType::Access(name, ..) | NodeType::Assign(name, ..) | NodeType::Call(name, ..) => {
+            name.clone()
+        }
+        NodeType::BinOp(op, ..) | NodeType::UnaryOp(op, _) => format!("{:?}", op),
+        NodeType::Number(n) => n.to_string(),
+        NodeType::FloatNumber(f) => f.to_string(),
+        _ => String::new(),
+    };
+    let kids: Vec<String> = children(node).into_iter().map(shape).collect();
+    format!("{:?}{}({})", node.kind(), own, kids.join(" "))
+}
+
+fn round_trip(source: &str) -> String {
+    let ast = parse_source(source);
+    let emitted = emit_source(&ast);
+    let reparsed = parse_source(&emitted);
+    let before: Vec<String> = ast.iter().map(shape).collect();
+    let after: Vec<String> = reparsed.iter().map(shape).collect();
+    assert_eq!(before, after, "emitted:\n{}", emitted);
+    assert_eq!(emit_source(&reparsed), emitted);
+    emitted
+}
+
+#[test]
+fn program_round_trips() {
+    let emitted = round_trip(
+        "const int N = 3, M[2][2] = {{1, 2}, {3}};\nfloat g[N];\n\
+         int sum(int a[], int b[][2], float x);\n\
+         int sum(int a[], int b[][2], float x) { int s = 0, i = 0; while (i < N) { s = s + a[i] * (b[i][0] - 1); i = i + 1; } return s; }\n\
+         void f() { ; }\n\
+         int main() { int x = -(-N) + !1; if (x > 0 && (x < 2 || x == 3)) x = 1; else if (x) { x = 2; } else ; \
+         switch (x) { case 1: x = 3; break; default: ; } putint(sum(g, M, 1.5)); return x - -1; }\n",
+    );
+    assert_eq!(
+        emitted,
+        "const int N = 3, M[2][2] = {{1, 2}, {3}};\n\
+         float g[N];\n\
+         int sum(int a[], int b[][2], float x);\n\
+         \n\
+         int sum(int a[], int b[][2], float x) {\n\
+         \x20   int s = 0, i = 0;\n\
+         \x20   while (i < N) {\n\
+         \x20       s = s + a[i] * (b[i][0] - 1);\n\
+         \x20       i = i + 1;\n\
+         \x20   }\n\
+         \x20   return s;\n\
+         }\n\
+         \n\
+         void f() {\n\
+         \x20   ;\n\
+         }\n\
+         \n\
+         int main() {\n\
+         \x20   int x = -(-N) + !1;\n\
+         \x20   if (x > 0 && (x < 2 || x == 3))\n\
+         \x20       x = 1;\n\
+         \x20   else if (x) {\n\
+         \x20       x = 2;\n\
+         \x20   } else\n\
+         \x20       ;\n\
+         \x20   switch (x) {\n\
+         \x20       case 1:\n\
+         \x20           x = 3;\n\
+         \x20           break;\n\
+         \x20       default:\n\
+         \x20           ;\n\
+         \x20   }\n\
+         \x20   putint(sum(g, M, 1.5));\n\
+         \x20   return x - -1;\n\
+         }\n"
+    );
+}
+
+#[test]
+fn parentheses_and_dangling_else_are_preserved() {
+    round_trip(
+        "int main() { int a = 1, b = 2, c;\n\
+         c = (a + b) * (a - (b - 1)) / (a % b);\n\
+         c = (a < b) ? a : (b ? 1 : 2) + 1;\n\
+         c = (a = 3, b = a + 1, a * b);\n\
+         if (a) if (b) c = 1; else c = 2;\n\
+         if (a) { if (b) c = 1; } else c = 2;\n\
+         while (!(a == b)) if ((a ? b : c)) a = a + 1; else b = b + 1;\n\
+         return -(a + b) - -(-c) + 2.0; }\n",
+    );
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use common::SourceSession;
+use sysy_alpha::interp;
+use sysy_alpha::lower::lower;
+use sysy_alpha::parser::Node;
+use sysy_alpha::session::CompileOptions;
+use sysy_alpha::visit::node_map;
+use sysy_alpha::{NodeKind, NodeType};
+
+/*
+    空语句: 单独的';'在任何需要语句的地方都是Empty节点(块中连续的';', while和if/else的空分支),
+    以运算符或字面量开头的表达式语句也能正确解析, 不会丢掉第一个token.
+*/
+
+fn session(source: &str) -> SourceSession {
+    SourceSession::new("empty", "empty.sy", source, CompileOptions::default())
+}
+
+/* 函数体中各语句的种类. */
+fn statement_kinds(body: &Node) -> Vec<NodeKind> {
+    let NodeType::Block(stmts) = &body.node_type else {
+        panic!("expected a block");
+    };
+    stmts.iter().map(Node::kind).collect()
+}
+
+#[test]
+fn lone_semicolons_are_empty_statements() {
+    let ast = session(
+        "int main() {
+  int a = 1;;
+  ;
+  while (a > 5) ;
+  if (a) ; else ;
+  return a;
+}
+",
+    )
+    .parse()
+    .unwrap();
+    let (_, _, _, body) = ast[0].as_func().unwrap();
+    use NodeKind::*;
+    assert_eq!(
+        statement_kinds(body),
+        [DeclStmt, Empty, Empty, While, If, Return]
+    );
+    let map = node_map(&ast);
+    for node in map.values() {
+        match &node.node_type {
+            NodeType::While(_, body) => assert_eq!(body.kind(), Empty),
+            NodeType::If(_, on_true, on_false) => {
+                assert_eq!(on_true.kind(), Empty);
+                assert_eq!(on_false.as_ref().map(|n| n.kind()), Some(Empty));
+            }
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn expression_statements_keep_their_first_token() {
+    let ast = session("int main() { int x = 1; -x; 1; (x); return x; }\n")
+        .parse()
+        .unwrap();
+    let (_, _, _, body) = ast[0].as_func().unwrap();
+    let NodeType::Block(stmts) = &body.node_type else {
+        unreachable!()
+    };
+    let expressions: Vec<NodeKind> = stmts
+        .iter()
+        .filter_map(|s| match &s.node_type {
+            NodeType::ExprStmt(expr) => Some(expr.kind()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        expressions,
+        [NodeKind::UnaryOp, NodeKind::Number, NodeKind::Access]
+    );
+}
+
+#[test]
+fn empty_loop_bodies_run() {
+    // while的空循环体: 一直读到0为止.
+    let checked = session(
+        "int main() {
+  while (getint()) ;
+  ;;
+  if (1) ; else putint(0);
+  return getint();
+}
+",
+    )
+    .check()
+    .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    let outcome = interp::run(&lower(&checked.hir), b"3 2 0 9").unwrap();
+    assert!(outcome.output.is_empty());
+    assert_eq!(outcome.exit_code, 9);
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::diagnostics::Severity;
+use sysy_alpha::session::{CompileOptions, Session};
+
+/*
+    未定义的名字只报告第一次, 出错的表达式不再连带报告类型不符;
+    CompileOptions::max_errors限制错误个数, 达到后停止分析并附加一条说明.
+*/
+
+fn check(name: &str, source: &str, max_errors: Option<usize>) -> Vec<(Severity, String)> {
+    let dir = TempDir::new("error_limit");
+    let path = dir.write(name, source);
+    let options = CompileOptions {
+        max_errors,
+        ..Default::default()
+    };
+    let checked = Session::new(path.to_string_lossy(), options)
+        .check()
+        .unwrap();
+    checked
+        .diagnostics
+        .iter()
+        .filter(|d| d.severity != Severity::Warning)
+        .map(|d| (d.severity, d.message.clone()))
+        .collect()
+}
+
+#[test]
+fn undefined_symbols_do_not_cascade() {
+    let errors = check(
+        "cascade.sy",
+        "int main() {
+  int x = y + 1;
+  y = 2;
+  x = y[1] + -y;
+  if (y) x = g(y) + g(2);
+  while (y < 3) x = 1;
+  return y;
+}
+",
+        None,
+    );
+    assert_eq!(
+        errors,
+        vec![
+            (
+                Severity::Error,
+                "Error type 1 at this line: undefined variable \"y\".".to_string()
+            ),
+            (
+                Severity::Error,
+                "Error type 3 at this line: undefined function `\"g\"`".to_string()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn analysis_stops_at_the_error_limit() {
+    let source = "int f() { return a; }
+int g() { return b; }
+int h() { return c; }
+int main() { return d; }
+";
+    let errors = check("limit.sy", source, Some(2));
+    let messages: Vec<_> = errors.iter().map(|(_, m)| m.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "Error type 1 at this line: undefined variable \"a\".",
+            "Error type 1 at this line: undefined variable \"b\".",
+            "too many errors, analysis stopped after 2 errors",
+        ]
+    );
+    assert_eq!(errors[2].0, Severity::Note);
+    assert_eq!(check("unlimited.sy", source, None).len(), 4);
+}
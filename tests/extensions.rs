@@ -0,0 +1,128 @@
+mod common;
+
+use common::SourceSession;
+use sysy_alpha::lexer::LangLevel;
+use sysy_alpha::parser::ParserOptions;
+use sysy_alpha::session::{CompileError, CompileOptions};
+
+/*
+    ParserOptions: 扩展语法可以逐项打开. 关闭的扩展报告需要打开的开关, 其余扩展照常接受.
+*/
+
+const SOURCE: &str = "int first(const int a[]) { return a[0]; }\n\
+int main() {\n\
+    int a = 1, b;\n\
+    b = a > 0 ? a : -a;\n\
+    switch (b) { case 1: b = 2; break; default: break; }\n\
+    b = (a = 2, a + 1);\n\
+    putf(\"%d\\n\", b);\n\
+    int c[1] = {b};\n\
+    b = first(c);\n\
+    return b;\n\
+}\n";
+
+fn session(name: &str, options: CompileOptions) -> SourceSession {
+    SourceSession::new("ext", name, SOURCE, options)
+}
+
+fn parse_messages(name: &str, parser: ParserOptions) -> Vec<String> {
+    let options = CompileOptions {
+        parser: Some(parser),
+        ..Default::default()
+    };
+    match session(name, options).parse() {
+        Ok(_) => vec![],
+        Err(CompileError::Parse(errors)) => errors.into_iter().map(|e| e.message).collect(),
+        Err(e) => panic!("{}", e),
+    }
+}
+
+#[test]
+fn presets_follow_the_language_level() {
+    let extended = CompileOptions {
+        level: LangLevel::Extended,
+        ..Default::default()
+    };
+    let checked = session("extended.sy", extended).check().unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+
+    let strict = ParserOptions::default();
+    assert!(!strict.allow_ternary && !strict.allow_switch && !strict.allow_comma);
+    assert!(!strict.allow_const_params);
+    assert!(strict.allow_strings);
+    assert_eq!(strict.lex_level(), LangLevel::SysY2022);
+}
+
+#[test]
+fn extensions_can_be_enabled_one_by_one() {
+    let all = ParserOptions::from(LangLevel::Extended);
+    assert!(parse_messages("all.sy", all).is_empty());
+
+    let cases = [
+        (
+            "no_ternary.sy",
+            ParserOptions {
+                allow_ternary: false,
+                ..all
+            },
+            "conditional expressions are not part of SysY, enable ParserOptions::allow_ternary",
+        ),
+        (
+            "no_switch.sy",
+            ParserOptions {
+                allow_switch: false,
+                ..all
+            },
+            "switch statements are not part of SysY, enable ParserOptions::allow_switch",
+        ),
+        (
+            "no_comma.sy",
+            ParserOptions {
+                allow_comma: false,
+                ..all
+            },
+            "comma expressions are not part of SysY, enable ParserOptions::allow_comma",
+        ),
+        (
+            "no_strings.sy",
+            ParserOptions {
+                allow_strings: false,
+                ..all
+            },
+            "string literal arguments are disabled, enable ParserOptions::allow_strings",
+        ),
+        (
+            "no_const_params.sy",
+            ParserOptions {
+                allow_const_params: false,
+                ..all
+            },
+            "const parameters are not part of SysY, enable ParserOptions::allow_const_params",
+        ),
+    ];
+    for (name, options, expected) in cases {
+        let messages = parse_messages(name, options);
+        assert_eq!(messages.len(), 1, "{}: {:?}", name, messages);
+        assert!(messages[0].contains(expected), "{}: {:?}", name, messages);
+    }
+}
+
+#[test]
+fn enabling_a_token_level_extension_switches_the_lexer() {
+    // 严格级别下单独打开条件表达式: '?'和':'按扩展词法得到, 其余关闭的扩展仍然报告.
+    let options = CompileOptions {
+        parser: Some(ParserOptions {
+            allow_ternary: true,
+            ..ParserOptions::default()
+        }),
+        ..Default::default()
+    };
+    let Err(CompileError::Parse(errors)) = session("ternary_only.sy", options).parse() else {
+        panic!("const parameters, switch and comma expressions should be rejected");
+    };
+    let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+    assert_eq!(messages.len(), 3, "{:?}", messages);
+    assert!(messages[0].contains("allow_const_params"));
+    assert!(messages[1].contains("allow_switch"));
+    assert!(messages[2].contains("allow_comma"));
+}
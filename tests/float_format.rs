@@ -0,0 +1,94 @@
+mod common;
+
+use common::{SourceSession, TempDir};
+use sysy_alpha::lower::lower;
+use sysy_alpha::session::CompileOptions;
+use sysy_alpha::utils::FloatFormat;
+
+/*
+    FloatFormat必须与评测机(glibc printf的%f)的输出逐字一致.
+    下面的期望值都是用gcc编译printf("%f|%.0f|%.2f\n", x, x, x)实际得到的.
+    Session按CompileOptions::float_format输出AST的dump文件和解释器中putf的%f.
+*/
+
+const EXPECTED: &[(f32, &str, &str, &str)] = &[
+    (std::f32::consts::PI, "3.141593", "3", "3.14"),
+    (0.1, "0.100000", "0", "0.10"),
+    (-0.0, "-0.000000", "-0", "-0.00"),
+    (1e10, "10000000000.000000", "10000000000", "10000000000.00"),
+    (2.5, "2.500000", "2", "2.50"),
+    (0.000_000_5, "0.000000", "0", "0.00"),
+    (0.000_001_5, "0.000002", "0", "0.00"),
+    (123_456.79, "123456.789062", "123457", "123456.79"),
+    (-1.0 / 3.0, "-0.333333", "-0", "-0.33"),
+    (16_777_217.0, "16777216.000000", "16777216", "16777216.00"),
+    (1e-7, "0.000000", "0", "0.00"),
+];
+
+#[test]
+fn matches_printf_fixed_notation() {
+    let default = FloatFormat::default();
+    let integral = FloatFormat {
+        precision: 0,
+        ..Default::default()
+    };
+    let two = FloatFormat {
+        precision: 2,
+        ..Default::default()
+    };
+    for &(value, six, zero, two_places) in EXPECTED {
+        assert_eq!(default.format(value), six, "%f of {}", value);
+        assert_eq!(integral.format(value), zero, "%.0f of {}", value);
+        assert_eq!(two.format(value), two_places, "%.2f of {}", value);
+    }
+}
+
+#[test]
+fn matches_printf_special_values() {
+    let format = FloatFormat::default();
+    assert_eq!(format.format(f32::NAN), "nan");
+    assert_eq!(format.format(f32::INFINITY), "inf");
+    assert_eq!(format.format(f32::NEG_INFINITY), "-inf");
+}
+
+#[test]
+fn compares_outputs_within_epsilon() {
+    let format = FloatFormat::default();
+    assert!(format.outputs_match("3.141593 10\n", "3.1415927 10"));
+    assert!(format.outputs_match("100000000.000000", "100000001.000000"));
+    assert!(!format.outputs_match("3.141593 10", "3.141593 11"));
+    assert!(!format.outputs_match("0.100000", "0.100100"));
+    assert!(!format.outputs_match("1 2", "1"));
+}
+
+#[test]
+fn session_format_reaches_dumps_and_the_interpreter() {
+    let source = "int main() { float x = 1.5; putf(\"%f %.1f\\n\", x, x); return 0; }\n";
+    let two = CompileOptions {
+        float_format: FloatFormat {
+            precision: 2,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let outputs = [CompileOptions::default(), two].map(|options| {
+        let session = SourceSession::new("float_format", "main.sy", source, options);
+        let checked = session.check().unwrap();
+        let dir = TempDir::new("float_format");
+        session.print_tree(&checked.annotated_ast, &dir.join("main"), "sem", true);
+        let dump = std::fs::read_to_string(dir.join("main.sem")).unwrap();
+        let outcome = session.run(&lower(&checked.hir), b"").unwrap();
+        (dump, String::from_utf8(outcome.output).unwrap())
+    });
+    let [(default_dump, default_output), (dump, output)] = outputs;
+    assert!(
+        default_dump.contains("FloatNumber 1.500000"),
+        "{}",
+        default_dump
+    );
+    assert_eq!(default_output, "1.500000 1.5\n");
+    // 非默认的格式改变没有写精度的浮点输出, 写了精度的%.1f不变.
+    assert!(dump.contains("FloatNumber 1.50"), "{}", dump);
+    assert!(!dump.contains("1.500000"), "{}", dump);
+    assert_eq!(output, "1.50 1.5\n");
+}
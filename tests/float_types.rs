@@ -0,0 +1,120 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::session::{CompileOptions, Session};
+use sysy_alpha::visit::node_map;
+use sysy_alpha::{BasicType, NodeType, TokenType};
+
+/*
+    float参与语义分析: 赋值, 返回值, 运算和条件都接受int/float标量并在两者之间隐式转换;
+    算术运算有float时结果是float, 关系和逻辑运算的结果总是int.
+*/
+
+fn check(name: &str, source: &str) -> sysy_alpha::session::Checked {
+    let dir = TempDir::new("float");
+    let path = dir.write(name, source);
+    Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap()
+}
+
+const PROGRAM: &str = "float scale(float x[], int n) {
+  float sum = 0.0;
+  int i = 0;
+  while (i < n) { sum = sum + x[i] * 2; i = i + 1; }
+  if (sum) return sum / n;
+  return 0;
+}
+int main() {
+  float a[3] = {1.0, 2.5, 3};
+  float f = 1;
+  int k = 2.5;
+  a[1] = k;
+  f = f * a[0];
+  if (f > 1.5 && !f) k = f;
+  if (scale(a, 3) >= 2.0) return 1;
+  return 0;
+}
+";
+
+#[test]
+fn float_programs_pass_semantic_analysis() {
+    let checked = check("float.sy", PROGRAM);
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+
+    let binops: Vec<(TokenType, BasicType)> = node_map(&checked.annotated_ast)
+        .into_values()
+        .filter_map(|n| match &n.node_type {
+            NodeType::BinOp(op, ..) => Some((op.clone(), n.basic_type.clone())),
+            _ => None,
+        })
+        .collect();
+    for (op, ty) in &binops {
+        let expected = match op {
+            // 只有i = i + 1是int运算, 其余算术运算都有float参与.
+            TokenType::Plus => continue,
+            TokenType::Multi | TokenType::Divide => BasicType::Float,
+            _ => BasicType::Int,
+        };
+        assert_eq!(ty, &expected, "{:?}", op);
+    }
+    assert!(binops
+        .iter()
+        .any(|(op, ty)| *op == TokenType::GreatEqual && *ty == BasicType::Int));
+}
+
+#[test]
+fn modulo_and_arrays_are_still_rejected() {
+    let cases = [
+        "int main() { float f = 1.0; return f % 2; }",
+        "int main() { float a[2]; float b[2]; a = b; return 0; }",
+        "int main() { float a[2]; float f = a; return 0; }",
+        "int main() { float a[2]; if (a) return 1; return 0; }",
+    ];
+    for (i, source) in cases.iter().enumerate() {
+        let checked = check(&format!("bad{}.sy", i), source);
+        assert!(checked.has_errors(), "{}", source);
+    }
+}
+
+#[test]
+fn mixed_operands_get_explicit_casts() {
+    let checked = check(
+        "cast.sy",
+        "int h(float x) { return x; }
+int main() {
+  float x = 1;
+  int k = 3;
+  x = x * k;
+  k = h(k);
+  return k;
+}
+",
+    );
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    let nodes = node_map(&checked.annotated_ast);
+    let mut casts: Vec<(BasicType, BasicType)> = nodes
+        .values()
+        .filter_map(|n| match &n.node_type {
+            NodeType::Cast(ty, expr) => {
+                assert_eq!(&n.basic_type, ty);
+                Some((expr.basic_type.clone(), ty.clone()))
+            }
+            _ => None,
+        })
+        .collect();
+    casts.sort_by_key(|(from, _)| from.to_string());
+    // return x转换成int, x * k和h(k)中的k转换成float.
+    assert_eq!(
+        casts,
+        vec![
+            (BasicType::Float, BasicType::Int),
+            (BasicType::Int, BasicType::Float),
+            (BasicType::Int, BasicType::Float),
+        ]
+    );
+    // 常量直接折叠成目标类型: float x = 1的初始值是1.0.
+    assert!(nodes
+        .values()
+        .any(|n| matches!(n.node_type, NodeType::FloatNumber(v) if v == 1.0)));
+}
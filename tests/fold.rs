@@ -0,0 +1,77 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::fold::{fold_all, fold_children, Folder};
+use sysy_alpha::parser::Node;
+use sysy_alpha::semantics::semantic;
+use sysy_alpha::session::{CompileOptions, Session};
+use sysy_alpha::span::Span;
+use sysy_alpha::{NodeKind, NodeType, TokenType};
+
+/*
+    Folder的默认实现是恒等变换; 只重写一个方法就能写出小的改写.
+    这里把语法分析后的`-e`改写成`0 - e`, 再交给语义分析, 检查改写后的AST仍然能通过检查并被折叠.
+*/
+
+struct NegToSub;
+
+impl Folder for NegToSub {
+    fn fold_unary(&mut self, node: &Node) -> Node {
+        let node = fold_children(self, node);
+        match &node.node_type {
+            NodeType::UnaryOp(TokenType::Minus, operand) => {
+                let mut zero = Node::new(NodeType::Number(0));
+                zero.span = Span::new(node.span.file_id, node.span.start, node.span.start);
+                let mut sub = Node::new(NodeType::BinOp(
+                    TokenType::Minus,
+                    Box::new(zero),
+                    operand.clone(),
+                ));
+                sub.span = node.span;
+                sub
+            }
+            _ => node,
+        }
+    }
+}
+
+fn count(nodes: &[Node], kind: NodeKind) -> usize {
+    struct Count(NodeKind, usize);
+    impl sysy_alpha::visit::Visitor for Count {
+        fn visit_node(&mut self, node: &Node) {
+            if node.kind() == self.0 {
+                self.1 += 1;
+            }
+            sysy_alpha::visit::walk(self, node);
+        }
+    }
+    let mut counter = Count(kind, 0);
+    sysy_alpha::visit::walk_all(&mut counter, nodes);
+    counter.1
+}
+
+#[test]
+fn rewrite_then_check() {
+    let dir = TempDir::new("fold");
+    let path = dir.join("neg.sy");
+    std::fs::write(
+        &path,
+        "const int N = -3;\nint main() { int x = -N; return -x + -(x * 2); }\n",
+    )
+    .unwrap();
+    let path = path.to_string_lossy().to_string();
+
+    let ast = Session::new(path.clone(), CompileOptions::default())
+        .parse()
+        .unwrap();
+    assert_eq!(count(&ast, NodeKind::UnaryOp), 4);
+
+    let rewritten = fold_all(&mut NegToSub, &ast);
+    assert_eq!(count(&rewritten, NodeKind::UnaryOp), 0);
+    assert_eq!(count(&rewritten, NodeKind::BinOp), 6);
+
+    let (annotated, diagnostics) = semantic(&rewritten);
+    assert!(diagnostics.is_empty());
+    // N和x的初始值在语义分析中被折叠, 只剩return中的两个减法和一个乘法.
+    assert_eq!(count(&annotated, NodeKind::BinOp), 4);
+}
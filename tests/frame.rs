@@ -0,0 +1,197 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::codegen::frame::{self, Abi, ArgLocation, Frame};
+use sysy_alpha::ir::{Module, Type};
+use sysy_alpha::lower::lower;
+use sysy_alpha::mem2reg::promote_module;
+use sysy_alpha::session::{CompileOptions, Session};
+use sysy_alpha::target::Target;
+
+/*
+    栈帧布局和调用约定: 实参的寄存器和栈位置, 槽的对齐, 大数组的偏移, 以及--dump-frame的文本.
+*/
+
+fn compile(name: &str, source: &str) -> Module {
+    let dir = TempDir::new("frame");
+    let path = dir.write(name, source);
+    let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    let mut module = lower(&checked.hir);
+    promote_module(&mut module);
+    module
+}
+
+#[test]
+fn arguments_use_registers_then_stack() {
+    let riscv = Abi::for_target(Target::Riscv64);
+    let ints = vec![Type::I32; 10];
+    let (locations, stack) = riscv.assign_args(&ints, ints.len());
+    assert_eq!(locations[7], ArgLocation::Reg("a7"));
+    assert_eq!(
+        locations[8..],
+        [ArgLocation::Stack(0), ArgLocation::Stack(8)]
+    );
+    assert_eq!(stack, 16);
+
+    // 浮点寄存器用完后, float改用剩下的整数寄存器.
+    let mut mixed = vec![Type::F32; 9];
+    mixed.push(Type::Ptr);
+    let (locations, stack) = riscv.assign_args(&mixed, mixed.len());
+    assert_eq!(locations[0], ArgLocation::Reg("fa0"));
+    assert_eq!(locations[8], ArgLocation::Reg("a0"));
+    assert_eq!(locations[9], ArgLocation::Reg("a1"));
+    assert_eq!(stack, 0);
+
+    let arm = Abi::for_target(Target::Armv7);
+    let (locations, stack) = arm.assign_args(&ints[..5], 5);
+    assert_eq!(locations[3], ArgLocation::Reg("r3"));
+    assert_eq!(locations[4], ArgLocation::Stack(0));
+    assert_eq!(stack, 8);
+    // 变长参数中的double从偶数号寄存器开始.
+    let (locations, _) = arm.assign_args(&[Type::Ptr, Type::I32, Type::F32], 1);
+    assert_eq!(locations[2], ArgLocation::Pair("r2", "r3"));
+    let (locations, stack) = arm.assign_args(&[Type::Ptr, Type::F32, Type::F32], 1);
+    assert_eq!(locations[1], ArgLocation::Pair("r2", "r3"));
+    assert_eq!(locations[2], ArgLocation::Stack(0));
+    assert_eq!(stack, 8);
+}
+
+#[test]
+fn frames_are_aligned_and_hold_large_arrays() {
+    let module = compile(
+        "large.sy",
+        "int sum(int a[], int n) {
+  int i = 0, s = 0;
+  while (i < n) { s = s + a[i]; i = i + 1; }
+  return s;
+}
+int main() {
+  int big[3000];
+  int small[3];
+  big[2999] = getint();
+  small[0] = 1;
+  return sum(big, 3000) + small[0];
+}
+",
+    );
+    for target in Target::ALL {
+        let abi = Abi::for_target(target);
+        let main = module.function("main").unwrap();
+        let frame = Frame::build(&module, main, target);
+        assert_eq!(frame.size % abi.stack_align, 0);
+        assert_eq!(frame.locals.len(), 2);
+        let (big, small) = (&frame.locals[0], &frame.locals[1]);
+        assert_eq!(big.size, 12000);
+        assert_eq!(small.offset, big.offset + 12000);
+        assert!(frame.locals.iter().all(|l| l.offset % abi.word == 0));
+        assert!(!abi.fits_offset(small.offset));
+        // 有调用时保存返回地址, 放在最上面.
+        assert_eq!(frame.saved.len(), 1);
+        assert_eq!(frame.saved[0].0, abi.return_address);
+        assert!(frame.saved[0].1 >= small.offset + small.size);
+        assert!(frame.saved[0].1 + abi.word <= frame.size);
+        for (v, slot) in frame.slots.iter().enumerate() {
+            if let Some(offset) = slot {
+                let ty = main.value_types[v];
+                assert_eq!(offset % abi.size_of(ty), 0);
+                assert!(*offset < big.offset);
+            }
+        }
+        // 叶函数不保存返回地址; 循环中的phi有影子槽.
+        let sum = Frame::build(&module, module.function("sum").unwrap(), target);
+        assert!(sum.saved.is_empty());
+        assert_eq!(sum.phi_copies.iter().flatten().count(), 2);
+        assert_eq!(
+            sum.params,
+            [
+                ArgLocation::Reg(abi.int_args[0]),
+                ArgLocation::Reg(abi.int_args[1])
+            ]
+        );
+    }
+}
+
+#[test]
+fn stack_arguments_are_found_above_the_frame() {
+    let module = compile(
+        "many.sy",
+        "int f(int a, int b, int c, int d, int e, int g, int h, int i, int j, int k) {
+  return a + b + c + d + e + g + h + i + j + k;
+}
+int main() { return f(1, 2, 3, 4, 5, 6, 7, 8, 9, 10); }
+",
+    );
+    let main = Frame::build(&module, module.function("main").unwrap(), Target::Riscv64);
+    assert_eq!(main.outgoing, 16);
+    assert!(main.slots.iter().flatten().all(|o| *o >= 16));
+    let f = Frame::build(&module, module.function("f").unwrap(), Target::Riscv64);
+    assert_eq!(f.incoming(8), Some(f.size));
+    assert_eq!(f.incoming(9), Some(f.size + 8));
+    assert_eq!(f.incoming(0), None);
+
+    let arm = Frame::build(&module, module.function("f").unwrap(), Target::Armv7);
+    assert_eq!(arm.incoming(4), Some(arm.size));
+    assert_eq!(arm.incoming(9), Some(arm.size + 20));
+}
+
+#[test]
+fn dump_shows_layout_and_offset_range() {
+    let module = compile(
+        "dump.sy",
+        "int main() {
+  int a[1000];
+  a[0] = getint();
+  putint(a[0]);
+  return 0;
+}
+",
+    );
+    let text = frame::dump(&module, Target::Riscv64);
+    assert!(text.starts_with("@main (riscv64):\n  frame "), "{}", text);
+    assert!(text.contains(", 4000 bytes"), "{}", text);
+    assert!(
+        text.contains("saved ra  (out of immediate range)"),
+        "{}",
+        text
+    );
+    let arm = frame::dump(&module, Target::Armv7);
+    assert!(arm.contains("saved lr"), "{}", arm);
+}
+
+#[test]
+fn call_sites_show_argument_and_return_locations() {
+    let module = compile(
+        "calls.sy",
+        "float fsum(float f0, int i0, float f1, float f2, float f3, float f4, float f5, float f6,
+           float f7, float f8, int i1, float f9, int arr[], int i2, int i3, int i4, int i5, int i6) {
+  return f0 + f9 + i0 + i6 + arr[0];
+}
+int main() {
+  int a[1] = {1};
+  putf(\"%f %d\\n\", fsum(0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, a, 13, 14, 15, 16, 17), 3);
+  return 0;
+}
+",
+    );
+    // 与LLVM按LP64D生成的代码相同: fa0-fa7用完后的float依次占用整数寄存器.
+    let riscv = frame::dump(&module, Target::Riscv64);
+    assert!(
+        riscv.contains("  call @fsum(fa0, a0, fa1, fa2, fa3, fa4, fa5, fa6, fa7, a1, a2, a3, a4, a5, a6, a7, [sp+0], [sp+8]) -> fa0\n"),
+        "{}",
+        riscv
+    );
+    assert!(riscv.contains("  call @putf(a0, a1, a2)\n"), "{}", riscv);
+    let arm = frame::dump(&module, Target::Armv7);
+    assert!(
+        arm.contains("  call @fsum(s0, r0, s1, s2, s3, s4, s5, s6, s7, s8, r1, s9, r2, r3, [sp+0], [sp+4], [sp+8], [sp+12]) -> s0\n"),
+        "{}",
+        arm
+    );
+    assert!(arm.contains("  call @putf(r0, r2:r3, [sp+0])\n"), "{}", arm);
+    let abi = Abi::for_target(Target::Armv7);
+    assert_eq!(abi.return_register(Type::Void), None);
+    assert_eq!(abi.return_register(Type::Ptr), Some("r0"));
+}
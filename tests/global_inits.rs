@@ -0,0 +1,90 @@
+mod common;
+
+use common::{SourceFile, SourceSession};
+use sysy_alpha::{
+    lexer::{tokenize, LangLevel},
+    parser::{parse, Node},
+    semantics::{global_inits, semantic, GlobalInit},
+    session::CompileOptions,
+    visit::node_map,
+    ConstValue, NodeType, Scope,
+};
+
+/*
+    语义分析后每个声明的初始值按行主序展开成一维数组: 全局变量的初始化列表补齐成完整的数组,
+    没有初始化的全局变量全为0; 局部变量中需要运行时求值的元素在flat_values中占一个0,
+    其偏移量和初始化表达式的NodeId记录在has_dynamic_parts中.
+*/
+
+fn check(name: &str, source: &str) -> Vec<Node> {
+    let file = SourceFile::new("global_inits", name, source);
+    let ast = parse(tokenize(file.path_string())).unwrap();
+    semantic(&ast).0
+}
+
+fn inits(name: &str, source: &str) -> Vec<GlobalInit> {
+    global_inits(&check(name, source))
+}
+
+fn find<'a>(inits: &'a [GlobalInit], name: &str) -> &'a GlobalInit {
+    inits.iter().find(|i| i.name == name).expect(name)
+}
+
+#[test]
+fn global_initializers_are_flattened_and_padded() {
+    let inits = inits(
+        "globals.sy",
+        "int a[2][3] = {1, 2, 3, 4};\nint z[2];\nfloat f[3] = {1, 2};\nint main() { return 0; }\n",
+    );
+    let a = find(&inits, "a");
+    assert_eq!(a.scope, Scope::Global);
+    assert_eq!(a.dims, [2, 3]);
+    let ints: Vec<ConstValue> = [1, 2, 3, 4, 0, 0].map(ConstValue::Int).into();
+    assert_eq!(a.flat_values, ints);
+    assert!(a.has_dynamic_parts.is_empty());
+    // 没有初始化的全局数组全为0.
+    assert_eq!(find(&inits, "z").flat_values, [ConstValue::Int(0); 2]);
+    let floats: Vec<ConstValue> = [1.0, 2.0, 0.0].map(ConstValue::Float).into();
+    assert_eq!(find(&inits, "f").flat_values, floats);
+}
+
+#[test]
+fn runtime_parts_of_local_initializers_are_recorded() {
+    let annotated = check(
+        "locals.sy",
+        "int main() {\n  int x = 2;\n  int b[4] = {1, x, 3};\n  int u[2];\n  return b[0];\n}\n",
+    );
+    let inits = global_inits(&annotated);
+    let b = find(&inits, "b");
+    assert_eq!(b.scope, Scope::Local);
+    let values: Vec<ConstValue> = [1, 0, 3, 0].map(ConstValue::Int).into();
+    assert_eq!(b.flat_values, values);
+    let [(offset, expr)] = b.has_dynamic_parts[..] else {
+        panic!("{:?}", b.has_dynamic_parts);
+    };
+    assert_eq!(offset, 1);
+    // 记录的编号就是语义AST中的初始化表达式`x`.
+    let nodes = node_map(&annotated);
+    assert!(matches!(&nodes[&expr].node_type, NodeType::Access(name, ..) if name == "x"));
+    assert!(matches!(&nodes[&b.decl].node_type, NodeType::Decl(_, name, ..) if name == "b"));
+    // 没有初始化的局部变量不在结果中.
+    assert!(inits.iter().all(|i| i.name != "u"));
+}
+
+#[test]
+fn declarations_inside_switch_cases_are_collected() {
+    let options = CompileOptions {
+        level: LangLevel::Extended,
+        ..Default::default()
+    };
+    let source = "int main() {\n  int x = 1;\n  switch (x) {\n    case 1: {\n      int inner[3] = {1, x};\n      return inner[0];\n    }\n    default:\n      int other = 4;\n      return other;\n  }\n  return 0;\n}\n";
+    let session = SourceSession::new("global_inits", "switch.sy", source, options);
+    let checked = session.check().unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    let inits = global_inits(&checked.annotated_ast);
+    let inner = find(&inits, "inner");
+    let values: Vec<ConstValue> = [1, 0, 0].map(ConstValue::Int).into();
+    assert_eq!(inner.flat_values, values);
+    assert_eq!(inner.has_dynamic_parts.len(), 1);
+    assert_eq!(find(&inits, "other").flat_values, [ConstValue::Int(4)]);
+}
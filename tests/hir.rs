@@ -0,0 +1,136 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::hir::{ExprKind, Hir, ItemKind, StmtKind, SymbolKind};
+use sysy_alpha::session::{CompileOptions, Session};
+use sysy_alpha::{BasicType, Scope};
+
+/*
+    HIR: 对变量和函数的引用是符号编号, 同一个声明的所有引用指向同一个符号;
+    检查器的语义AST和to_nodes转换出的语义AST建立的HIR都与session中的相同.
+*/
+
+fn check(name: &str, source: &str) -> sysy_alpha::session::Checked {
+    let dir = TempDir::new("hir");
+    let path = dir.write(name, source);
+    let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    checked
+}
+
+const SOURCE: &str = "int a[2][3];
+int f(int x, int y[]) { return x + y[0]; }
+int main() {
+  int x = getint();
+  a[1][2] = f(x, a[0]);
+  putint(a[1][2]);
+  return x;
+}
+";
+
+#[test]
+fn references_resolve_to_symbols() {
+    let hir = check("symbols.sy", SOURCE).hir;
+    let ItemKind::Globals(globals) = &hir.items[0].kind else {
+        panic!("the first item declares a")
+    };
+    let a = hir.symbol(globals[0].symbol);
+    assert_eq!(a.name, "a");
+    assert_eq!(a.ty, BasicType::IntArray(vec![2, 3]));
+    assert_eq!(a.kind, SymbolKind::Variable(Scope::Global));
+
+    let ItemKind::Func(f) = &hir.items[1].kind else {
+        panic!("the second item defines f")
+    };
+    let SymbolKind::Function {
+        ret,
+        params,
+        prototype,
+    } = &hir.symbol(f.symbol).kind
+    else {
+        panic!("f is a function")
+    };
+    assert_eq!(*ret, BasicType::Int);
+    assert!(!prototype);
+    assert_eq!(hir.symbol(params[1]).ty, BasicType::IntArray(vec![0]));
+
+    // main中的赋值写a, 调用的是f; getint和putint是运行时库函数.
+    let ItemKind::Func(main) = &hir.items[2].kind else {
+        panic!("the third item defines main")
+    };
+    let Some(StmtKind::Block(stmts)) = main.body.as_ref().map(|b| &b.kind) else {
+        panic!("main has a body")
+    };
+    let StmtKind::Assign(assign) = &stmts[1].kind else {
+        panic!("the second statement assigns a")
+    };
+    assert_eq!(assign.target, globals[0].symbol);
+    let ExprKind::Call(callee, args) = &assign.value.kind else {
+        panic!("the value is a call")
+    };
+    assert_eq!(*callee, f.symbol);
+    assert_eq!(assign.value.ty, BasicType::Int);
+    assert!(matches!(args[1].kind, ExprKind::Var(s, _) if s == globals[0].symbol));
+    assert_eq!(args[1].ty, BasicType::IntArray(vec![3]));
+
+    let runtime: Vec<_> = hir
+        .symbols
+        .iter()
+        .filter(|s| s.span.is_empty() && matches!(s.kind, SymbolKind::Function { .. }))
+        .map(|s| s.name.as_str())
+        .collect();
+    assert_eq!(runtime, vec!["getint", "putint"]);
+}
+
+#[test]
+fn converted_nodes_give_the_same_hir() {
+    let checked = check("round_trip.sy", SOURCE);
+    assert!(Hir::from_nodes(&checked.annotated_ast) == checked.hir);
+    assert!(Hir::from_nodes(&checked.hir.to_nodes()) == checked.hir);
+}
+
+#[test]
+fn nested_variables_get_unique_names() {
+    let hir = check(
+        "unique.sy",
+        "int i;
+int f(int i) { return i; }
+int main() {
+  int i = 0;
+  while (i < 2) {
+    int i = 1;
+    putint(i);
+  }
+  return f(i);
+}
+",
+    )
+    .hir;
+    let is = |name: &str| -> Vec<String> {
+        hir.symbols
+            .iter()
+            .filter(|s| s.name == name)
+            .map(|s| s.unique_name.clone())
+            .collect()
+    };
+    assert_eq!(is("i"), vec!["i", "i.1", "i.2", "i.3"]);
+    // 每个Access指向它所在作用域中的那个i.
+    let ItemKind::Func(main) = &hir.items[2].kind else {
+        panic!("the third item defines main")
+    };
+    let Some(StmtKind::Block(stmts)) = main.body.as_ref().map(|b| &b.kind) else {
+        panic!("main has a body")
+    };
+    let StmtKind::While(cond, _) = &stmts[1].kind else {
+        panic!("the second statement is a loop")
+    };
+    let ExprKind::Binary(_, lhs, _) = &cond.kind else {
+        panic!("the condition is a comparison")
+    };
+    let ExprKind::Var(outer, None) = lhs.kind else {
+        panic!("the comparison reads i")
+    };
+    assert_eq!(hir.symbol(outer).unique_name, "i.2");
+}
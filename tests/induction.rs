@@ -0,0 +1,173 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::cfg::{Cfg, Dominators};
+use sysy_alpha::induction::Inductions;
+use sysy_alpha::interp;
+use sysy_alpha::ir::{BinOp, BlockId, Function, InstKind, Module, Operand, Value};
+use sysy_alpha::loops::LoopForest;
+use sysy_alpha::lower::lower;
+use sysy_alpha::mem2reg::promote_module;
+use sysy_alpha::session::{CompileOptions, Session};
+use sysy_alpha::strength;
+
+/*
+    归纳变量: 基本和派生归纳变量的闭式, 循环次数, 以及强度削弱对派生归纳变量的处理.
+*/
+
+fn compile(name: &str, source: &str) -> Module {
+    let dir = TempDir::new("induction");
+    let path = dir.write(name, source);
+    let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    let mut module = lower(&checked.hir);
+    promote_module(&mut module);
+    module
+}
+
+fn analyze(function: &Function) -> (LoopForest, Inductions) {
+    let cfg = Cfg::build(function);
+    let loops = LoopForest::compute(&cfg, &Dominators::compute(&cfg));
+    let inductions = Inductions::compute(function, &loops);
+    (loops, inductions)
+}
+
+/* 函数中第一条op指令的结果. */
+fn first(function: &Function, wanted: BinOp) -> Value {
+    function
+        .blocks
+        .iter()
+        .flat_map(|b| &b.insts)
+        .find(|i| matches!(i.kind, InstKind::Binary(op, ..) if op == wanted))
+        .and_then(|i| i.dest)
+        .unwrap()
+}
+
+#[test]
+fn basic_and_derived_variables_have_closed_forms() {
+    let module = compile(
+        "forms.sy",
+        "int a[100];
+int main() {
+  int i = 3, s = 0, t = 0;
+  while (i < 50) {
+    a[(i * 4 - 1) * 2] = s;
+    s = s + a[i];
+    t = t + 1 + 2;
+    i = i + 5;
+  }
+  return s + t;
+}
+",
+    );
+    let main = module.function("main").unwrap();
+    let (loops, ivs) = analyze(main);
+    // i和t是基本归纳变量, s不是.
+    let basics = ivs.of_loop(0);
+    assert_eq!(basics.len(), 2);
+    let steps: Vec<i32> = basics.iter().map(|v| ivs.basic(*v).unwrap().step).collect();
+    assert!(steps.contains(&5) && steps.contains(&3), "{:?}", steps);
+
+    let i = *basics
+        .iter()
+        .find(|v| ivs.basic(**v).unwrap().step == 5)
+        .unwrap();
+    let form = ivs.closed_form(i).unwrap();
+    assert_eq!(form.init, Operand::Int(3));
+    assert_eq!((form.at(0), form.at(2)), (Some(3), Some(13)));
+
+    // (i * 4 - 1) * 2 = 8i - 2
+    let scaled = first(main, BinOp::Mul);
+    let outer = main
+        .blocks
+        .iter()
+        .flat_map(|b| &b.insts)
+        .find(|inst| {
+            matches!(&inst.kind, InstKind::Binary(BinOp::Mul, Operand::Value(v), _)
+                if ivs.affine(*v).is_some_and(|a| a.offset == -1))
+        })
+        .and_then(|inst| inst.dest)
+        .unwrap();
+    assert_eq!(ivs.affine(scaled).unwrap().scale, 4);
+    let affine = ivs.affine(outer).unwrap();
+    assert_eq!((affine.basic, affine.scale, affine.offset), (i, 8, -2));
+    let form = ivs.closed_form(outer).unwrap();
+    assert_eq!(
+        (form.at(0), form.at(1), form.step),
+        (Some(22), Some(62), 40)
+    );
+
+    // 读数组的值不是归纳变量.
+    let load = main
+        .blocks
+        .iter()
+        .flat_map(|b| &b.insts)
+        .find(|i| matches!(i.kind, InstKind::Load(_)))
+        .and_then(|i| i.dest)
+        .unwrap();
+    assert_eq!(ivs.affine(load), None);
+
+    // 3, 8, ..., 48共10次.
+    assert_eq!(ivs.trip_count(main, &loops, 0), Some(10));
+}
+
+#[test]
+fn trip_counts() {
+    let cases = [
+        ("int i = 0; while (i < 10) i = i + 1;", Some(10)),
+        ("int i = 0; while (i <= 10) i = i + 3;", Some(4)),
+        ("int i = 20; while (i > 0) i = i - 7;", Some(3)),
+        ("int i = 20; while (0 < i) i = i - 7;", Some(3)),
+        ("int i = 10; while (i >= 10) i = i - 1;", Some(1)),
+        ("int i = 0; while (i != 12) i = i + 4;", Some(3)),
+        ("int i = 5; while (i < 5) i = i + 1;", Some(0)),
+        // 初值不是常数, 条件的方向与步长相反(要回绕才停止), 或者有别的出口时都算不出来.
+        ("int i = getint(); while (i < 10) i = i + 1;", None),
+        ("int i = 0; while (i < 10) i = i - 1;", None),
+        ("int i = 0; while (i != 7) i = i + 2;", None),
+        ("int i = 0; while (i < 2147483647) i = i + 2;", None),
+        (
+            "int i = 0; while (i < 10) { if (i == getint()) break; i = i + 1; }",
+            None,
+        ),
+    ];
+    for (body, expected) in cases {
+        let module = compile("trip.sy", &format!("int main() {{ {} return 0; }}\n", body));
+        let main = module.function("main").unwrap();
+        let (loops, ivs) = analyze(main);
+        assert_eq!(loops.loops.len(), 1, "{}", body);
+        assert_eq!(ivs.trip_count(main, &loops, 0), expected, "{}", body);
+    }
+}
+
+#[test]
+fn derived_multiplications_are_reduced() {
+    let source = "int main() {
+  int n = getint(), i = getint(), s = 0;
+  while (i < n) {
+    s = s + (i + 1) * 12 + (i * 2 + 3) * 5;
+    i = i + 1;
+  }
+  putint(s);
+  return 0;
+}
+";
+    let mut module = compile("derived.sy", source);
+    let expected = interp::run(&module, b"20 3").unwrap().output;
+    assert!(strength::run_module(&mut module));
+    module.validate().unwrap();
+    let main = module.function("main").unwrap();
+    let (loops, _) = analyze(main);
+    let in_loop = main
+        .blocks
+        .iter()
+        .enumerate()
+        .filter(|(b, _)| loops.loops[0].contains(BlockId(*b as u32)))
+        .flat_map(|(_, b)| &b.insts)
+        .filter(|i| matches!(i.kind, InstKind::Binary(BinOp::Mul | BinOp::Shl, ..)))
+        .count();
+    assert_eq!(in_loop, 0, "{}", main);
+    assert_eq!(interp::run(&module, b"20 3").unwrap().output, expected);
+}
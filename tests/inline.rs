@@ -0,0 +1,100 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::inline;
+use sysy_alpha::ir::{InstKind, Module};
+use sysy_alpha::lower::lower;
+use sysy_alpha::mem2reg::promote_module;
+use sysy_alpha::session::{CompileOptions, Session};
+
+/*
+    函数内联: 小函数和只调用一次的函数被展开, 递归函数保留调用.
+*/
+
+fn compile(name: &str, source: &str) -> Module {
+    let dir = TempDir::new("inline");
+    let path = dir.write(name, source);
+    let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    let mut module = lower(&checked.hir);
+    promote_module(&mut module);
+    module
+}
+
+fn calls(module: &Module, function: &str) -> Vec<String> {
+    module
+        .function(function)
+        .unwrap()
+        .blocks
+        .iter()
+        .flat_map(|b| &b.insts)
+        .filter_map(|i| match &i.kind {
+            InstKind::Call(name, _) => Some(name.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn small_helpers_are_inlined_into_loops() {
+    let mut module = compile(
+        "small.sy",
+        "int sq(int x) { return x * x; }
+int clamp(int x, int hi) { if (x > hi) return hi; return x; }
+int main() {
+  int i = 0, s = 0;
+  while (i < 10) { s = s + clamp(sq(i), 50); i = i + 1; }
+  putint(s);
+  return 0;
+}
+",
+    );
+    assert!(inline::run_module(&mut module, inline::DEFAULT_BUDGET));
+    module
+        .validate()
+        .unwrap_or_else(|e| panic!("{}\n{}", e, module));
+    assert_eq!(calls(&module, "main"), ["putint"]);
+    // 内联后不再被调用的函数被删除.
+    assert!(module.function("sq").is_none());
+    assert!(module.function("clamp").is_none());
+    // clamp有两个ret, 返回值由phi合并.
+    let text = module.to_string();
+    assert!(text.contains("phi i32"), "{}", text);
+}
+
+#[test]
+fn recursion_and_budget_are_respected() {
+    let mut module = compile(
+        "recursive.sy",
+        "int even(int n);
+int odd(int n) { if (n == 0) return 0; return even(n - 1); }
+int even(int n) { if (n == 0) return 1; return odd(n - 1); }
+int fact(int n) { if (n < 2) return 1; return n * fact(n - 1); }
+int big(int a[]) { a[0] = 1; a[1] = 2; a[2] = 3; return a[0] + a[1] + a[2]; }
+int main() {
+  int a[3];
+  putint(odd(7) + fact(5));
+  putint(big(a));
+  putint(big(a));
+  return 0;
+}
+",
+    );
+    assert!(!inline::run_module(&mut module, 4));
+    assert_eq!(
+        calls(&module, "main"),
+        ["odd", "fact", "putint", "big", "putint", "big", "putint"]
+    );
+    // 预算足够时big被内联, 两处调用各有一份自己的局部变量和块.
+    assert!(inline::run_module(&mut module, inline::DEFAULT_BUDGET));
+    module
+        .validate()
+        .unwrap_or_else(|e| panic!("{}\n{}", e, module));
+    assert_eq!(
+        calls(&module, "main"),
+        ["odd", "fact", "putint", "putint", "putint"]
+    );
+    assert_eq!(calls(&module, "fact"), ["fact"]);
+}
@@ -0,0 +1,299 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::interp::{self, hex_float, Limits, Outcome};
+use sysy_alpha::ir::Module;
+use sysy_alpha::lower::lower;
+use sysy_alpha::passes::{OptLevel, Pass, PassManager};
+use sysy_alpha::session::{CompileOptions, Session};
+use sysy_alpha::utils::FloatFormat;
+use sysy_alpha::verify::verify_module;
+
+/*
+    解释器: 运行时库的输入输出与libsysy一致, 每个pass和每个优化级别前后程序的输出相同(差分测试).
+    设置SYSY_FUNCTIONAL_TESTS为官方功能测试的目录(x.sy, 可选的x.in, x.out)时, 同样检查其中的每个程序.
+*/
+
+fn compile(path: &std::path::Path) -> Module {
+    let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    lower(&checked.hir)
+}
+
+fn compile_source(name: &str, source: &str) -> Module {
+    let dir = TempDir::new("interp");
+    let path = dir.write(name, source);
+    compile(&path)
+}
+
+fn run(source: &str, input: &str) -> Outcome {
+    let module = compile_source("run.sy", source);
+    interp::run(&module, input.as_bytes()).unwrap_or_else(|e| panic!("{}\n{}", e, module))
+}
+
+fn output(outcome: &Outcome) -> String {
+    String::from_utf8(outcome.output.clone()).unwrap()
+}
+
+/* 在未优化的模块上运行, 再在每个pass(以mem2reg之后的模块为起点)和每个优化级别之后运行, 结果都应相同. */
+fn check_passes(name: &str, module: &Module, input: &[u8]) -> Outcome {
+    let expected = interp::run(module, input).unwrap_or_else(|e| panic!("{}: {}", name, e));
+    let mut promoted = module.clone();
+    Pass::Mem2Reg.run(&mut promoted);
+    let mut variants = vec![("mem2reg".to_string(), promoted.clone())];
+    for pass in Pass::ALL {
+        let mut optimized = promoted.clone();
+        pass.run(&mut optimized);
+        variants.push((pass.name().to_string(), optimized));
+    }
+    for level in [OptLevel::O1, OptLevel::O2] {
+        let mut optimized = module.clone();
+        // 流水线中每个pass之后的模块都要通过--verify的检查.
+        PassManager::for_level(level).run_with(&mut optimized, |pass, m| {
+            if let Some(error) = verify_module(pass.name(), m).first() {
+                panic!("{} at {:?}: {}\n{}", name, level, error.message, m);
+            }
+        });
+        variants.push((format!("{:?}", level), optimized));
+    }
+    for (variant, optimized) in variants {
+        if let Some(error) = verify_module(&variant, &optimized).first() {
+            panic!("{}: {}\n{}", name, error.message, optimized);
+        }
+        let actual = interp::run(&optimized, input)
+            .unwrap_or_else(|e| panic!("{} after {}: {}", name, variant, e));
+        // 与评测机相同, 浮点数的输出在FloatFormat的误差内即可.
+        assert_eq!(
+            actual.exit_code, expected.exit_code,
+            "{} after {}",
+            name, variant
+        );
+        assert!(
+            FloatFormat::default().outputs_match(&output(&actual), &output(&expected)),
+            "{} after {}:\n{}\nexpected:\n{}",
+            name,
+            variant,
+            output(&actual),
+            output(&expected)
+        );
+    }
+    expected
+}
+
+#[test]
+fn runtime_library_matches_libsysy() {
+    let outcome = run(
+        "int a[4] = {1, 2};
+int main() {
+  int n = getint();
+  float x = getfloat();
+  int c = getch();
+  int b[8];
+  int k = getarray(b);
+  putint(n); putch(32); putch(c); putch(10);
+  putfloat(x); putch(10);
+  putarray(k, b);
+  putarray(4, a);
+  return n + 256;
+}
+",
+        "  -12 0x1.8p1\n4\t3 4 5 6",
+    );
+    assert_eq!(outcome.exit_code, 244);
+    assert_eq!(
+        output(&outcome),
+        "-12 \n\n0x1.8p+1\n4: 3 4 5 6\n4: 1 2 0 0\n"
+    );
+    assert_eq!(
+        outcome.expected_file(),
+        b"-12 \n\n0x1.8p+1\n4: 3 4 5 6\n4: 1 2 0 0\n244\n"
+    );
+}
+
+#[test]
+fn putf_formats_like_printf() {
+    let outcome = run(
+        "int main() {
+  float f = 2.5;
+  putf(\"%d|%5d|%-4d|%05d|%c|%x|%%\\n\", -7, 42, 3, 12, 65, 255);
+  putf(\"%f|%.2f|%e|%g|%g|%a\\n\", f, f, f, f, 0.0001, f);
+  return 0;
+}
+",
+        "",
+    );
+    assert_eq!(
+        output(&outcome),
+        "-7|   42|3   |00012|A|ff|%\n2.500000|2.50|2.500000e+00|2.5|0.0001|0x1.4p+1\n"
+    );
+}
+
+#[test]
+fn putf_prints_infinities_like_float_format() {
+    let outcome = run(
+        "int main() {
+  float z = getfloat(), inf = 1 / z;
+  putf(\"%f|%.2f|%F|%e|%g|%8f\\n\", inf, -inf, inf, -inf, inf, -inf);
+  return 0;
+}
+",
+        "0x0p+0",
+    );
+    assert_eq!(output(&outcome), "inf|-inf|INF|-inf|inf|    -inf\n");
+    assert_eq!(FloatFormat::default().format(f32::NEG_INFINITY), "-inf");
+}
+
+#[test]
+fn hex_floats_match_glibc() {
+    assert_eq!(hex_float(0.0), "0x0p+0");
+    assert_eq!(hex_float(-0.0), "-0x0p+0");
+    assert_eq!(hex_float(1.0), "0x1p+0");
+    assert_eq!(hex_float(0.1f32 as f64), "0x1.99999ap-4");
+    assert_eq!(hex_float(-1e10f32 as f64), "-0x1.2a05f2p+33");
+    assert_eq!(hex_float(f64::INFINITY), "inf");
+}
+
+#[test]
+fn runtime_errors_are_reported() {
+    let module = compile_source("div.sy", "int main() { int z = getint(); return 1 / z; }\n");
+    let error = interp::run(&module, b"0").unwrap_err();
+    assert!(error.contains("division by zero"), "{}", error);
+
+    let module = compile_source(
+        "bounds.sy",
+        "int a[2];\nint main() { int i = getint(); return a[i * 100000000]; }\n",
+    );
+    let error = interp::run(&module, b"5").unwrap_err();
+    assert!(error.contains("out-of-bounds"), "{}", error);
+
+    let module = compile_source("forever.sy", "int main() { while (1) {} return 0; }\n");
+    let limits = Limits {
+        steps: Some(1000),
+        ..Limits::default()
+    };
+    let error = interp::run_with(&module, b"", &limits).unwrap_err();
+    assert!(error.contains("step limit"), "{}", error);
+}
+
+#[test]
+fn deep_recursion_does_not_overflow_the_host_stack() {
+    let outcome = run(
+        "int depth(int n) { if (n == 0) return 0; return depth(n - 1) + 1; }
+int main() { putint(depth(200000)); return 0; }
+",
+        "",
+    );
+    assert_eq!(output(&outcome), "200000");
+}
+
+#[test]
+fn optimizations_preserve_behaviour() {
+    let programs = [
+        (
+            "loops.sy",
+            "const int N = 8;
+int m[N][N];
+int sq(int x) { return x * x; }
+int main() {
+  int i = 0, s = 0;
+  while (i < N) {
+    int j = 0;
+    while (j < N) { m[i][j] = i * N + j; j = j + 1; }
+    s = s + sq(i) * 4 + i / 2 - i % 4;
+    i = i + 1;
+  }
+  putint(s); putch(10);
+  putint(m[3][5] + m[7][7]); putch(10);
+  return s % 256;
+}
+",
+            "",
+        ),
+        (
+            "branches.sy",
+            "int g = 3;
+int pick(int a, int b) { if (a > b && b != 0 || a == 0) return a; else return b; }
+int main() {
+  int n = getint(), k = 0, t = 1;
+  while (k < n) {
+    if (k % 3 == 0) t = t * 2; else if (k % 3 == 1) t = t + g; else t = pick(t, k);
+    if (t > 1000) break;
+    k = k + 1;
+  }
+  putint(t); putch(10);
+  if (1) putint(k);
+  return 0;
+}
+",
+            "20",
+        ),
+        (
+            "floats.sy",
+            "float area(float r) { return 3.14159 * r * r; }
+int main() {
+  float total = 0.0;
+  int i = 1;
+  while (i <= 5) { total = total + area(i) / 2; i = i + 1; }
+  putfloat(total); putch(10);
+  int whole = total;
+  putf(\"%d %f\\n\", whole, total);
+  return 0;
+}
+",
+            "",
+        ),
+        (
+            "arrays.sy",
+            "void fill(int a[], int n, int v) { int i = 0; while (i < n) { a[i] = v + i * 4; i = i + 1; } }
+int sum(int a[][4], int rows) {
+  int s = 0, i = 0;
+  while (i < rows) { int j = 0; while (j < 4) { s = s + a[i][j]; j = j + 1; } i = i + 1; }
+  return s;
+}
+int main() {
+  int b[3][4] = {{1, 2}, {3}};
+  fill(b[1], 4, getint());
+  int x = sum(b, 3);
+  int y = sum(b, 3);
+  putint(x + y);
+  return x;
+}
+",
+            "5",
+        ),
+    ];
+    for (name, source, input) in programs {
+        let module = compile_source(name, source);
+        check_passes(name, &module, input.as_bytes());
+    }
+}
+
+#[test]
+fn official_functional_tests() {
+    let Ok(dir) = std::env::var("SYSY_FUNCTIONAL_TESTS") else {
+        return;
+    };
+    let mut paths: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|e| e == "sy"))
+        .collect();
+    paths.sort();
+    for path in paths {
+        let name = path.display().to_string();
+        let input = std::fs::read(path.with_extension("in")).unwrap_or_default();
+        let outcome = check_passes(&name, &compile(&path), &input);
+        if let Ok(expected) = std::fs::read(path.with_extension("out")) {
+            // 按空白切分比较, .out文件的结尾可能没有换行.
+            let text = |s: &[u8]| String::from_utf8_lossy(s).into_owned();
+            assert!(
+                FloatFormat::default()
+                    .outputs_match(&text(&outcome.expected_file()), &text(&expected)),
+                "{}",
+                name
+            );
+        }
+    }
+}
@@ -0,0 +1,217 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::ir::{InstKind, Module, Operand};
+use sysy_alpha::lexer::LangLevel;
+use sysy_alpha::lower::lower;
+use sysy_alpha::session::{CompileOptions, Session};
+
+/*
+    三地址码: 由HIR生成, 结构满足validate的检查; 文本形式是稳定的, 值和块按出现的顺序编号.
+*/
+
+fn lowered(name: &str, source: &str) -> Module {
+    let dir = TempDir::new("ir");
+    let path = dir.write(name, source);
+    let options = CompileOptions {
+        level: LangLevel::Extended,
+        ..Default::default()
+    };
+    let checked = Session::new(path.to_string_lossy(), options)
+        .check()
+        .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    let module = lower(&checked.hir);
+    module
+        .validate()
+        .unwrap_or_else(|e| panic!("{}\n{}", e, module));
+    module
+}
+
+#[test]
+fn text_dump_is_stable() {
+    let module = lowered(
+        "small.sy",
+        "int g = 3;
+int add(int a, int b) { return a + b; }
+int main() {
+  int x = add(g, 4);
+  if (x > 5) putint(x);
+  return 0;
+}
+",
+    );
+    let expected = "global @g: i32 x 1 = [3]
+
+fn i32 @add(%0: i32, %1: i32) {
+bb0:
+  %2 = alloca i32 x 1 ; a
+  %3 = alloca i32 x 1 ; b
+  store %0, %2
+  store %1, %3
+  %4 = load i32 %2
+  %5 = load i32 %3
+  %6 = add i32 %4, %5
+  ret %6
+}
+
+fn i32 @main() {
+bb0:
+  %0 = alloca i32 x 1 ; x
+  %1 = load i32 @g
+  %2 = call i32 @add(%1, 4)
+  store %2, %0
+  %3 = load i32 %0
+  %4 = icmp gt %3, 5
+  br %4, bb1, bb2
+bb1:
+  %5 = load i32 %0
+  call void @putint(%5)
+  jump bb2
+bb2:
+  ret 0
+}
+
+declare void @putint(i32)
+";
+    assert_eq!(module.to_string(), expected);
+}
+
+#[test]
+fn lowering_covers_the_language() {
+    let module = lowered(
+        "full.sy",
+        "const int N = 4;
+int g[N] = {1, 2};
+float scale = 1.5;
+int sum(int a[], int n) {
+  int i = 0, s = 0;
+  while (i < n) {
+    if (a[i] > 0 && a[i] < 100 || !n) s = s + a[i];
+    else { i = i + 1; continue; }
+    i = i + 1;
+  }
+  return s;
+}
+void nothing() { return; }
+int main() {
+  int b[2][3] = {{1, 2, 3}, {4}};
+  int big[20] = {1};
+  float f = scale * 2;
+  int k = getint();
+  int c = k > 0 ? k : -k;
+  int t = (k < 5 ? c : 1) + !k;
+  switch (k) { case 1: c = 2; case 2: c = 3; break; default: c = 4; }
+  while (1) { if (c) break; c = c + 1; }
+  nothing();
+  putf(\"%d %f\\n\", sum(b[1], 3) + sum(g, N), f);
+  putint(big[k] + c + t);
+  return 0;
+}
+",
+    );
+    assert_eq!(module.strings, vec!["%d %f\n".to_string()]);
+    assert!(module.global("N").unwrap().constant);
+    assert_eq!(module.global("g").unwrap().len, 4);
+    assert!(module.function("putf").unwrap().variadic);
+    assert!(module.function("getint").unwrap().is_declaration());
+    let main = module.function("main").unwrap();
+    let insts = || main.blocks.iter().flat_map(|b| &b.insts);
+    // 两个?:各合并出一个phi, 大数组的清零循环也用phi计数.
+    assert_eq!(
+        insts()
+            .filter(|i| matches!(i.kind, InstKind::Phi(_)))
+            .count(),
+        3
+    );
+    assert!(insts().any(|i| matches!(i.kind, InstKind::Fcmp(..) | InstKind::Binary(..))));
+    // 所有alloca都在入口块.
+    assert!(main.blocks[1..]
+        .iter()
+        .flat_map(|b| &b.insts)
+        .all(|i| !matches!(i.kind, InstKind::Alloca { .. })));
+    let nothing = module.function("nothing").unwrap();
+    assert_eq!(nothing.blocks.len(), 1);
+}
+
+#[test]
+fn code_after_return_is_dropped() {
+    let module = lowered(
+        "dead.sy",
+        "int main() {
+  return 1;
+  putint(2);
+  while (1) {}
+}
+",
+    );
+    let main = module.function("main").unwrap();
+    assert_eq!(main.blocks.len(), 1);
+    assert!(module.function("putint").is_none());
+}
+
+#[test]
+fn local_const_arrays_become_global_constants() {
+    let module = lowered(
+        "consts.sy",
+        "int main() {
+  const int table[2][3] = {{1, 2}, {3}};
+  int copy[2] = {table[0][1], 0};
+  return table[1][0] + copy[0];
+}
+",
+    );
+    let global = module.global("main.table").unwrap();
+    assert!(global.constant);
+    assert_eq!(global.len, 6);
+    assert_eq!(
+        global.init[..3],
+        [Operand::Int(1), Operand::Int(2), Operand::Int(0)]
+    );
+    // 只有copy还在栈上.
+    let main = module.function("main").unwrap();
+    let allocas: Vec<&str> = main.blocks[0]
+        .insts
+        .iter()
+        .filter_map(|i| match &i.kind {
+            InstKind::Alloca { name, .. } => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(allocas, ["copy"]);
+    assert_eq!(sysy_alpha::interp::run(&module, b"").unwrap().exit_code, 5);
+}
+
+#[test]
+fn local_const_float_arrays_become_global_constants() {
+    let module = lowered(
+        "float_consts.sy",
+        "int main() {
+  const float w[3] = {0.5, 1.5};
+  int i = getint();
+  return w[i] * 4;
+}
+",
+    );
+    let global = module.global("main.w").unwrap();
+    assert!(global.constant);
+    assert_eq!(
+        global.init,
+        [
+            Operand::Float(0.5),
+            Operand::Float(1.5),
+            Operand::Float(0.0)
+        ]
+    );
+    let main = module.function("main").unwrap();
+    let allocas: Vec<&str> = main.blocks[0]
+        .insts
+        .iter()
+        .filter_map(|i| match &i.kind {
+            InstKind::Alloca { name, .. } => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(allocas, ["i"]);
+    assert_eq!(sysy_alpha::interp::run(&module, b"1").unwrap().exit_code, 6);
+}
@@ -0,0 +1,122 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::codegen::koopa;
+use sysy_alpha::lower::lower;
+use sysy_alpha::session::{CompileOptions, Session};
+
+/*
+    Koopa IR后端: 局部变量是alloc出的内存, phi变成块参数; 用到float或putf的程序无法翻译.
+*/
+
+fn emit(name: &str, source: &str) -> Result<String, String> {
+    let dir = TempDir::new("koopa");
+    let path = dir.write(name, source);
+    let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    koopa::emit(&lower(&checked.hir))
+}
+
+#[test]
+fn text_dump() {
+    let koopa = emit(
+        "small.sy",
+        "int g[2] = {1};
+int main() {
+  int x = getint();
+  if (x > 0 && x < 10) x = g[0];
+  putint(x);
+  return 0;
+}
+",
+    )
+    .unwrap();
+    let expected = "decl @getint(): i32
+decl @putint(i32)
+
+global @g = alloc [i32, 2], {1, 0}
+
+fun @main(): i32 {
+%bb0:
+  %v0 = alloc i32
+  %v1 = call @getint()
+  store %v1, %v0
+  %v2 = load %v0
+  %v3 = gt %v2, 0
+  br %v3, %bb3, %bb2
+%bb1:
+  %t1 = getelemptr @g, 0
+  %v4 = getptr %t1, 0
+  %v5 = load %v4
+  store %v5, %v0
+  jump %bb2
+%bb2:
+  %v6 = load %v0
+  call @putint(%v6)
+  ret 0
+%bb3:
+  %v7 = load %v0
+  %v8 = lt %v7, 10
+  br %v8, %bb1, %bb2
+}
+";
+    assert_eq!(koopa, expected);
+}
+
+#[test]
+fn phis_become_block_arguments() {
+    let koopa = emit(
+        "fill.sy",
+        "int main() {
+  int a[2][10] = {{1}, {2}};
+  return a[1][0];
+}
+",
+    )
+    .unwrap();
+    assert!(koopa.contains("%bb1(%v1: i32):"), "{}", koopa);
+    assert!(koopa.contains("jump %bb1(0)"), "{}", koopa);
+    assert!(koopa.contains("jump %bb1(%v4)"), "{}", koopa);
+    assert!(koopa.contains("%v6 = getptr %t3, 10"), "{}", koopa);
+    assert!(!koopa.contains("phi"));
+}
+
+#[test]
+fn floats_and_putf_are_rejected() {
+    let error = emit("float.sy", "float x = 1.5;\nint main() { return 0; }\n").unwrap_err();
+    assert!(error.contains("float"), "{}", error);
+    let error = emit("putf.sy", "int main() { putf(\"%d\\n\", 1); return 0; }\n").unwrap_err();
+    assert!(error.contains("putf"), "{}", error);
+}
+
+#[test]
+fn many_arguments_and_array_parameters() {
+    let koopa = emit(
+        "calls.sy",
+        "int g[3][4];
+int sum10(int a0, int a1, int a2, int a3, int a4, int a5, int a6, int a7, int a8, int a9) {
+  return a0 + a8 * 9 + a9;
+}
+int row(int r[], int n) { return r[n - 1]; }
+int mat(int m[][4], int k) { return row(m[k], 4) + m[k - 1][3]; }
+int main() { return mat(g, 2) + sum10(1, 2, 3, 4, 5, 6, 7, 8, 9, 10); }
+",
+    )
+    .unwrap();
+    assert!(koopa.contains("fun @sum10(%v0: i32, %v1: i32, %v2: i32, %v3: i32, %v4: i32, %v5: i32, %v6: i32, %v7: i32, %v8: i32, %v9: i32): i32 {"), "{}", koopa);
+    assert!(
+        koopa.contains("call @sum10(1, 2, 3, 4, 5, 6, 7, 8, 9, 10)"),
+        "{}",
+        koopa
+    );
+    // 数组形参是*i32, 传入全局数组时先取首元素的地址, 传入一行时按行的长度偏移.
+    assert!(
+        koopa.contains("fun @mat(%v0: *i32, %v1: i32): i32 {"),
+        "{}",
+        koopa
+    );
+    assert!(koopa.contains("getelemptr @g, 0"), "{}", koopa);
+    assert!(koopa.contains("call @row("), "{}", koopa);
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use common::{SourceFile, SourceSession};
+use sysy_alpha::lexer::{tokenize_with_diagnostics, LexError, LexLimits, LexOptions, LimitKind};
+use sysy_alpha::session::{CompileError, CompileOptions};
+
+/*
+    词法分析的资源上限: 恰好达到上限时正常, 超出任意一项时返回LexError::LimitExceeded,
+    其中记录超出的是哪一项, 上限和超限处的位置; Session把它作为CompileError::Lex返回, 不会panic.
+*/
+
+fn lex(source: &str, limits: LexLimits) -> Result<usize, LexError> {
+    let file = SourceFile::new("lex_limits", "limits.sy", source);
+    let options = LexOptions {
+        limits,
+        ..Default::default()
+    };
+    tokenize_with_diagnostics(file.path_string(), &options).map(|(tokens, _)| tokens.len())
+}
+
+fn exceeded(source: &str, limits: LexLimits) -> (LimitKind, usize, usize) {
+    match lex(source, limits) {
+        Err(LexError::LimitExceeded { kind, max, pos }) => (kind, max, pos),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn each_limit_stops_the_lexer_where_it_is_exceeded() {
+    let limits = LexLimits {
+        max_tokens: 6,
+        max_identifier_len: 4,
+        max_literal_len: 5,
+        max_comment_nesting: 2,
+    };
+    // 恰好达到每一项上限: 6个token, 4个字符的标识符, 5个字符的字面量, 两层注释开头.
+    assert_eq!(lex("int abcd = -123.5; /* /* */", limits).unwrap(), 6);
+
+    assert_eq!(exceeded("int a = 1; int b;", limits).0, LimitKind::Tokens);
+    assert_eq!(
+        exceeded("int abcde;", limits),
+        (LimitKind::IdentifierLength, 4, 4)
+    );
+    assert_eq!(
+        exceeded("a = 123456;", limits),
+        (LimitKind::LiteralLength, 5, 4)
+    );
+    assert_eq!(
+        exceeded("putf(\"%d%d\\n\");", limits).0,
+        LimitKind::LiteralLength
+    );
+    assert_eq!(
+        exceeded("/* /* /* */ int a;", limits),
+        (LimitKind::CommentNesting, 2, 6)
+    );
+}
+
+#[test]
+fn exceeded_limits_are_reported_through_the_session() {
+    let options = CompileOptions {
+        limits: LexLimits {
+            max_identifier_len: 8,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let session = SourceSession::new(
+        "lex_limits",
+        "session.sy",
+        "int main() { int very_long_name = 0; return 0; }\n",
+        options,
+    );
+    let Err(CompileError::Lex(error)) = session.check() else {
+        panic!("expected a lexer error");
+    };
+    assert_eq!(
+        error.to_string(),
+        "lexer limit exceeded: identifier length is larger than 8"
+    );
+}
@@ -0,0 +1,85 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::lexer::{check_span_coverage, try_tokenize, LangLevel, LexOptions};
+use sysy_alpha::utils::detokenize;
+
+/*
+    词法分析器的区间不变式: 保留trivia时, 所有token的span首尾相接,
+    恰好覆盖整个输入, 因此把token原文拼接起来就能得到原始源代码.
+    这里用若干刁钻的输入(包括有词法错误的输入)检查这一性质.
+*/
+
+const CASES: &[&str] = &[
+    "",
+    "   \n\t\n",
+    "int main() { return 0; }\n",
+    "int a = 0x1F + 017 + 0 + 3.25e-2 + .5 + 1.f;\n",
+    "// line comment without newline",
+    "/* block */ int /* nested /* twice */ b;\n",
+    "int c; /* unterminated block comment\n",
+    "#line 10 \"other.sy\"\nint d;\n",
+    "int e = 1 @ 2;\n$`\n",
+    "int f = 09 + 0x;\n",
+    "int m = 10u + 017L + 0x1Ful + 10uz; float n = 1.5f;\n",
+    "int g = a << 2 & b | c ^ ~d;\n",
+    "putf(\"%d\\n\", 'x');\n",
+    "putf(\"unterminated\n);\n",
+    "int h = 'ab';\n",
+    "# not a directive\n",
+    "int i = 1;\r\nint j;\n",
+    "const float k[2] = {1.0, 2e3};\nint l; // 中文注释\n",
+];
+
+fn check(source: &str, level: LangLevel) {
+    let dir = TempDir::new("spans");
+    let path = dir.write("spans.sy", source);
+
+    let options = LexOptions {
+        level,
+        preserve_trivia: true,
+        ..Default::default()
+    };
+    let tokens = try_tokenize(path.to_string_lossy().to_string(), &options).unwrap();
+    let len = source.chars().count();
+    if let Err(e) = check_span_coverage(&tokens, len) {
+        panic!("{:?}: {}", source, e);
+    }
+    assert_eq!(detokenize(&tokens), source, "round-trip of {:?}", source);
+}
+
+#[test]
+fn spans_tile_the_input_in_strict_mode() {
+    for source in CASES {
+        check(source, LangLevel::SysY2022);
+    }
+}
+
+#[test]
+fn spans_tile_the_input_in_extended_mode() {
+    for source in CASES {
+        check(source, LangLevel::Extended);
+    }
+}
+
+#[test]
+fn spans_tile_generated_inputs() {
+    // 用简单的线性同余生成器拼出伪随机输入, 覆盖各种字符的组合.
+    const ALPHABET: &[&str] = &[
+        "int", " ", "\n", "a1", "_", "0", "0x", "7", "9", ".", "e", "+", "-", "*", "/", "%", "<",
+        ">", "=", "!", "&", "|", "^", "~", "(", ")", "{", "}", "[", "]", ";", ",", "\"", "'", "\\",
+        "#", "@", "//", "/*", "*/", "\t",
+    ];
+    let mut seed: u64 = 0x5359_5359;
+    for _ in 0..300 {
+        let mut source = String::new();
+        for _ in 0..40 {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            source.push_str(ALPHABET[(seed >> 33) as usize % ALPHABET.len()]);
+        }
+        check(&source, LangLevel::SysY2022);
+        check(&source, LangLevel::Extended);
+    }
+}
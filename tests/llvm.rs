@@ -0,0 +1,243 @@
+mod common;
+
+use common::TempDir;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use sysy_alpha::codegen::llvm;
+use sysy_alpha::lexer::LangLevel;
+use sysy_alpha::lower::lower;
+use sysy_alpha::mem2reg::promote_module;
+use sysy_alpha::sccp;
+use sysy_alpha::session::{CompileOptions, Session};
+use sysy_alpha::{copyprop, cse, inline, peephole, strength};
+
+/*
+    LLVM IR后端: 生成的.ll文本; 机器上有lli时直接运行, 检查程序的输出和返回值.
+    运行时库在测试中用printf/scanf实现, putf通过vprintf转发变长参数.
+*/
+
+const RUNTIME: &str = r#"
+declare i32 @printf(ptr, ...)
+declare i32 @vprintf(ptr, ptr)
+declare i32 @scanf(ptr, ...)
+declare void @llvm.va_start(ptr)
+declare void @llvm.va_end(ptr)
+@.rt.d = private constant [3 x i8] c"%d\00"
+@.rt.c = private constant [3 x i8] c"%c\00"
+define i32 @getint() {
+  %p = alloca i32
+  store i32 0, ptr %p
+  %r = call i32 (ptr, ...) @scanf(ptr @.rt.d, ptr %p)
+  %v = load i32, ptr %p
+  ret i32 %v
+}
+define void @putint(i32 %x) {
+  %r = call i32 (ptr, ...) @printf(ptr @.rt.d, i32 %x)
+  ret void
+}
+define void @putch(i32 %x) {
+  %r = call i32 (ptr, ...) @printf(ptr @.rt.c, i32 %x)
+  ret void
+}
+define void @putf(ptr %fmt, ...) {
+  %ap = alloca [24 x i8]
+  call void @llvm.va_start(ptr %ap)
+  %r = call i32 @vprintf(ptr %fmt, ptr %ap)
+  call void @llvm.va_end(ptr %ap)
+  ret void
+}
+"#;
+
+/* passes: 0不优化, 1提升局部变量, 2再做常量传播, 3再删除复制和公共子表达式, 4再做强度削弱, 5再内联函数, 6再做窥孔优化. */
+fn emit(name: &str, source: &str, passes: u32) -> String {
+    let dir = TempDir::new("llvm");
+    let path = dir.write(name, source);
+    let options = CompileOptions {
+        level: LangLevel::Extended,
+        ..Default::default()
+    };
+    let checked = Session::new(path.to_string_lossy(), options)
+        .check()
+        .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    let mut module = lower(&checked.hir);
+    if passes >= 1 {
+        promote_module(&mut module);
+    }
+    if passes >= 5 {
+        inline::run_module(&mut module, inline::DEFAULT_BUDGET);
+    }
+    if passes >= 2 {
+        sccp::run_module(&mut module);
+    }
+    if passes >= 3 {
+        copyprop::run_module(&mut module);
+        cse::run_module(&mut module, true);
+    }
+    if passes >= 4 {
+        strength::run_module(&mut module);
+    }
+    if passes >= 6 {
+        peephole::run_module(&mut module);
+    }
+    llvm::emit(&module)
+}
+
+/* 用lli运行, 返回(标准输出, 退出码); 没有lli时返回None. LLVM 15以前需要-opaque-pointers. */
+fn run(name: &str, ll: &str, input: &str) -> Option<(String, i32)> {
+    let program: String = ll
+        .lines()
+        .filter(|l| !l.starts_with("declare"))
+        .map(|l| format!("{}\n", l))
+        .collect::<String>()
+        + RUNTIME;
+    let dir = TempDir::new("llvm");
+    let path = dir.write(name, &program);
+    for flags in [&["-opaque-pointers"][..], &[]] {
+        let Ok(mut child) = Command::new("lli")
+            .args(flags)
+            .arg(&path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+        else {
+            return None;
+        };
+        let mut stdin = child.stdin.take().unwrap();
+        stdin.write_all(input.as_bytes()).unwrap_or_default();
+        drop(stdin);
+        let output = child.wait_with_output().unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("Unknown command line argument") {
+            continue;
+        }
+        assert!(!stderr.contains("error"), "{}\n{}", stderr, program);
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        return Some((stdout, output.status.code().unwrap_or(-1)));
+    }
+    None
+}
+
+#[test]
+fn globals_and_declarations() {
+    let ll = emit(
+        "globals.sy",
+        "const int N = 2;
+int a[N][3] = {{1}, {2, 3}};
+float x = 0.1;
+int z[100];
+int main() {
+  putf(\"%f\\n\", x);
+  return a[1][1];
+}
+",
+        0,
+    );
+    assert!(ll.contains("@N = constant [1 x i32] [i32 2]"));
+    assert!(ll.contains("@a = global [6 x i32] [i32 1, i32 0, i32 0, i32 2, i32 3, i32 0]"));
+    assert!(ll.contains("@x = global [1 x float] [float 0x3FB99999A0000000]"));
+    assert!(ll.contains("@z = global [100 x i32] zeroinitializer"));
+    assert!(ll.contains("@.str.0 = private unnamed_addr constant [4 x i8] c\"%f\\0A\\00\""));
+    assert!(ll.contains("declare void @putf(...)"));
+    // 变长参数中的float提升为double.
+    assert!(ll.contains("fpext float"));
+    assert!(ll.contains("getelementptr [3 x i32], ptr @a, i32 1"));
+}
+
+/* 各个优化前后的程序结果相同. */
+#[test]
+fn programs_run_under_lli() {
+    for passes in 0..=6 {
+        programs_run_under_lli_with(passes);
+    }
+}
+
+fn programs_run_under_lli_with(passes: u32) {
+    let ll = emit(
+        "run.sy",
+        "int fib[20];
+int gcd(int a, int b) { if (b == 0) return a; return gcd(b, a % b); }
+float avg(float v[], int n) {
+  float s = 0; int i = 0;
+  while (i < n) { s = s + v[i]; i = i + 1; }
+  return s / n;
+}
+int main() {
+  int n = getint();
+  fib[0] = 0; fib[1] = 1;
+  int i = 2;
+  while (i < 20) {
+    fib[i] = fib[i - 1] + fib[i - 2];
+    if (fib[i] > 1000 || i > n) break;
+    i = i + 1;
+  }
+  putint(fib[i]); putch(10);
+  putint(gcd(84, 36)); putch(10);
+  float v[4] = {1.5, 2.5, 3, 5};
+  putf(\"%.2f %d\\n\", avg(v, 4), -7 / 2);
+  int k = n > 5 ? n : -n;
+  switch (k) { case 10: putint(1); case 11: putint(2); break; default: putint(3); }
+  putch(10);
+  int m[4][8], r = 0;
+  i = 0;
+  while (i < 4) {
+    int j = 0;
+    while (j < 8) { m[i][j] = i * 8 + j; j = j + 1; }
+    r = r + m[i][i] * 3;
+    i = i + 1;
+  }
+  putint(r); putch(32);
+  putint((0 - n) / 4); putch(32); putint((0 - n) % 8); putch(32); putint(n / -2); putch(32);
+  putint(n * 16 % 64); putch(10);
+  return k % 7;
+}
+",
+        passes,
+    );
+    let Some((stdout, code)) = run("run.ll", &ll, "10") else {
+        return;
+    };
+    assert_eq!(stdout, "89\n12\n3.00 -3\n12\n162 -2 -2 -5 32\n");
+    assert_eq!(code, 3);
+}
+
+/* 超过参数寄存器个数的实参, int和float交错的实参, 数组形参和一行数组作为实参, 各种返回类型. */
+#[test]
+fn many_arguments_run_under_lli() {
+    for passes in [0, 1, 6] {
+        let ll = emit(
+            "calls.sy",
+            "int g[3][4];
+int sum16(int a0, int a1, int a2, int a3, int a4, int a5, int a6, int a7,
+          int a8, int a9, int a10, int a11, int a12, int a13, int a14, int a15) {
+  return a0 + a1 * 2 + a2 * 3 + a8 * 9 + a15 * 16;
+}
+float fsum(float f0, int i0, float f1, float f2, float f3, float f4, float f5, float f6, float f7,
+           float f8, int i1, float f9, float f10, int arr[], float f11, int i2) {
+  return f0 + f1 + f2 + f7 + f8 * 2 + f9 + f10 * 3 + f11 + i0 + i1 * 10 + i2 * 100 + arr[1];
+}
+int row(int r[], int n) { int i = 0, s = 0; while (i < n) { s = s + r[i]; i = i + 1; } return s; }
+int mat(int m[][4], int k) { return row(m[k], 4) + m[k - 1][3]; }
+float half(int x) { return x / 2.0; }
+int trunc(float x) { return x; }
+void fill(int m[][4]) { int i = 0; while (i < 12) { m[i / 4][i % 4] = i; i = i + 1; } }
+int main() {
+  fill(g);
+  putint(sum16(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16)); putch(10);
+  int a[2] = {5, 7};
+  putf(\"%.1f %d %d\\n\", fsum(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, a, 14, 15), mat(g, 2), row(g[1], 4));
+  putf(\"%.1f %d\\n\", half(7), trunc(-3.7));
+  return sum16(g[0][0], g[0][1], g[0][2], g[0][3], g[1][0], g[1][1], g[1][2], g[1][3],
+               g[2][0], g[2][1], g[2][2], g[2][3], 1, 2, 3, 4);
+}
+",
+            passes,
+        );
+        let Some((stdout, code)) = run("calls.ll", &ll, "") else {
+            return;
+        };
+        assert_eq!(stdout, "351\n1721.0 45 22\n3.5 -3\n");
+        assert_eq!(code, 144);
+    }
+}
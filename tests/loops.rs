@@ -0,0 +1,187 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::cfg::{Cfg, Dominators};
+use sysy_alpha::interp;
+use sysy_alpha::ir::{
+    BinOp, BlockId, CmpOp, Function, Inst, InstKind, Module, Operand, Type, Value,
+};
+use sysy_alpha::loops::{self, LoopForest};
+use sysy_alpha::lower::lower;
+use sysy_alpha::mem2reg::promote_module;
+use sysy_alpha::session::{CompileOptions, Session};
+
+/*
+    循环分析: 嵌套循环的头, 回边, 出口和深度, 以及给没有preheader的循环补上preheader.
+*/
+
+fn compile(name: &str, source: &str) -> Module {
+    let dir = TempDir::new("loops");
+    let path = dir.write(name, source);
+    let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    let mut module = lower(&checked.hir);
+    promote_module(&mut module);
+    module
+}
+
+fn loop_forest(function: &Function) -> LoopForest {
+    let cfg = Cfg::build(function);
+    LoopForest::compute(&cfg, &Dominators::compute(&cfg))
+}
+
+#[test]
+fn nested_loops_form_a_forest() {
+    let module = compile(
+        "nested.sy",
+        "int main() {
+  int n = getint(), s = 0, i = 0;
+  while (i < n) {
+    int j = 0;
+    while (j < i) {
+      if (j == 7) break;
+      s = s + j;
+      j = j + 1;
+    }
+    i = i + 1;
+  }
+  while (s > 100) s = s / 2;
+  putint(s);
+  return 0;
+}
+",
+    );
+    let main = module.function("main").unwrap();
+    let forest = loop_forest(main);
+    assert_eq!(forest.loops.len(), 3);
+    assert_eq!(forest.roots().count(), 2);
+    let outer = &forest.loops[0];
+    assert_eq!(outer.depth, 1);
+    assert_eq!(outer.children.len(), 1);
+    assert_eq!(outer.latches.len(), 1);
+    assert_eq!(outer.exits.len(), 1);
+    assert!(outer.preheader.is_some());
+    let inner = &forest.loops[outer.children[0]];
+    assert_eq!(inner.depth, 2);
+    assert_eq!(inner.parent, Some(0));
+    assert!(inner.blocks.iter().all(|b| outer.contains(*b)));
+    // break和条件不成立各有一个出口.
+    assert_eq!(inner.exits.len(), 2);
+    assert_eq!(forest.depth(inner.header), 2);
+    assert_eq!(forest.depth(outer.header), 1);
+    assert_eq!(forest.depth(BlockId(0)), 0);
+    assert_eq!(forest.innermost(inner.header), Some(outer.children[0]));
+
+    let dump = loops::dump(&module);
+    assert!(dump.starts_with("@main:\nloop "), "{}", dump);
+    assert!(dump.contains("\n  loop "), "{}", dump);
+    assert!(dump.contains("(depth 2)"), "{}", dump);
+
+    // 没有循环的函数什么也不输出.
+    let module = compile("flat.sy", "int main() { return 0; }\n");
+    assert!(loop_forest(module.function("main").unwrap())
+        .loops
+        .is_empty());
+    assert_eq!(loops::dump(&module), "");
+}
+
+/*
+    bb0: %0 = getint(); br %0 > 0, bb1, bb2
+    bb1: jump bb2
+    bb2: %2 = phi [bb0, 0], [bb1, 5], [bb3, %4]; br %2 < 10, bb3, bb4
+    bb3: %4 = %2 + 1; jump bb2
+    bb4: ret %2 * 3
+    循环头bb2有两个循环外的前驱, 没有preheader.
+*/
+fn two_entries() -> Module {
+    let mut main = Function::new("main", Type::I32, vec![]);
+    let blocks: Vec<BlockId> = (0..5).map(|_| main.new_block()).collect();
+    let values: Vec<Value> = (0..6).map(|_| main.new_value(Type::I32)).collect();
+    let inst = |dest: Option<Value>, kind| Inst {
+        dest,
+        ty: if dest.is_some() {
+            Type::I32
+        } else {
+            Type::Void
+        },
+        kind,
+    };
+    let v = |i: usize| Operand::Value(values[i]);
+    main.blocks[0].insts = vec![
+        inst(Some(values[0]), InstKind::Call("getint".into(), vec![])),
+        inst(
+            Some(values[1]),
+            InstKind::Icmp(CmpOp::Gt, v(0), Operand::Int(0)),
+        ),
+        inst(None, InstKind::Branch(v(1), blocks[1], blocks[2])),
+    ];
+    main.blocks[1].insts = vec![inst(None, InstKind::Jump(blocks[2]))];
+    main.blocks[2].insts = vec![
+        inst(
+            Some(values[2]),
+            InstKind::Phi(vec![
+                (blocks[0], Operand::Int(0)),
+                (blocks[1], Operand::Int(5)),
+                (blocks[3], v(4)),
+            ]),
+        ),
+        inst(
+            Some(values[3]),
+            InstKind::Icmp(CmpOp::Lt, v(2), Operand::Int(10)),
+        ),
+        inst(None, InstKind::Branch(v(3), blocks[3], blocks[4])),
+    ];
+    main.blocks[3].insts = vec![
+        inst(
+            Some(values[4]),
+            InstKind::Binary(BinOp::Add, v(2), Operand::Int(1)),
+        ),
+        inst(None, InstKind::Jump(blocks[2])),
+    ];
+    main.blocks[4].insts = vec![
+        inst(
+            Some(values[5]),
+            InstKind::Binary(BinOp::Mul, v(2), Operand::Int(3)),
+        ),
+        inst(None, InstKind::Ret(Some(v(5)))),
+    ];
+    Module {
+        functions: vec![Function::new("getint", Type::I32, vec![]), main],
+        ..Module::default()
+    }
+}
+
+#[test]
+fn missing_preheaders_are_inserted() {
+    let mut module = two_entries();
+    module.validate().unwrap();
+    let before = loop_forest(module.function("main").unwrap());
+    assert_eq!(before.loops[0].entering, vec![BlockId(0), BlockId(1)]);
+    assert_eq!(before.loops[0].preheader, None);
+
+    let expected: Vec<i32> = ["-1", "3"]
+        .iter()
+        .map(|input| interp::run(&module, input.as_bytes()).unwrap().exit_code)
+        .collect();
+    assert!(loops::insert_preheaders_module(&mut module));
+    module.validate().unwrap();
+    let main = module.function("main").unwrap();
+    let after = loop_forest(main);
+    let preheader = after.loops[0].preheader.unwrap();
+    assert_eq!(after.loops[0].entering, vec![preheader]);
+    // 两个循环外的值在preheader中合并.
+    assert!(matches!(
+        &main.block(preheader).insts[0].kind,
+        InstKind::Phi(incoming) if incoming.len() == 2
+    ));
+    let actual: Vec<i32> = ["-1", "3"]
+        .iter()
+        .map(|input| interp::run(&module, input.as_bytes()).unwrap().exit_code)
+        .collect();
+    assert_eq!(actual, expected);
+
+    // 已经有preheader时什么也不做.
+    assert!(!loops::insert_preheaders_module(&mut module));
+}
@@ -0,0 +1,100 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::ir::{InstKind, Module};
+use sysy_alpha::lower::lower;
+use sysy_alpha::mem2reg::promote_module;
+use sysy_alpha::session::{CompileOptions, Session};
+
+/*
+    mem2reg: 只被load/store访问的标量alloca变成SSA值, 需要的地方插入phi; 数组和取了地址的变量保持不变.
+*/
+
+fn promoted(name: &str, source: &str) -> (Module, usize) {
+    let dir = TempDir::new("mem2reg");
+    let path = dir.write(name, source);
+    let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    let mut module = lower(&checked.hir);
+    let count = promote_module(&mut module);
+    module
+        .validate()
+        .unwrap_or_else(|e| panic!("{}\n{}", e, module));
+    (module, count)
+}
+
+#[test]
+fn loop_variables_become_phis() {
+    let (module, count) = promoted(
+        "loop.sy",
+        "int main() {
+  int i = 0, s = 0;
+  while (i < 10) {
+    if (i % 2) s = s + i;
+    i = i + 1;
+  }
+  return s;
+}
+",
+    );
+    assert_eq!(count, 2);
+    let expected = "fn i32 @main() {
+bb0:
+  jump bb1
+bb1:
+  %0 = phi i32 [bb0, 0], [bb5, %6]
+  %1 = phi i32 [bb0, 0], [bb5, %5]
+  %2 = icmp lt %0, 10
+  br %2, bb2, bb3
+bb2:
+  %3 = rem i32 %0, 2
+  br %3, bb4, bb5
+bb3:
+  ret %1
+bb4:
+  %4 = add i32 %1, %0
+  jump bb5
+bb5:
+  %5 = phi i32 [bb2, %1], [bb4, %4]
+  %6 = add i32 %0, 1
+  jump bb1
+}
+";
+    assert_eq!(module.to_string(), expected);
+}
+
+#[test]
+fn arrays_and_params() {
+    let (module, count) = promoted(
+        "arrays.sy",
+        "int f(int a[], int n) {
+  int x;
+  if (n > 0) x = a[0]; else x = n;
+  a[0] = x;
+  return x + n;
+}
+int main() {
+  int b[3] = {1, 2, 3};
+  return f(b, 2);
+}
+",
+    );
+    // f的x和n都被提升, 数组b保持在内存中.
+    assert_eq!(count, 2);
+    let f = module.function("f").unwrap();
+    let insts = || f.blocks.iter().flat_map(|b| &b.insts);
+    assert!(!insts().any(|i| matches!(i.kind, InstKind::Alloca { .. })));
+    assert_eq!(
+        insts()
+            .filter(|i| matches!(i.kind, InstKind::Phi(_)))
+            .count(),
+        1
+    );
+    let main = module.function("main").unwrap();
+    assert!(main.blocks[0]
+        .insts
+        .iter()
+        .any(|i| matches!(i.kind, InstKind::Alloca { len: 3, .. })));
+}
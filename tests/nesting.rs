@@ -0,0 +1,115 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::lexer::{try_tokenize, LangLevel, LexOptions};
+use sysy_alpha::parser::{parse_with_options, Ast, ParseError, ParseLimits, ParserOptions};
+
+/*
+    嵌套很深的输入不会让语法分析器栈溢出:
+    一元运算和初始化列表用循环处理, 各种嵌套(二元运算链除外)的层数受max_depth限制,
+    超出时只报一个错误.
+*/
+
+fn parse(name: &str, source: &str, max_depth: usize) -> Result<Ast, Vec<ParseError>> {
+    let dir = TempDir::new("nesting");
+    let path = dir.write(name, source);
+
+    let options = LexOptions {
+        level: LangLevel::Extended,
+        ..Default::default()
+    };
+    let tokens = try_tokenize(path.to_string_lossy().to_string(), &options).unwrap();
+    let options = ParserOptions {
+        limits: ParseLimits { max_depth },
+        ..LangLevel::Extended.into()
+    };
+    parse_with_options(tokens, &options)
+}
+
+fn in_main(expr: &str) -> String {
+    format!(
+        "int f(int x) {{ return x; }}\nint main() {{ int a[2]; return {}; }}\n",
+        expr
+    )
+}
+
+#[test]
+fn deep_inputs_report_a_single_error() {
+    let n = 10000;
+    let samples = [
+        (
+            "paren.sy",
+            in_main(&format!("{}1{}", "(".repeat(n), ")".repeat(n))),
+        ),
+        (
+            "call.sy",
+            in_main(&format!("{}1{}", "f(".repeat(n), ")".repeat(n))),
+        ),
+        (
+            "index.sy",
+            in_main(&format!("{}0{}", "a[".repeat(n), "]".repeat(n))),
+        ),
+        ("unary.sy", in_main(&format!("{}1", "- ".repeat(n)))),
+        (
+            "tern.sy",
+            in_main(&format!("{}1{}", "1 ? ".repeat(n), " : 0".repeat(n))),
+        ),
+        (
+            "init.sy",
+            format!("int a[1] = {}1{};\n", "{".repeat(n), "}".repeat(n)),
+        ),
+    ];
+    for (name, source) in samples {
+        let errors = parse(name, &source, ParseLimits::default().max_depth)
+            .err()
+            .expect(name);
+        assert_eq!(errors.len(), 1, "{}: {:?}", name, errors);
+        assert!(errors[0].message.contains("too deeply nested"), "{}", name);
+    }
+}
+
+#[test]
+fn long_binary_chains_parse() {
+    // 二元运算链由循环构造, 不计入嵌套层数.
+    let n = 10000;
+    let chain = in_main(&vec!["1"; n].join(" + "));
+    assert!(parse("chain.sy", &chain, 16).is_ok());
+}
+
+#[test]
+fn max_depth_is_inclusive() {
+    let nested = |d: usize| in_main(&format!("{}1{}", "(".repeat(d), ")".repeat(d)));
+    assert!(parse("ok.sy", &nested(8), 8).is_ok());
+    let errors = parse("deep.sy", &nested(9), 8).err().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].message.contains("more than 8 levels"));
+}
+
+#[test]
+fn default_depth_fits_in_a_test_thread() {
+    // 测试线程的栈只有2MB; 默认上限的嵌套要能完整走过语法分析和语义分析.
+    use sysy_alpha::session::{CompileOptions, Session};
+    let d = ParseLimits::default().max_depth;
+    let samples = [
+        (
+            "paren_max.sy",
+            format!("{}1{}", "(".repeat(d), ")".repeat(d)),
+        ),
+        (
+            "call_max.sy",
+            format!("{}1{}", "f(".repeat(d), ")".repeat(d)),
+        ),
+        (
+            "index_max.sy",
+            format!("{}0{}", "a[".repeat(d), "]".repeat(d)),
+        ),
+    ];
+    for (name, expr) in samples {
+        let dir = TempDir::new("nesting");
+        let path = dir.write(name, in_main(&expr));
+        let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+            .check()
+            .unwrap();
+        assert!(!checked.has_errors(), "{}", name);
+    }
+}
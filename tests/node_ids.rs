@@ -0,0 +1,124 @@
+mod common;
+
+use common::SourceFile;
+use std::collections::HashSet;
+use sysy_alpha::parser::{Node, NodeId};
+use sysy_alpha::semantics::{runtime_declarations, semantic};
+use sysy_alpha::session::{CompileOptions, Session};
+use sysy_alpha::visit::{children, node_map};
+use sysy_alpha::NodeType;
+
+/*
+    节点编号在语法分析时分配, 并在语义分析的改写中保留:
+    改写后的节点沿用原节点的编号, Call中的被调函数与函数定义编号相同, 出错后的占位节点也沿用原编号.
+*/
+
+fn all_ids(ast: &[Node]) -> Vec<NodeId> {
+    fn collect(node: &Node, ids: &mut Vec<NodeId>) {
+        ids.push(node.id);
+        for child in children(node) {
+            collect(child, ids);
+        }
+    }
+    let mut ids = vec![];
+    for node in ast {
+        collect(node, &mut ids);
+    }
+    ids
+}
+
+#[test]
+fn ids_are_unique_and_survive_checking() {
+    let file = SourceFile::new(
+        "ids",
+        "ok.sy",
+        "const int N = 2;\nint g(int x) { return x * N; }\nint main() { int a[N] = {1}; a[1] = g(a[0]); return a[1]; }\n",
+    );
+    let path = file.path_string();
+    let ast = Session::new(path.clone(), CompileOptions::default())
+        .parse()
+        .unwrap();
+    let before = all_ids(&ast);
+    assert_eq!(before.len(), before.iter().collect::<HashSet<_>>().len());
+
+    let (annotated, diagnostics) = semantic(&ast);
+    assert!(diagnostics.is_empty());
+    let after = all_ids(&annotated);
+    assert_eq!(after.len(), after.iter().collect::<HashSet<_>>().len());
+
+    // 函数和语句节点都原样保留编号.
+    let map = node_map(&annotated);
+    for node in &ast {
+        assert!(map.contains_key(&node.id));
+    }
+    let g = &annotated[1];
+    assert!(matches!(&g.node_type, NodeType::Func(_, name, ..) if name == "g"));
+    let call = map.values().find_map(|n| n.as_call()).expect("call to g");
+    assert_eq!(call.2, g.id);
+}
+
+#[test]
+fn placeholders_keep_the_id_of_the_bad_node() {
+    let file = SourceFile::new(
+        "ids",
+        "bad.sy",
+        "int f() { return 1; }\nint main() { int x; x = f; return x; }\n",
+    );
+    let path = file.path_string();
+    let ast = Session::new(path.clone(), CompileOptions::default())
+        .parse()
+        .unwrap();
+    let (annotated, diagnostics) = semantic(&ast);
+    assert!(!diagnostics.is_empty());
+
+    let before = node_map(&ast);
+    let after = node_map(&annotated);
+    let nil = after
+        .values()
+        .find(|n| matches!(n.node_type, NodeType::Nil))
+        .expect("placeholder");
+    let original = before[&nil.id];
+    assert!(matches!(&original.node_type, NodeType::Access(name, ..) if name == "f"));
+    assert_eq!(nil.span, original.span);
+}
+
+#[test]
+fn declarations_are_referenced_by_id() {
+    let file = SourceFile::new(
+        "ids",
+        "shared.sy",
+        "int a[4] = {1, 2, 3, 4};\nint main() { a[0] = a[1] + a[2]; putint(a[3]); return 0; }\n",
+    );
+    let path = file.path_string();
+    let ast = Session::new(path.clone(), CompileOptions::default())
+        .parse()
+        .unwrap();
+    let (annotated, _) = semantic(&ast);
+
+    // 每处使用记录的都是同一个声明节点的编号, 通过它能在树中找到这个声明.
+    let map = node_map(&annotated);
+    let mut decls = vec![];
+    for node in map.values() {
+        match &node.node_type {
+            NodeType::Access(_, _, decl) | NodeType::Assign(_, _, _, decl) => decls.push(*decl),
+            _ => {}
+        }
+    }
+    assert_eq!(decls.len(), 4);
+    let NodeType::DeclStmt(globals) = &annotated[0].node_type else {
+        panic!("expected a declaration statement");
+    };
+    assert!(decls.iter().all(|d| *d == globals[0].id));
+    assert!(matches!(&map[&decls[0]].node_type, NodeType::Decl(_, name, ..) if name == "a"));
+
+    // 运行时库函数不在树中, 调用记录的是runtime_declarations中对应节点的编号.
+    let call = map
+        .values()
+        .find_map(|n| n.as_call())
+        .expect("call to putint");
+    let callee = runtime_declarations()
+        .iter()
+        .find(|d| d.id == call.2)
+        .expect("runtime declaration");
+    assert!(matches!(&callee.node_type, NodeType::Func(_, name, ..) if name == "putint"));
+}
@@ -0,0 +1,86 @@
+use std::collections::HashSet;
+use sysy_alpha::parser::{Node, NodeId};
+use sysy_alpha::utils::emit_source;
+use sysy_alpha::{BasicType, NodeClass, NodeKind, NodeType, Scope, TokenType};
+
+/*
+    NodeType, NodeKind和各层对它们的处理必须保持一致.
+    sample中的match不带通配分支, NodeKind增加成员而这里没有跟上时无法编译.
+*/
+
+fn leaf(n: i32) -> Box<Node> {
+    Box::new(Node::new(NodeType::Number(n)))
+}
+
+fn sample(kind: NodeKind) -> Node {
+    let decl = NodeId::unresolved();
+    let node_type = match kind {
+        NodeKind::Decl => NodeType::Decl(BasicType::Int, "a".into(), None, None, Scope::Local),
+        NodeKind::DeclStmt => NodeType::DeclStmt(vec![sample(NodeKind::Decl)]),
+        NodeKind::InitList => NodeType::InitList(vec![*leaf(1), *leaf(2)]),
+        NodeKind::Assign => NodeType::Assign("a".into(), None, leaf(1), decl),
+        NodeKind::ExprStmt => NodeType::ExprStmt(Box::new(sample(NodeKind::Call))),
+        NodeKind::Access => NodeType::Access("a".into(), Some(vec![*leaf(0)]), decl),
+        NodeKind::BinOp => NodeType::BinOp(TokenType::Plus, leaf(1), leaf(2)),
+        NodeKind::UnaryOp => NodeType::UnaryOp(TokenType::Minus, leaf(1)),
+        NodeKind::Cond => NodeType::Cond(leaf(1), leaf(2), leaf(3)),
+        NodeKind::Comma => NodeType::Comma(vec![*leaf(1), *leaf(2)]),
+        NodeKind::Cast => NodeType::Cast(BasicType::Float, leaf(1)),
+        NodeKind::Func => NodeType::Func(
+            BasicType::Void,
+            "f".into(),
+            vec![],
+            Box::new(sample(NodeKind::Block)),
+        ),
+        NodeKind::Block => NodeType::Block(vec![sample(NodeKind::Empty)]),
+        NodeKind::Return => NodeType::Return(Some(leaf(0))),
+        NodeKind::Call => NodeType::Call("f".into(), vec![*leaf(1)], decl),
+        NodeKind::If => NodeType::If(leaf(1), Box::new(sample(NodeKind::Break)), None),
+        NodeKind::While => NodeType::While(leaf(1), Box::new(sample(NodeKind::Continue))),
+        NodeKind::Switch => NodeType::Switch(leaf(1), vec![sample(NodeKind::Case)]),
+        NodeKind::Case => NodeType::Case(Some(leaf(1)), vec![sample(NodeKind::Break)]),
+        NodeKind::Continue => NodeType::Continue,
+        NodeKind::Break => NodeType::Break,
+        NodeKind::Empty => NodeType::Empty,
+        NodeKind::Nil => NodeType::Nil,
+        NodeKind::Number => NodeType::Number(7),
+        NodeKind::FloatNumber => NodeType::FloatNumber(1.5),
+        NodeKind::Str => NodeType::Str("%d\n".into()),
+    };
+    Node::new(node_type)
+}
+
+#[test]
+fn all_lists_every_kind_once() {
+    let kinds: HashSet<NodeKind> = NodeKind::ALL.iter().copied().collect();
+    assert_eq!(kinds.len(), NodeKind::ALL.len());
+    for kind in NodeKind::ALL {
+        assert_eq!(sample(kind).kind(), kind);
+    }
+}
+
+#[test]
+fn classes_partition_the_kinds() {
+    let count = |class| NodeKind::ALL.iter().filter(|k| k.class() == class).count();
+    assert_eq!(count(NodeClass::Decl), 4);
+    assert_eq!(count(NodeClass::Stmt), 11);
+    assert_eq!(count(NodeClass::Expr), 10);
+    assert_eq!(count(NodeClass::Nil), 1);
+    assert!(NodeKind::Assign.is_stmt() && !NodeKind::Assign.is_expr());
+    assert!(NodeKind::Call.is_expr());
+}
+
+#[test]
+fn emitter_handles_every_kind() {
+    for kind in NodeKind::ALL {
+        let text = emit_source(&[sample(kind)]);
+        match kind.class() {
+            NodeClass::Nil => assert_eq!(text, ""),
+            // 表达式在语句的位置输出为表达式语句.
+            NodeClass::Expr => assert!(text.ends_with(";\n"), "{:?}: {:?}", kind, text),
+            NodeClass::Decl | NodeClass::Stmt => {
+                assert!(!text.trim().is_empty(), "{:?}", kind)
+            }
+        }
+    }
+}
@@ -0,0 +1,185 @@
+mod common;
+
+use common::TempDir;
+use object::{Object, ObjectSection, ObjectSymbol, RelocationFlags};
+use std::process::Command;
+use sysy_alpha::codegen::{object as elf_object, riscv};
+use sysy_alpha::ir::Module;
+use sysy_alpha::lower::lower;
+use sysy_alpha::mem2reg::promote_module;
+use sysy_alpha::passes::{OptLevel, PassManager};
+use sysy_alpha::session::{CompileOptions, Session};
+
+/*
+    ELF目标文件: 文件头, 节, 符号和重定位; 机器上有llvm-mc时与它从同一份汇编得到的机器码逐字节比较.
+*/
+
+fn compile(name: &str, source: &str, level: OptLevel) -> Module {
+    let dir = TempDir::new("object");
+    let path = dir.write(name, source);
+    let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    let mut module = lower(&checked.hir);
+    promote_module(&mut module);
+    PassManager::for_level(level).run(&mut module);
+    module
+}
+
+const PROGRAM: &str = "const int tab[4] = {1, 2, 3, 4};
+int g = 5, z[100];
+float scale = 1.5;
+int many(int a, int b, int c, int d, int e, int f, int h, int i, int j, float x) {
+  int r = 0;
+  if (x > 1.0 || x == 3.0) r = 1;
+  return a + b * c - d / e + f % h + i + j + r;
+}
+int main() {
+  int i = 0, s = 0;
+  float q = 2.5;
+  while (i < 10) { s = s + tab[i % 4] + z[i]; i = i + 1; }
+  putf(\"s=%d %f\\n\", s, q * scale);
+  if (!s) s = -s;
+  s = s + 100000 + many(1, 2, 3, 4, 5, 6, 7, 8, 9, q);
+  int t = q;
+  putfloat(-q / 2 - s);
+  return s + g + t;
+}
+";
+
+/* (偏移, 重定位类型, 符号名), 不包括auipc处的局部标签. */
+fn relocations(file: &object::File) -> Vec<(u64, u32, String)> {
+    let text = file.section_by_name(".text").unwrap();
+    text.relocations()
+        .map(|(offset, relocation)| {
+            let RelocationFlags::Elf { r_type } = relocation.flags() else {
+                panic!("not an ELF relocation");
+            };
+            let object::RelocationTarget::Symbol(index) = relocation.target() else {
+                panic!("relocation without a symbol");
+            };
+            let name = file.symbol_by_index(index).unwrap().name().unwrap();
+            // 较新的汇编器对call生成R_RISCV_CALL_PLT, 旧的生成R_RISCV_CALL, 链接器的处理相同.
+            let r_type = if r_type == object::elf::R_RISCV_CALL {
+                object::elf::R_RISCV_CALL_PLT
+            } else {
+                r_type
+            };
+            (offset, r_type, name.to_string())
+        })
+        .collect()
+}
+
+#[test]
+fn object_has_sections_symbols_and_relocations() {
+    let module = compile("sections.sy", PROGRAM, OptLevel::O0);
+    let bytes = elf_object::emit(&module).unwrap();
+    // ELF64, 小端, EM_RISCV, ET_REL.
+    assert_eq!(&bytes[..6], b"\x7fELF\x02\x01");
+    assert_eq!(u16::from_le_bytes([bytes[16], bytes[17]]), 1);
+    assert_eq!(u16::from_le_bytes([bytes[18], bytes[19]]), 0xf3);
+
+    let file = object::File::parse(&*bytes).unwrap();
+    let symbol = |name: &str| file.symbols().find(|s| s.name() == Ok(name));
+    let section = |name: &str| {
+        let s = symbol(name).unwrap();
+        file.section_by_index(s.section_index().unwrap())
+            .unwrap()
+            .name()
+            .unwrap()
+            .to_string()
+    };
+    assert_eq!(section("main"), ".text");
+    assert_eq!(section("many"), ".text");
+    assert_eq!(section("g"), ".data");
+    assert_eq!(section("scale"), ".data");
+    assert_eq!(section("tab"), ".rodata");
+    assert_eq!(section("z"), ".bss");
+    assert_eq!(symbol("z").unwrap().size(), 400);
+    assert!(symbol("main").unwrap().is_global());
+    assert!(symbol(".L.str.0").unwrap().is_local());
+    assert!(symbol("putf").unwrap().is_undefined());
+    // 基本块的标签不进符号表.
+    assert!(!file
+        .symbols()
+        .any(|s| s.name().unwrap().starts_with(".Lmain_")));
+
+    let relocations = relocations(&file);
+    let targets = |r_type| {
+        relocations
+            .iter()
+            .filter(|r| r.1 == r_type)
+            .map(|r| r.2.as_str())
+            .collect::<Vec<_>>()
+    };
+    let calls = targets(object::elf::R_RISCV_CALL_PLT);
+    for callee in ["putf", "putfloat", "many"] {
+        assert!(calls.contains(&callee), "{:?}", relocations);
+    }
+    let addresses = targets(object::elf::R_RISCV_PCREL_HI20);
+    for global in ["tab", "z", "scale", "g", ".L.str.0"] {
+        assert!(addresses.contains(&global), "{:?}", relocations);
+    }
+    // 每个auipc的高20位都有对应的addi的低12位.
+    assert_eq!(
+        targets(object::elf::R_RISCV_PCREL_LO12_I).len(),
+        addresses.len()
+    );
+}
+
+#[test]
+fn object_matches_llvm_mc() {
+    let dir = TempDir::new("object");
+    for (i, level) in [OptLevel::O0, OptLevel::O2].into_iter().enumerate() {
+        let module = compile("llvm_mc.sy", PROGRAM, level);
+        let asm = riscv::emit(&module).unwrap();
+        let path = dir.write(&format!("llvm_mc{}.s", i), &asm);
+        let Ok(output) = Command::new("llvm-mc")
+            .args([
+                "-triple=riscv64",
+                "-mattr=+m,+f,+d,-relax",
+                "-filetype=obj",
+                "-o",
+            ])
+            .arg(path.with_extension("o"))
+            .arg(&path)
+            .output()
+        else {
+            return;
+        };
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let expected = std::fs::read(path.with_extension("o")).unwrap();
+        let expected = object::File::parse(&*expected).unwrap();
+        let ours = elf_object::assemble(&asm).unwrap();
+        let ours = object::File::parse(&*ours).unwrap();
+        for name in [".text", ".data", ".rodata"] {
+            let data =
+                |file: &object::File| file.section_by_name(name).unwrap().data().unwrap().to_vec();
+            assert_eq!(data(&ours), data(&expected), "{} differs", name);
+        }
+        let without_labels = |file: &object::File| {
+            relocations(file)
+                .into_iter()
+                .filter(|r| !r.2.starts_with(".Lpcrel_hi"))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(without_labels(&ours), without_labels(&expected));
+    }
+}
+
+#[test]
+fn unknown_instructions_are_rejected() {
+    let error = elf_object::assemble("  .text\nf:\n  vadd.vv v0, v1, v2\n").unwrap_err();
+    assert!(
+        error.contains("unsupported instruction `vadd.vv`"),
+        "{}",
+        error
+    );
+    let error = elf_object::assemble("  .text\nf:\n  j .Lnowhere\n").unwrap_err();
+    assert!(error.contains(".Lnowhere"), "{}", error);
+}
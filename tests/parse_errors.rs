@@ -0,0 +1,162 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::lexer::{try_tokenize, LangLevel, LexOptions};
+use sysy_alpha::parser::{parse_with_level, Ast, ParseError};
+use sysy_alpha::{NodeKind, NodeType};
+
+/*
+    parse以值的形式返回语法错误: 不打印, 不panic, 每个错误带有位置, 期望的token和实际的token.
+    最后用伪随机的token序列检查任何输入都不会让语法分析器panic.
+*/
+
+fn parse_source(source: &str, level: LangLevel) -> Result<Ast, Vec<ParseError>> {
+    let dir = TempDir::new("parse");
+    let path = dir.write("parse.sy", source);
+
+    let options = LexOptions {
+        level,
+        ..Default::default()
+    };
+    let tokens = try_tokenize(path.to_string_lossy().to_string(), &options).unwrap();
+    parse_with_level(tokens, level)
+}
+
+fn parse_errors(source: &str) -> Vec<ParseError> {
+    match parse_source(source, LangLevel::SysY2022) {
+        Ok(_) => panic!("{:?} should not parse", source),
+        Err(errors) => errors,
+    }
+}
+
+#[test]
+fn valid_program_parses() {
+    let ast = parse_source("int main() { return 0; }\n", LangLevel::SysY2022);
+    assert_eq!(ast.map(|ast| ast.len()).ok(), Some(1));
+    let empty = parse_source("", LangLevel::SysY2022);
+    assert_eq!(empty.map(|ast| ast.len()).ok(), Some(0));
+}
+
+#[test]
+fn missing_semicolon_is_inserted_after_previous_token() {
+    let errors = parse_errors("int main() {\n  int a = 1\n  return a;\n}\n");
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    let first = &errors[0];
+    assert_eq!(first.expected.as_deref(), Some("';'"));
+    assert_eq!(first.found, "return");
+    assert_eq!((first.line_no, first.column), (2, 12));
+    assert!(first.span.is_empty());
+    let fix = first.fix.as_ref().expect("fix-it");
+    assert_eq!((fix.span, fix.replacement.as_str()), (first.span, ";"));
+    assert_eq!(first.to_diagnostic().fixes.len(), 1);
+    assert!(first
+        .to_string()
+        .starts_with("Parsing error: Error type B found."));
+}
+
+#[test]
+fn missing_tokens_do_not_cascade() {
+    // 每处只漏了一个')'或';', 补上之后其余部分都是正确的.
+    let errors = parse_errors(
+        "int f(int x) { return x; }\n\
+         int main() {\n\
+         \x20 int a = (1 + 2;\n\
+         \x20 int b = f(a;\n\
+         \x20 if (a > b {\n\
+         \x20   a = 1\n\
+         \x20 }\n\
+         \x20 b = 2 c = 3;\n\
+         \x20 return a\n\
+         }\n",
+    );
+    let found: Vec<(usize, usize, &str)> = errors
+        .iter()
+        .map(|e| {
+            (
+                e.line_no,
+                e.column,
+                e.fix.as_ref().unwrap().replacement.as_str(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (3, 17, ")"),
+            (4, 14, ")"),
+            (5, 12, ")"),
+            (6, 10, ";"),
+            (8, 8, ";"),
+            (9, 11, ";")
+        ]
+    );
+}
+
+#[test]
+fn mistyped_token_on_the_same_line_is_skipped() {
+    let errors = parse_errors("int main() { int a; a = 1] return a; }\n");
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert_eq!(errors[0].found, "]");
+    assert!(errors[0].fix.is_none());
+}
+
+#[test]
+fn unexpected_end_of_input_is_an_error() {
+    for source in [
+        "int main() {",
+        "int main() { if (1",
+        "int a[3] = {1, 2",
+        "int",
+    ] {
+        assert!(!parse_errors(source).is_empty());
+    }
+}
+
+#[test]
+fn const_without_initializer_is_an_error() {
+    let errors = parse_errors("const int a;\n");
+    assert!(errors[0].message.contains("assign in const declaration"));
+}
+
+#[test]
+fn comma_expression_requires_extended_mode() {
+    let source = "int main() { int a, b; a = (b = 1, b + 2); return a; }\n";
+    let errors = parse_errors(source);
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0]
+        .message
+        .contains("comma expressions are not part of SysY"));
+    assert_eq!(errors[0].found, ",");
+
+    let ast = parse_source(source, LangLevel::Extended).ok().unwrap();
+    let body = ast[0].as_func().unwrap().3;
+    let NodeType::Block(stmts) = &body.node_type else {
+        panic!("function body should be a block");
+    };
+    let (_, _, value, _) = stmts[1].as_assign().unwrap();
+    assert_eq!(value.kind(), NodeKind::Comma);
+}
+
+#[test]
+fn generated_token_sequences_never_panic() {
+    // 用简单的线性同余生成器拼出伪随机的token序列.
+    const ALPHABET: &[&str] = &[
+        "int ", "float ", "const ", "void ", "if ", "else ", "while ", "return ", "break ",
+        "switch ", "case ", "default ", "a ", "main ", "0 ", "1.5 ", "+ ", "- ", "* ", "! ", "< ",
+        "== ", "&& ", "= ", "? ", ": ", "( ", ") ", "{ ", "} ", "[ ", "] ", "; ", ", ",
+    ];
+    let mut seed: u64 = 0x5359_5359;
+    for _ in 0..500 {
+        let mut source = String::new();
+        for _ in 0..30 {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            source.push_str(ALPHABET[(seed >> 33) as usize % ALPHABET.len()]);
+        }
+        for level in [LangLevel::SysY2022, LangLevel::Extended] {
+            let result = std::panic::catch_unwind(|| parse_source(&source, level));
+            assert!(result.is_ok(), "parser panicked on {:?}", source);
+        }
+    }
+}
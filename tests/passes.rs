@@ -0,0 +1,134 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::ir::{InstKind, Module};
+use sysy_alpha::lower::lower;
+use sysy_alpha::passes::{OptLevel, Pass, PassManager};
+use sysy_alpha::session::{CompileOptions, Session};
+
+/*
+    pass管理器: 预设的流水线, 单独运行的pass, 每个pass之后的回调, 以及分析结果的缓存.
+*/
+
+fn compile(name: &str, source: &str) -> Module {
+    let dir = TempDir::new("passes");
+    let path = dir.write(name, source);
+    let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    lower(&checked.hir)
+}
+
+fn count(module: &Module, wanted: fn(&InstKind) -> bool) -> usize {
+    module
+        .functions
+        .iter()
+        .flat_map(|f| &f.blocks)
+        .flat_map(|b| &b.insts)
+        .filter(|i| wanted(&i.kind))
+        .count()
+}
+
+const LOOP: &str = "int sq(int x) { return x * x; }
+int main() {
+  int i = 0, s = 0;
+  while (i < 10) { s = s + sq(i) * 4 + i * 8; i = i + 1; }
+  putint(s);
+  return 0;
+}
+";
+
+#[test]
+fn pass_names_round_trip() {
+    for pass in Pass::ALL {
+        assert_eq!(Pass::from_name(pass.name()), Some(pass));
+    }
+    assert_eq!(Pass::from_name("dce"), None);
+    assert_eq!(OptLevel::from_name("2"), Some(OptLevel::O2));
+    assert_eq!(OptLevel::from_name("3"), None);
+}
+
+#[test]
+fn o0_leaves_the_module_alone() {
+    let mut module = compile("o0.sy", LOOP);
+    let before = module.to_string();
+    assert!(!PassManager::for_level(OptLevel::O0).run(&mut module));
+    assert_eq!(module.to_string(), before);
+}
+
+#[test]
+fn o2_promotes_inlines_and_reduces() {
+    let mut o1 = compile("o1.sy", LOOP);
+    let mut o2 = o1.clone();
+    assert!(PassManager::for_level(OptLevel::O1).run(&mut o1));
+    assert!(PassManager::for_level(OptLevel::O2).run(&mut o2));
+    for module in [&o1, &o2] {
+        module
+            .validate()
+            .unwrap_or_else(|e| panic!("{}\n{}", e, module));
+        assert_eq!(count(module, |k| matches!(k, InstKind::Alloca { .. })), 0);
+    }
+    // -O1不内联, -O2之后sq被展开并删除.
+    assert!(o1.function("sq").is_some());
+    assert!(o2.function("sq").is_none());
+    assert_eq!(
+        count(
+            &o2,
+            |k| matches!(k, InstKind::Call(name, _) if name == "sq")
+        ),
+        0
+    );
+}
+
+#[test]
+fn single_passes_run_on_their_own() {
+    let mut module = compile("single.sy", LOOP);
+    assert!(Pass::Mem2Reg.run(&mut module));
+    assert!(!Pass::Mem2Reg.run(&mut module));
+    assert!(Pass::Inline.run(&mut module));
+    module.validate().unwrap();
+}
+
+#[test]
+fn after_is_called_once_per_pass() {
+    let mut module = compile("after.sy", LOOP);
+    let mut manager = PassManager::new();
+    manager
+        .add(Pass::Mem2Reg)
+        .add(Pass::CopyProp)
+        .add(Pass::Peephole);
+    let mut seen = vec![];
+    manager.run_with(&mut module, |pass, module| {
+        module.validate().unwrap();
+        seen.push(pass);
+    });
+    assert_eq!(seen, manager.passes());
+}
+
+#[test]
+fn analyses_are_reused_until_invalidated() {
+    let mut module = compile("cache.sy", LOOP);
+    Pass::Mem2Reg.run(&mut module);
+    let functions = module
+        .functions
+        .iter()
+        .filter(|f| !f.is_declaration())
+        .count();
+
+    // cse, local-cse和strength-reduce不改变控制流, 控制流图, 支配树和循环每个函数只算一次.
+    let mut manager = PassManager::new();
+    manager
+        .add(Pass::Cse)
+        .add(Pass::LocalCse)
+        .add(Pass::StrengthReduce);
+    manager.run(&mut module);
+    assert_eq!(manager.analyses_computed(), 3 * functions);
+
+    // sccp之后缓存作废(至少在它改变的函数上), 再次需要时重新计算.
+    let mut module = compile("cache2.sy", "int main() { if (1) return 2; return 3; }\n");
+    let mut manager = PassManager::new();
+    manager.add(Pass::Cse).add(Pass::Sccp).add(Pass::Cse);
+    assert!(manager.run(&mut module));
+    assert_eq!(manager.analyses_computed(), 4);
+}
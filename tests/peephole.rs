@@ -0,0 +1,132 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::ir::{BinOp, CmpOp, Function, Inst, InstKind, Module, Operand, Type, Value};
+use sysy_alpha::lower::lower;
+use sysy_alpha::mem2reg::promote_module;
+use sysy_alpha::peephole;
+use sysy_alpha::session::{CompileOptions, Session};
+
+/*
+    窥孔优化: 代数恒等式, 比较与分支的合并, store之后多余的load.
+*/
+
+fn compile(name: &str, source: &str, promote: bool) -> Module {
+    let dir = TempDir::new("peephole");
+    let path = dir.write(name, source);
+    let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    let mut module = lower(&checked.hir);
+    if promote {
+        promote_module(&mut module);
+    }
+    assert!(peephole::run_module(&mut module));
+    module
+        .validate()
+        .unwrap_or_else(|e| panic!("{}\n{}", e, module));
+    module
+}
+
+const SOURCE: &str = "int main() {
+  int x = getint();
+  int y = x * 1 + 0 - (x - x);
+  int c = !y;
+  if (!c) y = y / 1;
+  if (x) return y;
+  return x;
+}
+";
+
+#[test]
+fn identities_and_compares_fold_away() {
+    let module = compile("ssa.sy", SOURCE, true);
+    assert_eq!(
+        module.function("main").unwrap().to_string(),
+        "fn i32 @main() {
+bb0:
+  %0 = call i32 @getint()
+  br %0, bb1, bb2
+bb1:
+  jump bb2
+bb2:
+  %1 = phi i32 [bb0, %0], [bb1, %0]
+  br %0, bb3, bb4
+bb3:
+  ret %1
+bb4:
+  ret %0
+}
+"
+    );
+}
+
+#[test]
+fn loads_after_stores_are_forwarded() {
+    let module = compile("memory.sy", SOURCE, false);
+    let main = module.function("main").unwrap();
+    let loads: Vec<usize> = main
+        .blocks
+        .iter()
+        .map(|b| {
+            b.insts
+                .iter()
+                .filter(|i| matches!(i.kind, InstKind::Load(_)))
+                .count()
+        })
+        .collect();
+    // 入口块中store之后的load都被删除, 其他块开头的load保留.
+    assert_eq!(loads, [0, 0, 1, 1, 1]);
+    // bb1中把刚读出的y再存回去的store也被删除.
+    assert_eq!(main.blocks[1].insts.len(), 1);
+}
+
+#[test]
+fn equal_to_zero_inverts_the_inner_compare() {
+    // %1 = icmp lt %0, 5; %2 = icmp eq %1, 0; ret %2  =>  %1 = icmp ge %0, 5; ret %1
+    let mut f = Function::new("f", Type::I32, vec![Type::I32]);
+    let entry = f.new_block();
+    let lt = f.new_value(Type::I32);
+    let eq = f.new_value(Type::I32);
+    let inst = |dest: Option<Value>, kind| Inst {
+        dest,
+        ty: if dest.is_some() {
+            Type::I32
+        } else {
+            Type::Void
+        },
+        kind,
+    };
+    f.block_mut(entry).insts = vec![
+        inst(
+            Some(lt),
+            InstKind::Icmp(CmpOp::Lt, Operand::Value(Value(0)), Operand::Int(5)),
+        ),
+        inst(
+            Some(eq),
+            InstKind::Icmp(CmpOp::Eq, Operand::Value(lt), Operand::Int(0)),
+        ),
+        inst(None, InstKind::Ret(Some(Operand::Value(eq)))),
+    ];
+    assert!(peephole::run(&mut f));
+    f.validate().unwrap();
+    assert_eq!(
+        f.to_string(),
+        "fn i32 @f(%0: i32) {\nbb0:\n  %1 = icmp ge %0, 5\n  ret %1\n}\n"
+    );
+    assert!(!peephole::run(&mut f));
+    // 浮点运算不按整数的恒等式化简.
+    let mut g = Function::new("g", Type::F32, vec![Type::F32]);
+    let entry = g.new_block();
+    let sum = g.new_value(Type::F32);
+    g.block_mut(entry).insts = vec![
+        Inst {
+            dest: Some(sum),
+            ty: Type::F32,
+            kind: InstKind::Binary(BinOp::FAdd, Operand::Value(Value(0)), Operand::Float(0.0)),
+        },
+        inst(None, InstKind::Ret(Some(Operand::Value(sum)))),
+    ];
+    assert!(!peephole::run(&mut g));
+}
@@ -0,0 +1,111 @@
+mod common;
+
+use common::SourceSession;
+use sysy_alpha::parser::Node;
+use sysy_alpha::session::{CompileError, CompileOptions};
+use sysy_alpha::visit::node_map;
+use sysy_alpha::NodeType;
+
+/*
+    二元表达式按优先级表解析(Pratt): 优先级与SysY文法的mul_exp..l_or_exp相同, 都是左结合,
+    一元运算符比所有二元运算符结合得紧; 普通表达式(Exp)中没有关系和逻辑运算, 只有条件(Cond)中才有.
+*/
+
+fn parse(source: &str) -> Result<Vec<Node>, CompileError> {
+    SourceSession::new(
+        "precedence",
+        "precedence.sy",
+        source,
+        CompileOptions::default(),
+    )
+    .parse()
+}
+
+/* 给每个运算加上括号, 显示语法分析得到的结构. */
+fn shape(node: &Node) -> String {
+    match &node.node_type {
+        NodeType::BinOp(op, lhs, rhs) => format!("({} {:?} {})", shape(lhs), op, shape(rhs)),
+        NodeType::UnaryOp(op, operand) => format!("({:?} {})", op, shape(operand)),
+        NodeType::Access(name, ..) => name.clone(),
+        NodeType::Number(n) => n.to_string(),
+        _ => format!("{:?}", node.kind()),
+    }
+}
+
+/* if条件的结构. */
+fn condition(cond: &str) -> String {
+    let source = format!(
+        "int main() {{ int a, b, c, d, e, f, g, h, i, j; if ({}) return 1; return 0; }}\n",
+        cond
+    );
+    let ast = parse(&source).unwrap();
+    let map = node_map(&ast);
+    let cond = map
+        .values()
+        .find_map(|n| match &n.node_type {
+            NodeType::If(cond, ..) => Some(cond),
+            _ => None,
+        })
+        .expect("if statement");
+    shape(cond)
+}
+
+#[test]
+fn operators_bind_by_the_sysy_precedence_levels() {
+    assert_eq!(
+        condition("!a || b && c == d < e + f * g - h / i % j"),
+        "((Not a) Or (b And (c Equal (d Lesserthan ((e Plus (f Multi g)) Minus ((h Divide i) Mods j))))))"
+    );
+    assert_eq!(
+        condition("a * (b + c) > -d"),
+        "((a Multi (b Plus c)) Greaterthan (Minus d))"
+    );
+}
+
+#[test]
+fn binary_operators_associate_to_the_left() {
+    assert_eq!(condition("a - b - c"), "((a Minus b) Minus c)");
+    assert_eq!(
+        condition("a / b * c % d"),
+        "(((a Divide b) Multi c) Mods d)"
+    );
+    assert_eq!(condition("a < b >= c"), "((a Lesserthan b) GreatEqual c)");
+    assert_eq!(condition("a == b != c"), "((a Equal b) NotEqual c)");
+    assert_eq!(
+        condition("a || b || c && d && e"),
+        "((a Or b) Or ((c And d) And e))"
+    );
+}
+
+#[test]
+fn unary_operators_bind_tighter_than_binary_ones() {
+    assert_eq!(
+        condition("-a * - - b"),
+        "((Minus a) Multi (Minus (Minus b)))"
+    );
+    assert_eq!(condition("!a == +b"), "((Not a) Equal (Plus b))");
+}
+
+#[test]
+fn binary_nodes_span_both_operands() {
+    let source = "int main() { int a, b, c; a = b + c * (a - 1); return a; }\n";
+    let ast = parse(source).unwrap();
+    let mut spans: Vec<&str> = node_map(&ast)
+        .values()
+        .filter(|n| matches!(n.node_type, NodeType::BinOp(..)))
+        .map(|n| &source[n.span.start..n.span.end])
+        .collect();
+    spans.sort();
+    // 加了括号的表达式的范围包括括号.
+    assert_eq!(spans, ["(a - 1)", "b + c * (a - 1)", "c * (a - 1)"]);
+}
+
+#[test]
+fn relational_operators_only_appear_in_conditions() {
+    let Err(CompileError::Parse(errors)) = parse("int main() { int a, b; a = a < b; return a; }\n")
+    else {
+        panic!("`a < b` outside a condition should not parse");
+    };
+    assert_eq!(errors[0].found, "<");
+    assert_eq!(errors[0].expected.as_deref(), Some("';'"));
+}
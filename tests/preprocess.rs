@@ -0,0 +1,114 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::preprocess::{preprocess, preprocess_to_file};
+use sysy_alpha::session::{CompileOptions, Session};
+
+/*
+    预处理: #include只包含每个文件一次(循环包含也能结束), #define的宏在字符串和注释之外展开,
+    不支持的指令报告文件和行号; 插入的#line指令让之后的报错指向原始文件.
+*/
+
+fn path(dir: &TempDir, file: &str) -> String {
+    dir.join(file).to_string_lossy().into_owned()
+}
+
+#[test]
+fn include_cycles_include_each_file_once() {
+    let dir = TempDir::new("preprocess");
+    dir.write("a.sy", "#include \"b.sy\"\nint a;\n");
+    dir.write("b.sy", "#include \"a.sy\"\n#include \"b.sy\"\nint b;\n");
+    dir.write(
+        "main.sy",
+        "#include \"a.sy\"\n#include \"b.sy\"\nint main() { return a + b; }\n",
+    );
+    let output = preprocess(&path(&dir, "main.sy")).unwrap();
+    assert_eq!(output.matches("int a;").count(), 1, "{}", output);
+    assert_eq!(output.matches("int b;").count(), 1, "{}", output);
+    // b.sy在a.sy之前展开, 每个文件结束后用#line回到包含它的文件的下一行.
+    assert!(output.find("int b;") < output.find("int a;"), "{}", output);
+    assert!(
+        output.contains(&format!("#line 2 \"{}\"\n", path(&dir, "main.sy"))),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn macros_expand_outside_strings_and_comments() {
+    let dir = TempDir::new("preprocess");
+    dir.write(
+        "main.sy",
+        "#define N 4\n\
+         #define M N * 2\n\
+         int a[M]; // N\n\
+         int NN = 0x1N;\n\
+         int main() { putf(\"N=%d\\n\", N); /* M */ return M; }\n",
+    );
+    let output = preprocess(&path(&dir, "main.sy")).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    // #define所在的行变成空行, 行号保持不变.
+    assert_eq!(lines[1..3], ["", ""]);
+    assert_eq!(lines[3], "int a[4 * 2]; // N");
+    // 宏名只替换完整的标识符, 数字中的字母也不是标识符.
+    assert_eq!(lines[4], "int NN = 0x1N;");
+    assert_eq!(
+        lines[5],
+        "int main() { putf(\"N=%d\\n\", 4); /* M */ return 4 * 2; }"
+    );
+}
+
+#[test]
+fn unsupported_directives_report_file_and_line() {
+    let dir = TempDir::new("preprocess");
+    let cases = [
+        ("#define F(x) x\n", 1, "only object-like macros"),
+        (
+            "int a;\n#include <sylib.h>\n",
+            2,
+            "expected #include \"file\"",
+        ),
+        ("\n\n#include \"missing.sy\"\n", 3, "`missing.sy` not found"),
+        (
+            "#ifdef N\n#endif\n",
+            1,
+            "unsupported preprocessor directive `#ifdef N`",
+        ),
+    ];
+    for (source, line, message) in cases {
+        dir.write("main.sy", source);
+        let error = preprocess(&path(&dir, "main.sy")).unwrap_err();
+        assert_eq!(
+            (error.file.as_str(), error.line),
+            (path(&dir, "main.sy").as_str(), line)
+        );
+        assert!(error.message.contains(message), "{}", error);
+    }
+}
+
+#[test]
+fn errors_point_into_the_included_file() {
+    let dir = TempDir::new("preprocess");
+    dir.write(
+        "lib.sy",
+        "#define LIMIT 10\n\nint f() { return missing; }\n",
+    );
+    dir.write(
+        "main.sy",
+        "#include \"lib.sy\"\nint main() { return f() + LIMIT; }\n",
+    );
+    let expanded = preprocess_to_file(&path(&dir, "main.sy")).unwrap();
+    assert_eq!(expanded, path(&dir, "main.i"));
+    let session = Session::new(expanded, CompileOptions::default());
+    let checked = session.check().unwrap();
+    let errors: Vec<String> = checked
+        .errors()
+        .map(|d| session.engine().render(d))
+        .collect();
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert!(
+        errors[0].contains(&format!("{}:3:", path(&dir, "lib.sy"))),
+        "{}",
+        errors[0]
+    );
+}
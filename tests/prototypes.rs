@@ -0,0 +1,114 @@
+mod common;
+
+use common::SourceSession;
+use sysy_alpha::diagnostics::{Diagnostic, DiagnosticKind};
+use sysy_alpha::interp;
+use sysy_alpha::lower::lower;
+use sysy_alpha::session::CompileOptions;
+
+/*
+    函数原型: 原型可以重复出现, 与之后的定义合并, 形参名可以不同但返回类型和各形参类型必须一致;
+    定义之前的调用按原型检查, 因此可以写互相递归的函数.
+*/
+
+fn session(source: &str) -> SourceSession {
+    SourceSession::new(
+        "prototypes",
+        "prototypes.sy",
+        source,
+        CompileOptions::default(),
+    )
+}
+
+fn errors(source: &str) -> Vec<Diagnostic> {
+    let checked = session(source).check().unwrap();
+    checked.errors().cloned().collect()
+}
+
+#[test]
+fn prototypes_allow_mutual_recursion() {
+    let checked = session(
+        "int is_odd(int n);
+int is_even(int n);
+int is_even(int m) { if (m == 0) return 1; return is_odd(m - 1); }
+int is_odd(int n) { if (n == 0) return 0; return is_even(n - 1); }
+int main() { putint(is_even(10)); putint(is_odd(7)); return is_even(3); }
+",
+    )
+    .check()
+    .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    let outcome = interp::run(&lower(&checked.hir), b"").unwrap();
+    assert_eq!(outcome.output, b"11");
+    assert_eq!(outcome.exit_code, 0);
+}
+
+#[test]
+fn mismatched_prototype_and_definition_are_reported() {
+    let cases = [
+        "int f(int a);\nfloat f(int a) { return a; }\n",
+        "int f(int a);\nint f(float a) { return 1; }\n",
+        "int f(int a);\nint f(int a, int b) { return a; }\n",
+        "int f(int a[]);\nint f(int a[][2]) { return 1; }\n",
+        "int f(int a) { return a; }\nint f();\n",
+    ];
+    for case in cases {
+        let source = format!("{}int main() {{ return 0; }}\n", case);
+        let errors = errors(&source);
+        assert_eq!(errors.len(), 1, "{}: {:?}", case, errors);
+        assert_eq!(errors[0].kind, Some(DiagnosticKind::Redefinition));
+        assert!(
+            errors[0]
+                .message
+                .ends_with("conflicting declarations of function `f`"),
+            "{}",
+            errors[0].message
+        );
+        let labels: Vec<&str> = errors[0]
+            .labels
+            .iter()
+            .map(|l| l.message.as_str())
+            .collect();
+        assert_eq!(labels, ["signature differs", "previously declared here"]);
+    }
+
+    // 运行时库函数没有源代码位置, 只标出冲突的声明.
+    let runtime = errors("float getint();\nint main() { return 0; }\n");
+    assert_eq!(runtime.len(), 1, "{:?}", runtime);
+    assert_eq!(runtime[0].labels.len(), 1);
+}
+
+#[test]
+fn repeated_prototypes_merge_but_definitions_do_not() {
+    let merged = errors(
+        "int f(int a);
+int f(int b);
+int f(int c) { return c; }
+int f(int d);
+int main() { return f(1); }
+",
+    );
+    assert!(merged.is_empty(), "{:?}", merged);
+
+    let redefined = errors(
+        "int f(int a) { return a; }
+int f(int a) { return a + 1; }
+int main() { return f(1); }
+",
+    );
+    assert_eq!(redefined.len(), 1, "{:?}", redefined);
+    assert_eq!(redefined[0].kind, Some(DiagnosticKind::Redefinition));
+    assert!(redefined[0].message.contains("has already defined"));
+}
+
+#[test]
+fn calls_before_the_definition_are_checked_against_the_prototype() {
+    let errors = errors(
+        "int f(int a, int b);
+int main() { return f(1); }
+int f(int a, int b) { return a + b; }
+",
+    );
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert_eq!(errors[0].kind, Some(DiagnosticKind::ArgumentCount));
+}
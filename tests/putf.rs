@@ -0,0 +1,65 @@
+mod common;
+
+use common::SourceSession;
+use sysy_alpha::session::CompileOptions;
+use sysy_alpha::utils::emit_source;
+use sysy_alpha::verify::verify;
+use sysy_alpha::NodeType;
+
+/*
+    putf(format, ...): 格式串是字符串字面量, 作为Str节点保存在实参中.
+    只有putf可以接受字符串, 其余实参个数与格式串中的转换说明一致.
+*/
+
+fn session(name: &str, source: &str) -> SourceSession {
+    SourceSession::new("putf", name, source, CompileOptions::default())
+}
+
+#[test]
+fn format_string_is_a_call_argument() {
+    let session = session(
+        "ok.sy",
+        "int main() { int a = 3; float f = 1.5;\n\
+         putf(\"a = %d, f = %f\\n\", a, f);\n\
+         putf(\"100%% \\\"done\\\"\\n\");\n\
+         return 0; }\n",
+    );
+    let checked = session.check().unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    assert!(verify("semantic", &checked.annotated_ast).is_empty());
+
+    let emitted = emit_source(&session.parse().unwrap());
+    assert!(emitted.contains("putf(\"a = %d, f = %f\\n\", a, f);"));
+    assert!(emitted.contains("putf(\"100%% \\\"done\\\"\\n\");"));
+
+    let call = sysy_alpha::visit::node_map(&checked.annotated_ast)
+        .into_values()
+        .find_map(|n| n.as_call().filter(|c| c.1.len() == 3))
+        .expect("first putf");
+    assert!(matches!(&call.1[0].node_type, NodeType::Str(s) if s == "a = %d, f = %f\n"));
+}
+
+#[test]
+fn strings_are_only_accepted_by_putf() {
+    let checked = session(
+        "bad.sy",
+        "int main() { int a = 3;\n\
+         putf(\"%d %d\\n\", a);\n\
+         putf(a);\n\
+         putint(\"x\");\n\
+         putf(\"%d\", \"y\");\n\
+         return 0; }\n",
+    )
+    .check()
+    .unwrap();
+    let messages: Vec<&str> = checked
+        .diagnostics
+        .iter()
+        .map(|d| d.message.as_str())
+        .collect();
+    assert_eq!(messages.len(), 4, "{:?}", messages);
+    assert!(messages[0].contains("expects 2 arguments instead of 1"));
+    assert!(messages[1].contains("first argument of putf should be a format string"));
+    assert!(messages[2].contains("string literal passed to putint"));
+    assert!(messages[3].contains("only the first argument of putf can be a string"));
+}
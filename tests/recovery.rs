@@ -0,0 +1,63 @@
+mod common;
+
+use common::SourceSession;
+use sysy_alpha::session::{CompileError, CompileOptions};
+use sysy_alpha::visit::node_map;
+use sysy_alpha::NodeType;
+
+/*
+    出错后语法分析仍然返回部分AST: 所有声明和函数都在, 无法解析的表达式是带位置的Nil占位节点.
+    语义分析可以在部分AST上运行, 占位节点本身不再引起语义错误.
+*/
+
+fn session(name: &str, source: &str) -> SourceSession {
+    SourceSession::new("recovery", name, source, CompileOptions::default())
+}
+
+const BROKEN: &str = "int g = 1;\n\
+int f(int x) { return x + ; }\n\
+int h(int y) { int z = y * 2 return z; }\n\
+int main() { int a = f(1) + h(2); if (a > ) a = 1; return g + f(a, a); }\n";
+
+#[test]
+fn every_top_level_item_survives_syntax_errors() {
+    let session = session("broken.sy", BROKEN);
+    assert!(matches!(session.parse(), Err(CompileError::Parse(_))));
+
+    let (ast, errors) = session.parse_recovering().unwrap();
+    let lines: Vec<usize> = errors.iter().map(|e| e.line_no).collect();
+    assert_eq!(lines, vec![2, 3, 4], "{:?}", errors);
+
+    let names: Vec<&str> = ast
+        .iter()
+        .map(|node| match &node.node_type {
+            NodeType::Func(_, name, ..) => name.as_str(),
+            NodeType::DeclStmt(decls) => decls[0].as_decl().unwrap().1,
+            _ => panic!("unexpected top-level node"),
+        })
+        .collect();
+    assert_eq!(names, vec!["g", "f", "h", "main"]);
+
+    // "x + ;"的右操作数是一个空的占位节点, 位置在';'处.
+    let placeholders: Vec<_> = node_map(&ast)
+        .into_values()
+        .filter(|n| matches!(n.node_type, NodeType::Nil))
+        .map(|n| (n.span.start, n.span.end))
+        .collect();
+    let semicolon = BROKEN.find("+ ;").unwrap() + 2;
+    assert!(
+        placeholders.contains(&(semicolon, semicolon)),
+        "{:?}",
+        placeholders
+    );
+}
+
+#[test]
+fn semantic_analysis_runs_on_the_partial_ast() {
+    let (checked, errors) = session("checked.sy", BROKEN).check_recovering().unwrap();
+    assert_eq!(errors.len(), 3);
+    // 只有main中调用f的实参个数是语义错误, 占位节点不再引起类型错误.
+    assert_eq!(checked.diagnostics.len(), 1, "{:?}", checked.diagnostics);
+    assert!(checked.diagnostics[0].message.contains("Error type 9"));
+    assert_eq!(checked.annotated_ast.len(), 4);
+}
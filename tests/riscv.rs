@@ -0,0 +1,194 @@
+mod common;
+
+use common::TempDir;
+use std::process::Command;
+use sysy_alpha::codegen::{frame::Frame, riscv};
+use sysy_alpha::ir::Module;
+use sysy_alpha::lower::lower;
+use sysy_alpha::mem2reg::promote_module;
+use sysy_alpha::passes::{OptLevel, PassManager};
+use sysy_alpha::session::{CompileOptions, Session};
+use sysy_alpha::target::Target;
+
+/*
+    RISC-V后端: 生成的汇编文本; 机器上有llvm-mc时汇编一遍, 检查指令和伪指令都是合法的.
+*/
+
+fn compile(name: &str, source: &str, level: OptLevel) -> Module {
+    let dir = TempDir::new("riscv");
+    let path = dir.write(name, source);
+    let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    let mut module = lower(&checked.hir);
+    promote_module(&mut module);
+    PassManager::for_level(level).run(&mut module);
+    module
+}
+
+/* 用llvm-mc汇编, 返回错误信息; 没有llvm-mc时返回None. */
+fn assemble(name: &str, asm: &str) -> Option<Result<(), String>> {
+    let dir = TempDir::new("riscv");
+    let path = dir.write(&format!("{}.s", name), asm);
+    let output = Command::new("llvm-mc")
+        .args(["-triple=riscv64", "-mattr=+m,+f,+d", "-filetype=obj", "-o"])
+        .arg(path.with_extension("o"))
+        .arg(&path)
+        .output()
+        .ok()?;
+    if output.status.success() {
+        Some(Ok(()))
+    } else {
+        Some(Err(String::from_utf8_lossy(&output.stderr).into_owned()))
+    }
+}
+
+const PROGRAM: &str = "int g[4] = {1, 2, 3};
+float scale = 0.5;
+float mix(int a, float b, int c[]) { return a * b + c[1] - scale; }
+int many(int a, int b, int c, int d, int e, int f, int h, int i, int j, int k) {
+  return a - b + c - d + e - f + h - i + j - k;
+}
+int main() {
+  int local[600];
+  int i = 0;
+  while (i < 600) {
+    local[i] = i * 3 % 7;
+    i = i + 1;
+  }
+  float x = mix(local[599], 1.5, g);
+  if (x > 1.0 || g[2] != 3) putf(\"%d %f\\n\", many(1, 2, 3, 4, 5, 6, 7, 8, 9, 10), x);
+  return -x;
+}
+";
+
+#[test]
+fn functions_have_prologue_and_epilogue() {
+    let module = compile("shape.sy", PROGRAM, OptLevel::O0);
+    let asm = riscv::emit(&module).unwrap();
+    for name in ["mix", "many", "main"] {
+        assert!(asm.contains(&format!("  .globl {}\n", name)), "{}", asm);
+        assert!(
+            asm.contains(&format!("  .size {}, .-{}\n", name, name)),
+            "{}",
+            asm
+        );
+    }
+    // 帧大于2047字节, sp的调整和远处的槽都要经过寄存器.
+    assert!(asm.contains("  sub sp, sp, t0\n"), "{}", asm);
+    assert!(asm.contains("  add t6, sp, t6\n"), "{}", asm);
+    assert!(asm.contains("  sd ra, "), "{}", asm);
+    // 第9, 10个实参在栈上, 变长参数中的float提升为double放在整数寄存器中.
+    assert!(asm.contains("  sd t0, 8(sp)\n"), "{}", asm);
+    assert!(asm.contains("  fmv.x.d a2, ft0\n"), "{}", asm);
+    assert!(asm.contains("  fcvt.w.s t0, ft0, rtz\n"), "{}", asm);
+    assert!(
+        asm.contains("g:\n  .word 1\n  .word 2\n  .word 3\n  .word 0\n"),
+        "{}",
+        asm
+    );
+    assert!(asm.contains("scale:\n  .word 1056964608\n"), "{}", asm);
+    assert!(
+        asm.contains(".L.str.0:\n  .asciz \"%d %f\\n\"\n"),
+        "{}",
+        asm
+    );
+}
+
+#[test]
+fn globals_go_to_data_rodata_and_bss() {
+    let module = compile(
+        "sections.sy",
+        "int zeros[1000];
+int data[8] = {1, 2};
+const int table[4] = {5, 0, 0, 7};
+int main() {
+  const int local[3] = {4, 5, 6};
+  int i = getint();
+  return local[i] + table[i] + data[i] + zeros[i];
+}
+",
+        OptLevel::O0,
+    );
+    let asm = riscv::emit(&module).unwrap();
+    let data = asm.find("\n  .data\n").unwrap();
+    let rodata = asm.find("\n  .section .rodata\n").unwrap();
+    let bss = asm.find("\n  .bss\n").unwrap();
+    assert!(data < rodata && rodata < bss, "{}", asm);
+    assert!(
+        asm[data..rodata].contains("data:\n  .word 1\n  .word 2\n  .zero 24\n"),
+        "{}",
+        asm
+    );
+    assert!(
+        asm[rodata..bss].contains("table:\n  .word 5\n  .zero 8\n  .word 7\n"),
+        "{}",
+        asm
+    );
+    // 局部常量数组不在栈上初始化, 也不导出.
+    assert!(
+        asm[rodata..bss].contains("main.local:\n  .word 4\n"),
+        "{}",
+        asm
+    );
+    assert!(!asm.contains(".globl main.local"), "{}", asm);
+    assert!(
+        asm[bss..].contains("  .size zeros, 4000\n  .p2align 3\nzeros:\n  .zero 4000\n"),
+        "{}",
+        asm
+    );
+    assert!(!asm.contains("  .word 0\n"), "{}", asm);
+}
+
+#[test]
+fn stack_arguments_are_passed_and_received() {
+    let module = compile(
+        "stack.sy",
+        "int f(int a, int b, int c, int d, int e, int g, int h, int i, int j, float x, int k[]) {
+  return a + j + x + k[0];
+}
+int main() { int k[1] = {3}; return f(1, 2, 3, 4, 5, 6, 7, 8, 9, 1.5, k); }
+",
+        OptLevel::O0,
+    );
+    let asm = riscv::emit(&module).unwrap();
+    let (f, main) = asm.split_at(asm.find("\nmain:").unwrap());
+    // 第9个int和数组的地址在栈上, float仍然用fa0.
+    assert!(main.contains("  li t0, 9\n  sd t0, 0(sp)\n"), "{}", main);
+    assert!(main.contains("  sd t0, 8(sp)\n  call f\n"), "{}", main);
+    assert!(main.contains("  fmv.w.x fa0, t5\n"), "{}", main);
+    let frame = Frame::build(&module, module.function("f").unwrap(), Target::Riscv64);
+    assert!(
+        f.contains(&format!("  addi sp, sp, -{}\n", frame.size)),
+        "{}",
+        f
+    );
+    assert!(
+        f.contains(&format!("  lw t0, {}(sp)\n", frame.size)),
+        "{}",
+        f
+    );
+    assert!(
+        f.contains(&format!("  ld t0, {}(sp)\n", frame.size + 8)),
+        "{}",
+        f
+    );
+    assert!(f.contains("  fsw fa0, "), "{}", f);
+    assert!(f.contains("  fcvt.w.s t0, ft0, rtz\n"), "{}", f);
+}
+
+#[test]
+fn output_assembles() {
+    for (i, level) in [OptLevel::O0, OptLevel::O1, OptLevel::O2]
+        .into_iter()
+        .enumerate()
+    {
+        let module = compile("assemble.sy", PROGRAM, level);
+        let asm = riscv::emit(&module).unwrap();
+        match assemble(&format!("assemble{}", i), &asm) {
+            None => return,
+            Some(result) => result.unwrap_or_else(|e| panic!("{}\n{}", e, asm)),
+        }
+    }
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::ir::{InstKind, Module};
+use sysy_alpha::lower::lower;
+use sysy_alpha::mem2reg::promote_module;
+use sysy_alpha::sccp;
+use sysy_alpha::session::{CompileOptions, Session};
+
+/*
+    SCCP: 常量沿phi和分支传播, 不会执行的边和块被删除; 不确定的值和副作用保持不变.
+*/
+
+fn optimized(name: &str, source: &str) -> Module {
+    let dir = TempDir::new("sccp");
+    let path = dir.write(name, source);
+    let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    let mut module = lower(&checked.hir);
+    promote_module(&mut module);
+    sccp::run_module(&mut module);
+    module
+        .validate()
+        .unwrap_or_else(|e| panic!("{}\n{}", e, module));
+    module
+}
+
+#[test]
+fn constants_flow_through_branches() {
+    let module = optimized(
+        "branch.sy",
+        "int main() {
+  int x = 3, y;
+  if (x > 2) y = 1; else y = getint();
+  return y * 2;
+}
+",
+    );
+    assert_eq!(
+        module.function("main").unwrap().to_string(),
+        "fn i32 @main() {\nbb0:\n  ret 2\n}\n"
+    );
+    // else分支被删除, getint不再被调用.
+    assert!(!module.to_string().contains("call i32 @getint"));
+}
+
+#[test]
+fn loop_invariant_constants() {
+    let module = optimized(
+        "loop.sy",
+        "int main() {
+  int i = 0, c = 5, s = 0;
+  while (i < getint()) {
+    if (c != 5) c = c + 1;
+    s = s + c;
+    i = i + 1;
+  }
+  return s + c;
+}
+",
+    );
+    let main = module.function("main").unwrap();
+    let text = main.to_string();
+    // c始终是5: 它的phi和c != 5的分支都消失了.
+    assert!(!text.contains("icmp ne"), "{}", text);
+    assert!(text.contains("add i32 %1, 5"), "{}", text);
+    let phis = main
+        .blocks
+        .iter()
+        .flat_map(|b| &b.insts)
+        .filter(|i| matches!(i.kind, InstKind::Phi(_)))
+        .count();
+    assert_eq!(phis, 2, "{}", text);
+}
+
+#[test]
+fn unsafe_folds_are_kept() {
+    let module = optimized(
+        "div.sy",
+        "int main() {
+  int z = 0;
+  float f = 10000000000.0;
+  putint(f);
+  return 7 / z;
+}
+",
+    );
+    let text = module.to_string();
+    assert!(text.contains("div i32 7, 0"), "{}", text);
+    assert!(text.contains("ftoi 10000000000.0"), "{}", text);
+}
@@ -0,0 +1,154 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::interp;
+use sysy_alpha::ir::{Block, InstKind, Module, Operand, Value};
+use sysy_alpha::lower::lower;
+use sysy_alpha::mem2reg::promote_module;
+use sysy_alpha::schedule::{self, LatencyTable};
+use sysy_alpha::session::{CompileOptions, Session};
+use sysy_alpha::target::Target;
+
+/*
+    指令调度: 隐藏load的延迟, 保持内存访问和调用的顺序, 寄存器紧张时控制活跃的值的个数.
+*/
+
+fn compile(name: &str, source: &str) -> Module {
+    let dir = TempDir::new("schedule");
+    let path = dir.write(name, source);
+    let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    let mut module = lower(&checked.hir);
+    promote_module(&mut module);
+    module
+}
+
+fn uses(block: &Block, value: Value) -> Vec<usize> {
+    block
+        .insts
+        .iter()
+        .enumerate()
+        .filter(|(_, i)| i.kind.operands().contains(&&Operand::Value(value)))
+        .map(|(n, _)| n)
+        .collect()
+}
+
+/* 块中同时活跃的(在块中定义, 之后还要用到的)值最多有几个. */
+fn max_live(block: &Block) -> usize {
+    let mut live: Vec<Value> = vec![];
+    let mut most = 0;
+    for (n, inst) in block.insts.iter().enumerate() {
+        live.retain(|v| uses(block, *v).iter().any(|u| *u > n));
+        if let Some(dest) = inst.dest {
+            if !uses(block, dest).is_empty() {
+                live.push(dest);
+            }
+        }
+        most = most.max(live.len());
+    }
+    most
+}
+
+#[test]
+fn independent_instructions_fill_load_latency() {
+    let mut module = compile(
+        "latency.sy",
+        "int f(int p[], int x, int y) {
+  int v = p[0] + 1;
+  return v + x * y + (x - y);
+}
+int main() { int a[1] = {4}; return f(a, 2, 3); }
+",
+    );
+    assert!(schedule::run_module(&mut module, &LatencyTable::default()));
+    module.validate().unwrap();
+    let block = &module.function("f").unwrap().blocks[0];
+    let (load, inst) = block
+        .insts
+        .iter()
+        .enumerate()
+        .find(|(_, i)| matches!(i.kind, InstKind::Load(_)))
+        .unwrap();
+    // 用到load的结果之前至少隔了两条别的指令.
+    let first_use = uses(block, inst.dest.unwrap())[0];
+    assert!(first_use >= load + 3, "{}", module);
+    assert_eq!(interp::run(&module, b"").unwrap().exit_code, 10);
+}
+
+#[test]
+fn memory_and_calls_keep_their_order() {
+    let source = "int g[4];
+int peek() { return g[0]; }
+int main() {
+  int l[2];
+  g[0] = getint();
+  l[0] = g[0] * 3;
+  putint(peek());
+  g[0] = 5;
+  putch(10);
+  l[1] = g[0] + l[0];
+  putint(l[1]);
+  putint(peek());
+  return l[0];
+}
+";
+    let module = compile("order.sy", source);
+    let expected = interp::run(&module, b"7").unwrap();
+    for target in Target::ALL {
+        let mut scheduled = module.clone();
+        schedule::run_module(&mut scheduled, &LatencyTable::for_target(target));
+        scheduled.validate().unwrap();
+        assert_eq!(
+            interp::run(&scheduled, b"7").unwrap().output,
+            expected.output
+        );
+        let calls: Vec<&str> = scheduled
+            .function("main")
+            .unwrap()
+            .blocks
+            .iter()
+            .flat_map(|b| &b.insts)
+            .filter_map(|i| match &i.kind {
+                InstKind::Call(name, _) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            calls,
+            ["getint", "peek", "putint", "putch", "putint", "peek", "putint"]
+        );
+    }
+}
+
+#[test]
+fn register_pressure_limits_hoisting() {
+    let source = "int a[8];
+int main() {
+  int i = getint();
+  return a[i] * 3 + a[i + 1] * 5 + a[i + 2] * 7 + a[i + 3] * 9 + a[i + 4] * 11;
+}
+";
+    let mut roomy = compile("pressure.sy", source);
+    let mut tight = roomy.clone();
+    schedule::run_module(&mut roomy, &LatencyTable::default());
+    let few = LatencyTable {
+        registers: 2,
+        ..LatencyTable::default()
+    };
+    schedule::run_module(&mut tight, &few);
+    let live = |m: &Module| max_live(&m.function("main").unwrap().blocks[0]);
+    assert!(live(&tight) < live(&roomy), "{}\n{}", tight, roomy);
+    for module in [&roomy, &tight] {
+        module.validate().unwrap();
+    }
+
+    let (riscv, arm) = (
+        LatencyTable::for_target(Target::Riscv64),
+        LatencyTable::for_target(Target::Armv7),
+    );
+    assert!(arm.registers < riscv.registers);
+    assert_eq!(LatencyTable::default(), riscv);
+    assert_eq!(riscv.latency(&InstKind::Load(Operand::Int(0))), riscv.load);
+}
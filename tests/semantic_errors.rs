@@ -0,0 +1,378 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::diagnostics::DiagnosticKind;
+use sysy_alpha::lexer::LangLevel;
+use sysy_alpha::session::{CompileOptions, Session};
+
+/*
+    semantic()不打印, 而是返回带有DiagnosticKind的诊断; 出错的程序不会让语义分析panic.
+*/
+
+fn kinds(name: &str, source: &str) -> Vec<DiagnosticKind> {
+    let dir = TempDir::new("semantic");
+    let path = dir.write(name, source);
+    let options = CompileOptions {
+        level: LangLevel::Extended,
+        ..Default::default()
+    };
+    let checked = Session::new(path.to_string_lossy(), options)
+        .check()
+        .unwrap();
+    // 所有语义错误的信息都以"Error type N at this line: "开头.
+    for d in checked.errors() {
+        let error_type = d
+            .message
+            .split_once(" at this line: ")
+            .and_then(|(head, _)| head.strip_prefix("Error type "));
+        assert!(
+            error_type.is_some_and(|n| n.parse::<u32>().is_ok()),
+            "{}",
+            d.message
+        );
+    }
+    checked
+        .diagnostics
+        .iter()
+        .map(|d| d.kind.expect("semantic diagnostics have a kind"))
+        .collect()
+}
+
+#[test]
+fn each_error_has_a_kind() {
+    use DiagnosticKind::*;
+    let cases = [
+        ("undefined.sy", "int main() { return x; }", UndefinedSymbol),
+        (
+            "redefined.sy",
+            "int main() { int a; int a; return 0; }",
+            Redefinition,
+        ),
+        (
+            "not_func.sy",
+            "int main() { int a; return a(); }",
+            NotAFunction,
+        ),
+        (
+            "args.sy",
+            "int f(int a) { return a; }\nint main() { return f(); }",
+            ArgumentCount,
+        ),
+        (
+            "break.sy",
+            "int main() { break; return 0; }",
+            BreakOutsideLoop,
+        ),
+        (
+            "continue.sy",
+            "int main() { continue; return 0; }",
+            ContinueOutsideLoop,
+        ),
+        (
+            "const.sy",
+            "const int c = 1;\nint main() { c = 2; return 0; }",
+            AssignToConstant,
+        ),
+        (
+            "return.sy",
+            "void f() { return 1; }\nint main() { return 0; }",
+            TypeMismatch,
+        ),
+        (
+            "dims.sy",
+            "int a[2];\nint main() { return a[0][1]; }",
+            IndexMismatch,
+        ),
+        (
+            "global.sy",
+            "int b;\nconst int a = b;\nint main() { return 0; }",
+            NotConstant,
+        ),
+        (
+            "size.sy",
+            "int a[-1];\nint main() { return 0; }",
+            InvalidArray,
+        ),
+        (
+            "long_init.sy",
+            "int a[2] = {1, 2, 3};\nint main() { return a[0]; }",
+            InvalidArray,
+        ),
+        (
+            "condition.sy",
+            "void f() {}\nint main() { if (f()) return 1; return 0; }",
+            TypeMismatch,
+        ),
+        (
+            "switch.sy",
+            "int a[2];\nint main() { switch (a) { default: break; } return 0; }",
+            TypeMismatch,
+        ),
+        (
+            "divide.sy",
+            "int a[4 / (2 - 2)];\nint main() { return 0; }",
+            DivisionByZero,
+        ),
+        ("no_main.sy", "int f() { return 0; }", InvalidMain),
+        ("void_main.sy", "void main() { }", InvalidMain),
+        (
+            "main_args.sy",
+            "int main(int argc) { return argc; }",
+            InvalidMain,
+        ),
+        (
+            "label.sy",
+            "int main() { switch (1) { case 1: break; case 1: break; } return 0; }",
+            DuplicateLabel,
+        ),
+    ];
+    for (name, source, kind) in cases {
+        let found = kinds(name, source);
+        assert_eq!(found.first(), Some(&kind), "{}: {:?}", name, found);
+    }
+}
+
+#[test]
+fn bad_constant_expressions_do_not_panic() {
+    let sources = [
+        "int f() { return 1; }\nconst int a = f();",
+        "const int c[2] = {1, 2};\nconst int a = c[5];",
+        "const int c[2] = {1, 2};\nconst int a = c;",
+        "const int c[2] = {1, 2};\nconst int a = c[0][1];",
+        "const int a = x;",
+        "int g = getint();",
+        "float x = 1;\nint c[x];",
+        "const int z = 0;\nconst int a = 1 % z;",
+        "int g = 7 / 0;",
+    ];
+    for (i, source) in sources.iter().enumerate() {
+        let program = format!("{}\nint main() {{ return 0; }}\n", source);
+        let found = kinds(&format!("const{}.sy", i), &program);
+        assert!(!found.is_empty(), "{:?}", source);
+    }
+}
+
+#[test]
+fn constant_indexes_are_bounds_checked() {
+    let source = "int f(int p[][3]) { return p[7][2]; }\n\
+                  int main() { int a[2][3]; a[1][3] = 1; a[-1][0] = 2; return f(a) + a[1][2] + a[2][0]; }";
+    let dir = TempDir::new("semantic");
+    let path = dir.write("bounds.sy", source);
+    let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap();
+    let messages: Vec<&str> = checked.errors().map(|d| d.message.as_str()).collect();
+    // 形参p的第一维长度未知, p[7]不检查.
+    assert_eq!(
+        messages,
+        vec![
+            "Error type 8 at this line: Index 3 out of bounds for `a`",
+            "Error type 8 at this line: Index -1 out of bounds for `a`",
+            "Error type 8 at this line: Index 2 out of bounds for `a`",
+        ]
+    );
+}
+
+#[test]
+fn void_calls_have_no_value() {
+    let source = "void f() { }\n\
+                  int g(int a) { return a; }\n\
+                  void h() { f(); return f(); }\n\
+                  int k() { return; }\n\
+                  int main() { int x = f(); h(); putint(g(f())); return x + k(); }";
+    let dir = TempDir::new("semantic");
+    let path = dir.write("void.sy", source);
+    let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap();
+    let messages: Vec<&str> = checked.errors().map(|d| d.message.as_str()).collect();
+    // 语句f();和h();没有问题; 用作值的调用只报告一次"used as a value", 不再连带报告类型不符.
+    assert_eq!(
+        messages,
+        vec![
+            "Error type 7 at this line: void function f used as a value",
+            "Error type 10 at this line: void function h should not return a value",
+            "Error type 10 at this line: function k should return a value of type int",
+            "Error type 7 at this line: void function f used as a value",
+            "Error type 10 at this line: void function f passed as an argument to g",
+        ]
+    );
+}
+
+#[test]
+fn void_variables_are_reported_at_the_declaration() {
+    let source = "void g[2];\nint main() { void y; y = 1; return y; }\n";
+    let dir = TempDir::new("semantic");
+    let path = dir.write("void_decl.sy", source);
+    let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap();
+    // 没有语法错误, 每个void变量各报告一次, 之后按int继续分析.
+    let errors: Vec<(&str, &str)> = checked
+        .errors()
+        .map(|d| {
+            let span = d.labels[0].span;
+            (d.message.as_str(), &source[span.start..span.end])
+        })
+        .collect();
+    assert_eq!(
+        errors,
+        vec![
+            (
+                "Error type 7 at this line: Variable g declared void",
+                "g[2]"
+            ),
+            ("Error type 7 at this line: Variable y declared void", "y"),
+        ]
+    );
+}
+
+#[test]
+fn arguments_of_the_wrong_shape_name_both_types() {
+    let source = "int s(int x) { return x; }\n\
+                  int arr(int a[], float b[]) { return a[0] + b[0]; }\n\
+                  int main() { int a[2][3]; float f[2]; int x = 1; s(a[0]); arr(x, f); arr(a[1], a[0]); return 0; }";
+    let dir = TempDir::new("semantic");
+    let path = dir.write("args.sy", source);
+    let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap();
+    let errors: Vec<(&str, &str)> = checked
+        .errors()
+        .map(|d| (d.message.as_str(), d.labels[0].message.as_str()))
+        .collect();
+    assert_eq!(
+        errors,
+        vec![
+            (
+                "Error type 10 at this line: array passed to scalar parameter `x` in function call s",
+                "expected `int`, found `int[3]`"
+            ),
+            (
+                "Error type 10 at this line: scalar passed to array parameter `a` in function call arr",
+                "expected `int[]`, found `int`"
+            ),
+            (
+                "Error type 10 at this line: mismatched array element type for parameter `b` in function call arr",
+                "expected `float[]`, found `int[3]`"
+            ),
+        ]
+    );
+}
+
+#[test]
+fn undefined_names_suggest_similar_symbols() {
+    let source = "int length = 3;
+int sum(int a, int b) { return a + b; }
+int main() { int y = x + lenght; putint(smu(1, 2)); return getnit() + y; }
+";
+    let dir = TempDir::new("semantic");
+    let path = dir.write("typos.sy", source);
+    let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap();
+    let errors: Vec<(&str, Option<&str>)> = checked
+        .errors()
+        .map(|d| {
+            let fix = d.fixes.first().map(|f| {
+                assert_eq!(
+                    source[f.span.start..f.span.end].len(),
+                    f.replacement.len(),
+                    "the fix replaces only the name"
+                );
+                f.replacement.as_str()
+            });
+            (d.message.as_str(), fix)
+        })
+        .collect();
+    assert_eq!(
+        errors,
+        vec![
+            ("Error type 1 at this line: undefined variable \"x\".", None),
+            (
+                "Error type 1 at this line: undefined variable \"lenght\", did you mean `length`?",
+                Some("length")
+            ),
+            (
+                "Error type 3 at this line: undefined function `\"smu\"`, did you mean `sum`?",
+                Some("sum")
+            ),
+            (
+                "Error type 3 at this line: undefined function `\"getnit\"`, did you mean `getint`?",
+                Some("getint")
+            ),
+        ]
+    );
+}
+
+#[test]
+fn identical_errors_are_reported_once() {
+    // N在a的维度中再次求值, 除以0的错误在同一位置又报告了一次.
+    let dir = TempDir::new("semantic");
+    let path = dir.write(
+        "dedup.sy",
+        "const int N = 1 / 0;\nint a[N];\nint main() { return a[0]; }\n",
+    );
+    let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap();
+    let divisions: Vec<_> = checked
+        .errors()
+        .filter(|d| d.kind == Some(DiagnosticKind::DivisionByZero))
+        .collect();
+    assert_eq!(divisions.len(), 1, "{:?}", checked.diagnostics);
+}
+
+#[test]
+fn repeated_uses_of_an_undefined_name_collapse() {
+    let dir = TempDir::new("semantic");
+    let path = dir.write(
+        "collapse.sy",
+        "int main() {\n  int s = y + y;\n  s = s + y + y + y;\n  return s + z;\n}\n",
+    );
+    let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap();
+    let undefined = |name: &str| -> Vec<_> {
+        checked
+            .errors()
+            .filter(|d| d.message.contains(&format!("\"{}\"", name)))
+            .collect()
+    };
+    // 5次使用只报告第一处, 附带其余的个数.
+    let [y] = undefined("y")[..] else {
+        panic!("{:?}", checked.diagnostics);
+    };
+    assert_eq!(y.labels[0].span.start, "int main() {\n  int s = ".len());
+    assert_eq!(y.notes, ["and 4 more uses"]);
+    let [z] = undefined("z")[..] else {
+        panic!("{:?}", checked.diagnostics);
+    };
+    assert!(z.notes.is_empty());
+}
+
+#[test]
+fn messages_repeated_more_than_three_times_collapse() {
+    use DiagnosticKind::*;
+    let dir = TempDir::new("semantic");
+    let path = dir.write(
+        "loops.sy",
+        "int main() {\n  break;\n  break;\n  break;\n  break;\n  continue;\n  continue;\n  return 0;\n}\n",
+    );
+    let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap();
+    let notes: Vec<_> = checked
+        .errors()
+        .map(|d| (d.kind.unwrap(), d.notes.clone()))
+        .collect();
+    // 4个break合并成一条; 2个continue没有超过3次, 分别报告.
+    assert_eq!(
+        notes,
+        vec![
+            (BreakOutsideLoop, vec!["and 3 more uses".to_string()]),
+            (ContinueOutsideLoop, vec![]),
+            (ContinueOutsideLoop, vec![]),
+        ]
+    );
+}
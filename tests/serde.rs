@@ -0,0 +1,62 @@
+#![cfg(feature = "serde")]
+
+mod common;
+
+use common::SourceFile;
+use sysy_alpha::lexer::Token;
+use sysy_alpha::parser::Node;
+use sysy_alpha::session::{CompileOptions, Session};
+use sysy_alpha::utils::{emit_source, print_tree_json};
+
+/*
+    serde feature: AST和token可以序列化成JSON再反序列化回来.
+    运行: cargo test --features serde
+*/
+
+#[test]
+fn ast_round_trips_through_json() {
+    let file = SourceFile::new(
+        "serde",
+        "ast.sy",
+        "const float PI = 3.5;\nint a[2][2] = {{1}, {2, 3}};\nint main() { if (a[1][0] > 1) return -a[0][0]; return 0; }\n",
+    );
+    let path = file.path_string();
+    let session = Session::new(path.clone(), CompileOptions::default());
+    let ast = session.parse().unwrap();
+
+    let json = serde_json::to_string(&ast).unwrap();
+    let back: Vec<Node> = serde_json::from_str(&json).unwrap();
+    assert_eq!(emit_source(&back), emit_source(&ast));
+    assert_eq!(back[2].id, ast[2].id);
+    assert_eq!(back[2].span, ast[2].span);
+
+    let checked = session.check().unwrap();
+    print_tree_json(&checked.annotated_ast, std::path::Path::new(&path), "json");
+    let written =
+        std::fs::read_to_string(std::path::Path::new(&path).with_extension("json")).unwrap();
+    let value: serde_json::Value = serde_json::from_str(&written).unwrap();
+    assert_eq!(value.as_array().unwrap().len(), 3);
+    let pi = &value[0]["node_type"]["DeclStmt"][0];
+    assert_eq!(pi["node_type"]["Decl"][0], "ConstFloat");
+    assert_eq!(pi["node_type"]["Decl"][1], "PI");
+}
+
+#[test]
+fn tokens_round_trip_through_json() {
+    let file = SourceFile::new("serde", "tokens.sy", "int main() {\n  return 42;\n}\n");
+    let path = file.path_string();
+    let tokens = Session::new(path, CompileOptions::default())
+        .tokens(false)
+        .unwrap();
+
+    let json = serde_json::to_string(&tokens).unwrap();
+    assert!(json.contains("\"text\":\"return\""));
+    let back: Vec<Token> = serde_json::from_str(&json).unwrap();
+    assert_eq!(back.len(), tokens.len());
+    for (b, t) in back.iter().zip(&tokens) {
+        assert_eq!(b.sort, t.sort);
+        assert_eq!(b.text(), t.text());
+        assert_eq!(b.span, t.span);
+        assert_eq!(b.line_no, t.line_no);
+    }
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::diagnostics::{DiagnosticKind, WarningCategory, WarningConfig};
+use sysy_alpha::session::{CompileOptions, Session};
+
+/*
+    遮蔽警告: 局部变量与外层的参数, 局部变量或全局变量同名时报告, 同时标出两处声明;
+    只有在WarningConfig中打开Shadowing类别时才输出.
+*/
+
+const SOURCE: &str = "int g;
+int f(int x) {
+  int x = 1;
+  {
+    int x = 2;
+    int g = x;
+    return g;
+  }
+}
+int main() { return f(1); }
+";
+
+fn shadowing(name: &str, warn_shadowing: bool) -> Vec<(String, Vec<String>)> {
+    let dir = TempDir::new("shadowing");
+    let path = dir.write(name, SOURCE);
+    let mut warnings = WarningConfig::default();
+    warnings.set(WarningCategory::Shadowing, warn_shadowing);
+    let options = CompileOptions {
+        warnings,
+        ..Default::default()
+    };
+    let checked = Session::new(path.to_string_lossy(), options)
+        .check()
+        .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    checked
+        .warnings()
+        .filter(|d| d.kind == Some(DiagnosticKind::Shadowing))
+        .map(|d| {
+            let lines = d
+                .labels
+                .iter()
+                .map(|l| SOURCE[..l.span.start].lines().count().to_string())
+                .collect();
+            (d.message.clone(), lines)
+        })
+        .collect()
+}
+
+#[test]
+fn shadowing_reports_both_declarations() {
+    assert_eq!(
+        shadowing("on.sy", true),
+        vec![
+            (
+                "declaration of `x` shadows a parameter".to_string(),
+                vec!["3".to_string(), "2".to_string()]
+            ),
+            (
+                "declaration of `x` shadows a local variable".to_string(),
+                vec!["5".to_string(), "3".to_string()]
+            ),
+            (
+                "declaration of `g` shadows a global variable".to_string(),
+                vec!["6".to_string(), "1".to_string()]
+            ),
+        ]
+    );
+}
+
+#[test]
+fn shadowing_warnings_are_opt_in() {
+    assert!(shadowing("off.sy", false).is_empty());
+}
@@ -0,0 +1,117 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::lexer::LangLevel;
+use sysy_alpha::session::{CompileOptions, Session};
+use sysy_alpha::short_circuit::lower_short_circuit;
+use sysy_alpha::verify::verify;
+use sysy_alpha::visit::node_map;
+use sysy_alpha::{NodeKind, NodeType, TokenType};
+
+/*
+    --lower-short-circuit: &&和||被改写成嵌套的if和临时变量, 改写后的AST仍然满足--verify的不变式;
+    常量表达式中的&&和||同样短路求值.
+*/
+
+/* 返回语义分析的结果和改写后的AST. */
+fn lowered(
+    name: &str,
+    source: &str,
+) -> (Vec<sysy_alpha::parser::Node>, Vec<sysy_alpha::parser::Node>) {
+    let dir = TempDir::new("short_circuit");
+    let path = dir.write(name, source);
+    let options = CompileOptions {
+        level: LangLevel::Extended,
+        ..Default::default()
+    };
+    let checked = Session::new(path.to_string_lossy(), options)
+        .check()
+        .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    let lowered = lower_short_circuit(checked.annotated_ast.clone());
+    let errors = verify("lower-short-circuit", &lowered);
+    assert!(errors.is_empty(), "{:?}", errors);
+    (checked.annotated_ast, lowered)
+}
+
+fn logical_ops(ast: &[sysy_alpha::parser::Node]) -> usize {
+    node_map(ast)
+        .values()
+        .filter(|n| {
+            matches!(
+                n.node_type,
+                NodeType::BinOp(TokenType::And | TokenType::Or, ..)
+            )
+        })
+        .count()
+}
+
+fn temps(ast: &[sysy_alpha::parser::Node]) -> Vec<String> {
+    let mut names: Vec<String> = node_map(ast)
+        .values()
+        .filter_map(|n| n.as_decl())
+        .map(|(_, name, ..)| name.to_string())
+        .filter(|name| name.starts_with("__sc"))
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn logical_operators_become_branches() {
+    let (_, ast) = lowered(
+        "lower.sy",
+        "int a[4] = {1, 2, 0, 3};
+int f(int x) { return x; }
+int main() {
+  int i = 0, n = 4, ok = i < n && a[i] != 0 ? 1 : 0;
+  while (i < n && a[i]) {
+    i = i + 1;
+    if (f(i) || f(0)) continue;
+  }
+  float g = 1.5;
+  int r = ok && g > 1.0 ? f(2) : 0;
+  if (r || !i) return (i, ok);
+  return 0;
+}
+",
+    );
+    assert_eq!(logical_ops(&ast), 0);
+    assert_eq!(
+        temps(&ast),
+        vec!["__sc0", "__sc1", "__sc2", "__sc3", "__sc4"]
+    );
+    // while的条件需要前置语句, 改写成while (1) { ...; if (!cond) break; 循环体 }
+    let loops: Vec<_> = node_map(&ast)
+        .into_values()
+        .filter(|n| n.kind() == NodeKind::While)
+        .collect();
+    assert_eq!(loops.len(), 1);
+    let NodeType::While(cond, _) = &loops[0].node_type else {
+        unreachable!()
+    };
+    assert!(matches!(cond.node_type, NodeType::Number(1)));
+}
+
+#[test]
+fn programs_without_logical_operators_are_unchanged() {
+    let source = "int main() { int i = 0; while (i < 3) i = i + 1; return i; }";
+    let (original, ast) = lowered("plain.sy", source);
+    // 没有新节点, 每个节点的编号和类型都不变.
+    let before = node_map(&original);
+    let after = node_map(&ast);
+    assert_eq!(before.len(), after.len());
+    for (id, node) in after {
+        assert_eq!(before[&id].kind(), node.kind());
+        assert_eq!(before[&id].basic_type, node.basic_type);
+    }
+}
+
+#[test]
+fn constant_logical_operators_short_circuit() {
+    // 右操作数除以0, 但左操作数已经决定了结果, 不会被求值.
+    lowered(
+        "const.sy",
+        "const int a = 0 && 1 / 0 ? 1 : 2;\nint b[1 || 1 % 0 ? 3 : 4];\nint main() { return a + b[0]; }\n",
+    );
+}
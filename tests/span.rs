@@ -0,0 +1,62 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::lexer::{try_tokenize, LexOptions};
+use sysy_alpha::span::{FileId, SourceMap, Span};
+
+/*
+    SourceMap按需把Span换算成行号列号: 物理行列号按字符计数,
+    逻辑位置考虑#line指令. token的span记录的是LexOptions::file_id.
+*/
+
+#[test]
+fn line_col_and_logical_location() {
+    let mut sources = SourceMap::new();
+    sources.add("first.sy", "int x;\n");
+    let id = sources.add("main.sy", "int a;\n#line 40 \"gen.sy\"\nint b;\n\nint c;");
+    assert_eq!(id, FileId(1));
+
+    let file = sources.file(id);
+    assert_eq!(file.line_col(0), (1, 1));
+    assert_eq!(file.line_col(4), (1, 5));
+    // 换行符属于它所在的那一行
+    assert_eq!(file.line_col(6), (1, 7));
+    let b = 7 + "#line 40 \"gen.sy\"\n".len() + 4;
+    assert_eq!(file.line_col(b), (3, 5));
+    assert_eq!(file.line_text(b).iter().collect::<String>(), "int b;");
+
+    assert_eq!(
+        sources.location(Span::new(id, 4, 5)),
+        ("main.sy".into(), 1, 5)
+    );
+    assert_eq!(
+        sources.location(Span::new(id, b, b + 1)),
+        ("gen.sy".into(), 40, 5)
+    );
+    let c = file.chars.len() - 2;
+    assert_eq!(
+        sources.location(Span::new(id, c, c + 1)),
+        ("gen.sy".into(), 42, 5)
+    );
+}
+
+#[test]
+fn tokens_carry_the_file_id() {
+    let dir = TempDir::new("span");
+    let path = dir.join("ids.sy");
+    std::fs::write(&path, "int main() {\n  return 0;\n}\n").unwrap();
+    let path = path.to_string_lossy().to_string();
+
+    let mut sources = SourceMap::new();
+    sources.add("other.sy", "");
+    let id = sources.load(&path).unwrap();
+    let options = LexOptions {
+        file_id: id,
+        ..Default::default()
+    };
+    let tokens = try_tokenize(path, &options).unwrap();
+    assert!(tokens.iter().all(|t| t.span.file_id == id));
+    let ret = tokens.iter().find(|t| t.text() == "return").unwrap();
+    assert_eq!(sources.line_col(ret.span), (2, 3));
+    assert_eq!(ret.span.len(), "return".len());
+}
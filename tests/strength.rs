@@ -0,0 +1,84 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::ir::{BinOp, InstKind, Module};
+use sysy_alpha::lower::lower;
+use sysy_alpha::mem2reg::promote_module;
+use sysy_alpha::session::{CompileOptions, Session};
+use sysy_alpha::strength;
+
+/*
+    强度削弱: 2的幂的乘除模变成移位, 循环中i * N变成每次加N的归纳变量.
+*/
+
+fn reduced(name: &str, source: &str) -> Module {
+    let dir = TempDir::new("strength");
+    let path = dir.write(name, source);
+    let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    let mut module = lower(&checked.hir);
+    promote_module(&mut module);
+    assert!(strength::run_module(&mut module));
+    module
+        .validate()
+        .unwrap_or_else(|e| panic!("{}\n{}", e, module));
+    module
+}
+
+fn ops(module: &Module, wanted: BinOp) -> usize {
+    module
+        .functions
+        .iter()
+        .flat_map(|f| &f.blocks)
+        .flat_map(|b| &b.insts)
+        .filter(|i| matches!(i.kind, InstKind::Binary(op, ..) if op == wanted))
+        .count()
+}
+
+#[test]
+fn powers_of_two_become_shifts() {
+    let module = reduced(
+        "pow2.sy",
+        "int main() {
+  int x = getint();
+  return x * 8 + x / 4 + x % 16 + x / 3;
+}
+",
+    );
+    let text = module.to_string();
+    assert_eq!(ops(&module, BinOp::Mul), 0);
+    assert_eq!(ops(&module, BinOp::Rem), 0);
+    // 除以3保留.
+    assert_eq!(ops(&module, BinOp::Div), 1);
+    assert!(text.contains("shl i32 %0, 3"), "{}", text);
+    assert!(text.contains("shr i32 %0, 31"), "{}", text);
+    assert!(text.contains("and i32 %2, 3"), "{}", text);
+}
+
+#[test]
+fn induction_multiplications_become_additions() {
+    let module = reduced(
+        "iv.sy",
+        "int a[100][10];
+int main() {
+  int i = getint(), s = 0;
+  while (i < 100) {
+    s = s + i * 10;
+    i = i + 2;
+  }
+  return s;
+}
+",
+    );
+    let main = module.function("main").unwrap();
+    let text = main.to_string();
+    // 进入循环时i * 10在循环前算一次, 循环中每次加20.
+    assert_eq!(ops(&module, BinOp::Mul), 1, "{}", text);
+    assert!(main.blocks[0]
+        .insts
+        .iter()
+        .any(|i| matches!(i.kind, InstKind::Binary(BinOp::Mul, ..))));
+    assert!(text.contains(", 20\n"), "{}", text);
+}
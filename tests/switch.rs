@@ -0,0 +1,128 @@
+mod common;
+
+use common::SourceSession;
+use sysy_alpha::diagnostics::{Diagnostic, DiagnosticKind};
+use sysy_alpha::interp;
+use sysy_alpha::lexer::LangLevel;
+use sysy_alpha::lower::lower;
+use sysy_alpha::session::CompileOptions;
+
+/*
+    switch(扩展语法): 从匹配的case开始顺序执行到break或switch结束(fall-through),
+    没有匹配的case时执行default(可以在任意位置), 都没有时什么也不做; continue作用于外层的循环.
+    case标签是互不相同的整数常量表达式.
+*/
+
+fn session(source: &str) -> SourceSession {
+    let options = CompileOptions {
+        level: LangLevel::Extended,
+        ..Default::default()
+    };
+    SourceSession::new("switch", "switch.sy", source, options)
+}
+
+/* 运行程序, 返回标准输出. */
+fn run(source: &str) -> String {
+    let checked = session(source).check().unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    let outcome = interp::run(&lower(&checked.hir), b"").unwrap();
+    String::from_utf8(outcome.output).unwrap()
+}
+
+fn errors(source: &str) -> Vec<Diagnostic> {
+    let checked = session(source).check().unwrap();
+    checked.errors().cloned().collect()
+}
+
+#[test]
+fn cases_fall_through_until_break() {
+    let output = run("int main() {
+  int k = 0;
+  while (k < 5) {
+    switch (k) {
+      case 1: putch(97);
+      case 2: putch(98); break;
+      case 3: putch(99);
+      default: putch(100);
+      case 4: putch(101);
+    }
+    putch(10);
+    k = k + 1;
+  }
+  return 0;
+}
+");
+    // 0和5之外没有匹配的case, 从default开始落到case 4.
+    assert_eq!(output, "de\nab\nb\ncde\ne\n");
+}
+
+#[test]
+fn unmatched_switch_without_default_does_nothing() {
+    let output = run("int main() {
+  int x = 7;
+  switch (x) { case 1: putint(1); case 2: putint(2); }
+  putint(x);
+  return 0;
+}
+");
+    assert_eq!(output, "7");
+}
+
+#[test]
+fn continue_inside_switch_continues_the_loop() {
+    let output = run("int main() {
+  int i = 0;
+  while (i < 4) {
+    i = i + 1;
+    switch (i % 2) {
+      case 0: continue;
+      default: putint(i);
+    }
+    putch(32);
+  }
+  return 0;
+}
+");
+    assert_eq!(output, "1 3 ");
+}
+
+#[test]
+fn labels_are_distinct_integer_constants() {
+    // 常量表达式的标签按值比较.
+    let output = run("const int N = 2;
+int main() {
+  switch (3) { case N + 1: putint(1); break; case N * 2: putint(2); }
+  return 0;
+}
+");
+    assert_eq!(output, "1");
+
+    let duplicate = errors(
+        "const int N = 2;
+int main() { switch (1) { case 2: break; case N: break; } return 0; }
+",
+    );
+    assert_eq!(duplicate.len(), 1, "{:?}", duplicate);
+    assert_eq!(duplicate[0].kind, Some(DiagnosticKind::DuplicateLabel));
+    assert_eq!(
+        duplicate[0].message,
+        "Error type 16 at this line: duplicate case label `2` in switch statement"
+    );
+    assert_eq!(duplicate[0].labels.len(), 2);
+
+    let not_constant = errors("int main() { int a = 1; switch (1) { case a: break; } return 0; }");
+    assert_eq!(not_constant.len(), 1, "{:?}", not_constant);
+    assert_eq!(not_constant[0].kind, Some(DiagnosticKind::NotConstant));
+
+    let defaults =
+        errors("int main() { switch (1) { default: break; default: break; } return 0; }");
+    assert_eq!(defaults.len(), 1, "{:?}", defaults);
+    assert_eq!(
+        defaults[0].message,
+        "Error type 16 at this line: multiple default labels in one switch"
+    );
+
+    let float = errors("int main() { switch (1.5) { case 1: break; } return 0; }");
+    assert_eq!(float.len(), 1, "{:?}", float);
+    assert_eq!(float[0].kind, Some(DiagnosticKind::TypeMismatch));
+}
@@ -0,0 +1,131 @@
+mod common;
+
+use common::SourceSession;
+use sysy_alpha::diagnostics::DiagnosticKind;
+use sysy_alpha::interp;
+use sysy_alpha::lexer::LangLevel;
+use sysy_alpha::lower::lower;
+use sysy_alpha::parser::Node;
+use sysy_alpha::session::CompileOptions;
+use sysy_alpha::visit::node_map;
+use sysy_alpha::{BasicType, NodeType};
+
+/*
+    条件表达式(扩展语法): 优先级低于||, 高于赋值, 右结合; 两个分支都是int/float, 有一边是float时结果为float,
+    运行时只求值选中的分支.
+*/
+
+fn session(source: &str) -> SourceSession {
+    let options = CompileOptions {
+        level: LangLevel::Extended,
+        ..Default::default()
+    };
+    SourceSession::new("ternary", "ternary.sy", source, options)
+}
+
+/* 给每个运算加上括号, 显示语法分析得到的结构. */
+fn shape(node: &Node) -> String {
+    match &node.node_type {
+        NodeType::Cond(cond, on_true, on_false) => {
+            format!(
+                "({} ? {} : {})",
+                shape(cond),
+                shape(on_true),
+                shape(on_false)
+            )
+        }
+        NodeType::BinOp(op, lhs, rhs) => format!("({} {:?} {})", shape(lhs), op, shape(rhs)),
+        NodeType::Access(name, ..) => name.clone(),
+        NodeType::Number(n) => n.to_string(),
+        _ => format!("{:?}", node.kind()),
+    }
+}
+
+/* main中唯一的赋值语句右边的表达式的结构. */
+fn assigned(source: &str) -> String {
+    let ast = session(source).parse().unwrap();
+    let map = node_map(&ast);
+    let value = map
+        .values()
+        .find_map(|n| match &n.node_type {
+            NodeType::Assign(_, _, value, _) => Some(value),
+            _ => None,
+        })
+        .expect("assignment");
+    shape(value)
+}
+
+#[test]
+fn binds_looser_than_or_and_associates_to_the_right() {
+    let program = |expr: &str| {
+        format!(
+            "int main() {{ int a, b, c, d, e; a = {}; return a; }}\n",
+            expr
+        )
+    };
+    assert_eq!(
+        assigned(&program("b || c ? d + 1 : e ? 2 : 3")),
+        "((b Or c) ? (d Plus 1) : (e ? 2 : 3))"
+    );
+    assert_eq!(
+        assigned(&program("b ? c ? 1 : 2 : d * e")),
+        "(b ? (c ? 1 : 2) : (d Multi e))"
+    );
+    assert_eq!(
+        assigned(&program("(b ? c : d) * 2")),
+        "((b ? c : d) Multi 2)"
+    );
+}
+
+#[test]
+fn only_the_selected_branch_is_evaluated() {
+    let checked = session(
+        "int f(int x) { putint(x); return x; }
+int main() {
+  int a = 0;
+  a = a ? f(1) : f(2);
+  a = a ? f(3) : f(4);
+  return a;
+}
+",
+    )
+    .check()
+    .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    let outcome = interp::run(&lower(&checked.hir), b"").unwrap();
+    assert_eq!(outcome.output, b"23");
+    assert_eq!(outcome.exit_code, 3);
+}
+
+#[test]
+fn branches_are_converted_to_a_common_type() {
+    let checked = session(
+        "const int M = 1 ? 2 : 3;
+int main() { int a = 1; float x = a ? 1 : 2.5; return M; }
+",
+    )
+    .check()
+    .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    let map = node_map(&checked.annotated_ast);
+    let cond = map
+        .values()
+        .find(|n| matches!(n.node_type, NodeType::Cond(..)))
+        .expect("conditional expression");
+    assert_eq!(cond.basic_type, BasicType::Float);
+    let NodeType::Cond(_, on_true, _) = &cond.node_type else {
+        unreachable!()
+    };
+    // int常量分支在编译期就转换成了float.
+    assert!(matches!(on_true.node_type, NodeType::FloatNumber(x) if x == 1.0));
+
+    let checked = session(
+        "void g() {}
+int main() { int a = 1; return a ? g() : 0; }
+",
+    )
+    .check()
+    .unwrap();
+    let kinds: Vec<_> = checked.errors().map(|d| d.kind).collect();
+    assert_eq!(kinds, [Some(DiagnosticKind::TypeMismatch)]);
+}
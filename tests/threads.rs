@@ -0,0 +1,38 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::session::{CompileOptions, Session};
+
+/* 多个线程同时分析不同的程序, 每个线程只得到自己程序中的错误. */
+#[test]
+fn concurrent_compilations_keep_their_diagnostics_apart() {
+    let dir = TempDir::new("threads");
+    let handles: Vec<_> = (0..8)
+        .map(|i| {
+            let path = dir.join(format!("t{}.sy", i));
+            std::fs::write(
+                &path,
+                format!("void f{0}() {{}}\nint main() {{ return f{0}(); }}\n", i),
+            )
+            .unwrap();
+            std::thread::spawn(move || {
+                let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+                    .check()
+                    .unwrap();
+                checked
+                    .errors()
+                    .map(|d| d.message.clone())
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+    for (i, handle) in handles.into_iter().enumerate() {
+        assert_eq!(
+            handle.join().unwrap(),
+            vec![format!(
+                "Error type 7 at this line: void function f{} used as a value",
+                i
+            )]
+        );
+    }
+}
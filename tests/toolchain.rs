@@ -0,0 +1,218 @@
+mod common;
+
+use common::TempDir;
+use object::{Object, ObjectSymbol};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use sysy_alpha::codegen::{llvm, riscv};
+use sysy_alpha::ir::Module;
+use sysy_alpha::lower::lower_with_sources;
+use sysy_alpha::mem2reg::promote_module;
+use sysy_alpha::session::{CompileOptions, Session};
+use sysy_alpha::target::Target;
+use sysy_alpha::toolchain::{find_runtime, Assembler, LinkError, Toolchain};
+
+/*
+    外部工具链: 汇编和链接的命令行, 工具找不到或失败时的错误, 内置汇编器, 以及内置的运行时库.
+    机器上通常没有交叉工具链, 这里用true/false等代替链接器; 运行时库用本机的gcc编译,
+    与llc从LLVM IR生成的本机目标文件链接后运行.
+*/
+
+fn lower_source(path: &Path, source: &str) -> Module {
+    std::fs::write(path, source).unwrap();
+    let session = Session::new(path.to_string_lossy(), CompileOptions::default());
+    let checked = session.check().unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    let mut module = lower_with_sources(&checked.hir, session.engine().sources());
+    promote_module(&mut module);
+    module
+}
+
+/* 编译成汇编文件, 返回它的路径. */
+fn compile(dir: &TempDir, name: &str, source: &str) -> PathBuf {
+    let path = dir.join(name);
+    let module = lower_source(&path, source);
+    let asm = path.with_extension("s");
+    std::fs::write(&asm, riscv::emit(&module).unwrap()).unwrap();
+    asm
+}
+
+const PROGRAM: &str = "int main() { putint(getint() + 1); return 0; }\n";
+
+#[test]
+fn commands_use_the_target_toolchain_and_runtime() {
+    let mut toolchain = Toolchain::for_target(Target::Riscv64);
+    toolchain.runtime = Some(PathBuf::from("lib/libsysy.a"));
+    assert_eq!(
+        toolchain
+            .assemble_command(Path::new("a.s"), Path::new("a.o"))
+            .unwrap()
+            .join(" "),
+        "riscv64-linux-gnu-as -march=rv64gc -mabi=lp64d -o a.o a.s"
+    );
+    // 运行时库在目标文件之后.
+    assert_eq!(
+        toolchain
+            .link_command(&[Path::new("a.o")], Path::new("a"))
+            .join(" "),
+        "riscv64-linux-gnu-gcc -static a.o lib/libsysy.a -o a"
+    );
+    let arm = Toolchain::for_target(Target::Armv7);
+    assert!(arm.linker.starts_with("arm-linux-gnueabihf-gcc"));
+    toolchain.assembler = Assembler::Builtin;
+    assert_eq!(
+        toolchain.assemble_command(Path::new("a.s"), Path::new("a.o")),
+        None
+    );
+}
+
+#[test]
+fn missing_and_failing_tools_are_reported() {
+    let dir = TempDir::new("toolchain");
+    let asm = compile(&dir, "missing.sy", PROGRAM);
+    let mut toolchain = Toolchain::for_target(Target::Riscv64);
+    toolchain.assembler = Assembler::Command("sysy-no-such-assembler --flag".to_string());
+    let error = toolchain.build(&asm, &asm.with_extension("")).unwrap_err();
+    assert!(
+        matches!(&error, LinkError::Missing { step: "assembler", command } if command == "sysy-no-such-assembler"),
+        "{:?}",
+        error
+    );
+    assert!(error.to_string().contains("--as"), "{}", error);
+
+    toolchain.assembler = Assembler::Builtin;
+    toolchain.linker = "false".to_string();
+    let error = toolchain.build(&asm, &asm.with_extension("")).unwrap_err();
+    let LinkError::Failed {
+        step,
+        command,
+        status,
+        ..
+    } = &error
+    else {
+        panic!("{:?}", error);
+    };
+    assert_eq!((*step, *status), ("linker", Some(1)));
+    assert!(command.starts_with("false ") && command.contains("missing.o"));
+    assert!(error.to_string().contains("exit code 1"), "{}", error);
+
+    toolchain.runtime = Some(dir.join("no_such_libsysy.a"));
+    let error = toolchain.build(&asm, &asm.with_extension("")).unwrap_err();
+    assert!(error.to_string().contains("does not exist"), "{}", error);
+}
+
+#[test]
+fn builtin_assembler_writes_the_object_next_to_the_executable() {
+    let dir = TempDir::new("toolchain");
+    let asm = compile(&dir, "builtin.sy", PROGRAM);
+    let mut toolchain = Toolchain::for_target(Target::Riscv64);
+    toolchain.assembler = Assembler::Builtin;
+    toolchain.linker = "true".to_string();
+    let exe = dir.join("builtin_exe");
+    toolchain.build(&asm, &exe).unwrap();
+    let object = std::fs::read(exe.with_extension("o")).unwrap();
+    assert_eq!(&object[..4], b"\x7fELF");
+
+    // armv7还没有内置汇编器.
+    let mut arm = Toolchain::for_target(Target::Armv7);
+    arm.assembler = Assembler::Builtin;
+    let error = arm.assemble(&asm, &exe.with_extension("o")).unwrap_err();
+    assert!(matches!(error, LinkError::Builtin(_)), "{:?}", error);
+}
+
+#[test]
+fn bundled_runtime_links_and_runs_on_the_host() {
+    let dir = TempDir::new("toolchain");
+    let path = dir.join("host.sy");
+    let module = lower_source(
+        &path,
+        "int a[5];
+int main() {
+  int n = getarray(a);
+  starttime();
+  int i = 0, s = 0;
+  while (i < n) { s = s + a[i]; i = i + 1; }
+  stoptime();
+  putarray(n, a);
+  putf(\"sum=%d avg=%f\\n\", s, s / 2.0);
+  putfloat(getfloat());
+  putch(10);
+  return s;
+}
+",
+    );
+    let ll = path.with_extension("ll");
+    std::fs::write(&ll, llvm::emit(&module)).unwrap();
+    let mut toolchain = Toolchain::for_target(Target::Riscv64);
+    toolchain.linker = "gcc".to_string();
+    let object = match toolchain.build_runtime(&dir.join("runtime")) {
+        Ok(object) => object,
+        Err(LinkError::Missing { .. }) => return,
+        Err(e) => panic!("{}", e),
+    };
+    // 与比赛的libsysy.a相同, 运行时库只导出_sysy_starttime/_sysy_stoptime, 没有starttime/stoptime.
+    let bytes = std::fs::read(&object).unwrap();
+    let file = object::File::parse(&*bytes).unwrap();
+    let exported: Vec<&str> = file
+        .symbols()
+        .filter(|s| s.is_global() && s.is_definition())
+        .map(|s| s.name().unwrap())
+        .collect();
+    assert!(exported.contains(&"_sysy_starttime") && exported.contains(&"getint"));
+    assert!(!exported.contains(&"starttime") && !exported.contains(&"stoptime"));
+    let Ok(status) = Command::new("ar")
+        .arg("rcs")
+        .arg(dir.join("libsysy.a"))
+        .arg(&object)
+        .status()
+    else {
+        return;
+    };
+    assert!(status.success());
+    toolchain.runtime = find_runtime(&dir);
+    assert!(toolchain.runtime.is_some());
+    // 用llc代替汇编器; LLVM 15以前需要-opaque-pointers.
+    let exe = dir.join("host");
+    let mut built = false;
+    for flags in ["-opaque-pointers ", ""] {
+        let command = format!("llc {}-filetype=obj -relocation-model=pic", flags);
+        toolchain.assembler = Assembler::Command(command);
+        match toolchain.build(&ll, &exe) {
+            Ok(()) => built = true,
+            Err(LinkError::Missing { .. }) => return,
+            Err(LinkError::Failed { output, .. }) if output.contains("Unknown command line") => {
+                continue
+            }
+            Err(e) => panic!("{}", e),
+        }
+        break;
+    }
+    assert!(built);
+    let mut child = Command::new(&exe)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"3 4 5 6\n0x1.8p+0\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "3: 4 5 6\nsum=15 avg=7.500000\n0x1.8p+0\n"
+    );
+    assert_eq!(output.status.code(), Some(15));
+    // 计时的统计打印到标准错误, 带有starttime()和stoptime()所在的行号.
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.starts_with("Timer@0004-0007: 0H-0M-0S-"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("\nTOTAL: 0H-0M-0S-"), "{}", stderr);
+}
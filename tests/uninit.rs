@@ -0,0 +1,73 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::diagnostics::DiagnosticKind;
+use sysy_alpha::session::{CompileOptions, Session};
+
+/*
+    使用前未初始化: 没有初始化器的局部标量在某条路径上没有被赋值就被读取时给出警告,
+    if/while/switch的分支在汇合处取交集, return/break之后的路径不参与汇合.
+*/
+
+fn uninitialized(name: &str, body: &str) -> Vec<String> {
+    let dir = TempDir::new("uninit");
+    let path = dir.write(name, format!("int main() {{\n{}\n}}\n", body));
+    let checked = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    checked
+        .warnings()
+        .filter(|d| d.kind == Some(DiagnosticKind::UninitializedRead))
+        .map(|d| d.message.clone())
+        .collect()
+}
+
+#[test]
+fn reads_on_some_path_without_assignment_are_reported() {
+    assert_eq!(
+        uninitialized("if.sy", "int x; int c = getint(); if (c) x = 1; return x;"),
+        vec!["variable `x` may be used uninitialized"]
+    );
+    assert_eq!(
+        uninitialized(
+            "while.sy",
+            "int x; int i = 0; while (i < 3) { x = i; i = i + 1; } return x;"
+        ),
+        vec!["variable `x` may be used uninitialized"]
+    );
+}
+
+#[test]
+fn assignments_on_every_path_initialize() {
+    let cases = [
+        "int x; int c = getint(); if (c) x = 1; else x = 2; return x;",
+        "int x; int c = getint(); if (c) { x = 1; } else { return 0; } return x;",
+        "int x; while (1) { x = 1; break; } return x;",
+        "int x; x = 2; return x;",
+        "int a[2]; return a[0];",
+    ];
+    for (i, body) in cases.iter().enumerate() {
+        assert!(
+            uninitialized(&format!("ok{}.sy", i), body).is_empty(),
+            "{}",
+            body
+        );
+    }
+}
+
+#[test]
+fn each_variable_is_reported_once() {
+    assert_eq!(
+        uninitialized("once.sy", "int x; int y = x + x; return y + x;"),
+        vec!["variable `x` may be used uninitialized"]
+    );
+}
+
+#[test]
+fn the_right_hand_side_is_read_before_the_assignment() {
+    assert_eq!(
+        uninitialized("self.sy", "int x; x = x + 1; return x;"),
+        vec!["variable `x` may be used uninitialized"]
+    );
+}
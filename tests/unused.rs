@@ -0,0 +1,93 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::diagnostics::{DiagnosticKind, Severity};
+use sysy_alpha::session::{CompileOptions, Session};
+
+/*
+    未使用警告: 从未被读取的变量/参数/全局变量, 以及main调用不到的函数.
+    警告不算错误, has_errors()只看错误; 只被不可达函数调用的函数同样不可达.
+*/
+
+fn check(name: &str, source: &str) -> sysy_alpha::session::Checked {
+    let dir = TempDir::new("unused");
+    let path = dir.write(name, source);
+    Session::new(path.to_string_lossy(), CompileOptions::default())
+        .check()
+        .unwrap()
+}
+
+fn warnings(checked: &sysy_alpha::session::Checked) -> Vec<String> {
+    checked.warnings().map(|d| d.message.clone()).collect()
+}
+
+#[test]
+fn unused_symbols_are_warnings() {
+    let checked = check(
+        "unused.sy",
+        "int g;\n\
+         int h = 1;\n\
+         int helper(int a, int b) { return a; }\n\
+         int dead() { return helper(1, 2); }\n\
+         int main() { int x; int y = h; x = 1; return y; }\n",
+    );
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    assert!(checked.warnings().all(|d| d.severity == Severity::Warning));
+    assert_eq!(
+        warnings(&checked),
+        vec![
+            "unused global variable `g`",
+            "unused parameter `b`",
+            "unused variable `x`",
+            "function `helper` is never called from main",
+            "function `dead` is never called from main",
+        ]
+    );
+    let kinds: Vec<_> = checked.warnings().map(|d| d.kind).collect();
+    assert_eq!(kinds[4], Some(DiagnosticKind::UnusedFunction));
+}
+
+#[test]
+fn calls_from_reachable_functions_count_as_uses() {
+    let checked = check(
+        "reachable.sy",
+        "int leaf(int n) { return n; }\n\
+         int mid() { return leaf(0); }\n\
+         int proto(int n);\n\
+         int main() { return mid(); }\n",
+    );
+    assert!(warnings(&checked).is_empty(), "{:?}", checked.diagnostics);
+}
+
+#[test]
+fn functions_are_not_checked_without_main() {
+    let checked = check("library.sy", "int f() { return 1; }\n");
+    assert!(warnings(&checked).is_empty(), "{:?}", checked.diagnostics);
+}
+
+#[test]
+fn writes_through_array_parameters_and_globals_are_uses() {
+    let checked = check(
+        "writes.sy",
+        "int out[4];\n\
+         void fill(int p[][2], int n) { p[n][1] = n; }\n\
+         int main() { int a[3][2]; int local[2]; fill(a, 1); out[0] = 1; local[0] = 2; return a[1][1]; }\n",
+    );
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    // 只写不读的局部数组仍然报告.
+    assert_eq!(warnings(&checked), vec!["unused variable `local`"]);
+}
+
+#[test]
+fn folded_constant_reads_are_uses() {
+    let checked = check(
+        "consts.sy",
+        "const int N = 2;\n\
+         const int M = N + 1;\n\
+         const int UNUSED = 4;\n\
+         int main() { const int k[2] = {1, 2}; int a[M]; a[0] = k[1]; return a[0]; }\n",
+    );
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    // 常量的访问在语义分析后已是Number, 但仍然算作使用.
+    assert_eq!(warnings(&checked), vec!["unused global variable `UNUSED`"]);
+}
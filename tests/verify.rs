@@ -0,0 +1,159 @@
+mod common;
+
+use common::{SourceSession, TempDir};
+use std::process::Command;
+use sysy_alpha::ir::{BinOp, BlockId, Function, Inst, InstKind, Module, Operand, Type, Value};
+use sysy_alpha::parser::Node;
+use sysy_alpha::session::CompileOptions;
+use sysy_alpha::verify::{verify, verify_module};
+use sysy_alpha::{BasicType, NodeType};
+
+/*
+    --verify: 语义AST的不变式, 以及三地址码的终结指令, phi的输入和定义对使用的支配;
+    命令行在降低之后和每个pass之后检查.
+*/
+
+fn inst(dest: Option<Value>, ty: Type, kind: InstKind) -> Inst {
+    Inst { dest, ty, kind }
+}
+
+/* bb0: br %0, bb1, bb2; bb1: %1 = add %0, 1; jump bb3; bb2: jump bb3; bb3: %2 = phi [bb1: %1], [bb2: 0]; ret %2 */
+fn diamond() -> Function {
+    let mut f = Function::new("f", Type::I32, vec![Type::I32]);
+    let blocks: Vec<BlockId> = (0..4).map(|_| f.new_block()).collect();
+    let sum = f.new_value(Type::I32);
+    let phi = f.new_value(Type::I32);
+    f.block_mut(blocks[0]).insts = vec![inst(
+        None,
+        Type::Void,
+        InstKind::Branch(Operand::Value(Value(0)), blocks[1], blocks[2]),
+    )];
+    f.block_mut(blocks[1]).insts = vec![
+        inst(
+            Some(sum),
+            Type::I32,
+            InstKind::Binary(BinOp::Add, Operand::Value(Value(0)), Operand::Int(1)),
+        ),
+        inst(None, Type::Void, InstKind::Jump(blocks[3])),
+    ];
+    f.block_mut(blocks[2]).insts = vec![inst(None, Type::Void, InstKind::Jump(blocks[3]))];
+    f.block_mut(blocks[3]).insts = vec![
+        inst(
+            Some(phi),
+            Type::I32,
+            InstKind::Phi(vec![
+                (blocks[1], Operand::Value(sum)),
+                (blocks[2], Operand::Int(0)),
+            ]),
+        ),
+        inst(None, Type::Void, InstKind::Ret(Some(Operand::Value(phi)))),
+    ];
+    f
+}
+
+fn errors(f: Function) -> Vec<String> {
+    let module = Module {
+        functions: vec![f],
+        ..Default::default()
+    };
+    verify_module("test", &module)
+        .into_iter()
+        .map(|d| d.message)
+        .collect()
+}
+
+#[test]
+fn well_formed_ssa_passes() {
+    assert_eq!(errors(diamond()), Vec::<String>::new());
+}
+
+#[test]
+fn phi_inputs_must_match_predecessors() {
+    let mut f = diamond();
+    let InstKind::Phi(incoming) = &mut f.blocks[3].insts[0].kind else {
+        unreachable!()
+    };
+    incoming[1].0 = BlockId(0);
+    assert_eq!(
+        errors(f),
+        [
+            "internal error after `test`: f: phi in bb3 has an input from bb0, which is not a predecessor",
+            "internal error after `test`: f: phi in bb3 has 0 inputs from predecessor bb2",
+        ]
+    );
+}
+
+#[test]
+fn definitions_must_dominate_uses() {
+    // %1只在bb1中定义, 不能在汇合点直接使用.
+    let mut f = diamond();
+    f.blocks[3].insts[0].kind = InstKind::Copy(Operand::Value(Value(1)));
+    assert_eq!(
+        errors(f),
+        ["internal error after `test`: f: %1 is used in bb3 where its definition does not dominate"]
+    );
+
+    // phi的输入要在对应的前驱中可用.
+    let mut f = diamond();
+    let InstKind::Phi(incoming) = &mut f.blocks[3].insts[0].kind else {
+        unreachable!()
+    };
+    incoming[1].1 = Operand::Value(Value(1));
+    assert_eq!(
+        errors(f),
+        ["internal error after `test`: f: %1 flows into the phi in bb3 from bb2, where it is not available"]
+    );
+}
+
+#[test]
+fn blocks_must_end_with_a_terminator() {
+    let mut f = diamond();
+    f.blocks[2].insts.clear();
+    assert_eq!(
+        errors(f),
+        ["internal error after `test`: f: bb2 has no terminator"]
+    );
+}
+
+#[test]
+fn annotated_ast_invariants() {
+    let session = SourceSession::new(
+        "verify",
+        "ast.sy",
+        "int main() { int a = 2; return a * 3; }\n",
+        CompileOptions::default(),
+    );
+    let checked = session.check().unwrap();
+    assert!(verify("semantic", &checked.annotated_ast).is_empty());
+
+    // 常量没有折叠成const int, 模拟一个忘记设置类型的pass.
+    let broken = vec![Node {
+        basic_type: BasicType::Int,
+        ..Node::new(NodeType::Number(3))
+    }];
+    let errors = verify("my-pass", &broken);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(
+        errors[0].message,
+        "internal error after `my-pass`: Number has type `int`"
+    );
+}
+
+#[test]
+fn command_line_verifies_every_pass() {
+    let dir = TempDir::new("verify");
+    let path = dir.write(
+        "passes.sy",
+        "int main() { int i = 0, s = 0; while (i < 10) { if (i % 2) s = s + i * 4; i = i + 1; } return s; }\n",
+    );
+    let output = Command::new(env!("CARGO_BIN_EXE_sysy_alpha"))
+        .arg(&path)
+        .args(["--verify", "-O2", "--emit-ir"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
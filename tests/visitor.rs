@@ -0,0 +1,51 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::parser::Node;
+use sysy_alpha::session::{CompileOptions, Session};
+use sysy_alpha::visit::{walk, walk_all, Visitor};
+
+/*
+    Visitor的默认实现会访问到每个节点: 只重写关心的方法,
+    其余节点(包括嵌套在语句, 初始化列表和实参中的表达式)仍然被遍历.
+*/
+
+#[derive(Default)]
+struct Counter {
+    binops: usize,
+    calls: Vec<String>,
+}
+
+impl Visitor for Counter {
+    fn visit_binop(&mut self, node: &Node) {
+        self.binops += 1;
+        walk(self, node);
+    }
+
+    fn visit_call(&mut self, node: &Node) {
+        self.calls.push(node.as_call().unwrap().0.to_string());
+        walk(self, node);
+    }
+}
+
+#[test]
+fn default_walk_reaches_nested_expressions() {
+    let dir = TempDir::new("visitor");
+    let path = dir.join("count.sy");
+    std::fs::write(
+        &path,
+        "int g(int x);\n\
+         int f(int a[], int n) { int s[2] = {n + 1, n * 2}; return g(a[n - 1] + s[0]); }\n\
+         int main() { int a[3]; while (a[0] < 3) { a[0] = a[0] + f(a, 1); } return 0; }\n\
+         int g(int x) { if (x == 0) return 1; return x / 2; }\n",
+    )
+    .unwrap();
+
+    let ast = Session::new(path.to_string_lossy(), CompileOptions::default())
+        .parse()
+        .unwrap();
+    let mut counter = Counter::default();
+    walk_all(&mut counter, &ast);
+    assert_eq!(counter.binops, 8);
+    assert_eq!(counter.calls, ["g", "f"]);
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use common::TempDir;
+use sysy_alpha::diagnostics::{DiagnosticKind, Severity, WarningCategory, WarningConfig};
+use sysy_alpha::session::{CompileOptions, Session};
+
+/*
+    WarningConfig: 每类警告可以单独打开或关闭(shadowing和constant-condition默认关闭),
+    werror把保留下来的警告升级为错误.
+*/
+
+const SOURCE: &str = "int unused_global;
+int main() {
+  int x = 1;
+  { int x = 2; putint(x); }
+  return 0;
+}
+";
+
+fn check(name: &str, warnings: WarningConfig) -> Vec<(Severity, DiagnosticKind)> {
+    let dir = TempDir::new("warning_config");
+    let path = dir.write(name, SOURCE);
+    let options = CompileOptions {
+        warnings,
+        ..Default::default()
+    };
+    let checked = Session::new(path.to_string_lossy(), options)
+        .check()
+        .unwrap();
+    checked
+        .diagnostics
+        .iter()
+        .map(|d| (d.severity, d.kind.unwrap()))
+        .collect()
+}
+
+#[test]
+fn categories_can_be_switched_individually() {
+    use DiagnosticKind::*;
+    let defaults = WarningConfig::default();
+    assert!(!defaults.is_enabled(WarningCategory::Shadowing));
+    assert_eq!(
+        check("default.sy", defaults),
+        vec![
+            (Severity::Warning, UnusedVariable),
+            (Severity::Warning, UnusedVariable)
+        ]
+    );
+
+    let mut warnings = WarningConfig::default();
+    warnings.set(WarningCategory::Unused, false);
+    warnings.set(WarningCategory::Shadowing, true);
+    assert_eq!(
+        check("switched.sy", warnings),
+        vec![(Severity::Warning, Shadowing)]
+    );
+}
+
+#[test]
+fn werror_turns_warnings_into_errors() {
+    let mut warnings = WarningConfig::default();
+    warnings.werror = true;
+    let kinds = check("werror.sy", warnings);
+    assert_eq!(kinds.len(), 2);
+    assert!(kinds
+        .iter()
+        .all(|(severity, _)| *severity == Severity::Error));
+}
+
+#[test]
+fn category_names_round_trip() {
+    for category in WarningCategory::ALL {
+        assert_eq!(WarningCategory::from_name(category.name()), Some(category));
+    }
+    assert_eq!(
+        DiagnosticKind::UnusedFunction.warning_category(),
+        Some(WarningCategory::Unreachable)
+    );
+    assert_eq!(DiagnosticKind::TypeMismatch.warning_category(), None);
+}
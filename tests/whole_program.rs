@@ -0,0 +1,95 @@
+mod common;
+
+use common::{SourceSession, TempDir};
+use std::process::Command;
+use sysy_alpha::parser::Node;
+use sysy_alpha::session::CompileOptions;
+use sysy_alpha::whole_program::{prune_unreachable, Removed};
+
+/*
+    全程序模式: 删除从main不可达的函数和只在它们中使用的全局声明, 可达的(包括递归的)函数和用到的全局变量保留;
+    命令行在标准错误上报告删除的内容, 数据段缩小的字节数与降低后的模块一致(包括提升成全局常量的局部数组).
+*/
+
+const PROGRAM: &str = "int big[100];
+const int tab[4] = {1, 2, 3, 4};
+int used = 3;
+int helper(int n) { return big[n]; }
+int dead(int i) { const int t[3] = {1, 2, 3}; return helper(i) + tab[i] + t[i]; }
+int fib(int n);
+int fib(int n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+int main() { return fib(used); }
+";
+
+fn prune(source: &str) -> (Vec<Node>, Removed) {
+    let checked = SourceSession::new(
+        "whole_program",
+        "main.sy",
+        source,
+        CompileOptions::default(),
+    )
+    .check()
+    .unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    prune_unreachable(checked.annotated_ast)
+}
+
+fn function_names(ast: &[Node]) -> Vec<&str> {
+    ast.iter()
+        .filter_map(|n| n.as_func())
+        .map(|f| f.1)
+        .collect()
+}
+
+#[test]
+fn unreachable_functions_and_their_globals_are_removed() {
+    let (pruned, removed) = prune(PROGRAM);
+    assert_eq!(removed.functions, ["helper", "dead"]);
+    assert_eq!(removed.globals, ["big", "tab"]);
+    // 原型和定义都保留, 没有被删除的全局变量的声明语句也保留.
+    assert_eq!(function_names(&pruned), ["fib", "fib", "main"]);
+    assert_eq!(pruned.len(), 4);
+}
+
+#[test]
+fn programs_without_main_are_kept_whole() {
+    let source = "int g;\nint f() { return 1; }\n";
+    let checked = SourceSession::new("whole_program", "lib.sy", source, CompileOptions::default())
+        .check()
+        .unwrap();
+    let before = checked.annotated_ast.len();
+    let (pruned, removed) = prune_unreachable(checked.annotated_ast);
+    assert!(removed.is_empty());
+    assert_eq!(pruned.len(), before);
+}
+
+#[test]
+fn command_line_reports_what_was_removed() {
+    let dir = TempDir::new("whole_program");
+    let path = dir.write("main.sy", PROGRAM);
+    let output = Command::new(env!("CARGO_BIN_EXE_sysy_alpha"))
+        .arg(&path)
+        .args(["--whole-program", "--emit-ir"])
+        .current_dir(&*dir)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let report: Vec<&str> = stderr
+        .lines()
+        .filter(|l| l.starts_with("whole-program:"))
+        .collect();
+    // big(400字节), tab(16字节)和dead中的常量数组t(提升成全局常量, 12字节).
+    assert_eq!(
+        report,
+        [
+            "whole-program: removed unreachable function `helper`",
+            "whole-program: removed unreachable function `dead`",
+            "whole-program: removed unused global `big`",
+            "whole-program: removed unused global `tab`",
+            "whole-program: 2 functions, 2 globals removed, data section shrunk by 428 bytes",
+        ]
+    );
+    let ir = std::fs::read_to_string(dir.join("main.ir")).unwrap();
+    assert!(!ir.contains("helper") && !ir.contains("big"), "{}", ir);
+}
@@ -0,0 +1,142 @@
+mod common;
+
+use common::SourceSession;
+use sysy_alpha::session::CompileOptions;
+use sysy_alpha::visit::node_map;
+use sysy_alpha::xref::{semantic_tokens, symbol_uses, usage_kind, UsageKind};
+use sysy_alpha::NodeType;
+
+/*
+    交叉引用: 语义分析后每处变量使用按源代码顺序列出, 并区分读, 写和既读又写
+    (给数组元素赋值, 把数组作为实参). 每处使用都指向它解析到的声明, 被遮蔽的同名变量互不混淆.
+*/
+
+fn check(name: &str, source: &str) -> sysy_alpha::session::Checked {
+    let session = SourceSession::new("xref", name, source, CompileOptions::default());
+    let checked = session.check().unwrap();
+    assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+    checked
+}
+
+/* main中每处使用的(名字, 方式), 按源代码顺序. */
+fn kinds(name: &str, body: &str) -> Vec<(String, UsageKind)> {
+    let source = format!(
+        "void f(int p[]) {{ p[0] = 1; }}\nint main() {{\n{}\n}}\n",
+        body
+    );
+    let checked = check(name, &source);
+    let main = source.find("int main").unwrap();
+    symbol_uses(&checked.annotated_ast)
+        .into_iter()
+        .filter(|u| u.span.start > main)
+        .map(|u| (u.name, u.kind))
+        .collect()
+}
+
+fn uses(expected: &[(&str, UsageKind)]) -> Vec<(String, UsageKind)> {
+    expected.iter().map(|(n, k)| (n.to_string(), *k)).collect()
+}
+
+#[test]
+fn element_stores_read_and_write_the_array() {
+    use UsageKind::*;
+    assert_eq!(
+        kinds(
+            "elements.sy",
+            "int a[3] = {}; int i = 1; a[i] = a[i] + 1; return a[0];"
+        ),
+        uses(&[
+            ("a", ReadWrite),
+            ("i", Read),
+            ("a", Read),
+            ("i", Read),
+            ("a", Read)
+        ])
+    );
+}
+
+#[test]
+fn scalar_assignments_write_after_reading_the_right_hand_side() {
+    use UsageKind::*;
+    assert_eq!(
+        kinds("scalar.sy", "int x = 0; x = x + 1; return x;"),
+        uses(&[("x", Write), ("x", Read), ("x", Read)])
+    );
+}
+
+#[test]
+fn arrays_passed_to_calls_may_be_read_or_written() {
+    use UsageKind::*;
+    assert_eq!(
+        kinds(
+            "calls.sy",
+            "int a[2] = {}; int b[2][2] = {}; int n = 2; f(a); f(b[n - 1]); putarray(n, a); return b[1][0];"
+        ),
+        uses(&[
+            ("a", ReadWrite),
+            ("b", ReadWrite),
+            ("n", Read),
+            ("n", Read),
+            ("a", ReadWrite),
+            ("b", Read)
+        ])
+    );
+}
+
+#[test]
+fn shadowed_names_resolve_to_their_own_declarations() {
+    let source =
+        "int x;\nint main() {\n  int x = 1;\n  {\n    int x = 2;\n    x = 3;\n  }\n  return x;\n}\n";
+    let checked = check("shadow.sy", source);
+    let nodes = node_map(&checked.annotated_ast);
+    let decl_line = |u: &sysy_alpha::xref::SymbolUse| {
+        let decl = nodes[&u.decl];
+        assert!(matches!(&decl.node_type, NodeType::Decl(_, name, ..) if name == "x"));
+        source[..decl.span.start].lines().count()
+    };
+    let uses = symbol_uses(&checked.annotated_ast);
+    assert_eq!(uses.len(), 2);
+    // 内层的赋值写的是第5行声明的x, return读的是第3行的x, 全局的x没有被使用.
+    assert_eq!((uses[0].kind, decl_line(&uses[0])), (UsageKind::Write, 5));
+    assert_eq!((uses[1].kind, decl_line(&uses[1])), (UsageKind::Read, 3));
+}
+
+#[test]
+fn usage_kind_classifies_single_nodes() {
+    let source =
+        "int main() {\n  int a[2] = {}; int x = 0;\n  a[1] = 2;\n  x = a[1];\n  return 0;\n}\n";
+    let checked = check("nodes.sy", source);
+    let nodes = node_map(&checked.annotated_ast);
+    for node in nodes.values() {
+        let expected = match &node.node_type {
+            NodeType::Access(..) => Some(UsageKind::Read),
+            NodeType::Assign(name, _, _, _) if name == "a" => Some(UsageKind::ReadWrite),
+            NodeType::Assign(..) => Some(UsageKind::Write),
+            _ => None,
+        };
+        assert_eq!(usage_kind(node), expected, "{:?}", node.kind());
+    }
+}
+
+#[test]
+fn semantic_tokens_cover_the_variable_names() {
+    let source = "int main() {\n  int total = 0; int arr[2] = {};\n  arr[0] = total;\n  total = arr[0] + total;\n  return total;\n}\n";
+    let checked = check("tokens.sy", source);
+    let tokens = semantic_tokens(&checked.annotated_ast);
+    let spelled: Vec<(&str, UsageKind)> = tokens
+        .into_iter()
+        .map(|(range, kind)| (&source[range], kind))
+        .collect();
+    use UsageKind::*;
+    assert_eq!(
+        spelled,
+        vec![
+            ("arr", ReadWrite),
+            ("total", Read),
+            ("total", Write),
+            ("arr", Read),
+            ("total", Read),
+            ("total", Read)
+        ]
+    );
+}